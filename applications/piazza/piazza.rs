@@ -11,6 +11,12 @@ mod populate;
 
 use crate::populate::Populate;
 
+/// How long to wait for inserted posts to show up in the posts view.
+const VERIFY_TIMEOUT: time::Duration = time::Duration::from_secs(30);
+
+/// How long to wait before reading a class that is missing posts again.
+const VERIFY_INTERVAL: time::Duration = time::Duration::from_millis(100);
+
 pub struct Backend {
     g: Handle<LocalAuthority>,
     done: Box<dyn Future<Output = ()> + Unpin>,
@@ -24,7 +30,12 @@ enum PopulateType {
 }
 
 impl Backend {
-    pub async fn new(partial: bool, _shard: bool, reuse: &str) -> Backend {
+    pub async fn new(
+        partial: bool,
+        _shard: bool,
+        reuse: &str,
+        memory_limit: Option<usize>,
+    ) -> Backend {
         let mut cb = Builder::default();
         let log = noria::logger_pls();
         let blender_log = log.clone();
@@ -33,6 +44,10 @@ impl Backend {
             cb.disable_partial();
         }
 
+        if let Some(limit) = memory_limit {
+            cb.set_memory_limit(limit, time::Duration::from_millis(10));
+        }
+
         match reuse {
             "finkelstein" => cb.set_reuse(ReuseConfigType::Finkelstein),
             "full" => cb.set_reuse(ReuseConfigType::Full),
@@ -69,6 +84,50 @@ impl Backend {
         i
     }
 
+    /// Check that the posts view returns exactly the given posts for every class.
+    ///
+    /// The last posts may still be propagating, so a class that is missing posts is read again
+    /// until it has them all, or until `VERIFY_TIMEOUT` has passed. Every class is read twice, so
+    /// that the second pass also reads classes whose posts were evicted during the first, and
+    /// have to be replayed.
+    async fn verify_posts(&mut self, posts: &[Vec<DataType>], nclasses: i32) {
+        let mut expected: HashMap<i32, Vec<i32>> = HashMap::new();
+        for post in posts {
+            let (pid, cid): (i32, i32) = ((&post[0]).into(), (&post[1]).into());
+            expected.entry(cid).or_default().push(pid);
+        }
+        for want in expected.values_mut() {
+            want.sort();
+        }
+
+        let deadline = time::Instant::now() + VERIFY_TIMEOUT;
+        let mut getter = self.g.view("posts").await.unwrap();
+        for pass in 0..2 {
+            for cid in 0..nclasses {
+                let want = expected.get(&cid).cloned().unwrap_or_default();
+                loop {
+                    let rows = getter.lookup(&[cid.into()], true).await.unwrap();
+                    let mut pids: Vec<i32> = rows.iter().map(|row| (&row[0]).into()).collect();
+                    pids.sort();
+                    if pids == want {
+                        break;
+                    }
+
+                    assert!(
+                        time::Instant::now() < deadline,
+                        "wrong posts for class {} in pass {}: got {:?}, want {:?}",
+                        cid,
+                        pass,
+                        pids,
+                        want
+                    );
+                    tokio::time::delay_for(VERIFY_INTERVAL).await;
+                }
+            }
+        }
+        println!("Verified posts of {} classes.", nclasses);
+    }
+
     async fn login(&mut self, user_context: HashMap<String, DataType>) -> Result<(), String> {
         self.g.create_universe(user_context.clone()).await.unwrap();
 
@@ -216,6 +275,13 @@ async fn main() {
                 .default_value("0.1")
                 .help("Percentage of private posts"),
         )
+        .arg(
+            Arg::with_name("memory-limit")
+                .long("memory-limit")
+                .takes_value(true)
+                .default_value("0")
+                .help("Memory, in bytes, available for partially materialized state [0 = unlimited]. Posts are checked after populating when set."),
+        )
         .get_matches();
 
     println!("Starting benchmark...");
@@ -235,6 +301,10 @@ async fn main() {
     let nclasses = value_t_or_exit!(args, "nclasses", i32);
     let nposts = value_t_or_exit!(args, "nposts", i32);
     let private = value_t_or_exit!(args, "private", f32);
+    let memory_limit = match value_t_or_exit!(args, "memory-limit", usize) {
+        0 => None,
+        limit => Some(limit),
+    };
 
     assert!(
         nlogged <= nusers,
@@ -247,7 +317,7 @@ async fn main() {
 
     // Initiliaze backend application with some queries and policies
     println!("Initiliazing database schema...");
-    let mut backend = Backend::new(partial, shard, reuse).await;
+    let mut backend = Backend::new(partial, shard, reuse, memory_limit).await;
     backend.migrate(sloc, None).await.unwrap();
 
     backend.set_security_config(ploc).await;
@@ -317,7 +387,11 @@ async fn main() {
     }
 
     if populate == PopulateType::After {
-        backend.populate("Post", posts).await;
+        backend.populate("Post", posts.clone()).await;
+    }
+
+    if memory_limit.is_some() && populate != PopulateType::NoPopulate {
+        backend.verify_posts(&posts, nclasses).await;
     }
 
     if !partial {
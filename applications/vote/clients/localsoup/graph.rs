@@ -1,5 +1,6 @@
 use noria::{self, FrontierStrategy, Handle, LocalAuthority, NodeIndex, PersistenceParameters};
 use std::future::Future;
+use std::time::Duration;
 
 pub(crate) const RECIPE: &str = "# base tables
 CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
@@ -30,6 +31,7 @@ pub struct Builder {
     pub logging: bool,
     pub threads: Option<usize>,
     pub purge: String,
+    pub memory_limit: Option<usize>,
 }
 
 impl Default for Builder {
@@ -41,6 +43,7 @@ impl Default for Builder {
             logging: false,
             threads: None,
             purge: "none".to_string(),
+            memory_limit: None,
         }
     }
 }
@@ -64,6 +67,9 @@ impl Builder {
         if let Some(threads) = self.threads {
             g.set_threads(threads);
         }
+        if let Some(limit) = self.memory_limit {
+            g.set_memory_limit(limit, Duration::from_millis(10));
+        }
         match &*self.purge {
             "all" => {
                 g.set_frontier_strategy(FrontierStrategy::AllPartial);
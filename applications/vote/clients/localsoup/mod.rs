@@ -1,9 +1,11 @@
 use crate::clients::{Parameters, ReadRequest, VoteClient, WriteRequest};
 use clap::{self, value_t_or_exit};
-use failure::ResultExt;
-use noria::{self, TableOperation};
+use failure::{bail, ResultExt};
+use noria::{self, DataType, TableOperation};
 use std::future::Future;
 use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time;
@@ -11,6 +13,55 @@ use tower_service::Service;
 
 pub(crate) mod graph;
 
+/// How many times to re-read the vote counts at the end of a run before giving up on them
+/// catching up with the issued votes.
+const VERIFY_ATTEMPTS: usize = 20;
+
+/// The votes issued and acknowledged for each article, kept to check the results we read.
+struct Votes {
+    issued: Vec<AtomicUsize>,
+    acked: Vec<AtomicUsize>,
+}
+
+impl Votes {
+    fn new(articles: usize) -> Self {
+        Votes {
+            issued: (0..articles).map(|_| AtomicUsize::new(0)).collect(),
+            acked: (0..articles).map(|_| AtomicUsize::new(0)).collect(),
+        }
+    }
+
+    /// Check a row of `ArticleWithVoteCount` for the given article.
+    ///
+    /// Reads may not yet reflect every acknowledged vote, but they can never count votes that
+    /// were not issued.
+    fn check(&self, article_id: i32, row: &[DataType]) {
+        let id: i64 = (&row[0]).into();
+        assert_eq!(id, i64::from(article_id), "read returned the wrong article");
+        assert_eq!(row[1], format!("Article #{}", article_id).into());
+        let votes = Self::votes(row);
+        let issued = self.issued[article_id as usize - 1].load(Ordering::Acquire);
+        assert!(
+            votes <= issued,
+            "article {} has {} votes, but only {} were issued",
+            article_id,
+            votes,
+            issued
+        );
+    }
+
+    fn votes(row: &[DataType]) -> usize {
+        match row[2] {
+            // articles without votes have no vote count
+            DataType::None => 0,
+            ref votes => {
+                let votes: i64 = votes.into();
+                votes as usize
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct LocalNoria {
     _g: Arc<graph::Graph>,
@@ -18,6 +69,7 @@ pub(crate) struct LocalNoria {
     // see https://aochagavia.github.io/blog/enforcing-drop-order-in-rust/
     r: Option<noria::View>,
     w: Option<noria::Table>,
+    votes: Option<Arc<Votes>>,
 }
 
 // View and Table are both Send, but graph::Graph isn't Sync, so Arc<Graph> isn't Send.
@@ -63,6 +115,15 @@ impl VoteClient for LocalNoria {
         let purge = args.value_of("purge").unwrap().to_string();
         s.purge = purge.clone();
 
+        // with limited memory, reads exercise eviction and replay, so we check what they return
+        let votes = match value_t_or_exit!(args, "memory-limit", usize) {
+            0 => None,
+            limit => {
+                s.memory_limit = Some(limit);
+                Some(Arc::new(Votes::new(params.articles)))
+            }
+        };
+
         async move {
             let mut g = s.start(persistence).await?;
 
@@ -105,9 +166,57 @@ impl VoteClient for LocalNoria {
                 _g: Arc::new(g),
                 r: Some(r),
                 w: Some(w),
+                votes,
             })
         }
     }
+
+    fn verify(&mut self) -> Pin<Box<dyn Future<Output = Result<(), failure::Error>> + Send + '_>> {
+        let votes = match self.votes {
+            Some(ref votes) => Arc::clone(votes),
+            None => return Box::pin(async { Ok(()) }),
+        };
+        let r = self.r.as_mut().unwrap();
+
+        Box::pin(async move {
+            // the last votes may still be propagating, so give the counts some time to catch up.
+            // most articles will have been evicted by now, so this also checks replayed state.
+            let articles = votes.issued.len();
+            for attempt in 1..=VERIFY_ATTEMPTS {
+                let mut behind = 0;
+                for chunk in (1..=articles as i32).collect::<Vec<_>>().chunks(1000) {
+                    let keys = chunk.iter().map(|&id| vec![id.into()]).collect();
+                    let rows = r.multi_lookup(keys, true).await?;
+                    for (&id, rows) in chunk.iter().zip(rows) {
+                        assert_eq!(rows.len(), 1, "article {} is missing", id);
+                        votes.check(id, &rows[0]);
+
+                        let i = id as usize - 1;
+                        let counted = Votes::votes(&rows[0]);
+                        let acked = votes.acked[i].load(Ordering::Acquire);
+                        if counted < acked {
+                            behind += 1;
+                        }
+                    }
+                }
+
+                if behind == 0 {
+                    println!("# verified vote counts for {} articles", articles);
+                    return Ok(());
+                }
+                if attempt < VERIFY_ATTEMPTS {
+                    tokio::time::delay_for(time::Duration::from_millis(500)).await;
+                } else {
+                    bail!(
+                        "{} articles are missing acknowledged votes after {} attempts",
+                        behind,
+                        attempt
+                    );
+                }
+            }
+            unreachable!();
+        })
+    }
 }
 
 impl Service<ReadRequest> for LocalNoria {
@@ -127,15 +236,23 @@ impl Service<ReadRequest> for LocalNoria {
         let len = req.0.len();
         let arg = req
             .0
-            .into_iter()
-            .map(|article_id| vec![(article_id as usize).into()])
+            .iter()
+            .map(|&article_id| vec![(article_id as usize).into()])
             .collect();
+        let ids = req.0;
 
         let fut = self.r.as_mut().unwrap().call((arg, true));
+        let votes = self.votes.clone();
         async move {
             let rows = fut.await?;
             // TODO: assert_eq!(rows.map(|rows| rows.len()), Ok(1));
             assert_eq!(rows.len(), len);
+            if let Some(votes) = votes {
+                for (&id, rows) in ids.iter().zip(rows) {
+                    assert_eq!(rows.len(), 1, "article {} is missing", id);
+                    votes.check(id, &rows[0]);
+                }
+            }
             Ok(())
         }
     }
@@ -153,13 +270,25 @@ impl Service<WriteRequest> for LocalNoria {
     fn call(&mut self, req: WriteRequest) -> Self::Future {
         let data: Vec<TableOperation> = req
             .0
-            .into_iter()
-            .map(|article_id| vec![(article_id as usize).into(), 0.into()].into())
+            .iter()
+            .map(|&article_id| vec![(article_id as usize).into(), 0.into()].into())
             .collect();
 
+        if let Some(ref votes) = self.votes {
+            for &id in &req.0 {
+                votes.issued[id as usize - 1].fetch_add(1, Ordering::AcqRel);
+            }
+        }
+
         let fut = self.w.as_mut().unwrap().call(data);
+        let votes = self.votes.clone();
         async move {
             fut.await?;
+            if let Some(votes) = votes {
+                for &id in &req.0 {
+                    votes.acked[id as usize - 1].fetch_add(1, Ordering::AcqRel);
+                }
+            }
             Ok(())
         }
     }
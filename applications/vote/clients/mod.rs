@@ -1,5 +1,6 @@
 use clap;
 use std::future::Future;
+use std::pin::Pin;
 
 #[derive(Copy, Clone, Debug)]
 pub(crate) struct Parameters {
@@ -16,6 +17,13 @@ where
 {
     type Future: Future<Output = Result<Self, failure::Error>> + Send + 'static;
     fn new(params: Parameters, args: clap::ArgMatches) -> <Self as VoteClient>::Future;
+
+    /// Check that the backend's results agree with the writes issued during the benchmark.
+    ///
+    /// Only backends that keep track of the writes they issue check anything.
+    fn verify(&mut self) -> Pin<Box<dyn Future<Output = Result<(), failure::Error>> + Send + '_>> {
+        Box::pin(async { Ok(()) })
+    }
 }
 
 //pub(crate) mod hybrid;
@@ -117,7 +117,7 @@ where
         .build()
         .unwrap();
 
-    let handle: C = {
+    let mut handle: C = {
         let local_args = local_args.clone();
         // we know that we won't drop the original args until the runtime has exited
         let local_args: clap::ArgMatches<'static> = unsafe { mem::transmute(local_args) };
@@ -168,6 +168,7 @@ where
         ops += gen;
         wops += completed;
     }
+    rt.block_on(handle.verify()).unwrap();
     drop(handle);
     drop(rt);
 
@@ -719,6 +720,13 @@ fn main() {
                         .default_value("none")
                         .help("Choose which views, if any, are placed beyond the materialization_frontier"),
                 )
+                .arg(
+                    Arg::with_name("memory-limit")
+                        .long("memory-limit")
+                        .takes_value(true)
+                        .default_value("0")
+                        .help("Memory, in bytes, available for partially materialized state [0 = unlimited]. Results are checked against the issued votes when set."),
+                )
                 .arg(
                     Arg::with_name("log-dir")
                         .long("log-dir")
@@ -0,0 +1,44 @@
+use crate::data::{DataType, TableOperation};
+use chrono::NaiveDateTime;
+
+/// The kind of write recorded in an [`AuditEntry`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditOperation {
+    /// A row was inserted.
+    Insert,
+    /// A row was deleted.
+    Delete,
+    /// A row was updated.
+    Update,
+    /// A row was inserted, or updated if it already existed.
+    InsertOrUpdate,
+}
+
+impl<'a> From<&'a TableOperation> for AuditOperation {
+    fn from(op: &'a TableOperation) -> Self {
+        match *op {
+            TableOperation::Insert(..) => AuditOperation::Insert,
+            TableOperation::Delete { .. } => AuditOperation::Delete,
+            TableOperation::Update { .. } => AuditOperation::Update,
+            TableOperation::InsertOrUpdate { .. } => AuditOperation::InsertOrUpdate,
+        }
+    }
+}
+
+/// A single write to an audited base table.
+///
+/// Entries are appended by the base table itself as writes arrive, so the log reflects the order
+/// in which the base observed the writes, not the order in which clients issued them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// The identity the writing `Table` handle was tagged with, if any.
+    pub identity: Option<String>,
+    /// When the base table received the write.
+    pub timestamp: NaiveDateTime,
+    /// What kind of write this was.
+    pub operation: AuditOperation,
+    /// The key of the affected row.
+    ///
+    /// For tables without a primary key, this is the full inserted row.
+    pub key: Vec<DataType>,
+}
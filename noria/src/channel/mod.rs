@@ -9,7 +9,7 @@ use std::io::{self, Write};
 use std::net::SocketAddr;
 use std::ops::{Deref, DerefMut};
 use std::sync::mpsc::{self, SendError};
-use std::sync::RwLock;
+use std::sync::{Arc, RwLock};
 use std::{
     pin::Pin,
     task::{Context, Poll},
@@ -20,12 +20,16 @@ use futures_util::sink::{Sink, SinkExt};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use tokio::io::BufWriter;
 
+mod compression;
 pub mod tcp;
 
+pub use self::compression::{Compression, CompressionStats};
 pub use self::tcp::{DualTcpStream, TcpSender};
 
 pub const CONNECTION_FROM_BASE: u8 = 1;
 pub const CONNECTION_FROM_DOMAIN: u8 = 2;
+/// Like `CONNECTION_FROM_DOMAIN`, but every packet on the connection is compressed with LZ4.
+pub const CONNECTION_FROM_DOMAIN_LZ4: u8 = 3;
 
 pub struct Remote;
 pub struct MaybeLocal;
@@ -35,6 +39,7 @@ pub struct DomainConnectionBuilder<D, T> {
     addr: SocketAddr,
     chan: Option<tokio::sync::mpsc::UnboundedSender<T>>,
     is_for_base: bool,
+    compression: Option<(Compression, Arc<CompressionStats>)>,
     _marker: D,
 }
 
@@ -67,6 +72,7 @@ impl<T> DomainConnectionBuilder<Remote, T> {
             chan: None,
             addr,
             is_for_base: true,
+            compression: None,
             _marker: Remote,
         }
     }
@@ -82,6 +88,15 @@ impl<D, T> DomainConnectionBuilder<D, T> {
         self.sport = Some(sport);
         self
     }
+
+    /// Compress the packets sent by `build_async` with `codec` if the connection is remote, and
+    /// record how well they compress in `stats`.
+    ///
+    /// Connections built with `build_sync` are never compressed.
+    pub fn compressed(mut self, codec: Compression, stats: Arc<CompressionStats>) -> Self {
+        self.compression = Some((codec, stats));
+        self
+    }
 }
 
 impl<T> DomainConnectionBuilder<Remote, T>
@@ -103,14 +118,19 @@ where
     }
 
     pub fn build_sync(self) -> io::Result<TcpSender<T>> {
+        let tag = if self.is_for_base {
+            CONNECTION_FROM_BASE
+        } else {
+            CONNECTION_FROM_DOMAIN
+        };
+        self.connect(tag)
+    }
+
+    fn connect(&self, tag: u8) -> io::Result<TcpSender<T>> {
         let mut s = TcpSender::connect_from(self.sport, &self.addr)?;
         {
             let s = s.get_mut();
-            s.write_all(&[if self.is_for_base {
-                CONNECTION_FROM_BASE
-            } else {
-                CONNECTION_FROM_DOMAIN
-            }])?;
+            s.write_all(&[tag])?;
             s.flush()?;
         }
 
@@ -118,6 +138,32 @@ where
     }
 }
 
+impl<T> DomainConnectionBuilder<Remote, T>
+where
+    T: serde::Serialize + 'static + Send,
+{
+    /// Like `build_async`, but compresses every packet if the builder was asked to.
+    pub fn build_async_maybe_compressed(
+        self,
+    ) -> io::Result<Box<dyn Sink<T, Error = bincode::Error> + Send + Unpin>> {
+        let (codec, stats) = match self.compression.clone() {
+            Some(compression) if !self.is_for_base => compression,
+            _ => return self.build_async().map(|c| Box::new(c) as Box<_>),
+        };
+
+        let s = self
+            .connect(CONNECTION_FROM_DOMAIN_LZ4)?
+            .into_inner()
+            .into_inner()?;
+        let w: AsyncBincodeWriter<_, Vec<u8>, _> =
+            AsyncBincodeWriter::from(BufWriter::new(tokio::net::TcpStream::from_std(s)?))
+                .for_async();
+        Ok(Box::new(w.with(move |t: T| {
+            futures_util::future::ready(compression::compress(codec, &t, &stats))
+        })) as Box<_>)
+    }
+}
+
 pub trait Sender {
     type Item;
 
@@ -155,10 +201,10 @@ where
                 chan: None,
                 addr: self.addr,
                 is_for_base: false,
+                compression: self.compression,
                 _marker: Remote,
             }
-            .build_async()
-            .map(|c| Box::new(c) as Box<_>)
+            .build_async_maybe_compressed()
         }
     }
 
@@ -171,6 +217,7 @@ where
                 chan: None,
                 addr: self.addr,
                 is_for_base: false,
+                compression: None,
                 _marker: Remote,
             }
             .build_sync()
@@ -261,6 +308,9 @@ struct ChannelCoordinatorInner<K: Eq + Hash + Clone, T> {
     addrs: HashMap<K, SocketAddr>,
     /// Map from key to channel sender for local connections.
     locals: HashMap<K, tokio::sync::mpsc::UnboundedSender<T>>,
+    /// Map from key to the version of the route in `addrs`, for routes announced by the
+    /// controller.
+    versions: HashMap<K, u64>,
 }
 
 pub struct ChannelCoordinator<K: Eq + Hash + Clone, T> {
@@ -279,6 +329,7 @@ impl<K: Eq + Hash + Clone, T> ChannelCoordinator<K, T> {
             inner: RwLock::new(ChannelCoordinatorInner {
                 addrs: Default::default(),
                 locals: Default::default(),
+                versions: Default::default(),
             }),
         }
     }
@@ -288,6 +339,50 @@ impl<K: Eq + Hash + Clone, T> ChannelCoordinator<K, T> {
         inner.addrs.insert(key, addr);
     }
 
+    /// Route `key` to `addr` as of the given route version.
+    ///
+    /// Routes are only ever replaced by newer versions, so an announcement that arrives after a
+    /// more recent one cannot reinstate a stale address. If the route moves away from a
+    /// local channel, that channel is forgotten. Returns whether the route was updated.
+    pub fn insert_remote_versioned(&self, key: K, addr: SocketAddr, version: u64) -> bool {
+        let mut inner = self.inner.write().unwrap();
+        if let Some(&known) = inner.versions.get(&key) {
+            if known >= version {
+                return false;
+            }
+        }
+
+        if inner.addrs.get(&key).map(|&a| a != addr).unwrap_or(false) {
+            inner.locals.remove(&key);
+        }
+        inner.addrs.insert(key.clone(), addr);
+        inner.versions.insert(key, version);
+        true
+    }
+
+    /// Forget the versions of all known routes.
+    ///
+    /// Route versions are only comparable within one controller's lifetime, so this must be
+    /// called when a new controller takes over.
+    pub fn clear_versions(&self) {
+        self.inner.write().unwrap().versions.clear();
+    }
+
+    /// The version of the route to `key`, or 0 if no versioned route is known.
+    pub fn version<Q>(&self, key: &Q) -> u64
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner
+            .read()
+            .unwrap()
+            .versions
+            .get(key)
+            .cloned()
+            .unwrap_or(0)
+    }
+
     pub fn insert_local(&self, key: K, chan: tokio::sync::mpsc::UnboundedSender<T>) {
         let mut inner = self.inner.write().unwrap();
         inner.locals.insert(key, chan);
@@ -328,7 +423,43 @@ impl<K: Eq + Hash + Clone, T> ChannelCoordinator<K, T> {
             addr: *inner.addrs.get(key)?,
             chan: inner.locals.get(key).cloned(),
             is_for_base: false,
+            compression: None,
             _marker: MaybeLocal,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versioned_routes() {
+        let cc = ChannelCoordinator::<usize, ()>::new();
+        let a: SocketAddr = "127.0.0.1:1000".parse().unwrap();
+        let b: SocketAddr = "127.0.0.1:2000".parse().unwrap();
+
+        assert_eq!(cc.version(&0), 0);
+        assert!(cc.insert_remote_versioned(0, a, 2));
+        assert_eq!(cc.get_addr(&0), Some(a));
+        assert_eq!(cc.version(&0), 2);
+
+        // stale and duplicate announcements are ignored
+        assert!(!cc.insert_remote_versioned(0, b, 1));
+        assert!(!cc.insert_remote_versioned(0, b, 2));
+        assert_eq!(cc.get_addr(&0), Some(a));
+
+        // a route that moves away drops the local channel
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        cc.insert_local(0, tx);
+        assert_eq!(cc.is_local(&0), Some(true));
+        assert!(cc.insert_remote_versioned(0, b, 3));
+        assert_eq!(cc.get_addr(&0), Some(b));
+        assert_eq!(cc.is_local(&0), None);
+
+        // a new controller starts over
+        cc.clear_versions();
+        assert!(cc.insert_remote_versioned(0, a, 1));
+        assert_eq!(cc.get_addr(&0), Some(a));
+    }
+}
@@ -3,7 +3,7 @@ use std::io::{self, Write};
 use std::marker::PhantomData;
 use std::net::{Ipv4Addr, SocketAddr};
 
-use crate::Tagged;
+use crate::{Tagged, WriteAck};
 use async_bincode::{AsyncBincodeStream, AsyncDestination};
 use bufstream::BufStream;
 use byteorder::{NetworkEndian, WriteBytesExt};
@@ -25,6 +25,9 @@ pub enum SendError {
     IoError(#[cause] io::Error),
     #[fail(display = "channel has previously encountered an error")]
     Poisoned,
+    /// The receiver is not keeping up, and too much is already queued for it.
+    #[fail(display = "too many messages are queued for the receiver")]
+    Backpressure,
 }
 
 impl From<bincode::Error> for SendError {
@@ -39,6 +42,15 @@ impl From<io::Error> for SendError {
     }
 }
 
+impl From<SendError> for io::Error {
+    fn from(e: SendError) -> Self {
+        match e {
+            SendError::IoError(e) => e,
+            e => io::Error::new(io::ErrorKind::Other, e.to_string()),
+        }
+    }
+}
+
 macro_rules! poisoning_try {
     ($self_:ident, $e:expr) => {
         match $e {
@@ -142,11 +154,15 @@ pub enum RecvError {
 
 #[pin_project]
 pub enum DualTcpStream<S, T, T2, D> {
-    Passthrough(#[pin] AsyncBincodeStream<S, T, Tagged<()>, D>),
+    Passthrough(#[pin] AsyncBincodeStream<S, T, Tagged<WriteAck>, D>),
     Upgrade(
-        #[pin] AsyncBincodeStream<S, T2, Tagged<()>, D>,
+        #[pin] AsyncBincodeStream<S, T2, Tagged<WriteAck>, D>,
         Box<dyn FnMut(T2) -> T + Send + Sync>,
     ),
+    Compressed(
+        #[pin] AsyncBincodeStream<S, Vec<u8>, Tagged<WriteAck>, D>,
+        super::Compression,
+    ),
 }
 
 impl<S, T, T2> From<S> for DualTcpStream<S, T, T2, AsyncDestination> {
@@ -157,24 +173,32 @@ impl<S, T, T2> From<S> for DualTcpStream<S, T, T2, AsyncDestination> {
 
 impl<S, T, T2> DualTcpStream<S, T, T2, AsyncDestination> {
     pub fn upgrade<F: 'static + FnMut(T2) -> T + Send + Sync>(stream: S, f: F) -> Self {
-        let s: AsyncBincodeStream<S, T2, Tagged<()>, AsyncDestination> =
+        let s: AsyncBincodeStream<S, T2, Tagged<WriteAck>, AsyncDestination> =
             AsyncBincodeStream::from(stream).for_async();
         DualTcpStream::Upgrade(s, Box::new(f))
     }
 
+    /// A stream whose messages were each compressed with `codec` by the sender.
+    pub fn compressed(stream: S, codec: super::Compression) -> Self {
+        DualTcpStream::Compressed(AsyncBincodeStream::from(stream).for_async(), codec)
+    }
+
     pub fn get_ref(&self) -> &S {
         match *self {
             DualTcpStream::Passthrough(ref abs) => abs.get_ref(),
             DualTcpStream::Upgrade(ref abs, _) => abs.get_ref(),
+            DualTcpStream::Compressed(ref abs, _) => abs.get_ref(),
         }
     }
 }
 
-impl<S, T, T2, D> Sink<Tagged<()>> for DualTcpStream<S, T, T2, D>
+impl<S, T, T2, D> Sink<Tagged<WriteAck>> for DualTcpStream<S, T, T2, D>
 where
     S: AsyncWrite,
-    AsyncBincodeStream<S, T, Tagged<()>, D>: Sink<Tagged<()>, Error = bincode::Error>,
-    AsyncBincodeStream<S, T2, Tagged<()>, D>: Sink<Tagged<()>, Error = bincode::Error>,
+    AsyncBincodeStream<S, T, Tagged<WriteAck>, D>: Sink<Tagged<WriteAck>, Error = bincode::Error>,
+    AsyncBincodeStream<S, T2, Tagged<WriteAck>, D>: Sink<Tagged<WriteAck>, Error = bincode::Error>,
+    AsyncBincodeStream<S, Vec<u8>, Tagged<WriteAck>, D>:
+        Sink<Tagged<WriteAck>, Error = bincode::Error>,
 {
     type Error = bincode::Error;
 
@@ -184,15 +208,17 @@ where
         match self.project() {
             DualTcpStream::Passthrough(abs) => abs.poll_ready(cx),
             DualTcpStream::Upgrade(abs, _) => abs.poll_ready(cx),
+            DualTcpStream::Compressed(abs, _) => abs.poll_ready(cx),
         }
     }
 
     #[project]
-    fn start_send(self: Pin<&mut Self>, item: Tagged<()>) -> Result<(), Self::Error> {
+    fn start_send(self: Pin<&mut Self>, item: Tagged<WriteAck>) -> Result<(), Self::Error> {
         #[project]
         match self.project() {
             DualTcpStream::Passthrough(abs) => abs.start_send(item),
             DualTcpStream::Upgrade(abs, _) => abs.start_send(item),
+            DualTcpStream::Compressed(abs, _) => abs.start_send(item),
         }
     }
 
@@ -202,6 +228,7 @@ where
         match self.project() {
             DualTcpStream::Passthrough(abs) => abs.poll_flush(cx),
             DualTcpStream::Upgrade(abs, _) => abs.poll_flush(cx),
+            DualTcpStream::Compressed(abs, _) => abs.poll_flush(cx),
         }
     }
 
@@ -211,6 +238,7 @@ where
         match self.project() {
             DualTcpStream::Passthrough(abs) => abs.poll_close(cx),
             DualTcpStream::Upgrade(abs, _) => abs.poll_close(cx),
+            DualTcpStream::Compressed(abs, _) => abs.poll_close(cx),
         }
     }
 }
@@ -220,8 +248,10 @@ where
     for<'a> T: Deserialize<'a>,
     for<'a> T2: Deserialize<'a>,
     S: AsyncRead,
-    AsyncBincodeStream<S, T, Tagged<()>, D>: Stream<Item = Result<T, bincode::Error>>,
-    AsyncBincodeStream<S, T2, Tagged<()>, D>: Stream<Item = Result<T2, bincode::Error>>,
+    AsyncBincodeStream<S, T, Tagged<WriteAck>, D>: Stream<Item = Result<T, bincode::Error>>,
+    AsyncBincodeStream<S, T2, Tagged<WriteAck>, D>: Stream<Item = Result<T2, bincode::Error>>,
+    AsyncBincodeStream<S, Vec<u8>, Tagged<WriteAck>, D>:
+        Stream<Item = Result<Vec<u8>, bincode::Error>>,
 {
     type Item = Result<T, bincode::Error>;
 
@@ -235,6 +265,10 @@ where
             DualTcpStream::Upgrade(abr, upgrade) => {
                 Poll::Ready(ready!(abr.poll_next(cx)).transpose()?.map(upgrade).map(Ok))
             }
+            DualTcpStream::Compressed(abr, codec) => Poll::Ready(
+                ready!(abr.poll_next(cx))
+                    .map(|frame| frame.and_then(|f| super::compression::decompress(*codec, &f))),
+            ),
         }
     }
 }
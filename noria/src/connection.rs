@@ -0,0 +1,38 @@
+use std::net::SocketAddr;
+use std::time::SystemTime;
+
+/// What a client connection to a worker is used for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConnectionKind {
+    /// Reads from the views whose readers the worker hosts.
+    Read,
+    /// Writes to a shard of a base table that runs on the worker.
+    Write,
+}
+
+/// A client that is connected to a worker.
+///
+/// See `ControllerHandle::connections`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClientConnection {
+    /// The worker the client is connected to.
+    pub worker: SocketAddr,
+    /// Identifies the connection among those to the same worker.
+    pub id: u64,
+    /// The address the client connected from.
+    pub peer: SocketAddr,
+    /// What the connection is used for.
+    pub kind: ConnectionKind,
+    /// The identity that the client last attached to its writes, if any.
+    ///
+    /// See `Table::set_identity`.
+    pub identity: Option<String>,
+    /// When the client connected.
+    pub connected_at: SystemTime,
+    /// The number of requests the client has sent over the connection.
+    pub ops: u64,
+    /// The number of bytes the worker has received over the connection.
+    pub bytes_in: u64,
+    /// The number of bytes the worker has sent over the connection.
+    pub bytes_out: u64,
+}
@@ -1,5 +1,5 @@
 use std::collections::BTreeMap;
-use std::sync::{Condvar, Mutex};
+use std::sync::{Condvar, Mutex, MutexGuard};
 
 use failure::Error;
 use serde::de::DeserializeOwned;
@@ -12,6 +12,7 @@ use super::CONTROLLER_KEY;
 struct LocalAuthorityInner {
     keys: BTreeMap<String, Vec<u8>>,
     epoch: Epoch,
+    reachable: bool,
 }
 
 pub struct LocalAuthority {
@@ -31,14 +32,41 @@ impl LocalAuthority {
             inner: Mutex::new(LocalAuthorityInner {
                 keys: BTreeMap::default(),
                 epoch: Epoch(0),
+                reachable: true,
             }),
             cv: Condvar::new(),
         }
     }
+
+    /// Make every operation on the authority fail (or succeed again), as if it could not be
+    /// reached. This is useful for testing how a deployment copes with losing its authority.
+    pub fn set_reachable(&self, reachable: bool) {
+        self.inner.lock().unwrap().reachable = reachable;
+        self.cv.notify_all();
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, LocalAuthorityInner>, Error> {
+        let inner = self.inner.lock().unwrap();
+        if !inner.reachable {
+            bail!("authority is unreachable");
+        }
+        Ok(inner)
+    }
+
+    fn wait<'a>(
+        &self,
+        inner: MutexGuard<'a, LocalAuthorityInner>,
+    ) -> Result<MutexGuard<'a, LocalAuthorityInner>, Error> {
+        let inner = self.cv.wait(inner).unwrap();
+        if !inner.reachable {
+            bail!("authority is unreachable");
+        }
+        Ok(inner)
+    }
 }
 impl Authority for LocalAuthority {
     fn become_leader(&self, payload_data: Vec<u8>) -> Result<Option<Epoch>, Error> {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.lock()?;
         if !inner.keys.contains_key(CONTROLLER_KEY) {
             inner.keys.insert(CONTROLLER_KEY.to_owned(), payload_data);
             self.cv.notify_all();
@@ -49,7 +77,7 @@ impl Authority for LocalAuthority {
     }
 
     fn surrender_leadership(&self) -> Result<(), Error> {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.lock()?;
         assert!(inner.keys.remove(CONTROLLER_KEY).is_some());
         inner.epoch = Epoch(inner.epoch.0 + 1);
         self.cv.notify_all();
@@ -57,9 +85,9 @@ impl Authority for LocalAuthority {
     }
 
     fn get_leader(&self) -> Result<(Epoch, Vec<u8>), Error> {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.lock()?;
         while !inner.keys.contains_key(CONTROLLER_KEY) {
-            inner = self.cv.wait(inner).unwrap();
+            inner = self.wait(inner)?;
         }
         Ok((
             inner.epoch,
@@ -68,7 +96,7 @@ impl Authority for LocalAuthority {
     }
 
     fn try_get_leader(&self) -> Result<Option<(Epoch, Vec<u8>)>, Error> {
-        let inner = self.inner.lock().unwrap();
+        let inner = self.lock()?;
 
         Ok(inner
             .keys
@@ -78,9 +106,9 @@ impl Authority for LocalAuthority {
     }
 
     fn await_new_epoch(&self, epoch: Epoch) -> Result<Option<(Epoch, Vec<u8>)>, Error> {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.lock()?;
         while inner.epoch == epoch && inner.keys.contains_key(CONTROLLER_KEY) {
-            inner = self.cv.wait(inner).unwrap();
+            inner = self.wait(inner)?;
         }
 
         Ok(inner
@@ -91,7 +119,7 @@ impl Authority for LocalAuthority {
     }
 
     fn try_read(&self, path: &str) -> Result<Option<Vec<u8>>, Error> {
-        let inner = self.inner.lock().unwrap();
+        let inner = self.lock()?;
         Ok(inner.keys.get(path).cloned())
     }
 
@@ -100,7 +128,7 @@ impl Authority for LocalAuthority {
         F: FnMut(Option<P>) -> Result<P, E>,
         P: Serialize + DeserializeOwned,
     {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.lock()?;
         let r = f(inner
             .keys
             .get(path)
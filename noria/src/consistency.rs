@@ -0,0 +1,24 @@
+use crate::data::DataType;
+
+/// The outcome of checking a sample of a view's keys against a recomputation from upstream.
+///
+/// See `ControllerHandle::check_consistency` for how the check is performed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ConsistencyEvent {
+    /// Every sampled key of `view` held the same rows as its recomputation.
+    Consistent {
+        /// The view that was checked.
+        view: String,
+        /// The number of keys that were compared.
+        checked: usize,
+    },
+    /// Some sampled keys of `view` held different rows than their recomputation.
+    Diverged {
+        /// The view that was checked.
+        view: String,
+        /// The number of keys that were compared.
+        checked: usize,
+        /// The keys whose rows differed.
+        keys: Vec<Vec<DataType>>,
+    },
+}
@@ -1,10 +1,15 @@
 use crate::consensus::{self, Authority};
 use crate::debug::stats;
 use crate::table::{Table, TableBuilder, TableRpc};
-use crate::view::{View, ViewBuilder, ViewRpc};
-use crate::ActivationResult;
+use crate::view::{ReplayPriority, View, ViewBuilder, ViewRefresh, ViewRpc};
+use crate::{
+    ActivationResult, AuditEntry, BaseExport, BaseVerification, ClientConnection, ConsistencyEvent,
+    DataType, DomainFailure, Failover, KeySample, NodeSample, QueryInfo, ReplayCorruption,
+    ScalingEvent, UpgradeEvent, WorkerFailure,
+};
 use failure::{self, ResultExt};
 use futures_util::future;
+use futures_util::stream::Stream;
 use petgraph::graph::NodeIndex;
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap};
@@ -18,6 +23,22 @@ use std::{
 use tower_buffer::Buffer;
 use tower_service::Service;
 
+/// The keys to pre-populate a view with using [`ControllerHandle::warm_view`].
+#[derive(Clone, Debug)]
+pub enum WarmKeys {
+    /// Warm exactly these keys.
+    Keys(Vec<Vec<DataType>>),
+    /// Warm every distinct value of the given columns of a base table.
+    ///
+    /// The base table must be fully materialized.
+    Base {
+        /// The name of the base table.
+        table: String,
+        /// The columns of the base table whose values make up the view's key, in key order.
+        columns: Vec<usize>,
+    },
+}
+
 /// Describes a running controller instance.
 ///
 /// A serialized version of this struct is stored in ZooKeeper so that clients can reach the
@@ -34,18 +55,119 @@ pub struct ControllerDescriptor {
 struct Controller<A> {
     authority: Arc<A>,
     client: hyper::Client<hyper::client::HttpConnector>,
+    /// The external address of the controller the authority last pointed us to.
+    leader: Arc<Mutex<Option<SocketAddr>>>,
+    policy: Arc<Mutex<RpcPolicy>>,
+}
+
+/// How many times an RPC is tried again by default before it gives up.
+///
+/// With the backoff between retries, this waits for about a minute for a controller to be elected.
+const DEFAULT_RPC_RETRIES: usize = 60;
+
+/// How long RPCs to the controller wait for an answer, and how often they are tried.
+#[derive(Clone, Debug)]
+struct RpcPolicy {
+    /// How long each attempt at an RPC waits for the controller to answer.
+    timeout: Option<Duration>,
+    /// Timeouts for specific RPCs, in place of `timeout`.
+    timeouts: HashMap<String, Option<Duration>>,
+    /// How many times an RPC is tried again before it gives up.
+    retries: usize,
+}
+
+impl Default for RpcPolicy {
+    fn default() -> Self {
+        RpcPolicy {
+            timeout: None,
+            timeouts: HashMap::new(),
+            retries: DEFAULT_RPC_RETRIES,
+        }
+    }
+}
+
+impl RpcPolicy {
+    fn timeout(&self, path: &str) -> Option<Duration> {
+        self.timeouts.get(path).cloned().unwrap_or(self.timeout)
+    }
+}
+
+/// Why an attempt at an RPC did not get an answer.
+enum Unanswered {
+    /// The controller could not be found or reached.
+    Unreachable(String),
+    /// The controller did not answer in time.
+    TimedOut,
+    /// The connection to the controller was lost after the request was sent.
+    Interrupted(String),
+    /// The controller is not ready to answer yet.
+    NotReady,
+}
+
+impl Unanswered {
+    /// Whether the controller may have carried out the attempt.
+    fn maybe_done(&self) -> bool {
+        match *self {
+            Unanswered::TimedOut | Unanswered::Interrupted(_) => true,
+            Unanswered::Unreachable(_) | Unanswered::NotReady => false,
+        }
+    }
+}
+
+/// An RPC to the controller that gave up without an answer.
+///
+/// See `ControllerHandle::set_rpc_retries`.
+#[derive(Debug, Fail)]
+pub enum RpcError {
+    /// The controller did not answer an attempt at the RPC before its timeout.
+    ///
+    /// Unless the RPC only reads, the controller may still have carried it out.
+    #[fail(display = "rpc call to {} timed out {} times", rpc, attempts)]
+    TimedOut {
+        /// The name of the RPC.
+        rpc: String,
+        /// The number of times the RPC was tried.
+        attempts: usize,
+    },
+    /// The connection to the controller was lost while it handled the RPC, which it may or may
+    /// not have carried out.
+    #[fail(display = "rpc call to {} was interrupted: {}", rpc, reason)]
+    Interrupted {
+        /// The name of the RPC.
+        rpc: String,
+        /// The number of times the RPC was tried.
+        attempts: usize,
+        /// Why the connection was lost.
+        reason: String,
+    },
+    /// No controller could be reached, or none was ready to answer.
+    #[fail(display = "rpc call to {} failed {} times: {}", rpc, attempts, reason)]
+    Unavailable {
+        /// The name of the RPC.
+        rpc: String,
+        /// The number of times the RPC was tried.
+        attempts: usize,
+        /// Why the last attempt failed.
+        reason: String,
+    },
 }
 
 #[derive(Debug)]
 struct ControllerRequest {
     path: &'static str,
+    idempotent: bool,
     request: Vec<u8>,
 }
 
 impl ControllerRequest {
-    fn new<Q: Serialize>(path: &'static str, r: Q) -> Result<Self, serde_json::Error> {
+    fn new<Q: Serialize>(
+        path: &'static str,
+        idempotent: bool,
+        r: Q,
+    ) -> Result<Self, serde_json::Error> {
         Ok(ControllerRequest {
             path,
+            idempotent,
             request: serde_json::to_vec(&r)?,
         })
     }
@@ -55,7 +177,7 @@ impl<A> Service<ControllerRequest> for Controller<A>
 where
     A: 'static + Authority,
 {
-    type Response = hyper::body::Bytes;
+    type Response = Result<hyper::body::Bytes, RpcError>;
     type Error = failure::Error;
 
     type Future = impl Future<Output = Result<Self::Response, Self::Error>> + Send;
@@ -67,58 +189,151 @@ where
     fn call(&mut self, req: ControllerRequest) -> Self::Future {
         let client = self.client.clone();
         let auth = self.authority.clone();
+        let leader = self.leader.clone();
         let path = req.path;
+        let idempotent = req.idempotent;
         let body = req.request;
+        let (timeout, retries) = {
+            let policy = self.policy.lock().unwrap();
+            (policy.timeout(path), policy.retries)
+        };
 
         async move {
             let mut url = None;
+            let mut attempts = 0;
+            let mut backoff = Duration::from_millis(100);
 
             loop {
+                attempts += 1;
+                let mut unanswered = None;
                 if url.is_none() {
                     // TODO: don't do blocking things here...
-                    // TODO: cache this value?
-                    let descriptor: ControllerDescriptor = serde_json::from_slice(
-                        &auth.get_leader().context("failed to get current leader")?.1,
-                    )
-                    .context("failed to deserialize authority reply")?;
-
-                    url = Some(format!("http://{}/{}", descriptor.external_addr, path));
+                    match auth
+                        .try_get_leader()
+                        .context("failed to get current leader")
+                    {
+                        Ok(Some((_, descriptor))) => {
+                            let descriptor: ControllerDescriptor =
+                                serde_json::from_slice(&descriptor)
+                                    .context("failed to deserialize authority reply")?;
+                            *leader.lock().unwrap() = Some(descriptor.external_addr);
+                            url = Some(format!("http://{}/{}", descriptor.external_addr, path));
+                        }
+                        Ok(None) => {
+                            // the old leader is gone, and no new one has been elected yet
+                            unanswered = Some(Unanswered::Unreachable(
+                                "no controller is currently elected".to_owned(),
+                            ));
+                        }
+                        Err(e) => {
+                            // keep talking to the last controller we knew of while the authority
+                            // cannot be reached
+                            let known = *leader.lock().unwrap();
+                            match known {
+                                Some(addr) => url = Some(format!("http://{}/{}", addr, path)),
+                                None => unanswered = Some(Unanswered::Unreachable(e.to_string())),
+                            }
+                        }
+                    }
                 }
 
-                let r = hyper::Request::post(url.as_ref().unwrap())
-                    .body(hyper::Body::from(body.clone()))
-                    .unwrap();
-
-                let res = client
-                    .request(r)
-                    .await
-                    .map_err(|he| failure::Error::from(he).context("hyper request failed"))?;
-
-                let status = res.status();
-                let body = hyper::body::to_bytes(res.into_body())
-                    .await
-                    .map_err(|he| failure::Error::from(he).context("hyper response failed"))?;
-
-                match status {
-                    hyper::StatusCode::OK => return Ok(body),
-                    hyper::StatusCode::INTERNAL_SERVER_ERROR => bail!(
-                        "rpc call to {} failed: {}",
-                        path,
-                        String::from_utf8_lossy(&*body)
-                    ),
-                    s => {
-                        if s == hyper::StatusCode::SERVICE_UNAVAILABLE {
-                            url = None;
-                        }
+                if let Some(ref u) = url {
+                    let attempt = Self::attempt(&client, u, body.clone(), path);
+                    let answer = match timeout {
+                        Some(timeout) => match tokio::time::timeout(timeout, attempt).await {
+                            Ok(answer) => answer?,
+                            Err(_) => Err(Unanswered::TimedOut),
+                        },
+                        None => attempt.await?,
+                    };
+                    match answer {
+                        Ok(body) => return Ok(Ok(body)),
+                        Err(u) => unanswered = Some(u),
+                    }
+                }
 
-                        tokio::time::delay_for(Duration::from_millis(100)).await;
+                let unanswered = unanswered.unwrap();
+                match unanswered {
+                    Unanswered::NotReady => {}
+                    _ => {
+                        // the leader may have moved; ask the authority again
+                        url = None;
                     }
                 }
+                // an attempt that the controller may have carried out must not be repeated
+                // unless doing so is harmless.
+                let repeatable = !unanswered.maybe_done() || idempotent;
+                if attempts > retries || !repeatable {
+                    let rpc = path.to_owned();
+                    return Ok(Err(match unanswered {
+                        Unanswered::TimedOut => RpcError::TimedOut { rpc, attempts },
+                        Unanswered::Interrupted(reason) => RpcError::Interrupted {
+                            rpc,
+                            attempts,
+                            reason,
+                        },
+                        Unanswered::Unreachable(reason) => RpcError::Unavailable {
+                            rpc,
+                            attempts,
+                            reason,
+                        },
+                        Unanswered::NotReady => RpcError::Unavailable {
+                            rpc,
+                            attempts,
+                            reason: "no controller was ready to answer".to_owned(),
+                        },
+                    }));
+                }
+
+                // clients that lost the same controller should not all come back at once
+                let jitter = 0.5 + rand::random::<f64>() / 2.0;
+                tokio::time::delay_for(backoff.mul_f64(jitter)).await;
+                backoff = std::cmp::min(backoff * 2, Duration::from_secs(1));
             }
         }
     }
 }
 
+impl<A> Controller<A> {
+    /// Make a single attempt at an RPC to the controller at `url`.
+    async fn attempt(
+        client: &hyper::Client<hyper::client::HttpConnector>,
+        url: &str,
+        body: Vec<u8>,
+        path: &'static str,
+    ) -> Result<Result<hyper::body::Bytes, Unanswered>, failure::Error> {
+        let r = hyper::Request::post(url)
+            .body(hyper::Body::from(body))
+            .unwrap();
+
+        let res = match client.request(r).await {
+            Ok(res) => res,
+            Err(e) if e.is_connect() => return Ok(Err(Unanswered::Unreachable(e.to_string()))),
+            Err(e) => return Ok(Err(Unanswered::Interrupted(e.to_string()))),
+        };
+
+        let status = res.status();
+        let body = match hyper::body::to_bytes(res.into_body()).await {
+            Ok(body) => body,
+            Err(e) => return Ok(Err(Unanswered::Interrupted(e.to_string()))),
+        };
+
+        match status {
+            hyper::StatusCode::OK => Ok(Ok(body)),
+            hyper::StatusCode::INTERNAL_SERVER_ERROR => bail!(
+                "rpc call to {} failed: {}",
+                path,
+                String::from_utf8_lossy(&*body)
+            ),
+            hyper::StatusCode::SERVICE_UNAVAILABLE => Ok(Err(Unanswered::Unreachable(
+                "the controller is not available".to_owned(),
+            ))),
+            // the server is not (yet) the controller, or did not take the request.
+            _ => Ok(Err(Unanswered::NotReady)),
+        }
+    }
+}
+
 /// A handle to a Noria controller.
 ///
 /// This handle is the primary mechanism for interacting with a running Noria instance, and lets
@@ -144,6 +359,7 @@ where
     handle: Buffer<Controller<A>, ControllerRequest>,
     domains: Arc<Mutex<HashMap<(SocketAddr, usize), TableRpc>>>,
     views: Arc<Mutex<HashMap<(SocketAddr, usize), ViewRpc>>>,
+    policy: Arc<Mutex<RpcPolicy>>,
     tracer: tracing::Dispatch,
 }
 
@@ -156,6 +372,7 @@ where
             handle: self.handle.clone(),
             domains: self.domains.clone(),
             views: self.views.clone(),
+            policy: self.policy.clone(),
             tracer: self.tracer.clone(),
         }
     }
@@ -176,14 +393,14 @@ type RpcFuture<A, R> = impl Future<Output = Result<R, failure::Error>>;
 
 // Needed b/c of https://github.com/rust-lang/rust/issues/65442
 async fn finalize<R, E>(
-    fut: impl Future<Output = Result<hyper::body::Bytes, E>>,
+    fut: impl Future<Output = Result<Result<hyper::body::Bytes, RpcError>, E>>,
     err: &'static str,
 ) -> Result<R, failure::Error>
 where
     for<'de> R: Deserialize<'de>,
     E: std::fmt::Display + Send + Sync + 'static,
 {
-    let body: hyper::body::Bytes = fut.await.map_err(failure::Context::new).context(err)?;
+    let body: hyper::body::Bytes = fut.await.map_err(failure::Context::new).context(err)??;
 
     serde_json::from_slice::<R>(&body)
         .context("failed to response")
@@ -196,6 +413,7 @@ impl<A: Authority + 'static> ControllerHandle<A> {
     pub async fn make(authority: Arc<A>) -> Result<Self, failure::Error> {
         // need to use lazy otherwise current executor won't be known
         let tracer = tracing::dispatcher::get_default(|d| d.clone());
+        let policy = Arc::new(Mutex::new(RpcPolicy::default()));
         Ok(ControllerHandle {
             views: Default::default(),
             domains: Default::default(),
@@ -203,13 +421,47 @@ impl<A: Authority + 'static> ControllerHandle<A> {
                 Controller {
                     authority,
                     client: hyper::Client::new(),
+                    leader: Default::default(),
+                    policy: policy.clone(),
                 },
                 1,
             ),
+            policy,
             tracer,
         })
     }
 
+    /// Give up on attempts at RPCs through this handle that the controller does not answer within
+    /// `timeout`, and try them again.
+    ///
+    /// After an attempt times out, the current controller is looked up again, since the old one
+    /// may have failed. Only RPCs that merely read, or that are harmless to repeat, are tried
+    /// again after a timeout; others fail with `RpcError::TimedOut`, since the controller may
+    /// still carry them out. By default, attempts wait for as long as it takes the controller to
+    /// answer, which can be a long time for migrations. This applies to all clones of the handle.
+    pub fn set_rpc_timeout(&mut self, timeout: Option<Duration>) {
+        self.policy.lock().unwrap().timeout = timeout;
+    }
+
+    /// Like `Self::set_rpc_timeout`, but only for the RPC with the given name, such as
+    /// `"extend_recipe"`.
+    pub fn set_rpc_timeout_for(&mut self, rpc: &str, timeout: Option<Duration>) {
+        let mut policy = self.policy.lock().unwrap();
+        policy.timeouts.insert(rpc.to_owned(), timeout);
+    }
+
+    /// Give up on RPCs through this handle after trying them again `retries` times.
+    ///
+    /// RPCs are tried again when no controller can be reached and when the controller is not
+    /// ready to answer. RPCs that are harmless to repeat are also tried again when an attempt
+    /// times out or loses its connection. Each retry waits for a randomly chosen time between
+    /// half of and the whole backoff, which starts at 100ms and doubles up to a second. An RPC
+    /// that gives up fails with an `RpcError`. By default, RPCs are tried again 60 times, which
+    /// takes about a minute. This applies to all clones of the handle.
+    pub fn set_rpc_retries(&mut self, retries: usize) {
+        self.policy.lock().unwrap().retries = retries;
+    }
+
     /// Check that the `ControllerHandle` can accept another request.
     ///
     /// Note that this method _must_ return `Poll::Ready` before any other methods that return
@@ -249,13 +501,13 @@ impl<A: Authority + 'static> ControllerHandle<A> {
     ) -> impl Future<Output = Result<BTreeMap<String, NodeIndex>, failure::Error>> {
         let fut = self
             .handle
-            .call(ControllerRequest::new("inputs", &()).unwrap());
+            .call(ControllerRequest::new("inputs", true, &()).unwrap());
 
         async move {
             let body: hyper::body::Bytes = fut
                 .await
                 .map_err(failure::Context::new)
-                .context("failed to fetch inputs")?;
+                .context("failed to fetch inputs")??;
 
             serde_json::from_slice(&body)
                 .context("couldn't parse input response")
@@ -273,13 +525,13 @@ impl<A: Authority + 'static> ControllerHandle<A> {
     ) -> impl Future<Output = Result<BTreeMap<String, NodeIndex>, failure::Error>> {
         let fut = self
             .handle
-            .call(ControllerRequest::new("outputs", &()).unwrap());
+            .call(ControllerRequest::new("outputs", true, &()).unwrap());
 
         async move {
             let body: hyper::body::Bytes = fut
                 .await
                 .map_err(failure::Context::new)
-                .context("failed to fetch outputs")?;
+                .context("failed to fetch outputs")??;
 
             serde_json::from_slice(&body)
                 .context("couldn't parse output response")
@@ -287,6 +539,16 @@ impl<A: Authority + 'static> ControllerHandle<A> {
         }
     }
 
+    /// Describe every query installed in the recipe that has a view, ordered by name.
+    ///
+    /// Unlike `Self::outputs`, this also reports each query's SQL text, how its view is keyed and
+    /// sharded, whether it can currently serve reads, and how much memory its view uses.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn queries(&mut self) -> impl Future<Output = Result<Vec<QueryInfo>, failure::Error>> {
+        self.rpc("queries", true, (), "failed to describe queries")
+    }
+
     /// Obtain a `View` that allows you to query the given external view.
     ///
     /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
@@ -301,15 +563,39 @@ impl<A: Authority + 'static> ControllerHandle<A> {
         let name = name.to_string();
         let fut = self
             .handle
-            .call(ControllerRequest::new("view_builder", &name).unwrap());
+            .call(ControllerRequest::new("view_builder", true, &name).unwrap());
+
+        // the view asks again where its readers are, since read replicas come and go
+        let handle = self.clone();
+        let view = name.clone();
+        let refresh: ViewRefresh = Arc::new(move || {
+            let mut handle = handle.clone();
+            let view = view.clone();
+            Box::pin(async move {
+                handle.ready().await?;
+                handle
+                    .rpc::<_, Option<ViewBuilder>>(
+                        "view_builder",
+                        true,
+                        view,
+                        "failed to refresh view builder",
+                    )
+                    .await
+            })
+        });
+
         async move {
             let body: hyper::body::Bytes = fut
                 .await
                 .map_err(failure::Context::new)
-                .context("failed to fetch view builder")?;
+                .context("failed to fetch view builder")??;
 
             match serde_json::from_slice::<Option<ViewBuilder>>(&body) {
-                Ok(Some(vb)) => Ok(vb.build(views)?),
+                Ok(Some(vb)) => {
+                    let mut view = vb.build(views)?;
+                    view.set_refresh(refresh);
+                    Ok(view)
+                }
                 Ok(None) => Err(failure::err_msg("view does not exist")),
                 Err(e) => Err(failure::Error::from(e)),
             }
@@ -331,13 +617,13 @@ impl<A: Authority + 'static> ControllerHandle<A> {
         let name = name.to_string();
         let fut = self
             .handle
-            .call(ControllerRequest::new("table_builder", &name).unwrap());
+            .call(ControllerRequest::new("table_builder", true, &name).unwrap());
 
         async move {
             let body: hyper::body::Bytes = fut
                 .await
                 .map_err(failure::Context::new)
-                .context("failed to fetch table builder")?;
+                .context("failed to fetch table builder")??;
 
             match serde_json::from_slice::<Option<TableBuilder>>(&body) {
                 Ok(Some(tb)) => Ok(tb.build(domains)?),
@@ -348,10 +634,17 @@ impl<A: Authority + 'static> ControllerHandle<A> {
         }
     }
 
+    /// Call the controller's `path` RPC with `r`.
+    ///
+    /// An attempt that times out or loses its connection may still have been carried out by the
+    /// controller, so it is only tried again if `idempotent` says that repeating the RPC does not
+    /// change its outcome. All RPCs are tried again when the controller cannot have seen the
+    /// attempt.
     #[doc(hidden)]
     pub fn rpc<Q: Serialize, R: 'static>(
         &mut self,
         path: &'static str,
+        idempotent: bool,
         r: Q,
         err: &'static str,
     ) -> RpcFuture<A, R>
@@ -359,7 +652,9 @@ impl<A: Authority + 'static> ControllerHandle<A> {
         for<'de> R: Deserialize<'de>,
         R: Send,
     {
-        let fut = self.handle.call(ControllerRequest::new(path, r).unwrap());
+        let fut = self
+            .handle
+            .call(ControllerRequest::new(path, idempotent, r).unwrap());
 
         finalize(fut, err)
     }
@@ -370,14 +665,615 @@ impl<A: Authority + 'static> ControllerHandle<A> {
     pub fn statistics(
         &mut self,
     ) -> impl Future<Output = Result<stats::GraphStats, failure::Error>> {
-        self.rpc("get_statistics", (), "failed to get stats")
+        self.rpc("get_statistics", true, (), "failed to get stats")
+    }
+
+    /// Follow the statistics that domains report to the controller on their own.
+    ///
+    /// Domains only do so if the controller was configured with a statistics interval. The first
+    /// item holds everything the controller knows. After that, the controller is asked for news
+    /// every `every`, and each item holds the domain shards that reported since the previous item,
+    /// along with only those of their nodes whose statistics changed. Unlike
+    /// `Self::statistics`, this never makes the controller wait for the domains.
+    pub fn statistics_stream(
+        &mut self,
+        every: Duration,
+    ) -> impl Stream<Item = Result<stats::GraphStats, failure::Error>> {
+        let handle = self.clone();
+        futures_util::stream::unfold(
+            (handle, 0, true),
+            move |(mut handle, since, mut first): (Self, u64, bool)| async move {
+                loop {
+                    if !first {
+                        tokio::time::delay_for(every).await;
+                    }
+                    let update: Result<(u64, stats::GraphStats), _> = async {
+                        handle.ready().await?;
+                        handle
+                            .rpc(
+                                "statistics_updates",
+                                true,
+                                since,
+                                "failed to get stats updates",
+                            )
+                            .await
+                    }
+                    .await;
+                    match update {
+                        Ok((_, ref stats)) if !first && stats.domains.is_empty() => {}
+                        Ok((seq, stats)) => return Some((Ok(stats), (handle, seq, false))),
+                        Err(e) => return Some((Err(e), (handle, since, false))),
+                    }
+                    first = false;
+                }
+            },
+        )
+    }
+
+    /// Fetch the audit log of the given base table, oldest entry first.
+    ///
+    /// This fails if auditing was not enabled for the table when it was created.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn audit_log(
+        &mut self,
+        table: &str,
+    ) -> impl Future<Output = Result<Vec<AuditEntry>, failure::Error>> {
+        self.rpc("audit_log", true, table, "failed to fetch audit log")
+    }
+
+    /// Fetch the sequence number of the last batch of writes committed to each shard of the given
+    /// base table.
+    ///
+    /// See `Table::perform_all_seq` for how writes are numbered. A shard that has not committed
+    /// any writes yet reports 0.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn commit_seqs(
+        &mut self,
+        table: &str,
+    ) -> impl Future<Output = Result<Vec<u64>, failure::Error>> {
+        self.rpc(
+            "commit_seqs",
+            true,
+            table,
+            "failed to fetch commit sequence numbers",
+        )
+    }
+
+    /// Export the full contents of the given base table, along with the sequence number of the
+    /// last batch of writes that each of its shards committed.
+    ///
+    /// Each shard is exported as of the moment it received the request, so the export of a shard
+    /// includes exactly the writes numbered up to its sequence number. Writes made after that can
+    /// be found by comparing with `Self::commit_seqs`. Only tables whose rows are fully kept in
+    /// their base can be exported.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn export_base(
+        &mut self,
+        table: &str,
+    ) -> impl Future<Output = Result<BaseExport, failure::Error>> {
+        self.rpc("export_base", true, table, "failed to export base table")
+    }
+
+    /// Import a base table exported with `Self::export_base`, possibly from another deployment,
+    /// into the identically named base table.
+    ///
+    /// The rows are written to the table as if by a client, so the table's views are updated
+    /// accordingly, and each shard adopts the sequence number that it was exported with, so that
+    /// writes made to the exporting deployment after the export can be told apart. The table
+    /// must have the same columns and number of shards as the exported table, must shard its rows
+    /// the same way, and must not have been written to yet.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn import_base(
+        &mut self,
+        export: BaseExport,
+    ) -> impl Future<Output = Result<(), failure::Error>> {
+        self.rpc("import_base", false, export, "failed to import base table")
+    }
+
+    /// Verify the rows that each shard of the given table keeps on disk.
+    ///
+    /// Every row is decoded and checked against the key it is stored under, and every index
+    /// entry is checked to refer to an intact row. If `repair` is set, corrupt rows and index
+    /// entries are dropped and missing index entries are rebuilt, so that the table can be used
+    /// again; the returned reports list what was lost. Rows dropped by a repair are not removed
+    /// from views that were computed from them.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn verify_base(
+        &mut self,
+        table: &str,
+        repair: bool,
+    ) -> impl Future<Output = Result<Vec<BaseVerification>, failure::Error>> {
+        self.rpc(
+            "verify_base",
+            true,
+            (table, repair),
+            "failed to verify base table",
+        )
+    }
+
+    /// Temporarily stop maintaining the view with the given name.
+    ///
+    /// While the view is paused, writes no longer update it. Reads are still served: keys that
+    /// were present when the view was paused return their state as of that moment, unless
+    /// `drop_state` is set, in which case all of the view's state is evicted to free up memory.
+    /// Operators upstream of the view keep processing writes, since other views may depend on
+    /// them.
+    ///
+    /// Only partially materialized views can be paused.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn pause_view(
+        &mut self,
+        name: &str,
+        drop_state: bool,
+    ) -> impl Future<Output = Result<(), failure::Error>> {
+        self.rpc(
+            "pause_view",
+            false,
+            (name, drop_state),
+            "failed to pause view",
+        )
+    }
+
+    /// Resume maintenance of a view paused with `Self::pause_view`.
+    ///
+    /// Whatever state the view kept while paused is discarded, and is brought up to date again by
+    /// replaying from upstream as keys are read.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn resume_view(&mut self, name: &str) -> impl Future<Output = Result<(), failure::Error>> {
+        self.rpc("resume_view", false, name, "failed to resume view")
+    }
+
+    /// Set the priority with which missing state in the view with the given name is computed.
+    ///
+    /// A view can also be given a priority in the recipe, as in
+    /// `QUERY Name PRIORITY interactive: SELECT ...`, which is applied whenever the recipe changes
+    /// it. Views default to `ReplayPriority::Normal`. Fully materialized views never compute
+    /// missing state, so their priority has no effect.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn set_replay_priority(
+        &mut self,
+        name: &str,
+        priority: ReplayPriority,
+    ) -> impl Future<Output = Result<(), failure::Error>> {
+        self.rpc(
+            "set_replay_priority",
+            true,
+            (name, priority),
+            "failed to set replay priority",
+        )
+    }
+
+    /// Start or stop attributing the reads of the view with the given name to the clients that
+    /// make them.
+    ///
+    /// While enabled, each shard of the view counts the keys looked up by every client identity
+    /// (see `View::set_identity`), along with the keys each identity looks up most often. The
+    /// counts can be fetched with `Self::read_attribution`, and are discarded when attribution is
+    /// disabled. Attribution is disabled by default, since it makes every read of the view take a
+    /// lock that is shared by all the threads serving reads.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn set_read_attribution(
+        &mut self,
+        name: &str,
+        enabled: bool,
+    ) -> impl Future<Output = Result<(), failure::Error>> {
+        self.rpc(
+            "set_read_attribution",
+            true,
+            (name, enabled),
+            "failed to set read attribution",
+        )
+    }
+
+    /// Fetch the reads of the view with the given name by each client identity since read
+    /// attribution was enabled for it, most active first.
+    ///
+    /// Reads by clients that did not set an identity are reported together, with no identity.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn read_attribution(
+        &mut self,
+        name: &str,
+    ) -> impl Future<Output = Result<Vec<stats::ReadAttribution>, failure::Error>> {
+        self.rpc(
+            "read_attribution",
+            true,
+            name,
+            "failed to fetch read attribution",
+        )
+    }
+
+    /// Check that the view called `name` agrees with the data it is computed from.
+    ///
+    /// Up to `samples` keys are chosen at random from each shard of the view. Their current
+    /// contents are fingerprinted, and then evicted and recomputed from upstream, and the
+    /// fingerprints of the recomputed contents are compared with the originals. A key that
+    /// differs is checked a second time before it is reported, since a concurrent write to the
+    /// key also changes its contents. Keys whose recomputation does not complete promptly are
+    /// not counted.
+    ///
+    /// Only partially materialized views can be checked. The result is also recorded among the
+    /// events returned by `Self::consistency_events`.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn check_consistency(
+        &mut self,
+        name: &str,
+        samples: usize,
+    ) -> impl Future<Output = Result<ConsistencyEvent, failure::Error>> {
+        self.rpc(
+            "check_consistency",
+            false,
+            (name, samples),
+            "failed to check view consistency",
+        )
+    }
+
+    /// Fetch the results of recent consistency checks, oldest first.
+    ///
+    /// This includes both checks requested through `Self::check_consistency` and those run in
+    /// the background if the controller was configured to do so.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn consistency_events(
+        &mut self,
+    ) -> impl Future<Output = Result<Vec<ConsistencyEvent>, failure::Error>> {
+        self.rpc(
+            "consistency_events",
+            true,
+            (),
+            "failed to fetch consistency events",
+        )
+    }
+
+    /// Fetch the domains that have panicked on workers with domain supervision enabled, oldest
+    /// first.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn domain_failures(
+        &mut self,
+    ) -> impl Future<Output = Result<Vec<DomainFailure>, failure::Error>> {
+        self.rpc(
+            "domain_failures",
+            true,
+            (),
+            "failed to fetch domain failures",
+        )
+    }
+
+    /// Fetch the replay pieces that failed their checksums, oldest first.
+    ///
+    /// Only domains started with replay checksums enabled check them. Queries that depend on a
+    /// domain that could not recover from a corrupt piece on its own are rebuilt.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn replay_corruptions(
+        &mut self,
+    ) -> impl Future<Output = Result<Vec<ReplayCorruption>, failure::Error>> {
+        self.rpc(
+            "replay_corruptions",
+            true,
+            (),
+            "failed to fetch replay corruptions",
+        )
+    }
+
+    /// Give the view with the given name exactly `replicas` read replicas, adding or removing
+    /// them as needed.
+    ///
+    /// Each `View` handle spreads its reads across the view's own reader and its replicas, and
+    /// picks up replicas added or removed after it was fetched. A controller that scales the
+    /// readers of views whose reads queue up may still change the replicas later.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn set_read_replicas(
+        &mut self,
+        name: &str,
+        replicas: usize,
+    ) -> impl Future<Output = Result<(), failure::Error>> {
+        self.rpc(
+            "set_read_replicas",
+            true,
+            (name, replicas),
+            "failed to set read replicas",
+        )
+    }
+
+    /// Fetch the read replicas the controller has added to or removed from views, oldest first.
+    ///
+    /// Views only get read replicas this way if the controller was configured to scale the
+    /// readers of views whose reads queue up.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn scaling_events(
+        &mut self,
+    ) -> impl Future<Output = Result<Vec<ScalingEvent>, failure::Error>> {
+        self.rpc("scaling_events", true, (), "failed to fetch scaling events")
+    }
+
+    /// Fetch the clients that are connected to each worker, as of the worker's last heartbeat.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn connections(
+        &mut self,
+    ) -> impl Future<Output = Result<Vec<ClientConnection>, failure::Error>> {
+        self.rpc(
+            "connections",
+            true,
+            (),
+            "failed to fetch client connections",
+        )
+    }
+
+    /// Start a rolling upgrade of the worker at the given address.
+    ///
+    /// The domains running on the worker are first handed off to its peers. The rows of the base
+    /// tables it holds are copied to new base tables on its peers, which take over writes to the
+    /// tables, so this fails if some of those tables cannot be moved, like those that are
+    /// persisted permanently. Once `UpgradeEvent::AwaitingRestart` is reported by
+    /// `Self::upgrade_status`, the worker can be restarted with the new binary, and with the
+    /// upgrade token this returns (`--upgrade-token`). When it registers with the controller again
+    /// with that token, the domains that were handed off are moved back onto it. Other workers
+    /// that join in the meantime do not finish the upgrade.
+    ///
+    /// Only one upgrade can be in progress at a time.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn rolling_upgrade(
+        &mut self,
+        worker: SocketAddr,
+    ) -> impl Future<Output = Result<u64, failure::Error>> {
+        self.rpc(
+            "rolling_upgrade",
+            false,
+            worker,
+            "failed to start rolling upgrade",
+        )
+    }
+
+    /// Fetch the progress of the most recent rolling upgrade.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn upgrade_status(
+        &mut self,
+    ) -> impl Future<Output = Result<Vec<UpgradeEvent>, failure::Error>> {
+        self.rpc("upgrade_status", true, (), "failed to fetch upgrade status")
+    }
+
+    /// Move shard `shard` of domain `domain` off the worker that runs it, for example ahead of
+    /// maintenance on that worker.
+    ///
+    /// The queries that depend on the domain are removed and added again without that worker,
+    /// just as they would be if the worker had failed, but without waiting for the failure to be
+    /// detected. Their views are unavailable while they are rebuilt. Domains that hold base
+    /// tables cannot be moved, since their rows would be lost.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn fail_over(
+        &mut self,
+        domain: usize,
+        shard: usize,
+    ) -> impl Future<Output = Result<Failover, failure::Error>> {
+        self.rpc(
+            "fail_over",
+            false,
+            (domain, shard),
+            "failed to fail over domain",
+        )
+    }
+
+    /// Change the number of shards of the given base table, and return the queries that were
+    /// rebuilt as a result.
+    ///
+    /// A new table split into `shards` shards is added next to the old one; a single shard leaves
+    /// it unsharded. The old table copies its rows to the new one, and from then on passes every
+    /// write it accepts on to it as well, so that no write is lost while the queries that read
+    /// from the table keep being updated. The queries are then rebuilt over the new table, and
+    /// fill their views from it, after which the old table is removed. Tables and views fetched
+    /// before must be fetched again; writes to a table fetched before fail once the old table is
+    /// gone. The table must keep all of its rows and have a key, must not take part in cascading
+    /// deletes, and must not be kept on disk permanently.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn reshard_base(
+        &mut self,
+        table: &str,
+        shards: usize,
+    ) -> impl Future<Output = Result<Vec<String>, failure::Error>> {
+        self.rpc(
+            "reshard_base",
+            false,
+            (table, shards),
+            "failed to reshard base table",
+        )
+    }
+
+    /// Split the given base table by ranges of its key, and return the queries that were rebuilt
+    /// as a result.
+    ///
+    /// The table gets one shard more than there are `split_points`: the first shard holds the
+    /// keys below the first split point, and each later shard the keys from its split point up to
+    /// the next. The split points must be given in ascending order, and the table must be keyed
+    /// by a single column. Calling this again with other split points moves the keys whose shard
+    /// changes, the same way `Self::reshard_base` moves a table, and `Self::reshard_base` makes
+    /// the table hash its keys again.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn set_shard_ranges(
+        &mut self,
+        table: &str,
+        split_points: Vec<DataType>,
+    ) -> impl Future<Output = Result<Vec<String>, failure::Error>> {
+        self.rpc(
+            "set_shard_ranges",
+            false,
+            (table, split_points),
+            "failed to split base table by range",
+        )
+    }
+
+    /// Fetch the domains moved by `Self::fail_over`, oldest first.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn failovers(&mut self) -> impl Future<Output = Result<Vec<Failover>, failure::Error>> {
+        self.rpc("failovers", true, (), "failed to fetch failovers")
+    }
+
+    /// Fetch the workers that the controller found to have stopped sending heartbeats, oldest
+    /// first, along with the domains they ran and the queries that were rebuilt as a result.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn worker_failures(
+        &mut self,
+    ) -> impl Future<Output = Result<Vec<WorkerFailure>, failure::Error>> {
+        self.rpc(
+            "worker_failures",
+            true,
+            (),
+            "failed to fetch worker failures",
+        )
+    }
+
+    /// Copy the current contents of every view into a read-only view named `<view>@<name>`, and
+    /// return the names of the copies.
+    ///
+    /// The copies live in domains of their own, so long-running reads against them do not slow
+    /// down the live views, and they do not change as the tables they were computed from are
+    /// written to. Views that only keep counts or that are keyed on computed values are not
+    /// copied, and partially materialized views are copied with only the keys they currently
+    /// hold. Snapshots are not recovered if the controller fails over.
+    ///
+    /// All the copies reflect the same writes. Writes to the tables are held, rather than turned
+    /// away, while the copies are taken.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn fork_snapshot(
+        &mut self,
+        name: &str,
+    ) -> impl Future<Output = Result<Vec<String>, failure::Error>> {
+        self.rpc("fork_snapshot", false, name, "failed to fork snapshot")
+    }
+
+    /// Remove the views of a snapshot made with `Self::fork_snapshot`.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn drop_snapshot(
+        &mut self,
+        name: &str,
+    ) -> impl Future<Output = Result<(), failure::Error>> {
+        self.rpc("drop_snapshot", false, name, "failed to drop snapshot")
+    }
+
+    /// Execute a SQL `INSERT`, `UPDATE`, or `DELETE` statement against a base table.
+    ///
+    /// `UPDATE` and `DELETE` statements must identify the affected row by giving its full primary
+    /// key as a conjunction of equalities in their `WHERE` clause, such as `WHERE id = 42`.
+    /// Assignments may set a column to a literal, or add or subtract a literal from the column's
+    /// current value (`SET votes = votes + 1`).
+    ///
+    /// Returns the number of writes issued to the table.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub async fn execute(&mut self, sql: &str) -> Result<usize, failure::Error> {
+        let stmt = crate::dml::parse(sql)?;
+        let mut table = self.table(&stmt.table).await?;
+        stmt.execute(&mut table).await
+    }
+
+    /// Pre-populate the partial state of the given view for the keys in `keys`.
+    ///
+    /// Every key is looked up in the view, which triggers a replay for any key that is not yet
+    /// present. At most `concurrency` keys are requested at a time, and the next batch is only
+    /// issued once all replays for the previous batch have completed, so that warming a view does
+    /// not overload the data-flow while it is serving other traffic. Fails if `concurrency` is 0.
+    ///
+    /// Returns the number of keys that were warmed.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub async fn warm_view(
+        &mut self,
+        name: &str,
+        keys: WarmKeys,
+        concurrency: usize,
+    ) -> Result<usize, failure::Error> {
+        if concurrency == 0 {
+            bail!("cannot warm a view with a concurrency of 0");
+        }
+        let keys: Vec<Vec<DataType>> = match keys {
+            WarmKeys::Keys(keys) => keys,
+            WarmKeys::Base { table, columns } => {
+                self.rpc(
+                    "base_keys",
+                    true,
+                    (table, columns),
+                    "failed to fetch base keys",
+                )
+                .await?
+            }
+        };
+
+        let mut view = self.view(name).await?;
+        for batch in keys.chunks(concurrency) {
+            view.multi_lookup(batch.to_vec(), true)
+                .await
+                .context("failed to warm view")?;
+        }
+        Ok(keys.len())
+    }
+
+    /// Look up `key` in the given view, and sample the state that upstream operators hold for it.
+    ///
+    /// The key is traced from the view back towards the base tables, and every operator along
+    /// the way that keeps state reports the rows it has for the key. Comparing these rows with
+    /// those of the view helps find out whether a wrong result comes from a join, a filter, or
+    /// the base data itself.
+    ///
+    /// The view is read first, so any missing state along the way is filled in by the lookup.
+    /// Writes that arrive while sampling may make the rows of different operators disagree.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub async fn sample_key(
+        &mut self,
+        view: &str,
+        key: Vec<DataType>,
+    ) -> Result<KeySample, failure::Error> {
+        let rows: Vec<Vec<DataType>> = self
+            .view(view)
+            .await?
+            .lookup(&key[..], true)
+            .await
+            .context("failed to look up key in view")?
+            .into();
+        let upstream: Vec<NodeSample> = self
+            .rpc(
+                "sample_key",
+                true,
+                (view, &key),
+                "failed to sample upstream state",
+            )
+            .await?;
+        Ok(KeySample {
+            view: view.to_string(),
+            key,
+            rows,
+            upstream,
+        })
     }
 
     /// Flush all partial state, evicting all rows present.
     ///
     /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
     pub fn flush_partial(&mut self) -> impl Future<Output = Result<(), failure::Error>> {
-        self.rpc("flush_partial", (), "failed to flush partial")
+        self.rpc("flush_partial", false, (), "failed to flush partial")
     }
 
     /// Extend the existing recipe with the given set of queries.
@@ -387,7 +1283,12 @@ impl<A: Authority + 'static> ControllerHandle<A> {
         &mut self,
         recipe_addition: &str,
     ) -> impl Future<Output = Result<ActivationResult, failure::Error>> {
-        self.rpc("extend_recipe", recipe_addition, "failed to extend recipe")
+        self.rpc(
+            "extend_recipe",
+            false,
+            recipe_addition,
+            "failed to extend recipe",
+        )
     }
 
     /// Replace the existing recipe with this one.
@@ -397,14 +1298,19 @@ impl<A: Authority + 'static> ControllerHandle<A> {
         &mut self,
         new_recipe: &str,
     ) -> impl Future<Output = Result<ActivationResult, failure::Error>> {
-        self.rpc("install_recipe", new_recipe, "failed to install recipe")
+        self.rpc(
+            "install_recipe",
+            false,
+            new_recipe,
+            "failed to install recipe",
+        )
     }
 
     /// Fetch a graphviz description of the dataflow graph.
     ///
     /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
     pub fn graphviz(&mut self) -> impl Future<Output = Result<String, failure::Error>> {
-        self.rpc("graphviz", (), "failed to fetch graphviz output")
+        self.rpc("graphviz", true, (), "failed to fetch graphviz output")
     }
 
     /// Fetch a simplified graphviz description of the dataflow graph.
@@ -413,6 +1319,7 @@ impl<A: Authority + 'static> ControllerHandle<A> {
     pub fn simple_graphviz(&mut self) -> impl Future<Output = Result<String, failure::Error>> {
         self.rpc(
             "simple_graphviz",
+            true,
             (),
             "failed to fetch simple graphviz output",
         )
@@ -426,6 +1333,6 @@ impl<A: Authority + 'static> ControllerHandle<A> {
         view: NodeIndex,
     ) -> impl Future<Output = Result<(), failure::Error>> {
         // TODO: this should likely take a view name, and we should verify that it's a Reader.
-        self.rpc("remove_node", view, "failed to remove node")
+        self.rpc("remove_node", false, view, "failed to remove node")
     }
 }
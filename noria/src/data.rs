@@ -7,6 +7,7 @@ use nom_sql::Literal;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::ops::{Add, Div, Mul, Sub};
+use std::sync::Arc;
 
 const FLOAT_PRECISION: f64 = 1_000_000_000.0;
 const TINYTEXT_WIDTH: usize = 15;
@@ -41,6 +42,36 @@ pub enum DataType {
     TinyText([u8; TINYTEXT_WIDTH]),
     /// A timestamp for date/time types.
     Timestamp(NaiveDateTime),
+    /// A reference-counted binary value.
+    Bytes(Arc<Vec<u8>>),
+    /// A reference-counted list of values, which can be expanded into one row per element with
+    /// `UNNEST`.
+    List(Arc<Vec<DataType>>),
+}
+
+// Writes `values` as a comma-separated list in brackets, formatting each value with `item`.
+fn write_list(
+    f: &mut fmt::Formatter<'_>,
+    values: &[DataType],
+    item: fn(&DataType, &mut fmt::Formatter<'_>) -> fmt::Result,
+) -> fmt::Result {
+    write!(f, "[")?;
+    for (i, v) in values.iter().enumerate() {
+        if i != 0 {
+            write!(f, ", ")?;
+        }
+        item(v, f)?;
+    }
+    write!(f, "]")
+}
+
+// Writes `bytes` as a hexadecimal literal.
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    write!(f, "0x")?;
+    for b in bytes {
+        write!(f, "{:02x}", b)?;
+    }
+    Ok(())
 }
 
 impl fmt::Display for DataType {
@@ -65,6 +96,8 @@ impl fmt::Display for DataType {
                 }
             }
             DataType::Timestamp(ts) => write!(f, "{}", ts.format("%c")),
+            DataType::Bytes(ref bytes) => write_hex(f, bytes),
+            DataType::List(ref values) => write_list(f, values, fmt::Display::fmt),
         }
     }
 }
@@ -87,6 +120,16 @@ impl fmt::Debug for DataType {
             DataType::UnsignedInt(n) => write!(f, "UnsignedInt({})", n),
             DataType::BigInt(n) => write!(f, "BigInt({})", n),
             DataType::UnsignedBigInt(n) => write!(f, "UnsignedBigInt({})", n),
+            DataType::Bytes(ref bytes) => {
+                write!(f, "Bytes(")?;
+                write_hex(f, bytes)?;
+                write!(f, ")")
+            }
+            DataType::List(ref values) => {
+                write!(f, "List(")?;
+                write_list(f, values, fmt::Debug::fmt)?;
+                write!(f, ")")
+            }
         }
     }
 }
@@ -99,6 +142,10 @@ impl DataType {
     pub fn deep_clone(&self) -> Self {
         match *self {
             DataType::Text(ref cstr) => DataType::Text(ArcCStr::from(&**cstr)),
+            DataType::Bytes(ref bytes) => DataType::Bytes(Arc::new(Vec::clone(bytes))),
+            DataType::List(ref values) => {
+                DataType::List(Arc::new(values.iter().map(DataType::deep_clone).collect()))
+            }
             ref dt => dt.clone(),
         }
     }
@@ -143,6 +190,38 @@ impl DataType {
             _ => false,
         }
     }
+
+    /// Checks if this value is of a binary data type (i.e., can be converted into `Vec<u8>` and
+    /// `&[u8]`).
+    pub fn is_bytes(&self) -> bool {
+        match *self {
+            DataType::Bytes(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Checks if this value is a list of values.
+    pub fn is_list(&self) -> bool {
+        match *self {
+            DataType::List(_) => true,
+            _ => false,
+        }
+    }
+
+    /// The number of bytes held by a string or binary value, or `None` for other values.
+    ///
+    /// This is the size that column size limits of base tables apply to.
+    pub fn content_len(&self) -> Option<usize> {
+        match *self {
+            DataType::Bytes(ref bytes) => Some(bytes.len()),
+            DataType::Text(ref s) => Some(s.to_bytes().len()),
+            DataType::TinyText(..) => {
+                let s: Cow<'_, str> = self.into();
+                Some(s.len())
+            }
+            _ => None,
+        }
+    }
 }
 
 impl PartialEq for DataType {
@@ -190,6 +269,8 @@ impl PartialEq for DataType {
             }
             (&DataType::Real(ai, af), &DataType::Real(bi, bf)) => ai == bi && af == bf,
             (&DataType::Timestamp(tsa), &DataType::Timestamp(tsb)) => tsa == tsb,
+            (&DataType::Bytes(ref a), &DataType::Bytes(ref b)) => a == b,
+            (&DataType::List(ref a), &DataType::List(ref b)) => a == b,
             (&DataType::None, &DataType::None) => true,
 
             _ => false,
@@ -239,9 +320,11 @@ impl Ord for DataType {
                 ai.cmp(bi).then_with(|| af.cmp(bf))
             }
             (&DataType::Timestamp(tsa), &DataType::Timestamp(ref tsb)) => tsa.cmp(tsb),
+            (&DataType::Bytes(ref a), &DataType::Bytes(ref b)) => a.cmp(b),
+            (&DataType::List(ref a), &DataType::List(ref b)) => a.cmp(b),
             (&DataType::None, &DataType::None) => Ordering::Equal,
 
-            // order Ints, Reals, Text, Timestamps, None
+            // order Ints, Reals, Text, Timestamps, Bytes, Lists, None
             (&DataType::Int(..), _)
             | (&DataType::UnsignedInt(..), _)
             | (&DataType::BigInt(..), _)
@@ -249,6 +332,8 @@ impl Ord for DataType {
             (&DataType::Real(..), _) => Ordering::Greater,
             (&DataType::Text(..), _) | (&DataType::TinyText(..), _) => Ordering::Greater,
             (&DataType::Timestamp(..), _) => Ordering::Greater,
+            (&DataType::Bytes(..), _) => Ordering::Greater,
+            (&DataType::List(..), _) => Ordering::Greater,
             (&DataType::None, _) => Ordering::Greater,
         }
     }
@@ -278,6 +363,8 @@ impl Hash for DataType {
                 t.hash(state)
             }
             DataType::Timestamp(ts) => ts.hash(state),
+            DataType::Bytes(ref bytes) => bytes.hash(state),
+            DataType::List(ref values) => values.hash(state),
         }
     }
 }
@@ -366,6 +453,7 @@ impl<'a> From<&'a Literal> for DataType {
             Literal::Null => DataType::None,
             Literal::Integer(i) => (i as i64).into(),
             Literal::String(ref s) => s.as_str().into(),
+            Literal::Blob(ref b) => b.clone().into(),
             Literal::CurrentTimestamp => {
                 let ts = chrono::Local::now().naive_local();
                 DataType::Timestamp(ts)
@@ -384,6 +472,7 @@ impl From<Literal> for DataType {
             Literal::Null => DataType::None,
             Literal::Integer(i) => (i as i64).into(),
             Literal::String(s) => s.as_str().into(),
+            Literal::Blob(b) => b.into(),
             Literal::CurrentTimestamp => {
                 let ts = chrono::Local::now().naive_local();
                 DataType::Timestamp(ts)
@@ -394,12 +483,30 @@ impl From<Literal> for DataType {
     }
 }
 
+impl From<Vec<u8>> for DataType {
+    fn from(bytes: Vec<u8>) -> Self {
+        DataType::Bytes(Arc::new(bytes))
+    }
+}
+
+impl<'a> From<&'a [u8]> for DataType {
+    fn from(bytes: &'a [u8]) -> Self {
+        DataType::from(bytes.to_vec())
+    }
+}
+
 impl From<NaiveDateTime> for DataType {
     fn from(dt: NaiveDateTime) -> Self {
         DataType::Timestamp(dt)
     }
 }
 
+impl From<Vec<DataType>> for DataType {
+    fn from(values: Vec<DataType>) -> Self {
+        DataType::List(Arc::new(values))
+    }
+}
+
 /*
 impl<'a, T> Into<Option<T>> for &'a DataType
 where
@@ -451,6 +558,40 @@ impl Into<String> for DataType {
     }
 }
 
+impl<'a> Into<&'a [u8]> for &'a DataType {
+    fn into(self) -> &'a [u8] {
+        match *self {
+            DataType::Bytes(ref bytes) => &bytes[..],
+            _ => panic!("attempted to convert a {:?} to bytes", self),
+        }
+    }
+}
+
+impl Into<Vec<u8>> for &'_ DataType {
+    fn into(self) -> Vec<u8> {
+        let bytes: &[u8] = self.into();
+        bytes.to_vec()
+    }
+}
+
+impl Into<Vec<u8>> for DataType {
+    fn into(self) -> Vec<u8> {
+        match self {
+            DataType::Bytes(bytes) => Arc::try_unwrap(bytes).unwrap_or_else(|b| Vec::clone(&b)),
+            _ => panic!("attempted to convert a {:?} to bytes", self),
+        }
+    }
+}
+
+impl<'a> Into<&'a [DataType]> for &'a DataType {
+    fn into(self) -> &'a [DataType] {
+        match *self {
+            DataType::List(ref values) => &values[..],
+            _ => panic!("attempted to convert a {:?} to a list", self),
+        }
+    }
+}
+
 impl Into<i128> for DataType {
     fn into(self) -> i128 {
         match self {
@@ -703,6 +844,41 @@ impl TableOperation {
             _ => None,
         }
     }
+
+    /// The first column whose value written by this operation is larger than the column's limit
+    /// in `limits`, along with that value's size. Columns past the end of `limits` are
+    /// unlimited.
+    #[doc(hidden)]
+    pub fn exceeds_size_limits(&self, limits: &[Option<usize>]) -> Option<(usize, usize)> {
+        fn sets(update: &[Modification]) -> impl Iterator<Item = (usize, &DataType)> {
+            update.iter().enumerate().filter_map(|(i, m)| match *m {
+                Modification::Set(ref v) => Some((i, v)),
+                _ => None,
+            })
+        }
+
+        let written: Box<dyn Iterator<Item = (usize, &DataType)> + '_> = match *self {
+            TableOperation::Insert(ref row) => Box::new(row.iter().enumerate()),
+            TableOperation::InsertOrUpdate {
+                ref row,
+                ref update,
+            } => Box::new(row.iter().enumerate().chain(sets(update))),
+            TableOperation::Update { ref set, .. } => Box::new(sets(set)),
+            TableOperation::Delete { .. } => return None,
+        };
+
+        written
+            .filter_map(|(i, v)| {
+                let limit = limits.get(i).and_then(|&l| l)?;
+                let len = v.content_len()?;
+                if len > limit {
+                    Some((i, len))
+                } else {
+                    None
+                }
+            })
+            .next()
+    }
 }
 
 impl From<Vec<DataType>> for TableOperation {
@@ -821,6 +997,75 @@ mod tests {
         assert_eq!(format!("{}", big_int), "5");
     }
 
+    #[test]
+    fn data_type_bytes() {
+        let bytes: DataType = vec![0xde_u8, 0xad].into();
+        assert!(bytes.is_bytes());
+        assert!(!bytes.is_string());
+        assert_eq!(bytes, DataType::from(&[0xde_u8, 0xad][..]));
+        assert_ne!(bytes, DataType::from(vec![0xde_u8]));
+        assert!(DataType::from(vec![0xde_u8]) < bytes);
+        assert_eq!(bytes.content_len(), Some(2));
+        assert_eq!(format!("{}", bytes), "0xdead");
+        assert_eq!(format!("{:?}", bytes), "Bytes(0xdead)");
+
+        let b: &[u8] = (&bytes).into();
+        assert_eq!(b, &[0xde, 0xad]);
+        let b: Vec<u8> = bytes.deep_clone().into();
+        assert_eq!(b, vec![0xde, 0xad]);
+    }
+
+    #[test]
+    fn data_type_list() {
+        let list: DataType = vec![DataType::from(1), "a".into()].into();
+        assert!(list.is_list());
+        assert!(!list.is_bytes());
+        assert_eq!(list, DataType::from(vec![DataType::from(1), "a".into()]));
+        assert_ne!(list, DataType::from(vec![DataType::from(1)]));
+        assert!(DataType::from(vec![DataType::from(1)]) < list);
+        assert_eq!(list.content_len(), None);
+        assert_eq!(format!("{}", list), "[1, \"a\"]");
+        assert_eq!(format!("{:?}", list), "List([Int(1), TinyText(\"a\")])");
+
+        let values: &[DataType] = (&list).into();
+        assert_eq!(values, &[DataType::from(1), "a".into()]);
+        assert_eq!(list.deep_clone(), list);
+    }
+
+    #[test]
+    fn operation_size_limits() {
+        let limits = vec![None, Some(2)];
+        let row = |b: Vec<u8>| vec![DataType::from(1), b.into()];
+        assert_eq!(
+            TableOperation::Insert(row(vec![1, 2])).exceeds_size_limits(&limits),
+            None
+        );
+        assert_eq!(
+            TableOperation::Insert(row(vec![1, 2, 3])).exceeds_size_limits(&limits),
+            Some((1, 3))
+        );
+        // strings are limited by the length of their encoding
+        assert_eq!(
+            TableOperation::Insert(vec![1.into(), "abc".into()]).exceeds_size_limits(&limits),
+            Some((1, 3))
+        );
+        assert_eq!(
+            TableOperation::Update {
+                key: vec![1.into()],
+                set: vec![
+                    Modification::None,
+                    Modification::Set(vec![1_u8, 2, 3].into())
+                ],
+            }
+            .exceeds_size_limits(&limits),
+            Some((1, 3))
+        );
+        assert_eq!(
+            TableOperation::Insert(row(vec![1, 2, 3])).exceeds_size_limits(&[]),
+            None
+        );
+    }
+
     #[test]
     #[allow(clippy::cognitive_complexity)]
     fn data_type_fungibility() {
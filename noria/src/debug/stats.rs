@@ -1,15 +1,16 @@
 use crate::internal::*;
-use crate::MaterializationStatus;
+use crate::{DataType, MaterializationStatus};
 use petgraph::graph::NodeIndex;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
+use std::net::SocketAddr;
 
 type DomainMap = HashMap<(DomainIndex, usize), (DomainStats, HashMap<NodeIndex, NodeStats>)>;
 
 /// Statistics about a domain.
 ///
 /// All times are in nanoseconds.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct DomainStats {
     /// Total wall-clock time elapsed while processing in this domain.
     pub total_time: u64,
@@ -21,12 +22,67 @@ pub struct DomainStats {
     pub total_forward_time: u64,
     /// Total wall-clock time spent waiting for work in this domain.
     pub wait_time: u64,
+    /// Number of write batches whose buffer was recycled from an earlier batch.
+    #[serde(default)]
+    pub reused_buffers: u64,
+    /// Number of write batches for which a fresh buffer had to be allocated.
+    #[serde(default)]
+    pub allocated_buffers: u64,
+    /// Number of load-shedding windows during which this domain was overloaded.
+    #[serde(default)]
+    pub overloaded_windows: u64,
+    /// Number of records routed through this domain's sharders without their keys being tracked.
+    #[serde(default)]
+    pub shed_traces: u64,
+    /// Number of batch-priority upqueries held back because this domain was overloaded.
+    #[serde(default)]
+    pub delayed_replays: u64,
+    /// Number of writes to best-effort tables rejected because this domain was overloaded.
+    #[serde(default)]
+    pub rejected_writes: u64,
+    /// Number of hot keys whose records this domain's sharders spread across all shards.
+    #[serde(default)]
+    pub split_keys: u64,
+    /// Number of records this domain's sharders sent to another shard than their key hashes to.
+    #[serde(default)]
+    pub split_records: u64,
+    /// Number of bytes this domain sent over compressed connections, before compression.
+    #[serde(default)]
+    pub uncompressed_bytes: u64,
+    /// Number of bytes this domain sent over compressed connections, after compression.
+    #[serde(default)]
+    pub compressed_bytes: u64,
+    /// Number of replay pieces this domain received whose data did not match their checksum.
+    #[serde(default)]
+    pub corrupt_replay_pieces: u64,
+    /// Number of packets this domain has been handed to process.
+    #[serde(default)]
+    pub processed_packets: u64,
+    /// Number of replay pieces this domain has processed.
+    #[serde(default)]
+    pub replay_pieces: u64,
+    /// In deterministic mode, a fingerprint of the order in which this domain processed its
+    /// packets. Runs with the same seed and the same workload should agree on it.
+    #[serde(default)]
+    pub schedule: Option<u64>,
+}
+
+impl DomainStats {
+    /// How many times smaller the packets this domain sent over compressed connections became,
+    /// or `None` if it has not sent any.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        if self.compressed_bytes == 0 {
+            None
+        } else {
+            Some(self.uncompressed_bytes as f64 / self.compressed_bytes as f64)
+        }
+    }
 }
 
 /// Statistics about a node.
 ///
 /// All times are in nanoseconds.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct NodeStats {
     /// A textual description of this node.
     pub desc: String,
@@ -40,6 +96,89 @@ pub struct NodeStats {
     pub materialized: MaterializationStatus,
     /// The value returned from Ingredient::probe.
     pub probe_result: HashMap<String, String>,
+    /// The most frequently seen keys, along with their approximate frequencies, hottest first.
+    ///
+    /// For readers, these are the keys most frequently looked up by clients. For sharders, these
+    /// are the shard keys of the records most frequently routed through the sharder. Empty for all
+    /// other nodes.
+    #[serde(default)]
+    pub hot_keys: Vec<(Vec<DataType>, u64)>,
+    /// How much replay work the client reads of this node have caused, if it is a reader.
+    #[serde(default)]
+    pub read_amplification: Option<ReadAmplification>,
+    /// How long writes to this node currently wait for others to be batched with, if it is a base
+    /// table whose group commit window adapts to its load.
+    #[serde(default)]
+    pub flush_window: Option<u64>,
+    /// How many times this node's state has grown past one of the configured state-size
+    /// watermarks.
+    #[serde(default)]
+    pub watermarks_crossed: u64,
+}
+
+/// The reads of a view by the clients with one identity.
+///
+/// See `ControllerHandle::read_attribution`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReadAttribution {
+    /// The identity the clients tagged their reads with, if any.
+    ///
+    /// See `View::set_identity`.
+    pub identity: Option<String>,
+    /// The number of keys the clients looked up in the view.
+    pub reads: u64,
+    /// The keys the clients looked up most frequently, along with their approximate frequencies,
+    /// hottest first.
+    pub hot_keys: Vec<(Vec<DataType>, u64)>,
+}
+
+/// How much replay work the client reads of a reader have caused.
+///
+/// A view whose reads regularly miss, or whose misses each replay many records, is likely keyed in
+/// a way that does not match how it is read.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadAmplification {
+    /// Number of keys clients looked up in the reader.
+    pub reads: u64,
+    /// Number of keys for which misses sent an upquery.
+    pub upqueries: u64,
+    /// Number of records that replays filled into the reader.
+    pub replayed_records: u64,
+    /// Total size of the replayed records, in bytes.
+    pub replayed_bytes: u64,
+    /// Number of blocking reads that were answered along with an identical read that was already
+    /// waiting for a replay, instead of waiting for one of their own.
+    #[serde(default)]
+    pub coalesced: u64,
+}
+
+impl ReadAmplification {
+    /// The number of records replayed into the reader for each key clients looked up in it.
+    pub fn records_per_read(&self) -> f64 {
+        if self.reads == 0 {
+            0.0
+        } else {
+            self.replayed_records as f64 / self.reads as f64
+        }
+    }
+}
+
+/// The resources a worker is using, as of its last heartbeat.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WorkerStats {
+    /// The address of the worker.
+    pub worker: SocketAddr,
+    /// Whether the controller considers the worker to be alive.
+    pub healthy: bool,
+    /// The number of domain shards the worker has started.
+    pub domains_hosted: usize,
+    /// Total memory size, in bytes, of the partial state kept by the worker's domains, as last
+    /// measured by the domains.
+    pub memory_used: u64,
+    /// Disk space, in bytes, used by base table persistence on the worker.
+    pub disk_usage: u64,
+    /// The one-minute load average of the worker's host, where the platform reports it.
+    pub cpu_load: Option<f64>,
 }
 
 /// Statistics about the Soup data-flow.
@@ -49,6 +188,9 @@ pub struct GraphStats {
     #[serde(deserialize_with = "deserialize_domainmap")]
     #[doc(hidden)]
     pub domains: DomainMap,
+    /// The resources each worker is using.
+    #[serde(default)]
+    pub workers: Vec<WorkerStats>,
 }
 
 use std::ops::Deref;
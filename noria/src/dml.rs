@@ -0,0 +1,291 @@
+//! Execution of SQL data-manipulation statements against base tables.
+//!
+//! This lets applications written against a SQL database issue their `INSERT`, `UPDATE`, and
+//! `DELETE` statements to Noria directly (see `ControllerHandle::execute`), rather than rewriting
+//! them in terms of the `Table` API. `UPDATE` and `DELETE` must identify a single row by giving
+//! its full primary key as a conjunction of equalities in the `WHERE` clause.
+
+use crate::data::{DataType, Modification, Operation};
+use crate::table::Table;
+use nom_sql::{
+    ArithmeticBase, ArithmeticOperator, Column, ConditionBase, ConditionExpression,
+    FieldValueExpression, Operator, SqlQuery,
+};
+
+/// A parsed data-manipulation statement.
+#[derive(Debug)]
+pub(crate) struct Statement {
+    /// The base table the statement targets.
+    pub(crate) table: String,
+    kind: Kind,
+}
+
+#[derive(Debug)]
+enum Kind {
+    Insert {
+        fields: Option<Vec<String>>,
+        rows: Vec<Vec<DataType>>,
+        on_duplicate: Option<Vec<(String, Modification)>>,
+    },
+    Update {
+        key: Vec<(String, DataType)>,
+        set: Vec<(String, Modification)>,
+    },
+    Delete {
+        key: Vec<(String, DataType)>,
+    },
+}
+
+/// Parse `sql` as an `INSERT`, `UPDATE`, or `DELETE` statement.
+pub(crate) fn parse(sql: &str) -> Result<Statement, failure::Error> {
+    let q = nom_sql::parse_query(sql).map_err(|e| format_err!("failed to parse query: {}", e))?;
+    match q {
+        SqlQuery::Insert(q) => Ok(Statement {
+            table: q.table.name,
+            kind: Kind::Insert {
+                fields: q.fields.map(|fs| fs.into_iter().map(|c| c.name).collect()),
+                rows: q
+                    .data
+                    .into_iter()
+                    .map(|row| row.into_iter().map(DataType::from).collect())
+                    .collect(),
+                on_duplicate: q
+                    .on_duplicate
+                    .map(|set| set.into_iter().map(modification).collect::<Result<_, _>>())
+                    .transpose()?,
+            },
+        }),
+        SqlQuery::Update(q) => Ok(Statement {
+            table: q.table.name,
+            kind: Kind::Update {
+                key: equalities(q.where_clause)?,
+                set: q
+                    .fields
+                    .into_iter()
+                    .map(modification)
+                    .collect::<Result<_, _>>()?,
+            },
+        }),
+        SqlQuery::Delete(q) => Ok(Statement {
+            table: q.table.name,
+            kind: Kind::Delete {
+                key: equalities(q.where_clause)?,
+            },
+        }),
+        q => bail!("not a data-manipulation statement: {}", q),
+    }
+}
+
+/// Translate `col = <value>` from a `SET` or `ON DUPLICATE KEY UPDATE` clause.
+fn modification(
+    (col, value): (Column, FieldValueExpression),
+) -> Result<(String, Modification), failure::Error> {
+    let m = match value {
+        FieldValueExpression::Literal(l) => Modification::Set(l.value.into()),
+        FieldValueExpression::Arithmetic(ref a) => match (&a.left, &a.op, &a.right) {
+            (ArithmeticBase::Column(c), op, ArithmeticBase::Scalar(v)) if c.name == col.name => {
+                let op = match op {
+                    ArithmeticOperator::Add => Operation::Add,
+                    ArithmeticOperator::Subtract => Operation::Sub,
+                    _ => bail!("unsupported operator in assignment to {}: {}", col.name, a),
+                };
+                Modification::Apply(op, v.into())
+            }
+            _ => bail!(
+                "unsupported expression in assignment to {}: {}",
+                col.name,
+                a
+            ),
+        },
+    };
+    Ok((col.name, m))
+}
+
+/// Extract the `col = literal` pairs from a `WHERE` clause made up only of such conjuncts.
+fn equalities(
+    cond: Option<ConditionExpression>,
+) -> Result<Vec<(String, DataType)>, failure::Error> {
+    fn walk(
+        cond: ConditionExpression,
+        out: &mut Vec<(String, DataType)>,
+    ) -> Result<(), failure::Error> {
+        match cond {
+            ConditionExpression::LogicalOp(ct) if ct.operator == Operator::And => {
+                walk(*ct.left, out)?;
+                walk(*ct.right, out)
+            }
+            ConditionExpression::ComparisonOp(ct) if ct.operator == Operator::Equal => {
+                match (*ct.left, *ct.right) {
+                    (
+                        ConditionExpression::Base(ConditionBase::Field(c)),
+                        ConditionExpression::Base(ConditionBase::Literal(l)),
+                    )
+                    | (
+                        ConditionExpression::Base(ConditionBase::Literal(l)),
+                        ConditionExpression::Base(ConditionBase::Field(c)),
+                    ) => {
+                        out.push((c.name, l.into()));
+                        Ok(())
+                    }
+                    (l, r) => bail!("unsupported condition: {} = {}", l, r),
+                }
+            }
+            ConditionExpression::Bracketed(c) => walk(*c, out),
+            c => bail!("unsupported condition: {}", c),
+        }
+    }
+
+    let cond = cond.ok_or_else(|| format_err!("statement must have a WHERE clause"))?;
+    let mut out = Vec::new();
+    walk(cond, &mut out)?;
+    Ok(out)
+}
+
+fn column_index(table: &Table, name: &str) -> Result<usize, failure::Error> {
+    table
+        .columns()
+        .iter()
+        .position(|c| c == name)
+        .ok_or_else(|| format_err!("no column {} in table {}", name, table.table_name()))
+}
+
+/// Order the values in `key` according to the primary key of `table`.
+fn primary_key(
+    table: &Table,
+    key: Vec<(String, DataType)>,
+) -> Result<Vec<DataType>, failure::Error> {
+    let pk = table.primary_key().ok_or_else(|| {
+        format_err!(
+            "table {} has no primary key, so rows cannot be addressed individually",
+            table.table_name()
+        )
+    })?;
+
+    let mut values = vec![None; pk.len()];
+    for (col, v) in key {
+        let ci = column_index(table, &col)?;
+        match pk.iter().position(|&k| k == ci) {
+            Some(i) => values[i] = Some(v),
+            None => bail!(
+                "WHERE clause may only refer to primary key columns, not {}",
+                col
+            ),
+        }
+    }
+    values
+        .into_iter()
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| format_err!("WHERE clause must specify the full primary key"))
+}
+
+fn modifications(
+    table: &Table,
+    set: Vec<(String, Modification)>,
+) -> Result<Vec<(usize, Modification)>, failure::Error> {
+    set.into_iter()
+        .map(|(col, m)| Ok((column_index(table, &col)?, m)))
+        .collect()
+}
+
+impl Statement {
+    /// Apply this statement to `table`, which must be the table named by the statement.
+    ///
+    /// Returns the number of writes issued.
+    pub(crate) async fn execute(self, table: &mut Table) -> Result<usize, failure::Error> {
+        assert_eq!(table.table_name(), self.table);
+        match self.kind {
+            Kind::Insert {
+                fields,
+                rows,
+                on_duplicate,
+            } => {
+                // columns not named by the statement are left as NULL
+                let positions = match fields {
+                    Some(fields) => Some(
+                        fields
+                            .iter()
+                            .map(|f| column_index(table, f))
+                            .collect::<Result<Vec<_>, _>>()?,
+                    ),
+                    None => None,
+                };
+                let on_duplicate = match on_duplicate {
+                    Some(set) => Some(modifications(table, set)?),
+                    None => None,
+                };
+
+                let n = rows.len();
+                for row in rows {
+                    let row = match positions {
+                        Some(ref positions) => {
+                            if positions.len() != row.len() {
+                                bail!(
+                                    "INSERT has {} columns but {} values",
+                                    positions.len(),
+                                    row.len()
+                                );
+                            }
+                            let mut full = vec![DataType::None; table.columns().len()];
+                            for (&ci, v) in positions.iter().zip(row) {
+                                full[ci] = v;
+                            }
+                            full
+                        }
+                        None => row,
+                    };
+
+                    match on_duplicate {
+                        Some(ref set) => table.insert_or_update(row, set.clone()).await?,
+                        None => table.insert(row).await?,
+                    }
+                }
+                Ok(n)
+            }
+            Kind::Update { key, set } => {
+                let key = primary_key(table, key)?;
+                let set = modifications(table, set)?;
+                table.update(key, set).await?;
+                Ok(1)
+            }
+            Kind::Delete { key } => {
+                let key = primary_key(table, key)?;
+                table.delete(key).await?;
+                Ok(1)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_update() {
+        let s = parse("UPDATE t SET b = b + 1, c = 'x' WHERE a = 1").unwrap();
+        assert_eq!(s.table, "t");
+        match s.kind {
+            Kind::Update { key, set } => {
+                assert_eq!(key, vec![("a".to_owned(), 1.into())]);
+                assert_eq!(
+                    set,
+                    vec![
+                        (
+                            "b".to_owned(),
+                            Modification::Apply(Operation::Add, 1.into())
+                        ),
+                        ("c".to_owned(), Modification::Set("x".into())),
+                    ]
+                );
+            }
+            k => unreachable!("{:?}", k),
+        }
+    }
+
+    #[test]
+    fn rejects_non_key_where() {
+        assert!(parse("DELETE FROM t WHERE a > 1").is_err());
+        assert!(parse("DELETE FROM t").is_err());
+        assert!(parse("SELECT * FROM t").is_err());
+    }
+}
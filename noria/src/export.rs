@@ -0,0 +1,38 @@
+use crate::data::DataType;
+
+/// The contents of a base table, as exported by `ControllerHandle::export_base`.
+///
+/// An export can be imported into the identically named base table of another deployment with
+/// `ControllerHandle::import_base`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BaseExport {
+    /// The name of the base table.
+    pub table: String,
+    /// The columns of the base table, including any that have been dropped.
+    pub columns: Vec<String>,
+    /// The contents of each shard of the base table.
+    pub shards: Vec<ShardExport>,
+}
+
+/// The contents of one shard of a base table, as of the last batch of writes it committed.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ShardExport {
+    /// The sequence number of the last batch of writes that is included in `rows`.
+    ///
+    /// See `ControllerHandle::commit_seqs`.
+    pub commit_seq: u64,
+    /// The rows of the shard.
+    pub rows: Vec<Vec<DataType>>,
+}
+
+impl BaseExport {
+    /// The total number of rows in the export.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.rows.len()).sum()
+    }
+
+    /// Whether the export holds no rows.
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|s| s.rows.is_empty())
+    }
+}
@@ -0,0 +1,144 @@
+use crate::data::DataType;
+use chrono::{Datelike, NaiveDate, Timelike};
+use std::borrow::Cow;
+
+/// A unit that timestamps can be truncated to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TimeUnit {
+    /// Drop fractional seconds.
+    Second,
+    /// Drop seconds.
+    Minute,
+    /// Drop minutes.
+    Hour,
+    /// Keep only the date.
+    Day,
+    /// Keep only the year and month.
+    Month,
+    /// Keep only the year.
+    Year,
+}
+
+impl TimeUnit {
+    fn name(self) -> &'static str {
+        match self {
+            TimeUnit::Second => "second",
+            TimeUnit::Minute => "minute",
+            TimeUnit::Hour => "hour",
+            TimeUnit::Day => "day",
+            TimeUnit::Month => "month",
+            TimeUnit::Year => "year",
+        }
+    }
+}
+
+/// A value computed from a column of a view, which the view can be keyed on in place of the
+/// column itself.
+///
+/// Views keyed on computed values store the computed values as additional columns after the
+/// view's own columns. Every expression gives the same result when applied to its own result, so
+/// lookups can use either the original or the computed values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum KeyExpression {
+    /// The column itself.
+    Column(usize),
+    /// The column in lower case. Values that are not strings are left as they are.
+    Lower(usize),
+    /// The column truncated to the given unit. Values that are not timestamps are left as they
+    /// are.
+    DateTrunc(TimeUnit, usize),
+}
+
+impl KeyExpression {
+    /// The column that the expression is computed from.
+    pub fn column(&self) -> usize {
+        match *self {
+            KeyExpression::Column(c) | KeyExpression::Lower(c) | KeyExpression::DateTrunc(_, c) => {
+                c
+            }
+        }
+    }
+
+    /// Whether the expression is just the column itself.
+    pub fn is_column(&self) -> bool {
+        if let KeyExpression::Column(_) = *self {
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Compute the expression for the given value of its column.
+    pub fn apply(&self, value: &DataType) -> DataType {
+        match *self {
+            KeyExpression::Column(_) => value.clone(),
+            KeyExpression::Lower(_) if value.is_string() => {
+                let s: Cow<'_, str> = value.into();
+                DataType::from(s.to_lowercase())
+            }
+            KeyExpression::DateTrunc(unit, _) => match *value {
+                DataType::Timestamp(ts) => {
+                    let date = ts.date();
+                    DataType::Timestamp(match unit {
+                        TimeUnit::Second => date.and_hms(ts.hour(), ts.minute(), ts.second()),
+                        TimeUnit::Minute => date.and_hms(ts.hour(), ts.minute(), 0),
+                        TimeUnit::Hour => date.and_hms(ts.hour(), 0, 0),
+                        TimeUnit::Day => date.and_hms(0, 0, 0),
+                        TimeUnit::Month => {
+                            NaiveDate::from_ymd(date.year(), date.month(), 1).and_hms(0, 0, 0)
+                        }
+                        TimeUnit::Year => NaiveDate::from_ymd(date.year(), 1, 1).and_hms(0, 0, 0),
+                    })
+                }
+                _ => value.clone(),
+            },
+            KeyExpression::Lower(_) => value.clone(),
+        }
+    }
+
+    /// A name for the expression, given the name of its column.
+    pub fn name(&self, column: &str) -> String {
+        match *self {
+            KeyExpression::Column(_) => column.to_owned(),
+            KeyExpression::Lower(_) => format!("lower({})", column),
+            KeyExpression::DateTrunc(unit, _) => {
+                format!("date_trunc('{}', {})", unit.name(), column)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lower() {
+        let e = KeyExpression::Lower(0);
+        assert_eq!(e.apply(&"Foo@Example.COM".into()), "foo@example.com".into());
+        assert_eq!(e.apply(&e.apply(&"AbC".into())), "abc".into());
+        assert_eq!(e.apply(&42.into()), 42.into());
+        assert_eq!(e.name("email"), "lower(email)");
+    }
+
+    #[test]
+    fn date_trunc() {
+        let ts = NaiveDate::from_ymd(2019, 7, 23).and_hms(13, 45, 12);
+        let day = KeyExpression::DateTrunc(TimeUnit::Day, 1);
+        let month = KeyExpression::DateTrunc(TimeUnit::Month, 1);
+        assert_eq!(
+            day.apply(&ts.into()),
+            NaiveDate::from_ymd(2019, 7, 23).and_hms(0, 0, 0).into()
+        );
+        assert_eq!(
+            month.apply(&ts.into()),
+            NaiveDate::from_ymd(2019, 7, 1).and_hms(0, 0, 0).into()
+        );
+        assert_eq!(
+            month.apply(&month.apply(&ts.into())),
+            month.apply(&ts.into())
+        );
+        assert_eq!(day.apply(&DataType::None), DataType::None);
+        assert_eq!(day.name("ts"), "date_trunc('day', ts)");
+    }
+}
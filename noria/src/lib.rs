@@ -180,9 +180,22 @@ use petgraph::graph::NodeIndex;
 use std::collections::HashMap;
 use tokio_tower::multiplex;
 
+mod audit;
+mod connection;
+mod consistency;
 mod controller;
 mod data;
+mod dml;
+mod export;
+mod key;
+mod query;
+mod sample;
+mod scaling;
+mod supervision;
 mod table;
+mod telemetry;
+mod upgrade;
+mod verification;
 mod view;
 
 #[doc(hidden)]
@@ -214,11 +227,13 @@ pub mod prelude {
 
 /// Wrapper types for Noria query results.
 pub mod results {
+    pub use super::view::filter::{Comparison, Predicate};
     pub use super::view::results::{ResultRow, Results, Row};
 }
 
 /// Noria errors.
 pub mod error {
+    pub use crate::controller::RpcError;
     pub use crate::table::TableError;
     pub use crate::view::ViewError;
 }
@@ -237,7 +252,9 @@ fn trace_next_op() -> bool {
 
 /// The next Noria read or write issued from the current thread will be traced using tokio-trace.
 ///
-/// The trace output is visible by setting the environment variable `RUST_LOG=trace`.
+/// The trace output is visible by setting the environment variable `RUST_LOG=trace`. Writes also
+/// carry a `TraceContext` to the server, which records the write's path through the data-flow
+/// graph if it exports traces.
 pub fn trace_my_next_op() {
     TRACE_NEXT.with(|tn| {
         *tn.borrow_mut() = true;
@@ -263,7 +280,7 @@ impl<Request, Response> multiplex::TagStore<Tagged<Request>, Tagged<Response>> f
 }
 
 #[doc(hidden)]
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Tagged<T> {
     pub tag: u32,
     pub v: T,
@@ -275,13 +292,25 @@ impl<T> From<T> for Tagged<T> {
     }
 }
 
-pub use crate::controller::{ControllerDescriptor, ControllerHandle};
+pub use crate::audit::{AuditEntry, AuditOperation};
+pub use crate::connection::{ClientConnection, ConnectionKind};
+pub use crate::consistency::ConsistencyEvent;
+pub use crate::controller::{ControllerDescriptor, ControllerHandle, WarmKeys};
 pub use crate::data::{DataType, Modification, Operation, TableOperation};
-pub use crate::table::Table;
-pub use crate::view::View;
+pub use crate::export::{BaseExport, ShardExport};
+pub use crate::key::{KeyExpression, TimeUnit};
+pub use crate::query::QueryInfo;
+pub use crate::sample::{KeySample, NodeSample};
+pub use crate::scaling::ScalingEvent;
+pub use crate::supervision::{DomainFailure, Failover, ReplayCorruption, WorkerFailure};
+pub use crate::table::{MirroredWrites, OrderedTable, Table, WriteWaits};
+pub use crate::telemetry::TraceContext;
+pub use crate::upgrade::UpgradeEvent;
+pub use crate::verification::BaseVerification;
+pub use crate::view::{ReplayPriority, ValidTime, View, ViewState};
 
 #[doc(hidden)]
-pub use crate::table::Input;
+pub use crate::table::{Input, WriteAck, WriteRejection};
 
 #[doc(hidden)]
 pub use crate::view::{ReadQuery, ReadReply};
@@ -306,6 +335,40 @@ pub struct ActivationResult {
     pub expressions_added: usize,
     /// Number of expressions the recipe removed compared to the prior recipe.
     pub expressions_removed: usize,
+    /// Named queries that the recipe added.
+    pub queries_added: Vec<String>,
+    /// Named queries that the recipe removed.
+    pub queries_removed: Vec<String>,
+    /// Named queries that the recipe replaced with a different query of the same name.
+    pub queries_changed: Vec<String>,
+    /// The changes that activating the recipe made to the dataflow graph.
+    pub dataflow: DataflowDiff,
+}
+
+/// The changes that a recipe activation made to the dataflow graph.
+///
+/// This allows checking what a recipe change costs, for example that it does not cause any
+/// existing state to be rebuilt from scratch.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct DataflowDiff {
+    /// Nodes that were added to the graph, including ingress, egress, and sharder nodes.
+    pub nodes_created: Vec<NodeIndex>,
+    /// Nodes that existed before and that the new queries were built on.
+    pub nodes_reused: Vec<NodeIndex>,
+    /// Nodes that were removed from the graph.
+    pub nodes_removed: Vec<NodeIndex>,
+    /// Domains that were created.
+    pub domains_created: Vec<usize>,
+    /// Nodes whose state was filled by replaying all of the state of their ancestors.
+    pub full_replays: Vec<NodeIndex>,
+    /// Partially materialized nodes, whose state is replayed on demand once they are read.
+    pub partial_replays: Vec<NodeIndex>,
+    /// Joins that were added without some of the columns of their inputs, because their queries
+    /// do not use them, along with how many columns each leaves out of every row it emits.
+    ///
+    /// Only joins planned with column pruning leave columns out.
+    #[serde(default)]
+    pub pruned_columns: Vec<(NodeIndex, usize)>,
 }
 
 #[doc(hidden)]
@@ -324,6 +387,18 @@ pub fn shard_by(dt: &DataType, shards: usize) -> usize {
             hasher.write(s.as_bytes());
             hasher.finish() as usize % shards
         }
+        DataType::Bytes(ref bytes) => {
+            use std::hash::Hasher;
+            let mut hasher = fnv::FnvHasher::default();
+            hasher.write(bytes);
+            hasher.finish() as usize % shards
+        }
+        DataType::List(..) => {
+            use std::hash::{Hash, Hasher};
+            let mut hasher = fnv::FnvHasher::default();
+            dt.hash(&mut hasher);
+            hasher.finish() as usize % shards
+        }
         // a bit hacky: send all NULL values to the first shard
         DataType::None => 0,
         ref x => {
@@ -331,3 +406,210 @@ pub fn shard_by(dt: &DataType, shards: usize) -> usize {
         }
     }
 }
+
+/// Like `shard_by`, but for keys that span several columns.
+#[doc(hidden)]
+#[inline]
+pub fn shard_by_multi(key: &[DataType], shards: usize) -> usize {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = fnv::FnvHasher::default();
+    key.hash(&mut hasher);
+    hasher.finish() as usize % shards
+}
+
+/// Like `shard_by`, but with each shard owning a share of the keys proportional to its weight in
+/// `weights`. Keys are spread evenly if `weights` does not have a weight for each of the `shards`.
+///
+/// Keys are placed by weighted rendezvous hashing: every shard scores the key, scaled by its
+/// weight, and the shard with the highest score owns it. Changing the weight of one shard thus
+/// only moves keys to or from that shard.
+#[doc(hidden)]
+#[inline]
+pub fn shard_by_weight(dt: &DataType, shards: usize, weights: &[u32]) -> usize {
+    if weights.len() != shards {
+        return shard_by(dt, shards);
+    }
+    if let DataType::None = *dt {
+        // like `shard_by`, send all NULL values to the first shard
+        return 0;
+    }
+
+    let key = key_hash(dt);
+    let mut best = (0, std::f64::NEG_INFINITY);
+    for (shard, &w) in weights.iter().enumerate() {
+        // a uniform draw in (0, 1) that only depends on the key and the shard
+        let draw = ((mix(key ^ mix(shard as u64)) >> 11) as f64 + 0.5) / (1u64 << 53) as f64;
+        let score = f64::from(w) / -draw.ln();
+        if score > best.1 {
+            best = (shard, score);
+        }
+    }
+    best.0
+}
+
+/// A stable 64-bit hash of a key, for placing it with `shard_by_weight`.
+fn key_hash(dt: &DataType) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = fnv::FnvHasher::default();
+    match *dt {
+        DataType::Int(n) => hasher.write_i64(i64::from(n)),
+        DataType::UnsignedInt(n) => hasher.write_i64(i64::from(n)),
+        DataType::BigInt(n) => hasher.write_i64(n),
+        DataType::UnsignedBigInt(n) => hasher.write_i64(n as i64),
+        DataType::Text(..) | DataType::TinyText(..) => {
+            let s: std::borrow::Cow<'_, str> = dt.into();
+            hasher.write(s.as_bytes());
+        }
+        DataType::Bytes(ref bytes) => hasher.write(bytes),
+        DataType::List(..) => dt.hash(&mut hasher),
+        ref x => {
+            unimplemented!("asked to shard on value {:?}", x);
+        }
+    }
+    hasher.finish()
+}
+
+/// The finalizer of SplitMix64, which spreads the bits of `x` over the whole word.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Like `shard_by_weight`, but with each shard owning a range of keys if `ranges` has a split
+/// point between each pair of adjacent `shards`: shard `i` then owns the keys from `ranges[i - 1]`
+/// up to, but not including, `ranges[i]`. Otherwise, keys are spread as by `shard_by_weight`.
+///
+/// Keys are compared to the split points with `range_order`.
+#[doc(hidden)]
+#[inline]
+pub fn shard_by_range(dt: &DataType, shards: usize, ranges: &[DataType], weights: &[u32]) -> usize {
+    if ranges.len() + 1 != shards {
+        return shard_by_weight(dt, shards, weights);
+    }
+    match ranges.binary_search_by(|split| range_order(split, dt)) {
+        Ok(i) => i + 1,
+        Err(i) => i,
+    }
+}
+
+/// A total order over keys for placing them in the ranges of range-sharded tables.
+///
+/// `DataType`'s own ordering is only consistent among values of the same kind. This one compares
+/// numbers by their value whatever their type, so that integer keys fall into the right range
+/// between split points with a fractional part and the other way around. Values of different
+/// kinds are ordered by kind: `NULL`, numbers, text, timestamps, bytes, and then lists.
+#[doc(hidden)]
+pub fn range_order(a: &DataType, b: &DataType) -> std::cmp::Ordering {
+    fn kind(dt: &DataType) -> u8 {
+        match *dt {
+            DataType::None => 0,
+            DataType::Int(..)
+            | DataType::UnsignedInt(..)
+            | DataType::BigInt(..)
+            | DataType::UnsignedBigInt(..)
+            | DataType::Real(..) => 1,
+            DataType::Text(..) | DataType::TinyText(..) => 2,
+            DataType::Timestamp(..) => 3,
+            DataType::Bytes(..) => 4,
+            DataType::List(..) => 5,
+        }
+    }
+
+    fn number(dt: &DataType) -> f64 {
+        match *dt {
+            DataType::UnsignedInt(n) => f64::from(n),
+            DataType::UnsignedBigInt(n) => n as f64,
+            ref n => n.into(),
+        }
+    }
+
+    match (a, b) {
+        (&DataType::Real(..), _) | (_, &DataType::Real(..)) if kind(a) == 1 && kind(b) == 1 => {
+            let (a, b) = (number(a), number(b));
+            a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+        }
+        _ if kind(a) == kind(b) => a.cmp(b),
+        _ => kind(a).cmp(&kind(b)),
+    }
+}
+
+/// The shard that `op` must be sent to, for a base table with `shards` shards that is sharded by
+/// the columns `key`. Single-column keys are placed by `shard_by_range`, and compound keys by
+/// `shard_by_multi`.
+#[doc(hidden)]
+pub fn shard_by_operation(
+    op: &TableOperation,
+    key: &[usize],
+    shards: usize,
+    ranges: &[DataType],
+    weights: &[u32],
+) -> usize {
+    use std::borrow::Cow;
+
+    if key.len() == 1 {
+        let key_col = key[0];
+        let key = match *op {
+            TableOperation::Insert(ref r) => &r[key_col],
+            TableOperation::Delete { ref key } => &key[0],
+            TableOperation::Update { ref key, .. } => &key[0],
+            TableOperation::InsertOrUpdate { ref row, .. } => &row[key_col],
+        };
+        shard_by_range(key, shards, ranges, weights)
+    } else {
+        // base sharded by compound key
+        let key: Cow<'_, [DataType]> = match *op {
+            TableOperation::Insert(ref r) | TableOperation::InsertOrUpdate { row: ref r, .. } => {
+                key.iter().map(|&c| r[c].clone()).collect()
+            }
+            TableOperation::Delete { ref key } | TableOperation::Update { ref key, .. } => {
+                Cow::Borrowed(&key[..])
+            }
+        };
+        shard_by_multi(&key, shards)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_shards_compare_numbers_by_value() {
+        let ranges = vec![DataType::from(10), DataType::from(20.5)];
+        assert_eq!(shard_by_range(&9.into(), 3, &ranges, &[]), 0);
+        assert_eq!(shard_by_range(&DataType::BigInt(10), 3, &ranges, &[]), 1);
+        assert_eq!(
+            shard_by_range(&DataType::UnsignedInt(20), 3, &ranges, &[]),
+            1
+        );
+        assert_eq!(shard_by_range(&DataType::from(20.75), 3, &ranges, &[]), 2);
+        assert_eq!(shard_by_range(&21.into(), 3, &ranges, &[]), 2);
+
+        // other kinds of values are ordered by kind, around the numbers
+        assert_eq!(shard_by_range(&DataType::None, 3, &ranges, &[]), 0);
+        assert_eq!(shard_by_range(&"9".into(), 3, &ranges, &[]), 2);
+    }
+
+    #[test]
+    fn weighted_shards_only_give_up_keys_to_the_shard_that_changed() {
+        let before: Vec<_> = (0..1000)
+            .map(|k: i32| shard_by_weight(&k.into(), 3, &[1, 1, 1]))
+            .collect();
+        let after: Vec<_> = (0..1000)
+            .map(|k: i32| shard_by_weight(&k.into(), 3, &[1, 1, 3]))
+            .collect();
+
+        // about a third of the keys went to the shard that got heavier, and no key moved
+        // between the other two
+        let moved = before.iter().zip(&after).filter(|(b, a)| b != a).count();
+        assert!(moved > 200 && moved < 500, "{} keys moved", moved);
+        assert!(before.iter().zip(&after).all(|(&b, &a)| b == a || a == 2));
+        let heavy = after.iter().filter(|&&s| s == 2).count();
+        assert!(
+            heavy > 500 && heavy < 700,
+            "{} keys on the heavy shard",
+            heavy
+        );
+    }
+}
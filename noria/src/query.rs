@@ -0,0 +1,20 @@
+/// A query installed in the recipe, as described by `ControllerHandle::queries`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct QueryInfo {
+    /// The name of the query, which is also the name of its view.
+    pub name: String,
+    /// The SQL text of the query, as it is stored in the recipe.
+    pub sql: String,
+    /// The names of the columns the query's view is looked up by.
+    pub key_columns: Vec<String>,
+    /// The number of shards the query's view is split into.
+    pub shards: usize,
+    /// Whether the view only holds the keys that have been read.
+    pub partial: bool,
+    /// Whether the view has been paused with `ControllerHandle::pause_view`.
+    pub paused: bool,
+    /// Whether every shard of the view is running on a healthy worker and can serve reads.
+    pub ready: bool,
+    /// The memory used by the view's state across all shards that could be reached, in bytes.
+    pub mem_size: u64,
+}
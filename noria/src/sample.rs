@@ -0,0 +1,43 @@
+use crate::data::DataType;
+
+/// The rows a view returns for a key, along with the state entries that upstream operators hold
+/// for the same key.
+///
+/// See `ControllerHandle::sample_key`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeySample {
+    /// The view that was sampled.
+    pub view: String,
+    /// The key that was sampled.
+    pub key: Vec<DataType>,
+    /// The rows the view currently returns for `key`.
+    pub rows: Vec<Vec<DataType>>,
+    /// The operators that the rows of the view are computed from, ordered from the view towards
+    /// the base tables.
+    ///
+    /// Only operators whose columns the key can be traced back to are included.
+    pub upstream: Vec<NodeSample>,
+}
+
+/// The state entries an operator holds for a sampled key.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NodeSample {
+    /// The global index of the operator.
+    pub node: usize,
+    /// The name of the operator.
+    pub name: String,
+    /// A description of what the operator does.
+    pub description: String,
+    /// The global indices of the operator's parents.
+    pub parents: Vec<usize>,
+    /// The columns of the operator that hold the sampled key.
+    pub key_columns: Vec<usize>,
+    /// Whether the operator keeps any state.
+    pub materialized: bool,
+    /// The operator's rows for the sampled key.
+    ///
+    /// This is `None` if the operator keeps no state, if its state is partial and does not
+    /// currently hold the key, or if its state is not indexed by `key_columns` and is either
+    /// partial or too large to scan.
+    pub rows: Option<Vec<Vec<DataType>>>,
+}
@@ -0,0 +1,23 @@
+/// A change to the number of readers that serve a view, made by the controller as the reads of
+/// the view queue up or subside.
+///
+/// See `ControllerHandle::scaling_events`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScalingEvent {
+    /// A read replica was added to the view because reads stayed queued on it.
+    ReplicaAdded {
+        /// The name of the view.
+        view: String,
+        /// The number of read replicas the view has after the change.
+        replicas: usize,
+        /// The most reads waiting on any shard of the view's readers when the replica was added.
+        queued: usize,
+    },
+    /// A read replica was removed from the view because its reads no longer queued up.
+    ReplicaRemoved {
+        /// The name of the view.
+        view: String,
+        /// The number of read replicas the view has after the change.
+        replicas: usize,
+    },
+}
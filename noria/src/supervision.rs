@@ -0,0 +1,79 @@
+use std::net::SocketAddr;
+
+/// A domain that panicked on a worker with domain supervision enabled.
+///
+/// See `ControllerHandle::domain_failures`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DomainFailure {
+    /// The worker that ran the domain.
+    pub worker: SocketAddr,
+    /// The index of the domain.
+    pub domain: usize,
+    /// The shard of the domain.
+    pub shard: usize,
+    /// What the domain panicked with.
+    pub reason: String,
+    /// Whether the worker rebuilt the domain in place.
+    ///
+    /// Only domains that keep no state are rebuilt, and only if they panicked on something other
+    /// than updates, replays, or evictions, which would otherwise be lost. A domain that was not
+    /// rebuilt no longer processes updates.
+    pub respawned: bool,
+    /// The queries that were rebuilt because they depended on the domain, if it was not
+    /// respawned. Their views are served by new domains from then on.
+    ///
+    /// Domains with base tables cannot be rebuilt, and neither can the queries over them.
+    pub queries: Vec<String>,
+}
+
+/// A domain that was moved off the worker that ran it on request.
+///
+/// See `ControllerHandle::fail_over`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Failover {
+    /// The worker the domain was moved off.
+    pub worker: SocketAddr,
+    /// The index of the domain.
+    pub domain: usize,
+    /// The shard of the domain.
+    pub shard: usize,
+    /// The queries that were rebuilt on other workers. Their views are served by new domains from
+    /// then on.
+    pub queries: Vec<String>,
+}
+
+/// A worker that stopped sending heartbeats, and whose domains were placed anew.
+///
+/// See `ControllerHandle::worker_failures`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WorkerFailure {
+    /// The worker that failed.
+    pub worker: SocketAddr,
+    /// The shards of domains that the worker ran, as `(domain, shard)` pairs.
+    pub domains: Vec<(usize, usize)>,
+    /// The queries that were rebuilt on the remaining workers because they depended on those
+    /// domains.
+    pub queries: Vec<String>,
+}
+
+/// A replay piece that arrived at a domain with data that did not match its checksum.
+///
+/// See `ControllerHandle::replay_corruptions`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReplayCorruption {
+    /// The worker that runs the domain.
+    pub worker: SocketAddr,
+    /// The index of the domain that received the piece.
+    pub domain: usize,
+    /// The shard of the domain that received the piece.
+    pub shard: usize,
+    /// The replay path the piece was sent along.
+    pub tag: u32,
+    /// How many keys the piece was for, or 0 if it was part of a full replay.
+    pub keys: usize,
+    /// Whether the domain recovered by asking for the piece's keys again.
+    pub recovered: bool,
+    /// The queries that were rebuilt because the domain could not recover. Their views are served
+    /// by new domains from then on.
+    pub queries: Vec<String>,
+}
@@ -2,6 +2,7 @@ use crate::channel::CONNECTION_FROM_BASE;
 use crate::data::*;
 use crate::internal::*;
 use crate::LocalOrNot;
+use crate::TraceContext;
 use crate::{Tagged, Tagger};
 use async_bincode::{AsyncBincodeStream, AsyncDestination};
 use futures_util::{
@@ -10,11 +11,14 @@ use futures_util::{
 };
 use nom_sql::CreateTableStatement;
 use petgraph::graph::NodeIndex;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::future::Future;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 use std::{fmt, io};
 use tokio::io::AsyncWriteExt;
 use tokio_tower::multiplex;
@@ -26,7 +30,7 @@ use vec_map::VecMap;
 
 type Transport = AsyncBincodeStream<
     tokio::net::TcpStream,
-    Tagged<()>,
+    Tagged<WriteAck>,
     Tagged<LocalOrNot<Input>>,
     AsyncDestination,
 >;
@@ -261,9 +265,76 @@ pub enum TableError {
     )]
     WrongKeyColumnCount(usize, usize),
 
+    /// A value was larger than the size limit of its column.
+    #[fail(
+        display = "value for column {} is {} bytes, which exceeds its limit of {} bytes",
+        _0, _1, _2
+    )]
+    ValueTooLarge(usize, usize, usize),
+
     /// The underlying connection to Noria produced an error.
     #[fail(display = "{}", _0)]
     TransportError(#[cause] failure::Error),
+
+    /// The write was not acknowledged before its deadline.
+    ///
+    /// The write may still be applied. The `Table` handle remains usable.
+    #[fail(display = "write was not acknowledged before its deadline")]
+    TimedOut,
+
+    /// An all-or-nothing write was not applied, since some of its operations could not be.
+    ///
+    /// Holds the index of each such operation in the write, along with why it was rejected.
+    #[fail(display = "write was rejected: {:?}", _0)]
+    Rejected(Vec<(usize, String)>),
+
+    /// An all-or-nothing write had operations for more than one shard of the table.
+    #[fail(display = "all-or-nothing write spans several shards")]
+    SpansShards,
+
+    /// The write was turned away without being applied, since a migration is in progress.
+    ///
+    /// The write can safely be sent again once the migration completes, which
+    /// `Table::set_migration_backoff` makes the handle do by itself. If the write spanned several
+    /// shards, the shards that did not turn it away may have applied their part of it.
+    #[fail(display = "write was not applied since a migration is in progress")]
+    MigrationInProgress,
+
+    /// The write was turned away without being applied, since the table is being removed.
+    #[fail(display = "write was not applied since the table is being removed")]
+    Draining,
+
+    /// The write was turned away without being applied, since it was made to a best-effort table
+    /// while the table's domain was overloaded.
+    ///
+    /// The write can be sent again, but will likely be turned away again until the load subsides.
+    #[fail(display = "write was shed since the table's domain is overloaded")]
+    Shed,
+
+    /// An ordered table handle was asked for with no lanes.
+    #[fail(display = "an ordered table needs at least one lane per shard")]
+    NoLanes,
+
+    /// The fraction of writes to mirror was not in (0, 1].
+    #[fail(
+        display = "the mirrored fraction of writes must be in (0, 1], not {}",
+        _0
+    )]
+    InvalidMirrorFraction(f64),
+}
+
+impl From<WriteRejection> for TableError {
+    fn from(r: WriteRejection) -> Self {
+        match r {
+            WriteRejection::Rows(rows) => TableError::Rejected(rows),
+            WriteRejection::MigrationInProgress => TableError::MigrationInProgress,
+            WriteRejection::Draining => TableError::Draining,
+            WriteRejection::Shed => TableError::Shed,
+            WriteRejection::ValueTooLarge(col, len, limit) => {
+                TableError::ValueTooLarge(col, len, limit)
+            }
+        }
+    }
 }
 
 impl From<Box<dyn std::error::Error + Send + Sync>> for TableError {
@@ -272,11 +343,88 @@ impl From<Box<dyn std::error::Error + Send + Sync>> for TableError {
     }
 }
 
+/// How long writes through a [`Table`] and its clones have spent waiting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WriteWaits {
+    /// The number of writes that were issued.
+    pub writes: u64,
+    /// The number of writes that gave up because they were not acknowledged before their deadline.
+    pub timed_out: u64,
+    /// The total time writes waited for busy shards to accept them.
+    pub backpressure: Duration,
+    /// The longest time a single write waited for busy shards to accept it.
+    pub max_backpressure: Duration,
+    /// The total time from issuing writes until they were acknowledged or gave up.
+    pub total: Duration,
+}
+
+/// The writes a [`Table`] and its clones have mirrored to a shadow table.
+///
+/// See `Table::mirror_to`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MirroredWrites {
+    /// The number of operations that were sampled and sent to the shadow table.
+    pub sent: u64,
+    /// The number of sent operations that the shadow table did not acknowledge.
+    pub failed: u64,
+    /// The number of sampled operations that were not sent because too many mirrored writes
+    /// were already in flight.
+    pub dropped: u64,
+}
+
+/// Where, and how many of, the writes through a `Table` are mirrored.
+#[derive(Clone)]
+struct Mirror {
+    shadow: Box<Table>,
+    fraction: f64,
+    writes: Arc<Mutex<MirroredWrites>>,
+    in_flight: Arc<AtomicUsize>,
+    max_in_flight: usize,
+}
+
+/// A shard's reply to a write: the sequence number the write was committed with, or why the
+/// write was not applied.
+#[doc(hidden)]
+pub type WriteAck = Result<u64, WriteRejection>;
+
+/// Why a shard did not apply a write.
+#[doc(hidden)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WriteRejection {
+    /// Operations of an all-or-nothing write that could not be applied, along with why.
+    Rows(Vec<(usize, String)>),
+    /// A migration is in progress, and the domain is turning writes away until it completes.
+    MigrationInProgress,
+    /// The table is being removed, and its domain has stopped accepting writes.
+    Draining,
+    /// The table is a best-effort table, and its domain is shedding its writes since it is
+    /// overloaded.
+    Shed,
+    /// A value of the write was larger than the size limit of its column.
+    ///
+    /// Holds the column, the size of the value in bytes, and the limit of the column.
+    ValueTooLarge(usize, usize, usize),
+}
+
+/// The longest a `Table` waits before sending a write again while a migration is in progress.
+const MAX_MIGRATION_BACKOFF: Duration = Duration::from_secs(1);
+
+/// How many mirrored writes a `Table` and its clones have in flight at most, unless the server
+/// sets a limit for the base table.
+const DEFAULT_MIRROR_LIMIT: usize = 1024;
+
 #[doc(hidden)]
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Input {
     pub dst: LocalNodeIndex,
     pub data: Vec<TableOperation>,
+    #[serde(default)]
+    pub identity: Option<String>,
+    #[serde(default)]
+    pub trace: Option<TraceContext>,
+    /// Apply `data` only if every one of the operations can be applied.
+    #[serde(default)]
+    pub all_or_nothing: bool,
 }
 
 impl fmt::Debug for Input {
@@ -284,6 +432,9 @@ impl fmt::Debug for Input {
         fmt.debug_struct("Input")
             .field("dst", &self.dst)
             .field("data", &self.data)
+            .field("identity", &self.identity)
+            .field("trace", &self.trace)
+            .field("all_or_nothing", &self.all_or_nothing)
             .finish()
     }
 }
@@ -301,6 +452,14 @@ pub struct TableBuilder {
     pub table_name: String,
     pub columns: Vec<String>,
     pub schema: Option<CreateTableStatement>,
+    #[serde(default)]
+    pub size_limits: Vec<Option<usize>>,
+    #[serde(default)]
+    pub shard_weights: Vec<u32>,
+    #[serde(default)]
+    pub shard_ranges: Vec<DataType>,
+    #[serde(default)]
+    pub mirror_limit: Option<usize>,
 }
 
 impl TableBuilder {
@@ -354,7 +513,16 @@ impl TableBuilder {
             dropped: self.dropped,
             table_name: self.table_name,
             schema: self.schema,
+            size_limits: self.size_limits,
+            shard_weights: self.shard_weights,
+            shard_ranges: self.shard_ranges,
+            mirror_limit: self.mirror_limit.unwrap_or(DEFAULT_MIRROR_LIMIT),
             dst_is_local: false,
+            identity: None,
+            write_timeout: None,
+            migration_backoff: None,
+            waits: Default::default(),
+            mirror: None,
 
             shard_addrs: addrs,
             shards: conns,
@@ -380,7 +548,16 @@ pub struct Table {
     dropped: VecMap<DataType>,
     table_name: String,
     schema: Option<CreateTableStatement>,
+    size_limits: Vec<Option<usize>>,
+    shard_weights: Vec<u32>,
+    shard_ranges: Vec<DataType>,
+    mirror_limit: usize,
     dst_is_local: bool,
+    identity: Option<String>,
+    write_timeout: Option<Duration>,
+    migration_backoff: Option<Duration>,
+    waits: Arc<Mutex<WriteWaits>>,
+    mirror: Option<Mirror>,
 
     shards: Vec<TableRpc>,
     shard_addrs: Vec<SocketAddr>,
@@ -399,7 +576,18 @@ impl fmt::Debug for Table {
             .field("dropped", &self.dropped)
             .field("table_name", &self.table_name)
             .field("schema", &self.schema)
+            .field("size_limits", &self.size_limits)
             .field("dst_is_local", &self.dst_is_local)
+            .field("identity", &self.identity)
+            .field("write_timeout", &self.write_timeout)
+            .field("migration_backoff", &self.migration_backoff)
+            .field(
+                "mirror",
+                &self
+                    .mirror
+                    .as_ref()
+                    .map(|m| (m.shadow.table_name(), m.fraction)),
+            )
             .field("shard_addrs", &self.shard_addrs)
             .finish()
     }
@@ -410,8 +598,9 @@ impl Table {
     fn input(
         &mut self,
         mut i: Input,
-    ) -> impl Future<Output = Result<Tagged<()>, TableError>> + Send {
+    ) -> impl Future<Output = Result<Tagged<Vec<Option<u64>>>, TableError>> + Send {
         let span = if crate::trace_next_op() {
+            i.trace = Some(TraceContext::new_root());
             Some(tracing::trace_span!(
                 "table-request",
                 base = self.ni.index()
@@ -424,6 +613,10 @@ impl Table {
         let immediate_err = || {
             let ncols = self.columns.len() + self.dropped.len();
             for op in &i.data {
+                if let Some((col, len)) = op.exceeds_size_limits(&self.size_limits) {
+                    let limit = self.size_limits[col].unwrap();
+                    return Err(TableError::ValueTooLarge(col, len, limit));
+                }
                 match op {
                     TableOperation::Insert(ref row) => {
                         if row.len() != ncols {
@@ -464,6 +657,14 @@ impl Table {
                     }
                 }
             }
+            if i.all_or_nothing && self.shards.len() > 1 {
+                let mut shards = i.data.iter().map(|op| self.shard_of(op));
+                if let Some(first) = shards.next() {
+                    if shards.any(|s| s != first) {
+                        return Err(TableError::SpansShards);
+                    }
+                }
+            }
             Ok(())
         };
 
@@ -472,60 +673,42 @@ impl Table {
         }
 
         if self.shards.len() == 1 {
-            let request = Tagged::from(if self.dst_is_local {
-                unsafe { LocalOrNot::for_local_transfer(i) }
-            } else {
-                LocalOrNot::new(i)
-            });
-
             let _guard = span.as_ref().map(tracing::Span::enter);
             tracing::trace!("submit request");
-            future::Either::Right(future::Either::Left(
-                self.shards[0].call(request).map_err(TableError::from),
-            ))
+            future::Either::Right(future::Either::Left(self.submit(0, i).and_then(
+                |Tagged { tag, v: ack }| {
+                    future::ready(
+                        ack.map(|seq| Tagged {
+                            tag,
+                            v: vec![Some(seq)],
+                        })
+                        .map_err(TableError::from),
+                    )
+                },
+            )))
         } else {
             if self.key.is_empty() {
                 unreachable!("sharded base without a key?");
             }
-            if self.key.len() != 1 {
-                // base sharded by complex key
-                unimplemented!();
-            }
-            let key_col = self.key[0];
 
             let _guard = span.as_ref().map(tracing::Span::enter);
             tracing::trace!("shard request");
             let mut shard_writes = vec![Vec::new(); self.shards.len()];
             for r in i.data.drain(..) {
-                let shard = {
-                    let key = match r {
-                        TableOperation::Insert(ref r) => &r[key_col],
-                        TableOperation::Delete { ref key } => &key[0],
-                        TableOperation::Update { ref key, .. } => &key[0],
-                        TableOperation::InsertOrUpdate { ref row, .. } => &row[key_col],
-                    };
-                    crate::shard_by(key, self.shards.len())
-                };
+                let shard = self.shard_of(&r);
                 shard_writes[shard].push(r);
             }
 
             let wait_for = FuturesUnordered::new();
             for (s, rs) in shard_writes.drain(..).enumerate() {
                 if !rs.is_empty() {
-                    let p = if self.dst_is_local {
-                        unsafe {
-                            LocalOrNot::for_local_transfer(Input {
-                                dst: i.dst,
-                                data: rs,
-                            })
-                        }
-                    } else {
-                        LocalOrNot::new(Input {
-                            dst: i.dst,
-                            data: rs,
-                        })
+                    let input = Input {
+                        dst: i.dst,
+                        data: rs,
+                        identity: i.identity.clone(),
+                        trace: i.trace,
+                        all_or_nothing: i.all_or_nothing,
                     };
-                    let request = Tagged::from(p);
 
                     // make a span per shard
                     let span = if span.is_some() {
@@ -536,7 +719,7 @@ impl Table {
                     let _guard = span.as_ref().map(tracing::Span::enter);
                     tracing::trace!("submit request shard");
 
-                    wait_for.push(self.shards[s].call(request));
+                    wait_for.push(self.submit(s, input).map_ok(move |t| (s, t.v)));
                 } else {
                     // poll_ready reserves a sender slot which we have to release
                     // we do that by dropping the old handle and replacing it with a clone
@@ -545,20 +728,111 @@ impl Table {
                 }
             }
 
+            let nshards = self.shards.len();
             future::Either::Right(future::Either::Right(
                 wait_for
-                    .try_for_each(|_| async { Ok(()) })
-                    .map_err(TableError::from)
+                    .try_fold(vec![None; nshards], |mut seqs, (s, ack)| async move {
+                        seqs[s] = Some(ack.map_err(TableError::from)?);
+                        Ok(seqs)
+                    })
                     .map_ok(Tagged::from),
             ))
         }
     }
+
+    /// Send `input` to shard `s`, whose handle must already be ready.
+    ///
+    /// If the shard turns the write away since a migration is in progress, and a backoff has been
+    /// set with `set_migration_backoff`, the write is sent again once the backoff has passed, and
+    /// the backoff doubles every time the write is turned away.
+    fn submit(
+        &mut self,
+        s: usize,
+        input: Input,
+    ) -> impl Future<Output = Result<Tagged<WriteAck>, TableError>> + Send {
+        let local = self.dst_is_local;
+        let request = move |i: Input| {
+            Tagged::from(if local {
+                unsafe { LocalOrNot::for_local_transfer(i) }
+            } else {
+                LocalOrNot::new(i)
+            })
+        };
+
+        let retry = self
+            .migration_backoff
+            .map(|backoff| (self.shards[s].clone(), input.clone(), backoff));
+        let first = self.shards[s].call(request(input));
+        async move {
+            let mut reply = first.await.map_err(TableError::from)?;
+            if let Some((mut shard, input, mut backoff)) = retry {
+                while let Err(WriteRejection::MigrationInProgress) = reply.v {
+                    tokio::time::delay_for(backoff).await;
+                    backoff = std::cmp::min(backoff * 2, MAX_MIGRATION_BACKOFF);
+                    future::poll_fn(|cx| shard.poll_ready(cx))
+                        .await
+                        .map_err(TableError::from)?;
+                    reply = shard
+                        .call(request(input.clone()))
+                        .await
+                        .map_err(TableError::from)?;
+                }
+            }
+            Ok(reply)
+        }
+    }
+
+    /// The shard that `op` must be sent to.
+    fn shard_of(&self, op: &TableOperation) -> usize {
+        crate::shard_by_operation(
+            op,
+            &self.key,
+            self.shards.len(),
+            &self.shard_ranges,
+            &self.shard_weights,
+        )
+    }
+
+    /// The shard that `op` must be sent to, and a hash of the key it writes to.
+    ///
+    /// Tables without a key are keyed by their whole row. Operations that are missing key columns
+    /// are all mapped to the same place; the write itself reports what is wrong with them.
+    fn ordering_of(&self, op: &TableOperation) -> (usize, u64) {
+        use std::hash::{Hash, Hasher};
+
+        let key: Cow<'_, [DataType]> = match *op {
+            TableOperation::Insert(ref r) | TableOperation::InsertOrUpdate { row: ref r, .. } => {
+                if self.key.is_empty() {
+                    Cow::Borrowed(&r[..])
+                } else if self.key.iter().all(|&c| c < r.len()) {
+                    self.key.iter().map(|&c| r[c].clone()).collect()
+                } else {
+                    return (0, 0);
+                }
+            }
+            TableOperation::Delete { ref key } | TableOperation::Update { ref key, .. } => {
+                if key.len() != self.key.len() {
+                    return (0, 0);
+                }
+                Cow::Borrowed(&key[..])
+            }
+        };
+
+        let shard = if self.shards.len() == 1 {
+            0
+        } else {
+            self.shard_of(op)
+        };
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        key.hash(&mut hasher);
+        (shard, hasher.finish())
+    }
 }
 
 impl Service<Vec<TableOperation>> for Table {
     type Error = TableError;
-    type Response = <TableRpc as Service<Tagged<LocalOrNot<Input>>>>::Response;
-    type Future = impl Future<Output = Result<Tagged<()>, TableError>> + Send;
+    type Response = Tagged<Vec<Option<u64>>>;
+    type Future = impl Future<Output = Result<Tagged<Vec<Option<u64>>>, TableError>> + Send;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         for s in &mut self.shards {
@@ -568,22 +842,191 @@ impl Service<Vec<TableOperation>> for Table {
     }
 
     fn call(&mut self, ops: Vec<TableOperation>) -> Self::Future {
+        self.mirror(&ops);
         let i = self.prep_records(ops);
         self.input(i)
     }
 }
 
+impl Service<Input> for Table {
+    type Error = TableError;
+    type Response = Tagged<Vec<Option<u64>>>;
+    type Future = impl Future<Output = Result<Tagged<Vec<Option<u64>>>, TableError>> + Send;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        <Self as Service<Vec<TableOperation>>>::poll_ready(self, cx)
+    }
+
+    fn call(&mut self, i: Input) -> Self::Future {
+        self.input(i)
+    }
+}
+
 impl Table {
     /// Get the name of this base table.
     pub fn table_name(&self) -> &str {
         &self.table_name
     }
 
+    /// Tag all subsequent writes through this handle with the given identity.
+    ///
+    /// If the base table has auditing enabled, the identity is recorded alongside every write in
+    /// the table's audit log (see `ControllerHandle::audit_log`). Clones of this handle made
+    /// after this call carry the same identity.
+    pub fn set_identity<S: Into<String>>(&mut self, identity: S) {
+        self.identity = Some(identity.into());
+    }
+
+    /// The identity writes through this handle are tagged with, if any.
+    pub fn identity(&self) -> Option<&str> {
+        self.identity.as_deref()
+    }
+
+    /// Give up on writes through this handle that are not acknowledged within `timeout`.
+    ///
+    /// Such writes fail with `TableError::TimedOut`. By default, writes wait for as long as it
+    /// takes the shards of the table to accept and acknowledge them.
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+        self.write_timeout = timeout;
+    }
+
+    /// Send writes through this handle again if they are turned away since a migration is in
+    /// progress, first after `backoff`, and then after twice as long each time, up to a second.
+    ///
+    /// Without a backoff, such writes fail with `TableError::MigrationInProgress`, which is the
+    /// default. Retried writes still give up once the write timeout passes, if one is set.
+    pub fn set_migration_backoff(&mut self, backoff: Option<Duration>) {
+        self.migration_backoff = backoff;
+    }
+
+    /// How long writes through this handle and its clones have spent waiting so far.
+    pub fn write_waits(&self) -> WriteWaits {
+        *self.waits.lock().unwrap()
+    }
+
+    /// Also send a random `fraction` of the operations written through this handle to `shadow`.
+    ///
+    /// `shadow` is usually a table of the same name in a separate deployment, such as one
+    /// running a changed recipe, which can then be load tested with a sample of real writes.
+    /// Operations are sent to `shadow` as they were given to this handle, so its columns may
+    /// differ. Writes through this handle neither wait for nor fail with the mirrored writes,
+    /// whose outcome is only counted in `mirrored_writes`. Clones of this handle made after this
+    /// call mirror their writes too, and so do the handles returned by `ordered`.
+    ///
+    /// At most as many mirrored writes as the server allows for this table (1024 unless set with
+    /// `Builder::set_mirror_limit`) are in flight at once across this handle and its clones;
+    /// sampled operations beyond that are dropped rather than sent, and counted as `dropped`.
+    ///
+    /// Fails with `TableError::InvalidMirrorFraction` unless `fraction` is in (0, 1].
+    ///
+    /// This spawns a task per mirrored write, and so must be used from within a tokio runtime.
+    pub fn mirror_to(&mut self, shadow: Table, fraction: f64) -> Result<(), TableError> {
+        if !(fraction > 0.0 && fraction <= 1.0) {
+            return Err(TableError::InvalidMirrorFraction(fraction));
+        }
+        self.mirror = Some(Mirror {
+            shadow: Box::new(shadow),
+            fraction,
+            writes: Default::default(),
+            in_flight: Default::default(),
+            max_in_flight: self.mirror_limit,
+        });
+        Ok(())
+    }
+
+    /// Stop mirroring the writes through this handle.
+    pub fn stop_mirroring(&mut self) {
+        self.mirror = None;
+    }
+
+    /// The writes this handle and its clones have mirrored since `mirror_to` was called.
+    pub fn mirrored_writes(&self) -> MirroredWrites {
+        self.mirror
+            .as_ref()
+            .map(|m| *m.writes.lock().unwrap())
+            .unwrap_or_default()
+    }
+
+    /// Send a sample of `ops` to the shadow table, if writes through this handle are mirrored.
+    fn mirror(&self, ops: &[TableOperation]) {
+        let mirror = match self.mirror {
+            Some(ref mirror) => mirror,
+            None => return,
+        };
+        let sampled: Vec<_> = ops
+            .iter()
+            .filter(|_| rand::random::<f64>() < mirror.fraction)
+            .cloned()
+            .collect();
+        if sampled.is_empty() {
+            return;
+        }
+
+        let n = sampled.len() as u64;
+        if mirror.in_flight.fetch_add(1, Ordering::AcqRel) >= mirror.max_in_flight {
+            mirror.in_flight.fetch_sub(1, Ordering::AcqRel);
+            mirror.writes.lock().unwrap().dropped += n;
+            return;
+        }
+        mirror.writes.lock().unwrap().sent += n;
+        let mut shadow = (*mirror.shadow).clone();
+        let writes = mirror.writes.clone();
+        let in_flight = mirror.in_flight.clone();
+        tokio::spawn(async move {
+            if shadow.perform_all(sampled).await.is_err() {
+                writes.lock().unwrap().failed += n;
+            }
+            in_flight.fetch_sub(1, Ordering::AcqRel);
+        });
+    }
+
+    /// Get a handle that delivers the writes for each key to this table in the order they were
+    /// submitted, using `lanes` queues per shard of the table.
+    ///
+    /// See [`OrderedTable`] for details. Each queue sends its writes through its own clone of
+    /// this handle, so they inherit its identity. The write timeout of this handle bounds how long
+    /// submitters wait for their writes, but the queues themselves wait for as long as it takes.
+    ///
+    /// Fails with `TableError::NoLanes` if `lanes` is 0.
+    ///
+    /// This spawns a task per queue, and so must be called from within a tokio runtime.
+    pub fn ordered(&self, lanes: usize) -> Result<OrderedTable, TableError> {
+        if lanes == 0 {
+            return Err(TableError::NoLanes);
+        }
+        let mut lane = self.clone();
+        lane.write_timeout = None;
+        let queues = (0..self.shards.len())
+            .map(|_| {
+                (0..lanes)
+                    .map(|_| {
+                        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+                        tokio::spawn(run_ordered_lane(lane.clone(), rx));
+                        tx
+                    })
+                    .collect()
+            })
+            .collect();
+        Ok(OrderedTable {
+            table: self.clone(),
+            queues,
+        })
+    }
+
     #[doc(hidden)]
     pub fn i_promise_dst_is_same_process(&mut self) {
         self.dst_is_local = true;
     }
 
+    /// The columns that make up this table's primary key, if it has one.
+    pub(crate) fn primary_key(&self) -> Option<&[usize]> {
+        if self.key_is_primary && !self.key.is_empty() {
+            Some(&self.key)
+        } else {
+            None
+        }
+    }
+
     /// Get the list of columns in this base table.
     ///
     /// Note that this will *not* be updated if the underlying recipe changes and adds or removes
@@ -694,19 +1137,68 @@ impl Table {
         Input {
             dst: self.node,
             data: ops,
+            identity: self.identity.clone(),
+            trace: None,
+            all_or_nothing: false,
         }
     }
 
-    async fn quick_n_dirty<Request, R>(
+    async fn quick_n_dirty<Request, R>(&mut self, r: Request) -> Result<R, TableError>
+    where
+        Request: Send + 'static,
+        Self: Service<Request, Response = Tagged<R>, Error = TableError>,
+    {
+        let deadline = self.write_timeout.map(|t| Instant::now() + t);
+        self.send_until(r, deadline).await
+    }
+
+    async fn send_until<Request, R>(
         &mut self,
         r: Request,
-    ) -> Result<R, <Self as Service<Request>>::Error>
+        deadline: Option<Instant>,
+    ) -> Result<R, TableError>
     where
         Request: Send + 'static,
-        Self: Service<Request, Response = Tagged<R>>,
+        Self: Service<Request, Response = Tagged<R>, Error = TableError>,
     {
-        future::poll_fn(|cx| self.poll_ready(cx)).await?;
-        Ok(self.call(r).await?.v)
+        let start = Instant::now();
+        let mut accepted = None;
+        let send = async {
+            future::poll_fn(|cx| self.poll_ready(cx)).await?;
+            accepted = Some(Instant::now());
+            Ok::<_, TableError>(self.call(r).await?.v)
+        };
+        let res = match deadline {
+            None => send.await,
+            Some(deadline) => {
+                let deadline = tokio::time::Instant::from_std(deadline);
+                match tokio::time::timeout_at(deadline, send).await {
+                    Ok(res) => res,
+                    Err(_) => Err(TableError::TimedOut),
+                }
+            }
+        };
+
+        if let Err(TableError::TimedOut) = res {
+            // poll_ready may have reserved sender slots that the abandoned write never used. we
+            // release them by replacing the handles with clones, so that the handle stays usable.
+            // https://github.com/tokio-rs/tokio/issues/898
+            for s in &mut self.shards {
+                *s = s.clone();
+            }
+        }
+
+        let done = Instant::now();
+        let backpressure = accepted.unwrap_or(done) - start;
+        let mut waits = self.waits.lock().unwrap();
+        waits.writes += 1;
+        if let Err(TableError::TimedOut) = res {
+            waits.timed_out += 1;
+        }
+        waits.backpressure += backpressure;
+        waits.max_backpressure = waits.max_backpressure.max(backpressure);
+        waits.total += done - start;
+        res
     }
 
     /// Insert a single row of data into this base table.
@@ -716,10 +1208,30 @@ impl Table {
     {
         self.quick_n_dirty(vec![TableOperation::Insert(u.into())])
             .await
+            .map(|_| ())
     }
 
     /// Perform multiple operation on this base table.
     pub async fn perform_all<I, V>(&mut self, i: I) -> Result<(), TableError>
+    where
+        I: IntoIterator<Item = V>,
+        V: Into<TableOperation>,
+    {
+        self.perform_all_seq(i).await.map(|_| ())
+    }
+
+    /// Perform multiple operation on this base table, and return the commit sequence numbers they
+    /// were assigned.
+    ///
+    /// Each shard of a base table numbers the batches of writes it commits, starting at 1. The
+    /// numbering is persisted along with the table's rows, so it carries on where it left off
+    /// when a durable table is recovered. The returned vector has one entry per shard of the
+    /// table, which is `None` for the shards the operations did not touch. Writes that were
+    /// merged into the same batch share a sequence number.
+    ///
+    /// The latest sequence number of every shard can be found with
+    /// `ControllerHandle::commit_seqs`.
+    pub async fn perform_all_seq<I, V>(&mut self, i: I) -> Result<Vec<Option<u64>>, TableError>
     where
         I: IntoIterator<Item = V>,
         V: Into<TableOperation>,
@@ -728,6 +1240,50 @@ impl Table {
             .await
     }
 
+    /// Perform multiple operations on this base table, giving up if they are not acknowledged by
+    /// `deadline`.
+    ///
+    /// This returns the commit sequence numbers of the operations like `Table::perform_all_seq`
+    /// does, or `TableError::TimedOut` if the deadline passes first, in which case the operations
+    /// may or may not still be applied. The handle can be used for further writes either way.
+    pub async fn perform_all_until<I, V>(
+        &mut self,
+        i: I,
+        deadline: Instant,
+    ) -> Result<Vec<Option<u64>>, TableError>
+    where
+        I: IntoIterator<Item = V>,
+        V: Into<TableOperation>,
+    {
+        self.send_until(
+            i.into_iter().map(Into::into).collect::<Vec<_>>(),
+            Some(deadline),
+        )
+        .await
+    }
+
+    /// Insert multiple rows into this base table, but only if every one of them can be inserted.
+    ///
+    /// If any of the rows has the same key as a row that already exists or that comes before it
+    /// in `rows`, or is otherwise refused by the base, none of the rows are inserted, and
+    /// `TableError::Rejected` lists the index of each offending row along with what is wrong with
+    /// it. On a sharded table, all of the rows must belong to the same shard, or the write fails
+    /// with `TableError::SpansShards`.
+    pub async fn insert_all_or_nothing<I, V>(&mut self, rows: I) -> Result<(), TableError>
+    where
+        I: IntoIterator<Item = V>,
+        V: Into<Vec<DataType>>,
+    {
+        let ops = rows
+            .into_iter()
+            .map(|r| TableOperation::Insert(r.into()))
+            .collect::<Vec<_>>();
+        self.mirror(&ops);
+        let mut i = self.prep_records(ops);
+        i.all_or_nothing = true;
+        self.quick_n_dirty(i).await.map(|_| ())
+    }
+
     /// Delete the row with the given key from this base table.
     pub async fn delete<I>(&mut self, key: I) -> Result<(), TableError>
     where
@@ -735,6 +1291,7 @@ impl Table {
     {
         self.quick_n_dirty(vec![TableOperation::Delete { key: key.into() }])
             .await
+            .map(|_| ())
     }
 
     /// Update the row with the given key in this base table.
@@ -760,6 +1317,7 @@ impl Table {
 
         self.quick_n_dirty(vec![TableOperation::Update { key, set }])
             .await
+            .map(|_| ())
     }
 
     /// Perform a insert-or-update on this base table.
@@ -792,5 +1350,126 @@ impl Table {
             update: set,
         }])
         .await
+        .map(|_| ())
+    }
+}
+
+type OrderedWrite = (
+    TableOperation,
+    tokio::sync::oneshot::Sender<Result<(), TableError>>,
+);
+
+/// Send the writes queued for one lane of an [`OrderedTable`], one at a time.
+///
+/// `table` has no write timeout: a write that was given up on might still be applied after the
+/// one queued behind it, so the lane only moves on once it knows how each write turned out.
+async fn run_ordered_lane(
+    mut table: Table,
+    mut writes: tokio::sync::mpsc::UnboundedReceiver<OrderedWrite>,
+) {
+    while let Some((op, done)) = writes.recv().await {
+        let res = table.quick_n_dirty(vec![op]).await.map(|_| ());
+        // the submitter may have stopped waiting for the outcome
+        let _ = done.send(res);
+    }
+}
+
+/// A handle that delivers the writes for any given key to a base table in the order they were
+/// submitted.
+///
+/// A `Table` spreads its writes over several connections to each shard of the table, so two
+/// writes for the same key that are in flight at the same time, whether through one handle or
+/// through clones of it on different threads, may reach the base in either order. An
+/// `OrderedTable` instead hashes each write by its key onto one of a fixed number of queues for
+/// the shard it belongs to, and each queue only sends a write once the one before it has been
+/// acknowledged. Writes for the same key thus reach the base in the order they were submitted,
+/// while writes for different keys can still proceed in parallel.
+///
+/// Clones of an `OrderedTable` share its queues. The queues are unbounded, so submitters that
+/// do not wait for their writes to be acknowledged get no back-pressure. Create one with
+/// `Table::ordered`.
+///
+/// A write that is not acknowledged within the write timeout of the `Table` it was created from
+/// fails with `TableError::TimedOut`, but stays in its queue, and the writes queued behind it are
+/// only sent once it has been acknowledged or has failed. A timed out write may therefore still
+/// be applied, but never after a later write for the same key.
+#[derive(Clone)]
+pub struct OrderedTable {
+    table: Table,
+    queues: Vec<Vec<tokio::sync::mpsc::UnboundedSender<OrderedWrite>>>,
+}
+
+impl fmt::Debug for OrderedTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OrderedTable")
+            .field("table", &self.table)
+            .field("lanes", &self.queues[0].len())
+            .finish()
+    }
+}
+
+impl OrderedTable {
+    /// Submit `op` to the table, and wait for it to be acknowledged.
+    ///
+    /// `op` is queued when this method is called, not when the returned future is first polled.
+    /// It is therefore delivered after every write to the same key that was submitted before this
+    /// call returned, through this handle or any of its clones.
+    pub fn perform<V>(&self, op: V) -> impl Future<Output = Result<(), TableError>> + Send
+    where
+        V: Into<TableOperation>,
+    {
+        let op = op.into();
+        let (shard, hash) = self.table.ordering_of(&op);
+        let lanes = &self.queues[shard];
+        let lane = (hash % lanes.len() as u64) as usize;
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let queued = lanes[lane].send((op, tx)).is_ok();
+        let deadline = self.table.write_timeout.map(|t| Instant::now() + t);
+        let waits = self.table.waits.clone();
+        async move {
+            let gone = || {
+                TableError::TransportError(failure::err_msg("ordered write queue has shut down"))
+            };
+            if !queued {
+                return Err(gone());
+            }
+            let res = match deadline {
+                None => rx.await,
+                Some(deadline) => {
+                    let deadline = tokio::time::Instant::from_std(deadline);
+                    match tokio::time::timeout_at(deadline, rx).await {
+                        Ok(res) => res,
+                        Err(_) => {
+                            // the lane still waits for the outcome before it sends the next write
+                            waits.lock().unwrap().timed_out += 1;
+                            return Err(TableError::TimedOut);
+                        }
+                    }
+                }
+            };
+            res.unwrap_or_else(|_| Err(gone()))
+        }
+    }
+
+    /// Insert a single row of data into the table.
+    pub fn insert<V>(&self, u: V) -> impl Future<Output = Result<(), TableError>> + Send
+    where
+        V: Into<Vec<DataType>>,
+    {
+        self.perform(TableOperation::Insert(u.into()))
+    }
+
+    /// Delete the row with the given key from the table.
+    pub fn delete<I>(&self, key: I) -> impl Future<Output = Result<(), TableError>> + Send
+    where
+        I: Into<Vec<DataType>>,
+    {
+        self.perform(TableOperation::Delete { key: key.into() })
+    }
+
+    /// The table this handle writes to.
+    pub fn table(&self) -> &Table {
+        &self.table
     }
 }
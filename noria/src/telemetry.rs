@@ -0,0 +1,24 @@
+/// Identifies a span of a distributed trace, so that the work done on behalf of a traced write in
+/// other domains can be attributed to it.
+///
+/// Writes issued after `trace_my_next_op` carry a trace context with them, and every domain the
+/// write passes through records a span that is a child of the span of the domain it came from. If
+/// the server exports traces, the spans for a write can then be seen together in a trace viewer
+/// such as Jaeger.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TraceContext {
+    /// The trace that the span belongs to.
+    pub trace_id: u128,
+    /// The span itself.
+    pub span_id: u64,
+}
+
+impl TraceContext {
+    /// Start a new trace.
+    pub fn new_root() -> Self {
+        TraceContext {
+            trace_id: rand::random(),
+            span_id: rand::random(),
+        }
+    }
+}
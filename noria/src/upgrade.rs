@@ -0,0 +1,42 @@
+use std::net::SocketAddr;
+
+/// Progress of a rolling worker upgrade, as reported by the controller.
+///
+/// Events are reported in the order in which they occurred. See
+/// `ControllerHandle::rolling_upgrade` for how the upgrade proceeds.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UpgradeEvent {
+    /// The domains on `worker` are being handed off to its peers.
+    Draining {
+        /// The worker being upgraded.
+        worker: SocketAddr,
+        /// The number of domains that are being handed off.
+        domains: usize,
+    },
+    /// `worker` no longer hosts any domains, and can be restarted with the new binary.
+    AwaitingRestart {
+        /// The worker being upgraded.
+        worker: SocketAddr,
+    },
+    /// The upgraded worker registered with the controller again.
+    Rejoined {
+        /// The address the worker had before it was restarted.
+        old: SocketAddr,
+        /// The address the worker registered with after it was restarted.
+        new: SocketAddr,
+    },
+    /// The domains handed off during the drain have been moved back to the upgraded worker.
+    Complete {
+        /// The upgraded worker.
+        worker: SocketAddr,
+        /// The number of domains that now run on the upgraded worker.
+        domains: usize,
+    },
+    /// The upgrade could not be completed.
+    Failed {
+        /// The worker being upgraded.
+        worker: SocketAddr,
+        /// Why the upgrade failed.
+        reason: String,
+    },
+}
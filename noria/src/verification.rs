@@ -0,0 +1,41 @@
+use crate::data::DataType;
+
+/// The outcome of verifying the rows one shard of a base table keeps on disk.
+///
+/// See `ControllerHandle::verify_base` for what is checked.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BaseVerification {
+    /// The shard that was verified.
+    pub shard: usize,
+    /// Whether the shard keeps its rows on disk at all. Shards that don't have nothing to verify.
+    pub durable: bool,
+    /// The number of intact rows.
+    pub rows: u64,
+    /// The number of rows that could not be decoded, or that were stored under a key that does
+    /// not match their contents.
+    pub corrupt_rows: u64,
+    /// The contents of those corrupt rows that could still be decoded.
+    pub lost_rows: Vec<Vec<DataType>>,
+    /// The number of index entries that could not be decoded, or that did not refer to an intact
+    /// row.
+    pub dangling_index_entries: u64,
+    /// The number of index entries that intact rows were missing.
+    pub missing_index_entries: u64,
+    /// Whether the stored commit sequence number of the base could not be decoded.
+    pub corrupt_commit_seq: bool,
+    /// Whether any problems that were found have been repaired.
+    ///
+    /// Repairing drops corrupt rows and index entries, rebuilds missing index entries, and resets
+    /// an undecodable commit sequence number.
+    pub repaired: bool,
+}
+
+impl BaseVerification {
+    /// Whether no problems were found.
+    pub fn is_clean(&self) -> bool {
+        self.corrupt_rows == 0
+            && self.dangling_index_entries == 0
+            && self.missing_index_entries == 0
+            && !self.corrupt_commit_seq
+    }
+}
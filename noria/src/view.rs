@@ -1,4 +1,5 @@
 use crate::data::*;
+use crate::key::KeyExpression;
 use crate::{Tagged, Tagger};
 use async_bincode::{AsyncBincodeStream, AsyncDestination};
 use futures_util::{
@@ -12,8 +13,11 @@ use std::fmt;
 use std::future::Future;
 use std::io;
 use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 use tokio_tower::multiplex;
 use tower_balance::pool::{self, Pool};
 use tower_buffer::Buffer;
@@ -64,15 +68,34 @@ impl Service<()> for ViewEndpoint {
 
 pub(crate) type ViewRpc = Buffer<Pool<ViewEndpoint, (), Tagged<ReadQuery>>, Tagged<ReadQuery>>;
 
+/// Fetches a fresh `ViewBuilder` for a view, or `None` if the view no longer exists.
+pub(crate) type ViewRefresh = Arc<
+    dyn Fn() -> Pin<Box<dyn Future<Output = Result<Option<ViewBuilder>, failure::Error>> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// How often a `View` asks the controller where the view's readers are.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
 /// A failed [`SyncView`] operation.
 #[derive(Debug, Fail)]
 pub enum ViewError {
     /// The given view is not yet available.
     #[fail(display = "the view is not yet available")]
     NotYetAvailable,
+    /// The given view has been removed from the data-flow.
+    #[fail(display = "the view has been removed")]
+    Deprecated,
     /// A lower-level error occurred while communicating with Soup.
     #[fail(display = "{}", _0)]
     TransportError(#[cause] failure::Error),
+    /// The read timed out before the missing state for some of its keys was backfilled.
+    ///
+    /// Holds the results for each key in the order they were requested, or `None` for the keys
+    /// that were still being backfilled.
+    #[fail(display = "timed out waiting for missing state to be backfilled")]
+    ReplayPending(Vec<Option<Results>>),
 }
 
 impl From<Box<dyn std::error::Error + Send + Sync>> for ViewError {
@@ -92,21 +115,106 @@ pub enum ReadQuery {
         keys: Vec<Vec<DataType>>,
         /// Whether to block if a partial replay is triggered
         block: bool,
+        /// Stop blocking after this long, and reply with the keys that could be read by then.
+        timeout: Option<Duration>,
+        /// Only return rows that match this predicate
+        filter: Option<Predicate>,
+        /// Reply with the number of rows for each key that match `filter` rather than the rows
+        /// themselves.
+        ///
+        /// When set, the reply for each key is a single row holding the count.
+        count: bool,
+        /// The identity of the client, for attributing reads to it.
+        #[serde(default)]
+        identity: Option<String>,
     },
     /// Read the size of a leaf view
     Size {
         /// Where to read from
         target: (NodeIndex, usize),
     },
+    /// Read the lifecycle state of a leaf view
+    State {
+        /// Where to read from
+        target: (NodeIndex, usize),
+    },
 }
 
 #[doc(hidden)]
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub enum ReadReply {
     /// Errors if view isn't ready yet.
     Normal(Result<Vec<Vec<Vec<DataType>>>, ()>),
+    /// Like `Normal(Ok(_))`, except that the rows for some keys were read from a snapshot of the
+    /// view taken before the deployment was restarted. Each key's rows are flagged with whether
+    /// they are stale.
+    Stale(Vec<(Vec<Vec<DataType>>, bool)>),
+    /// Like `Stale`, except that the read timed out before the partial replays for some keys
+    /// completed. Also holds the indices of those keys, whose rows are left empty.
+    Pending(Vec<(Vec<Vec<DataType>>, bool)>, Vec<usize>),
     /// Read size of view
     Size(usize),
+    /// Lifecycle state of a single shard of a view
+    State(ViewState),
+}
+
+/// The lifecycle state of a view.
+///
+/// A view that is not `Ready` returns no rows, which would otherwise be indistinguishable from a
+/// view that is simply empty.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ViewState {
+    /// The view has been added to the data-flow, but none of its shards can serve reads yet.
+    Installing,
+    /// Some of the view's shards have been populated. The contained value is the fraction (between
+    /// 0 and 1) of shards that are ready.
+    Backfilling(f64),
+    /// All of the view's shards can serve reads.
+    Ready,
+    /// The view has been removed from the data-flow and will not be updated again.
+    Deprecated,
+}
+
+/// How urgently missing state in a partially materialized view should be computed.
+///
+/// When a domain has more upqueries outstanding than it can issue concurrently, it issues those
+/// made on behalf of higher-priority views first, and those of equal priority in the order they
+/// were made. A steady stream of `Interactive` upqueries can therefore hold back `Batch` ones
+/// indefinitely.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ReplayPriority {
+    /// Background work, such as analytics, that can wait for other upqueries.
+    Batch,
+    /// The default.
+    Normal,
+    /// Latency-critical reads, such as those serving users.
+    Interactive,
+}
+
+impl Default for ReplayPriority {
+    fn default() -> Self {
+        ReplayPriority::Normal
+    }
+}
+
+/// Which rows a `View` over a table with valid-time columns returns.
+///
+/// A row is valid at a given time if its valid-from column is `NULL` or at most that time, and
+/// its valid-to column is `NULL` or after that time.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ValidTime {
+    /// Only rows that are valid at the time the server serves the read. This is the default.
+    Current,
+    /// Only rows that are valid at the given time.
+    AsOf(DataType),
+    /// All rows, regardless of when they are valid.
+    All,
+}
+
+impl Default for ValidTime {
+    fn default() -> Self {
+        ValidTime::Current
+    }
 }
 
 #[doc(hidden)]
@@ -116,6 +224,23 @@ pub struct ViewBuilder {
     pub columns: Vec<String>,
     pub schema: Option<Vec<ColumnSpecification>>,
     pub shards: Vec<SocketAddr>,
+    /// The position in the view's key of the column its shards are split by.
+    #[serde(default)]
+    pub shard_key: usize,
+    /// The columns that hold the valid-from and valid-to times of the view's rows, if any.
+    pub valid_time: Option<(usize, usize)>,
+    /// The column that marks soft-deleted rows, if any.
+    #[serde(default)]
+    pub tombstone: Option<usize>,
+    /// What the view's key is computed from, if it is keyed on computed values.
+    pub key_expressions: Vec<KeyExpression>,
+    /// The share of the keys each shard owns relative to the others, if they are not spread
+    /// evenly.
+    #[serde(default)]
+    pub shard_weights: Vec<u32>,
+    /// The read replicas of the view, each as its reader and the addresses of its shards.
+    #[serde(default)]
+    pub replicas: Vec<(NodeIndex, Vec<SocketAddr>)>,
 }
 
 impl ViewBuilder {
@@ -125,19 +250,55 @@ impl ViewBuilder {
         &self,
         rpcs: Arc<Mutex<HashMap<(SocketAddr, usize), ViewRpc>>>,
     ) -> Result<View, io::Error> {
-        let node = self.node;
-        let columns = self.columns.clone();
-        let shards = self.shards.clone();
-        let schema = self.schema.clone();
+        let tracer = tracing::dispatcher::get_default(|d| d.clone());
+        Ok(View {
+            schema: self.schema.clone(),
+            columns: Arc::from(self.columns.clone()),
+            readers: self.readers(&rpcs),
+            next_reader: 0,
+            rpcs,
+            refresh: None,
+            refreshed: Instant::now(),
+            shard_key: self.shard_key,
+            valid_time: self.valid_time,
+            valid_at: ValidTime::default(),
+            tombstone: self.tombstone,
+            key_expressions: self.key_expressions.clone(),
+            shard_weights: self.shard_weights.clone(),
+            identity: None,
+            tracer,
+        })
+    }
 
-        let mut addrs = Vec::with_capacity(shards.len());
-        let mut conns = Vec::with_capacity(shards.len());
+    /// Connect to the view's own reader, followed by its read replicas.
+    fn readers(&self, rpcs: &Mutex<HashMap<(SocketAddr, usize), ViewRpc>>) -> Vec<ViewReader> {
+        std::iter::once((self.node, &self.shards))
+            .chain(self.replicas.iter().map(|(node, shards)| (*node, shards)))
+            .map(|(node, shards)| ViewReader::connect(node, shards, rpcs))
+            .collect()
+    }
+}
 
+/// One of the readers that serve the reads of a view: either its own reader, or a read replica.
+#[derive(Clone)]
+struct ViewReader {
+    node: NodeIndex,
+    shards: Vec<ViewRpc>,
+    shard_addrs: Vec<SocketAddr>,
+    /// Set once a read from this reader has failed, so that the handle stops using it.
+    failed: Arc<AtomicBool>,
+}
+
+impl ViewReader {
+    fn connect(
+        node: NodeIndex,
+        shards: &[SocketAddr],
+        rpcs: &Mutex<HashMap<(SocketAddr, usize), ViewRpc>>,
+    ) -> Self {
+        let mut conns = Vec::with_capacity(shards.len());
         for (shardi, &addr) in shards.iter().enumerate() {
             use std::collections::hash_map::Entry;
 
-            addrs.push(addr);
-
             // one entry per shard so that we can send sharded requests in parallel even if
             // they happen to be targeting the same machine.
             let mut rpcs = rpcs.lock().unwrap();
@@ -167,30 +328,55 @@ impl ViewBuilder {
             conns.push(s);
         }
 
-        let tracer = tracing::dispatcher::get_default(|d| d.clone());
-        Ok(View {
+        ViewReader {
             node,
-            schema,
-            columns: Arc::from(columns),
-            shard_addrs: addrs,
             shards: conns,
-            tracer,
-        })
+            shard_addrs: shards.to_vec(),
+            failed: Arc::new(AtomicBool::new(false)),
+        }
     }
 }
 
+/// Wait until every one of a reader's shards can take a request.
+fn poll_shards_ready(shards: &mut [ViewRpc], cx: &mut Context<'_>) -> Poll<Result<(), ViewError>> {
+    for s in shards {
+        ready!(s.poll_ready(cx)).map_err(ViewError::from)?;
+    }
+    Poll::Ready(Ok(()))
+}
+
 /// A `View` is used to query previously defined external views.
 ///
 /// Note that if you create multiple `View` handles from a single `ControllerHandle`, they may
 /// share connections to the Soup workers.
+///
+/// If the view has read replicas, a handle takes turns reading from the view's own reader and
+/// from each replica. A read from a replica that fails, for example because the replica has since
+/// been removed, is served by the view's own reader instead. Handles fetched through a
+/// `ControllerHandle` regularly ask the controller where the view's readers are, so they also
+/// pick up replicas that were added after they were fetched.
 #[derive(Clone)]
 pub struct View {
-    node: NodeIndex,
     columns: Arc<[String]>,
     schema: Option<Vec<ColumnSpecification>>,
 
-    shards: Vec<ViewRpc>,
-    shard_addrs: Vec<SocketAddr>,
+    /// The view's own reader, followed by its read replicas.
+    readers: Vec<ViewReader>,
+    /// The reader that serves the next read.
+    next_reader: usize,
+    rpcs: Arc<Mutex<HashMap<(SocketAddr, usize), ViewRpc>>>,
+    refresh: Option<ViewRefresh>,
+    refreshed: Instant,
+    shard_key: usize,
+
+    valid_time: Option<(usize, usize)>,
+    valid_at: ValidTime,
+
+    tombstone: Option<usize>,
+
+    key_expressions: Vec<KeyExpression>,
+    shard_weights: Vec<u32>,
+    identity: Option<String>,
 
     tracer: tracing::Dispatch,
 }
@@ -198,14 +384,20 @@ pub struct View {
 impl fmt::Debug for View {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("View")
-            .field("node", &self.node)
+            .field("node", &self.readers[0].node)
             .field("columns", &self.columns)
-            .field("shard_addrs", &self.shard_addrs)
+            .field("shard_addrs", &self.readers[0].shard_addrs)
+            .field(
+                "replicas",
+                &self.readers[1..].iter().map(|r| r.node).collect::<Vec<_>>(),
+            )
             .finish()
     }
 }
 
+pub(crate) mod filter;
 pub(crate) mod results;
+use self::filter::Predicate;
 use self::results::{Results, Row};
 
 impl Service<(Vec<Vec<DataType>>, bool)> for View {
@@ -214,114 +406,292 @@ impl Service<(Vec<Vec<DataType>>, bool)> for View {
     type Future = impl Future<Output = Result<Self::Response, Self::Error>> + Send;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        for s in &mut self.shards {
-            ready!(s.poll_ready(cx)).map_err(ViewError::from)?;
+        // stop reading from replicas that failed. the view's own reader is never marked as such.
+        if self
+            .readers
+            .iter()
+            .any(|r| r.failed.load(Ordering::Relaxed))
+        {
+            self.readers.retain(|r| !r.failed.load(Ordering::Relaxed));
         }
-        Poll::Ready(Ok(()))
+        self.next_reader %= self.readers.len();
+        poll_shards_ready(&mut self.readers[self.next_reader].shards, cx)
     }
 
     fn call(&mut self, (keys, block): (Vec<Vec<DataType>>, bool)) -> Self::Future {
+        self.submit(keys, block, None, None, false)
+    }
+}
+
+/// The rows for each key in `reply`, flagged with whether they were read from a snapshot and
+/// whether the key was still being backfilled.
+fn reply_rows(reply: ReadReply) -> Result<Vec<(Vec<Vec<DataType>>, bool, bool)>, ViewError> {
+    match reply {
+        ReadReply::Normal(Ok(rows)) => {
+            Ok(rows.into_iter().map(|rows| (rows, false, false)).collect())
+        }
+        ReadReply::Stale(rows) => Ok(rows
+            .into_iter()
+            .map(|(rows, stale)| (rows, stale, false))
+            .collect()),
+        ReadReply::Pending(rows, pending) => Ok(rows
+            .into_iter()
+            .enumerate()
+            .map(|(i, (rows, stale))| (rows, stale, pending.contains(&i)))
+            .collect()),
+        ReadReply::Normal(Err(())) => Err(ViewError::NotYetAvailable),
+        _ => unreachable!(),
+    }
+}
+
+/// Turn the rows read for each key into `Results`, unless some keys were still being backfilled.
+fn into_results(
+    rows: Vec<(Vec<Vec<DataType>>, bool, bool)>,
+    columns: &Arc<[String]>,
+) -> Result<Vec<Results>, ViewError> {
+    let any_pending = rows.iter().any(|&(_, _, pending)| pending);
+    let results = rows.into_iter().map(|(rows, stale, pending)| {
+        if pending {
+            None
+        } else if stale {
+            Some(Results::stale(rows, Arc::clone(columns)))
+        } else {
+            Some(Results::new(rows, Arc::clone(columns)))
+        }
+    });
+    if any_pending {
+        Err(ViewError::ReplayPending(results.collect()))
+    } else {
+        Ok(results.map(Option::unwrap).collect())
+    }
+}
+
+/// The parts of a read that are the same for every shard it is sent to.
+#[derive(Clone)]
+struct ReadOptions {
+    block: bool,
+    timeout: Option<Duration>,
+    filter: Option<Predicate>,
+    count: bool,
+    identity: Option<String>,
+}
+
+impl ReadOptions {
+    fn query(&self, target: (NodeIndex, usize), keys: Vec<Vec<DataType>>) -> Tagged<ReadQuery> {
+        Tagged::from(ReadQuery::Normal {
+            target,
+            keys,
+            block: self.block,
+            timeout: self.timeout,
+            filter: self.filter.clone(),
+            count: self.count,
+            identity: self.identity.clone(),
+        })
+    }
+}
+
+/// Send a read of `keys` to the shards of the reader `node` that own them.
+///
+/// All of `shards` must have been polled ready.
+fn read_shards(
+    node: NodeIndex,
+    shards: &mut [ViewRpc],
+    shard_key: usize,
+    shard_weights: &[u32],
+    keys: Vec<Vec<DataType>>,
+    options: &ReadOptions,
+    span: Option<&tracing::Span>,
+) -> impl Future<Output = Result<Vec<(Vec<Vec<DataType>>, bool, bool)>, ViewError>> + Send {
+    if shards.len() == 1 {
+        let request = options.query((node, 0), keys);
+
+        let _guard = span.map(tracing::Span::enter);
+        tracing::trace!("submit request");
+
+        return future::Either::Left(
+            shards[0]
+                .call(request)
+                .map_err(ViewError::from)
+                .and_then(|reply| async move { reply_rows(reply.v) }),
+        );
+    }
+
+    if let Some(span) = span {
+        span.in_scope(|| tracing::trace!("shard request"));
+    }
+    // each key only needs to go to the shard that owns the value of its sharding column
+    assert!(keys.iter().all(|k| k.len() > shard_key));
+    let mut shard_queries = vec![Vec::new(); shards.len()];
+    for key in keys {
+        let shard = crate::shard_by_weight(&key[shard_key], shards.len(), shard_weights);
+        shard_queries[shard].push(key);
+    }
+
+    future::Either::Right(
+        shards
+            .iter_mut()
+            .enumerate()
+            .zip(shard_queries.into_iter())
+            .filter_map(|((shardi, shard), shard_queries)| {
+                if shard_queries.is_empty() {
+                    // poll_ready reserves a sender slot which we have to release
+                    // we do that by dropping the old handle and replacing it with a clone
+                    // https://github.com/tokio-rs/tokio/issues/898
+                    *shard = shard.clone();
+                    None
+                } else {
+                    Some(((shardi, shard), shard_queries))
+                }
+            })
+            .map(move |((shardi, shard), shard_queries)| {
+                let request = options.query((node, shardi), shard_queries);
+
+                let _guard = span.map(tracing::Span::enter);
+                // make a span per shard
+                let span = if span.is_some() {
+                    Some(tracing::trace_span!("view-shard", shardi))
+                } else {
+                    None
+                };
+                let _guard = span.as_ref().map(tracing::Span::enter);
+                tracing::trace!("submit request shard");
+
+                shard
+                    .call(request)
+                    .map_err(ViewError::from)
+                    .and_then(|reply| async move { reply_rows(reply.v) })
+            })
+            .collect::<FuturesUnordered<_>>()
+            .try_concat(),
+    )
+}
+
+impl View {
+    fn submit(
+        &mut self,
+        keys: Vec<Vec<DataType>>,
+        block: bool,
+        timeout: Option<Duration>,
+        filter: Option<Predicate>,
+        count: bool,
+    ) -> impl Future<Output = Result<Vec<Results>, ViewError>> + Send {
+        let filter = match (self.valid_time_filter(), filter) {
+            (Some(valid), Some(filter)) => Some(filter.and(valid)),
+            (valid, filter) => valid.or(filter),
+        };
+
+        // views keyed on computed values are looked up by the computed values
+        let keys = if self.key_expressions.is_empty() {
+            keys
+        } else {
+            keys.into_iter()
+                .map(|key| {
+                    key.iter()
+                        .zip(&self.key_expressions)
+                        .map(|(v, e)| e.apply(v))
+                        .collect()
+                })
+                .collect()
+        };
+
+        // poll_ready readied the reader whose turn it is
+        let i = self.next_reader;
+        self.next_reader = (i + 1) % self.readers.len();
+
         let span = if crate::trace_next_op() {
             Some(tracing::trace_span!(
                 "view-request",
                 ?keys,
-                node = self.node.index()
+                node = self.readers[i].node.index()
             ))
         } else {
             None
         };
 
+        let options = ReadOptions {
+            block,
+            timeout,
+            filter,
+            count,
+            identity: self.identity.clone(),
+        };
+        // a replica may have been removed since this handle learned of it, so keep what is needed
+        // to read from the view's own reader instead.
+        let fallback = if i == 0 {
+            None
+        } else {
+            let primary = &self.readers[0];
+            Some((
+                primary.node,
+                primary.shards.clone(),
+                keys.clone(),
+                Arc::clone(&self.readers[i].failed),
+            ))
+        };
+
+        let reader = &mut self.readers[i];
+        let rows = read_shards(
+            reader.node,
+            &mut reader.shards,
+            self.shard_key,
+            &self.shard_weights,
+            keys,
+            &options,
+            span.as_ref(),
+        );
+
         let columns = Arc::clone(&self.columns);
-        if self.shards.len() == 1 {
-            let request = Tagged::from(ReadQuery::Normal {
-                target: (self.node, 0),
-                keys,
-                block,
-            });
-
-            let _guard = span.as_ref().map(tracing::Span::enter);
-            tracing::trace!("submit request");
-
-            return future::Either::Left(
-                self.shards[0]
-                    .call(request)
-                    .map_err(ViewError::from)
-                    .and_then(move |reply| async move {
-                        match reply.v {
-                            ReadReply::Normal(Ok(rows)) => Ok(rows
-                                .into_iter()
-                                .map(|rows| Results::new(rows, Arc::clone(&columns)))
-                                .collect()),
-                            ReadReply::Normal(Err(())) => Err(ViewError::NotYetAvailable),
-                            _ => unreachable!(),
-                        }
-                    }),
-            );
+        let shard_key = self.shard_key;
+        let shard_weights = self.shard_weights.clone();
+        async move {
+            let rows = match (rows.await, fallback) {
+                (Err(_), Some((node, mut shards, keys, failed))) => {
+                    failed.store(true, Ordering::Relaxed);
+                    future::poll_fn(|cx| poll_shards_ready(&mut shards, cx)).await?;
+                    let rows = read_shards(
+                        node,
+                        &mut shards,
+                        shard_key,
+                        &shard_weights,
+                        keys,
+                        &options,
+                        None,
+                    );
+                    rows.await?
+                }
+                (rows, _) => rows?,
+            };
+            into_results(rows, &columns)
         }
+    }
+}
 
-        if let Some(ref span) = span {
-            span.in_scope(|| tracing::trace!("shard request"));
-        }
-        assert!(keys.iter().all(|k| k.len() == 1));
-        let mut shard_queries = vec![Vec::new(); self.shards.len()];
-        for key in keys {
-            let shard = crate::shard_by(&key[0], self.shards.len());
-            shard_queries[shard].push(key);
-        }
+impl View {
+    /// Have this handle use `refresh` to learn where the view's readers are.
+    pub(crate) fn set_refresh(&mut self, refresh: ViewRefresh) {
+        self.refresh = Some(refresh);
+    }
 
-        let node = self.node;
-        future::Either::Right(
-            self.shards
-                .iter_mut()
-                .enumerate()
-                .zip(shard_queries.into_iter())
-                .filter_map(|((shardi, shard), shard_queries)| {
-                    if shard_queries.is_empty() {
-                        // poll_ready reserves a sender slot which we have to release
-                        // we do that by dropping the old handle and replacing it with a clone
-                        // https://github.com/tokio-rs/tokio/issues/898
-                        *shard = shard.clone();
-                        None
-                    } else {
-                        Some(((shardi, shard), shard_queries))
-                    }
-                })
-                .map(move |((shardi, shard), shard_queries)| {
-                    let request = Tagged::from(ReadQuery::Normal {
-                        target: (node, shardi),
-                        keys: shard_queries,
-                        block,
-                    });
-
-                    let _guard = span.as_ref().map(tracing::Span::enter);
-                    // make a span per shard
-                    let span = if span.is_some() {
-                        Some(tracing::trace_span!("view-shard", shardi))
-                    } else {
-                        None
-                    };
-                    let _guard = span.as_ref().map(tracing::Span::enter);
-                    tracing::trace!("submit request shard");
-
-                    shard
-                        .call(request)
-                        .map_err(ViewError::from)
-                        .and_then(|reply| async move {
-                            match reply.v {
-                                ReadReply::Normal(Ok(rows)) => Ok(rows),
-                                ReadReply::Normal(Err(())) => Err(ViewError::NotYetAvailable),
-                                _ => unreachable!(),
-                            }
-                        })
-                })
-                .collect::<FuturesUnordered<_>>()
-                .try_concat()
-                .map_ok(move |rows| {
-                    rows.into_iter()
-                        .map(|rows| Results::new(rows, Arc::clone(&columns)))
-                        .collect()
-                }),
-        )
+    /// The predicate that selects the rows that are valid according to `self.valid_at`.
+    fn valid_time_filter(&self) -> Option<Predicate> {
+        use self::filter::Comparison;
+
+        let (from, to) = self.valid_time?;
+        // the current time is filled in by the reader, so that it is the server's time.
+        let (started, not_ended) = match self.valid_at {
+            ValidTime::Current => (
+                Predicate::compare_now(from, Comparison::LessOrEqual),
+                Predicate::compare_now(to, Comparison::Greater),
+            ),
+            ValidTime::AsOf(ref at) => (
+                Predicate::compare(from, Comparison::LessOrEqual, at.clone()),
+                Predicate::compare(to, Comparison::Greater, at.clone()),
+            ),
+            ValidTime::All => return None,
+        };
+        let started = Predicate::compare(from, Comparison::Equal, DataType::None).or(started);
+        let not_ended = Predicate::compare(to, Comparison::Equal, DataType::None).or(not_ended);
+        Some(started.and(not_ended))
     }
 }
 
@@ -337,14 +707,93 @@ impl View {
         self.schema.as_deref()
     }
 
+    /// Get the columns that hold the valid-from and valid-to times of this view's rows.
+    ///
+    /// This is `None` unless the view's rows come from a table with valid-time columns, and the
+    /// view includes both of those columns.
+    pub fn valid_time_columns(&self) -> Option<(usize, usize)> {
+        self.valid_time
+    }
+
+    /// Choose which rows subsequent reads return, based on the period in which they are valid.
+    ///
+    /// By default, a view with valid-time columns only returns the rows that are valid at the
+    /// time of the read. This has no effect on views without valid-time columns.
+    pub fn set_valid_time(&mut self, valid_at: ValidTime) {
+        self.valid_at = valid_at;
+    }
+
+    /// Get the column that marks the soft-deleted rows of this view.
+    ///
+    /// This is `None` unless the view's rows come from a table with soft deletes, and the view
+    /// includes the column that marks deleted rows. Deleted rows only reach views that ask for
+    /// them, such as those declared `WITH DELETED` in the recipe, in which this column is 1 for
+    /// the rows that have been deleted.
+    pub fn tombstone_column(&self) -> Option<usize> {
+        self.tombstone
+    }
+
+    /// Tag all subsequent reads through this handle with the given identity.
+    ///
+    /// If read attribution is enabled for the view (see `ControllerHandle::set_read_attribution`),
+    /// its reads are counted separately for each identity. Clones of this handle made after this
+    /// call carry the same identity.
+    pub fn set_identity<S: Into<String>>(&mut self, identity: S) {
+        self.identity = Some(identity.into());
+    }
+
+    /// The identity reads through this handle are tagged with, if any.
+    pub fn identity(&self) -> Option<&str> {
+        self.identity.as_deref()
+    }
+
+    /// Ask the controller where the view's readers are if it is time to, or if a read from one
+    /// of them has failed.
+    ///
+    /// The handle keeps reading from the readers it knows of if the controller cannot be reached,
+    /// or if the view has been replaced by one with different columns.
+    async fn refresh(&mut self) {
+        let refresh = match self.refresh {
+            Some(ref refresh) => Arc::clone(refresh),
+            None => return,
+        };
+        let failed = self
+            .readers
+            .iter()
+            .any(|r| r.failed.load(Ordering::Relaxed));
+        if !failed && self.refreshed.elapsed() < REFRESH_INTERVAL {
+            return;
+        }
+        self.refreshed = Instant::now();
+
+        match refresh().await {
+            Ok(Some(vb)) if vb.columns[..] == self.columns[..] => {
+                self.readers = vb.readers(&self.rpcs);
+                self.next_reader = 0;
+                self.shard_key = vb.shard_key;
+                self.shard_weights = vb.shard_weights;
+            }
+            Ok(_) => {}
+            Err(e) => tracing::debug!(error = %e, "failed to refresh view readers"),
+        }
+    }
+
+    /// Refresh the view's readers if needed, and wait until the next read can be sent.
+    async fn ready(&mut self) -> Result<(), ViewError> {
+        self.refresh().await;
+        future::poll_fn(|cx| self.poll_ready(cx)).await
+    }
+
     /// Get the current size of this view.
     ///
     /// Note that you must also continue to poll this `View` for the returned future to resolve.
     pub async fn len(&mut self) -> Result<usize, ViewError> {
-        future::poll_fn(|cx| self.poll_ready(cx)).await?;
+        self.refresh().await;
+        let primary = &mut self.readers[0];
+        future::poll_fn(|cx| poll_shards_ready(&mut primary.shards, cx)).await?;
 
-        let node = self.node;
-        let mut rsps = self
+        let node = primary.node;
+        let mut rsps = primary
             .shards
             .iter_mut()
             .enumerate()
@@ -367,6 +816,57 @@ impl View {
         Ok(nrows)
     }
 
+    /// Get the current lifecycle state of this view.
+    pub async fn state(&mut self) -> Result<ViewState, ViewError> {
+        self.refresh().await;
+        let primary = &mut self.readers[0];
+        future::poll_fn(|cx| poll_shards_ready(&mut primary.shards, cx)).await?;
+
+        let node = primary.node;
+        let shards = primary.shards.len();
+        let mut rsps = primary
+            .shards
+            .iter_mut()
+            .enumerate()
+            .map(|(shardi, shard)| {
+                shard.call(Tagged::from(ReadQuery::State {
+                    target: (node, shardi),
+                }))
+            })
+            .collect::<FuturesUnordered<_>>();
+
+        let mut ready = 0;
+        while let Some(reply) = rsps.next().await.transpose()? {
+            match reply.v {
+                ReadReply::State(ViewState::Deprecated) => return Ok(ViewState::Deprecated),
+                ReadReply::State(ViewState::Ready) => ready += 1,
+                ReadReply::State(_) => {}
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(if ready == shards {
+            ViewState::Ready
+        } else if ready == 0 {
+            ViewState::Installing
+        } else {
+            ViewState::Backfilling(ready as f64 / shards as f64)
+        })
+    }
+
+    /// Wait until all shards of this view can serve reads.
+    ///
+    /// Fails with `ViewError::Deprecated` if the view has been removed.
+    pub async fn wait_until_ready(&mut self) -> Result<(), ViewError> {
+        loop {
+            match self.state().await? {
+                ViewState::Ready => return Ok(()),
+                ViewState::Deprecated => return Err(ViewError::Deprecated),
+                _ => tokio::time::delay_for(std::time::Duration::from_millis(10)).await,
+            }
+        }
+    }
+
     /// Retrieve the query results for the given parameter values.
     ///
     /// The method will block if the results are not yet available only when `block` is `true`.
@@ -377,7 +877,7 @@ impl View {
         keys: Vec<Vec<DataType>>,
         block: bool,
     ) -> Result<Vec<Results>, ViewError> {
-        future::poll_fn(|cx| self.poll_ready(cx)).await?;
+        self.ready().await?;
         self.call((keys, block)).await
     }
 
@@ -390,6 +890,35 @@ impl View {
         Ok(rs.into_iter().next().unwrap())
     }
 
+    /// Retrieve the query results for the given parameter values, waiting at most `timeout` for
+    /// missing state to be backfilled.
+    ///
+    /// If some of the keys are still being backfilled once `timeout` has passed, this fails with
+    /// `ViewError::ReplayPending`, which holds the results for the keys that could be read.
+    pub async fn multi_lookup_timeout(
+        &mut self,
+        keys: Vec<Vec<DataType>>,
+        timeout: Duration,
+    ) -> Result<Vec<Results>, ViewError> {
+        self.ready().await?;
+        self.submit(keys, true, Some(timeout), None, false).await
+    }
+
+    /// Retrieve the query results for the given parameter value, waiting at most `timeout` for
+    /// missing state to be backfilled.
+    ///
+    /// See `View::multi_lookup_timeout`.
+    pub async fn lookup_timeout(
+        &mut self,
+        key: &[DataType],
+        timeout: Duration,
+    ) -> Result<Results, ViewError> {
+        let rs = self
+            .multi_lookup_timeout(vec![Vec::from(key)], timeout)
+            .await?;
+        Ok(rs.into_iter().next().unwrap())
+    }
+
     /// Retrieve the first query result for the given parameter value.
     ///
     /// The method will block if the results are not yet available only when `block` is `true`.
@@ -402,4 +931,57 @@ impl View {
         let rs = self.multi_lookup(vec![Vec::from(key)], block).await?;
         Ok(rs.into_iter().next().unwrap().into_iter().next())
     }
+
+    /// Retrieve the query results for the given parameter value that match `predicate`.
+    ///
+    /// The predicate is evaluated at the reader, so rows that do not match are never sent back to
+    /// the client. This is useful for keys with large result sets of which only a few rows are
+    /// needed.
+    ///
+    /// The method will block if the results are not yet available only when `block` is `true`.
+    pub async fn lookup_filtered(
+        &mut self,
+        key: &[DataType],
+        predicate: Predicate,
+        block: bool,
+    ) -> Result<Results, ViewError> {
+        self.ready().await?;
+        let rs = self
+            .submit(vec![Vec::from(key)], block, None, Some(predicate), false)
+            .await?;
+        Ok(rs.into_iter().next().unwrap())
+    }
+
+    /// Retrieve the number of query results for the given parameter value.
+    ///
+    /// The rows are counted at the reader, so only the count is sent back to the client.
+    ///
+    /// The method will block if the results are not yet available only when `block` is `true`.
+    pub async fn count(&mut self, key: &[DataType], block: bool) -> Result<usize, ViewError> {
+        self.ready().await?;
+        let rs = self
+            .submit(vec![Vec::from(key)], block, None, None, true)
+            .await?;
+        let count = rs
+            .into_iter()
+            .next()
+            .unwrap()
+            .into_iter()
+            .next()
+            .map(|row| {
+                let count: u64 = (&row[0]).into();
+                count as usize
+            })
+            .unwrap_or(0);
+        Ok(count)
+    }
+
+    /// Check whether there are any query results for the given parameter value.
+    ///
+    /// Like `count`, this is evaluated at the reader, so no rows are sent back to the client.
+    ///
+    /// The method will block if the results are not yet available only when `block` is `true`.
+    pub async fn exists(&mut self, key: &[DataType], block: bool) -> Result<bool, ViewError> {
+        Ok(self.count(key, block).await? > 0)
+    }
 }
@@ -0,0 +1,157 @@
+use crate::data::DataType;
+use std::cmp::Ordering;
+
+/// A comparison operator used in a [`Predicate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Comparison {
+    /// `column = value`
+    Equal,
+    /// `column != value`
+    NotEqual,
+    /// `column < value`
+    Less,
+    /// `column <= value`
+    LessOrEqual,
+    /// `column > value`
+    Greater,
+    /// `column >= value`
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn holds(self, ord: Ordering) -> bool {
+        match self {
+            Comparison::Equal => ord == Ordering::Equal,
+            Comparison::NotEqual => ord != Ordering::Equal,
+            Comparison::Less => ord == Ordering::Less,
+            Comparison::LessOrEqual => ord != Ordering::Greater,
+            Comparison::Greater => ord == Ordering::Greater,
+            Comparison::GreaterOrEqual => ord != Ordering::Less,
+        }
+    }
+}
+
+/// A residual predicate that is evaluated at the reader before rows are returned.
+///
+/// Only rows for which the predicate holds are sent back to the client, which avoids shipping
+/// rows the client would discard anyway when a key has many results.
+///
+/// ```
+/// use noria::results::{Comparison, Predicate};
+///
+/// // column 1 > 10 AND column 2 != "foo"
+/// let p = Predicate::compare(1, Comparison::Greater, 10.into())
+///     .and(Predicate::compare(2, Comparison::NotEqual, "foo".into()));
+/// assert!(p.matches(&[0.into(), 11.into(), "bar".into()]));
+/// assert!(!p.matches(&[0.into(), 11.into(), "foo".into()]));
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Predicate {
+    /// Compare the value in the given column against a constant.
+    Compare {
+        /// The index of the column to compare.
+        column: usize,
+        /// How to compare the column to `value`.
+        op: Comparison,
+        /// The value to compare against.
+        value: DataType,
+    },
+    /// Compare the value in the given column against the time at which the read is served.
+    ///
+    /// The time is taken from the clock of the server that serves the read, not from that of the
+    /// client, so that clients agree on what the current time is.
+    CompareNow {
+        /// The index of the column to compare.
+        column: usize,
+        /// How to compare the column to the current time.
+        op: Comparison,
+    },
+    /// Holds if all of the contained predicates hold.
+    And(Vec<Predicate>),
+    /// Holds if any of the contained predicates hold.
+    Or(Vec<Predicate>),
+    /// Holds if the contained predicate does not hold.
+    Not(Box<Predicate>),
+}
+
+impl Predicate {
+    /// Construct a predicate that compares `column` against `value`.
+    pub fn compare(column: usize, op: Comparison, value: DataType) -> Self {
+        Predicate::Compare { column, op, value }
+    }
+
+    /// Construct a predicate that compares `column` against the time at which the read is served.
+    pub fn compare_now(column: usize, op: Comparison) -> Self {
+        Predicate::CompareNow { column, op }
+    }
+
+    /// Replace every comparison against the current time with a comparison against `now`.
+    ///
+    /// Readers do this once for each read, so that all rows of a read are compared against the
+    /// same time.
+    pub fn at(self, now: &DataType) -> Self {
+        match self {
+            Predicate::CompareNow { column, op } => Predicate::Compare {
+                column,
+                op,
+                value: now.clone(),
+            },
+            Predicate::And(ps) => Predicate::And(ps.into_iter().map(|p| p.at(now)).collect()),
+            Predicate::Or(ps) => Predicate::Or(ps.into_iter().map(|p| p.at(now)).collect()),
+            Predicate::Not(p) => Predicate::Not(Box::new(p.at(now))),
+            p => p,
+        }
+    }
+
+    /// Construct a predicate that holds if both `self` and `other` hold.
+    pub fn and(self, other: Predicate) -> Self {
+        match self {
+            Predicate::And(mut ps) => {
+                ps.push(other);
+                Predicate::And(ps)
+            }
+            p => Predicate::And(vec![p, other]),
+        }
+    }
+
+    /// Construct a predicate that holds if either `self` or `other` holds.
+    pub fn or(self, other: Predicate) -> Self {
+        match self {
+            Predicate::Or(mut ps) => {
+                ps.push(other);
+                Predicate::Or(ps)
+            }
+            p => Predicate::Or(vec![p, other]),
+        }
+    }
+
+    /// Construct a predicate that holds if `self` does not.
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Self {
+        Predicate::Not(Box::new(self))
+    }
+
+    /// Evaluate the predicate against the given row.
+    ///
+    /// Comparisons against columns that do not exist in `row` never hold. Comparisons against the
+    /// current time use the local clock; see [`Predicate::at`].
+    pub fn matches(&self, row: &[DataType]) -> bool {
+        match *self {
+            Predicate::CompareNow { column, op } => row
+                .get(column)
+                .map(|v| op.holds(v.cmp(&DataType::from(chrono::Local::now().naive_local()))))
+                .unwrap_or(false),
+            Predicate::Compare {
+                column,
+                op,
+                ref value,
+            } => row
+                .get(column)
+                .map(|v| op.holds(v.cmp(value)))
+                .unwrap_or(false),
+            Predicate::And(ref ps) => ps.iter().all(|p| p.matches(row)),
+            Predicate::Or(ref ps) => ps.iter().any(|p| p.matches(row)),
+            Predicate::Not(ref p) => !p.matches(row),
+        }
+    }
+}
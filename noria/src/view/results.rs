@@ -8,6 +8,7 @@ use std::sync::Arc;
 pub struct Results {
     results: Vec<Vec<DataType>>,
     columns: Arc<[String]>,
+    stale: bool,
 }
 
 impl Results {
@@ -15,7 +16,29 @@ impl Results {
     // https://github.com/rust-lang/rust/issues/69785
     #[doc(hidden)]
     pub fn new(results: Vec<Vec<DataType>>, columns: Arc<[String]>) -> Self {
-        Self { results, columns }
+        Self {
+            results,
+            columns,
+            stale: false,
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn stale(results: Vec<Vec<DataType>>, columns: Arc<[String]>) -> Self {
+        Self {
+            results,
+            columns,
+            stale: true,
+        }
+    }
+
+    /// Whether these results were read from a snapshot of the view that was taken before the
+    /// deployment was last restarted, rather than from the view itself.
+    ///
+    /// Stale results are returned while the view is still being rebuilt, and may not reflect
+    /// recent writes. Once the view has caught up, reads return fresh results again.
+    pub fn is_stale(&self) -> bool {
+        self.stale
     }
 
     /// Iterate over references to the returned rows.
@@ -21,6 +21,10 @@ impl SizeOf for DataType {
 
         let inner = match *self {
             DataType::Text(ref t) => size_of_val(t) as u64 + t.to_bytes().len() as u64,
+            DataType::Bytes(ref b) => size_of_val(&**b) as u64 + b.len() as u64,
+            DataType::List(ref l) => {
+                size_of_val(&**l) as u64 + l.iter().map(SizeOf::deep_size_of).sum::<u64>()
+            }
             _ => 0u64,
         };
 
@@ -1,9 +1,17 @@
 use crate::prelude::*;
+use crate::sketch::HeavyHitters;
+use crate::state::versioned::{self, Versioned};
 use common::SizeOf;
 use fnv::FnvBuildHasher;
+use noria::debug::stats::{ReadAmplification, ReadAttribution};
 use rand::prelude::*;
 use std::borrow::Cow;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 
 /// Allocate a new end-user facing result table.
 pub(crate) fn new(cols: usize, key: &[usize]) -> (SingleReadHandle, WriteHandle) {
@@ -63,18 +71,34 @@ fn new_inner(
         _ => make!(Many),
     };
 
+    let hot_keys = Arc::new(Mutex::new(HeavyHitters::default()));
+    let counters = Arc::new(ReadCounters::default());
+    let snapshot = Arc::new(RwLock::new(None));
+    let mask = Arc::new(RwLock::new(None));
     let w = WriteHandle {
         partial: trigger.is_some(),
+        hot_keys: hot_keys.clone(),
+        counters: counters.clone(),
         handle: w,
         key: Vec::from(key),
         cols,
         contiguous,
         mem_size: 0,
+        count_only: false,
+        pending_counts: HashMap::default(),
+        snapshot: snapshot.clone(),
+        restored: false,
+        mask: mask.clone(),
     };
     let r = SingleReadHandle {
         handle: r,
         trigger,
         key: Vec::from(key),
+        hot_keys,
+        counters,
+        count_only: false,
+        snapshot,
+        mask,
     };
 
     (r, w)
@@ -83,6 +107,105 @@ fn new_inner(
 mod multir;
 mod multiw;
 
+/// Relates the lookups in a reader to the replays they caused.
+#[derive(Default)]
+struct ReadCounters {
+    reads: AtomicU64,
+    upqueries: AtomicU64,
+    replayed_records: AtomicU64,
+    replayed_bytes: AtomicU64,
+    coalesced: AtomicU64,
+    /// Whether lookups are attributed to the identities of the clients that make them.
+    attributing: AtomicBool,
+    attribution: Mutex<Attribution>,
+}
+
+/// The lookups made by each client identity while a reader attributes them.
+#[derive(Default)]
+struct Attribution {
+    anonymous: IdentityReads,
+    named: HashMap<String, IdentityReads>,
+}
+
+#[derive(Default)]
+struct IdentityReads {
+    reads: u64,
+    hot_keys: HeavyHitters,
+}
+
+impl Attribution {
+    fn record(&mut self, key: &[DataType], identity: Option<&str>) {
+        let reads = match identity {
+            None => &mut self.anonymous,
+            Some(identity) => {
+                if !self.named.contains_key(identity) {
+                    self.named.insert(identity.to_owned(), Default::default());
+                }
+                self.named.get_mut(identity).unwrap()
+            }
+        };
+        reads.reads += 1;
+        reads.hot_keys.observe(key);
+    }
+
+    fn report(&self) -> Vec<ReadAttribution> {
+        let anonymous = Some((None, &self.anonymous)).filter(|(_, r)| r.reads != 0);
+        self.named
+            .iter()
+            .map(|(identity, r)| (Some(identity.clone()), r))
+            .chain(anonymous)
+            .map(|(identity, r)| ReadAttribution {
+                identity,
+                reads: r.reads,
+                hot_keys: r.hot_keys.top(),
+            })
+            .collect()
+    }
+}
+
+/// Columns of a reader whose values are hidden from clients.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadMask {
+    /// The columns whose values are replaced with `NULL` in what clients read.
+    pub columns: Vec<usize>,
+}
+
+/// The rows of a reader as they were when it was last saved to disk, by key.
+type Snapshot = HashMap<Vec<DataType>, Vec<Vec<DataType>>>;
+
+/// What `WriteHandle::save_snapshot` writes to disk.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SnapshotFile {
+    cols: usize,
+    key: Vec<usize>,
+    rows: Vec<(Vec<DataType>, Vec<Vec<DataType>>)>,
+}
+
+impl SnapshotFile {
+    /// Write the snapshot to `path`.
+    pub(crate) fn write(&self, path: &Path) -> io::Result<()> {
+        // write to a temporary file first so that a crash never leaves a truncated snapshot
+        let tmp = path.with_extension("snapshot.tmp");
+        let mut f = io::BufWriter::new(fs::File::create(&tmp)?);
+        versioned::encode_into(&mut f, self)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        f.into_inner()?.sync_all()?;
+        fs::rename(&tmp, path)
+    }
+}
+
+impl Versioned for SnapshotFile {
+    const VERSION: u32 = 1;
+
+    fn upgrade(version: u32, data: &[u8]) -> bincode::Result<Self> {
+        match version {
+            // the layout is unchanged, the file just didn't say which version it was
+            0 => bincode::deserialize(data),
+            _ => unreachable!("unknown snapshot version {}", version),
+        }
+    }
+}
+
 fn key_to_single(k: Key) -> Cow<DataType> {
     assert_eq!(k.len(), 1);
     match k {
@@ -107,10 +230,29 @@ fn key_to_double(k: Key) -> Cow<(DataType, DataType)> {
 pub(crate) struct WriteHandle {
     handle: multiw::Handle,
     partial: bool,
+    hot_keys: Arc<Mutex<HeavyHitters>>,
+    counters: Arc<ReadCounters>,
     cols: usize,
     key: Vec<usize>,
     contiguous: bool,
     mem_size: usize,
+    count_only: bool,
+    /// Counts written to a count-only handle since the last swap, which readers (and thus
+    /// `meta_get_and`) cannot see yet.
+    pending_counts: HashMap<Vec<DataType>, u64, FnvBuildHasher>,
+    /// Rows restored from disk that readers are served until this handle has caught up.
+    snapshot: Arc<RwLock<Option<Snapshot>>>,
+    /// Whether `snapshot` may still hold rows, so that we don't have to lock it to find out.
+    restored: bool,
+    mask: Arc<RwLock<Option<ReadMask>>>,
+}
+
+/// The number of rows stored for a key of a count-only handle.
+fn stored_count(rs: &evmap::Values<Vec<DataType>, FnvBuildHasher>) -> u64 {
+    rs.iter()
+        .next()
+        .map(|r| (&r[r.len() - 1]).into())
+        .unwrap_or(0)
 }
 
 type Key<'a> = Cow<'a, [DataType]>;
@@ -130,6 +272,20 @@ impl<'a> MutWriteHandleEntry<'a> {
             .handle
             .meta_get_and(Cow::Borrowed(&*self.key), |rs| rs.is_empty())
         {
+            if self.handle.restored {
+                // the rows for the key are about to be replayed, so the ones restored from disk
+                // are no longer needed.
+                let mut snapshot = self.handle.snapshot.write().unwrap();
+                let emptied = snapshot.as_mut().map_or(true, |s| {
+                    s.remove(&*self.key);
+                    s.is_empty()
+                });
+                if emptied {
+                    *snapshot = None;
+                    drop(snapshot);
+                    self.handle.restored = false;
+                }
+            }
             self.handle.handle.clear(self.key)
         } else {
             unreachable!("attempted to fill already-filled key");
@@ -146,6 +302,7 @@ impl<'a> MutWriteHandleEntry<'a> {
             .map(|r| r.0.unwrap_or(0))
             .unwrap_or(0);
         self.handle.mem_size = self.handle.mem_size.checked_sub(size as usize).unwrap();
+        self.handle.pending_counts.remove(&*self.key);
         self.handle.handle.empty(self.key)
     }
 }
@@ -230,6 +387,173 @@ impl WriteHandle {
 
     pub(crate) fn swap(&mut self) {
         self.handle.refresh();
+        self.pending_counts.clear();
+
+        // a fully materialized reader is only swapped once its state has been built in full, at
+        // which point its own rows supersede the ones restored from disk.
+        if self.restored && !self.partial {
+            *self.snapshot.write().unwrap() = None;
+            self.restored = false;
+        }
+    }
+
+    /// All rows currently visible to readers, or `None` if this handle only counts the rows of
+    /// each key.
+    pub(crate) fn rows(&self) -> Option<Vec<Vec<DataType>>> {
+        if self.count_only {
+            return None;
+        }
+        Some(
+            self.handle
+                .contents()
+                .into_iter()
+                .flat_map(|(_, rs)| rs)
+                .collect(),
+        )
+    }
+
+    /// Write the rows currently visible to readers to `path`, so that a reader created for the
+    /// same view after a restart can serve them with `restore_snapshot` until it has caught up.
+    ///
+    /// Returns `false` without writing anything if a fully materialized reader is still being
+    /// rebuilt, since its own state is incomplete until then.
+    pub(crate) fn save_snapshot(&self, path: &Path) -> io::Result<bool> {
+        match self.snapshot() {
+            Some(file) => file.write(path).map(|()| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Copy the rows that `save_snapshot` would save, so that they can be written to disk
+    /// elsewhere. Returns `None` in the same cases as `save_snapshot` returns `false`.
+    pub(crate) fn snapshot(&self) -> Option<SnapshotFile> {
+        if self.restored && !self.partial {
+            return None;
+        }
+
+        let mut rows = self.handle.contents();
+        if self.restored {
+            // keys that haven't been replayed yet should survive another restart too
+            if let Some(ref snapshot) = *self.snapshot.read().unwrap() {
+                rows.extend(snapshot.iter().map(|(k, rs)| (k.clone(), rs.clone())));
+            }
+        }
+        Some(SnapshotFile {
+            cols: self.cols,
+            key: self.key.clone(),
+            rows,
+        })
+    }
+
+    /// Serve the rows saved to `path` by `save_snapshot` to readers, marked as stale, until this
+    /// handle has caught up.
+    ///
+    /// A fully materialized handle has caught up once it is first swapped. A partially
+    /// materialized one has caught up for a key once that key is filled. Returns `false` if there
+    /// is no snapshot at `path`, or if it was saved by a reader with different columns or key.
+    ///
+    /// This must be called before any rows are added.
+    pub(crate) fn restore_snapshot(&mut self, path: &Path) -> io::Result<bool> {
+        assert_eq!(self.mem_size, 0);
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        let file: SnapshotFile =
+            versioned::decode(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if file.cols != self.cols || file.key != self.key {
+            return Ok(false);
+        }
+
+        *self.snapshot.write().unwrap() = Some(file.rows.into_iter().collect());
+        self.restored = true;
+        Ok(true)
+    }
+
+    /// Key this handle on `n` values that are computed from each row and appended to it, rather
+    /// than on columns of the rows themselves.
+    ///
+    /// This must be called before any rows are added.
+    pub(crate) fn set_computed_key(&mut self, n: usize) {
+        self.key = (self.cols..self.cols + n).collect();
+        self.cols += n;
+        self.contiguous = true;
+    }
+
+    /// Make this handle keep only the number of rows for each key, rather than the rows
+    /// themselves.
+    ///
+    /// Each key is then stored as a single row holding the key's columns followed by the count, so
+    /// records must be passed through `count_updates` before they are added.
+    pub(crate) fn set_count_only(&mut self) {
+        assert_eq!(self.mem_size, 0);
+        self.count_only = true;
+        self.cols = self.key.len() + 1;
+        self.key = (0..self.key.len()).collect();
+        self.contiguous = true;
+    }
+
+    /// Turn updates to the rows of a count-only view into updates to the per-key counts that this
+    /// handle stores.
+    ///
+    /// `key` gives the key columns of the incoming rows. In partial state, regular updates to keys
+    /// that are holes are dropped, and replays only fill keys that are holes.
+    pub(crate) fn count_updates<I>(&mut self, key: &[usize], rs: I, replay: bool) -> Vec<Record>
+    where
+        I: IntoIterator<Item = Record>,
+    {
+        assert!(self.count_only);
+
+        // net change in the number of rows for each key, in the order the keys first appear
+        let mut deltas: Vec<(Vec<DataType>, i64)> = Vec::new();
+        let mut positions = HashMap::new();
+        for r in rs {
+            let (row, positive) = r.extract();
+            let k: Vec<_> = key.iter().map(|&c| row[c].clone()).collect();
+            let d = if positive { 1 } else { -1 };
+            match positions.get(&k) {
+                Some(&i) => deltas[i].1 += d,
+                None => {
+                    positions.insert(k.clone(), deltas.len());
+                    deltas.push((k, d));
+                }
+            }
+        }
+
+        let mut out = Vec::with_capacity(deltas.len() * 2);
+        for (k, delta) in deltas {
+            if delta == 0 {
+                continue;
+            }
+
+            let published = self
+                .handle
+                .meta_get_and(Cow::Borrowed(&k[..]), stored_count);
+            let current = match (self.pending_counts.get(&k), published) {
+                (Some(&n), _) => n,
+                // the state has not been swapped in yet, so we are building it from scratch
+                (None, None) => 0,
+                (None, Some((Some(n), _))) if !(replay && self.partial) => n,
+                (None, Some((None, _))) if replay || !self.partial => 0,
+                // a regular update to a hole, or a duplicate replay
+                _ => continue,
+            };
+
+            let new = (current as i64 + delta).max(0) as u64;
+            if current > 0 {
+                let mut row = k.clone();
+                row.push(current.into());
+                out.push(Record::Negative(row));
+            }
+            if new > 0 {
+                let mut row = k.clone();
+                row.push(new.into());
+                out.push(Record::Positive(row));
+            }
+            self.pending_counts.insert(k, new);
+        }
+        out
     }
 
     /// Add a new set of records to the backlog.
@@ -250,10 +574,91 @@ impl WriteHandle {
         }
     }
 
+    /// The keys most frequently looked up through the corresponding read handles.
+    pub(crate) fn hot_keys(&self) -> Vec<(Vec<DataType>, u64)> {
+        self.hot_keys.lock().unwrap().top()
+    }
+
+    /// Account for records that a replay is about to fill into this handle.
+    pub(crate) fn record_replay(&self, rs: &[Record]) {
+        let bytes: u64 = rs.iter().map(|r| r.deep_size_of()).sum();
+        self.counters
+            .replayed_records
+            .fetch_add(rs.len() as u64, Ordering::Relaxed);
+        self.counters
+            .replayed_bytes
+            .fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Start or stop attributing the lookups through the corresponding read handles to the
+    /// identities of the clients that make them.
+    ///
+    /// Whatever was attributed before is discarded whenever attribution is switched on or off.
+    pub(crate) fn set_read_attribution(&self, enabled: bool) {
+        if self.counters.attributing.swap(enabled, Ordering::Relaxed) != enabled {
+            *self.counters.attribution.lock().unwrap() = Default::default();
+        }
+    }
+
+    /// Hide the values of some columns from the clients that read through the corresponding read
+    /// handles, or stop hiding them if `mask` is `None`.
+    pub(crate) fn set_read_mask(&self, mask: Option<ReadMask>) {
+        *self.mask.write().unwrap() = mask;
+    }
+
+    /// The lookups through the corresponding read handles by each client identity, since read
+    /// attribution was switched on.
+    pub(crate) fn read_attribution(&self) -> Vec<ReadAttribution> {
+        self.counters.attribution.lock().unwrap().report()
+    }
+
+    /// How much replay work the lookups through the corresponding read handles have caused.
+    pub(crate) fn read_amplification(&self) -> ReadAmplification {
+        ReadAmplification {
+            reads: self.counters.reads.load(Ordering::Relaxed),
+            upqueries: self.counters.upqueries.load(Ordering::Relaxed),
+            replayed_records: self.counters.replayed_records.load(Ordering::Relaxed),
+            replayed_bytes: self.counters.replayed_bytes.load(Ordering::Relaxed),
+            coalesced: self.counters.coalesced.load(Ordering::Relaxed),
+        }
+    }
+
     pub(crate) fn is_partial(&self) -> bool {
         self.partial
     }
 
+    /// Choose up to `count` of the keys that are currently filled, uniformly at random.
+    pub(crate) fn sample_keys(&self, count: usize) -> Vec<Vec<DataType>> {
+        self.handle.sample_keys(count, &mut rand::thread_rng())
+    }
+
+    /// Compute a digest of the rows currently stored for `key`, or `None` if `key` is a hole.
+    ///
+    /// The digest does not depend on the order of the rows, so two copies of the same state
+    /// produce the same fingerprint regardless of the order in which they were built.
+    pub(crate) fn fingerprint(&self, key: &[DataType]) -> Option<u64> {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        self.handle
+            .meta_get_and(Cow::Borrowed(key), |rs| {
+                rs.iter().fold(0u64, |acc, r| {
+                    let mut h = DefaultHasher::new();
+                    r.hash(&mut h);
+                    acc.wrapping_add(h.finish())
+                })
+            })
+            .and_then(|(fp, _)| fp)
+    }
+
+    /// Evict every key from state and return the number of bytes that will be freed once the
+    /// underlying `evmap` applies the operation.
+    pub(crate) fn evict_all(&mut self) -> u64 {
+        self.handle.purge();
+        self.pending_counts.clear();
+        std::mem::replace(&mut self.mem_size, 0) as u64
+    }
+
     /// Evict `count` randomly selected keys from state and return them along with the number of
     /// bytes that will be freed once the underlying `evmap` applies the operation.
     pub(crate) fn evict_random_key(&mut self, rng: &mut ThreadRng) -> u64 {
@@ -297,9 +702,60 @@ pub struct SingleReadHandle {
     handle: multir::Handle,
     trigger: Option<Arc<dyn Fn(&mut dyn Iterator<Item = &[DataType]>) -> bool + Send + Sync>>,
     key: Vec<usize>,
+    hot_keys: Arc<Mutex<HeavyHitters>>,
+    counters: Arc<ReadCounters>,
+    count_only: bool,
+    snapshot: Arc<RwLock<Option<Snapshot>>>,
+    mask: Arc<RwLock<Option<ReadMask>>>,
 }
 
 impl SingleReadHandle {
+    /// Read the per-key counts of a count-only view from this handle.
+    ///
+    /// See `WriteHandle::set_count_only`.
+    pub(crate) fn set_count_only(&mut self) {
+        self.count_only = true;
+        self.key = (0..self.key.len()).collect();
+    }
+
+    /// The columns whose values must be hidden from clients.
+    ///
+    /// Count-only views only hold the number of rows for each key, so nothing is hidden in them.
+    pub fn hidden_columns(&self) -> Vec<usize> {
+        if self.count_only {
+            return Vec::new();
+        }
+        match *self.mask.read().unwrap() {
+            Some(ref mask) => mask.columns.clone(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Record a client lookup of `key`, for the purposes of hot-key detection and read accounting.
+    ///
+    /// The hot-key tracking is best-effort: if another reader thread is recording a lookup at the
+    /// same time, this lookup is not counted rather than waiting for the lock. Lookups that are
+    /// attributed to the `identity` of the client are always counted.
+    pub fn record_lookup(&self, key: &[DataType], identity: Option<&str>) {
+        self.counters.reads.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut hh) = self.hot_keys.try_lock() {
+            hh.observe(key);
+        }
+        if self.counters.attributing.load(Ordering::Relaxed) {
+            self.counters
+                .attribution
+                .lock()
+                .unwrap()
+                .record(key, identity);
+        }
+    }
+
+    /// Record a blocking read that is answered along with an identical read that was already
+    /// waiting, rather than waiting for its keys to be filled itself.
+    pub fn record_coalesced(&self) {
+        self.counters.coalesced.fetch_add(1, Ordering::Relaxed);
+    }
+
     /// Trigger a replay of a missing key from a partially materialized view.
     pub fn trigger<'a, I>(&self, keys: I) -> bool
     where
@@ -310,7 +766,10 @@ impl SingleReadHandle {
             "tried to trigger a replay for a fully materialized view"
         );
 
-        let mut it = keys;
+        let upqueries = &self.counters.upqueries;
+        let mut it = keys.inspect(|_| {
+            upqueries.fetch_add(1, Ordering::Relaxed);
+        });
 
         // trigger a replay to populate
         (*self.trigger.as_ref().unwrap())(&mut it)
@@ -339,10 +798,69 @@ impl SingleReadHandle {
             })
     }
 
+    /// Find the number of rows for the given key.
+    ///
+    /// Like `try_find_and`, holes in partially materialized state are returned as
+    /// `Ok((None, _))`.
+    pub fn try_count(&self, key: &[DataType]) -> Result<(Option<usize>, i64), ()> {
+        if self.count_only {
+            self.try_find_and(key, |rs| stored_count(rs) as usize)
+        } else {
+            self.try_find_and(key, |rs| rs.len())
+        }
+    }
+
+    /// Find the rows for `key` in the snapshot this reader was restored from, if the reader's own
+    /// state can't answer for `key` yet.
+    ///
+    /// A fully materialized reader answers every key from its snapshot until its state has been
+    /// built. A partially materialized reader only does so for keys that the snapshot has rows
+    /// for and that have not been filled since.
+    pub fn try_find_stale_and<F, T>(&self, key: &[DataType], then: F) -> Option<T>
+    where
+        F: FnOnce(&[Vec<DataType>]) -> T,
+    {
+        let snapshot = self.snapshot.read().unwrap();
+        let snapshot = snapshot.as_ref()?;
+        match snapshot.get(key) {
+            Some(rs) => Some(then(&rs[..])),
+            None if self.trigger.is_none() => Some(then(&[])),
+            None => None,
+        }
+    }
+
+    /// Find the number of rows for `key` in the snapshot this reader was restored from.
+    ///
+    /// See `try_find_stale_and`.
+    pub fn try_count_stale(&self, key: &[DataType]) -> Option<usize> {
+        if self.count_only {
+            self.try_find_stale_and(key, |rs| {
+                rs.first()
+                    .map(|r| {
+                        let n: u64 = (&r[r.len() - 1]).into();
+                        n as usize
+                    })
+                    .unwrap_or(0)
+            })
+        } else {
+            self.try_find_stale_and(key, |rs| rs.len())
+        }
+    }
+
+    /// Whether this reader is partially materialized.
+    pub fn is_partial(&self) -> bool {
+        self.trigger.is_some()
+    }
+
     pub fn len(&self) -> usize {
         self.handle.len()
     }
 
+    /// Whether this reader's state has been populated and can serve reads.
+    pub fn is_ready(&self) -> bool {
+        self.handle.is_ready()
+    }
+
     pub fn is_empty(&self) -> bool {
         self.handle.len() == 0
     }
@@ -551,4 +1069,192 @@ mod tests {
             .0
             .unwrap());
     }
+
+    #[test]
+    fn count_only() {
+        let a = vec![1.into(), "a".into()];
+        let b = vec![1.into(), "b".into()];
+
+        let (mut r, mut w) = new(2, &[0]);
+        r.set_count_only();
+        w.set_count_only();
+
+        let rs = w.count_updates(
+            &[0],
+            vec![Record::Positive(a.clone()), Record::Positive(b.clone())],
+            false,
+        );
+        w.add(rs);
+        // the second batch must build on the count of the first, even though it isn't visible yet
+        let rs = w.count_updates(&[0], vec![Record::Negative(a.clone())], false);
+        w.add(rs);
+        w.swap();
+
+        assert_eq!(r.try_count(&a[0..1]).unwrap().0, Some(1));
+        assert_eq!(
+            r.try_find_and(&a[0..1], |rs| rs.iter().cloned().collect::<Vec<_>>())
+                .unwrap()
+                .0,
+            Some(vec![vec![1.into(), 1.into()]])
+        );
+
+        let rs = w.count_updates(&[0], vec![Record::Negative(b.clone())], false);
+        w.add(rs);
+        w.swap();
+
+        assert_eq!(r.try_count(&a[0..1]).unwrap().0, Some(0));
+    }
+
+    #[test]
+    fn snapshot_is_served_until_swap() {
+        let a = vec![1.into(), "a".into()];
+        let b = vec![2.into(), "b".into()];
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("view.snapshot");
+
+        let (_, mut w) = new(2, &[0]);
+        w.add(vec![Record::Positive(a.clone())]);
+        w.swap();
+        assert!(w.save_snapshot(&path).unwrap());
+
+        let (r, mut w) = new(2, &[0]);
+        assert_eq!(r.try_find_stale_and(&a[0..1], |rs| rs.len()), None);
+        assert!(w.restore_snapshot(&path).unwrap());
+        assert_eq!(
+            r.try_find_stale_and(&a[0..1], |rs| rs.to_vec()),
+            Some(vec![a.clone()])
+        );
+        assert_eq!(r.try_count_stale(&b[0..1]), Some(0));
+
+        // not saved again while it is being rebuilt
+        assert!(!w.save_snapshot(&path).unwrap());
+
+        w.add(vec![Record::Positive(b.clone())]);
+        w.swap();
+        assert_eq!(r.try_find_stale_and(&a[0..1], |rs| rs.len()), None);
+        assert_eq!(r.try_find_and(&b[0..1], |rs| rs.len()).unwrap().0, Some(1));
+    }
+
+    #[test]
+    fn partial_snapshot_is_served_until_filled() {
+        let a = vec![1.into(), "a".into()];
+        let b = vec![2.into(), "b".into()];
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("view.snapshot");
+
+        let (_, mut w) = new_partial(2, &[0], |_: &mut dyn Iterator<Item = &[DataType]>| true);
+        w.swap();
+        w.mut_with_key(&a[0..1]).mark_filled();
+        w.mut_with_key(&b[0..1]).mark_filled();
+        w.add(vec![
+            Record::Positive(a.clone()),
+            Record::Positive(b.clone()),
+        ]);
+        w.swap();
+        assert!(w.save_snapshot(&path).unwrap());
+
+        let (r, mut w) = new_partial(2, &[0], |_: &mut dyn Iterator<Item = &[DataType]>| true);
+        assert!(w.restore_snapshot(&path).unwrap());
+        w.swap();
+        assert_eq!(r.try_count_stale(&a[0..1]), Some(1));
+        assert_eq!(r.try_count_stale(&[3.into()]), None);
+
+        w.mut_with_key(&a[0..1]).mark_filled();
+        w.swap();
+        assert_eq!(r.try_count_stale(&a[0..1]), None);
+        assert_eq!(r.try_count_stale(&b[0..1]), Some(1));
+
+        // the keys that were not filled yet are saved along with the ones that were
+        assert!(w.save_snapshot(&path).unwrap());
+        let (r, mut w) = new_partial(2, &[0], |_: &mut dyn Iterator<Item = &[DataType]>| true);
+        assert!(w.restore_snapshot(&path).unwrap());
+        assert_eq!(r.try_count_stale(&a[0..1]), Some(0));
+        assert_eq!(r.try_count_stale(&b[0..1]), Some(1));
+    }
+
+    #[test]
+    fn reads_are_related_to_replays() {
+        let a = vec![1.into(), "a".into()];
+        let b = vec![1.into(), "b".into()];
+
+        let (r, mut w) = new_partial(2, &[0], |_: &mut dyn Iterator<Item = &[DataType]>| true);
+        w.swap();
+        r.record_lookup(&a[0..1], None);
+        r.record_lookup(&[2.into()], None);
+        assert!(r.trigger(vec![&a[0..1], &[2.into()][..]].into_iter()));
+
+        let replay = vec![Record::Positive(a.clone()), Record::Positive(b.clone())];
+        w.record_replay(&replay);
+        w.mut_with_key(&a[0..1]).mark_filled();
+        w.add(replay);
+        w.swap();
+        r.record_lookup(&a[0..1], None);
+
+        let amp = w.read_amplification();
+        assert_eq!(amp.reads, 3);
+        assert_eq!(amp.upqueries, 2);
+        assert_eq!(amp.replayed_records, 2);
+        assert_eq!(amp.replayed_bytes, a.deep_size_of() + b.deep_size_of());
+        assert!((amp.records_per_read() - 2.0 / 3.0).abs() < std::f64::EPSILON);
+    }
+
+    #[test]
+    fn reads_are_attributed() {
+        let (r, w) = new(2, &[0]);
+        r.record_lookup(&[1.into()], Some("a"));
+        assert!(w.read_attribution().is_empty());
+
+        w.set_read_attribution(true);
+        r.record_lookup(&[1.into()], Some("a"));
+        r.record_lookup(&[2.into()], Some("a"));
+        r.record_lookup(&[2.into()], Some("a"));
+        r.record_lookup(&[3.into()], None);
+        let mut reads = w.read_attribution();
+        reads.sort_by(|a, b| a.identity.cmp(&b.identity));
+        assert_eq!(reads.len(), 2);
+        assert_eq!(reads[0].identity, None);
+        assert_eq!(reads[0].reads, 1);
+        assert_eq!(reads[1].identity.as_deref(), Some("a"));
+        assert_eq!(reads[1].reads, 3);
+        assert_eq!(reads[1].hot_keys[0], (vec![2.into()], 2));
+
+        // switching attribution off discards what was attributed
+        w.set_read_attribution(false);
+        r.record_lookup(&[1.into()], Some("a"));
+        assert!(w.read_attribution().is_empty());
+        assert_eq!(w.read_amplification().reads, 6);
+    }
+
+    #[test]
+    fn read_masks_hide_columns() {
+        let (r, w) = new(3, &[0]);
+        assert!(r.hidden_columns().is_empty());
+
+        w.set_read_mask(Some(ReadMask {
+            columns: vec![1, 2],
+        }));
+        assert_eq!(r.hidden_columns(), vec![1, 2]);
+
+        w.set_read_mask(None);
+        assert!(r.hidden_columns().is_empty());
+    }
+
+    #[test]
+    fn snapshot_of_other_view_is_ignored() {
+        let a = vec![1.into(), "a".into()];
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("view.snapshot");
+
+        let (_, mut w) = new(2, &[0]);
+        w.add(vec![Record::Positive(a.clone())]);
+        w.swap();
+        assert!(w.save_snapshot(&path).unwrap());
+
+        let (_, mut w) = new(2, &[1]);
+        assert!(!w.restore_snapshot(&path).unwrap());
+        let (_, mut w) = new(2, &[0]);
+        assert!(!w
+            .restore_snapshot(&dir.path().join("missing.snapshot"))
+            .unwrap());
+    }
 }
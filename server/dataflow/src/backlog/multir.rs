@@ -18,6 +18,15 @@ impl Handle {
         }
     }
 
+    /// Whether the writer has published the map at least once.
+    pub(super) fn is_ready(&self) -> bool {
+        match *self {
+            Handle::Single(ref h) => h.read().meta().is_some(),
+            Handle::Double(ref h) => h.read().meta().is_some(),
+            Handle::Many(ref h) => h.read().meta().is_some(),
+        }
+    }
+
     pub(super) fn meta_get_and<F, T>(&self, key: &[DataType], then: F) -> Option<(Option<T>, i64)>
     where
         F: FnOnce(&evmap::Values<Vec<DataType>, fnv::FnvBuildHasher>) -> T,
@@ -2,6 +2,7 @@ use super::{key_to_double, key_to_single, Key};
 use crate::prelude::*;
 use evmap;
 use fnv::FnvBuildHasher;
+use rand::prelude::*;
 
 pub(super) enum Handle {
     Single(evmap::WriteHandle<DataType, Vec<DataType>, i64, FnvBuildHasher>),
@@ -46,6 +47,20 @@ impl Handle {
         }
     }
 
+    pub fn purge(&mut self) {
+        match *self {
+            Handle::Single(ref mut h) => {
+                h.purge();
+            }
+            Handle::Double(ref mut h) => {
+                h.purge();
+            }
+            Handle::Many(ref mut h) => {
+                h.purge();
+            }
+        }
+    }
+
     /// Evict `count` randomly selected keys from state and return them along with the number of
     /// bytes freed.
     pub fn empty_at_index(
@@ -73,6 +88,48 @@ impl Handle {
         }
     }
 
+    /// Choose up to `count` of the keys currently present in the map, uniformly at random.
+    pub fn sample_keys<R: Rng>(&self, count: usize, rng: &mut R) -> Vec<Vec<DataType>> {
+        match *self {
+            Handle::Single(ref h) => h
+                .read()
+                .iter()
+                .map(|(k, _)| vec![k.clone()])
+                .choose_multiple(rng, count),
+            Handle::Double(ref h) => h
+                .read()
+                .iter()
+                .map(|(k, _)| vec![k.0.clone(), k.1.clone()])
+                .choose_multiple(rng, count),
+            Handle::Many(ref h) => h
+                .read()
+                .iter()
+                .map(|(k, _)| k.clone())
+                .choose_multiple(rng, count),
+        }
+    }
+
+    /// All keys currently present in the map, along with their rows.
+    pub fn contents(&self) -> Vec<(Vec<DataType>, Vec<Vec<DataType>>)> {
+        match *self {
+            Handle::Single(ref h) => h
+                .read()
+                .iter()
+                .map(|(k, rs)| (vec![k.clone()], rs.iter().cloned().collect()))
+                .collect(),
+            Handle::Double(ref h) => h
+                .read()
+                .iter()
+                .map(|(k, rs)| (vec![k.0.clone(), k.1.clone()], rs.iter().cloned().collect()))
+                .collect(),
+            Handle::Many(ref h) => h
+                .read()
+                .iter()
+                .map(|(k, rs)| (k.clone(), rs.iter().cloned().collect()))
+                .collect(),
+        }
+    }
+
     pub fn meta_get_and<F, T>(&self, key: Key, then: F) -> Option<(Option<T>, i64)>
     where
         F: FnOnce(&evmap::Values<Vec<DataType>, fnv::FnvBuildHasher>) -> T,
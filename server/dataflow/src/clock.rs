@@ -0,0 +1,81 @@
+//! The time that domains and the controller go by.
+//!
+//! Audit log retention, periodic work such as reader snapshots and statistics reports, and the
+//! controller's checks on workers all depend on how much time has passed. By default, that is
+//! measured by the host's clock. Tests can instead use a `Clock::manual`, which they can move
+//! forward by hand to make those things happen without waiting for them.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A handle to the clock that domains and the controller go by.
+///
+/// Clones of a clock share its time.
+#[derive(Clone, Debug, Default)]
+pub struct Clock {
+    /// How far the clock has been moved ahead of the host's, in nanoseconds, if it can be moved.
+    skew: Option<Arc<AtomicU64>>,
+}
+
+impl Clock {
+    /// A clock that can be moved forward with `Clock::advance`.
+    ///
+    /// Between calls to `advance`, it keeps moving at the host's pace, since domains and the
+    /// controller also rely on time passing to make progress.
+    pub fn manual() -> Self {
+        Clock {
+            skew: Some(Arc::new(AtomicU64::new(0))),
+        }
+    }
+
+    /// Move this clock, and all of its clones, forward by `by`.
+    ///
+    /// Panics if the clock was not made with `Clock::manual`.
+    pub fn advance(&self, by: Duration) {
+        let skew = self
+            .skew
+            .as_ref()
+            .expect("only manual clocks can be advanced");
+        skew.fetch_add(by.as_nanos() as u64, Ordering::SeqCst);
+    }
+
+    fn skew(&self) -> Duration {
+        self.skew
+            .as_ref()
+            .map(|skew| Duration::from_nanos(skew.load(Ordering::SeqCst)))
+            .unwrap_or_default()
+    }
+
+    /// The current time, for measuring how much time has passed.
+    pub fn now(&self) -> Instant {
+        Instant::now() + self.skew()
+    }
+
+    /// The current local date and time, for timestamps.
+    pub fn local(&self) -> chrono::NaiveDateTime {
+        let skew = chrono::Duration::from_std(self.skew()).unwrap();
+        chrono::Local::now().naive_local() + skew
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advance_moves_clones() {
+        let clock = Clock::manual();
+        let clone = clock.clone();
+        let (before, local) = (Instant::now(), chrono::Local::now().naive_local());
+        clock.advance(Duration::from_secs(3600));
+        assert!(clone.now() >= before + Duration::from_secs(3600));
+        assert!(clone.local() >= local + chrono::Duration::hours(1));
+    }
+
+    #[test]
+    #[should_panic]
+    fn host_clock_cannot_advance() {
+        Clock::default().advance(Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,96 @@
+//! Work that a domain hands off to a thread of its own, so that it does not hold up the
+//! processing of packets.
+//!
+//! Only work that does not touch the domain's state can be handed off, since that state is owned
+//! by the domain's thread. The domain copies whatever the work needs, and sends it over.
+
+use crate::backlog::SnapshotFile;
+use crate::prelude::*;
+use slog::Logger;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+/// The contents of a reader to be saved to disk.
+pub(super) struct ReaderSnapshot {
+    pub(super) node: LocalNodeIndex,
+    pub(super) path: PathBuf,
+    pub(super) file: SnapshotFile,
+}
+
+enum Task {
+    SaveSnapshots(Vec<ReaderSnapshot>),
+}
+
+/// A domain's maintenance thread.
+pub(super) struct Maintenance {
+    tx: Option<mpsc::Sender<Task>>,
+    thread: Option<thread::JoinHandle<()>>,
+    /// The number of tasks that have been handed off but not yet completed.
+    pending: Arc<AtomicUsize>,
+}
+
+impl Maintenance {
+    pub(super) fn spawn(name: String, log: Logger) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let done = Arc::clone(&pending);
+        let thread = thread::Builder::new()
+            .name(name)
+            .spawn(move || {
+                for task in rx {
+                    match task {
+                        Task::SaveSnapshots(snapshots) => save_snapshots(&log, snapshots),
+                    }
+                    done.fetch_sub(1, Ordering::AcqRel);
+                }
+            })
+            .unwrap();
+
+        Maintenance {
+            tx: Some(tx),
+            thread: Some(thread),
+            pending,
+        }
+    }
+
+    /// Whether all the work handed off so far has been completed.
+    pub(super) fn is_idle(&self) -> bool {
+        self.pending.load(Ordering::Acquire) == 0
+    }
+
+    /// Encode the given reader snapshots and write them to disk.
+    pub(super) fn save_snapshots(&self, snapshots: Vec<ReaderSnapshot>) {
+        self.submit(Task::SaveSnapshots(snapshots));
+    }
+
+    fn submit(&self, task: Task) {
+        self.pending.fetch_add(1, Ordering::AcqRel);
+        self.tx.as_ref().unwrap().send(task).unwrap();
+    }
+}
+
+impl Drop for Maintenance {
+    fn drop(&mut self) {
+        // finish what has been handed off already, so that no snapshot is left half-written
+        drop(self.tx.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn save_snapshots(log: &Logger, snapshots: Vec<ReaderSnapshot>) {
+    for ReaderSnapshot { node, path, file } in snapshots {
+        match file.write(&path) {
+            Ok(()) => {
+                trace!(log, "saved reader snapshot"; "local" => node.id());
+            }
+            Err(e) => {
+                warn!(log, "failed to save reader snapshot";
+                      "local" => node.id(), "err" => %e);
+            }
+        }
+    }
+}
@@ -1,3 +1,8 @@
+mod maintenance;
+mod replay_queue;
+
+use self::maintenance::{Maintenance, ReaderSnapshot};
+use self::replay_queue::ReplayRequestQueue;
 use petgraph::graph::NodeIndex;
 use std::borrow::Cow;
 use std::cell;
@@ -10,15 +15,20 @@ use std::sync::Arc;
 use std::time;
 
 use crate::group_commit::GroupCommitQueueSet;
-use crate::payload::{ControlReplyPacket, ReplayPieceContext, SourceSelection};
+use crate::payload::{ControlReplyPacket, ReplayPieceContext, ReshardTarget, SourceSelection};
 use crate::prelude::*;
+use crate::shedding::{LoadShedder, LoadSheddingPolicy, ShedAction};
+use crate::telemetry;
 use futures_util::{future::FutureExt, stream::StreamExt};
 use noria::channel::{self, TcpSender};
 pub use noria::internal::DomainIndex as Index;
+use noria::internal::LocalOrNot;
+use noria::{ReplayPriority, TableOperation, TraceContext, WriteRejection};
 use slog::Logger;
 use stream_cancel::Valve;
 
-use crate::Readers;
+use crate::watermark::{OperatorKind, WatermarkAction, WatermarkCrossing, WatermarkPolicy};
+use crate::{Clock, Readers};
 use timekeeper::{RealTime, SimpleTracker, ThreadTime, Timer, TimerSet};
 use tokio;
 
@@ -40,10 +50,126 @@ pub enum ProcessResult {
 pub struct Config {
     pub concurrent_replays: usize,
     pub replay_batch_timeout: time::Duration,
+    /// If set, the order in which a domain consumes its inputs is decided by a random number
+    /// generator seeded with this value, so that a given interleaving can be replayed.
+    #[serde(default)]
+    pub interleave_seed: Option<u64>,
+    /// If set, any key in a domain's in-memory operator state that accumulates more than this
+    /// many rows has its rows moved to a temporary file on local disk.
+    #[serde(default)]
+    pub spill_threshold: Option<usize>,
+    /// If set, entries in the audit logs of the domain's base tables are discarded once they are
+    /// older than this, in addition to when a log reaches its capacity.
+    #[serde(default)]
+    pub audit_retention: Option<time::Duration>,
+    /// If set, what the domain should stop doing when it cannot keep up with its input.
+    #[serde(default)]
+    pub load_shedding: Option<LoadSheddingPolicy>,
+    /// If set, and base tables are persisted with `DurabilityMode::Permanent`, the contents of
+    /// the domain's readers are saved to disk this often. Readers that are created for the same
+    /// views after a restart serve the saved rows, marked as stale, until they have caught up.
+    #[serde(default)]
+    pub reader_snapshot_interval: Option<time::Duration>,
+    /// The share of the keys each shard of a sharded domain owns, relative to the other shards.
+    /// Empty if keys are spread evenly, and ignored for domains with a different number of shards.
+    ///
+    /// The controller sets this for each domain it creates, as these are only the default weights.
+    #[serde(default)]
+    pub shard_weights: Vec<u32>,
+    /// If set, the packets the domain sends to domains on other workers are compressed with this
+    /// codec.
+    #[serde(default)]
+    pub compression: Option<channel::Compression>,
+    /// If set, the domain encodes and writes its reader snapshots to disk on its own thread.
+    /// Otherwise, a domain that saves reader snapshots hands that to a maintenance thread of its
+    /// own, and only copies the rows to save itself.
+    #[serde(default)]
+    pub inline_reader_snapshots: bool,
+    /// If set, the replay pieces the domain sends to other domains carry checksums of their data,
+    /// which the receiving domains check.
+    #[serde(default)]
+    pub replay_checksums: bool,
+    /// If set along with `replay_checksums`, every this many'th replay piece the domain sends has
+    /// its checksum spoiled, as if its data had been corrupted on the way. Only for testing.
+    #[doc(hidden)]
+    #[serde(default)]
+    pub corrupt_replay_pieces: Option<usize>,
+    /// If set, the domain reports its statistics to the controller this often, including only
+    /// the nodes whose statistics changed since the previous report.
+    #[serde(default)]
+    pub statistics_interval: Option<time::Duration>,
+    /// If set, the group commit window of each of the domain's base tables adapts to how many
+    /// writes arrive within it and how long their batches take to process, from the persistence
+    /// `flush_timeout` up to this long.
+    #[serde(default)]
+    pub adaptive_flush: Option<time::Duration>,
+    /// State sizes, in bytes and in increasing order, at which the domain asks its watermark
+    /// policy what to do about an operator whose state grows past them.
+    #[serde(default)]
+    pub state_watermarks: Vec<u64>,
 }
 
 const BATCH_SIZE: usize = 256;
 
+/// How many times in a row the keys of a partial replay may be asked for again, because their
+/// replay arrived corrupted or was lost, before the domain gives up on them.
+const MAX_REPLAY_RETRIES: usize = 3;
+
+/// The most rows a domain scans to sample a key from state that has no index on the key. Larger
+/// states are not sampled, since the scan holds up everything else the domain does.
+const MAX_SAMPLE_SCAN: usize = 10_000;
+
+/// The most rows a base sends to the base that replaces it in one write while it copies its rows
+/// there.
+const RESHARD_COPY_BATCH: usize = 1024;
+
+/// Stamps the replay pieces a domain sends to other domains with checksums of their data.
+struct ChecksumReplays<'a> {
+    inner: &'a mut dyn Executor,
+    /// Spoil the checksum of every this many'th replay piece sent; see
+    /// `Config::corrupt_replay_pieces`.
+    corrupt_every: Option<usize>,
+    /// How many replay pieces the domain has sent.
+    sent: Arc<AtomicUsize>,
+}
+
+impl Executor for ChecksumReplays<'_> {
+    fn ack(&mut self, tag: SourceChannelIdentifier, seq: u64) {
+        self.inner.ack(tag, seq)
+    }
+    fn reject(&mut self, tag: SourceChannelIdentifier, why: WriteRejection) {
+        self.inner.reject(tag, why)
+    }
+    fn create_universe(&mut self, req: HashMap<String, DataType>) {
+        self.inner.create_universe(req)
+    }
+    fn send(&mut self, dest: ReplicaAddr, mut m: Box<Packet>) {
+        m.stamp_checksum();
+        if let Packet::ReplayPiece {
+            checksum: Some(ref mut checksum),
+            ..
+        } = *m
+        {
+            let sent = self.sent.fetch_add(1, Ordering::Relaxed) + 1;
+            if self.corrupt_every.map(|n| sent % n == 0).unwrap_or(false) {
+                *checksum = !*checksum;
+            }
+        }
+        self.inner.send(dest, m)
+    }
+    fn report_statistics(
+        &mut self,
+        from: ReplicaAddr,
+        domain: noria::debug::stats::DomainStats,
+        nodes: HashMap<NodeIndex, noria::debug::stats::NodeStats>,
+    ) {
+        self.inner.report_statistics(from, domain, nodes)
+    }
+    fn report_corrupt_replay(&mut self, from: ReplicaAddr, tag: Tag, keys: usize, recovered: bool) {
+        self.inner.report_corrupt_replay(from, tag, keys, recovered)
+    }
+}
+
 #[derive(Debug)]
 enum DomainMode {
     Forwarding,
@@ -144,6 +270,8 @@ impl DomainBuilder {
         control_addr: SocketAddr,
         shutdown_valve: &Valve,
         state_size: Arc<AtomicUsize>,
+        clock: Clock,
+        watermark_policy: Arc<dyn WatermarkPolicy>,
     ) -> Domain {
         // initially, all nodes are not ready
         let not_ready = self
@@ -152,9 +280,28 @@ impl DomainBuilder {
             .map(|n| n.borrow().local_addr())
             .collect();
 
+        // base and reader nodes keep state even if they are not materialized
+        let stateless = self.nodes.values().all(|n| {
+            let n = n.borrow();
+            !n.is_base() && !n.is_reader()
+        });
+
         let log = log.new(o!("domain" => self.index.index(), "shard" => self.shard.unwrap_or(0)));
         let control_reply_tx = TcpSender::connect(&control_addr).unwrap();
-        let group_commit_queues = GroupCommitQueueSet::new(&self.persistence_parameters);
+        let group_commit_queues =
+            GroupCommitQueueSet::new(&self.persistence_parameters, self.config.adaptive_flush);
+        let maintenance = if self.config.reader_snapshot_interval.is_some()
+            && !self.config.inline_reader_snapshots
+        {
+            let name = format!(
+                "domain{}.{}-maintenance",
+                self.index.index(),
+                self.shard.unwrap_or(0)
+            );
+            Some(Maintenance::spawn(name, log.clone()))
+        } else {
+            None
+        };
 
         Domain {
             index: self.index,
@@ -166,9 +313,12 @@ impl DomainBuilder {
             state: StateMap::default(),
             log,
             not_ready,
+            paused: Default::default(),
+            replay_priorities: Default::default(),
             mode: DomainMode::Forwarding,
             waiting: Default::default(),
             reader_triggered: Default::default(),
+            replay_spans: Default::default(),
             replay_paths: Default::default(),
             replay_paths_by_dst: Default::default(),
 
@@ -185,9 +335,54 @@ impl DomainBuilder {
 
             concurrent_replays: 0,
             max_concurrent_replays: self.config.concurrent_replays,
+            interleave_seed: self.config.interleave_seed,
+            spill_threshold: self.config.spill_threshold,
+            audit_retention: self.config.audit_retention,
+            reader_snapshot_interval: self.config.reader_snapshot_interval,
+            shard_weights: self.config.shard_weights,
+            compression: self.config.compression,
+            compression_stats: Default::default(),
+            replay_checksums: self.config.replay_checksums,
+            corrupt_replays_every: self.config.corrupt_replay_pieces,
+            sent_replay_pieces: Default::default(),
+            corrupt_replay_pieces: 0,
+            processed_packets: 0,
+            schedule: self.config.interleave_seed.map(|_| 0),
+            replay_pieces: 0,
+            replay_retries: Default::default(),
+            next_reader_snapshot: self
+                .config
+                .reader_snapshot_interval
+                .map(|every| clock.now() + every),
+            statistics_interval: self.config.statistics_interval,
+            next_statistics_report: self
+                .config
+                .statistics_interval
+                .map(|every| clock.now() + every),
+            reported_node_stats: Default::default(),
+            clock,
+            state_watermarks: self.config.state_watermarks,
+            watermark_policy,
+            watermarks_crossed: Default::default(),
+            maintenance,
+            shedder: self
+                .config
+                .load_shedding
+                .clone()
+                .map(|policy| LoadShedder::new(policy, time::Instant::now())),
             replay_request_queue: Default::default(),
             delayed_for_self: Default::default(),
 
+            setup: if stateless { Some(Vec::new()) } else { None },
+            restoring: false,
+            draining: false,
+            migrating: false,
+            held_writes: None,
+            released_writes: VecDeque::new(),
+            settle: None,
+            settle_markers: 0,
+            resharding: HashMap::new(),
+
             group_commit_queues,
 
             state_size,
@@ -221,6 +416,10 @@ pub struct Domain {
     log: Logger,
 
     not_ready: HashSet<LocalNodeIndex>,
+    /// Readers that have been told to stop applying updates.
+    paused: HashSet<LocalNodeIndex>,
+    /// Priorities of upqueries for readers that have been given one.
+    replay_priorities: HashMap<LocalNodeIndex, ReplayPriority>,
 
     ingress_inject: Map<(usize, Vec<DataType>)>,
 
@@ -230,26 +429,100 @@ pub struct Domain {
     waiting: Map<Waiting>,
     replay_paths: HashMap<Tag, ReplayPath>,
     reader_triggered: Map<HashSet<Vec<DataType>>>,
+    /// Spans for the replays that are filling holes, by the node and key of the hole.
+    replay_spans: HashMap<(LocalNodeIndex, Vec<DataType>), telemetry::Span>,
     timed_purges: VecDeque<TimedPurge>,
 
     replay_paths_by_dst: Map<HashMap<Vec<usize>, Vec<Tag>>>,
 
     concurrent_replays: usize,
     max_concurrent_replays: usize,
-    replay_request_queue: VecDeque<(Tag, Vec<Vec<DataType>>)>,
+    interleave_seed: Option<u64>,
+    spill_threshold: Option<usize>,
+    audit_retention: Option<time::Duration>,
+    reader_snapshot_interval: Option<time::Duration>,
+    shard_weights: Vec<u32>,
+    compression: Option<channel::Compression>,
+    /// How well the packets sent over compressed connections to other domains compress.
+    compression_stats: Arc<channel::CompressionStats>,
+    replay_checksums: bool,
+    corrupt_replays_every: Option<usize>,
+    /// The number of replay pieces the domain has sent while checksumming them.
+    sent_replay_pieces: Arc<AtomicUsize>,
+    /// The number of replay pieces that arrived with data that did not match their checksum.
+    corrupt_replay_pieces: u64,
+    /// The number of packets the domain has been handed to process.
+    processed_packets: u64,
+    /// A fingerprint of the packets the domain has processed so far, in order, if it runs in
+    /// deterministic mode.
+    schedule: Option<u64>,
+    /// The number of replay pieces the domain has processed.
+    replay_pieces: u64,
+    /// How many times in a row the replays of keys asked for again have arrived corrupted.
+    replay_retries: HashMap<(Tag, Vec<DataType>), usize>,
+    /// When the domain's readers should next be saved to disk.
+    next_reader_snapshot: Option<time::Instant>,
+    statistics_interval: Option<time::Duration>,
+    /// When the domain should next report its statistics to the controller.
+    next_statistics_report: Option<time::Instant>,
+    /// The statistics of each node as of the domain's last report to the controller.
+    reported_node_stats: HashMap<NodeIndex, noria::debug::stats::NodeStats>,
+    maintenance: Option<Maintenance>,
+    shedder: Option<LoadShedder>,
+    /// Replay requests waiting for a free slot.
+    replay_request_queue: ReplayRequestQueue,
 
     shutdown_valve: Valve,
     readers: Readers,
     control_reply_tx: TcpSender<ControlReplyPacket>,
     channel_coordinator: Arc<ChannelCoordinator>,
 
-    buffered_replay_requests: HashMap<Tag, (time::Instant, HashSet<Vec<DataType>>, bool)>,
+    buffered_replay_requests: HashMap<
+        Tag,
+        (
+            time::Instant,
+            HashSet<Vec<DataType>>,
+            bool,
+            ReplayPriority,
+            Option<TraceContext>,
+        ),
+    >,
     replay_batch_timeout: time::Duration,
     delayed_for_self: VecDeque<Box<Packet>>,
 
+    /// The control packets that configured this domain after it was built, or `None` once the
+    /// domain keeps state. A domain without state can be rebuilt by handing it these again.
+    setup: Option<Vec<Box<Packet>>>,
+    /// Set while a rebuilt domain is handed its `setup`, which must not be acknowledged again.
+    restoring: bool,
+    /// Set once the domain has been drained; it rejects any writes that arrive after that.
+    draining: bool,
+    /// Set while the controller is migrating; writes from clients are turned away until then.
+    migrating: bool,
+    /// The writes from clients that arrived while writes are held, if they are.
+    held_writes: Option<VecDeque<Box<Packet>>>,
+    /// Held writes that are to be handled now that they are no longer held.
+    released_writes: VecDeque<Box<Packet>>,
+    /// The number of `SettleMarker`s to wait for, and where to send markers on to after that, if
+    /// the controller is waiting for this domain to settle.
+    settle: Option<(usize, Vec<ReplicaAddr>)>,
+    /// The `SettleMarker`s that have arrived and that no `Settle` has accounted for yet.
+    settle_markers: usize,
+    /// The bases that replace the bases of this domain that are being resharded, which every
+    /// write that those accept is forwarded to.
+    resharding: HashMap<LocalNodeIndex, ReshardTarget>,
+
     group_commit_queues: GroupCommitQueueSet,
 
     state_size: Arc<AtomicUsize>,
+    /// The time that audit log retention and the reader snapshot and statistics intervals go by.
+    clock: Clock,
+    /// The state sizes at which `watermark_policy` is asked what to do about an operator.
+    state_watermarks: Vec<u64>,
+    watermark_policy: Arc<dyn WatermarkPolicy>,
+    /// How many watermarks the state of each operator was past when last checked, and how many
+    /// times it has grown past one.
+    watermarks_crossed: HashMap<LocalNodeIndex, (usize, u64)>,
     total_time: Timer<SimpleTracker, RealTime>,
     total_ptime: Timer<SimpleTracker, ThreadTime>,
     wait_time: Timer<SimpleTracker, RealTime>,
@@ -268,6 +541,7 @@ impl Domain {
         miss_keys: Vec<Vec<DataType>>,
         miss_columns: &[usize],
         miss_in: LocalNodeIndex,
+        priority: ReplayPriority,
     ) {
         let mut tags = Vec::new();
         if let Some(ref candidates) = self.replay_paths_by_dst.get(miss_in) {
@@ -302,11 +576,14 @@ impl Domain {
                 // so instead, we simply keep track of the fact that we have a replay to handle,
                 // and then get back to it after all processing has finished (at the bottom of
                 // `Self::handle()`)
+                let trace = self.replay_trace(tag, &keys);
                 self.delayed_for_self
                     .push_back(Box::new(Packet::RequestPartialReplay {
                         tag,
                         keys,
                         unishard: true, // local replays are necessarily single-shard
+                        priority,
+                        trace,
                     }));
                 continue;
             }
@@ -314,7 +591,7 @@ impl Domain {
             // NOTE: due to max_concurrent_replays, it may be that we only replay from *some* of
             // these ancestors now, and some later. this will cause more of the replay to be
             // buffered up at the union above us, but that's probably fine.
-            self.request_partial_replay(tag, keys);
+            self.request_partial_replay(tag, keys, priority);
         }
 
         if tags.is_empty() {
@@ -325,6 +602,30 @@ impl Domain {
         }
     }
 
+    /// Start a span for the replay that fills the hole for `key` in `node`.
+    fn start_replay_span(&mut self, node: LocalNodeIndex, key: &[DataType]) {
+        if !telemetry::enabled() {
+            return;
+        }
+
+        let span = telemetry::Span::start("replay", None);
+        span.set("domain", self.index.index());
+        span.set("shard", self.shard.unwrap_or(0));
+        span.set("node", node.id() as usize);
+        self.replay_spans.insert((node, key.to_vec()), span);
+    }
+
+    /// The context of the span of a replay that waits for one of `keys` to arrive along `tag`.
+    fn replay_trace(&self, tag: Tag, keys: &[Vec<DataType>]) -> Option<TraceContext> {
+        if self.replay_spans.is_empty() {
+            return None;
+        }
+
+        let target = self.replay_paths[&tag].path.last().unwrap().node;
+        keys.iter()
+            .find_map(|key| self.replay_spans.get(&(target, key.clone()))?.context())
+    }
+
     fn on_replay_miss(
         &mut self,
         miss_in: LocalNodeIndex,
@@ -333,6 +634,7 @@ impl Domain {
         miss_key: Vec<DataType>,
         was_single_shard: bool,
         needed_for: Tag,
+        priority: ReplayPriority,
     ) {
         use std::collections::hash_map::Entry;
         use std::ops::AddAssign;
@@ -358,6 +660,7 @@ impl Domain {
             }
             Entry::Vacant(e) => {
                 // we haven't already requested backfill of this key
+                self.start_replay_span(miss_in, &miss_key);
                 let mut redos = HashSet::new();
                 // remember to notify this Redo when backfill completes
                 redos.insert(redo.clone());
@@ -372,17 +675,23 @@ impl Domain {
             return;
         }
 
-        self.find_tags_and_replay(vec![miss_key], miss_columns, miss_in);
+        self.find_tags_and_replay(vec![miss_key], miss_columns, miss_in, priority);
     }
 
-    fn send_partial_replay_request(&mut self, tag: Tag, keys: Vec<Vec<DataType>>) {
+    fn send_partial_replay_request(
+        &mut self,
+        tag: Tag,
+        keys: Vec<Vec<DataType>>,
+        priority: ReplayPriority,
+    ) {
         debug_assert!(self.concurrent_replays < self.max_concurrent_replays);
+        let trace = self.replay_trace(tag, &keys);
         if let TriggerEndpoint::End {
-            source,
+            ref source,
             ref mut options,
         } = self.replay_paths.get_mut(&tag).unwrap().trigger
         {
-            let ask_shard_by_key_i = match source {
+            let ask_shard_by_key_i = match *source {
                 SourceSelection::AllShards(_) => None,
                 SourceSelection::SameShard => {
                     // note that we "ask all" here because we're not indexing the vector by the
@@ -390,7 +699,12 @@ impl Domain {
                     // options.len() == 1.
                     None
                 }
-                SourceSelection::KeyShard { key_i_to_shard, .. } => Some(key_i_to_shard),
+                SourceSelection::KeyShard {
+                    key_i_to_shard,
+                    ref ranges,
+                    ref weights,
+                    ..
+                } => Some((key_i_to_shard, ranges, weights)),
             };
 
             if ask_shard_by_key_i.is_none() && options.len() != 1 {
@@ -410,6 +724,8 @@ impl Domain {
                             tag,
                             unishard: false, // ask_all is true, so replay is sharded
                             keys: keys.clone(), // sad to clone here
+                            priority,
+                            trace,
                         }))
                         .is_err()
                     {
@@ -433,15 +749,18 @@ impl Domain {
                         tag,
                         keys,
                         unishard: true, // only one option, so only one path
+                        priority,
+                        trace,
                     }))
                     .is_err()
                 {
                     // we're shutting down -- it's fine.
                 }
-            } else if let Some(key_shard_i) = ask_shard_by_key_i {
+            } else if let Some((key_shard_i, ranges, weights)) = ask_shard_by_key_i {
                 let mut shards = HashMap::new();
                 for key in keys {
-                    let shard = crate::shard_by(&key[key_shard_i], options.len());
+                    let shard =
+                        crate::shard_by_range(&key[key_shard_i], options.len(), ranges, weights);
                     shards.entry(shard).or_insert_with(Vec::new).push(key);
                 }
                 for (shard, keys) in shards {
@@ -450,6 +769,8 @@ impl Domain {
                             tag,
                             keys,
                             unishard: true, // !ask_all, so only one path
+                            priority,
+                            trace,
                         }))
                         .is_err()
                     {
@@ -465,17 +786,30 @@ impl Domain {
         }
     }
 
-    fn request_partial_replay(&mut self, tag: Tag, keys: Vec<Vec<DataType>>) {
-        if self.concurrent_replays < self.max_concurrent_replays {
-            assert_eq!(self.replay_request_queue.len(), 0);
-            self.send_partial_replay_request(tag, keys);
+    fn request_partial_replay(
+        &mut self,
+        tag: Tag,
+        keys: Vec<Vec<DataType>>,
+        priority: ReplayPriority,
+    ) {
+        let delay =
+            priority == ReplayPriority::Batch && self.is_shedding(ShedAction::DelayBatchReplays);
+        if self.concurrent_replays < self.max_concurrent_replays && !delay {
+            // the only requests that may be left waiting while there are free slots are those
+            // held back because we're overloaded.
+            debug_assert!(self.replay_request_queue.all(ReplayPriority::Batch));
+            self.send_partial_replay_request(tag, keys, priority);
         } else {
+            if delay {
+                self.shedder.as_mut().unwrap().delayed_replays += 1;
+            }
             trace!(self.log, "buffering replay request";
                 "tag" => ?tag,
                 "keys" => ?keys,
+                "priority" => ?priority,
                 "buffered" => self.replay_request_queue.len(),
             );
-            self.replay_request_queue.push_back((tag, keys));
+            self.replay_request_queue.push(tag, keys, priority);
         }
     }
 
@@ -518,33 +852,652 @@ impl Domain {
                 "ongoing" => self.concurrent_replays,
                 );
                 debug_assert!(self.concurrent_replays < self.max_concurrent_replays);
-                let mut per_tag = HashMap::new();
-                while self.concurrent_replays < self.max_concurrent_replays {
-                    if let Some((tag, mut keys)) = self.replay_request_queue.pop_front() {
-                        per_tag
-                            .entry(tag)
-                            .or_insert_with(Vec::new)
-                            .append(&mut keys);
+                self.release_queued_replays();
+            }
+            TriggerEndpoint::Local(..) => {
+                // didn't count against our quote, so we're also not decementing
+            }
+            TriggerEndpoint::Start(..) | TriggerEndpoint::None => {
+                unreachable!();
+            }
+        }
+    }
+
+    /// Ask again for every key this domain is waiting for a replay of from another domain.
+    ///
+    /// Requests whose replays already arrived are forgotten, so that the replay slots they hold
+    /// are freed. Pieces that still arrive for keys that have been filled since are discarded, as
+    /// for any replay nobody waits for. A key that has been asked for again `MAX_REPLAY_RETRIES`
+    /// times in a row without its replay arriving is given up on, so that a replay that makes a
+    /// domain on its way panic is not retried forever; the key is asked for anew the next time it
+    /// is missed.
+    fn retry_partial_replays(&mut self) {
+        let mut holes: HashMap<(LocalNodeIndex, Vec<usize>), Vec<Vec<DataType>>> = HashMap::new();
+        for (ni, w) in self.waiting.iter() {
+            for (cols, key) in w.redos.keys() {
+                holes
+                    .entry((ni, cols.clone()))
+                    .or_default()
+                    .push(key.clone());
+            }
+        }
+        for (ni, keys) in self.reader_triggered.iter() {
+            let cols = self.nodes[ni]
+                .borrow()
+                .with_reader(|r| r.key().map(Vec::from))
+                .unwrap_or(None);
+            if let Some(cols) = cols {
+                holes
+                    .entry((ni, cols))
+                    .or_default()
+                    .extend(keys.iter().cloned());
+            }
+        }
+
+        // every request we had in flight or queued is made again below
+        self.concurrent_replays = 0;
+        while self.replay_request_queue.pop().is_some() {}
+
+        let mut retried = 0;
+        for ((ni, cols), keys) in holes {
+            let tags = self
+                .replay_paths_by_dst
+                .get(ni)
+                .and_then(|candidates| candidates.get(&cols))
+                .cloned()
+                .unwrap_or_default();
+            let priority = self.replay_priorities.get(&ni).cloned().unwrap_or_default();
+            for tag in tags {
+                match self.replay_paths[&tag].trigger {
+                    TriggerEndpoint::End { .. } => {}
+                    // replays from within the domain cannot have been lost elsewhere
+                    _ => continue,
+                }
+
+                let mut retry = Vec::new();
+                for key in &keys {
+                    let retries = self.replay_retries.entry((tag, key.clone())).or_insert(0);
+                    *retries += 1;
+                    if *retries > MAX_REPLAY_RETRIES {
+                        self.replay_retries.remove(&(tag, key.clone()));
+                        self.forget_hole(ni, &cols, key);
                     } else {
-                        break;
+                        retry.push(key.clone());
                     }
                 }
+                if !retry.is_empty() {
+                    retried += retry.len();
+                    self.request_partial_replay(tag, retry, priority);
+                }
+            }
+        }
+        if retried != 0 {
+            warn!(self.log, "asking for replays again"; "keys" => retried);
+        }
+    }
+
+    /// Stop waiting for a replay to fill the hole for `key` in the `cols` of `ni`.
+    ///
+    /// Replays that were to be redone once the hole was filled are abandoned.
+    fn forget_hole(&mut self, ni: LocalNodeIndex, cols: &[usize], key: &[DataType]) {
+        warn!(self.log, "giving up on replay"; "local" => ni.id(), "key" => ?key);
+        if let Some(prev) = self.reader_triggered.get_mut(ni) {
+            prev.remove(key);
+        }
+        if let Some(w) = self.waiting.get_mut(ni) {
+            if let Some(redos) = w.redos.remove(&(cols.to_vec(), key.to_vec())) {
+                for redo in redos {
+                    w.holes.remove(&redo);
+                }
+            }
+            if w.redos.is_empty() {
+                self.waiting.remove(ni);
+            }
+        }
+    }
+
+    /// Issue queued replay requests for as long as there are free replay slots.
+    fn release_queued_replays(&mut self) {
+        let delay_batch = self.is_shedding(ShedAction::DelayBatchReplays);
+        let mut per_tag = HashMap::new();
+        while self.concurrent_replays < self.max_concurrent_replays {
+            match self.replay_request_queue.next_priority() {
+                Some(ReplayPriority::Batch) if delay_batch => break,
+                Some(_) => {}
+                None => break,
+            }
+            let (tag, mut keys, priority) = self.replay_request_queue.pop().unwrap();
+            let e = per_tag.entry(tag).or_insert_with(|| (Vec::new(), priority));
+            e.0.append(&mut keys);
+            e.1 = cmp::max(e.1, priority);
+        }
+
+        for (tag, (keys, priority)) in per_tag {
+            trace!(self.log, "releasing replay request";
+                "tag" => ?tag,
+                "keys" => ?keys,
+                "priority" => ?priority,
+                "left" => self.replay_request_queue.len(),
+                "ongoing" => self.concurrent_replays,
+            );
+            self.send_partial_replay_request(tag, keys, priority);
+        }
+    }
 
-                for (tag, keys) in per_tag {
-                    trace!(self.log, "releasing replay request";
-                        "tag" => ?tag,
-                        "keys" => ?keys,
-                        "left" => self.replay_request_queue.len(),
-                        "ongoing" => self.concurrent_replays,
+    fn is_shedding(&self, action: ShedAction) -> bool {
+        self.shedder
+            .as_ref()
+            .map(|s| s.is_shedding(action))
+            .unwrap_or(false)
+    }
+
+    /// Whether `p` is a write to a best-effort table that should be rejected to shed load.
+    fn is_shed_write(&self, p: &Packet) -> bool {
+        if let Packet::Input { ref inner, .. } = *p {
+            if let Some(ref shedder) = self.shedder {
+                if shedder.is_shedding(ShedAction::RejectBestEffortWrites) {
+                    let dst = unsafe { inner.deref() }.dst;
+                    return shedder.is_best_effort(self.nodes[dst].borrow().name());
+                }
+            }
+        }
+        false
+    }
+
+    /// Whether `p` is a write that arrived after the domain was drained, and must be rejected.
+    fn is_refused_write(&self, p: &Packet) -> bool {
+        if let Packet::Input { .. } = *p {
+            self.draining
+        } else {
+            false
+        }
+    }
+
+    /// Whether `p` is a write from a client that must be held on to rather than handled now.
+    fn is_held_write(&self, p: &Packet) -> bool {
+        if let Packet::Input { ref src, .. } = *p {
+            self.held_writes.is_some() && src.is_some()
+        } else {
+            false
+        }
+    }
+
+    /// The commit sequence numbers of the base nodes of this domain.
+    fn base_commit_seqs(&self) -> Vec<(NodeIndex, u64)> {
+        self.nodes
+            .values()
+            .filter_map(|n| {
+                let n = n.borrow();
+                n.get_base().map(|b| (n.global_addr(), b.commit_seq()))
+            })
+            .collect()
+    }
+
+    /// Pass the settle markers on and tell the controller, once as many markers as it expects
+    /// have arrived.
+    fn settle_if_ready(&mut self, executor: &mut dyn Executor) {
+        match self.settle {
+            Some((expected, _)) if self.settle_markers >= expected => {}
+            _ => return,
+        }
+        let (expected, downstream) = self.settle.take().unwrap();
+        self.settle_markers -= expected;
+        for to in downstream {
+            executor.send(to, Box::new(Packet::SettleMarker));
+        }
+        self.control_reply_tx
+            .send(ControlReplyPacket::ack())
+            .unwrap();
+    }
+
+    /// Whether `p` is a write from a client that must be turned away since a migration is in
+    /// progress.
+    fn is_migrating_write(&self, p: &Packet) -> bool {
+        if let Packet::Input { ref src, .. } = *p {
+            self.migrating && src.is_some()
+        } else {
+            false
+        }
+    }
+
+    /// Send the writes `data` to the shards of the base `to` that they belong to.
+    fn send_to_reshard_target(
+        to: &ReshardTarget,
+        data: Vec<TableOperation>,
+        identity: Option<String>,
+        executor: &mut dyn Executor,
+    ) {
+        let mut by_shard = vec![Vec::new(); to.shards];
+        for op in data {
+            by_shard[to.shard_of(&op)].push(op);
+        }
+        for (shard, data) in by_shard.into_iter().enumerate() {
+            if data.is_empty() {
+                continue;
+            }
+            executor.send(
+                (to.domain, shard),
+                Box::new(Packet::Input {
+                    inner: LocalOrNot::new(Input {
+                        dst: to.node,
+                        data,
+                        identity: identity.clone(),
+                        trace: None,
+                        all_or_nothing: false,
+                    }),
+                    src: None,
+                    senders: Vec::new(),
+                    trace: None,
+                }),
+            );
+        }
+    }
+
+    /// The file that the reader `node` is saved to, or `None` if readers are not saved.
+    fn reader_snapshot_path(&self, node: LocalNodeIndex) -> Option<std::path::PathBuf> {
+        let params = &self.persistence_parameters;
+        if self.reader_snapshot_interval.is_none() || params.mode != DurabilityMode::Permanent {
+            return None;
+        }
+
+        // like the files of base tables, snapshots live in the current directory
+        Some(
+            format!(
+                "{}-{}-{}.snapshot",
+                params.log_prefix,
+                self.nodes[node].borrow().name(),
+                self.shard.unwrap_or(0),
+            )
+            .into(),
+        )
+    }
+
+    /// The file that the cascade `node` keeps the deletes it has yet to apply in, or `None` if
+    /// `node` is not a cascade or they are not kept.
+    fn cascade_journal_path(&self, node: LocalNodeIndex) -> Option<std::path::PathBuf> {
+        let params = &self.persistence_parameters;
+        let n = self.nodes[node].borrow();
+        if !n.is_cascade() || params.mode != DurabilityMode::Permanent {
+            return None;
+        }
+
+        Some(
+            format!(
+                "{}-{}-{}.cascade",
+                params.log_prefix,
+                n.name(),
+                self.shard.unwrap_or(0),
+            )
+            .into(),
+        )
+    }
+
+    /// Have the reader `node` serve the rows that were saved to `path` before a restart until its
+    /// state has been rebuilt.
+    fn restore_reader_snapshot(
+        &self,
+        node: LocalNodeIndex,
+        path: &std::path::Path,
+        w: &mut crate::backlog::WriteHandle,
+    ) {
+        match w.restore_snapshot(path) {
+            Ok(true) => {
+                info!(self.log, "restored reader snapshot"; "local" => node.id());
+            }
+            Ok(false) => {}
+            Err(e) => {
+                warn!(self.log, "failed to restore reader snapshot";
+                      "local" => node.id(), "err" => %e);
+            }
+        }
+    }
+
+    /// The statistics of the domain and of each of its nodes.
+    fn statistics(
+        &self,
+    ) -> (
+        noria::debug::stats::DomainStats,
+        HashMap<NodeIndex, noria::debug::stats::NodeStats>,
+    ) {
+        let (reused_buffers, allocated_buffers) = self.group_commit_queues.buffer_stats();
+        let domain_stats = noria::debug::stats::DomainStats {
+            total_time: self.total_time.num_nanoseconds(),
+            total_ptime: self.total_ptime.num_nanoseconds(),
+            total_replay_time: self.total_replay_time.num_nanoseconds(),
+            total_forward_time: self.total_forward_time.num_nanoseconds(),
+            wait_time: self.wait_time.num_nanoseconds(),
+            reused_buffers,
+            allocated_buffers,
+            overloaded_windows: self
+                .shedder
+                .as_ref()
+                .map(|s| s.overloaded_windows)
+                .unwrap_or(0),
+            shed_traces: self
+                .nodes
+                .values()
+                .filter_map(|n| n.borrow().with_sharder(|s| s.untracked()))
+                .sum(),
+            delayed_replays: self
+                .shedder
+                .as_ref()
+                .map(|s| s.delayed_replays)
+                .unwrap_or(0),
+            rejected_writes: self
+                .shedder
+                .as_ref()
+                .map(|s| s.rejected_writes)
+                .unwrap_or(0),
+            split_keys: self
+                .nodes
+                .values()
+                .filter_map(|n| n.borrow().with_sharder(|s| s.split_keys() as u64))
+                .sum(),
+            split_records: self
+                .nodes
+                .values()
+                .filter_map(|n| n.borrow().with_sharder(|s| s.split_records()))
+                .sum(),
+            uncompressed_bytes: self.compression_stats.uncompressed_bytes(),
+            compressed_bytes: self.compression_stats.compressed_bytes(),
+            corrupt_replay_pieces: self.corrupt_replay_pieces,
+            processed_packets: self.processed_packets,
+            replay_pieces: self.replay_pieces,
+            schedule: self.schedule,
+        };
+
+        let node_stats = self
+            .nodes
+            .values()
+            .filter_map(|nd| {
+                let n = &*nd.borrow();
+                let local_index = n.local_addr();
+                let node_index: NodeIndex = n.global_addr();
+
+                let time = self.process_times.num_nanoseconds(local_index);
+                let ptime = self.process_ptimes.num_nanoseconds(local_index);
+                let mem_size = if n.is_reader() {
+                    let mut size = 0;
+                    n.with_reader(|r| size = r.state_size().unwrap_or(0))
+                        .unwrap();
+                    size
+                } else {
+                    self.state
+                        .get(local_index)
+                        .map(|s| s.deep_size_of())
+                        .unwrap_or(0)
+                };
+
+                let mat_state = if !n.is_reader() {
+                    match self.state.get(local_index) {
+                        Some(ref s) => {
+                            if s.is_partial() {
+                                MaterializationStatus::Partial {
+                                    beyond_materialization_frontier: n.purge,
+                                }
+                            } else {
+                                MaterializationStatus::Full
+                            }
+                        }
+                        None => MaterializationStatus::Not,
+                    }
+                } else {
+                    n.with_reader(|r| {
+                        if r.is_partial() {
+                            MaterializationStatus::Partial {
+                                beyond_materialization_frontier: n.purge,
+                            }
+                        } else {
+                            MaterializationStatus::Full
+                        }
+                    })
+                    .unwrap()
+                };
+
+                let probe_result = if n.is_internal() {
+                    n.probe()
+                } else if let Some(b) = n.get_base() {
+                    b.probe()
+                } else {
+                    Default::default()
+                };
+
+                let hot_keys = n
+                    .with_reader(|r| r.hot_keys())
+                    .ok()
+                    .or_else(|| n.with_sharder(|s| s.hot_keys()))
+                    .unwrap_or_default();
+                let read_amplification = n.with_reader(|r| r.read_amplification()).ok().flatten();
+                let watermarks_crossed = self
+                    .watermarks_crossed
+                    .get(&local_index)
+                    .map(|&(_, crossed)| crossed)
+                    .unwrap_or(0);
+                let flush_window = if n.is_base() {
+                    self.group_commit_queues
+                        .adaptive_window(local_index)
+                        .map(|w| w.as_nanos() as u64)
+                } else {
+                    None
+                };
+
+                if time.is_some() && ptime.is_some() {
+                    Some((
+                        node_index,
+                        noria::debug::stats::NodeStats {
+                            desc: format!("{:?}", n),
+                            process_time: time.unwrap(),
+                            process_ptime: ptime.unwrap(),
+                            mem_size,
+                            materialized: mat_state,
+                            probe_result,
+                            hot_keys,
+                            read_amplification,
+                            flush_window,
+                            watermarks_crossed,
+                        },
+                    ))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        (domain_stats, node_stats)
+    }
+
+    /// Ask the watermark policy what to do about every operator whose state has grown past a
+    /// watermark since it was last checked, and do it.
+    fn check_watermarks(&mut self, executor: &mut dyn Executor) {
+        if self.state_watermarks.is_empty() {
+            return;
+        }
+
+        let mut crossings = Vec::new();
+        for (local, state) in self.state.iter() {
+            let n = self.nodes[local].borrow();
+            if !n.is_internal() {
+                // base tables hold the data itself, not state derived from it
+                continue;
+            }
+            let size = state.deep_size_of();
+            let past = self
+                .state_watermarks
+                .iter()
+                .take_while(|&&w| size >= w)
+                .count();
+            let crossed = self.watermarks_crossed.entry(local).or_default();
+            if past > crossed.0 {
+                crossed.1 += 1;
+                crossings.push((
+                    local,
+                    WatermarkCrossing {
+                        node: n.global_addr(),
+                        shard: self.shard,
+                        kind: OperatorKind::of(&**n),
+                        partial: state.is_partial(),
+                        watermark: self.state_watermarks[past - 1],
+                        size,
+                    },
+                ));
+            }
+            crossed.0 = past;
+        }
+
+        for (local, crossing) in crossings {
+            match self.watermark_policy.on_crossing(&crossing) {
+                WatermarkAction::Ignore => {}
+                WatermarkAction::Alert => {
+                    warn!(self.log, "operator state grew past watermark";
+                          "node" => crossing.node.index(),
+                          "kind" => ?crossing.kind,
+                          "watermark" => crossing.watermark,
+                          "size" => crossing.size);
+                }
+                WatermarkAction::Evict if crossing.partial => {
+                    let num_bytes = (crossing.size - crossing.watermark) as usize + 1;
+                    self.handle_eviction(
+                        Box::new(Packet::Evict {
+                            node: Some(local),
+                            num_bytes,
+                        }),
+                        executor,
                     );
-                    self.send_partial_replay_request(tag, keys);
+                }
+                WatermarkAction::Evict => {}
+                WatermarkAction::Spill(rows) => {
+                    self.state[local].spill_keys_over(rows);
                 }
             }
-            TriggerEndpoint::Local(..) => {
-                // didn't count against our quote, so we're also not decementing
+        }
+    }
+
+    /// Process a batch of writes flushed from the group commit queue of its base table, and let
+    /// the queue know how long that took.
+    fn handle_batch(&mut self, m: Box<Packet>, executor: &mut dyn Executor) {
+        let node = m.dst();
+        let start = time::Instant::now();
+        self.handle(m, executor, true);
+        self.group_commit_queues.processed(node, start.elapsed());
+    }
+
+    /// Report the domain's statistics to the controller if it is time to do so.
+    ///
+    /// Only the nodes whose statistics changed since the last report are included, so the
+    /// controller can merge the report into what it already knows.
+    fn report_statistics_if_due(&mut self, executor: &mut dyn Executor) {
+        let now = self.clock.now();
+        match self.next_statistics_report {
+            Some(at) if at <= now => {}
+            _ => return,
+        }
+        self.next_statistics_report = Some(now + self.statistics_interval.unwrap());
+
+        let (domain_stats, mut node_stats) = self.statistics();
+        node_stats.retain(|ni, stats| self.reported_node_stats.get(ni) != Some(&*stats));
+        for (&ni, stats) in &node_stats {
+            self.reported_node_stats.insert(ni, stats.clone());
+        }
+        executor.report_statistics(
+            (self.index, self.shard.unwrap_or(0)),
+            domain_stats,
+            node_stats,
+        );
+    }
+
+    /// Save the contents of every reader in the domain to disk if it is time to do so.
+    fn snapshot_readers_if_due(&mut self) {
+        let now = self.clock.now();
+        match self.next_reader_snapshot {
+            Some(at) if at <= now => {}
+            _ => return,
+        }
+        self.next_reader_snapshot = Some(now + self.reader_snapshot_interval.unwrap());
+        if let Some(ref maintenance) = self.maintenance {
+            if !maintenance.is_idle() {
+                // the previous snapshots are still being written; we'll try again next time
+                return;
             }
-            TriggerEndpoint::Start(..) | TriggerEndpoint::None => {
-                unreachable!();
+        }
+
+        let readers: Vec<_> = self
+            .nodes
+            .values()
+            .map(|n| n.borrow())
+            .filter(|n| n.is_reader())
+            .map(|n| n.local_addr())
+            .collect();
+        let mut snapshots = Vec::new();
+        for node in readers {
+            let path = match self.reader_snapshot_path(node) {
+                Some(path) => path,
+                None => return,
+            };
+            let n = self.nodes[node].borrow();
+            if self.maintenance.is_some() {
+                // the rows are copied here, but encoded and written on the maintenance thread
+                let file = n
+                    .with_reader(|r| r.writer().and_then(|w| w.snapshot()))
+                    .unwrap();
+                if let Some(file) = file {
+                    snapshots.push(ReaderSnapshot { node, path, file });
+                }
+                continue;
+            }
+
+            let saved = n
+                .with_reader(|r| r.writer().map(|w| w.save_snapshot(&path)))
+                .unwrap();
+            match saved {
+                Some(Ok(true)) => {
+                    trace!(self.log, "saved reader snapshot"; "local" => node.id());
+                }
+                Some(Err(e)) => {
+                    warn!(self.log, "failed to save reader snapshot";
+                          "local" => node.id(), "err" => %e);
+                }
+                // not materialized, or still being rebuilt
+                None | Some(Ok(false)) => {}
+            }
+        }
+
+        if let Some(ref maintenance) = self.maintenance {
+            if !snapshots.is_empty() {
+                maintenance.save_snapshots(snapshots);
+            }
+        }
+    }
+
+    /// Account for time spent handling an event, and enable or disable shedding actions if the
+    /// current load-shedding window has ended.
+    fn update_shedding(&mut self, busy: time::Duration) {
+        let change = match self.shedder {
+            Some(ref mut shedder) => {
+                shedder.record_busy(busy);
+                shedder.tick(time::Instant::now())
+            }
+            None => return,
+        };
+
+        if let Some((action, enabled)) = change {
+            if enabled {
+                warn!(self.log, "domain is overloaded, shedding load"; "action" => ?action);
+            } else {
+                info!(self.log, "domain is no longer shedding load"; "action" => ?action);
+            }
+            match action {
+                ShedAction::DropTraces => {
+                    for n in self.nodes.values() {
+                        let mut n = n.borrow_mut();
+                        if n.is_sharder() {
+                            n.with_sharder_mut(|s| s.set_skip_hot_keys(enabled));
+                        }
+                    }
+                }
+                ShedAction::DelayBatchReplays if !enabled => {
+                    self.release_queued_replays();
+                }
+                ShedAction::DelayBatchReplays | ShedAction::RejectBestEffortWrites => {}
             }
         }
     }
@@ -570,6 +1523,10 @@ impl Domain {
             return;
         }
 
+        if !self.paused.is_empty() && self.paused.contains(&me) {
+            return;
+        }
+
         let (mut m, evictions) = {
             let mut n = self.nodes[me].borrow_mut();
             self.process_times.start(me);
@@ -740,17 +1697,34 @@ impl Domain {
     }
 
     #[allow(clippy::cognitive_complexity)]
-    fn handle(&mut self, m: Box<Packet>, executor: &mut dyn Executor, top: bool) {
+    fn handle(&mut self, mut m: Box<Packet>, executor: &mut dyn Executor, top: bool) {
         if self.wait_time.is_running() {
             self.wait_time.stop();
         }
 
+        self.record_setup(&m);
+
         match *m {
             Packet::Message { .. } | Packet::Input { .. } => {
+                // record this domain's part in a traced write, and make it the parent of the spans
+                // of the domains the write continues on to
+                let span = match m.trace() {
+                    Some(parent) if telemetry::enabled() => {
+                        let span = telemetry::Span::start("domain", Some(parent));
+                        span.set("domain", self.index.index());
+                        span.set("shard", self.shard.unwrap_or(0));
+                        span.set("node", m.dst().id() as usize);
+                        m.set_trace(span.context());
+                        span
+                    }
+                    _ => telemetry::Span::disabled(),
+                };
+
                 // WO for https://github.com/rust-lang/rfcs/issues/1403
                 self.total_forward_time.start();
                 self.dispatch(m, executor);
                 self.total_forward_time.stop();
+                drop(span);
             }
             Packet::ReplayPiece { .. } => {
                 self.total_replay_time.start();
@@ -779,7 +1753,17 @@ impl Domain {
                     }
                     Packet::RemoveNodes { nodes } => {
                         for &node in &nodes {
-                            self.nodes[node].borrow_mut().remove();
+                            let mut n = self.nodes[node].borrow_mut();
+                            if n.is_reader() {
+                                // stop serving reads, so clients learn the view is gone
+                                let shard = self.shard.unwrap_or(0);
+                                self.readers
+                                    .lock()
+                                    .unwrap()
+                                    .remove(&(n.global_addr(), shard));
+                            }
+                            n.remove();
+                            drop(n);
                             self.state.remove(node);
                             trace!(self.log, "node removed"; "local" => node.id());
                         }
@@ -823,6 +1807,15 @@ impl Domain {
                             .send(ControlReplyPacket::ack())
                             .unwrap();
                     }
+                    Packet::WidenBaseColumn { node, column } => {
+                        let mut n = self.nodes[node].borrow_mut();
+                        n.get_base_mut()
+                            .expect("told to widen base column of non-base node")
+                            .widen_column(column);
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::ack())
+                            .unwrap();
+                    }
                     Packet::UpdateEgress {
                         node,
                         new_tx,
@@ -838,12 +1831,33 @@ impl Domain {
                             }
                         });
                     }
+                    Packet::RemoveEgressTargets { node, targets } => {
+                        let mut n = self.nodes[node].borrow_mut();
+                        n.with_egress_mut(|e| e.remove_txs(&targets));
+                        if !self.restoring {
+                            self.control_reply_tx
+                                .send(ControlReplyPacket::ack())
+                                .unwrap();
+                        }
+                    }
                     Packet::UpdateSharder { node, new_txs } => {
                         let mut n = self.nodes[node].borrow_mut();
                         n.with_sharder_mut(move |s| {
                             s.add_sharded_child(new_txs.0, new_txs.1);
                         });
                     }
+                    Packet::StopSplittingKeys { node } => {
+                        let mut n = self.nodes[node].borrow_mut();
+                        n.with_sharder_mut(|s| s.stop_splitting_keys());
+                        if !self.restoring {
+                            self.control_reply_tx
+                                .send(ControlReplyPacket::ack())
+                                .unwrap();
+                        }
+                    }
+                    Packet::RetryPartialReplays => {
+                        self.retry_partial_replays();
+                    }
                     Packet::AddStreamer { node, new_streamer } => {
                         let mut n = self.nodes[node].borrow_mut();
                         n.with_reader_mut(|r| r.add_streamer(new_streamer).unwrap())
@@ -861,7 +1875,9 @@ impl Domain {
                         match state {
                             InitialState::PartialLocal(index) => {
                                 if !self.state.contains_key(node) {
-                                    self.state.insert(node, Box::new(MemoryState::default()));
+                                    let state =
+                                        MemoryState::with_spill_threshold(self.spill_threshold);
+                                    self.state.insert(node, Box::new(state));
                                 }
                                 let state = self.state.get_mut(node).unwrap();
                                 for (key, tags) in index {
@@ -873,7 +1889,9 @@ impl Domain {
                             }
                             InitialState::IndexedLocal(index) => {
                                 if !self.state.contains_key(node) {
-                                    self.state.insert(node, Box::new(MemoryState::default()));
+                                    let state =
+                                        MemoryState::with_spill_threshold(self.spill_threshold);
+                                    self.state.insert(node, Box::new(state));
                                 }
                                 let state = self.state.get_mut(node).unwrap();
                                 for idx in index {
@@ -926,7 +1944,8 @@ impl Domain {
                                         tx
                                     })
                                     .collect::<Vec<_>>();
-                                let (r_part, w_part) = backlog::new_partial(
+                                let weights = self.shard_weights.clone();
+                                let (mut r_part, mut w_part) = backlog::new_partial(
                                     cols,
                                     &k[..],
                                     move |misses: &mut dyn Iterator<Item = &[DataType]>| {
@@ -943,7 +1962,8 @@ impl Domain {
                                             let mut per_shard = HashMap::new();
                                             for miss in misses {
                                                 assert_eq!(miss.len(), 1);
-                                                let shard = crate::shard_by(&miss[0], n);
+                                                let shard =
+                                                    crate::shard_by_weight(&miss[0], n, &weights);
                                                 per_shard
                                                     .entry(shard)
                                                     .or_insert_with(Vec::new)
@@ -959,8 +1979,18 @@ impl Domain {
                                     },
                                 );
 
+                                let snapshot = self.reader_snapshot_path(node);
                                 let mut n = self.nodes[node].borrow_mut();
                                 n.with_reader_mut(|r| {
+                                    // computed keys can't be replayed from upstream
+                                    assert!(r.key_expressions().is_empty());
+                                    if r.is_count_only() {
+                                        r_part.set_count_only();
+                                        w_part.set_count_only();
+                                    }
+                                    if let Some(path) = snapshot {
+                                        self.restore_reader_snapshot(node, &path, &mut w_part);
+                                    }
                                     assert!(self
                                         .readers
                                         .lock()
@@ -975,10 +2005,21 @@ impl Domain {
                             }
                             InitialState::Global { gid, cols, key } => {
                                 use crate::backlog;
-                                let (r_part, w_part) = backlog::new(cols, &key[..]);
+                                let (mut r_part, mut w_part) = backlog::new(cols, &key[..]);
 
+                                let snapshot = self.reader_snapshot_path(node);
                                 let mut n = self.nodes[node].borrow_mut();
                                 n.with_reader_mut(|r| {
+                                    if !r.key_expressions().is_empty() {
+                                        w_part.set_computed_key(r.key_expressions().len());
+                                    }
+                                    if r.is_count_only() {
+                                        r_part.set_count_only();
+                                        w_part.set_count_only();
+                                    }
+                                    if let Some(path) = snapshot {
+                                        self.restore_reader_snapshot(node, &path, &mut w_part);
+                                    }
                                     assert!(self
                                         .readers
                                         .lock()
@@ -1001,9 +2042,11 @@ impl Domain {
                         trigger,
                     } => {
                         // let coordinator know that we've registered the tagged path
-                        self.control_reply_tx
-                            .send(ControlReplyPacket::ack())
-                            .unwrap();
+                        if !self.restoring {
+                            self.control_reply_tx
+                                .send(ControlReplyPacket::ack())
+                                .unwrap();
+                        }
 
                         if notify_done {
                             info!(self.log,
@@ -1112,8 +2155,16 @@ impl Domain {
                                 .or_default()
                                 .insert(key.clone())
                         });
+                        for key in &keys {
+                            self.start_replay_span(node, key);
+                        }
                         if !keys.is_empty() {
-                            self.find_tags_and_replay(keys, &cols[..], node);
+                            let priority = self
+                                .replay_priorities
+                                .get(&node)
+                                .cloned()
+                                .unwrap_or_default();
+                            self.find_tags_and_replay(keys, &cols[..], node, priority);
                         }
                         self.total_replay_time.stop();
                     }
@@ -1121,6 +2172,8 @@ impl Domain {
                         tag,
                         keys,
                         unishard,
+                        priority,
+                        trace,
                     } => {
                         trace!(
                             self.log,
@@ -1130,7 +2183,8 @@ impl Domain {
                         );
                         self.total_replay_time.start();
                         for key in keys {
-                            self.seed_replay(tag, Cow::Owned(key), unishard, executor);
+                            let key = Cow::Owned(key);
+                            self.seed_replay(tag, key, unishard, priority, trace, executor);
                         }
                         self.total_replay_time.stop();
                     }
@@ -1177,21 +2231,25 @@ impl Domain {
                                 last: state.is_empty(),
                             },
                             data: Vec::<Record>::new().into(),
+                            checksum: None,
+                            trace: None,
                         });
 
                         if !state.is_empty() {
                             let log = self.log.new(o!());
 
                             let added_cols = self.ingress_inject.get(from).cloned();
-                            let default = {
+                            let (default, widened) = {
                                 let n = self.nodes[from].borrow();
                                 let mut default = None;
+                                let mut widened = Vec::new();
                                 if let Some(b) = n.get_base() {
                                     let mut row = Vec::new();
                                     b.fix(&mut row);
                                     default = Some(row);
+                                    widened = b.get_widened().to_vec();
                                 }
-                                default
+                                (default, widened)
                             };
                             let fix = move |mut r: Vec<DataType>| -> Vec<DataType> {
                                 if let Some((start, ref added)) = added_cols {
@@ -1201,6 +2259,7 @@ impl Domain {
                                     let rlen = r.len();
                                     r.extend(defaults.iter().skip(rlen).cloned());
                                 }
+                                crate::node::special::Base::widen_row(&widened, &mut r);
                                 r
                             };
 
@@ -1246,6 +2305,8 @@ impl Domain {
                                             link, // to is overwritten by receiver
                                             context: ReplayPieceContext::Regular { last },
                                             data: chunk,
+                                            checksum: None,
+                                            trace: None,
                                         });
 
                                         trace!(log, "sending batch"; "#" => i, "[]" => len);
@@ -1263,162 +2324,494 @@ impl Domain {
                                 })
                                 .unwrap();
                         }
-                        self.handle_replay(p, executor);
+                        self.handle_replay(p, executor);
+
+                        self.total_replay_time.stop();
+                    }
+                    Packet::Finish(tag, ni) => {
+                        self.total_replay_time.start();
+                        self.finish_replay(tag, ni, executor);
+                        self.total_replay_time.stop();
+                    }
+                    Packet::Ready { node, purge, index } => {
+                        assert_eq!(self.mode, DomainMode::Forwarding);
+
+                        self.nodes[node].borrow_mut().purge = purge;
+
+                        if !index.is_empty() {
+                            let mut s: Box<dyn State> = {
+                                let n = self.nodes[node].borrow();
+                                let params = &self.persistence_parameters;
+                                match (n.get_base(), &params.mode) {
+                                    (Some(base), &DurabilityMode::DeleteOnExit)
+                                    | (Some(base), &DurabilityMode::Permanent) => {
+                                        let base_name = format!(
+                                            "{}-{}-{}",
+                                            params.log_prefix,
+                                            n.name(),
+                                            self.shard.unwrap_or(0),
+                                        );
+
+                                        Box::new(PersistentState::new(
+                                            base_name,
+                                            base.key(),
+                                            &params,
+                                        ))
+                                    }
+                                    _ => Box::new(MemoryState::with_spill_threshold(
+                                        self.spill_threshold,
+                                    )),
+                                }
+                            };
+                            for idx in index {
+                                s.add_key(&idx[..], None);
+                            }
+                            if let Some(seq) = s.commit_seq() {
+                                // pick up numbering where we left off before a restart
+                                let mut n = self.nodes[node].borrow_mut();
+                                n.get_base_mut().unwrap().restore_commit_seq(seq);
+                            }
+                            assert!(self.state.insert(node, s).is_none());
+                        } else {
+                            // NOTE: just because index_on is None does *not* mean we're not
+                            // materialized
+                        }
+
+                        if self.not_ready.remove(&node) {
+                            trace!(self.log, "readying empty node"; "local" => node.id());
+                        }
+
+                        // swap replayed reader nodes to expose new state
+                        {
+                            let mut n = self.nodes[node].borrow_mut();
+                            if n.is_reader() {
+                                n.with_reader_mut(|r| {
+                                    if let Some(ref mut state) = r.writer_mut() {
+                                        trace!(self.log, "swapping state"; "local" => node.id());
+                                        state.swap();
+                                        trace!(self.log, "state swapped"; "local" => node.id());
+                                    }
+                                })
+                                .unwrap();
+                            }
+                        }
+
+                        if let Some(path) = self.cascade_journal_path(node) {
+                            let mut n = self.nodes[node].borrow_mut();
+                            if let Err(e) = n.get_cascade_mut().unwrap().set_journal(path) {
+                                warn!(self.log, "failed to read cascade journal";
+                                      "local" => node.id(), "err" => %e);
+                            }
+                        }
+
+                        if !self.restoring {
+                            self.control_reply_tx
+                                .send(ControlReplyPacket::ack())
+                                .unwrap();
+                        }
+                    }
+                    Packet::GetStatistics => {
+                        let (domain_stats, node_stats) = self.statistics();
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::Statistics(domain_stats, node_stats))
+                            .unwrap();
+                    }
+                    Packet::UpdateStateSize => {
+                        self.update_state_sizes();
+                    }
+                    Packet::GetAuditLog { node } => {
+                        let mut n = self.nodes[node].borrow_mut();
+                        let log = n.get_base_mut().and_then(|b| {
+                            if let Some(max_age) = self.audit_retention {
+                                b.expire_audit(max_age, self.clock.local());
+                            }
+                            b.audit_log()
+                        });
+                        drop(n);
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::AuditLog(log))
+                            .unwrap();
+                    }
+                    Packet::PauseReader { node, drop_state } => {
+                        let mut n = self.nodes[node].borrow_mut();
+                        let partial = n
+                            .with_reader(|r| r.is_partial())
+                            .expect("told to pause non-reader node");
+                        // a fully materialized reader would have no way to catch up again
+                        assert!(partial, "told to pause fully materialized reader");
+
+                        self.paused.insert(node);
+                        if drop_state {
+                            n.with_reader_mut(|r| r.evict_all()).unwrap();
+                        }
+                        drop(n);
+                        self.update_state_sizes();
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::ack())
+                            .unwrap();
+                    }
+                    Packet::SetMigrating { migrating } => {
+                        self.migrating = migrating;
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::ack())
+                            .unwrap();
+                    }
+                    Packet::ResumeReader { node } => {
+                        // any key that is still present missed the updates that arrived while the
+                        // reader was paused. evicting them all means that they will be filled in
+                        // again, with up-to-date data, by upqueries the next time they are read.
+                        if self.paused.remove(&node) {
+                            self.nodes[node]
+                                .borrow_mut()
+                                .with_reader_mut(|r| r.evict_all())
+                                .unwrap();
+                            self.update_state_sizes();
+                        }
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::ack())
+                            .unwrap();
+                    }
+                    Packet::SetReadAttribution { node, enabled } => {
+                        self.nodes[node]
+                            .borrow_mut()
+                            .with_reader_mut(|r| r.set_read_attribution(enabled))
+                            .expect("told to attribute reads of non-reader node");
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::ack())
+                            .unwrap();
+                    }
+                    Packet::SetReadMask { node, mask } => {
+                        self.nodes[node]
+                            .borrow_mut()
+                            .with_reader_mut(|r| r.set_read_mask(mask))
+                            .expect("told to mask non-reader node");
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::ack())
+                            .unwrap();
+                    }
+                    Packet::GetReadAttribution { node } => {
+                        let reads = self.nodes[node]
+                            .borrow()
+                            .with_reader(|r| r.read_attribution())
+                            .expect("asked for read attribution of non-reader node");
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::ReadAttribution(reads))
+                            .unwrap();
+                    }
+                    Packet::SetReplayPriority { node, priority } => {
+                        if priority == ReplayPriority::default() {
+                            self.replay_priorities.remove(&node);
+                        } else {
+                            self.replay_priorities.insert(node, priority);
+                        }
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::ack())
+                            .unwrap();
+                    }
+                    Packet::SampleReaderKeys { node, count, keys } => {
+                        // a paused reader is expected to diverge from upstream
+                        let sampled = if self.paused.contains(&node) {
+                            Vec::new()
+                        } else {
+                            let (sampled, cols) = self.nodes[node]
+                                .borrow_mut()
+                                .with_reader_mut(|r| {
+                                    let cols = Vec::from(r.key().expect("reader has no key"));
+                                    let w = r
+                                        .writer_mut()
+                                        .expect("told to sample non-materialized reader");
+                                    assert!(w.is_partial(), "told to sample full reader");
+
+                                    let mut sampled: Vec<_> = w
+                                        .sample_keys(count)
+                                        .into_iter()
+                                        .chain(keys)
+                                        .map(|k| {
+                                            let fp = w.fingerprint(&k[..]);
+                                            (k, fp)
+                                        })
+                                        .collect();
+                                    sampled.retain(|(_, fp)| fp.is_some());
+
+                                    // evict the sampled keys so that they are recomputed from
+                                    // upstream, which the controller can then compare against.
+                                    for (k, _) in &sampled {
+                                        w.mut_with_key(&k[..]).mark_hole();
+                                    }
+                                    w.swap();
+                                    (sampled, cols)
+                                })
+                                .expect("told to sample non-reader node");
+
+                            if !sampled.is_empty() {
+                                self.delayed_for_self.push_back(Box::new(
+                                    Packet::RequestReaderReplay {
+                                        node,
+                                        cols,
+                                        keys: sampled.iter().map(|(k, _)| k.clone()).collect(),
+                                    },
+                                ));
+                            }
+                            sampled
+                        };
+                        self.update_state_sizes();
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::Fingerprints(sampled))
+                            .unwrap();
+                    }
+                    Packet::FingerprintReaderKeys { node, keys } => {
+                        let fps = self.nodes[node]
+                            .borrow()
+                            .with_reader(|r| {
+                                let w = r
+                                    .writer()
+                                    .expect("told to fingerprint non-materialized reader");
+                                keys.into_iter()
+                                    .map(|k| {
+                                        let fp = w.fingerprint(&k[..]);
+                                        (k, fp)
+                                    })
+                                    .collect()
+                            })
+                            .expect("told to fingerprint non-reader node");
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::Fingerprints(fps))
+                            .unwrap();
+                    }
+                    Packet::CopyReaderRows { node, domain, to } => {
+                        let rows = self.nodes[node]
+                            .borrow()
+                            .with_reader(|r| r.writer().and_then(|w| w.rows()))
+                            .expect("asked to copy rows of non-reader node")
+                            .unwrap_or_default();
+                        executor.send((domain, 0), Box::new(Packet::InsertRows { node: to, rows }));
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::ack())
+                            .unwrap();
+                    }
+                    Packet::InsertRows { node, rows } => {
+                        let data = rows.into_iter().map(TableOperation::Insert).collect();
+                        let input = Packet::Input {
+                            inner: LocalOrNot::new(Input {
+                                dst: node,
+                                data,
+                                identity: None,
+                                trace: None,
+                                all_or_nothing: false,
+                            }),
+                            src: None,
+                            senders: Vec::new(),
+                            trace: None,
+                        };
+                        self.handle(Box::new(input), executor, true);
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::ack())
+                            .unwrap();
+                    }
+                    Packet::StartResharding { node, to } => {
+                        let writes = {
+                            let n = self.nodes[node].borrow();
+                            let base = n.get_base().unwrap();
+                            self.state
+                                .get(node)
+                                .map(|s| s.cloned_records())
+                                .unwrap_or_else(Vec::new)
+                                .into_iter()
+                                .flat_map(|r| base.writes_for(r))
+                                .collect::<Vec<_>>()
+                        };
+                        info!(self.log, "copying base to resharded base";
+                              "local" => node.id(), "writes" => writes.len());
+
+                        // the copied rows go out before any forwarded write, and so arrive first.
+                        for chunk in writes.chunks(RESHARD_COPY_BATCH) {
+                            Self::send_to_reshard_target(&to, chunk.to_vec(), None, executor);
+                        }
+                        self.resharding.insert(node, to);
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::ack())
+                            .unwrap();
+                    }
+                    Packet::GetBaseRows { node } => {
+                        let n = self.nodes[node].borrow();
+                        let base = n.get_base().unwrap();
+                        let rows = self.state.get(node).filter(|s| !s.is_partial()).map(|s| {
+                            s.cloned_records()
+                                .into_iter()
+                                .map(|mut r| {
+                                    // rows written before columns were added are shorter
+                                    base.fix(&mut r);
+                                    r
+                                })
+                                .collect()
+                        });
+                        let shard = self.shard.unwrap_or(0);
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::BaseRows(shard, base.commit_seq(), rows))
+                            .unwrap();
+                    }
+                    Packet::ImportRows {
+                        node,
+                        rows,
+                        commit_seq,
+                    } => {
+                        if !rows.is_empty() {
+                            let data = rows.into_iter().map(TableOperation::Insert).collect();
+                            let input = Packet::Input {
+                                inner: LocalOrNot::new(Input {
+                                    dst: node,
+                                    data,
+                                    identity: None,
+                                    trace: None,
+                                    all_or_nothing: false,
+                                }),
+                                src: None,
+                                senders: Vec::new(),
+                                trace: None,
+                            };
+                            self.handle(Box::new(input), executor, true);
+                        }
 
-                        self.total_replay_time.stop();
+                        let mut n = self.nodes[node].borrow_mut();
+                        let base = n.get_base_mut().unwrap();
+                        base.restore_commit_seq(commit_seq);
+                        if let Some(s) = self.state.get_mut(node) {
+                            s.set_commit_seq(base.commit_seq());
+                            // persist the adopted sequence number right away
+                            s.process_records(&mut Records::default(), None);
+                        }
+                        drop(n);
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::ack())
+                            .unwrap();
                     }
-                    Packet::Finish(tag, ni) => {
-                        self.total_replay_time.start();
-                        self.finish_replay(tag, ni, executor);
-                        self.total_replay_time.stop();
+                    Packet::GetBaseKeys { node, columns } => {
+                        let keys = self.state.get(node).filter(|s| !s.is_partial()).map(|s| {
+                            let mut keys: Vec<Vec<DataType>> = s
+                                .cloned_records()
+                                .into_iter()
+                                .map(|r| columns.iter().map(|&c| r[c].clone()).collect())
+                                .collect();
+                            keys.sort();
+                            keys.dedup();
+                            keys
+                        });
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::BaseKeys(keys))
+                            .unwrap();
                     }
-                    Packet::Ready { node, purge, index } => {
-                        assert_eq!(self.mode, DomainMode::Forwarding);
-
-                        self.nodes[node].borrow_mut().purge = purge;
-
-                        if !index.is_empty() {
-                            let mut s: Box<dyn State> = {
-                                let n = self.nodes[node].borrow();
-                                let params = &self.persistence_parameters;
-                                match (n.get_base(), &params.mode) {
-                                    (Some(base), &DurabilityMode::DeleteOnExit)
-                                    | (Some(base), &DurabilityMode::Permanent) => {
-                                        let base_name = format!(
-                                            "{}-{}-{}",
-                                            params.log_prefix,
-                                            n.name(),
-                                            self.shard.unwrap_or(0),
-                                        );
-
-                                        Box::new(PersistentState::new(
-                                            base_name,
-                                            base.key(),
-                                            &params,
-                                        ))
+                    Packet::GetReaderSummary { node } => {
+                        let n = self.nodes[node].borrow();
+                        // the reader has no state until it has been set up
+                        let size = n
+                            .with_reader(|r| r.state_size())
+                            .expect("asked for summary of non-reader node");
+                        let ready = size.is_some() && !self.not_ready.contains(&node);
+                        let mem_size = size.unwrap_or(0);
+                        let paused = self.paused.contains(&node);
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::ReaderSummary(ready, paused, mem_size))
+                            .unwrap();
+                    }
+                    Packet::GetCommitSeq { node } => {
+                        let seq = self.nodes[node].borrow().get_base().unwrap().commit_seq();
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::CommitSeq(self.shard.unwrap_or(0), seq))
+                            .unwrap();
+                    }
+                    Packet::VerifyBase { node, repair } => {
+                        let mut report = self
+                            .state
+                            .get_mut(node)
+                            .and_then(|s| s.verify(repair))
+                            .unwrap_or_default();
+                        report.shard = self.shard.unwrap_or(0);
+                        if !report.is_clean() {
+                            warn!(
+                                self.log,
+                                "base failed verification";
+                                "node" => %node,
+                                "corrupt_rows" => report.corrupt_rows,
+                                "dangling_index_entries" => report.dangling_index_entries,
+                                "missing_index_entries" => report.missing_index_entries,
+                                "repaired" => report.repaired,
+                            );
+                        }
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::BaseVerification(report))
+                            .unwrap();
+                    }
+                    Packet::SampleState { node, columns, key } => {
+                        let materialized = self.state.contains_key(node);
+                        let rows = self.state.get(node).and_then(|s| {
+                            if s.keys().contains(&columns) {
+                                match s.lookup(&columns[..], &KeyType::from(&key[..])) {
+                                    LookupResult::Some(rs) => {
+                                        Some(rs.into_iter().map(Cow::into_owned).collect())
                                     }
-                                    _ => Box::new(MemoryState::default()),
+                                    LookupResult::Missing => None,
                                 }
-                            };
-                            for idx in index {
-                                s.add_key(&idx[..], None);
+                            } else if !s.is_partial() && s.rows() <= MAX_SAMPLE_SCAN {
+                                // no index on the key, so we have to scan all the rows
+                                Some(
+                                    s.cloned_records()
+                                        .into_iter()
+                                        .filter(|r| {
+                                            columns.iter().zip(&key).all(|(&c, k)| &r[c] == k)
+                                        })
+                                        .collect(),
+                                )
+                            } else {
+                                None
                             }
-                            assert!(self.state.insert(node, s).is_none());
-                        } else {
-                            // NOTE: just because index_on is None does *not* mean we're not
-                            // materialized
+                        });
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::StateSample(materialized, rows))
+                            .unwrap();
+                    }
+                    Packet::Drain => {
+                        self.draining = true;
+                        for m in self.group_commit_queues.flush_all() {
+                            self.handle_batch(m, executor);
                         }
 
-                        if self.not_ready.remove(&node) {
-                            trace!(self.log, "readying empty node"; "local" => node.id());
+                        let seqs = self.base_commit_seqs();
+                        info!(self.log, "drained domain");
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::Drained(self.shard.unwrap_or(0), seqs))
+                            .unwrap();
+                    }
+                    Packet::HoldWrites { hold: true } => {
+                        if self.held_writes.is_none() {
+                            self.held_writes = Some(VecDeque::new());
                         }
-
-                        // swap replayed reader nodes to expose new state
-                        {
-                            let mut n = self.nodes[node].borrow_mut();
-                            if n.is_reader() {
-                                n.with_reader_mut(|r| {
-                                    if let Some(ref mut state) = r.writer_mut() {
-                                        trace!(self.log, "swapping state"; "local" => node.id());
-                                        state.swap();
-                                        trace!(self.log, "state swapped"; "local" => node.id());
-                                    }
-                                })
-                                .unwrap();
-                            }
+                        for m in self.group_commit_queues.flush_all() {
+                            self.handle_batch(m, executor);
                         }
 
+                        let seqs = self.base_commit_seqs();
                         self.control_reply_tx
-                            .send(ControlReplyPacket::ack())
+                            .send(ControlReplyPacket::Drained(self.shard.unwrap_or(0), seqs))
                             .unwrap();
                     }
-                    Packet::GetStatistics => {
-                        let domain_stats = noria::debug::stats::DomainStats {
-                            total_time: self.total_time.num_nanoseconds(),
-                            total_ptime: self.total_ptime.num_nanoseconds(),
-                            total_replay_time: self.total_replay_time.num_nanoseconds(),
-                            total_forward_time: self.total_forward_time.num_nanoseconds(),
-                            wait_time: self.wait_time.num_nanoseconds(),
-                        };
-
-                        let node_stats = self
-                            .nodes
-                            .values()
-                            .filter_map(|nd| {
-                                let n = &*nd.borrow();
-                                let local_index = n.local_addr();
-                                let node_index: NodeIndex = n.global_addr();
-
-                                let time = self.process_times.num_nanoseconds(local_index);
-                                let ptime = self.process_ptimes.num_nanoseconds(local_index);
-                                let mem_size = if n.is_reader() {
-                                    let mut size = 0;
-                                    n.with_reader(|r| size = r.state_size().unwrap_or(0))
-                                        .unwrap();
-                                    size
-                                } else {
-                                    self.state
-                                        .get(local_index)
-                                        .map(|s| s.deep_size_of())
-                                        .unwrap_or(0)
-                                };
-
-                                let mat_state = if !n.is_reader() {
-                                    match self.state.get(local_index) {
-                                        Some(ref s) => {
-                                            if s.is_partial() {
-                                                MaterializationStatus::Partial {
-                                                    beyond_materialization_frontier: n.purge,
-                                                }
-                                            } else {
-                                                MaterializationStatus::Full
-                                            }
-                                        }
-                                        None => MaterializationStatus::Not,
-                                    }
-                                } else {
-                                    n.with_reader(|r| {
-                                        if r.is_partial() {
-                                            MaterializationStatus::Partial {
-                                                beyond_materialization_frontier: n.purge,
-                                            }
-                                        } else {
-                                            MaterializationStatus::Full
-                                        }
-                                    })
-                                    .unwrap()
-                                };
-
-                                let probe_result = if n.is_internal() {
-                                    n.probe()
-                                } else {
-                                    Default::default()
-                                };
-
-                                if time.is_some() && ptime.is_some() {
-                                    Some((
-                                        node_index,
-                                        noria::debug::stats::NodeStats {
-                                            desc: format!("{:?}", n),
-                                            process_time: time.unwrap(),
-                                            process_ptime: ptime.unwrap(),
-                                            mem_size,
-                                            materialized: mat_state,
-                                            probe_result,
-                                        },
-                                    ))
-                                } else {
-                                    None
-                                }
-                            })
-                            .collect();
-
+                    Packet::HoldWrites { hold: false } => {
+                        if let Some(held) = self.held_writes.take() {
+                            self.released_writes.extend(held);
+                        }
                         self.control_reply_tx
-                            .send(ControlReplyPacket::Statistics(domain_stats, node_stats))
+                            .send(ControlReplyPacket::ack())
                             .unwrap();
                     }
-                    Packet::UpdateStateSize => {
-                        self.update_state_sizes();
+                    Packet::Settle {
+                        expected,
+                        downstream,
+                    } => {
+                        self.settle = Some((expected, downstream));
+                        self.settle_if_ready(executor);
+                    }
+                    Packet::SettleMarker => {
+                        self.settle_markers += 1;
+                        self.settle_if_ready(executor);
                     }
                     Packet::Quit => unreachable!("Quit messages are handled by event loop"),
                     Packet::Spin => {
@@ -1448,20 +2841,31 @@ impl Domain {
                     let elapsed_replays: Vec<_> = {
                         self.buffered_replay_requests
                             .iter_mut()
-                            .filter_map(|(&tag, &mut (first, ref mut keys, single_shard))| {
-                                if !keys.is_empty() && now.duration_since(first) > to {
-                                    // will be removed by retain below
-                                    Some((tag, mem::replace(keys, HashSet::new()), single_shard))
-                                } else {
-                                    None
-                                }
-                            })
+                            .filter_map(
+                                |(
+                                    &tag,
+                                    &mut (first, ref mut keys, single_shard, priority, trace),
+                                )| {
+                                    if !keys.is_empty() && now.duration_since(first) > to {
+                                        // will be removed by retain below
+                                        Some((
+                                            tag,
+                                            mem::replace(keys, HashSet::new()),
+                                            single_shard,
+                                            priority,
+                                            trace,
+                                        ))
+                                    } else {
+                                        None
+                                    }
+                                },
+                            )
                             .collect()
                     };
                     self.buffered_replay_requests
-                        .retain(|_, (_, ref keys, _)| !keys.is_empty());
-                    for (tag, keys, single_shard) in elapsed_replays {
-                        self.seed_all(tag, keys, single_shard, executor);
+                        .retain(|_, (_, ref keys, ..)| !keys.is_empty());
+                    for (tag, keys, single_shard, priority, trace) in elapsed_replays {
+                        self.seed_all(tag, keys, single_shard, priority, trace, executor);
                     }
                     self.total_replay_time.stop();
                 }
@@ -1531,6 +2935,8 @@ impl Domain {
         tag: Tag,
         keys: HashSet<Vec<DataType>>,
         single_shard: bool,
+        priority: ReplayPriority,
+        trace: Option<TraceContext>,
         ex: &mut dyn Executor,
     ) {
         let (m, source, is_miss) = match self.replay_paths[&tag] {
@@ -1570,8 +2976,11 @@ impl Domain {
                             for_keys: keys,
                             unishard: single_shard, // if we are the only source, only one path
                             ignore: false,
+                            priority,
                         },
                         data: rs.into(),
+                        checksum: None,
+                        trace,
                     }))
                 } else {
                     None
@@ -1596,7 +3005,15 @@ impl Domain {
                        "missed during replay request";
                        "tag" => tag.id(),
                        "key" => ?key);
-                self.on_replay_miss(source, &cols[..], key.clone(), key, single_shard, tag);
+                self.on_replay_miss(
+                    source,
+                    &cols[..],
+                    key.clone(),
+                    key,
+                    single_shard,
+                    tag,
+                    priority,
+                );
             }
         }
 
@@ -1625,6 +3042,8 @@ impl Domain {
         tag: Tag,
         key: Cow<[DataType]>,
         single_shard: bool,
+        priority: ReplayPriority,
+        trace: Option<TraceContext>,
         ex: &mut dyn Executor,
     ) {
         if let ReplayPath {
@@ -1643,12 +3062,17 @@ impl Domain {
             match self.buffered_replay_requests.entry(tag) {
                 Entry::Occupied(o) => {
                     assert!(!o.get().1.is_empty());
-                    o.into_mut().1.insert(key);
+                    let o = o.into_mut();
+                    o.1.insert(key);
+                    // the batch is replayed as a whole, so it goes at the most urgent priority
+                    o.3 = cmp::max(o.3, priority);
+                    // and is traced as part of the first traced replay that asked for it
+                    o.4 = o.4.or(trace);
                 }
                 Entry::Vacant(v) => {
                     let mut ks = HashSet::new();
                     ks.insert(key);
-                    v.insert((time::Instant::now(), ks, single_shard));
+                    v.insert((time::Instant::now(), ks, single_shard, priority, trace));
                 }
             }
 
@@ -1688,8 +3112,11 @@ impl Domain {
                             for_keys: k,
                             unishard: single_shard, // if we are the only source, only one path
                             ignore: false,
+                            priority,
                         },
                         data,
+                        checksum: None,
+                        trace,
                     }));
                     (m, source, None)
                 } else {
@@ -1713,6 +3140,7 @@ impl Domain {
                 key.into_owned(),
                 single_shard,
                 tag,
+                priority,
             );
         } else {
             trace!(self.log,
@@ -1728,8 +3156,102 @@ impl Domain {
         }
     }
 
+    /// Handle a replay piece whose data does not match the checksum it was sent with, and report
+    /// it to the controller.
+    ///
+    /// If the piece is part of a partial replay this domain asked a single source for, it is
+    /// dropped and its keys are asked for again, up to `MAX_REPLAY_RETRIES` times. Otherwise there
+    /// is no way to get just this piece again, and the domain leaves it to the controller to
+    /// rebuild the queries that depend on it. A piece of a full replay is then let through without
+    /// its data, so that the replay still completes; any other piece is dropped.
+    fn on_corrupt_replay_piece(
+        &mut self,
+        mut m: Box<Packet>,
+        ex: &mut dyn Executor,
+    ) -> Option<Box<Packet>> {
+        self.corrupt_replay_pieces += 1;
+        let from = (self.index, self.shard.unwrap_or(0));
+        let tag = m.tag().unwrap();
+        let (keys, unishard, priority) = match *m {
+            Packet::ReplayPiece {
+                context: ReplayPieceContext::Partial { ignore: true, .. },
+                ..
+            } => {
+                // the piece would have been dropped anyway
+                return None;
+            }
+            Packet::ReplayPiece {
+                context: ReplayPieceContext::Regular { .. },
+                ..
+            } => {
+                crit!(self.log, "full replay piece failed its checksum, dropping its data";
+                      "tag" => tag.id());
+                ex.report_corrupt_replay(from, tag, 0, false);
+                m.take_data();
+                return Some(m);
+            }
+            Packet::ReplayPiece {
+                context:
+                    ReplayPieceContext::Partial {
+                        ref for_keys,
+                        unishard,
+                        priority,
+                        ..
+                    },
+                ..
+            } => (
+                for_keys.iter().cloned().collect::<Vec<_>>(),
+                unishard,
+                priority,
+            ),
+            _ => unreachable!(),
+        };
+
+        let dst = self.replay_paths[&tag].path.last().unwrap().node;
+        let is_target = !self.nodes[dst].borrow().is_sender();
+        let is_requester = match self.replay_paths[&tag].trigger {
+            TriggerEndpoint::End { .. } => true,
+            _ => false,
+        };
+        if !unishard || !is_target || !is_requester {
+            crit!(
+                self.log,
+                "partial replay piece failed its checksum, and cannot be asked for again";
+                "tag" => tag.id(),
+                "keys" => keys.len()
+            );
+            ex.report_corrupt_replay(from, tag, keys.len(), false);
+            return None;
+        }
+
+        let mut exhausted = false;
+        for key in &keys {
+            let retries = self.replay_retries.entry((tag, key.clone())).or_insert(0);
+            *retries += 1;
+            exhausted |= *retries > MAX_REPLAY_RETRIES;
+        }
+
+        // the request we made for the keys is over either way
+        self.finished_partial_replay(tag, keys.len());
+        if exhausted {
+            crit!(self.log, "partial replay piece failed its checksum too many times";
+                  "tag" => tag.id(), "keys" => keys.len());
+            for key in &keys {
+                self.replay_retries.remove(&(tag, key.clone()));
+            }
+            ex.report_corrupt_replay(from, tag, keys.len(), false);
+            return None;
+        }
+
+        warn!(self.log, "replay piece failed its checksum, asking for it again";
+              "tag" => tag.id(), "keys" => keys.len());
+        ex.report_corrupt_replay(from, tag, keys.len(), true);
+        self.request_partial_replay(tag, keys, priority);
+        None
+    }
+
     #[allow(clippy::cognitive_complexity)]
-    fn handle_replay(&mut self, m: Box<Packet>, ex: &mut dyn Executor) {
+    fn handle_replay(&mut self, mut m: Box<Packet>, ex: &mut dyn Executor) {
         let tag = m.tag().unwrap();
         if self.nodes[self.replay_paths[&tag].path.last().unwrap().node]
             .borrow()
@@ -1738,6 +3260,40 @@ impl Domain {
             return;
         }
 
+        if m.is_corrupt() {
+            m = match self.on_corrupt_replay_piece(m, ex) {
+                Some(m) => m,
+                None => return,
+            };
+        }
+        self.replay_pieces += 1;
+
+        // record this domain's part in a traced replay, and make it the parent of the spans of
+        // the domains the replay continues on to
+        let _span = match m.trace() {
+            Some(parent) if telemetry::enabled() => {
+                let span = telemetry::Span::start("replay piece", Some(parent));
+                span.set("domain", self.index.index());
+                span.set("shard", self.shard.unwrap_or(0));
+                span.set("tag", tag.id() as usize);
+                span.set("records", m.data().len());
+                m.set_trace(span.context());
+                span
+            }
+            _ => telemetry::Span::disabled(),
+        };
+        if !self.replay_retries.is_empty() {
+            if let Packet::ReplayPiece {
+                context: ReplayPieceContext::Partial { ref for_keys, .. },
+                ..
+            } = *m
+            {
+                for key in for_keys {
+                    self.replay_retries.remove(&(tag, key.clone()));
+                }
+            }
+        }
+
         let mut finished = None;
         let mut need_replay = Vec::new();
         let mut finished_partial = 0;
@@ -1784,6 +3340,8 @@ impl Domain {
                     link,
                     mut data,
                     mut context,
+                    trace,
+                    ..
                 } => {
                     if let ReplayPieceContext::Partial { ref for_keys, .. } = context {
                         trace!(
@@ -1852,6 +3410,8 @@ impl Domain {
                         tag,
                         data,
                         context: context.clone(),
+                        checksum: None,
+                        trace,
                     });
                     let mut m = Some(m);
 
@@ -1973,6 +3533,7 @@ impl Domain {
                                 {
                                     for key in backfill_keys.as_ref().unwrap().iter() {
                                         prev.remove(&key[..]);
+                                        self.replay_spans.remove(&(segment.node, key.clone()));
                                     }
                                 }
                             }
@@ -2047,12 +3608,15 @@ impl Domain {
 
                         // if we missed during replay, we need to do another replay
                         if backfill_keys.is_some() && !misses.is_empty() {
-                            let single_shard = if let Packet::ReplayPiece {
-                                context: ReplayPieceContext::Partial { unishard, .. },
+                            let (single_shard, priority) = if let Packet::ReplayPiece {
+                                context:
+                                    ReplayPieceContext::Partial {
+                                        unishard, priority, ..
+                                    },
                                 ..
                             } = **m.as_mut().unwrap()
                             {
-                                unishard
+                                (unishard, priority)
                             } else {
                                 unreachable!("backfill_keys.is_some() implies Context::Partial");
                             };
@@ -2064,6 +3628,7 @@ impl Domain {
                                     miss.lookup_idx,
                                     single_shard,
                                     tag,
+                                    priority,
                                 ));
                             }
 
@@ -2298,7 +3863,7 @@ impl Domain {
                             );
                             if notify_done {
                                 debug!(self.log, "last batch received"; "local" => dst.id());
-                                finished = Some((tag, dst, None));
+                                finished = Some((tag, dst, None, ReplayPriority::default()));
                             }
                         }
                         ReplayPieceContext::Regular { .. } => {
@@ -2308,6 +3873,7 @@ impl Domain {
                             for_keys,
                             ignore,
                             unishard: _,
+                            priority,
                         } => {
                             assert!(!ignore);
                             if dst_is_reader {
@@ -2327,7 +3893,7 @@ impl Domain {
                                 if finished_partial == 0 {
                                     assert!(for_keys.is_empty());
                                 }
-                                finished = Some((tag, dst, Some(for_keys)));
+                                finished = Some((tag, dst, Some(for_keys), priority));
                             } else {
                                 // we're just on the replay path
                             }
@@ -2343,7 +3909,9 @@ impl Domain {
             self.finished_partial_replay(tag, finished_partial);
         }
 
-        for (node, while_replaying_key, miss_key, miss_cols, single_shard, tag) in need_replay {
+        for (node, while_replaying_key, miss_key, miss_cols, single_shard, tag, priority) in
+            need_replay
+        {
             trace!(self.log,
                    "missed during replay processing";
                    "tag" => tag.id(),
@@ -2358,10 +3926,11 @@ impl Domain {
                 miss_key,
                 single_shard,
                 tag,
+                priority,
             );
         }
 
-        if let Some((tag, ni, for_keys)) = finished {
+        if let Some((tag, ni, for_keys, priority)) = finished {
             trace!(self.log, "partial replay finished";
                    "node" => ?ni,
                    "keys" => ?for_keys);
@@ -2392,6 +3961,7 @@ impl Domain {
                             hole.1, tag
                         )
                     });
+                    self.replay_spans.remove(&(ni, hole.1));
 
                     // we may need more holes to fill before some replays should be re-attempted
                     let replay: Vec<_> = replay
@@ -2419,6 +3989,7 @@ impl Domain {
                         })
                         .collect();
 
+                    // the redone replays inherit the priority of the replay that filled the hole
                     for Redo {
                         tag,
                         replay_key,
@@ -2430,6 +4001,8 @@ impl Domain {
                                 tag,
                                 unishard,
                                 keys: vec![replay_key],
+                                priority,
+                                trace: None,
                             }));
                     }
                 }
@@ -2525,6 +4098,11 @@ impl Domain {
                 unreachable!();
             }
 
+            // a cascade can only look up the rows it has yet to delete once it has them all
+            if let Some(c) = self.nodes[node].borrow_mut().get_cascade_mut() {
+                c.resume(ex, &self.state);
+            }
+
             if self.replay_paths[&tag].notify_done {
                 // NOTE: this will only be Some for non-partial replays
                 info!(self.log, "acknowledging replay completed"; "node" => node.id());
@@ -2764,6 +4342,72 @@ impl Domain {
         (self.index, self.shard.unwrap_or(0))
     }
 
+    /// The codec to compress packets to domains on other workers with, if any, and where to
+    /// record how well they compress.
+    pub fn output_compression(
+        &self,
+    ) -> Option<(channel::Compression, Arc<channel::CompressionStats>)> {
+        self.compression
+            .map(|codec| (codec, Arc::clone(&self.compression_stats)))
+    }
+
+    /// Whether this domain keeps no state, so that it can be rebuilt from its `DomainBuilder`
+    /// and the packets returned by `take_setup` if it fails.
+    pub fn is_stateless(&self) -> bool {
+        self.setup.is_some()
+    }
+
+    /// Whether this domain holds any base tables.
+    pub fn has_base_tables(&self) -> bool {
+        self.nodes.values().any(|n| n.borrow().is_base())
+    }
+
+    /// Take the control packets that configured this domain after it was built.
+    ///
+    /// Empty if the domain keeps state.
+    pub fn take_setup(&mut self) -> Vec<Box<Packet>> {
+        self.setup.take().unwrap_or_default()
+    }
+
+    /// Bring a freshly built domain up to date with the packets taken from the domain it
+    /// replaces, without acknowledging them to the controller again.
+    pub fn restore(&mut self, setup: Vec<Box<Packet>>, executor: &mut dyn Executor) {
+        self.restoring = true;
+        for m in setup {
+            self.handle(m, executor, true);
+        }
+        self.restoring = false;
+    }
+
+    /// Remember `m` if it configures a stateless domain, and forget all setup once it does not.
+    fn record_setup(&mut self, m: &Packet) {
+        let setup = match self.setup {
+            Some(ref mut setup) => setup,
+            None => return,
+        };
+        match *m {
+            Packet::AddNode { ref node, .. } if !node.is_base() && !node.is_reader() => {
+                setup.push(Box::new(m.clone()))
+            }
+            Packet::Ready { ref index, .. } if index.is_empty() => setup.push(Box::new(m.clone())),
+            Packet::RemoveNodes { .. }
+            | Packet::UpdateEgress { .. }
+            | Packet::RemoveEgressTargets { .. }
+            | Packet::UpdateSharder { .. }
+            | Packet::StopSplittingKeys { .. }
+            | Packet::SetupReplayPath { .. } => setup.push(Box::new(m.clone())),
+            Packet::AddNode { .. } | Packet::Ready { .. } | Packet::PrepareState { .. } => {
+                self.setup = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// The seed for deterministic input interleaving, if enabled; see `Config::interleave_seed`.
+    pub fn interleave_seed(&self) -> Option<u64> {
+        self.interleave_seed
+    }
+
     pub fn booted(&mut self, addr: SocketAddr) {
         info!(self.log, "booted domain"; "nodes" => self.nodes.len());
         self.control_reply_tx
@@ -2805,9 +4449,40 @@ impl Domain {
     }
 
     pub fn on_event(&mut self, executor: &mut dyn Executor, event: PollEvent) -> ProcessResult {
+        let res = self.handle_event(executor, event);
+        // writes that were held are handled as if they had only just arrived
+        while let Some(packet) = self.released_writes.pop_front() {
+            self.handle_event(executor, PollEvent::Process(packet));
+        }
+        res
+    }
+
+    fn handle_event(&mut self, executor: &mut dyn Executor, event: PollEvent) -> ProcessResult {
+        let mut stamping;
+        let executor: &mut dyn Executor = if self.replay_checksums {
+            stamping = ChecksumReplays {
+                inner: executor,
+                corrupt_every: self.corrupt_replays_every,
+                sent: self.sent_replay_pieces.clone(),
+            };
+            &mut stamping
+        } else {
+            executor
+        };
         if self.wait_time.is_running() {
             self.wait_time.stop();
         }
+        let started = time::Instant::now();
+        if let PollEvent::Process(ref p) = event {
+            self.processed_packets += 1;
+            if let Some(ref mut schedule) = self.schedule {
+                use std::hash::{Hash, Hasher};
+                let mut h = std::collections::hash_map::DefaultHasher::new();
+                schedule.hash(&mut h);
+                format!("{:?}", p).hash(&mut h);
+                *schedule = h.finish();
+            }
+        }
         //self.total_time.start();
         //self.total_ptime.start();
         let res = match event {
@@ -2817,8 +4492,8 @@ impl Domain {
                 let opt1 = self
                     .buffered_replay_requests
                     .iter()
-                    .filter(|&(_, &(_, ref keys, _))| !keys.is_empty())
-                    .map(|(_, &(first, _, _))| {
+                    .filter(|&(_, &(_, ref keys, ..))| !keys.is_empty())
+                    .map(|(_, &(first, ..))| {
                         self.replay_batch_timeout
                             .checked_sub(now.duration_since(first))
                             .unwrap_or(time::Duration::from_millis(0))
@@ -2832,49 +4507,206 @@ impl Domain {
                         time::Duration::from_millis(0)
                     }
                 });
+                // while shedding load, we must wake up to notice that we're no longer overloaded
+                let opt4 = self
+                    .shedder
+                    .as_ref()
+                    .and_then(|s| s.duration_until_tick(now));
+                // the periodic work goes by the domain's clock, which tests may have moved ahead
+                let clock_now = self.clock.now();
+                let opt5 = self.next_reader_snapshot.map(|at| {
+                    if at > clock_now {
+                        at - clock_now
+                    } else {
+                        time::Duration::from_millis(0)
+                    }
+                });
+
+                let opt6 = self.next_statistics_report.map(|at| {
+                    if at > clock_now {
+                        at - clock_now
+                    } else {
+                        time::Duration::from_millis(0)
+                    }
+                });
 
-                let mut timeout = opt1.or(opt2).or(opt3);
+                let mut timeout = opt1.or(opt2).or(opt3).or(opt4).or(opt5).or(opt6);
                 if let Some(opt2) = opt2 {
                     timeout = Some(std::cmp::min(timeout.unwrap(), opt2));
                 }
                 if let Some(opt3) = opt3 {
                     timeout = Some(std::cmp::min(timeout.unwrap(), opt3));
                 }
+                if let Some(opt4) = opt4 {
+                    timeout = Some(std::cmp::min(timeout.unwrap(), opt4));
+                }
+                if let Some(opt5) = opt5 {
+                    timeout = Some(std::cmp::min(timeout.unwrap(), opt5));
+                }
+                if let Some(opt6) = opt6 {
+                    timeout = Some(std::cmp::min(timeout.unwrap(), opt6));
+                }
                 ProcessResult::KeepPolling(timeout)
             }
-            PollEvent::Process(packet) => {
+            PollEvent::Process(mut packet) if self.is_refused_write(&packet) => {
+                // the domain is about to be torn down, so the write would be lost if we took it.
+                if let Packet::Input { ref mut src, .. } = *packet {
+                    if let Some(src) = src.take() {
+                        executor.reject(src, WriteRejection::Draining);
+                    }
+                }
+                ProcessResult::Processed
+            }
+            PollEvent::Process(packet) if self.is_held_write(&packet) => {
+                // answered once the writes are released
+                self.held_writes.as_mut().unwrap().push_back(packet);
+                ProcessResult::Processed
+            }
+            PollEvent::Process(mut packet) if self.is_migrating_write(&packet) => {
+                // the client can send the write again once the migration has completed.
+                if let Packet::Input { ref mut src, .. } = *packet {
+                    if let Some(src) = src.take() {
+                        executor.reject(src, WriteRejection::MigrationInProgress);
+                    }
+                }
+                ProcessResult::Processed
+            }
+            PollEvent::Process(mut packet) if self.is_shed_write(&packet) => {
+                if let Packet::Input { ref mut src, .. } = *packet {
+                    if let Some(src) = src.take() {
+                        executor.reject(src, WriteRejection::Shed);
+                    }
+                }
+                self.shedder.as_mut().unwrap().rejected_writes += 1;
+                ProcessResult::Processed
+            }
+            PollEvent::Process(mut packet) => {
                 if let Packet::Quit = *packet {
                     return ProcessResult::StopPolling;
                 }
 
+                // writes that the base would ignore in part are turned away as a whole instead,
+                // so that the client learns that they were not applied.
+                if let Packet::Input {
+                    ref inner,
+                    ref mut src,
+                    ..
+                } = *packet
+                {
+                    let input = unsafe { inner.deref() };
+                    let why = self.nodes[input.dst]
+                        .borrow()
+                        .get_base()
+                        .and_then(|b| b.check(&input.data));
+                    if let Some(why) = why {
+                        if let Some(src) = src.take() {
+                            executor.reject(src, why);
+                        }
+                        return ProcessResult::Processed;
+                    }
+                }
+
+                // an all-or-nothing write must be checked against every write that came before
+                // it, so those have to be applied first.
+                let all_or_nothing = match *packet {
+                    Packet::Input { ref inner, .. } => unsafe { inner.deref() }.all_or_nothing,
+                    _ => false,
+                };
+                if all_or_nothing {
+                    let dst = packet.dst();
+                    if let Some(p) = self.group_commit_queues.flush(dst) {
+                        self.handle_batch(p, executor);
+                    }
+
+                    if let Packet::Input {
+                        ref inner,
+                        ref mut src,
+                        ..
+                    } = *packet
+                    {
+                        let input = unsafe { inner.deref() };
+                        let rejected = self.nodes[dst].borrow().get_base().unwrap().validate(
+                            dst,
+                            &input.data,
+                            &self.state,
+                        );
+                        if !rejected.is_empty() {
+                            if let Some(src) = src.take() {
+                                executor.reject(src, WriteRejection::Rows(rejected));
+                            }
+                            return ProcessResult::Processed;
+                        }
+                    }
+                }
+
+                // a base that is being resharded hands every write it accepts on to the base that
+                // replaces it.
+                if let Packet::Input { ref inner, .. } = *packet {
+                    let input = unsafe { inner.deref() };
+                    if let Some(to) = self.resharding.get(&input.dst) {
+                        let identity = input.identity.clone();
+                        Self::send_to_reshard_target(to, input.data.clone(), identity, executor);
+                    }
+                }
+
+                // audit writes before group commit merges them, since that loses track of which
+                // client each write came from.
+                if let Packet::Input { ref inner, .. } = *packet {
+                    let input = unsafe { inner.deref() };
+                    if let Some(b) = self.nodes[input.dst].borrow_mut().get_base_mut() {
+                        let now = self.clock.local();
+                        b.audit(input.identity.as_deref(), &input.data, now);
+                        if let Some(max_age) = self.audit_retention {
+                            b.expire_audit(max_age, now);
+                        }
+                    }
+                }
+
                 // TODO: Initialize tracer here, and when flushing group commit
                 // queue.
                 if self.group_commit_queues.should_append(&packet, &self.nodes) {
-                    if let Some(packet) = self.group_commit_queues.append(packet) {
-                        self.handle(packet, executor, true);
+                    // don't let later writes join an all-or-nothing write, since they haven't
+                    // been validated against it.
+                    let dst = packet.dst();
+                    let flushed = self.group_commit_queues.append(packet).or_else(|| {
+                        if all_or_nothing {
+                            self.group_commit_queues.flush(dst)
+                        } else {
+                            None
+                        }
+                    });
+                    if let Some(packet) = flushed {
+                        self.handle_batch(packet, executor);
                     }
                 } else {
                     self.handle(packet, executor, true);
                 }
 
                 while let Some(m) = self.group_commit_queues.flush_if_necessary() {
-                    self.handle(m, executor, true);
+                    self.handle_batch(m, executor);
                 }
 
+                self.check_watermarks(executor);
+                self.snapshot_readers_if_due();
+                self.report_statistics_if_due(executor);
                 ProcessResult::Processed
             }
             PollEvent::Timeout => {
                 while let Some(m) = self.group_commit_queues.flush_if_necessary() {
-                    self.handle(m, executor, true);
+                    self.handle_batch(m, executor);
                 }
 
                 if !self.buffered_replay_requests.is_empty() || !self.timed_purges.is_empty() {
                     self.handle(Box::new(Packet::Spin), executor, true);
                 }
 
+                self.check_watermarks(executor);
+                self.snapshot_readers_if_due();
+                self.report_statistics_if_due(executor);
                 ProcessResult::Processed
             }
         };
+        self.update_shedding(started.elapsed());
         if !self.wait_time.is_running() {
             self.wait_time.start();
         }
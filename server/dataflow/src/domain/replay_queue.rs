@@ -0,0 +1,73 @@
+//! Replay requests that a domain holds back until it has a free replay slot.
+
+use crate::prelude::*;
+use noria::ReplayPriority;
+use std::collections::VecDeque;
+
+/// Replay requests waiting for a free slot.
+///
+/// Requests are issued in order of decreasing priority, and requests of equal priority in the
+/// order they were made.
+#[derive(Debug, Default)]
+pub(super) struct ReplayRequestQueue {
+    queue: VecDeque<(Tag, Vec<Vec<DataType>>, ReplayPriority)>,
+}
+
+impl ReplayRequestQueue {
+    /// Queue a request behind all requests of the same or higher priority.
+    pub(super) fn push(&mut self, tag: Tag, keys: Vec<Vec<DataType>>, priority: ReplayPriority) {
+        // the common case is that everything has the same priority, in which case this appends.
+        let at = self
+            .queue
+            .iter()
+            .rposition(|&(_, _, p)| p >= priority)
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        self.queue.insert(at, (tag, keys, priority));
+    }
+
+    /// The priority of the request that is to be issued next, if any.
+    pub(super) fn next_priority(&self) -> Option<ReplayPriority> {
+        self.queue.front().map(|&(_, _, p)| p)
+    }
+
+    /// Take the request that is to be issued next.
+    pub(super) fn pop(&mut self) -> Option<(Tag, Vec<Vec<DataType>>, ReplayPriority)> {
+        self.queue.pop_front()
+    }
+
+    /// Whether every waiting request has the given priority.
+    pub(super) fn all(&self, priority: ReplayPriority) -> bool {
+        self.queue.iter().all(|&(_, _, p)| p == priority)
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.queue.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_priorities() {
+        let mut q = ReplayRequestQueue::default();
+        q.push(Tag(0), vec![vec![0.into()]], ReplayPriority::Batch);
+        q.push(Tag(1), vec![vec![1.into()]], ReplayPriority::Normal);
+        q.push(Tag(2), vec![vec![2.into()]], ReplayPriority::Interactive);
+        q.push(Tag(3), vec![vec![3.into()]], ReplayPriority::Normal);
+        q.push(Tag(4), vec![vec![4.into()]], ReplayPriority::Batch);
+        q.push(Tag(5), vec![vec![5.into()]], ReplayPriority::Interactive);
+        assert_eq!(q.len(), 6);
+        assert_eq!(q.next_priority(), Some(ReplayPriority::Interactive));
+
+        // higher priorities go first, and equal priorities go in the order they were requested
+        let order: Vec<_> = std::iter::from_fn(|| q.pop())
+            .map(|(tag, ..)| tag)
+            .collect();
+        assert_eq!(order, vec![Tag(2), Tag(5), Tag(1), Tag(3), Tag(0), Tag(4)]);
+        assert_eq!(q.next_priority(), None);
+        assert!(q.all(ReplayPriority::Batch));
+    }
+}
@@ -1,5 +1,8 @@
+use crate::pool::BufferPool;
 use crate::prelude::*;
+use crate::telemetry;
 use noria::internal::LocalOrNot;
+use std::cmp;
 use std::time;
 
 pub struct GroupCommitQueueSet {
@@ -7,17 +10,89 @@ pub struct GroupCommitQueueSet {
     #[allow(clippy::vec_box)]
     pending_packets: Map<(time::Instant, Vec<Box<Packet>>)>,
     params: PersistenceParameters,
+    /// Recycled buffers for the operations in merged packets.
+    buffers: BufferPool<TableOperation>,
+    /// If set, the longest each queue's window may grow to.
+    max_window: Option<time::Duration>,
+    /// The window of each queue whose window has adapted to its load.
+    windows: Map<time::Duration>,
+    /// How long the first packet waited, and how many packets there were, in the batch last
+    /// flushed from each queue whose window adapts.
+    flushed: Map<(time::Duration, usize)>,
+}
+
+/// The window of a queue after a batch of `packets` packets waited `waited` to be flushed under
+/// `window`, and then took `took` to process.
+///
+/// The window never shrinks below `min` or grows beyond `max`.
+fn adapt(
+    window: time::Duration,
+    (min, max): (time::Duration, time::Duration),
+    packets: usize,
+    waited: time::Duration,
+    took: time::Duration,
+) -> time::Duration {
+    let next = if packets <= 1 || waited + took > max {
+        // no other writes joined the batch, or its writes waited too long to be acknowledged
+        window / 2
+    } else {
+        // writes arrive faster than they can be processed one by one, so wait for more of them
+        cmp::max(window * 2, took)
+    };
+    cmp::min(cmp::max(next, min), max)
 }
 
 impl GroupCommitQueueSet {
     /// Create a new `GroupCommitQueue`.
-    pub fn new(params: &PersistenceParameters) -> Self {
+    ///
+    /// If `max_window` is set, the window of each queue starts out as `params.flush_timeout`, and
+    /// is then adjusted to how many packets arrive within it and how long their batches take to
+    /// process, without growing beyond `max_window`.
+    pub fn new(params: &PersistenceParameters, max_window: Option<time::Duration>) -> Self {
         Self {
             pending_packets: Map::default(),
             params: params.clone(),
+            buffers: BufferPool::default(),
+            max_window,
+            windows: Map::default(),
+            flushed: Map::default(),
         }
     }
 
+    /// How long the queue for `node` waits for more packets before it is flushed.
+    pub fn window(&self, node: LocalNodeIndex) -> time::Duration {
+        self.windows
+            .get(node)
+            .cloned()
+            .unwrap_or(self.params.flush_timeout)
+    }
+
+    /// The window of the queue for `node`, if it adapts to the queue's load.
+    pub fn adaptive_window(&self, node: LocalNodeIndex) -> Option<time::Duration> {
+        self.max_window.map(|_| self.window(node))
+    }
+
+    /// Adjust the window of the queue for `node` now that the batch last flushed from it took
+    /// `took` to process.
+    pub fn processed(&mut self, node: LocalNodeIndex, took: time::Duration) {
+        let max = match self.max_window {
+            Some(max) => max,
+            None => return,
+        };
+        let (waited, packets) = match self.flushed.remove(node) {
+            Some(flushed) => flushed,
+            None => return,
+        };
+        let window = adapt(
+            self.window(node),
+            (self.params.flush_timeout, max),
+            packets,
+            waited,
+            took,
+        );
+        self.windows.insert(node, window);
+    }
+
     /// Returns whether the given packet should be persisted.
     pub fn should_append(&self, p: &Packet, nodes: &DomainNodes) -> bool {
         if let Packet::Input { .. } = *p {
@@ -31,11 +106,12 @@ impl GroupCommitQueueSet {
     /// Find the first queue that has timed out waiting for more packets, and flush it to disk.
     pub fn flush_if_necessary(&mut self) -> Option<Box<Packet>> {
         let now = time::Instant::now();
-        let to = self.params.flush_timeout;
         let node = self
             .pending_packets
             .iter()
-            .find(|(_, &(first, ref ps))| now.duration_since(first) >= to && !ps.is_empty())
+            .find(|&(n, &(first, ref ps))| {
+                now.duration_since(first) >= self.window(n) && !ps.is_empty()
+            })
             .map(|(n, _)| n);
 
         if let Some(node) = node {
@@ -45,9 +121,41 @@ impl GroupCommitQueueSet {
         }
     }
 
+    /// Merge the pending packets of every queue, however long they have been waiting.
+    pub fn flush_all(&mut self) -> Vec<Box<Packet>> {
+        let nodes: Vec<_> = self
+            .pending_packets
+            .iter()
+            .filter(|(_, &(_, ref ps))| !ps.is_empty())
+            .map(|(n, _)| n)
+            .collect();
+        nodes
+            .into_iter()
+            .filter_map(|node| self.flush_internal(node))
+            .collect()
+    }
+
+    /// Merge the pending packets for `node`, however long they have been waiting.
+    pub fn flush(&mut self, node: LocalNodeIndex) -> Option<Box<Packet>> {
+        if self.pending_packets.contains_key(node) {
+            self.flush_internal(node)
+        } else {
+            None
+        }
+    }
+
     /// Merge any pending packets.
     fn flush_internal(&mut self, node: LocalNodeIndex) -> Option<Box<Packet>> {
-        Self::merge_packets(&mut self.pending_packets[node].1)
+        let (first, ref mut packets) = self.pending_packets[node];
+        if self.max_window.is_some() && !packets.is_empty() {
+            self.flushed.insert(node, (first.elapsed(), packets.len()));
+        }
+        Self::merge_packets(packets, &mut self.buffers)
+    }
+
+    /// The number of operation buffers that were reused and freshly allocated, respectively.
+    pub fn buffer_stats(&self) -> (u64, u64) {
+        (self.buffers.reused(), self.buffers.allocated())
     }
 
     /// Add a new packet to be persisted, and if this triggered a flush return an iterator over the
@@ -64,7 +172,8 @@ impl GroupCommitQueueSet {
         }
 
         pp.1.push(p);
-        if pp.0.elapsed() >= self.params.flush_timeout {
+        let waited = pp.0.elapsed();
+        if waited >= self.window(node) {
             self.flush_internal(node)
         } else {
             None
@@ -74,18 +183,20 @@ impl GroupCommitQueueSet {
     /// Returns how long until a flush should occur.
     pub fn duration_until_flush(&self) -> Option<time::Duration> {
         self.pending_packets
-            .values()
-            .filter(|(_, ps)| !ps.is_empty())
-            .map(|p| {
-                self.params
-                    .flush_timeout
+            .iter()
+            .filter(|(_, (_, ps))| !ps.is_empty())
+            .map(|(n, p)| {
+                self.window(n)
                     .checked_sub(p.0.elapsed())
                     .unwrap_or(time::Duration::from_millis(0))
             })
             .min()
     }
 
-    fn merge_committed_packets<I>(packets: I) -> Option<Box<Packet>>
+    fn merge_committed_packets<I>(
+        packets: I,
+        buffers: &mut BufferPool<TableOperation>,
+    ) -> Option<Box<Packet>>
     where
         I: Iterator<Item = Box<Packet>>,
     {
@@ -93,45 +204,92 @@ impl GroupCommitQueueSet {
         let merged_dst = packets.peek().as_mut().unwrap().dst();
 
         let mut all_senders = vec![];
-        let merged_data = packets.fold(Vec::new(), |mut acc, p| {
+        let mut traces = vec![];
+        let merged_data = packets.fold(buffers.take(), |mut acc, p| {
             match *p {
                 Packet::Input {
                     inner,
                     src,
                     senders,
+                    trace,
                 } => {
-                    let Input { dst, data } = unsafe { inner.take() };
+                    let Input { dst, mut data, .. } = unsafe { inner.take() };
 
                     assert_eq!(senders.len(), 0);
                     assert_eq!(merged_dst, dst);
-                    acc.extend(data);
+                    acc.extend(data.drain(..));
+                    buffers.recycle(data);
 
                     if let Some(src) = src {
                         all_senders.push(src);
                     }
+                    traces.extend(trace);
                 }
                 _ => unreachable!(),
             }
             acc
         });
 
+        // the merged write is processed on behalf of every traced write that went into it
+        let trace = match traces.len() {
+            0 | 1 => traces.pop(),
+            _ => {
+                let span = telemetry::Span::linked("group-commit", Some(traces[0]), &traces[1..]);
+                span.set("writes", traces.len());
+                span.context().or(Some(traces[0]))
+            }
+        };
+
         Some(Box::new(Packet::Input {
             inner: LocalOrNot::new(Input {
                 dst: merged_dst,
                 data: merged_data,
+                // writes are audited before they are merged
+                identity: None,
+                trace: None,
+                // all-or-nothing writes are checked before they are merged, and never merged with
+                // later writes
+                all_or_nothing: false,
             }),
             src: None,
             senders: all_senders,
+            trace,
         }))
     }
 
     /// Merge the contents of packets into a single packet, emptying packets in the process.
     #[allow(clippy::vec_box)]
-    fn merge_packets(packets: &mut Vec<Box<Packet>>) -> Option<Box<Packet>> {
+    fn merge_packets(
+        packets: &mut Vec<Box<Packet>>,
+        buffers: &mut BufferPool<TableOperation>,
+    ) -> Option<Box<Packet>> {
         if packets.is_empty() {
             return None;
         }
 
-        Self::merge_committed_packets(packets.drain(..))
+        Self::merge_committed_packets(packets.drain(..), buffers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_adapts_to_batches() {
+        let ms = time::Duration::from_millis;
+        let bounds = (ms(1), ms(16));
+
+        // batches that other writes join and that are acknowledged in time widen the window
+        assert_eq!(adapt(ms(1), bounds, 4, ms(1), ms(1)), ms(2));
+        // to at least as long as a batch takes to process
+        assert_eq!(adapt(ms(2), bounds, 4, ms(2), ms(6)), ms(6));
+        // but not beyond the bound
+        assert_eq!(adapt(ms(12), bounds, 4, ms(1), ms(1)), ms(16));
+
+        // waiting alone, or for too long, narrows it again
+        assert_eq!(adapt(ms(8), bounds, 1, ms(8), ms(1)), ms(4));
+        assert_eq!(adapt(ms(8), bounds, 4, ms(8), ms(10)), ms(4));
+        assert_eq!(adapt(ms(1), bounds, 1, ms(1), ms(1)), ms(1));
     }
 }
@@ -19,23 +19,34 @@ pub mod ops;
 pub mod payload; // it makes me _really_ sad that this has to be pub
 pub mod prelude;
 pub(crate) mod state;
+pub mod telemetry;
 
+mod clock;
 mod domain;
 mod group_commit;
+mod pool;
 mod processing;
+mod shedding;
+mod sketch;
+mod watermark;
 
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::time;
 
-pub use crate::backlog::SingleReadHandle;
+pub use crate::backlog::{ReadMask, SingleReadHandle};
+pub use crate::clock::Clock;
 pub type Readers =
     Arc<Mutex<HashMap<(petgraph::graph::NodeIndex, usize), backlog::SingleReadHandle>>>;
 pub type DomainConfig = domain::Config;
 
 pub use crate::domain::{Domain, DomainBuilder, Index, PollEvent, ProcessResult};
 pub use crate::payload::Packet;
+pub use crate::shedding::{LoadSheddingPolicy, ShedAction};
+pub use crate::watermark::{
+    DefaultWatermarkPolicy, OperatorKind, WatermarkAction, WatermarkCrossing, WatermarkPolicy,
+};
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum Sharding {
@@ -128,4 +139,34 @@ impl PersistenceParameters {
     }
 }
 
-pub use noria::shard_by;
+/// Verify, and optionally repair, the rows that shard `shard` of the base table `base` keeps on
+/// disk, while no worker is running it.
+///
+/// The base's files are looked up in the current directory, as they are with
+/// `DurabilityMode::Permanent`. See `noria::BaseVerification` for what is checked. Returns `None`
+/// if the shard has no files on disk.
+///
+/// Panics if the files are in use by a running worker.
+pub fn verify_base_offline(
+    base: &str,
+    shard: usize,
+    params: &PersistenceParameters,
+    repair: bool,
+) -> Option<noria::BaseVerification> {
+    use crate::state::{PersistentState, State};
+
+    let name = format!("{}-{}-{}", params.log_prefix, base, shard);
+    if !std::path::Path::new(&format!("{}.db", name)).exists() {
+        return None;
+    }
+
+    let params = PersistenceParameters {
+        mode: DurabilityMode::Permanent,
+        ..params.clone()
+    };
+    let mut report = PersistentState::new(name, None, &params).verify(repair)?;
+    report.shard = shard;
+    Some(report)
+}
+
+pub use noria::{shard_by, shard_by_multi, shard_by_range, shard_by_weight};
@@ -90,6 +90,11 @@ impl DanglingDomainNode {
             .filter(|&c| !graph[c].is_source() && graph[c].domain() == dm)
             .map(|ni| graph[ni].local_addr())
             .collect();
+        if let NodeType::Internal(ops::NodeOperator::Cascade(ref mut c)) = n.inner {
+            // cascades write straight into their child table, wherever it ended up
+            let child = &graph[c.child()];
+            c.set_target(child.domain(), child.local_addr(), child.sharded_by());
+        }
         n
     }
 }
@@ -215,7 +220,7 @@ impl Node {
 
 // derefs
 impl Node {
-    pub(crate) fn with_sharder_mut<F>(&mut self, f: F)
+    pub fn with_sharder_mut<F>(&mut self, f: F)
     where
         F: FnOnce(&mut special::Sharder),
     {
@@ -382,6 +387,22 @@ impl Node {
         }
     }
 
+    pub fn get_cascade(&self) -> Option<&ops::cascade::Cascade> {
+        if let NodeType::Internal(NodeOperator::Cascade(ref c)) = self.inner {
+            Some(c)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_cascade_mut(&mut self) -> Option<&mut ops::cascade::Cascade> {
+        if let NodeType::Internal(NodeOperator::Cascade(ref mut c)) = self.inner {
+            Some(c)
+        } else {
+            None
+        }
+    }
+
     pub fn add_to(&mut self, domain: domain::Index) {
         assert_eq!(self.domain, None);
         assert!(!self.is_dropped());
@@ -466,6 +487,14 @@ impl Node {
         }
     }
 
+    pub fn is_cascade(&self) -> bool {
+        if let NodeType::Internal(NodeOperator::Cascade(..)) = self.inner {
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn is_shard_merger(&self) -> bool {
         if let NodeType::Internal(NodeOperator::Union(ref u)) = self.inner {
             u.is_shard_merger()
@@ -27,11 +27,16 @@ impl Node {
             }
             NodeType::Base(ref mut b) => {
                 // NOTE: bases only accept BaseOperations
-                match m.take().map(|p| *p) {
-                    Some(Packet::Input {
-                        inner, mut senders, ..
-                    }) => {
-                        let Input { dst, data } = unsafe { inner.take() };
+                // we re-use the incoming packet's allocation for the outgoing message
+                let mut p = m.take().unwrap();
+                match mem::replace(&mut *p, Packet::Spin) {
+                    Packet::Input {
+                        inner,
+                        mut senders,
+                        trace,
+                        ..
+                    } => {
+                        let Input { dst, data, .. } = unsafe { inner.take() };
                         let mut rs = b.process(addr, data, &*state);
 
                         // When a replay originates at a base node, we replay the data *through* that
@@ -42,23 +47,29 @@ impl Node {
                         //
                         // So: only materialize if the message we're processing is not a replay!
                         if keyed_by.is_none() {
+                            let seq = b.next_commit_seq();
+                            if let Some(s) = state.get_mut(addr) {
+                                s.set_commit_seq(seq);
+                            }
                             materialize(&mut rs, None, state.get_mut(addr));
                         }
 
                         // Send write-ACKs to all the clients with updates that made
                         // it into this merged packet:
-                        senders.drain(..).for_each(|src| ex.ack(src));
+                        let seq = b.commit_seq();
+                        senders.drain(..).for_each(|src| ex.ack(src, seq));
 
-                        *m = Some(Box::new(Packet::Message {
+                        *p = Packet::Message {
                             link: Link::new(dst, dst),
                             data: rs,
-                        }));
+                            trace,
+                        };
+                        *m = Some(p);
                     }
-                    Some(ref p) => {
+                    p => {
                         // TODO: replays?
                         unreachable!("base received non-input packet {:?}", p);
                     }
-                    None => unreachable!(),
                 }
             }
             NodeType::Reader(ref mut r) => {
@@ -88,6 +99,7 @@ impl Node {
                                     ref mut for_keys,
                                     unishard,
                                     ignore,
+                                    ..
                                 },
                             ..
                         },) => {
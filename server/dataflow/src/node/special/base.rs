@@ -1,8 +1,9 @@
 use crate::prelude::*;
-use noria::{Modification, Operation, TableOperation};
+use noria::{AuditEntry, AuditOperation, Modification, Operation, TableOperation, WriteRejection};
 use std::borrow::Cow;
-use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::cmp::{self, Ordering};
+use std::collections::{HashMap, VecDeque};
+use std::time;
 use vec_map::VecMap;
 
 /// Base is used to represent the root nodes of the Noria data flow graph.
@@ -16,7 +17,39 @@ pub struct Base {
 
     defaults: Vec<DataType>,
     dropped: Vec<usize>,
+    #[serde(default)]
+    widened: Vec<usize>,
+    /// The maximum size, in bytes, of the values of each column, if limited.
+    #[serde(default)]
+    size_limits: Vec<Option<usize>>,
+    /// The columns that hold the start and end of the period in which each row is valid.
+    #[serde(default)]
+    valid_time: Option<(usize, usize)>,
+    /// The column that marks soft-deleted rows, if deletes only mark rows.
+    #[serde(default)]
+    soft_delete: Option<usize>,
+    /// The split points between the ranges of keys each shard owns, if shards own ranges of keys.
+    #[serde(default)]
+    shard_ranges: Vec<DataType>,
     unmodified: bool,
+
+    audit: Option<AuditLog>,
+    /// Sequence number of the last batch of writes committed to this base.
+    #[serde(default)]
+    commit_seq: u64,
+}
+
+/// A bounded, in-memory log of the writes a base table has received.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct AuditLog {
+    capacity: usize,
+    entries: VecDeque<AuditEntry>,
+    /// Number of entries discarded because the log was full.
+    #[serde(default)]
+    truncated: u64,
+    /// Number of entries discarded because they were older than the retention period.
+    #[serde(default)]
+    expired: u64,
 }
 
 impl Base {
@@ -33,6 +66,197 @@ impl Base {
         self
     }
 
+    /// Builder that limits the values of `column` to at most `limit` bytes.
+    ///
+    /// The limit applies to string and binary values. Writes that would store a larger value are
+    /// rejected by `Table`, and discarded by the base if they are sent some other way.
+    pub fn with_size_limit(mut self, column: usize, limit: usize) -> Base {
+        if self.size_limits.len() <= column {
+            self.size_limits.resize(column + 1, None);
+        }
+        self.size_limits[column] = Some(limit);
+        self
+    }
+
+    /// The maximum size, in bytes, of the values of each column. Columns past the end are not
+    /// limited.
+    pub fn get_size_limits(&self) -> &[Option<usize>] {
+        &self.size_limits[..]
+    }
+
+    /// Builder that marks `from` and `to` as the columns that hold the start (inclusive) and end
+    /// (exclusive) of the period in which each row is valid.
+    ///
+    /// Both columns should hold timestamps, or `NULL` for a period that is open-ended on that
+    /// side. Views whose rows carry both columns only return the rows that are valid at the time
+    /// of the read, unless told otherwise with `View::set_valid_time`. Inserts whose period ends
+    /// before it starts are discarded.
+    pub fn with_valid_time(mut self, from: usize, to: usize) -> Base {
+        assert_ne!(from, to);
+        self.valid_time = Some((from, to));
+        self
+    }
+
+    /// The columns that hold the start and end of the period in which each row is valid, if any.
+    pub fn valid_time(&self) -> Option<(usize, usize)> {
+        self.valid_time
+    }
+
+    /// Builder that makes deletes mark rows as deleted in `column`, rather than remove them.
+    ///
+    /// The base sets `column` to 0 for every row it inserts, and deleting the row sets it to 1.
+    /// Inserting a row with the same key as a deleted row brings it back, while updates to
+    /// deleted rows are ignored like updates to rows that do not exist. This requires the base to
+    /// have a primary key.
+    ///
+    /// Deleted rows still flow to the nodes below the base, with `column` set to 1. Queries over
+    /// tables declared with `SOFT DELETE` in a recipe filter them out right below the base, unless
+    /// the query is declared `WITH DELETED`.
+    pub fn with_soft_delete(mut self, column: usize) -> Base {
+        self.soft_delete = Some(column);
+        self
+    }
+
+    /// The column that marks soft-deleted rows, if deletes only mark rows in this base.
+    pub fn soft_delete(&self) -> Option<usize> {
+        self.soft_delete
+    }
+
+    /// Builder that has each shard of the base own the range of keys between two adjacent split
+    /// points in `ranges`, instead of the keys that hash to it.
+    ///
+    /// There must be one split point fewer than the base has shards. The nodes below the base
+    /// spread its rows by their hash again.
+    pub fn with_shard_ranges(mut self, ranges: Vec<DataType>) -> Base {
+        self.shard_ranges = ranges;
+        self
+    }
+
+    /// The split points between the ranges of keys each shard of this base owns, if any.
+    pub fn shard_ranges(&self) -> &[DataType] {
+        &self.shard_ranges[..]
+    }
+
+    /// Whether `row` has been soft-deleted.
+    fn is_deleted(&self, row: &[DataType]) -> bool {
+        match self.soft_delete.and_then(|col| row.get(col)) {
+            Some(v) => !v.is_none() && *v != DataType::from(0),
+            None => false,
+        }
+    }
+
+    /// Builder that enables the audit log for this base, retaining at most `capacity` entries.
+    ///
+    /// Once the log is full, the oldest entries are discarded first.
+    pub fn with_audit(mut self, capacity: usize) -> Base {
+        assert_ne!(capacity, 0);
+        self.audit = Some(AuditLog {
+            capacity,
+            entries: VecDeque::new(),
+            truncated: 0,
+            expired: 0,
+        });
+        self
+    }
+
+    /// Whether this base keeps an audit log of its writes.
+    pub fn is_audited(&self) -> bool {
+        self.audit.is_some()
+    }
+
+    /// The current contents of this base's audit log, oldest entry first.
+    pub fn audit_log(&self) -> Option<Vec<AuditEntry>> {
+        self.audit
+            .as_ref()
+            .map(|a| a.entries.iter().cloned().collect())
+    }
+
+    /// Record the given writes, performed by `identity` at `timestamp`, in this base's audit log.
+    ///
+    /// This is a no-op if auditing is not enabled for this base.
+    pub(crate) fn audit(
+        &mut self,
+        identity: Option<&str>,
+        ops: &[TableOperation],
+        timestamp: chrono::NaiveDateTime,
+    ) {
+        let audit = match self.audit {
+            Some(ref mut audit) => audit,
+            None => return,
+        };
+
+        for op in ops {
+            let key = match self.primary_key {
+                Some(ref key_cols) => key_of(key_cols, op).cloned().collect(),
+                None => op.row().map(Vec::from).unwrap_or_default(),
+            };
+
+            if audit.entries.len() == audit.capacity {
+                audit.entries.pop_front();
+                audit.truncated += 1;
+            }
+            audit.entries.push_back(AuditEntry {
+                identity: identity.map(String::from),
+                timestamp,
+                operation: AuditOperation::from(op),
+                key,
+            });
+        }
+    }
+
+    /// Discard the entries in this base's audit log that are older than `max_age` as of `now`.
+    pub(crate) fn expire_audit(&mut self, max_age: time::Duration, now: chrono::NaiveDateTime) {
+        let audit = match self.audit {
+            Some(ref mut audit) => audit,
+            None => return,
+        };
+
+        let max_age =
+            chrono::Duration::from_std(max_age).unwrap_or_else(|_| chrono::Duration::max_value());
+        let cutoff = match now.checked_sub_signed(max_age) {
+            Some(cutoff) => cutoff,
+            None => return,
+        };
+        // entries are appended as writes arrive, so they are ordered by timestamp
+        while audit
+            .entries
+            .front()
+            .map(|e| e.timestamp < cutoff)
+            .unwrap_or(false)
+        {
+            audit.entries.pop_front();
+            audit.expired += 1;
+        }
+    }
+
+    /// The sequence number of the last batch of writes committed to this base, or 0 if no writes
+    /// have been committed yet.
+    pub fn commit_seq(&self) -> u64 {
+        self.commit_seq
+    }
+
+    /// Number the next batch of writes committed to this base.
+    pub(crate) fn next_commit_seq(&mut self) -> u64 {
+        self.commit_seq += 1;
+        self.commit_seq
+    }
+
+    /// Continue numbering batches after `seq`, which was recovered from this base's durable state.
+    pub(crate) fn restore_commit_seq(&mut self, seq: u64) {
+        self.commit_seq = cmp::max(self.commit_seq, seq);
+    }
+
+    /// Statistics about this base's audit log, if it has one.
+    pub(crate) fn probe(&self) -> HashMap<String, String> {
+        let mut hm = HashMap::new();
+        if let Some(ref audit) = self.audit {
+            hm.insert("audit_entries".into(), format!("{}", audit.entries.len()));
+            hm.insert("audit_truncated".into(), format!("{}", audit.truncated));
+            hm.insert("audit_expired".into(), format!("{}", audit.expired));
+        }
+        hm
+    }
+
     pub fn key(&self) -> Option<&[usize]> {
         self.primary_key.as_ref().map(|cols| &cols[..])
     }
@@ -57,6 +281,13 @@ impl Base {
              setting default values for initial columns"
         );
         assert!(column < self.defaults.len());
+        assert!(
+            self.valid_time
+                .map(|(from, to)| column != from && column != to)
+                .unwrap_or(true),
+            "cannot drop valid-time column {}",
+            column
+        );
         self.unmodified = false;
 
         // note that we don't need to *do* anything for dropped columns when we receive records.
@@ -65,6 +296,48 @@ impl Base {
         self.dropped.push(column);
     }
 
+    /// Widen an integer column of this base node to its 64-bit counterpart.
+    ///
+    /// Records that were written before the column was widened, whether they are still in flight
+    /// or have already been persisted, are widened as they leave the base. Since integers of
+    /// different widths compare and hash equal, existing downstream state remains consistent with
+    /// the widened records without having to be rebuilt.
+    ///
+    /// Primary key columns cannot be widened, since persisted rows are looked up by the encoding of
+    /// their key.
+    pub fn widen_column(&mut self, column: usize) {
+        assert!(
+            self.primary_key
+                .as_ref()
+                .map(|key| !key.contains(&column))
+                .unwrap_or(true),
+            "cannot widen primary key column {}",
+            column
+        );
+        if !self.widened.contains(&column) {
+            self.widened.push(column);
+        }
+        self.unmodified = false;
+    }
+
+    /// The columns of this base node that have been widened.
+    pub fn get_widened(&self) -> &[usize] {
+        &self.widened[..]
+    }
+
+    /// Widen the given integer columns of `row` to their 64-bit counterparts.
+    pub(crate) fn widen_row(widened: &[usize], row: &mut [DataType]) {
+        for &col in widened {
+            match row.get(col) {
+                Some(&DataType::Int(n)) => row[col] = DataType::BigInt(i64::from(n)),
+                Some(&DataType::UnsignedInt(n)) => {
+                    row[col] = DataType::UnsignedBigInt(u64::from(n))
+                }
+                _ => {}
+            }
+        }
+    }
+
     pub fn get_dropped(&self) -> VecMap<DataType> {
         self.dropped
             .iter()
@@ -72,6 +345,31 @@ impl Base {
             .collect()
     }
 
+    /// A base with the same configuration as this one, but none of its writes.
+    pub fn duplicate(&self) -> Base {
+        let mut base = self.clone();
+        if let Some(ref mut audit) = base.audit {
+            audit.entries.clear();
+            audit.truncated = 0;
+            audit.expired = 0;
+        }
+        base.commit_seq = 0;
+        base
+    }
+
+    /// The writes that recreate `row`, which this base holds, in a duplicate of this base.
+    pub(crate) fn writes_for(&self, mut row: Vec<DataType>) -> Vec<TableOperation> {
+        self.fix(&mut row);
+        if self.is_deleted(&row) {
+            // inserting a row brings it back, so it has to be deleted again.
+            let key = self.primary_key.as_ref().unwrap();
+            let key = key.iter().map(|&c| row[c].clone()).collect();
+            vec![TableOperation::Insert(row), TableOperation::Delete { key }]
+        } else {
+            vec![TableOperation::Insert(row)]
+        }
+    }
+
     pub(crate) fn fix(&self, row: &mut Vec<DataType>) {
         if self.unmodified {
             return;
@@ -81,6 +379,7 @@ impl Base {
             let rlen = row.len();
             row.extend(self.defaults.iter().skip(rlen).cloned());
         }
+        Self::widen_row(&self.widened, row);
     }
 }
 
@@ -94,7 +393,15 @@ impl Clone for Base {
 
             defaults: self.defaults.clone(),
             dropped: self.dropped.clone(),
+            widened: self.widened.clone(),
+            size_limits: self.size_limits.clone(),
+            valid_time: self.valid_time,
+            soft_delete: self.soft_delete,
+            shard_ranges: self.shard_ranges.clone(),
             unmodified: self.unmodified,
+
+            audit: self.audit.clone(),
+            commit_seq: self.commit_seq,
         }
     }
 }
@@ -106,7 +413,15 @@ impl Default for Base {
 
             defaults: Vec::new(),
             dropped: Vec::new(),
+            widened: Vec::new(),
+            size_limits: Vec::new(),
+            valid_time: None,
+            soft_delete: None,
+            shard_ranges: Vec::new(),
             unmodified: true,
+
+            audit: None,
+            commit_seq: 0,
         }
     }
 }
@@ -132,12 +447,144 @@ impl Base {
         Clone::clone(self)
     }
 
+    /// Check for operations among `ops` that this base will never apply, whatever its state.
+    ///
+    /// The domain rejects writes that contain such operations as a whole, so that clients learn
+    /// that they were not applied.
+    pub(crate) fn check(&self, ops: &[TableOperation]) -> Option<WriteRejection> {
+        if !self.size_limits.is_empty() {
+            let too_large = ops
+                .iter()
+                .filter_map(|op| op.exceeds_size_limits(&self.size_limits))
+                .next();
+            if let Some((col, len)) = too_large {
+                let limit = self.size_limits[col].unwrap();
+                return Some(WriteRejection::ValueTooLarge(col, len, limit));
+            }
+        }
+
+        let inverted: Vec<_> = ops
+            .iter()
+            .enumerate()
+            .filter_map(|(i, op)| {
+                let (f, t) = self.inverted_period(op)?;
+                Some((i, format!("row is valid from {} to {}", f, t)))
+            })
+            .collect();
+        if inverted.is_empty() {
+            None
+        } else {
+            Some(WriteRejection::Rows(inverted))
+        }
+    }
+
+    /// The period that the row `op` inserts is valid for, if that period ends before it begins.
+    fn inverted_period<'a>(&self, op: &'a TableOperation) -> Option<(&'a DataType, &'a DataType)> {
+        let (from, to) = self.valid_time?;
+        match *op {
+            TableOperation::Insert(ref row) | TableOperation::InsertOrUpdate { ref row, .. } => {
+                match (row.get(from), row.get(to)) {
+                    (Some(f), Some(t)) if !f.is_none() && !t.is_none() && t < f => Some((f, t)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Check that every one of `ops` can be applied, as is required of all-or-nothing writes.
+    ///
+    /// Returns the index of each operation that `process` would ignore, along with why. That is
+    /// the case for operations that exceed a size limit or are valid for a negative period, and
+    /// for those that insert a key that exists, or delete or update one that does not, once the
+    /// operations before them are applied.
+    pub(crate) fn validate(
+        &self,
+        us: LocalNodeIndex,
+        ops: &[TableOperation],
+        state: &StateMap,
+    ) -> Vec<(usize, String)> {
+        let mut rejected = Vec::new();
+        let mut exists: HashMap<Vec<DataType>, bool> = HashMap::new();
+        for (i, op) in ops.iter().enumerate() {
+            if let Some((col, len)) = op.exceeds_size_limits(&self.size_limits) {
+                let limit = self.size_limits[col].unwrap();
+                rejected.push((
+                    i,
+                    format!(
+                        "value for column {} is {} bytes, which exceeds its limit of {} bytes",
+                        col, len, limit
+                    ),
+                ));
+                continue;
+            }
+
+            if let Some((f, t)) = self.inverted_period(op) {
+                rejected.push((i, format!("row is valid from {} to {}", f, t)));
+                continue;
+            }
+
+            let key_cols = match self.primary_key {
+                Some(ref key_cols) => &key_cols[..],
+                None => continue,
+            };
+            let key: Vec<_> = key_of(key_cols, op).cloned().collect();
+            let present = *exists.entry(key.clone()).or_insert_with(|| {
+                let db = state
+                    .get(us)
+                    .expect("base with primary key must be materialized");
+                match db.lookup(key_cols, &KeyType::from(&key[..])) {
+                    LookupResult::Some(rows) => rows.iter().any(|r| !self.is_deleted(r)),
+                    LookupResult::Missing => unreachable!(),
+                }
+            });
+            let (ok, present_after) = match *op {
+                TableOperation::Insert(..) => (!present, true),
+                TableOperation::Delete { .. } => (present, false),
+                TableOperation::Update { .. } => (present, true),
+                TableOperation::InsertOrUpdate { .. } => (true, true),
+            };
+            if ok {
+                exists.insert(key, present_after);
+            } else if present {
+                rejected.push((i, format!("a row with key {:?} already exists", key)));
+            } else {
+                rejected.push((i, format!("there is no row with key {:?}", key)));
+            }
+        }
+        rejected
+    }
+
     pub(in crate::node) fn process(
         &mut self,
         us: LocalNodeIndex,
         mut ops: Vec<TableOperation>,
         state: &StateMap,
     ) -> Records {
+        if !self.size_limits.is_empty() {
+            let limits = &self.size_limits;
+            ops.retain(|op| match op.exceeds_size_limits(limits) {
+                Some((col, len)) => {
+                    eprintln!(
+                        "base ignoring write of {} bytes to column {} since it exceeds its limit",
+                        len, col
+                    );
+                    false
+                }
+                None => true,
+            });
+        }
+
+        if self.valid_time.is_some() {
+            ops.retain(|op| match self.inverted_period(op) {
+                Some((f, t)) => {
+                    eprintln!("base ignoring row that is valid from {} to {}", f, t);
+                    false
+                }
+                None => true,
+            });
+        }
+
         if self.primary_key.is_none() || ops.is_empty() {
             return ops
                 .into_iter()
@@ -199,6 +646,43 @@ impl Base {
                 was = current.clone();
             }
 
+            if let Some(col) = self.soft_delete {
+                let live = current
+                    .as_ref()
+                    .map(|r| !self.is_deleted(r))
+                    .unwrap_or(false);
+                match op {
+                    TableOperation::Insert(mut row)
+                    | TableOperation::InsertOrUpdate { mut row, .. }
+                        if !live =>
+                    {
+                        // this also brings back a soft-deleted row with the same key
+                        if let Some(v) = row.get_mut(col) {
+                            *v = 0.into();
+                        }
+                        current = Some(Cow::Owned(row));
+                        continue;
+                    }
+                    TableOperation::Insert(row) => {
+                        eprintln!("base ignoring {:?} since it already has {:?}", row, current);
+                        continue;
+                    }
+                    TableOperation::Delete { .. } => {
+                        if live {
+                            let mut row = current.take().unwrap().into_owned();
+                            row[col] = 1.into();
+                            current = Some(Cow::Owned(row));
+                        }
+                        continue;
+                    }
+                    TableOperation::Update { .. } if !live => {
+                        // updates to soft-deleted rows are ignored
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+
             let update = match op {
                 TableOperation::Insert(row) => {
                     if let Some(ref was) = was {
@@ -307,6 +791,305 @@ mod tests {
         assert_eq!(b.unmodified, true);
     }
 
+    #[test]
+    fn audit_log_is_bounded() {
+        let mut b = Base::new(vec![]).with_key(vec![0]).with_audit(2);
+        assert!(b.is_audited());
+
+        let now = chrono::Local::now().naive_local();
+        b.audit(
+            Some("alice"),
+            &[
+                TableOperation::Insert(vec![1.into(), "a".into()]),
+                TableOperation::Delete {
+                    key: vec![1.into()],
+                },
+            ],
+            now,
+        );
+        b.audit(
+            None,
+            &[TableOperation::Update {
+                key: vec![2.into()],
+                set: vec![],
+            }],
+            now,
+        );
+
+        let log = b.audit_log().unwrap();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[0].identity, Some("alice".to_owned()));
+        assert_eq!(log[0].operation, AuditOperation::Delete);
+        assert_eq!(log[0].key, vec![DataType::from(1)]);
+        assert_eq!(log[1].identity, None);
+        assert_eq!(log[1].operation, AuditOperation::Update);
+        assert_eq!(log[1].key, vec![DataType::from(2)]);
+
+        assert!(Base::default().audit_log().is_none());
+        assert_eq!(b.probe()["audit_truncated"], "1");
+    }
+
+    #[test]
+    fn audit_log_expires() {
+        let mut b = Base::new(vec![]).with_key(vec![0]).with_audit(10);
+        let start = chrono::Local::now().naive_local();
+        b.audit(None, &[TableOperation::Insert(vec![1.into()])], start);
+        b.expire_audit(time::Duration::from_secs(60), start);
+        assert_eq!(b.audit_log().unwrap().len(), 1);
+
+        let later = start + chrono::Duration::milliseconds(20);
+        b.audit(None, &[TableOperation::Insert(vec![2.into()])], later);
+        b.expire_audit(time::Duration::from_millis(10), later);
+        let log = b.audit_log().unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].key, vec![DataType::from(2)]);
+        assert_eq!(b.probe()["audit_expired"], "1");
+        assert_eq!(b.probe()["audit_truncated"], "0");
+    }
+
+    #[test]
+    fn widened_columns() {
+        let mut b = Base::new(vec![0.into(), 0.into(), DataType::None]).with_key(vec![0]);
+        b.widen_column(1);
+        b.add_column(DataType::None);
+
+        // an old, narrow write that also predates the added column
+        let mut row = vec![1.into(), DataType::Int(7), "x".into()];
+        b.fix(&mut row);
+        assert_eq!(row, vec![1.into(), 7.into(), "x".into(), DataType::None]);
+        match row[1] {
+            DataType::BigInt(7) => {}
+            ref v => unreachable!("{:?}", v),
+        }
+
+        // non-integer values are left alone
+        let mut row = vec![2.into(), DataType::None, "y".into(), DataType::None];
+        b.fix(&mut row);
+        assert_eq!(row[1], DataType::None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn cannot_widen_key() {
+        Base::new(vec![0.into()]).with_key(vec![0]).widen_column(0);
+    }
+
+    #[test]
+    fn size_limits() {
+        let mut b = Base::new(vec![]).with_size_limit(1, 2);
+        assert_eq!(b.get_size_limits(), &[None, Some(2)]);
+
+        let fits = vec![1.into(), vec![1_u8, 2].into()];
+        assert_eq!(b.check(&[TableOperation::Insert(fits.clone())]), None);
+        assert_eq!(
+            b.check(&[
+                TableOperation::Insert(fits.clone()),
+                TableOperation::Insert(vec![2.into(), vec![1_u8, 2, 3].into()]),
+            ]),
+            Some(WriteRejection::ValueTooLarge(1, 3, 2))
+        );
+
+        // the base still ignores oversized writes that reach it without being checked
+        let local = unsafe { LocalNodeIndex::make(0 as u32) };
+        let records = b.process(
+            local,
+            vec![
+                TableOperation::Insert(fits.clone()),
+                TableOperation::Insert(vec![2.into(), vec![1_u8, 2, 3].into()]),
+                TableOperation::Insert(vec![3.into(), "abc".into()]),
+            ],
+            &StateMap::new(),
+        );
+        assert_eq!(records, vec![Record::Positive(fits)].into());
+    }
+
+    #[test]
+    fn valid_time() {
+        let mut b = Base::new(vec![]).with_valid_time(1, 2);
+        assert_eq!(b.valid_time(), Some((1, 2)));
+
+        let local = unsafe { LocalNodeIndex::make(0 as u32) };
+        let jan = chrono::NaiveDate::from_ymd(2020, 1, 1).and_hms(0, 0, 0);
+        let feb = chrono::NaiveDate::from_ymd(2020, 2, 1).and_hms(0, 0, 0);
+        let closed = vec![1.into(), jan.into(), feb.into()];
+        let open = vec![2.into(), jan.into(), DataType::None];
+        assert_eq!(
+            b.check(&[
+                TableOperation::Insert(closed.clone()),
+                TableOperation::Insert(open.clone()),
+            ]),
+            None
+        );
+        match b.check(&[
+            TableOperation::Insert(closed.clone()),
+            TableOperation::Insert(vec![3.into(), feb.into(), jan.into()]),
+        ]) {
+            Some(WriteRejection::Rows(rows)) => {
+                assert_eq!(
+                    rows.into_iter().map(|(i, _)| i).collect::<Vec<_>>(),
+                    vec![1]
+                )
+            }
+            r => unreachable!("{:?}", r),
+        }
+
+        // the base still ignores inverted rows that reach it without being checked
+        let records = b.process(
+            local,
+            vec![
+                TableOperation::Insert(closed.clone()),
+                TableOperation::Insert(open.clone()),
+                TableOperation::Insert(vec![3.into(), feb.into(), jan.into()]),
+            ],
+            &StateMap::new(),
+        );
+        assert_eq!(
+            records,
+            vec![Record::Positive(closed), Record::Positive(open)].into()
+        );
+    }
+
+    #[test]
+    fn soft_delete() {
+        use crate::node;
+
+        let mut b = Base::new(vec![]).with_key(vec![0]).with_soft_delete(2);
+        assert_eq!(b.soft_delete(), Some(2));
+
+        let local = unsafe { LocalNodeIndex::make(0 as u32) };
+        let mut state = MemoryState::default();
+        state.add_key(&[0], None);
+        let mut states = StateMap::new();
+        states.insert(local, Box::new(state) as Box<dyn State>);
+        let mut process = |b: &mut Base, ops| {
+            let mut rs = b.process(local, ops, &states);
+            node::materialize(&mut rs, None, states.get_mut(local));
+            rs
+        };
+
+        // inserted rows are live, whatever the tombstone column says
+        let live = vec![1.into(), "a".into(), 0.into()];
+        let rs = process(
+            &mut b,
+            vec![TableOperation::Insert(vec![
+                1.into(),
+                "a".into(),
+                DataType::None,
+            ])],
+        );
+        assert_eq!(rs, vec![Record::Positive(live.clone())].into());
+
+        // deletes only mark the row
+        let deleted = vec![1.into(), "a".into(), 1.into()];
+        let rs = process(
+            &mut b,
+            vec![TableOperation::Delete {
+                key: vec![1.into()],
+            }],
+        );
+        assert_eq!(
+            rs,
+            vec![
+                Record::Negative(live.clone()),
+                Record::Positive(deleted.clone())
+            ]
+            .into()
+        );
+
+        // deleted rows are not updated
+        let rs = process(
+            &mut b,
+            vec![TableOperation::Update {
+                key: vec![1.into()],
+                set: vec![Modification::None, Modification::Set("b".into())],
+            }],
+        );
+        assert!(rs.is_empty());
+
+        // but inserting the key again brings the row back
+        let back = vec![1.into(), "c".into(), 0.into()];
+        let rs = process(
+            &mut b,
+            vec![TableOperation::Insert(vec![1.into(), "c".into(), 1.into()])],
+        );
+        assert_eq!(
+            rs,
+            vec![Record::Negative(deleted), Record::Positive(back)].into()
+        );
+    }
+
+    #[test]
+    fn duplicate_recreates_rows() {
+        let b = Base::new(vec![0.into(), 0.into(), 0.into()])
+            .with_key(vec![0])
+            .with_soft_delete(2)
+            .with_audit(10);
+        let d = b.duplicate();
+        assert_eq!(d.key(), Some(&[0][..]));
+        assert_eq!(d.soft_delete(), Some(2));
+        assert_eq!(d.audit_log(), Some(vec![]));
+        assert_eq!(d.commit_seq(), 0);
+
+        // short rows are filled in, and soft-deleted rows are deleted again after the insert
+        assert_eq!(
+            b.writes_for(vec![1.into(), 2.into()]),
+            vec![TableOperation::Insert(vec![1.into(), 2.into(), 0.into()])]
+        );
+        assert_eq!(
+            b.writes_for(vec![1.into(), 2.into(), 1.into()]),
+            vec![
+                TableOperation::Insert(vec![1.into(), 2.into(), 1.into()]),
+                TableOperation::Delete {
+                    key: vec![1.into()]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn all_or_nothing_validation() {
+        use crate::node;
+
+        let mut b = Base::new(vec![]).with_key(vec![0]).with_size_limit(1, 2);
+        let local = unsafe { LocalNodeIndex::make(0 as u32) };
+        let mut state = MemoryState::default();
+        state.add_key(&[0], None);
+        let mut states = StateMap::new();
+        states.insert(local, Box::new(state) as Box<dyn State>);
+        let mut rs = b.process(
+            local,
+            vec![TableOperation::Insert(vec![1.into(), "a".into()])],
+            &states,
+        );
+        node::materialize(&mut rs, None, states.get_mut(local));
+
+        let rejected = b.validate(
+            local,
+            &[
+                TableOperation::Insert(vec![2.into(), "b".into()]),
+                // exists already
+                TableOperation::Insert(vec![1.into(), "c".into()]),
+                // inserted by an earlier operation
+                TableOperation::Insert(vec![2.into(), "d".into()]),
+                // too large
+                TableOperation::Insert(vec![3.into(), "abc".into()]),
+                // does not exist
+                TableOperation::Delete {
+                    key: vec![4.into()],
+                },
+                TableOperation::Delete {
+                    key: vec![1.into()],
+                },
+                TableOperation::Insert(vec![1.into(), "e".into()]),
+            ],
+            &states,
+        );
+        assert_eq!(
+            rejected.into_iter().map(|(i, _)| i).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4]
+        );
+    }
+
     fn test_lots_of_changes_in_same_batch(mut state: Box<dyn State>) {
         use crate::node;
         use crate::prelude::*;
@@ -43,6 +43,11 @@ impl Egress {
         });
     }
 
+    /// Stop sending to the given ingress nodes.
+    pub fn remove_txs(&mut self, dsts: &[NodeIndex]) {
+        self.txs.retain(|tx| !dsts.contains(&tx.node));
+    }
+
     pub fn add_tag(&mut self, tag: Tag, dst: NodeIndex) {
         self.tags.insert(tag, dst);
     }
@@ -59,7 +64,11 @@ impl Egress {
         } = self;
 
         // send any queued updates to all external children
-        assert!(!txs.is_empty());
+        if txs.is_empty() {
+            // every domain downstream of us has been torn down
+            m.take();
+            return;
+        }
         let txn = txs.len() - 1;
 
         // we need to find the ingress node following this egress according to the path
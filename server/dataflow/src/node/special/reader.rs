@@ -1,6 +1,9 @@
 use crate::backlog;
 use crate::prelude::*;
 use noria::channel;
+use noria::debug::stats::{ReadAmplification, ReadAttribution};
+use noria::KeyExpression;
+use std::borrow::Cow;
 
 /// A StreamUpdate reflects the addition or deletion of a row from a reader node.
 #[derive(Clone, Debug, PartialEq)]
@@ -36,6 +39,20 @@ pub struct Reader {
 
     for_node: NodeIndex,
     state: Option<Vec<usize>>,
+
+    /// Only keep the number of rows for each key.
+    count_only: bool,
+
+    /// What the key is computed from, if the reader is keyed on computed values.
+    key_expressions: Vec<KeyExpression>,
+
+    /// Columns whose values are hidden from clients, if any.
+    #[serde(default)]
+    mask: Option<backlog::ReadMask>,
+
+    /// Whether this is a read replica of another reader of the same node.
+    #[serde(default)]
+    replica: bool,
 }
 
 impl Clone for Reader {
@@ -46,6 +63,10 @@ impl Clone for Reader {
             streamers: self.streamers.clone(),
             state: self.state.clone(),
             for_node: self.for_node,
+            count_only: self.count_only,
+            key_expressions: self.key_expressions.clone(),
+            mask: self.mask.clone(),
+            replica: self.replica,
         }
     }
 }
@@ -57,6 +78,10 @@ impl Reader {
             streamers: Vec::new(),
             state: None,
             for_node,
+            count_only: false,
+            key_expressions: Vec::new(),
+            mask: None,
+            replica: false,
         }
     }
 
@@ -67,7 +92,7 @@ impl Reader {
     }
 
     #[allow(dead_code)]
-    fn writer(&self) -> Option<&backlog::WriteHandle> {
+    pub(crate) fn writer(&self) -> Option<&backlog::WriteHandle> {
         self.writer.as_ref()
     }
 
@@ -82,6 +107,10 @@ impl Reader {
             streamers: mem::replace(&mut self.streamers, Vec::new()),
             state: self.state.clone(),
             for_node: self.for_node,
+            count_only: self.count_only,
+            key_expressions: self.key_expressions.clone(),
+            mask: self.mask.clone(),
+            replica: self.replica,
         }
     }
 
@@ -106,6 +135,7 @@ impl Reader {
 
     pub(crate) fn set_write_handle(&mut self, wh: backlog::WriteHandle) {
         assert!(self.writer.is_none());
+        wh.set_read_mask(self.mask.clone());
         self.writer = Some(wh);
     }
 
@@ -121,6 +151,52 @@ impl Reader {
         }
     }
 
+    /// Keep only the number of rows for each key, rather than the rows themselves.
+    ///
+    /// Lookups into the reader then return a single row per key that holds the key's columns
+    /// followed by the count. This must be set before the reader's state is built.
+    pub fn set_count_only(&mut self) {
+        assert!(self.writer.is_none());
+        self.count_only = true;
+    }
+
+    pub fn is_count_only(&self) -> bool {
+        self.count_only
+    }
+
+    /// Mark the reader as a read replica of another reader of the same node.
+    ///
+    /// Read replicas are placed in domains of their own, so that they take reads off the domain
+    /// of the reader they replicate.
+    pub fn set_replica(&mut self) {
+        self.replica = true;
+    }
+
+    pub fn is_replica(&self) -> bool {
+        self.replica
+    }
+
+    /// Key the reader on values computed from its key columns, rather than on the columns
+    /// themselves.
+    ///
+    /// The computed values are stored in additional columns after the reader's own columns, and
+    /// the reader's state is keyed on those. Since computed values can't be replayed from
+    /// upstream, the reader is always fully materialized. This must be set before the reader's
+    /// state is built.
+    pub fn set_key_expressions(&mut self, exprs: Vec<KeyExpression>) {
+        assert!(self.writer.is_none());
+        let key: Vec<_> = exprs.iter().map(KeyExpression::column).collect();
+        self.set_key(&key[..]);
+        if exprs.iter().any(|e| !e.is_column()) {
+            self.key_expressions = exprs;
+        }
+    }
+
+    /// What the reader's key is computed from, or nothing if it is keyed on its columns.
+    pub fn key_expressions(&self) -> &[KeyExpression] {
+        &self.key_expressions[..]
+    }
+
     pub(crate) fn state_size(&self) -> Option<u64> {
         self.writer.as_ref().map(SizeOf::deep_size_of)
     }
@@ -138,6 +214,16 @@ impl Reader {
         bytes_freed
     }
 
+    /// Evict all keys, returning the number of bytes evicted.
+    pub(crate) fn evict_all(&mut self) -> u64 {
+        let mut bytes_freed = 0;
+        if let Some(ref mut handle) = self.writer {
+            bytes_freed = handle.evict_all();
+            handle.swap();
+        }
+        bytes_freed
+    }
+
     pub(in crate::node) fn on_eviction(&mut self, _key_columns: &[usize], keys: &[Vec<DataType>]) {
         // NOTE: *could* be None if reader has been created but its state hasn't been built yet
         if let Some(w) = self.writer.as_mut() {
@@ -148,9 +234,79 @@ impl Reader {
         }
     }
 
+    /// The keys most frequently looked up in this reader, hottest first.
+    pub fn hot_keys(&self) -> Vec<(Vec<DataType>, u64)> {
+        self.writer
+            .as_ref()
+            .map(|w| w.hot_keys())
+            .unwrap_or_default()
+    }
+
+    /// Start or stop attributing the client reads of this reader to the identities of the
+    /// clients, discarding whatever was attributed so far.
+    ///
+    /// This has no effect before the reader's state has been set up.
+    pub fn set_read_attribution(&mut self, enabled: bool) {
+        if let Some(w) = self.writer.as_ref() {
+            w.set_read_attribution(enabled);
+        }
+    }
+
+    /// Hide the values of some columns of this reader from clients, or stop hiding them if `mask`
+    /// is `None`.
+    ///
+    /// The mask also applies to the state the reader is given later, if it has none yet.
+    pub fn set_read_mask(&mut self, mask: Option<backlog::ReadMask>) {
+        if let Some(w) = self.writer.as_ref() {
+            w.set_read_mask(mask.clone());
+        }
+        self.mask = mask;
+    }
+
+    pub fn read_mask(&self) -> Option<&backlog::ReadMask> {
+        self.mask.as_ref()
+    }
+
+    /// The client reads of this reader by each client identity, since read attribution was
+    /// switched on.
+    pub fn read_attribution(&self) -> Vec<ReadAttribution> {
+        self.writer
+            .as_ref()
+            .map(|w| w.read_attribution())
+            .unwrap_or_default()
+    }
+
+    /// How much replay work the client reads of this reader have caused.
+    pub fn read_amplification(&self) -> Option<ReadAmplification> {
+        self.writer.as_ref().map(|w| w.read_amplification())
+    }
+
     pub(in crate::node) fn process(&mut self, m: &mut Option<Box<Packet>>, swap: bool) {
         if let Some(ref mut state) = self.writer {
             let m = m.as_mut().unwrap();
+            let mut key = Cow::Borrowed(&self.state.as_ref().unwrap()[..]);
+            if !self.key_expressions.is_empty() {
+                // materialize the computed key columns
+                let exprs = &self.key_expressions;
+                let mut cols = None;
+                m.map_data(|data| {
+                    for r in data.iter_mut() {
+                        cols = Some(r.len());
+                        let computed: Vec<_> =
+                            exprs.iter().map(|e| e.apply(&r[e.column()])).collect();
+                        r.extend(computed);
+                    }
+                });
+                if let Some(cols) = cols {
+                    key = Cow::Owned((cols..cols + exprs.len()).collect());
+                }
+            }
+            if self.count_only {
+                // turn the row updates into updates to the per-key counts we keep instead
+                let counts = state.count_updates(&key, m.take_data(), !m.is_regular());
+                m.map_data(|data| *data = counts.into());
+            }
+
             // make sure we don't fill a partial materialization
             // hole with incomplete (i.e., non-replay) state.
             if m.is_regular() && state.is_partial() {
@@ -198,6 +354,7 @@ impl Reader {
                         }
                     });
                 });
+                state.record_replay(m.data());
             }
 
             if self.streamers.is_empty() {
@@ -1,12 +1,38 @@
 use crate::payload;
 use crate::prelude::*;
+use crate::sketch::HeavyHitters;
+use std::collections::HashSet;
 use vec_map::VecMap;
 
+/// How many records a sharder routes between checks for keys that have become hot enough to split.
+const SPLIT_CHECK_INTERVAL: u64 = 1024;
+
 #[derive(Serialize, Deserialize)]
 pub struct Sharder {
     txs: Vec<(LocalNodeIndex, ReplicaAddr)>,
     sharded: VecMap<Box<Packet>>,
     shard_by: usize,
+    /// If set, keys that account for more than this share of the records routed through the
+    /// sharder are spread across all shards instead of being sent to the shard they hash to.
+    #[serde(default)]
+    split_share: Option<f64>,
+    /// The share of the keys each shard owns relative to the others, if they are not spread
+    /// evenly.
+    #[serde(default)]
+    weights: Vec<u32>,
+
+    #[serde(skip)]
+    hot_keys: HeavyHitters,
+    #[serde(skip)]
+    skip_hot_keys: bool,
+    #[serde(skip)]
+    untracked: u64,
+    #[serde(skip)]
+    observed: u64,
+    #[serde(skip)]
+    split: HashSet<DataType>,
+    #[serde(skip)]
+    split_records: u64,
 }
 
 impl Clone for Sharder {
@@ -17,6 +43,14 @@ impl Clone for Sharder {
             txs: Vec::new(),
             sharded: Default::default(),
             shard_by: self.shard_by,
+            split_share: self.split_share,
+            weights: self.weights.clone(),
+            hot_keys: Default::default(),
+            skip_hot_keys: false,
+            untracked: 0,
+            observed: 0,
+            split: Default::default(),
+            split_records: 0,
         }
     }
 }
@@ -26,7 +60,15 @@ impl Sharder {
         Self {
             txs: Default::default(),
             shard_by: by,
+            split_share: None,
+            weights: Vec::new(),
             sharded: VecMap::default(),
+            hot_keys: Default::default(),
+            skip_hot_keys: false,
+            untracked: 0,
+            observed: 0,
+            split: Default::default(),
+            split_records: 0,
         }
     }
 
@@ -37,6 +79,14 @@ impl Sharder {
             txs,
             sharded: VecMap::default(),
             shard_by: self.shard_by,
+            split_share: self.split_share,
+            weights: self.weights.clone(),
+            hot_keys: Default::default(),
+            skip_hot_keys: false,
+            untracked: 0,
+            observed: 0,
+            split: Default::default(),
+            split_records: 0,
         }
     }
 
@@ -52,6 +102,77 @@ impl Sharder {
         self.shard_by
     }
 
+    /// The shard keys most frequently routed through this sharder, hottest first.
+    pub fn hot_keys(&self) -> Vec<(Vec<DataType>, u64)> {
+        self.hot_keys.top()
+    }
+
+    /// Stop (or resume) tracking hot keys, to save work when the domain is overloaded.
+    pub fn set_skip_hot_keys(&mut self, skip: bool) {
+        self.skip_hot_keys = skip;
+    }
+
+    /// The number of records routed through this sharder without their key being tracked.
+    pub fn untracked(&self) -> u64 {
+        self.untracked
+    }
+
+    /// Spread the updates for any key that accounts for more than `share` of the records routed
+    /// through this sharder across all shards.
+    ///
+    /// This is only correct if the nodes below the sharder do not care which shard a given key's
+    /// records end up at, which is for the caller to ensure. Replays are always sent to the shard
+    /// their key hashes to.
+    pub fn split_hot_keys(&mut self, share: f64) {
+        assert!(share > 0.0 && share <= 1.0);
+        self.split_share = Some(share);
+    }
+
+    /// Give each shard a share of the keys proportional to its weight in `weights`, instead of
+    /// spreading them evenly.
+    ///
+    /// Every other node that routes keys to the same shards must use the same weights.
+    pub fn set_shard_weights(&mut self, weights: Vec<u32>) {
+        assert!(weights.iter().all(|&w| w > 0));
+        self.weights = weights;
+    }
+
+    /// Send the records of every key to the shard it hashes to again.
+    pub fn stop_splitting_keys(&mut self) {
+        self.split_share = None;
+        self.split.clear();
+    }
+
+    /// Whether this sharder may spread hot keys across shards.
+    pub fn splits_hot_keys(&self) -> bool {
+        self.split_share.is_some()
+    }
+
+    /// The number of keys whose records are currently spread across all shards.
+    pub fn split_keys(&self) -> usize {
+        self.split.len()
+    }
+
+    /// The number of records that were not sent to the shard their key hashes to, because their
+    /// key was split.
+    pub fn split_records(&self) -> u64 {
+        self.split_records
+    }
+
+    /// Start splitting any tracked key that is now hotter than the sharder's split share.
+    fn update_split_keys(&mut self) {
+        let share = match self.split_share {
+            Some(share) => share,
+            None => return,
+        };
+        let threshold = share * self.observed as f64;
+        for (key, count) in self.hot_keys.top() {
+            if count as f64 > threshold {
+                self.split.extend(key);
+            }
+        }
+    }
+
     #[inline]
     fn to_shard(&self, r: &Record) -> usize {
         self.shard(&r[self.shard_by])
@@ -59,7 +180,7 @@ impl Sharder {
 
     #[inline]
     fn shard(&self, dt: &DataType) -> usize {
-        crate::shard_by(dt, self.txs.len())
+        crate::shard_by_weight(dt, self.txs.len(), &self.weights)
     }
 
     pub fn process(
@@ -71,8 +192,26 @@ impl Sharder {
     ) {
         // we need to shard the records inside `m` by their key,
         let mut m = m.take().unwrap();
+        let split = self.split_share.is_some() && self.txs.len() > 1 && m.is_regular();
         for record in m.take_data() {
-            let shard = self.to_shard(&record);
+            if self.skip_hot_keys {
+                self.untracked += 1;
+            } else {
+                self.hot_keys
+                    .observe(&record[self.shard_by..=self.shard_by]);
+                self.observed += 1;
+                if self.observed % SPLIT_CHECK_INTERVAL == 0 {
+                    self.update_split_keys();
+                }
+            }
+            let shard = if split && self.split.contains(&record[self.shard_by]) {
+                // spread the key's records across all shards by the whole row, so that a row's
+                // negative record reaches the same shard as its positive one did.
+                self.split_records += 1;
+                crate::shard_by_multi(&record[..], self.txs.len())
+            } else {
+                self.to_shard(&record)
+            };
             let p = self
                 .sharded
                 .entry(shard)
@@ -179,3 +318,137 @@ impl Sharder {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct Sent(Vec<(ReplicaAddr, Box<Packet>)>);
+
+    impl Executor for Sent {
+        fn ack(&mut self, _: SourceChannelIdentifier, _: u64) {}
+        fn reject(&mut self, _: SourceChannelIdentifier, _: noria::WriteRejection) {}
+        fn create_universe(&mut self, _: HashMap<String, DataType>) {}
+        fn send(&mut self, dest: ReplicaAddr, m: Box<Packet>) {
+            self.0.push((dest, m));
+        }
+        fn report_statistics(
+            &mut self,
+            _: ReplicaAddr,
+            _: noria::debug::stats::DomainStats,
+            _: HashMap<NodeIndex, noria::debug::stats::NodeStats>,
+        ) {
+        }
+        fn report_corrupt_replay(&mut self, _: ReplicaAddr, _: Tag, _: usize, _: bool) {}
+    }
+
+    fn send_key(s: &mut Sharder, key: i32, n: usize, ex: &mut Sent) {
+        let data: Records = (0..n)
+            .map(|i| vec![DataType::from(key), DataType::from(i as i32)])
+            .collect();
+        send(s, data, ex);
+    }
+
+    fn send(s: &mut Sharder, data: Records, ex: &mut Sent) {
+        let local = unsafe { LocalNodeIndex::make(0) };
+        let mut m = Some(Box::new(Packet::Message {
+            link: Link::new(local, local),
+            data,
+            trace: None,
+        }));
+        s.process(&mut m, local, false, ex);
+    }
+
+    fn sharder(split_share: Option<f64>) -> Sharder {
+        let mut s = Sharder::new(0);
+        if let Some(share) = split_share {
+            s.split_hot_keys(share);
+        }
+        let dst = unsafe { LocalNodeIndex::make(1) };
+        let domain = DomainIndex::from(0);
+        s.add_sharded_child(dst, vec![(domain, 0), (domain, 1)]);
+        s
+    }
+
+    fn keys_per_shard(s: &mut Sharder, keys: i32) -> [usize; 2] {
+        let mut ex = Sent::default();
+        for key in 0..keys {
+            send_key(s, key, 1, &mut ex);
+        }
+        let mut per_shard = [0; 2];
+        for &((_, shard), _) in &ex.0 {
+            per_shard[shard] += 1;
+        }
+        per_shard
+    }
+
+    fn shards_reached(ex: &Sent) -> HashSet<usize> {
+        ex.0.iter().map(|&((_, shard), _)| shard).collect()
+    }
+
+    #[test]
+    fn hot_key_is_spread_across_shards() {
+        let mut s = sharder(Some(0.5));
+        let mut ex = Sent::default();
+
+        // not hot yet, so everything goes to the key's own shard
+        send_key(&mut s, 1, SPLIT_CHECK_INTERVAL as usize - 1, &mut ex);
+        assert_eq!(shards_reached(&ex).len(), 1);
+        assert_eq!(s.split_keys(), 0);
+
+        ex.0.clear();
+        send_key(&mut s, 1, 1, &mut ex);
+        send_key(&mut s, 1, 2, &mut ex);
+        assert_eq!(s.split_keys(), 1);
+        assert_eq!(shards_reached(&ex).len(), 2);
+        assert_eq!(s.split_records(), 3);
+
+        // a row's negative record follows its positive one, wherever that went
+        ex.0.clear();
+        let row = vec![DataType::from(1), DataType::from(5)];
+        send(
+            &mut s,
+            vec![Record::Positive(row.clone()), Record::Negative(row)].into(),
+            &mut ex,
+        );
+        assert_eq!(shards_reached(&ex).len(), 1);
+
+        s.stop_splitting_keys();
+        ex.0.clear();
+        send_key(&mut s, 1, 4, &mut ex);
+        assert_eq!(shards_reached(&ex).len(), 1);
+    }
+
+    #[test]
+    fn keys_are_not_split_unless_enabled() {
+        let mut s = sharder(None);
+        let mut ex = Sent::default();
+        send_key(&mut s, 1, 2 * SPLIT_CHECK_INTERVAL as usize, &mut ex);
+        send_key(&mut s, 1, 2, &mut ex);
+        assert_eq!(s.split_keys(), 0);
+        assert_eq!(shards_reached(&ex).len(), 1);
+    }
+
+    #[test]
+    fn weighted_shards_own_their_share_of_keys() {
+        let mut s = sharder(None);
+        assert_eq!(keys_per_shard(&mut s, 100), [50, 50]);
+
+        let mut s = sharder(None);
+        s.set_shard_weights(vec![1, 3]);
+        let [light, heavy] = keys_per_shard(&mut s, 1000);
+        assert!(
+            light > 200 && light < 300,
+            "{} keys on the light shard",
+            light
+        );
+        assert_eq!(light + heavy, 1000);
+
+        // weights for a different number of shards are ignored
+        let mut s = sharder(None);
+        s.set_shard_weights(vec![1, 1, 2]);
+        assert_eq!(keys_per_shard(&mut s, 100), [50, 50]);
+    }
+}
@@ -0,0 +1,623 @@
+use crate::prelude::*;
+use crate::state::versioned::{self, Versioned};
+use noria::internal::LocalOrNot;
+use noria::TableOperation;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The number of deletes a cascade may have sent to its child table that have not yet been
+/// applied.
+const MAX_IN_FLIGHT: usize = 256;
+
+/// Where the rows of a cascade's child table live.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Target {
+    domain: DomainIndex,
+    node: LocalNodeIndex,
+    sharding: Sharding,
+}
+
+/// What a cascade keeps in its journal.
+#[derive(Serialize, Deserialize)]
+struct Journal {
+    /// Parent keys whose referencing child rows may not all have been deleted yet.
+    orphans: Vec<DataType>,
+}
+
+impl Journal {
+    fn write(&self, path: &Path) -> io::Result<()> {
+        // write to a temporary file first so that a crash never leaves a truncated journal
+        let tmp = path.with_extension("cascade.tmp");
+        let mut f = io::BufWriter::new(fs::File::create(&tmp)?);
+        versioned::encode_into(&mut f, self)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        f.into_inner()?.sync_all()?;
+        fs::rename(&tmp, path)
+    }
+}
+
+impl Versioned for Journal {
+    const VERSION: u32 = 1;
+
+    fn upgrade(version: u32, _: &[u8]) -> bincode::Result<Self> {
+        // journals have been versioned from the start
+        Err(Box::new(bincode::ErrorKind::Custom(format!(
+            "unknown cascade journal version {}",
+            version
+        ))))
+    }
+}
+
+/// A Cascade data-flow operator.
+///
+/// This node implements `ON DELETE CASCADE` for a foreign key from column `child_col` of the
+/// `child` base table to column `parent_col` of the `parent` base table, which must be the
+/// parent's primary key. It keeps the rows of `child`, indexed by the referencing column, and
+/// whenever a key is deleted from `parent`, it deletes the rows of `child` that referenced that key
+/// by sending deletes to `child` like any other writer would.
+///
+/// Deletes are throttled: at most `MAX_IN_FLIGHT` of them are outstanding at any time, and a
+/// delete counts as acknowledged once the removal of the row it targets has come back down from
+/// `child`. The node only emits the rows of `child`.
+///
+/// Once the parent row is gone, nothing upstream remembers that its child rows are still to be
+/// deleted. If given a journal, the node therefore keeps the parent keys whose child rows are not
+/// all gone yet on disk, and cascades them again after a restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cascade {
+    us: Option<IndexPair>,
+    parent: IndexPair,
+    parent_col: usize,
+    child: IndexPair,
+    child_col: usize,
+    child_key: Vec<usize>,
+    target: Option<Target>,
+    /// The share of the keys each shard of the child table owns, if they are not spread evenly.
+    #[serde(default)]
+    shard_weights: Vec<u32>,
+    /// The split points between the ranges of keys each shard of the child table owns, if any.
+    #[serde(default)]
+    shard_ranges: Vec<DataType>,
+
+    /// Keys of child rows we have decided to delete, the parent key they referred to, and whether
+    /// the delete has been sent.
+    #[serde(skip)]
+    pending: HashMap<Vec<DataType>, (DataType, bool)>,
+    /// Parent keys with child rows in `pending`, and how many.
+    #[serde(skip)]
+    orphans: HashMap<DataType, usize>,
+    /// Keys of child rows whose delete has not been sent yet, in the order they were decided on.
+    #[serde(skip)]
+    queued: VecDeque<Vec<DataType>>,
+    #[serde(skip)]
+    in_flight: usize,
+    /// The file that the keys of `orphans` are kept in, if any.
+    #[serde(skip)]
+    journal: Option<PathBuf>,
+    /// Parent keys read back from the journal, whose child rows are yet to be looked up again.
+    #[serde(skip)]
+    restored: Vec<DataType>,
+}
+
+impl Cascade {
+    /// Construct a new Cascade operator.
+    ///
+    /// `child_key` is the primary key of `child`, which is what the deletes that are sent to it
+    /// name rows by.
+    pub fn new(
+        parent: NodeIndex,
+        parent_col: usize,
+        child: NodeIndex,
+        child_col: usize,
+        child_key: Vec<usize>,
+    ) -> Cascade {
+        assert!(!child_key.is_empty());
+        Cascade {
+            us: None,
+            parent: parent.into(),
+            parent_col,
+            child: child.into(),
+            child_col,
+            child_key,
+            target: None,
+            shard_weights: Vec::new(),
+            shard_ranges: Vec::new(),
+            pending: HashMap::new(),
+            orphans: HashMap::new(),
+            queued: VecDeque::new(),
+            in_flight: 0,
+            journal: None,
+            restored: Vec::new(),
+        }
+    }
+
+    /// The base table that rows are deleted from.
+    pub fn child(&self) -> NodeIndex {
+        self.child.as_global()
+    }
+
+    /// The parent table, the referenced column, the child table, and the referencing column.
+    pub fn foreign_key(&self) -> (NodeIndex, usize, NodeIndex, usize) {
+        (
+            self.parent.as_global(),
+            self.parent_col,
+            self.child.as_global(),
+            self.child_col,
+        )
+    }
+
+    /// Keep the parent keys whose child rows are still to be deleted in the file at `path`, and
+    /// pick up the ones that were kept there before a restart.
+    ///
+    /// The child rows of the picked up keys are deleted once `resume` is called.
+    pub fn set_journal(&mut self, path: PathBuf) -> io::Result<()> {
+        let data = match fs::read(&path) {
+            Ok(data) => Some(data),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        self.journal = Some(path);
+        if let Some(data) = data {
+            let journal: Journal = versioned::decode(&data)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            self.restored = journal.orphans;
+        }
+        Ok(())
+    }
+
+    /// Delete the child rows of the parent keys picked up by `set_journal`.
+    ///
+    /// This must be called once the rows of the child table have all been replayed to us.
+    pub fn resume(&mut self, executor: &mut dyn Executor, state: &StateMap) {
+        if self.restored.is_empty() {
+            return;
+        }
+        let restored = std::mem::replace(&mut self.restored, Vec::new());
+        self.orphan(restored, state);
+        // parent keys whose child rows were all deleted before the restart are done with
+        self.save_journal();
+        self.send_queued(executor);
+    }
+
+    /// Tell the operator where the child table lives, so that it can send deletes to it.
+    pub fn set_target(&mut self, domain: DomainIndex, node: LocalNodeIndex, sharding: Sharding) {
+        self.target = Some(Target {
+            domain,
+            node,
+            sharding,
+        });
+    }
+
+    /// Route deletes to the shards of the child table according to `weights`, like the table
+    /// handles that write to it do.
+    pub fn set_shard_weights(&mut self, weights: Vec<u32>) {
+        self.shard_weights = weights;
+    }
+
+    /// Route deletes to the shards of the child table according to the split points in `ranges`,
+    /// like the table handles that write to it do.
+    pub fn with_shard_ranges(mut self, ranges: Vec<DataType>) -> Self {
+        self.shard_ranges = ranges;
+        self
+    }
+
+    fn key_of(&self, row: &[DataType]) -> Vec<DataType> {
+        self.child_key.iter().map(|&c| row[c].clone()).collect()
+    }
+
+    /// The parent keys that `rs` removes without adding back. Updates that leave a row's key
+    /// alone show up as a removal and an addition of the same key, and must not cascade.
+    fn orphaned(&self, rs: &Records) -> Vec<DataType> {
+        let added: HashSet<_> = rs
+            .iter()
+            .filter(|r| r.is_positive())
+            .map(|r| &r[self.parent_col])
+            .collect();
+        let mut removed: Vec<_> = rs
+            .iter()
+            .filter(|r| !r.is_positive())
+            .map(|r| &r[self.parent_col])
+            .filter(|k| !added.contains(k))
+            .cloned()
+            .collect();
+        removed.sort();
+        removed.dedup();
+        removed
+    }
+
+    /// Decide to delete the child rows that refer to the parent keys in `orphaned`.
+    fn orphan(&mut self, orphaned: Vec<DataType>, state: &StateMap) {
+        let us = self.us.unwrap();
+        let db = state
+            .get(*us)
+            .expect("cascade must have its own state materialized");
+
+        let mut changed = false;
+        for k in orphaned {
+            let rs = match db.lookup(&[self.child_col], &KeyType::Single(&k)) {
+                LookupResult::Some(rs) => rs,
+                LookupResult::Missing => unreachable!("cascade state is never partial"),
+            };
+            for r in rs {
+                let key = self.key_of(&r);
+                if self.pending.contains_key(&key) {
+                    continue;
+                }
+                self.pending.insert(key.clone(), (k.clone(), false));
+                self.queued.push_back(key);
+                let n = self.orphans.entry(k.clone()).or_insert(0);
+                changed |= *n == 0;
+                *n += 1;
+            }
+        }
+        if changed {
+            self.save_journal();
+        }
+    }
+
+    /// Write the keys of `orphans` to the journal, if there is one.
+    fn save_journal(&self) {
+        let path = match self.journal {
+            Some(ref path) => path,
+            None => return,
+        };
+        let mut orphans: Vec<_> = self.orphans.keys().cloned().collect();
+        orphans.sort();
+        if let Err(e) = (Journal { orphans }).write(path) {
+            eprintln!("cascade failed to save its journal to {:?}: {}", path, e);
+        }
+    }
+
+    fn send_queued(&mut self, executor: &mut dyn Executor) {
+        let mut deletes = Vec::new();
+        while self.in_flight < MAX_IN_FLIGHT {
+            let key = match self.queued.pop_front() {
+                Some(key) => key,
+                None => break,
+            };
+            match self.pending.get_mut(&key) {
+                Some((_, sent)) => *sent = true,
+                // the row went away before we got around to deleting it
+                None => continue,
+            }
+            self.in_flight += 1;
+            deletes.push(key);
+        }
+        if deletes.is_empty() {
+            return;
+        }
+
+        let target = self
+            .target
+            .as_ref()
+            .expect("cascade was never told where its child table is");
+        let mut by_shard: HashMap<usize, Vec<TableOperation>> = HashMap::new();
+        for key in deletes {
+            match target.sharding {
+                Sharding::ByColumn(col, shards) => {
+                    match self.child_key.iter().position(|&c| c == col) {
+                        Some(i) => {
+                            let shard = crate::shard_by_range(
+                                &key[i],
+                                shards,
+                                &self.shard_ranges,
+                                &self.shard_weights,
+                            );
+                            by_shard
+                                .entry(shard)
+                                .or_default()
+                                .push(TableOperation::Delete { key });
+                        }
+                        None => {
+                            // we can't tell which shard has the row, so ask all of them
+                            for shard in 0..shards {
+                                by_shard
+                                    .entry(shard)
+                                    .or_default()
+                                    .push(TableOperation::Delete { key: key.clone() });
+                            }
+                        }
+                    }
+                }
+                Sharding::Random(shards) => {
+                    for shard in 0..shards {
+                        by_shard
+                            .entry(shard)
+                            .or_default()
+                            .push(TableOperation::Delete { key: key.clone() });
+                    }
+                }
+                Sharding::None | Sharding::ForcedNone => {
+                    by_shard
+                        .entry(0)
+                        .or_default()
+                        .push(TableOperation::Delete { key });
+                }
+            }
+        }
+
+        for (shard, data) in by_shard {
+            executor.send(
+                (target.domain, shard),
+                Box::new(Packet::Input {
+                    inner: LocalOrNot::new(Input {
+                        dst: target.node,
+                        data,
+                        identity: None,
+                        trace: None,
+                        all_or_nothing: false,
+                    }),
+                    src: None,
+                    senders: Vec::new(),
+                    trace: None,
+                }),
+            );
+        }
+    }
+}
+
+impl Ingredient for Cascade {
+    fn take(&mut self) -> NodeOperator {
+        Clone::clone(self).into()
+    }
+
+    fn ancestors(&self) -> Vec<NodeIndex> {
+        vec![self.parent.as_global(), self.child.as_global()]
+    }
+
+    fn must_replay_among(&self) -> Option<HashSet<NodeIndex>> {
+        // only the child's rows make up our state
+        Some(Some(self.child.as_global()).into_iter().collect())
+    }
+
+    fn is_join(&self) -> bool {
+        // so that replays only walk up to the child
+        true
+    }
+
+    fn on_connected(&mut self, _: &Graph) {}
+
+    fn on_commit(&mut self, us: NodeIndex, remap: &HashMap<NodeIndex, IndexPair>) {
+        self.parent.remap(remap);
+        self.child.remap(remap);
+        self.us = Some(remap[&us]);
+    }
+
+    fn on_input(
+        &mut self,
+        executor: &mut dyn Executor,
+        from: LocalNodeIndex,
+        rs: Records,
+        _: Option<&[usize]>,
+        _: &DomainNodes,
+        state: &StateMap,
+    ) -> ProcessingResult {
+        if from == *self.child {
+            // the removal of a child row acknowledges any delete we sent for it
+            let mut changed = false;
+            for r in rs.iter().filter(|r| !r.is_positive()) {
+                let key = self.key_of(r);
+                if let Some((k, sent)) = self.pending.remove(&key) {
+                    if sent {
+                        self.in_flight -= 1;
+                    }
+                    let n = self.orphans.get_mut(&k).unwrap();
+                    *n -= 1;
+                    if *n == 0 {
+                        self.orphans.remove(&k);
+                        changed = true;
+                    }
+                }
+            }
+            if changed {
+                self.save_journal();
+            }
+            self.send_queued(executor);
+
+            return ProcessingResult {
+                results: rs,
+                ..Default::default()
+            };
+        }
+        debug_assert_eq!(from, *self.parent);
+
+        let orphaned = self.orphaned(&rs);
+        if !orphaned.is_empty() {
+            self.orphan(orphaned, state);
+            self.send_queued(executor);
+        }
+
+        // parent rows are not part of our output
+        ProcessingResult {
+            results: Records::default(),
+            ..Default::default()
+        }
+    }
+
+    fn suggest_indexes(&self, this: NodeIndex) -> HashMap<NodeIndex, Vec<usize>> {
+        Some((this, vec![self.child_col])).into_iter().collect()
+    }
+
+    fn resolve(&self, _: usize) -> Option<Vec<(NodeIndex, usize)>> {
+        // parent deletes must be seen by whichever shard holds the referencing child rows, which
+        // only works if nothing above us is sharded.
+        None
+    }
+
+    fn description(&self, detailed: bool) -> String {
+        if !detailed {
+            return "⇣".into();
+        }
+        format!(
+            "⇣ {}[{}] ← {}[{}]",
+            self.child.as_global().index(),
+            self.child_col,
+            self.parent.as_global().index(),
+            self.parent_col
+        )
+    }
+
+    fn parent_columns(&self, column: usize) -> Vec<(NodeIndex, Option<usize>)> {
+        vec![(self.child.as_global(), Some(column))]
+    }
+
+    // the rows of the child that reference a deleted parent must all be known, or they would
+    // never be deleted.
+    fn requires_full_materialization(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ops;
+
+    fn setup() -> (ops::test::MockGraph, IndexPair, IndexPair) {
+        let mut g = ops::test::MockGraph::new();
+        let parent = g.add_base("class", &["cid", "name"]);
+        let child = g.add_base("post", &["pid", "cid", "content"]);
+        g.set_op(
+            "cascade",
+            &["pid", "cid", "content"],
+            Cascade::new(parent.as_global(), 0, child.as_global(), 1, vec![0]),
+            true,
+        );
+        (g, parent, child)
+    }
+
+    #[test]
+    fn it_forwards_child_rows() {
+        let (mut g, _, child) = setup();
+        let r: Vec<DataType> = vec![1.into(), 1.into(), "a".into()];
+        assert_eq!(g.one_row(child, r.clone(), false), vec![r].into());
+    }
+
+    #[test]
+    fn it_swallows_parent_rows() {
+        let (mut g, parent, _) = setup();
+        let r: Vec<DataType> = vec![1.into(), "a".into()];
+        assert!(g.one_row(parent, r, false).is_empty());
+    }
+
+    #[test]
+    fn it_only_cascades_removed_keys() {
+        let c = Cascade::new(0.into(), 0, 1.into(), 1, vec![0]);
+        let rs: Records = vec![
+            (vec![1.into(), "a".into()], false),
+            (vec![1.into(), "b".into()], true),
+            (vec![2.into(), "c".into()], false),
+            (vec![2.into(), "c".into()], false),
+        ]
+        .into();
+        assert_eq!(c.orphaned(&rs), vec![2.into()]);
+    }
+
+    #[test]
+    fn it_cascades_again_after_a_restart() {
+        struct Sends(usize);
+
+        impl Executor for Sends {
+            fn ack(&mut self, _: SourceChannelIdentifier, _: u64) {}
+            fn reject(&mut self, _: SourceChannelIdentifier, _: noria::WriteRejection) {}
+            fn create_universe(&mut self, _: HashMap<String, DataType>) {}
+            fn send(&mut self, _: ReplicaAddr, _: Box<Packet>) {
+                self.0 += 1;
+            }
+            fn report_statistics(
+                &mut self,
+                _: ReplicaAddr,
+                _: noria::debug::stats::DomainStats,
+                _: HashMap<NodeIndex, noria::debug::stats::NodeStats>,
+            ) {
+            }
+            fn report_corrupt_replay(&mut self, _: ReplicaAddr, _: Tag, _: usize, _: bool) {}
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let journal = dir.path().join("class_cascade_post.cascade");
+
+        let (mut g, parent, child) = setup();
+        g.one_row(child, vec![1.into(), 1.into(), "a".into()], true);
+        g.one_row(child, vec![2.into(), 1.into(), "b".into()], true);
+        g.one_row(child, vec![3.into(), 2.into(), "c".into()], true);
+        {
+            let mut n = g.node_mut();
+            let c = n.get_cascade_mut().unwrap();
+            c.set_target(DomainIndex::from(0), *child, Sharding::None);
+            c.set_journal(journal.clone()).unwrap();
+        }
+
+        // class 1 is deleted, but the process stops before the deletes of its posts are applied
+        g.one_row(parent, (vec![1.into(), "x".into()], false), false);
+
+        let restart = || {
+            let n = g.node();
+            let us = n.global_addr();
+            let mut remap = HashMap::new();
+            remap.insert(parent.as_global(), parent);
+            remap.insert(child.as_global(), child);
+            let mut ip = IndexPair::from(us);
+            ip.set_local(n.local_addr());
+            remap.insert(us, ip);
+
+            let mut c = Cascade::new(parent.as_global(), 0, child.as_global(), 1, vec![0]);
+            c.on_commit(us, &remap);
+            c.set_target(DomainIndex::from(0), *child, Sharding::None);
+            c.set_journal(journal.clone()).unwrap();
+            c
+        };
+
+        // after a restart, the posts of class 1 are deleted again
+        let mut c = restart();
+        let mut sends = Sends(0);
+        c.resume(&mut sends, &g.states);
+        assert_eq!(sends.0, 1);
+        assert_eq!(c.in_flight, 2);
+
+        // and once they are gone, class 1 is done with
+        let removed: Records = vec![
+            (vec![1.into(), 1.into(), "a".into()], false),
+            (vec![2.into(), 1.into(), "b".into()], false),
+        ]
+        .into();
+        c.on_input(
+            &mut sends,
+            *child,
+            removed,
+            None,
+            &DomainNodes::default(),
+            &g.states,
+        );
+        assert_eq!(c.in_flight, 0);
+        let mut c = restart();
+        c.resume(&mut sends, &g.states);
+        assert_eq!(sends.0, 1);
+    }
+
+    #[test]
+    fn it_suggests_indices() {
+        let (g, _, _) = setup();
+        let me = 3.into();
+        let idx = g.node().suggest_indexes(me);
+        assert_eq!(idx.len(), 1);
+        assert_eq!(idx[&me], vec![1]);
+    }
+
+    #[test]
+    fn it_replays_from_child() {
+        let (g, _, child) = setup();
+        assert!(g.node().is_join());
+        assert_eq!(
+            g.node().must_replay_among(),
+            Some(Some(child.as_global()).into_iter().collect())
+        );
+        assert_eq!(
+            g.node().parent_columns(1),
+            vec![(child.as_global(), Some(1))]
+        );
+    }
+}
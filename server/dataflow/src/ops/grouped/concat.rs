@@ -100,6 +100,7 @@ impl GroupConcat {
                     DataType::UnsignedBigInt(ref n) => s.push_str(&n.to_string()),
                     DataType::Real(..) => s.push_str(&rec[*i].to_string()),
                     DataType::Timestamp(ref ts) => s.push_str(&ts.format("%+").to_string()),
+                    DataType::Bytes(..) | DataType::List(..) => s.push_str(&rec[*i].to_string()),
                     DataType::None => unreachable!(),
                 },
             }
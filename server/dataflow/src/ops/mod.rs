@@ -3,6 +3,7 @@ use std::collections::{HashMap, HashSet};
 
 use crate::prelude::*;
 
+pub mod cascade;
 pub mod distinct;
 pub mod filter;
 pub mod grouped;
@@ -14,6 +15,7 @@ pub mod rewrite;
 pub mod topk;
 pub mod trigger;
 pub mod union;
+pub mod unnest;
 
 #[derive(Clone, Serialize, Deserialize)]
 #[allow(clippy::large_enum_variant)]
@@ -32,6 +34,8 @@ pub enum NodeOperator {
     Trigger(trigger::Trigger),
     Rewrite(rewrite::Rewrite),
     Distinct(distinct::Distinct),
+    Cascade(cascade::Cascade),
+    Unnest(unnest::Unnest),
 }
 
 macro_rules! nodeop_from_impl {
@@ -70,6 +74,8 @@ nodeop_from_impl!(NodeOperator::TopK, topk::TopK);
 nodeop_from_impl!(NodeOperator::Trigger, trigger::Trigger);
 nodeop_from_impl!(NodeOperator::Rewrite, rewrite::Rewrite);
 nodeop_from_impl!(NodeOperator::Distinct, distinct::Distinct);
+nodeop_from_impl!(NodeOperator::Cascade, cascade::Cascade);
+nodeop_from_impl!(NodeOperator::Unnest, unnest::Unnest);
 
 macro_rules! impl_ingredient_fn_mut {
     ($self:ident, $fn:ident, $( $arg:ident ),* ) => {
@@ -88,6 +94,8 @@ macro_rules! impl_ingredient_fn_mut {
             NodeOperator::Trigger(ref mut i) => i.$fn($($arg),*),
             NodeOperator::Rewrite(ref mut i) => i.$fn($($arg),*),
             NodeOperator::Distinct(ref mut i) => i.$fn($($arg),*),
+            NodeOperator::Cascade(ref mut i) => i.$fn($($arg),*),
+            NodeOperator::Unnest(ref mut i) => i.$fn($($arg),*),
         }
     }
 }
@@ -109,6 +117,8 @@ macro_rules! impl_ingredient_fn_ref {
             NodeOperator::Trigger(ref i) => i.$fn($($arg),*),
             NodeOperator::Rewrite(ref i) => i.$fn($($arg),*),
             NodeOperator::Distinct(ref i) => i.$fn($($arg),*),
+            NodeOperator::Cascade(ref i) => i.$fn($($arg),*),
+            NodeOperator::Unnest(ref i) => i.$fn($($arg),*),
         }
     }
 }
@@ -412,9 +422,18 @@ pub mod test {
             struct Ex;
 
             impl Executor for Ex {
-                fn ack(&mut self, _: SourceChannelIdentifier) {}
+                fn ack(&mut self, _: SourceChannelIdentifier, _: u64) {}
+                fn reject(&mut self, _: SourceChannelIdentifier, _: noria::WriteRejection) {}
                 fn create_universe(&mut self, _: HashMap<String, DataType>) {}
                 fn send(&mut self, _: ReplicaAddr, _: Box<Packet>) {}
+                fn report_statistics(
+                    &mut self,
+                    _: ReplicaAddr,
+                    _: noria::debug::stats::DomainStats,
+                    _: HashMap<NodeIndex, noria::debug::stats::NodeStats>,
+                ) {
+                }
+                fn report_corrupt_replay(&mut self, _: ReplicaAddr, _: Tag, _: usize, _: bool) {}
             }
 
             let mut u = {
@@ -455,6 +474,10 @@ pub mod test {
             self.nodes[*self.nut.unwrap()].borrow()
         }
 
+        pub fn node_mut(&self) -> cell::RefMut<Node> {
+            self.nodes[*self.nut.unwrap()].borrow_mut()
+        }
+
         pub fn narrow_base_id(&self) -> IndexPair {
             assert_eq!(self.remap.len(), 2 /* base + nut */);
             *self
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use crate::prelude::*;
+
+/// Expands a list column into one row per element of the list.
+///
+/// Every other column of an input row is repeated in each of the rows it expands into. A
+/// negative input row expands into the same rows, only negative, so removing a row takes back
+/// everything it once produced. Rows whose list is empty or `NULL` produce no rows at all, while
+/// rows that hold a single value where a list was expected are forwarded unchanged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Unnest {
+    src: IndexPair,
+    column: usize,
+}
+
+impl Unnest {
+    /// Construct a new unnest operator that expands the list in `column` of `src`'s rows.
+    pub fn new(src: NodeIndex, column: usize) -> Unnest {
+        Unnest {
+            src: src.into(),
+            column,
+        }
+    }
+
+    fn expand(&self, r: Record, out: &mut Vec<Record>) {
+        let (r, positive) = r.extract();
+        match r[self.column] {
+            DataType::List(ref values) => {
+                for v in values.iter() {
+                    let mut row = r.clone();
+                    row[self.column] = v.clone();
+                    out.push((row, positive).into());
+                }
+            }
+            DataType::None => {}
+            _ => out.push((r, positive).into()),
+        }
+    }
+}
+
+impl Ingredient for Unnest {
+    fn take(&mut self) -> NodeOperator {
+        Clone::clone(self).into()
+    }
+
+    fn ancestors(&self) -> Vec<NodeIndex> {
+        vec![self.src.as_global()]
+    }
+
+    fn on_connected(&mut self, g: &Graph) {
+        assert!(self.column < g[self.src.as_global()].fields().len());
+    }
+
+    fn on_commit(&mut self, _: NodeIndex, remap: &HashMap<NodeIndex, IndexPair>) {
+        self.src.remap(remap);
+    }
+
+    fn on_input(
+        &mut self,
+        _: &mut dyn Executor,
+        from: LocalNodeIndex,
+        rs: Records,
+        _: Option<&[usize]>,
+        _: &DomainNodes,
+        _: &StateMap,
+    ) -> ProcessingResult {
+        debug_assert_eq!(from, *self.src);
+
+        let mut results = Vec::with_capacity(rs.len());
+        for r in rs {
+            self.expand(r, &mut results);
+        }
+
+        ProcessingResult {
+            results: results.into(),
+            ..Default::default()
+        }
+    }
+
+    fn suggest_indexes(&self, _: NodeIndex) -> HashMap<NodeIndex, Vec<usize>> {
+        HashMap::new()
+    }
+
+    fn resolve(&self, col: usize) -> Option<Vec<(NodeIndex, usize)>> {
+        if col == self.column {
+            // the elements are not a column of our parent
+            None
+        } else {
+            Some(vec![(self.src.as_global(), col)])
+        }
+    }
+
+    fn description(&self, detailed: bool) -> String {
+        if !detailed {
+            return "⋔".into();
+        }
+        format!("⋔ {}", self.column)
+    }
+
+    fn parent_columns(&self, column: usize) -> Vec<(NodeIndex, Option<usize>)> {
+        if column == self.column {
+            // a key on the elements cannot be looked up in the parent, whose rows hold whole
+            // lists, so anything keyed on them has to be fully materialized.
+            vec![(self.src.as_global(), None)]
+        } else {
+            vec![(self.src.as_global(), Some(column))]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ops;
+
+    fn setup() -> ops::test::MockGraph {
+        let mut g = ops::test::MockGraph::new();
+        let s = g.add_base("post", &["id", "tags"]);
+        g.set_op(
+            "unnest",
+            &["id", "tag"],
+            Unnest::new(s.as_global(), 1),
+            false,
+        );
+        g
+    }
+
+    fn tags(tags: &[&str]) -> DataType {
+        tags.iter()
+            .map(|&t| DataType::from(t))
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    #[test]
+    fn it_expands_lists() {
+        let mut g = setup();
+        let r: Vec<DataType> = vec![1.into(), tags(&["a", "b"])];
+        assert_eq!(
+            g.narrow_one_row(r, false),
+            vec![vec![1.into(), "a".into()], vec![1.into(), "b".into()]].into()
+        );
+    }
+
+    #[test]
+    fn it_folds_back_negatives() {
+        let mut g = setup();
+        let old: Vec<DataType> = vec![1.into(), tags(&["a", "b"])];
+        let new: Vec<DataType> = vec![1.into(), tags(&["b", "c"])];
+        let rs: Records = vec![(old, false), (new, true)].into();
+        assert_eq!(
+            g.narrow_one(rs, false),
+            vec![
+                (vec![1.into(), "a".into()], false),
+                (vec![1.into(), "b".into()], false),
+                (vec![1.into(), "b".into()], true),
+                (vec![1.into(), "c".into()], true),
+            ]
+            .into()
+        );
+    }
+
+    #[test]
+    fn it_handles_empty_and_scalar_values() {
+        let mut g = setup();
+        assert!(g
+            .narrow_one_row(vec![1.into(), tags(&[])], false)
+            .is_empty());
+        assert!(g
+            .narrow_one_row(vec![1.into(), DataType::None], false)
+            .is_empty());
+        let r: Vec<DataType> = vec![1.into(), "a".into()];
+        assert_eq!(g.narrow_one_row(r.clone(), false), vec![r].into());
+    }
+
+    #[test]
+    fn it_resolves() {
+        let g = setup();
+        let base = g.narrow_base_id().as_global();
+        assert_eq!(g.node().resolve(0), Some(vec![(base, 0)]));
+        assert_eq!(g.node().resolve(1), None);
+        assert_eq!(g.node().parent_columns(0), vec![(base, Some(0))]);
+        assert_eq!(g.node().parent_columns(1), vec![(base, None)]);
+    }
+}
@@ -7,9 +7,11 @@ use crate::prelude::*;
 use noria;
 use noria::channel;
 use noria::internal::LocalOrNot;
+use noria::{TableOperation, TraceContext};
 
 use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::io;
 use std::net::SocketAddr;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -18,12 +20,19 @@ pub struct ReplayPathSegment {
     pub partial_key: Option<Vec<usize>>,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum SourceSelection {
     /// Query only the shard of the source that matches the key.
     KeyShard {
         key_i_to_shard: usize,
         nshards: usize,
+        /// The split points between the ranges of keys each shard of the source owns, if the
+        /// source is a base table whose shards own ranges of keys.
+        #[serde(default)]
+        ranges: Vec<DataType>,
+        /// The share of the keys each shard of the source owns, if they are not spread evenly.
+        #[serde(default)]
+        weights: Vec<u32>,
     },
     /// Query the same shard of the source as the destination.
     SameShard,
@@ -33,6 +42,30 @@ pub enum SourceSelection {
     AllShards(usize),
 }
 
+/// A base table that another base table copies its rows and forwards its writes to while it is
+/// being resharded.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReshardTarget {
+    pub domain: domain::Index,
+    pub node: LocalNodeIndex,
+    /// The columns that the target is sharded by.
+    pub key: Vec<usize>,
+    pub shards: usize,
+    pub ranges: Vec<DataType>,
+    pub weights: Vec<u32>,
+}
+
+impl ReshardTarget {
+    /// The shard of the target that `op` must be sent to.
+    pub fn shard_of(&self, op: &TableOperation) -> usize {
+        if self.shards == 1 {
+            0
+        } else {
+            noria::shard_by_operation(op, &self.key, self.shards, &self.ranges, &self.weights)
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub enum TriggerEndpoint {
     None,
@@ -64,6 +97,9 @@ pub enum ReplayPieceContext {
         for_keys: HashSet<Vec<DataType>>,
         unishard: bool,
         ignore: bool,
+        /// The priority of the replay that this piece belongs to, which any upqueries it
+        /// triggers inherit.
+        priority: noria::ReplayPriority,
     },
     Regular {
         last: bool,
@@ -86,12 +122,16 @@ pub enum Packet {
         inner: LocalOrNot<Input>,
         src: Option<SourceChannelIdentifier>,
         senders: Vec<SourceChannelIdentifier>,
+        /// The span that the write is processed on behalf of, if it is traced.
+        trace: Option<TraceContext>,
     },
 
     /// Regular data-flow update.
     Message {
         link: Link,
         data: Records,
+        /// The span of the domain that sent the update, if it is part of a traced write.
+        trace: Option<TraceContext>,
     },
 
     /// Update that is part of a tagged data-flow replay path.
@@ -100,6 +140,11 @@ pub enum Packet {
         tag: Tag,
         data: Records,
         context: ReplayPieceContext,
+        /// A hash of `data`, set when the piece is sent to another domain with replay checksums
+        /// enabled, so that the receiving domain can tell whether it arrived intact.
+        checksum: Option<u64>,
+        /// The span of the domain that sent the piece, if it is part of a traced replay.
+        trace: Option<TraceContext>,
     },
 
     /// Trigger an eviction from the target node.
@@ -147,6 +192,12 @@ pub enum Packet {
         column: usize,
     },
 
+    /// Widens an integer column of a `Base` node.
+    WidenBaseColumn {
+        node: LocalNodeIndex,
+        column: usize,
+    },
+
     /// Update Egress node.
     UpdateEgress {
         node: LocalNodeIndex,
@@ -154,6 +205,12 @@ pub enum Packet {
         new_tag: Option<(Tag, NodeIndex)>,
     },
 
+    /// Stop sending from an egress node to the given ingress nodes, which are being removed.
+    RemoveEgressTargets {
+        node: LocalNodeIndex,
+        targets: Vec<NodeIndex>,
+    },
+
     /// Add a shard to a Sharder node.
     ///
     /// Note that this *must* be done *before* the sharder starts being used!
@@ -162,6 +219,15 @@ pub enum Packet {
         new_txs: (LocalNodeIndex, Vec<ReplicaAddr>),
     },
 
+    /// Make a Sharder node send the records of every key to the shard the key hashes to again.
+    StopSplittingKeys {
+        node: LocalNodeIndex,
+    },
+
+    /// Ask again for the keys of every partial replay the domain is still waiting for, since a
+    /// domain that replays pass through was rebuilt and lost whatever it was in the middle of.
+    RetryPartialReplays,
+
     /// Add a streamer to an existing reader node.
     AddStreamer {
         node: LocalNodeIndex,
@@ -195,6 +261,9 @@ pub enum Packet {
         tag: Tag,
         keys: Vec<Vec<DataType>>,
         unishard: bool,
+        priority: noria::ReplayPriority,
+        /// The span of the replay that the keys are requested for, if it is traced.
+        trace: Option<TraceContext>,
     },
 
     /// Ask domain (nicely) to replay a particular set of keys into a Reader.
@@ -221,6 +290,12 @@ pub enum Packet {
     /// Notification from Blender for domain to terminate
     Quit,
 
+    /// Stop accepting writes, apply and acknowledge the writes already received, and report the
+    /// final commit sequence numbers of the domain's base nodes on the control reply channel.
+    ///
+    /// Sent before a domain is torn down, so that no acknowledged write is lost with it.
+    Drain,
+
     /// A packet used solely to drive the event loop forward.
     Spin,
 
@@ -230,6 +305,160 @@ pub enum Packet {
 
     /// Ask domain to log its state size
     UpdateStateSize,
+
+    /// Request the audit log of the given base node on the control reply channel.
+    GetAuditLog {
+        node: LocalNodeIndex,
+    },
+
+    /// Request the distinct values of `columns` across all rows of the given base node on the
+    /// control reply channel.
+    GetBaseKeys {
+        node: LocalNodeIndex,
+        columns: Vec<usize>,
+    },
+
+    /// Request whether the given reader node can serve reads, whether it is paused, and the size
+    /// of its state, on the control reply channel.
+    GetReaderSummary {
+        node: LocalNodeIndex,
+    },
+
+    /// Request the sequence number of the last batch of writes committed to the given base node
+    /// on the control reply channel.
+    GetCommitSeq {
+        node: LocalNodeIndex,
+    },
+
+    /// Check that the rows the given base node keeps on disk are intact, optionally repairing
+    /// any problems, and send a report on the control reply channel.
+    VerifyBase {
+        node: LocalNodeIndex,
+        repair: bool,
+    },
+
+    /// Send the rows that the given node holds for `key` in `columns` on the control reply
+    /// channel.
+    SampleState {
+        node: LocalNodeIndex,
+        columns: Vec<usize>,
+        key: Vec<DataType>,
+    },
+
+    /// Stop applying updates to the given reader node, optionally evicting all of its state.
+    PauseReader {
+        node: LocalNodeIndex,
+        drop_state: bool,
+    },
+
+    /// Resume applying updates to the given reader node.
+    ResumeReader {
+        node: LocalNodeIndex,
+    },
+
+    /// Start or stop turning away writes from clients, since a migration is in progress.
+    SetMigrating {
+        migrating: bool,
+    },
+
+    /// Start or stop attributing client reads of the given reader node to client identities.
+    SetReadAttribution {
+        node: LocalNodeIndex,
+        enabled: bool,
+    },
+
+    /// Hide the values of some columns of the given reader node from clients, or stop hiding
+    /// them if `mask` is `None`.
+    SetReadMask {
+        node: LocalNodeIndex,
+        mask: Option<crate::ReadMask>,
+    },
+
+    /// Ask the given reader node for the client reads it has attributed.
+    GetReadAttribution {
+        node: LocalNodeIndex,
+    },
+
+    /// Set the priority of upqueries made to fill holes in the given reader node.
+    SetReplayPriority {
+        node: LocalNodeIndex,
+        priority: noria::ReplayPriority,
+    },
+
+    /// Report fingerprints for `keys` and up to `count` randomly chosen keys of the given reader
+    /// node on the control reply channel, and then evict those keys and re-fill them from
+    /// upstream. Keys that are not present in the reader are ignored.
+    SampleReaderKeys {
+        node: LocalNodeIndex,
+        count: usize,
+        keys: Vec<Vec<DataType>>,
+    },
+
+    /// Report fingerprints for the given keys of the given reader node on the control reply
+    /// channel.
+    FingerprintReaderKeys {
+        node: LocalNodeIndex,
+        keys: Vec<Vec<DataType>>,
+    },
+
+    /// Send all the rows of the given base node, along with its commit sequence number, on the
+    /// control reply channel.
+    GetBaseRows {
+        node: LocalNodeIndex,
+    },
+
+    /// Insert `rows` into the given base node, then have it continue numbering its commits after
+    /// `commit_seq`, and acknowledge once that has been persisted.
+    ImportRows {
+        node: LocalNodeIndex,
+        rows: Vec<Vec<DataType>>,
+        commit_seq: u64,
+    },
+
+    /// Insert `rows` into the given base node, and acknowledge once they have been processed.
+    InsertRows {
+        node: LocalNodeIndex,
+        rows: Vec<Vec<DataType>>,
+    },
+
+    /// Copy the rows of the given base node to `to`, and from then on forward every write the
+    /// base accepts there as well. Acknowledge once the rows have been sent.
+    StartResharding {
+        node: LocalNodeIndex,
+        to: ReshardTarget,
+    },
+
+    /// Start or stop holding on to the writes that clients send.
+    ///
+    /// A domain that starts holding applies the writes it has already accepted, and reports the
+    /// commit sequence numbers of its base nodes on the control reply channel like `Drain`. Held
+    /// writes are neither applied nor answered until the domain stops holding them, and then
+    /// handled in the order they arrived. Stopping is acknowledged.
+    HoldWrites {
+        hold: bool,
+    },
+
+    /// Acknowledge once `expected` `SettleMarker`s have arrived, and then send a `SettleMarker`
+    /// to each of `downstream`.
+    ///
+    /// Sent to every domain while writes are held. Since a marker follows all the updates its
+    /// sender sent before it, a domain that has heard from every domain above it has applied all
+    /// the writes that were accepted before they were held.
+    Settle {
+        expected: usize,
+        downstream: Vec<(domain::Index, usize)>,
+    },
+
+    /// Sent by a settled domain to the domains below it. See `Settle`.
+    SettleMarker,
+
+    /// Send all the rows the given reader node currently holds to the base node `to` in the first
+    /// shard of `domain` with `InsertRows`, and acknowledge once they have been sent.
+    CopyReaderRows {
+        node: LocalNodeIndex,
+        domain: domain::Index,
+        to: LocalNodeIndex,
+    },
 }
 
 impl Packet {
@@ -292,6 +521,45 @@ impl Packet {
         }
     }
 
+    /// Whether the packet carries updates, replayed records, evictions, or requests for replays,
+    /// which would leave the state downstream of its domain diverged or waiting if it were lost.
+    pub fn carries_updates(&self) -> bool {
+        match *self {
+            Packet::Input { .. }
+            | Packet::Message { .. }
+            | Packet::ReplayPiece { .. }
+            | Packet::EvictKeys { .. }
+            | Packet::Finish(..)
+            | Packet::RequestPartialReplay { .. }
+            | Packet::RequestReaderReplay { .. }
+            | Packet::StartReplay { .. }
+            | Packet::ImportRows { .. }
+            | Packet::InsertRows { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// The span that the packet is sent on behalf of, if it is part of a traced write or replay.
+    pub(crate) fn trace(&self) -> Option<TraceContext> {
+        match *self {
+            Packet::Input { trace, .. }
+            | Packet::Message { trace, .. }
+            | Packet::ReplayPiece { trace, .. } => trace,
+            _ => None,
+        }
+    }
+
+    pub(crate) fn set_trace(&mut self, cx: Option<TraceContext>) {
+        match *self {
+            Packet::Input { ref mut trace, .. }
+            | Packet::Message { ref mut trace, .. }
+            | Packet::ReplayPiece { ref mut trace, .. } => {
+                *trace = cx;
+            }
+            _ => {}
+        }
+    }
+
     pub(crate) fn tag(&self) -> Option<Tag> {
         match *self {
             Packet::ReplayPiece { tag, .. } => Some(tag),
@@ -318,28 +586,89 @@ impl Packet {
         mem::replace(inner, Records::default())
     }
 
+    /// Set the checksum of a replay piece to that of its data.
+    pub(crate) fn stamp_checksum(&mut self) {
+        if let Packet::ReplayPiece {
+            ref data,
+            ref mut checksum,
+            ..
+        } = *self
+        {
+            *checksum = Some(replay_checksum(data));
+        }
+    }
+
+    /// Whether this is a replay piece whose data does not match the checksum it was sent with.
+    pub(crate) fn is_corrupt(&self) -> bool {
+        match *self {
+            Packet::ReplayPiece {
+                ref data,
+                checksum: Some(checksum),
+                ..
+            } => replay_checksum(data) != checksum,
+            _ => false,
+        }
+    }
+
     pub(crate) fn clone_data(&self) -> Self {
         match *self {
-            Packet::Message { link, ref data } => Packet::Message {
+            Packet::Message {
+                link,
+                ref data,
+                trace,
+            } => Packet::Message {
                 link,
                 data: data.clone(),
+                trace,
             },
             Packet::ReplayPiece {
                 link,
                 tag,
                 ref data,
                 ref context,
+                checksum,
+                trace,
             } => Packet::ReplayPiece {
                 link,
                 tag,
                 data: data.clone(),
                 context: context.clone(),
+                checksum,
+                trace,
             },
             _ => unreachable!(),
         }
     }
 }
 
+/// A hash of the records of a replay piece.
+///
+/// The checksum is computed by the sending domain and checked by the receiving one, which may run
+/// a different build on a different worker. It is therefore the FNV-1a hash of the records'
+/// bincode encoding, both of which are fixed, rather than whatever `Hash` and the standard
+/// library's hasher happen to do.
+fn replay_checksum(data: &Records) -> u64 {
+    use std::hash::Hasher;
+
+    struct Checksum(fnv::FnvHasher);
+    impl io::Write for Checksum {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut checksum = Checksum(fnv::FnvHasher::default());
+    for r in data.iter() {
+        checksum.0.write_u8(r.is_positive() as u8);
+        bincode::serialize_into(&mut checksum, r.rec()).expect("records are always encodable");
+    }
+    checksum.0.finish()
+}
+
 impl fmt::Debug for Packet {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -381,6 +710,28 @@ pub enum ControlReplyPacket {
         HashMap<petgraph::graph::NodeIndex, noria::debug::stats::NodeStats>,
     ),
     Booted(usize, SocketAddr),
+    /// The audit log of a base node, or `None` if auditing is not enabled for it.
+    AuditLog(Option<Vec<noria::AuditEntry>>),
+    /// Distinct keys of a base node, or `None` if the base node is not fully materialized.
+    BaseKeys(Option<Vec<Vec<DataType>>>),
+    /// The shard of a base node, and the sequence number of the last batch it committed.
+    CommitSeq(usize, u64),
+    /// The shard of a base node, the sequence number of the last batch it committed, and its
+    /// rows, or `None` if the base node does not keep all of its rows.
+    BaseRows(usize, u64, Option<Vec<Vec<DataType>>>),
+    /// The outcome of verifying the on-disk rows of a shard of a base node.
+    BaseVerification(noria::BaseVerification),
+    /// Whether a node keeps state, and its rows for a sampled key if it has them.
+    StateSample(bool, Option<Vec<Vec<DataType>>>),
+    /// Whether a reader node can serve reads, whether it is paused, and its memory size in bytes.
+    ReaderSummary(bool, bool, u64),
+    /// Fingerprints of keys in a reader node, or `None` for keys that are currently holes.
+    Fingerprints(Vec<(Vec<DataType>, Option<u64>)>),
+    /// The shard of a drained domain, and the last commit sequence number of each of its base
+    /// nodes.
+    Drained(usize, Vec<(petgraph::graph::NodeIndex, u64)>),
+    /// The client reads of a reader node by each client identity.
+    ReadAttribution(Vec<noria::debug::stats::ReadAttribution>),
 }
 
 impl ControlReplyPacket {
@@ -388,3 +739,67 @@ impl ControlReplyPacket {
         ControlReplyPacket::Ack(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn piece(data: Records) -> Packet {
+        let local = unsafe { LocalNodeIndex::make(0) };
+        Packet::ReplayPiece {
+            link: Link::new(local, local),
+            tag: Tag(0),
+            data,
+            context: ReplayPieceContext::Regular { last: true },
+            checksum: None,
+            trace: None,
+        }
+    }
+
+    #[test]
+    fn corrupt_replay_pieces() {
+        let rows = |price: i32| -> Records {
+            vec![
+                vec![1.into(), price.into(), "a".into()],
+                vec![2.into(), 10.into(), "b".into()],
+            ]
+            .into_iter()
+            .collect()
+        };
+
+        // pieces without a checksum are never corrupt
+        let mut p = piece(rows(10));
+        assert!(!p.is_corrupt());
+        p.stamp_checksum();
+        assert!(!p.is_corrupt());
+
+        // the checksum only depends on the records, so equal records get equal checksums
+        let mut q = piece(rows(10));
+        q.stamp_checksum();
+        match (&p, &q) {
+            (Packet::ReplayPiece { checksum: a, .. }, Packet::ReplayPiece { checksum: b, .. }) => {
+                assert_eq!(a, b)
+            }
+            _ => unreachable!(),
+        }
+
+        // a changed value, a changed sign, and a lost record are all caught
+        if let Packet::ReplayPiece { ref mut data, .. } = p {
+            *data = rows(11);
+        }
+        assert!(p.is_corrupt());
+        if let Packet::ReplayPiece { ref mut data, .. } = q {
+            *data = rows(10)
+                .into_iter()
+                .map(|r| Record::from((r.extract().0, false)))
+                .collect();
+        }
+        assert!(q.is_corrupt());
+        let mut r = piece(rows(10));
+        r.stamp_checksum();
+        if let Packet::ReplayPiece { ref mut data, .. } = r {
+            data.pop();
+        }
+        assert!(r.is_corrupt());
+    }
+}
@@ -0,0 +1,89 @@
+/// Never keep more than this many buffers around.
+const MAX_POOLED: usize = 64;
+
+/// Don't keep buffers that grew beyond this many elements, so that one unusually large batch
+/// does not pin its memory forever.
+const MAX_RETAINED_CAPACITY: usize = 4096;
+
+/// A pool of reusable vectors.
+///
+/// Buffers handed out by `take` are empty, but may have capacity left over from a previous use,
+/// which saves re-allocating (and re-growing) a fresh vector for every packet.
+pub(crate) struct BufferPool<T> {
+    free: Vec<Vec<T>>,
+    reused: u64,
+    allocated: u64,
+}
+
+impl<T> Default for BufferPool<T> {
+    fn default() -> Self {
+        BufferPool {
+            free: Vec::new(),
+            reused: 0,
+            allocated: 0,
+        }
+    }
+}
+
+impl<T> BufferPool<T> {
+    /// Get an empty buffer, reusing a previously recycled one if possible.
+    pub(crate) fn take(&mut self) -> Vec<T> {
+        match self.free.pop() {
+            Some(v) => {
+                self.reused += 1;
+                v
+            }
+            None => {
+                self.allocated += 1;
+                Vec::new()
+            }
+        }
+    }
+
+    /// Return a buffer to the pool so that a later `take` can reuse its allocation.
+    pub(crate) fn recycle(&mut self, mut v: Vec<T>) {
+        if v.capacity() == 0
+            || v.capacity() > MAX_RETAINED_CAPACITY
+            || self.free.len() >= MAX_POOLED
+        {
+            return;
+        }
+        v.clear();
+        self.free.push(v);
+    }
+
+    /// The number of buffers handed out that reused an earlier allocation.
+    pub(crate) fn reused(&self) -> u64 {
+        self.reused
+    }
+
+    /// The number of buffers handed out that had to be freshly allocated.
+    pub(crate) fn allocated(&self) -> u64 {
+        self.allocated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_allocations() {
+        let mut pool = BufferPool::default();
+        let mut v = pool.take();
+        v.extend(0..10);
+        let cap = v.capacity();
+        pool.recycle(v);
+
+        let v = pool.take();
+        assert!(v.is_empty());
+        assert_eq!(v.capacity(), cap);
+        assert_eq!(pool.allocated(), 1);
+        assert_eq!(pool.reused(), 1);
+
+        // buffers that never allocated are not worth keeping
+        pool.recycle(Vec::<usize>::new());
+        let _ = pool.take();
+        assert_eq!(pool.allocated(), 2);
+    }
+}
@@ -41,7 +41,21 @@ pub use crate::PersistenceParameters;
 /// Channel coordinator type specialized for domains
 pub type ChannelCoordinator = noria::channel::ChannelCoordinator<(DomainIndex, usize), Box<Packet>>;
 pub trait Executor {
-    fn ack(&mut self, tag: SourceChannelIdentifier);
+    /// Acknowledge a write, which was committed to its base table with sequence number `seq`.
+    fn ack(&mut self, tag: SourceChannelIdentifier, seq: u64);
+    /// Reject a write without applying it, for the given reason.
+    fn reject(&mut self, tag: SourceChannelIdentifier, why: noria::WriteRejection);
     fn create_universe(&mut self, req: HashMap<String, DataType>);
     fn send(&mut self, dest: ReplicaAddr, m: Box<Packet>);
+    /// Report the statistics of the domain shard `from`, and of those of its nodes that are
+    /// included, to the controller.
+    fn report_statistics(
+        &mut self,
+        from: ReplicaAddr,
+        domain: noria::debug::stats::DomainStats,
+        nodes: HashMap<NodeIndex, noria::debug::stats::NodeStats>,
+    );
+    /// Report to the controller that the domain shard `from` received a replay piece for `tag`
+    /// whose data did not match its checksum, and whether the domain recovered from it on its own.
+    fn report_corrupt_replay(&mut self, from: ReplicaAddr, tag: Tag, keys: usize, recovered: bool);
 }
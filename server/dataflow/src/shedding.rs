@@ -0,0 +1,196 @@
+use std::time;
+
+/// Something a domain can give up on to keep up with its input when it is overloaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ShedAction {
+    /// Stop tracking the hot keys of records routed through sharders.
+    DropTraces,
+    /// Hold back upqueries with `ReplayPriority::Batch` until the domain is no longer overloaded.
+    DelayBatchReplays,
+    /// Reject writes to the policy's best-effort tables without applying them.
+    RejectBestEffortWrites,
+}
+
+/// Describes what a domain should shed, and in what order, when it is overloaded.
+///
+/// A domain is considered overloaded during a window if it spent more than `busy_threshold` of
+/// that window processing events. Every overloaded window enables the next action in `actions`,
+/// and every window that is not overloaded disables the most recently enabled one again.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LoadSheddingPolicy {
+    /// Fraction of wall-clock time, between 0 and 1, a domain may spend busy before it sheds load.
+    pub busy_threshold: f64,
+    /// How often a domain re-evaluates whether it is overloaded.
+    pub window: time::Duration,
+    /// Actions to take, in the order in which they are enabled.
+    pub actions: Vec<ShedAction>,
+    /// Tables whose writes may be rejected under `ShedAction::RejectBestEffortWrites`.
+    pub best_effort_tables: Vec<String>,
+}
+
+impl Default for LoadSheddingPolicy {
+    fn default() -> Self {
+        LoadSheddingPolicy {
+            busy_threshold: 0.9,
+            window: time::Duration::from_secs(1),
+            actions: vec![
+                ShedAction::DropTraces,
+                ShedAction::DelayBatchReplays,
+                ShedAction::RejectBestEffortWrites,
+            ],
+            best_effort_tables: Vec::new(),
+        }
+    }
+}
+
+/// Enforces a `LoadSheddingPolicy` for a single domain.
+pub(crate) struct LoadShedder {
+    policy: LoadSheddingPolicy,
+    window_start: time::Instant,
+    busy: time::Duration,
+    /// Number of actions from the front of `policy.actions` that are currently enabled.
+    level: usize,
+
+    pub(crate) overloaded_windows: u64,
+    pub(crate) delayed_replays: u64,
+    pub(crate) rejected_writes: u64,
+}
+
+impl LoadShedder {
+    pub(crate) fn new(policy: LoadSheddingPolicy, now: time::Instant) -> Self {
+        LoadShedder {
+            policy,
+            window_start: now,
+            busy: time::Duration::from_secs(0),
+            level: 0,
+            overloaded_windows: 0,
+            delayed_replays: 0,
+            rejected_writes: 0,
+        }
+    }
+
+    /// Account for time spent processing an event.
+    pub(crate) fn record_busy(&mut self, busy: time::Duration) {
+        self.busy += busy;
+    }
+
+    /// Close the current window if it has ended, and adjust which actions are enabled.
+    ///
+    /// Returns the action that was enabled or disabled as a result, if any, and whether it was
+    /// enabled.
+    pub(crate) fn tick(&mut self, now: time::Instant) -> Option<(ShedAction, bool)> {
+        let elapsed = now.duration_since(self.window_start);
+        if elapsed < self.policy.window {
+            return None;
+        }
+
+        let load = self.busy.as_secs_f64() / elapsed.as_secs_f64();
+        self.window_start = now;
+        self.busy = time::Duration::from_secs(0);
+
+        if load > self.policy.busy_threshold {
+            self.overloaded_windows += 1;
+            if self.level < self.policy.actions.len() {
+                self.level += 1;
+                return Some((self.policy.actions[self.level - 1], true));
+            }
+        } else if self.level > 0 {
+            self.level -= 1;
+            return Some((self.policy.actions[self.level], false));
+        }
+        None
+    }
+
+    /// How long until the current window ends, if any actions are enabled.
+    ///
+    /// While nothing is being shed, there is no need to wake up the domain just to notice that it
+    /// is idle; the next event will close the window.
+    pub(crate) fn duration_until_tick(&self, now: time::Instant) -> Option<time::Duration> {
+        if self.level == 0 {
+            return None;
+        }
+        Some(
+            (self.window_start + self.policy.window)
+                .checked_duration_since(now)
+                .unwrap_or(time::Duration::from_millis(0)),
+        )
+    }
+
+    pub(crate) fn is_shedding(&self, action: ShedAction) -> bool {
+        self.policy.actions[..self.level].contains(&action)
+    }
+
+    pub(crate) fn is_best_effort(&self, table: &str) -> bool {
+        self.policy.best_effort_tables.iter().any(|t| t == table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> LoadSheddingPolicy {
+        LoadSheddingPolicy {
+            busy_threshold: 0.5,
+            window: time::Duration::from_millis(100),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn escalates_one_action_per_overloaded_window() {
+        let start = time::Instant::now();
+        let mut s = LoadShedder::new(policy(), start);
+        assert!(!s.is_shedding(ShedAction::DropTraces));
+
+        // window hasn't ended yet
+        s.record_busy(time::Duration::from_millis(90));
+        assert_eq!(s.tick(start + time::Duration::from_millis(50)), None);
+
+        let t1 = start + time::Duration::from_millis(100);
+        assert_eq!(s.tick(t1), Some((ShedAction::DropTraces, true)));
+        assert!(s.is_shedding(ShedAction::DropTraces));
+        assert!(!s.is_shedding(ShedAction::DelayBatchReplays));
+
+        s.record_busy(time::Duration::from_millis(90));
+        let t2 = t1 + time::Duration::from_millis(100);
+        assert_eq!(s.tick(t2), Some((ShedAction::DelayBatchReplays, true)));
+
+        s.record_busy(time::Duration::from_millis(90));
+        let t3 = t2 + time::Duration::from_millis(100);
+        assert_eq!(s.tick(t3), Some((ShedAction::RejectBestEffortWrites, true)));
+
+        // nothing left to enable
+        s.record_busy(time::Duration::from_millis(90));
+        let t4 = t3 + time::Duration::from_millis(100);
+        assert_eq!(s.tick(t4), None);
+        assert_eq!(s.overloaded_windows, 4);
+    }
+
+    #[test]
+    fn de_escalates_when_idle() {
+        let start = time::Instant::now();
+        let mut s = LoadShedder::new(policy(), start);
+        assert_eq!(s.duration_until_tick(start), None);
+
+        s.record_busy(time::Duration::from_millis(100));
+        let t1 = start + time::Duration::from_millis(100);
+        s.tick(t1);
+        s.record_busy(time::Duration::from_millis(100));
+        let t2 = t1 + time::Duration::from_millis(100);
+        s.tick(t2);
+        assert!(s.is_shedding(ShedAction::DelayBatchReplays));
+        assert_eq!(
+            s.duration_until_tick(t2 + time::Duration::from_millis(30)),
+            Some(time::Duration::from_millis(70))
+        );
+
+        let t3 = t2 + time::Duration::from_millis(100);
+        assert_eq!(s.tick(t3), Some((ShedAction::DelayBatchReplays, false)));
+        assert!(s.is_shedding(ShedAction::DropTraces));
+        let t4 = t3 + time::Duration::from_millis(100);
+        assert_eq!(s.tick(t4), Some((ShedAction::DropTraces, false)));
+        assert!(!s.is_shedding(ShedAction::DropTraces));
+        assert_eq!(s.duration_until_tick(t4), None);
+    }
+}
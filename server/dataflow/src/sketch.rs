@@ -0,0 +1,97 @@
+use crate::prelude::*;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// Number of independent hash rows in the count-min sketch.
+const DEPTH: usize = 4;
+/// Number of counters in each row of the count-min sketch.
+const WIDTH: usize = 1024;
+/// Number of heavy hitters to keep track of.
+const TOP_K: usize = 10;
+
+/// Approximate tracking of the most frequently observed keys.
+///
+/// Key frequencies are estimated using a count-min sketch, which may over-estimate (but never
+/// under-estimate) how often a key was seen. Alongside the sketch, we keep the `TOP_K` keys with
+/// the highest estimates seen so far, so that they can be reported without scanning all keys.
+pub(crate) struct HeavyHitters {
+    counters: Vec<u64>,
+    top: Vec<(Vec<DataType>, u64)>,
+}
+
+impl Default for HeavyHitters {
+    fn default() -> Self {
+        HeavyHitters {
+            counters: vec![0; DEPTH * WIDTH],
+            top: Vec::with_capacity(TOP_K),
+        }
+    }
+}
+
+impl HeavyHitters {
+    fn slot(row: usize, key: &[DataType]) -> usize {
+        let mut hasher = DefaultHasher::new();
+        row.hash(&mut hasher);
+        key.hash(&mut hasher);
+        row * WIDTH + (hasher.finish() as usize % WIDTH)
+    }
+
+    /// Record one occurrence of `key`.
+    pub(crate) fn observe(&mut self, key: &[DataType]) {
+        let mut estimate = u64::max_value();
+        for row in 0..DEPTH {
+            let c = &mut self.counters[Self::slot(row, key)];
+            *c += 1;
+            estimate = estimate.min(*c);
+        }
+
+        if let Some(e) = self.top.iter_mut().find(|(k, _)| &k[..] == key) {
+            e.1 = estimate;
+            return;
+        }
+
+        if self.top.len() < TOP_K {
+            self.top.push((Vec::from(key), estimate));
+            return;
+        }
+
+        // replace the coldest tracked key if this one is now hotter
+        let (coldest, &(_, count)) = self
+            .top
+            .iter()
+            .enumerate()
+            .min_by_key(|&(_, &(_, count))| count)
+            .unwrap();
+        if estimate > count {
+            self.top[coldest] = (Vec::from(key), estimate);
+        }
+    }
+
+    /// The estimated hottest keys, hottest first.
+    pub(crate) fn top(&self) -> Vec<(Vec<DataType>, u64)> {
+        let mut top = self.top.clone();
+        top.sort_by(|a, b| b.1.cmp(&a.1));
+        top
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_heavy_hitters() {
+        let mut hh = HeavyHitters::default();
+        for i in 0..1000 {
+            hh.observe(&[(i % 100).into()]);
+            if i % 2 == 0 {
+                hh.observe(&[1000.into()]);
+            }
+        }
+
+        let top = hh.top();
+        assert_eq!(top.len(), TOP_K);
+        assert_eq!(top[0].0, vec![1000.into()]);
+        assert!(top[0].1 >= 500);
+    }
+}
@@ -74,8 +74,8 @@ impl KeyedState {
         ))
     }
 
-    /// Remove all rows for the given key, returning the number of bytes freed.
-    pub(super) fn evict(&mut self, key: &[DataType]) -> u64 {
+    /// Remove the given key, returning the rows that were stored under it.
+    pub(super) fn remove(&mut self, key: &[DataType]) -> Option<Rows> {
         match *self {
             KeyedState::Single(ref mut m) => m.swap_remove(&(key[0])),
             KeyedState::Double(ref mut m) => {
@@ -94,13 +94,18 @@ impl KeyedState {
                 m.swap_remove::<(DataType, _, _, _, _, _)>(&MakeKey::from_key(key))
             }
         }
-        .map(|rows| {
-            rows.iter()
-                .filter(|r| Rc::strong_count(&r.0) == 1)
-                .map(SizeOf::deep_size_of)
-                .sum()
-        })
-        .unwrap_or(0)
+    }
+
+    /// Remove all rows for the given key, returning the number of bytes freed.
+    pub(super) fn evict(&mut self, key: &[DataType]) -> u64 {
+        self.remove(key)
+            .map(|rows| {
+                rows.iter()
+                    .filter(|r| Rc::strong_count(&r.0) == 1)
+                    .map(SizeOf::deep_size_of)
+                    .sum()
+            })
+            .unwrap_or(0)
     }
 }
 
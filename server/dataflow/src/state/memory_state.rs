@@ -12,6 +12,7 @@ pub struct MemoryState {
     state: Vec<SingleState>,
     by_tag: HashMap<Tag, usize>,
     mem_size: u64,
+    spill_threshold: Option<usize>,
 }
 
 impl SizeOf for MemoryState {
@@ -46,8 +47,11 @@ impl State for MemoryState {
             return;
         }
 
-        self.state
-            .push(SingleState::new(columns, partial.is_some()));
+        self.state.push(SingleState::new(
+            columns,
+            partial.is_some(),
+            self.spill_threshold,
+        ));
 
         if !self.state.is_empty() && partial.is_none() {
             // we need to *construct* the index!
@@ -55,11 +59,23 @@ impl State for MemoryState {
 
             if !old.is_empty() {
                 assert!(!old[0].partial());
+                let mut freed = 0;
                 for rs in old[0].values() {
                     for r in rs {
-                        new.insert_row(Row::from(r.0.clone()));
+                        new.insert_row(Row::from(r.0.clone()), &mut freed);
                     }
                 }
+
+                // rows that the old index has moved to disk are only in memory if the new index
+                // keeps them there
+                for r in old[0].spilled_records() {
+                    let r = Rc::new(r);
+                    new.insert_row(Row::from(r.clone()), &mut freed);
+                    if Rc::strong_count(&r) > 1 {
+                        self.mem_size += r.deep_size_of();
+                    }
+                }
+                self.mem_size = self.mem_size.saturating_sub(freed);
             }
         }
     }
@@ -146,7 +162,11 @@ impl State for MemoryState {
         }
 
         assert!(!self.state[0].partial());
-        self.state[0].values().flat_map(fix).collect()
+        self.state[0]
+            .values()
+            .flat_map(fix)
+            .chain(self.state[0].spilled_records())
+            .collect()
     }
 
     fn evict_random_keys(&mut self, count: usize) -> (&[usize], Vec<Vec<DataType>>, u64) {
@@ -174,9 +194,25 @@ impl State for MemoryState {
         }
         self.mem_size = 0;
     }
+
+    fn spill_keys_over(&mut self, rows: usize) -> u64 {
+        let freed: u64 = self.state.iter_mut().map(|s| s.spill_keys_over(rows)).sum();
+        self.mem_size = self.mem_size.saturating_sub(freed);
+        freed
+    }
 }
 
 impl MemoryState {
+    /// Construct a new, empty state in which keys with more than `threshold` rows are moved to
+    /// disk. This bounds the memory used by any single key, for example a join key that almost
+    /// every record shares, at the cost of reading that key's rows back from disk on lookup.
+    pub(crate) fn with_spill_threshold(threshold: Option<usize>) -> Self {
+        MemoryState {
+            spill_threshold: threshold,
+            ..Default::default()
+        }
+    }
+
     /// Returns the index in `self.state` of the index keyed on `cols`, or None if no such index
     /// exists.
     fn state_for(&self, cols: &[usize]) -> Option<usize> {
@@ -196,16 +232,24 @@ impl MemoryState {
                     return true;
                 }
             };
-            self.mem_size += r.deep_size_of();
-            self.state[i].insert_row(Row::from(r))
+            let mut freed = 0;
+            let hit = self.state[i].insert_row(Row::from(r.clone()), &mut freed);
+            if Rc::strong_count(&r) > 1 {
+                self.mem_size += r.deep_size_of();
+            }
+            self.mem_size = self.mem_size.saturating_sub(freed);
+            hit
         } else {
             let mut hit_any = false;
+            let mut freed = 0;
             for i in 0..self.state.len() {
-                hit_any |= self.state[i].insert_row(Row::from(r.clone()));
+                hit_any |= self.state[i].insert_row(Row::from(r.clone()), &mut freed);
             }
-            if hit_any {
+            // the row may have gone straight to disk in every index
+            if Rc::strong_count(&r) > 1 {
                 self.mem_size += r.deep_size_of();
             }
+            self.mem_size = self.mem_size.saturating_sub(freed);
             hit_any
         }
     }
@@ -265,6 +309,70 @@ mod tests {
         }
     }
 
+    #[test]
+    fn memory_state_spills_large_keys() {
+        let mut state = MemoryState::with_spill_threshold(Some(2));
+        state.add_key(&[0], None);
+        insert(&mut state, vec![1.into(), "A".into()]);
+        let small = state.deep_size_of();
+
+        for i in 0..10 {
+            insert(&mut state, vec![2.into(), i.into()]);
+        }
+        state.process_records(&mut vec![(vec![2.into(), 3.into()], false)].into(), None);
+        // removing a spilled row that isn't there changes nothing
+        state.process_records(&mut vec![(vec![2.into(), 42.into()], false)].into(), None);
+        // only the row for key 1 remains in memory
+        assert_eq!(state.deep_size_of(), small);
+        assert_eq!(state.rows(), 10);
+
+        match state.lookup(&[0], &KeyType::Single(&2.into())) {
+            LookupResult::Some(RecordResult::Owned(mut rows)) => {
+                rows.sort();
+                let expected: Vec<Vec<DataType>> = (0..10)
+                    .filter(|&i| i != 3)
+                    .map(|i| vec![2.into(), i.into()])
+                    .collect();
+                assert_eq!(rows, expected);
+            }
+            _ => unreachable!(),
+        };
+        match state.lookup(&[0], &KeyType::Single(&1.into())) {
+            LookupResult::Some(RecordResult::Borrowed(rows)) => assert_eq!(rows.len(), 1),
+            _ => unreachable!(),
+        };
+
+        // spilled rows are still visible when building a new index
+        state.add_key(&[1], None);
+        match state.lookup(&[1], &KeyType::Single(&5.into())) {
+            LookupResult::Some(RecordResult::Borrowed(rows)) => assert_eq!(rows.len(), 1),
+            _ => unreachable!(),
+        };
+        assert_eq!(state.cloned_records().len(), 10);
+    }
+
+    #[test]
+    fn memory_state_spills_large_keys_on_demand() {
+        let mut state = MemoryState::default();
+        state.add_key(&[0], None);
+        insert(&mut state, vec![1.into(), "A".into()]);
+        let small = state.deep_size_of();
+        for i in 0..10 {
+            insert(&mut state, vec![2.into(), i.into()]);
+        }
+
+        assert!(state.spill_keys_over(2) > 0);
+        assert_eq!(state.deep_size_of(), small);
+        assert_eq!(state.rows(), 11);
+        match state.lookup(&[0], &KeyType::Single(&2.into())) {
+            LookupResult::Some(RecordResult::Owned(rows)) => assert_eq!(rows.len(), 10),
+            _ => unreachable!(),
+        };
+
+        // nothing is left to spill
+        assert_eq!(state.spill_keys_over(2), 0);
+    }
+
     #[test]
     fn memory_state_old_records_new_index() {
         let mut state = MemoryState::default();
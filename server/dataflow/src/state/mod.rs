@@ -3,6 +3,8 @@ mod memory_state;
 mod mk_key;
 mod persistent_state;
 mod single_state;
+mod spill;
+pub(crate) mod versioned;
 
 use std::borrow::Cow;
 use std::ops::Deref;
@@ -52,6 +54,33 @@ pub(crate) trait State: SizeOf + Send {
     fn evict_keys(&mut self, tag: Tag, keys: &[Vec<DataType>]) -> Option<(&[usize], u64)>;
 
     fn clear(&mut self);
+
+    /// Move the rows of every key with more than `rows` rows to disk, returning the number of
+    /// bytes freed.
+    ///
+    /// States that keep their rows on disk already have nothing to spill, and return 0.
+    fn spill_keys_over(&mut self, _rows: usize) -> u64 {
+        0
+    }
+
+    /// Persist `seq` as the commit sequence number of the base this state belongs to, atomically
+    /// with the next records passed to `process_records`.
+    ///
+    /// States that aren't durable have nothing to persist it to, and ignore it.
+    fn set_commit_seq(&mut self, _seq: u64) {}
+
+    /// The commit sequence number last persisted with `set_commit_seq`, if any.
+    fn commit_seq(&self) -> Option<u64> {
+        None
+    }
+
+    /// Check that the rows this state keeps on disk are intact, optionally repairing any problems
+    /// that were found. See `noria::BaseVerification`.
+    ///
+    /// States that aren't durable have nothing to verify, and return `None`.
+    fn verify(&mut self, _repair: bool) -> Option<noria::BaseVerification> {
+        None
+    }
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
@@ -5,8 +5,13 @@ use serde;
 use tempfile::{tempdir, TempDir};
 
 use crate::prelude::*;
+use crate::state::versioned::{self, Versioned};
 use crate::state::{RecordResult, State};
 use common::SizeOf;
+use noria::BaseVerification;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 
 // Incremented on each PersistentState initialization so that IndexSeq
 // can be used to create unique identifiers for rows.
@@ -17,6 +22,8 @@ type IndexSeq = u64;
 
 // RocksDB key used for storing meta information (like indices).
 const META_KEY: &[u8] = b"meta";
+// RocksDB key used for storing the commit sequence number of the base.
+const COMMIT_SEQ_KEY: &[u8] = b"commit_seq";
 // A default column family is always created, so we'll make use of that for meta information.
 // The indices themselves are stored in a column family each, with their position in
 // PersistentState::indices as name.
@@ -32,6 +39,18 @@ struct PersistentMeta {
     epoch: IndexEpoch,
 }
 
+impl Versioned for PersistentMeta {
+    const VERSION: u32 = 1;
+
+    fn upgrade(version: u32, data: &[u8]) -> bincode::Result<Self> {
+        match version {
+            // the layout is unchanged, the meta data just didn't say which version it was
+            0 => bincode::deserialize(data),
+            _ => unreachable!("unknown meta data version {}", version),
+        }
+    }
+}
+
 #[derive(Clone)]
 struct PersistentIndex {
     column_family: String,
@@ -53,6 +72,8 @@ pub struct PersistentState {
     seq: IndexSeq,
     epoch: IndexEpoch,
     has_unique_index: bool,
+    // The base's commit sequence number, if it has changed since it was last written to RocksDB.
+    pending_commit_seq: Option<u64>,
     // With DurabilityMode::DeleteOnExit,
     // RocksDB files are stored in a temporary directory.
     _directory: Option<TempDir>,
@@ -61,11 +82,17 @@ pub struct PersistentState {
 impl State for PersistentState {
     fn process_records(&mut self, records: &mut Records, partial_tag: Option<Tag>) {
         assert!(partial_tag.is_none(), "PersistentState can't be partial");
-        if records.len() == 0 {
+        if records.len() == 0 && self.pending_commit_seq.is_none() {
             return;
         }
 
         let mut batch = WriteBatch::default();
+        if let Some(seq) = self.pending_commit_seq.take() {
+            // written in the same batch as the records so that the two never disagree
+            batch
+                .put(COMMIT_SEQ_KEY, &bincode::serialize(&seq).unwrap())
+                .unwrap();
+        }
         for r in records.iter() {
             match *r {
                 Record::Positive(ref r) => {
@@ -211,6 +238,116 @@ impl State for PersistentState {
     fn clear(&mut self) {
         unreachable!("can't clear PersistentState")
     }
+
+    fn set_commit_seq(&mut self, seq: u64) {
+        self.pending_commit_seq = Some(seq);
+    }
+
+    fn commit_seq(&self) -> Option<u64> {
+        if let Some(seq) = self.pending_commit_seq {
+            return Some(seq);
+        }
+        self.db
+            .as_ref()
+            .unwrap()
+            .get(COMMIT_SEQ_KEY)
+            .unwrap()
+            .map(|raw| bincode::deserialize(&*raw).unwrap())
+    }
+
+    // NOTE: our SST files use RocksDB's plain table format, which has no block checksums, so
+    // decoding every row and index entry is the only way to tell that something is wrong.
+    fn verify(&mut self, repair: bool) -> Option<BaseVerification> {
+        let mut report = BaseVerification {
+            durable: true,
+            ..Default::default()
+        };
+        let db = self.db.as_ref().unwrap();
+        let mut batch = WriteBatch::default();
+
+        if let Some(raw) = db.get(COMMIT_SEQ_KEY).unwrap() {
+            if bincode::deserialize::<u64>(&*raw).is_err() {
+                // the next committed write will store a new one
+                report.corrupt_commit_seq = true;
+                batch.delete(COMMIT_SEQ_KEY).unwrap();
+            }
+        }
+
+        if !self.indices.is_empty() {
+            // The fingerprint of every intact row, by the primary key it is stored under.
+            let mut intact = HashMap::new();
+            let value_cf = db.cf_handle(&self.indices[0].column_family).unwrap();
+            for (pk, raw) in db
+                .full_iterator_cf(value_cf, rocksdb::IteratorMode::Start)
+                .unwrap()
+            {
+                let row = bincode::deserialize::<Vec<DataType>>(&*raw).ok();
+                let stored_correctly = row.as_ref().map(|row| {
+                    // all indices have to be able to find the row, not just the primary one
+                    self.indices
+                        .iter()
+                        .all(|index| index.columns.iter().all(|&c| c < row.len()))
+                        && match Self::key_suffix(&pk, row, &self.indices[0].columns) {
+                            // unique keys have no suffix, others end in an (epoch, seq) pair
+                            Some(rest) => rest.is_empty() || rest.len() == 16,
+                            None => false,
+                        }
+                });
+
+                if stored_correctly == Some(true) {
+                    report.rows += 1;
+                    intact.insert(pk, Self::fingerprint(&raw));
+                } else {
+                    report.corrupt_rows += 1;
+                    report.lost_rows.extend(row);
+                    batch.delete_cf(value_cf, &pk).unwrap();
+                }
+            }
+
+            for index in self.indices[1..].iter() {
+                let cf = db.cf_handle(&index.column_family).unwrap();
+                let mut referenced = HashSet::new();
+                for (key, raw) in db
+                    .full_iterator_cf(cf, rocksdb::IteratorMode::Start)
+                    .unwrap()
+                {
+                    let pk = bincode::deserialize::<Vec<DataType>>(&*raw)
+                        .ok()
+                        .and_then(|row| Self::key_suffix(&key, &row, &index.columns))
+                        .filter(|pk| intact.get(*pk) == Some(&Self::fingerprint(&raw)))
+                        .map(Vec::from);
+
+                    match pk {
+                        Some(pk) => {
+                            referenced.insert(pk);
+                        }
+                        None => {
+                            report.dangling_index_entries += 1;
+                            batch.delete_cf(cf, &key).unwrap();
+                        }
+                    }
+                }
+
+                for pk in intact.keys().filter(|pk| !referenced.contains(&pk[..])) {
+                    report.missing_index_entries += 1;
+                    let raw = db.get_cf(value_cf, pk).unwrap().unwrap();
+                    let row: Vec<DataType> = bincode::deserialize(&*raw).unwrap();
+                    let key = Self::build_key(&row, &index.columns);
+                    batch
+                        .put_cf(cf, &Self::serialize_secondary(&key, pk), &raw)
+                        .unwrap();
+                }
+            }
+        }
+
+        if repair && !report.is_clean() {
+            let mut opts = rocksdb::WriteOptions::default();
+            opts.set_sync(true);
+            db.write_opt(batch, &opts).unwrap();
+            report.repaired = true;
+        }
+        Some(report)
+    }
 }
 
 impl PersistentState {
@@ -279,6 +416,7 @@ impl PersistentState {
             seq: 0,
             indices,
             has_unique_index: primary_key.is_some(),
+            pending_commit_seq: None,
             epoch: meta.epoch,
             db_opts: opts,
             db: Some(db),
@@ -373,12 +511,12 @@ impl PersistentState {
     fn retrieve_and_update_meta(db: &rocksdb::DB) -> PersistentMeta {
         let indices = db.get(META_KEY).unwrap();
         let mut meta = match indices {
-            Some(data) => bincode::deserialize(&*data).unwrap(),
+            Some(data) => versioned::decode(&*data).unwrap(),
             None => PersistentMeta::default(),
         };
 
         meta.epoch += 1;
-        let data = bincode::serialize(&meta).unwrap();
+        let data = versioned::encode(&meta);
         db.put(META_KEY, &data).unwrap();
         meta
     }
@@ -392,7 +530,7 @@ impl PersistentState {
             epoch: self.epoch,
         };
 
-        let data = bincode::serialize(&meta).unwrap();
+        let data = versioned::encode(&meta);
         db.put(META_KEY, &data).unwrap();
     }
 
@@ -438,6 +576,26 @@ impl PersistentState {
         bytes
     }
 
+    // If `key` is prefixed by the serialized `columns` of `row`, the remainder of the key.
+    fn key_suffix<'a>(key: &'a [u8], row: &[DataType], columns: &[usize]) -> Option<&'a [u8]> {
+        if columns.iter().any(|&c| c >= row.len()) {
+            return None;
+        }
+
+        let prefix = Self::serialize_prefix(&Self::build_key(row, columns));
+        if key.starts_with(&prefix) {
+            Some(&key[prefix.len()..])
+        } else {
+            None
+        }
+    }
+
+    fn fingerprint(raw: &[u8]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        raw.hash(&mut hasher);
+        hasher.finish()
+    }
+
     // Filters out secondary indices to return an iterator for the actual key-value pairs.
     fn all_rows(&self) -> impl Iterator<Item = (Box<[u8]>, Box<[u8]>)> + '_ {
         let db = self.db.as_ref().unwrap();
@@ -833,6 +991,82 @@ mod tests {
         }
     }
 
+    #[test]
+    fn persistent_state_recovers_commit_seq() {
+        let (_dir, name) = get_tmp_path();
+        let mut params = PersistenceParameters::default();
+        params.mode = DurabilityMode::Permanent;
+        {
+            let mut state = PersistentState::new(name.clone(), None, &params);
+            state.add_key(&[0], None);
+            assert_eq!(state.commit_seq(), None);
+            state.set_commit_seq(1);
+            state.process_records(&mut vec![vec![DataType::from(1)]].into(), None);
+            // a batch that ends up not changing any rows is still committed
+            state.set_commit_seq(2);
+            state.process_records(&mut Vec::<Record>::new().into(), None);
+            assert_eq!(state.commit_seq(), Some(2));
+        }
+
+        let state = PersistentState::new(name, None, &params);
+        assert_eq!(state.commit_seq(), Some(2));
+    }
+
+    #[test]
+    fn persistent_state_verify() {
+        let mut state = setup_persistent("persistent_state_verify");
+        state.add_key(&[0], None);
+        state.add_key(&[1], None);
+        let rows: Vec<Vec<DataType>> = (1..4)
+            .map(|i| vec![i.into(), i.to_string().into()])
+            .collect();
+        state.process_records(&mut rows.clone().into(), None);
+        assert!(state.verify(false).unwrap().is_clean());
+
+        let misplaced: Vec<DataType> = vec![5.into(), "5".into()];
+        {
+            let db = state.db.as_ref().unwrap();
+            let rows_cf = db.cf_handle("0").unwrap();
+            let index_cf = db.cf_handle("1").unwrap();
+            let find = |cf, key: DataType| {
+                let prefix = PersistentState::serialize_prefix(&KeyType::Single(&key));
+                db.prefix_iterator_cf(cf, &prefix)
+                    .unwrap()
+                    .next()
+                    .unwrap()
+                    .0
+            };
+
+            // a row that can't be decoded,
+            let key = PersistentState::serialize_prefix(&KeyType::Single(&4.into()));
+            db.put_cf(rows_cf, &key, b"garbage").unwrap();
+            // a row stored under the wrong key,
+            let key = PersistentState::serialize_prefix(&KeyType::Single(&6.into()));
+            let value = bincode::serialize(&misplaced).unwrap();
+            db.put_cf(rows_cf, &key, &value).unwrap();
+            // an index entry whose row is gone,
+            db.delete_cf(rows_cf, find(rows_cf, 2.into())).unwrap();
+            // and a row that is missing from the second index.
+            db.delete_cf(index_cf, find(index_cf, "3".into())).unwrap();
+        }
+
+        let report = state.verify(false).unwrap();
+        assert_eq!(report.rows, 2);
+        assert_eq!(report.corrupt_rows, 2);
+        assert_eq!(report.lost_rows, vec![misplaced]);
+        assert_eq!(report.dangling_index_entries, 1);
+        assert_eq!(report.missing_index_entries, 1);
+        assert!(!report.repaired);
+
+        assert!(state.verify(true).unwrap().repaired);
+        assert!(state.verify(false).unwrap().is_clean());
+        assert_eq!(state.rows(), 2);
+        match state.lookup(&[1], &KeyType::Single(&"3".into())) {
+            LookupResult::Some(RecordResult::Owned(rs)) => assert_eq!(rs, vec![rows[2].clone()]),
+            _ => unreachable!(),
+        }
+    }
+
     #[test]
     fn persistent_state_recover() {
         let (_dir, name) = get_tmp_path();
@@ -865,6 +1099,35 @@ mod tests {
         }
     }
 
+    #[test]
+    fn persistent_state_recovers_unversioned_meta() {
+        let (_dir, name) = get_tmp_path();
+        let mut params = PersistenceParameters::default();
+        params.mode = DurabilityMode::Permanent;
+        let row: Vec<DataType> = vec![10.into(), "Cat".into()];
+        {
+            let mut state = PersistentState::new(name.clone(), None, &params);
+            state.add_key(&[0], None);
+            state.add_key(&[1], None);
+            state.process_records(&mut vec![row.clone()].into(), None);
+
+            // as written by a release from before the meta data was versioned
+            let meta = PersistentMeta {
+                indices: vec![vec![0], vec![1]],
+                epoch: state.epoch,
+            };
+            let db = state.db.as_ref().unwrap();
+            db.put(META_KEY, &bincode::serialize(&meta).unwrap())
+                .unwrap();
+        }
+
+        let state = PersistentState::new(name, None, &params);
+        match state.lookup(&[1], &KeyType::Single(&"Cat".into())) {
+            LookupResult::Some(RecordResult::Owned(rows)) => assert_eq!(rows, vec![row]),
+            _ => unreachable!(),
+        }
+    }
+
     #[test]
     fn persistent_state_recover_unique_key() {
         let (_dir, name) = get_tmp_path();
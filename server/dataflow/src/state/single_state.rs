@@ -1,8 +1,10 @@
 use super::mk_key::MakeKey;
+use super::spill::{self, SpillFile};
 use crate::prelude::*;
 use crate::state::keyed_state::KeyedState;
 use common::SizeOf;
 use rand::prelude::*;
+use std::collections::HashMap;
 use std::rc::Rc;
 
 pub(super) struct SingleState {
@@ -10,6 +12,12 @@ pub(super) struct SingleState {
     state: KeyedState,
     partial: bool,
     rows: usize,
+
+    /// Keys with more rows than this are moved to disk.
+    spill_threshold: Option<usize>,
+    /// The rows of keys that have been moved to disk. Such keys keep an empty entry in `state` so
+    /// that they are not mistaken for holes.
+    spilled: HashMap<Vec<DataType>, SpillFile>,
 }
 
 macro_rules! insert_row_match_impl {
@@ -38,19 +46,39 @@ macro_rules! remove_row_match_impl {
 }
 
 impl SingleState {
-    pub(super) fn new(columns: &[usize], partial: bool) -> Self {
+    pub(super) fn new(columns: &[usize], partial: bool, spill_threshold: Option<usize>) -> Self {
         Self {
             key: Vec::from(columns),
             state: columns.into(),
             partial,
             rows: 0,
+            spill_threshold,
+            spilled: HashMap::new(),
         }
     }
 
+    fn key_of(&self, r: &[DataType]) -> Vec<DataType> {
+        self.key.iter().map(|&c| r[c].clone()).collect()
+    }
+
     /// Inserts the given record, or returns false if a hole was encountered (and the record hence
     /// not inserted).
-    pub(super) fn insert_row(&mut self, r: Row) -> bool {
+    ///
+    /// If the insert pushes its key over the spill threshold, that key's rows are moved to disk,
+    /// and the memory released by rows no other index holds on to is added to `freed`.
+    pub(super) fn insert_row(&mut self, r: Row, freed: &mut u64) -> bool {
         use indexmap::map::Entry;
+
+        if !self.spilled.is_empty() {
+            let key = self.key_of(&r);
+            if let Some(f) = self.spilled.get_mut(&key) {
+                f.extend(std::iter::once(&r[..]));
+                self.rows += 1;
+                return true;
+            }
+        }
+
+        let probe = self.spill_threshold.map(|_| r.clone());
         match self.state {
             KeyedState::Single(ref mut map) => {
                 // treat this specially to avoid the extra Vec
@@ -58,14 +86,13 @@ impl SingleState {
                 // i *wish* we could use the entry API here, but it would mean an extra clone
                 // in the common case of an entry already existing for the given key...
                 if let Some(ref mut rs) = map.get_mut(&r[self.key[0]]) {
-                    self.rows += 1;
                     rs.insert(r);
-                    return true;
                 } else if self.partial {
                     // trying to insert a record into partial materialization hole!
                     return false;
+                } else {
+                    map.insert(r[self.key[0]].clone(), std::iter::once(r).collect());
                 }
-                map.insert(r[self.key[0]].clone(), std::iter::once(r).collect());
             }
             KeyedState::Double(ref mut map) => insert_row_match_impl!(self, r, map),
             KeyedState::Tri(ref mut map) => insert_row_match_impl!(self, r, map),
@@ -75,11 +102,65 @@ impl SingleState {
         }
 
         self.rows += 1;
+
+        if let (Some(threshold), Some(r)) = (self.spill_threshold, probe) {
+            let len = self
+                .state
+                .lookup(&KeyType::from(self.key.iter().map(|&c| &r[c])))
+                .map(|rs| rs.len())
+                .unwrap_or(0);
+            if len > threshold {
+                let key = self.key_of(&r);
+                drop(r);
+                *freed += self.spill(key);
+            }
+        }
         true
     }
 
+    /// Move all rows for `key` to disk, returning the number of bytes freed.
+    fn spill(&mut self, key: Vec<DataType>) -> u64 {
+        let rows = self
+            .state
+            .remove(&key)
+            .expect("spilling key that is not present");
+        let mut file = SpillFile::new();
+        file.extend(rows.iter().map(|r| &r[..]));
+        let freed = rows
+            .iter()
+            .filter(|r| Rc::strong_count(&r.0) == 1)
+            .map(SizeOf::deep_size_of)
+            .sum();
+
+        self.mark_filled(key.clone());
+        self.spilled.insert(key, file);
+        freed
+    }
+
+    /// Move the rows of every key with more than `rows` rows to disk, returning the number of
+    /// bytes freed.
+    pub(super) fn spill_keys_over(&mut self, rows: usize) -> u64 {
+        let keys: Vec<_> = self
+            .values()
+            .filter(|rs| rs.len() > rows)
+            .filter_map(|rs| rs.iter().next().map(|r| self.key_of(r)))
+            .collect();
+        keys.into_iter().map(|key| self.spill(key)).sum()
+    }
+
     /// Attempt to remove row `r`.
     pub(super) fn remove_row(&mut self, r: &[DataType], hit: &mut bool) -> Option<Row> {
+        if !self.spilled.is_empty() {
+            let key = self.key_of(r);
+            if let Some(f) = self.spilled.get_mut(&key) {
+                *hit = true;
+                if f.remove(r) {
+                    self.rows -= 1;
+                }
+                return None;
+            }
+        }
+
         let mut do_remove = |self_rows: &mut usize, rs: &mut Rows| -> Option<Row> {
             *hit = true;
             let rm = if rs.len() == 1 {
@@ -181,24 +262,8 @@ impl SingleState {
     }
 
     pub(super) fn mark_hole(&mut self, key: &[DataType]) -> u64 {
-        let removed = match self.state {
-            KeyedState::Single(ref mut m) => m.swap_remove(&(key[0])),
-            KeyedState::Double(ref mut m) => {
-                m.swap_remove::<(DataType, _)>(&MakeKey::from_key(key))
-            }
-            KeyedState::Tri(ref mut m) => {
-                m.swap_remove::<(DataType, _, _)>(&MakeKey::from_key(key))
-            }
-            KeyedState::Quad(ref mut m) => {
-                m.swap_remove::<(DataType, _, _, _)>(&MakeKey::from_key(key))
-            }
-            KeyedState::Quin(ref mut m) => {
-                m.swap_remove::<(DataType, _, _, _, _)>(&MakeKey::from_key(key))
-            }
-            KeyedState::Sex(ref mut m) => {
-                m.swap_remove::<(DataType, _, _, _, _, _)>(&MakeKey::from_key(key))
-            }
-        };
+        let removed = self.state.remove(key);
+        self.spilled.remove(key);
         // mark_hole should only be called on keys we called mark_filled on
         removed
             .unwrap()
@@ -210,6 +275,7 @@ impl SingleState {
 
     pub(super) fn clear(&mut self) {
         self.rows = 0;
+        self.spilled.clear();
         match self.state {
             KeyedState::Single(ref mut map) => map.clear(),
             KeyedState::Double(ref mut map) => map.clear(),
@@ -232,6 +298,7 @@ impl SingleState {
         for _ in 0..count {
             if let Some((n, key)) = self.state.evict_with_seed(rng.gen()) {
                 bytes_freed += n;
+                self.spilled.remove(&key);
                 keys.push(key);
             } else {
                 break;
@@ -242,6 +309,9 @@ impl SingleState {
 
     /// Evicts a specified key from this state, returning the number of bytes freed.
     pub(super) fn evict_keys(&mut self, keys: &[Vec<DataType>]) -> u64 {
+        for k in keys {
+            self.spilled.remove(k);
+        }
         keys.iter().map(|k| self.state.evict(k)).sum()
     }
 
@@ -255,6 +325,12 @@ impl SingleState {
             KeyedState::Sex(ref map) => Box::new(map.values()),
         }
     }
+
+    /// All rows that have been moved to disk.
+    pub(super) fn spilled_records<'a>(&'a self) -> impl Iterator<Item = Vec<DataType>> + 'a {
+        self.spilled.values().flat_map(|f| f.records())
+    }
+
     pub(super) fn key(&self) -> &[usize] {
         &self.key
     }
@@ -265,6 +341,12 @@ impl SingleState {
         self.rows
     }
     pub(super) fn lookup<'a>(&'a self, key: &KeyType) -> LookupResult<'a> {
+        if !self.spilled.is_empty() {
+            if let Some(f) = self.spilled.get(&spill::key_to_vec(key)) {
+                return LookupResult::Some(RecordResult::Owned(f.records().collect()));
+            }
+        }
+
         if let Some(rs) = self.state.lookup(key) {
             LookupResult::Some(RecordResult::Borrowed(rs))
         } else if self.partial() {
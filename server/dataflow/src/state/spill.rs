@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+use crate::prelude::*;
+
+/// Compact a spill file once this many of the rows in it have been removed.
+const COMPACT_AFTER: usize = 1024;
+
+/// On-disk storage for the rows of a single key whose bucket grew too large to keep in memory.
+///
+/// The file is an append-only log of bincode-encoded rows in an anonymous temporary file, which
+/// the operating system removes once the file is dropped. Removed rows stay in the file, and are
+/// only counted in memory, until enough of them have piled up to rewrite the file without them.
+/// Reads stream through the file, so the rows are never all held in memory at once.
+pub(super) struct SpillFile {
+    file: File,
+    /// The number of rows in the file, including removed ones.
+    entries: usize,
+    /// How many copies of each row in the file have been removed.
+    removed: HashMap<Vec<DataType>, usize>,
+    /// The total number of removed copies.
+    removals: usize,
+}
+
+impl SpillFile {
+    pub(super) fn new() -> Self {
+        SpillFile {
+            file: tempfile::tempfile().expect("failed to create spill file"),
+            entries: 0,
+            removed: HashMap::new(),
+            removals: 0,
+        }
+    }
+
+    /// Append the given rows.
+    pub(super) fn extend<'a, I>(&mut self, rows: I)
+    where
+        I: IntoIterator<Item = &'a [DataType]>,
+    {
+        self.file
+            .seek(SeekFrom::End(0))
+            .expect("failed to seek in spill file");
+        let mut writer = BufWriter::new(&self.file);
+        for r in rows {
+            bincode::serialize_into(&mut writer, r).expect("failed to write spill file");
+            self.entries += 1;
+        }
+        writer.flush().expect("failed to write spill file");
+    }
+
+    /// Remove a single copy of `r`, returning false if there is no copy of it left to remove.
+    ///
+    /// This reads through the file to make sure that the row is there.
+    pub(super) fn remove(&mut self, r: &[DataType]) -> bool {
+        let removed = self.removed.get(r).copied().unwrap_or(0);
+        // the row is there if the file has more copies of it than have been removed
+        let present = self.rows_in_file().filter(|row| &row[..] == r).nth(removed);
+        if present.is_none() {
+            return false;
+        }
+
+        *self.removed.entry(r.to_vec()).or_insert(0) += 1;
+        self.removals += 1;
+        if self.removals >= COMPACT_AFTER {
+            self.compact();
+        }
+        true
+    }
+
+    /// Read back all rows currently stored, one at a time.
+    pub(super) fn records(&self) -> impl Iterator<Item = Vec<DataType>> + '_ {
+        let mut removed = self.removed.clone();
+        self.rows_in_file()
+            .filter(move |r| match removed.get_mut(r) {
+                Some(n) if *n > 0 => {
+                    *n -= 1;
+                    false
+                }
+                _ => true,
+            })
+    }
+
+    /// All rows in the file, including removed ones.
+    fn rows_in_file(&self) -> impl Iterator<Item = Vec<DataType>> + '_ {
+        let mut reader = BufReader::new(FileAt {
+            file: &self.file,
+            at: 0,
+        });
+        (0..self.entries).map(move |_| {
+            bincode::deserialize_from(&mut reader).expect("failed to read spill file")
+        })
+    }
+
+    /// Rewrite the file so that it contains only the rows that have not been removed.
+    fn compact(&mut self) {
+        let file = tempfile::tempfile().expect("failed to create spill file");
+        let mut writer = BufWriter::new(&file);
+        let mut entries = 0;
+        for r in self.records() {
+            bincode::serialize_into(&mut writer, &r).expect("failed to write spill file");
+            entries += 1;
+        }
+        writer.flush().expect("failed to write spill file");
+        drop(writer);
+
+        self.file = file;
+        self.entries = entries;
+        self.removed.clear();
+        self.removals = 0;
+    }
+}
+
+/// Reads a file from a position of its own, so that reads of the same file don't interfere.
+struct FileAt<'a> {
+    file: &'a File,
+    at: u64,
+}
+
+impl Read for FileAt<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut file = self.file;
+        file.seek(SeekFrom::Start(self.at))?;
+        let n = file.read(buf)?;
+        self.at += n as u64;
+        Ok(n)
+    }
+}
+
+/// Turn a lookup key back into the owned form that `SpillFile`s are indexed by.
+pub(super) fn key_to_vec(key: &KeyType) -> Vec<DataType> {
+    match *key {
+        KeyType::Single(k) => vec![k.clone()],
+        KeyType::Double(ref k) => vec![k.0.clone(), k.1.clone()],
+        KeyType::Tri(ref k) => vec![k.0.clone(), k.1.clone(), k.2.clone()],
+        KeyType::Quad(ref k) => vec![k.0.clone(), k.1.clone(), k.2.clone(), k.3.clone()],
+        KeyType::Quin(ref k) => vec![
+            k.0.clone(),
+            k.1.clone(),
+            k.2.clone(),
+            k.3.clone(),
+            k.4.clone(),
+        ],
+        KeyType::Sex(ref k) => vec![
+            k.0.clone(),
+            k.1.clone(),
+            k.2.clone(),
+            k.3.clone(),
+            k.4.clone(),
+            k.5.clone(),
+        ],
+    }
+}
@@ -0,0 +1,137 @@
+//! Versioned encoding for state that is kept on disk across restarts.
+//!
+//! Every encoded value starts with a short header that records which version of its format it was
+//! written with. When a format changes, its `Versioned::VERSION` is bumped, and its
+//! `Versioned::upgrade` learns to read the earlier versions, so that state written by the previous
+//! release can still be loaded after an upgrade. Values written before formats were versioned
+//! have no header, and are read as version 0.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::Write;
+
+/// Marks the start of a versioned value.
+const MAGIC: &[u8; 4] = b"NVS\0";
+const HEADER_LEN: usize = 8;
+
+/// A format for state that is kept on disk.
+pub(crate) trait Versioned: Serialize + DeserializeOwned {
+    /// The version of the format that is written.
+    const VERSION: u32;
+
+    /// Decode `data`, which was written with the given earlier `version` of the format.
+    fn upgrade(version: u32, data: &[u8]) -> bincode::Result<Self>;
+}
+
+/// Write `value` to `w`, preceded by the version of its format.
+pub(crate) fn encode_into<T: Versioned, W: Write>(w: &mut W, value: &T) -> bincode::Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_all(&T::VERSION.to_le_bytes())?;
+    bincode::serialize_into(w, value)
+}
+
+/// Encode `value`, preceded by the version of its format.
+pub(crate) fn encode<T: Versioned>(value: &T) -> Vec<u8> {
+    let mut data = Vec::new();
+    encode_into(&mut data, value).unwrap();
+    data
+}
+
+/// Decode a value written by `encode`, upgrading it first if it was written with an earlier
+/// version of its format.
+pub(crate) fn decode<T: Versioned>(data: &[u8]) -> bincode::Result<T> {
+    let (version, body) = if data.len() >= HEADER_LEN && data[..MAGIC.len()] == MAGIC[..] {
+        let mut version = [0; 4];
+        version.copy_from_slice(&data[MAGIC.len()..HEADER_LEN]);
+        (u32::from_le_bytes(version), &data[HEADER_LEN..])
+    } else {
+        // written before the format was versioned
+        (0, data)
+    };
+
+    if version == T::VERSION {
+        bincode::deserialize(body)
+    } else if version < T::VERSION {
+        T::upgrade(version, body)
+    } else {
+        Err(Box::new(bincode::ErrorKind::Custom(format!(
+            "written with version {} of its format, but only up to version {} is supported",
+            version,
+            T::VERSION
+        ))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Old {
+        a: u32,
+    }
+
+    impl Versioned for Old {
+        const VERSION: u32 = 1;
+
+        fn upgrade(version: u32, data: &[u8]) -> bincode::Result<Self> {
+            assert_eq!(version, 0);
+            bincode::deserialize(data)
+        }
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct New {
+        a: u64,
+        b: String,
+    }
+
+    impl Versioned for New {
+        const VERSION: u32 = 2;
+
+        fn upgrade(version: u32, data: &[u8]) -> bincode::Result<Self> {
+            let old: Old = match version {
+                0 => bincode::deserialize(data)?,
+                1 => bincode::deserialize(data)?,
+                _ => unreachable!(),
+            };
+            Ok(New {
+                a: u64::from(old.a),
+                b: String::new(),
+            })
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let new = New {
+            a: 42,
+            b: "x".to_owned(),
+        };
+        assert_eq!(decode::<New>(&encode(&new)).unwrap(), new);
+    }
+
+    #[test]
+    fn upgrades_earlier_versions() {
+        let expected = New {
+            a: 42,
+            b: String::new(),
+        };
+        let old = encode(&Old { a: 42 });
+        assert_eq!(decode::<New>(&old).unwrap(), expected);
+
+        // from before formats had a version
+        let unversioned = bincode::serialize(&Old { a: 42 }).unwrap();
+        assert_eq!(decode::<Old>(&unversioned).unwrap(), Old { a: 42 });
+        assert_eq!(decode::<New>(&unversioned).unwrap(), expected);
+    }
+
+    #[test]
+    fn rejects_later_versions() {
+        let new = encode(&New {
+            a: 42,
+            b: "x".to_owned(),
+        });
+        assert!(decode::<Old>(&new).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+//! Distributed tracing of writes, replays, and migrations.
+//!
+//! Spans are reported to the globally installed OpenTelemetry tracer, but only once `enable` has
+//! been called. Until then, starting a span does nothing, and trace contexts that clients attach
+//! to their writes are passed along unchanged.
+//!
+//! The context of the span a domain records for a traced write travels with the write in the
+//! headers of the packets the domain sends on, so that the span of the next domain can name it as
+//! its parent. Replays are traced the same way: a request for a partial replay carries the context
+//! of the span of the replay that needs it, and so do the replay pieces sent in response.
+
+use noria::TraceContext;
+use opentelemetry::api::trace::{
+    Link, Span as _, SpanContext, SpanId, TraceId, Tracer as _, TRACE_FLAG_SAMPLED,
+};
+use opentelemetry::api::KeyValue;
+use opentelemetry::global::{self, BoxedSpan};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Start reporting spans to the global OpenTelemetry tracer.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Whether spans are reported.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+fn span_context(cx: TraceContext) -> SpanContext {
+    SpanContext::new(
+        TraceId::from_u128(cx.trace_id),
+        SpanId::from_u64(cx.span_id),
+        TRACE_FLAG_SAMPLED,
+        true,
+    )
+}
+
+/// A span that ends when it is dropped.
+pub struct Span(Option<BoxedSpan>);
+
+impl Span {
+    /// A span that is not reported.
+    pub fn disabled() -> Self {
+        Span(None)
+    }
+
+    /// Start a span, as a child of `parent` if one is given and as the root of a new trace
+    /// otherwise.
+    pub fn start(name: &'static str, parent: Option<TraceContext>) -> Self {
+        Self::linked(name, parent, &[])
+    }
+
+    /// Start a span that is also linked to the spans in `links`, for work done on behalf of
+    /// several traced operations at once.
+    pub fn linked(
+        name: &'static str,
+        parent: Option<TraceContext>,
+        links: &[TraceContext],
+    ) -> Self {
+        if !enabled() {
+            return Span::disabled();
+        }
+
+        let tracer = global::tracer("noria");
+        let mut builder = tracer.span_builder(name);
+        if let Some(parent) = parent {
+            builder = builder.with_parent(span_context(parent));
+        }
+        if !links.is_empty() {
+            builder = builder.with_links(
+                links
+                    .iter()
+                    .map(|&cx| Link::new(span_context(cx), Vec::new()))
+                    .collect(),
+            );
+        }
+        Span(Some(builder.start(&tracer)))
+    }
+
+    /// The context that spans started on behalf of this one should name as their parent.
+    ///
+    /// This is `None` if the span is not reported.
+    pub fn context(&self) -> Option<TraceContext> {
+        self.0.as_ref().map(|span| {
+            let cx = span.span_context();
+            TraceContext {
+                trace_id: cx.trace_id().to_u128(),
+                span_id: cx.span_id().to_u64(),
+            }
+        })
+    }
+
+    /// Record a numeric attribute of the span.
+    pub fn set(&self, key: &'static str, value: usize) {
+        if let Some(ref span) = self.0 {
+            span.set_attribute(KeyValue::new(key, value as i64));
+        }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(span) = self.0.take() {
+            span.end();
+        }
+    }
+}
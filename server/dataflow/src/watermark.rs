@@ -0,0 +1,85 @@
+use crate::ops::NodeOperator;
+use petgraph::graph::NodeIndex;
+
+/// The kinds of operators whose state a `WatermarkPolicy` is told about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OperatorKind {
+    /// A join.
+    Join,
+    /// An aggregation, such as a count, sum, extremum, or group concatenation.
+    Aggregation,
+    /// A top-k operator.
+    TopK,
+    /// Any other operator that keeps state, such as a distinct or a materialized projection.
+    Other,
+}
+
+impl OperatorKind {
+    pub(crate) fn of(op: &NodeOperator) -> Self {
+        match *op {
+            NodeOperator::Join(..) => OperatorKind::Join,
+            NodeOperator::Sum(..)
+            | NodeOperator::Extremum(..)
+            | NodeOperator::Concat(..)
+            | NodeOperator::FilterSum(..) => OperatorKind::Aggregation,
+            NodeOperator::TopK(..) => OperatorKind::TopK,
+            _ => OperatorKind::Other,
+        }
+    }
+}
+
+/// The state of an operator that has grown past one of the configured state-size watermarks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WatermarkCrossing {
+    /// The operator's node.
+    pub node: NodeIndex,
+    /// The shard of the operator whose state grew, if its domain is sharded.
+    pub shard: Option<usize>,
+    /// The kind of operator.
+    pub kind: OperatorKind,
+    /// Whether the operator's state is partially materialized, and so can be evicted from.
+    pub partial: bool,
+    /// The watermark that was crossed, in bytes.
+    pub watermark: u64,
+    /// The size of the operator's state, in bytes.
+    pub size: u64,
+}
+
+/// What a domain does about an operator whose state grew past a watermark.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatermarkAction {
+    /// Nothing.
+    Ignore,
+    /// Log a warning. The crossing is counted in the operator's node statistics either way.
+    Alert,
+    /// Evict randomly chosen keys until the state is back below the watermark. Ignored for state
+    /// that is not partially materialized.
+    Evict,
+    /// Move the rows of every key with more than this many rows to disk, as a spill threshold of
+    /// that many rows would have done as the rows were inserted.
+    Spill(usize),
+}
+
+/// Decides what to do about operators whose state grows past a watermark.
+///
+/// A policy is asked once for every watermark an operator's state grows past, and again if the
+/// state shrinks below the watermark and then grows past it anew.
+pub trait WatermarkPolicy: Send + Sync {
+    /// What to do about the given crossing.
+    fn on_crossing(&self, crossing: &WatermarkCrossing) -> WatermarkAction;
+}
+
+/// The policy used unless another is set: evict from partially materialized state, and alert
+/// about fully materialized state, which cannot be evicted from.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultWatermarkPolicy;
+
+impl WatermarkPolicy for DefaultWatermarkPolicy {
+    fn on_crossing(&self, crossing: &WatermarkCrossing) -> WatermarkAction {
+        if crossing.partial {
+            WatermarkAction::Evict
+        } else {
+            WatermarkAction::Alert
+        }
+    }
+}
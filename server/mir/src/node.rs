@@ -488,6 +488,10 @@ pub enum MirNodeType {
         column: String,
         key: String,
     },
+    /// list column to expand into one row per element
+    Unnest {
+        column: Column,
+    },
 }
 
 impl MirNodeType {
@@ -763,6 +767,12 @@ impl MirNodeType {
                 } => (value == our_value && our_key == key && our_col == column),
                 _ => false,
             },
+            MirNodeType::Unnest {
+                column: ref our_column,
+            } => match *other {
+                MirNodeType::Unnest { ref column } => column == our_column,
+                _ => false,
+            },
             _ => unimplemented!(),
         }
     }
@@ -1031,6 +1041,7 @@ impl Debug for MirNodeType {
                 write!(f, "{}", cols)
             }
             MirNodeType::Rewrite { ref column, .. } => write!(f, "Rw [{}]", column),
+            MirNodeType::Unnest { ref column } => write!(f, "Unnest [{}]", column.name),
         }
     }
 }
@@ -1,18 +1,22 @@
+use crate::column::Column;
 use crate::node::{MirNode, MirNodeType};
 use crate::query::MirQuery;
 use crate::MirNodeRef;
-use dataflow::ops::filter::FilterCondition;
+use dataflow::ops::filter::{FilterCondition, Value};
 use dataflow::ops::grouped::aggregate::Aggregation;
 use dataflow::ops::grouped::filteraggregate::FilterAggregation;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 // Mutate the given MirQuery in order to optimize it,
 // for example by merging certain nodes together.
 // Return a list of any new nodes created so that the caller
 // can add them to any other internal representations.
-pub fn optimize(mut q: &mut MirQuery) -> Vec<MirNodeRef> {
+pub fn optimize(mut q: &mut MirQuery, prune_columns: bool) -> Vec<MirNodeRef> {
     //remove_extraneous_projections(&mut q);
+    if prune_columns {
+        prune_join_columns(&mut q);
+    }
     find_and_merge_filter_aggregates(&mut q)
 }
 
@@ -20,6 +24,201 @@ pub fn optimize_post_reuse(_q: &mut MirQuery) {
     // find_and_merge_filter_chains(q);
 }
 
+/// The nodes of `q`, that is, its leaf and everything the leaf is built on, with every node
+/// listed after its ancestors.
+fn query_nodes(q: &MirQuery) -> Vec<MirNodeRef> {
+    fn visit(n: &MirNodeRef, visited: &mut HashSet<String>, nodes: &mut Vec<MirNodeRef>) {
+        if !visited.insert(n.borrow().versioned_name()) {
+            return;
+        }
+        for a in n.borrow().ancestors() {
+            visit(a, visited, nodes);
+        }
+        nodes.push(n.clone());
+    }
+
+    let mut nodes = Vec::new();
+    visit(&q.leaf, &mut HashSet::new(), &mut nodes);
+    nodes
+}
+
+/// The columns of a filter's parent that its conditions look at, by index.
+fn condition_columns(conditions: &[(usize, FilterCondition)]) -> Vec<usize> {
+    let mut columns = Vec::new();
+    for (i, cond) in conditions {
+        columns.push(*i);
+        if let FilterCondition::Comparison(_, Value::Column(j)) = *cond {
+            columns.push(j);
+        }
+    }
+    columns
+}
+
+/// The columns of its parents that `n` needs, given that its children need only its `live`
+/// columns (or all of them, if `live` is `None`). Returns `None` if `n` needs all of them, which
+/// is assumed for any node whose needs aren't known here.
+fn needed_columns(n: &MirNode, live: Option<&[Column]>) -> Option<Vec<Column>> {
+    if n.flow_node.is_some() {
+        // nodes that are already in the graph keep the columns they have
+        return None;
+    }
+    match n.inner {
+        MirNodeType::Join {
+            ref on_left,
+            ref on_right,
+            ref project,
+        }
+        | MirNodeType::LeftJoin {
+            ref on_left,
+            ref on_right,
+            ref project,
+        } => {
+            // joins whose columns don't line up with their projections are left as they are
+            let live = live.filter(|_| project.len() == n.columns.len());
+            let mut needed: Vec<_> = project
+                .iter()
+                .filter(|c| live.map_or(true, |live| live.contains(c)))
+                .cloned()
+                .collect();
+            needed.extend(on_left.iter().cloned());
+            needed.extend(on_right.iter().cloned());
+            Some(needed)
+        }
+        MirNodeType::Filter { ref conditions } => {
+            // filters emit all of their parent's columns, and so must have exactly as many
+            let parent = n.ancestors()[0].borrow();
+            if parent.columns().len() != n.columns.len() {
+                return None;
+            }
+            let mut needed = live?.to_vec();
+            needed.extend(
+                condition_columns(conditions)
+                    .into_iter()
+                    .map(|i| n.columns[i].clone()),
+            );
+            Some(needed)
+        }
+        MirNodeType::Identity => {
+            let parent = n.ancestors()[0].borrow();
+            if parent.columns().len() != n.columns.len() {
+                return None;
+            }
+            Some(live?.to_vec())
+        }
+        MirNodeType::Project {
+            ref emit,
+            ref arithmetic,
+            ..
+        } if arithmetic.is_empty() => Some(emit.clone()),
+        MirNodeType::Aggregation { .. }
+        | MirNodeType::Extremum { .. }
+        | MirNodeType::GroupConcat { .. } => Some(n.referenced_columns()),
+        _ => None,
+    }
+}
+
+/// Narrow the joins of `q` to the columns that the rest of the query uses, rather than carrying
+/// all of their parents' columns through the query and into its state.
+///
+/// Joins always keep their (left) join column, since the join operator emits it. The filters and
+/// identities below a narrowed join are narrowed along with it.
+fn prune_join_columns(q: &mut MirQuery) {
+    let nodes = query_nodes(q);
+    let in_query: HashSet<_> = nodes.iter().map(|n| n.borrow().versioned_name()).collect();
+
+    // work out which columns are live, starting from the leaf, whose columns all are
+    let leaf = q.leaf.borrow().versioned_name();
+    let mut needed: HashMap<String, Option<Vec<Column>>> = HashMap::new();
+    let mut live: HashMap<String, Option<Vec<Column>>> = HashMap::new();
+    for n in nodes.iter().rev() {
+        let n = n.borrow();
+        let name = n.versioned_name();
+        let mut l = if name == leaf { None } else { Some(Vec::new()) };
+        for c in n.children() {
+            let c = c.borrow().versioned_name();
+            let cs = match needed.get(&c) {
+                Some(Some(cs)) if in_query.contains(&c) => cs,
+                _ => {
+                    l = None;
+                    break;
+                }
+            };
+            if let Some(ref mut l) = l {
+                l.extend(cs.iter().cloned());
+            }
+        }
+        needed.insert(
+            name.clone(),
+            needed_columns(&n, l.as_ref().map(Vec::as_slice)),
+        );
+        live.insert(name, l);
+    }
+
+    // then narrow the joins, along with the nodes that pass their columns through unchanged.
+    // each narrowed node maps to the indices of the columns it keeps.
+    let mut narrowed: HashMap<String, Vec<usize>> = HashMap::new();
+    for n in &nodes {
+        let mut n = n.borrow_mut();
+        let name = n.versioned_name();
+        let keep: Vec<usize> = match n.inner {
+            _ if n.flow_node.is_some() => continue,
+            MirNodeType::Join {
+                ref on_left,
+                ref project,
+                ..
+            }
+            | MirNodeType::LeftJoin {
+                ref on_left,
+                ref project,
+                ..
+            } => {
+                let used = match live[&name] {
+                    Some(ref used) if project.len() == n.columns.len() => used,
+                    _ => continue,
+                };
+                (0..project.len())
+                    .filter(|&i| used.contains(&project[i]) || on_left.contains(&project[i]))
+                    .collect()
+            }
+            MirNodeType::Filter { .. } | MirNodeType::Identity => {
+                let parent = n.ancestors()[0].borrow().versioned_name();
+                match narrowed.get(&parent) {
+                    Some(keep) => keep.clone(),
+                    None => continue,
+                }
+            }
+            _ => continue,
+        };
+        if keep.len() == n.columns.len() {
+            continue;
+        }
+
+        let columns = keep.iter().map(|&i| n.columns[i].clone()).collect();
+        n.columns = columns;
+        match n.inner {
+            MirNodeType::Join {
+                ref mut project, ..
+            }
+            | MirNodeType::LeftJoin {
+                ref mut project, ..
+            } => {
+                *project = keep.iter().map(|&i| project[i].clone()).collect();
+            }
+            MirNodeType::Filter { ref mut conditions } => {
+                let remap = |i: usize| keep.iter().position(|&k| k == i).unwrap();
+                for (i, cond) in conditions.iter_mut() {
+                    *i = remap(*i);
+                    if let FilterCondition::Comparison(_, Value::Column(ref mut j)) = *cond {
+                        *j = remap(*j);
+                    }
+                }
+            }
+            _ => {}
+        }
+        narrowed.insert(name, keep);
+    }
+}
+
 fn find_and_merge_filter_aggregates(q: &mut MirQuery) -> Vec<MirNodeRef> {
     // 1. depth first search to find all the nodes, so we can process them later
 
@@ -355,3 +554,116 @@ fn to_conditions(chained_filters: &[MirNodeRef]) -> Vec<(usize, FilterCondition)
 fn remove_extraneous_projections(_q: &mut MirQuery) {
     unimplemented!()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dataflow::ops::filter::Operator;
+    use nom_sql::{self, ColumnSpecification, SqlType};
+
+    fn base(name: &str, columns: &[&str]) -> MirNodeRef {
+        MirNode::new(
+            name,
+            0,
+            columns.iter().map(|&c| Column::from(c)).collect(),
+            MirNodeType::Base {
+                column_specs: columns
+                    .iter()
+                    .map(|&c| {
+                        (
+                            ColumnSpecification::new(nom_sql::Column::from(c), SqlType::Text),
+                            None,
+                        )
+                    })
+                    .collect(),
+                keys: vec![Column::from(columns[0])],
+                adapted_over: None,
+            },
+            vec![],
+            vec![],
+        )
+    }
+
+    #[test]
+    fn joins_only_carry_used_columns() {
+        let a = base("a", &["aa", "ab", "ac"]);
+        let b = base("b", &["ba", "bb"]);
+        let mut ab = Column::from("ab");
+        ab.add_alias(&Column::from("ba"));
+        let project = vec![
+            Column::from("aa"),
+            ab,
+            Column::from("ac"),
+            Column::from("bb"),
+        ];
+        let j = MirNode::new(
+            "j",
+            0,
+            project.clone(),
+            MirNodeType::Join {
+                on_left: vec![Column::from("ab")],
+                on_right: vec![Column::from("ba")],
+                project: project.clone(),
+            },
+            vec![a.clone(), b.clone()],
+            vec![],
+        );
+        // filters on bb, which the query does not otherwise use
+        let f = MirNode::new(
+            "f",
+            0,
+            project,
+            MirNodeType::Filter {
+                conditions: vec![(
+                    3,
+                    FilterCondition::Comparison(Operator::Equal, Value::Constant(1.into())),
+                )],
+            },
+            vec![j.clone()],
+            vec![],
+        );
+        let p = MirNode::new(
+            "p",
+            0,
+            vec![Column::from("aa")],
+            MirNodeType::Project {
+                emit: vec![Column::from("aa")],
+                arithmetic: vec![],
+                literals: vec![],
+            },
+            vec![f.clone()],
+            vec![],
+        );
+        let leaf = MirNode::new(
+            "l",
+            0,
+            vec![Column::from("aa")],
+            MirNodeType::Leaf {
+                node: p.clone(),
+                keys: vec![Column::from("aa")],
+            },
+            vec![p],
+            vec![],
+        );
+        let mut q = MirQuery {
+            name: "q".to_owned(),
+            roots: vec![a, b],
+            leaf,
+        };
+
+        prune_join_columns(&mut q);
+
+        // ac is not used, but the join column always stays
+        let kept = vec![Column::from("aa"), Column::from("ab"), Column::from("bb")];
+        assert_eq!(j.borrow().columns(), &kept[..]);
+        match j.borrow().inner {
+            MirNodeType::Join { ref project, .. } => assert_eq!(project, &kept),
+            _ => unreachable!(),
+        }
+        assert_eq!(f.borrow().columns(), &kept[..]);
+        match f.borrow().inner {
+            MirNodeType::Filter { ref conditions } => assert_eq!(conditions[0].0, 2),
+            _ => unreachable!(),
+        }
+    }
+}
@@ -71,13 +71,15 @@ impl MirQuery {
     // merging certain nodes together, and return it.
     // Also return a list of any new nodes created so that the
     // caller can add them to any other internal representations.
+    // If `prune_columns` is set, joins only carry the columns that the query uses.
     pub fn optimize(
         mut self,
         table_mapping: Option<&HashMap<(String, Option<String>), String>>,
         sec: bool,
+        prune_columns: bool,
     ) -> (MirQuery, Vec<MirNodeRef>) {
         super::rewrite::pull_required_base_columns(&mut self, table_mapping, sec);
-        let nodes_added = super::optimize::optimize(&mut self);
+        let nodes_added = super::optimize::optimize(&mut self, prune_columns);
         (self, nodes_added)
     }
 
@@ -333,6 +333,9 @@ impl GraphViz for MirNodeType {
             MirNodeType::Rewrite { ref column, .. } => {
                 write!(out, "Rw | column: {}", column)?;
             }
+            MirNodeType::Unnest { ref column } => {
+                write!(out, "⋔ | column: {}", print_col(column))?;
+            }
         }
         Ok(out)
     }
@@ -0,0 +1,96 @@
+use clap::value_t_or_exit;
+use noria_server::{verify_base_offline, PersistenceParameters};
+use std::path::PathBuf;
+use std::process;
+
+fn main() {
+    use clap::{App, Arg};
+    let matches = App::new("noria-fsck")
+        .version("0.0.1")
+        .about("Verifies, and optionally repairs, the on-disk rows of a base table. Run it from the directory the worker stored them in, while the worker is stopped.")
+        .arg(
+            Arg::with_name("deployment")
+                .long("deployment")
+                .short("d")
+                .required(true)
+                .takes_value(true)
+                .help("Noria deployment ID."),
+        )
+        .arg(
+            Arg::with_name("table")
+                .long("table")
+                .short("t")
+                .required(true)
+                .takes_value(true)
+                .help("Base table to verify."),
+        )
+        .arg(
+            Arg::with_name("shards")
+                .long("shards")
+                .takes_value(true)
+                .default_value("1")
+                .help("Number of shards the base table is split into."),
+        )
+        .arg(
+            Arg::with_name("log-dir")
+                .long("log-dir")
+                .takes_value(true)
+                .help("Absolute path to the directory the worker wrote its log files to."),
+        )
+        .arg(
+            Arg::with_name("repair")
+                .long("repair")
+                .takes_value(false)
+                .help("Drop corrupt rows and index entries, and rebuild missing index entries."),
+        )
+        .get_matches();
+
+    let deployment = matches.value_of("deployment").unwrap();
+    let table = matches.value_of("table").unwrap();
+    let shards = value_t_or_exit!(matches, "shards", usize);
+    let repair = matches.is_present("repair");
+
+    let mut params = PersistenceParameters::default();
+    params.log_prefix = deployment.to_string();
+    params.log_dir = matches.value_of("log-dir").map(PathBuf::from);
+
+    let mut clean = true;
+    for shard in 0..shards {
+        let report = match verify_base_offline(table, shard, &params, repair) {
+            Some(report) => report,
+            None => {
+                eprintln!("shard {} of '{}' has no files on disk", shard, table);
+                clean = false;
+                continue;
+            }
+        };
+
+        println!(
+            "shard {}: {} intact rows, {} corrupt rows, {} dangling and {} missing index entries{}",
+            shard,
+            report.rows,
+            report.corrupt_rows,
+            report.dangling_index_entries,
+            report.missing_index_entries,
+            if report.corrupt_commit_seq {
+                ", corrupt commit sequence number"
+            } else {
+                ""
+            }
+        );
+        for row in &report.lost_rows {
+            println!("  lost row: {:?}", row);
+        }
+        if !report.is_clean() {
+            if report.repaired {
+                println!("  repaired");
+            } else {
+                clean = false;
+            }
+        }
+    }
+
+    if !clean {
+        process::exit(1);
+    }
+}
@@ -1,11 +1,15 @@
 use crate::handle::Handle;
 use crate::Config;
 use crate::FrontierStrategy;
+use crate::PlacementStrategy;
 use crate::ReuseConfigType;
-use dataflow::PersistenceParameters;
+use dataflow::{
+    Clock, DefaultWatermarkPolicy, LoadSheddingPolicy, PersistenceParameters, WatermarkPolicy,
+};
+use noria::channel::Compression;
 use noria::consensus::{Authority, LocalAuthority};
 use std::future::Future;
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use std::time;
 
@@ -14,8 +18,14 @@ pub struct Builder {
     config: Config,
     memory_limit: Option<usize>,
     memory_check_frequency: Option<time::Duration>,
+    disk_quota: Option<u64>,
+    worker_label: Option<String>,
+    upgrade_token: Option<u64>,
     listen_addr: IpAddr,
+    mysql_addr: Option<SocketAddr>,
     log: slog::Logger,
+    clock: Clock,
+    watermark_policy: Arc<dyn WatermarkPolicy>,
 }
 impl Default for Builder {
     fn default() -> Self {
@@ -25,6 +35,12 @@ impl Default for Builder {
             log: slog::Logger::root(slog::Discard, o!()),
             memory_limit: None,
             memory_check_frequency: None,
+            disk_quota: None,
+            worker_label: None,
+            upgrade_token: None,
+            mysql_addr: None,
+            clock: Clock::default(),
+            watermark_policy: Arc::new(DefaultWatermarkPolicy),
         }
     }
 }
@@ -43,6 +59,45 @@ impl Builder {
         self.config.domain_config.replay_batch_timeout = t;
     }
 
+    /// Make each domain's choice of which input to consume next deterministic.
+    ///
+    /// By default, domains alternate between inputs from domains on the same worker and inputs
+    /// arriving over the network. In deterministic mode, each such choice is instead drawn from a
+    /// random number generator seeded with `seed` (mixed with the domain's index and shard), so
+    /// re-running a failing test with the same seed replays the same sequence of decisions.
+    ///
+    /// Each worker also runs all of its domains on a single thread with a basic scheduler, so that
+    /// only one domain processes packets at a time. The order in which each domain processed its
+    /// packets is reported as `DomainStats::schedule`, so runs can be checked to agree.
+    ///
+    /// This is meant for testing. It does not control when packets *arrive* at a domain, and
+    /// eviction still picks keys at random.
+    pub fn set_deterministic(&mut self, seed: u64) {
+        self.config.domain_config.interleave_seed = Some(seed);
+    }
+
+    /// Make the instance go by `clock` rather than the host's clock.
+    ///
+    /// With a `Clock::manual`, a test can call `Clock::advance` to expire audit log entries, run
+    /// the periodic consistency checks and base verification, and make workers miss heartbeats
+    /// without waiting for that much time to pass.
+    ///
+    /// This is meant for testing. Timeouts on replays and client requests still use the host's
+    /// clock.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
+    /// Move the rows of any key in in-memory operator state that grows beyond `rows` rows to a
+    /// temporary file on local disk.
+    ///
+    /// This keeps a heavily skewed key, such as a join key shared by most records, from holding
+    /// an unbounded amount of memory. Lookups for such keys read their rows back from disk, so
+    /// they are slower. By default, all state is kept in memory.
+    pub fn set_spill_threshold(&mut self, rows: usize) {
+        self.config.domain_config.spill_threshold = Some(rows);
+    }
+
     /// Set the persistence parameters used by the system.
     pub fn set_persistence(&mut self, p: PersistenceParameters) {
         self.config.persistence = p;
@@ -58,6 +113,52 @@ impl Builder {
         self.config.frontier_strategy = f;
     }
 
+    /// Set how the workers for new domains are chosen (the default is round-robin).
+    pub fn set_placement_strategy(&mut self, placement: PlacementStrategy) {
+        self.config.placement = placement;
+    }
+
+    /// Turn away writes to existing base tables while a migration brings up new domains below
+    /// them.
+    ///
+    /// By default, such writes wait until the migration completes. With this enabled, they fail
+    /// with `TableError::MigrationInProgress` instead, unless the `Table` they are written through
+    /// has been told to retry them with `Table::set_migration_backoff`.
+    pub fn set_reject_writes_during_migration(&mut self, reject: bool) {
+        self.config.reject_writes_during_migration = reject;
+    }
+
+    /// Limit how many writes to the base table `base` each `Table` handle may have in flight to
+    /// the table it mirrors them to, in place of the default limit of 1024.
+    ///
+    /// A mirrored write that would exceed the limit is dropped, and counted in
+    /// `Table::mirrored_writes`. See `Table::mirror_to`.
+    pub fn set_mirror_limit(&mut self, base: &str, max: usize) {
+        self.config.mirror_limits.insert(base.to_owned(), max);
+    }
+
+    /// Give up on domains that do not reply to the controller within `timeout`.
+    ///
+    /// A domain that is still reachable when the timeout passes is given up to `retries` more
+    /// periods of `timeout` to reply. Migrations that time out fail, as do the operations that
+    /// report on or change the dataflow. By default, the controller waits for as long as it takes.
+    pub fn set_control_reply_timeout(&mut self, timeout: time::Duration, retries: usize) {
+        self.config.control_reply_timeout = Some((timeout, retries));
+    }
+
+    /// Label this worker, for placement strategies that take labels into account.
+    pub fn set_worker_label<S: Into<String>>(&mut self, label: S) {
+        self.worker_label = Some(label.into());
+    }
+
+    /// Finish the rolling upgrade with the given token once this worker registers.
+    ///
+    /// The token is returned by `ControllerHandle::rolling_upgrade`, and the worker being upgraded
+    /// must be restarted with it for the domains that were handed off to be moved back onto it.
+    pub fn set_upgrade_token(&mut self, token: u64) {
+        self.upgrade_token = Some(token);
+    }
+
     /// Set sharding policy for all subsequent migrations; `None` disables
     pub fn set_sharding(&mut self, shards: Option<usize>) {
         self.config.sharding = shards;
@@ -70,6 +171,21 @@ impl Builder {
         self.config.quorum = quorum;
     }
 
+    /// Set how often workers send the controller a heartbeat. Defaults to every second.
+    ///
+    /// A worker that misses four heartbeats in a row is considered failed: the queries that depend
+    /// on its domains are rebuilt on the remaining workers, and the failure is listed by
+    /// `ControllerHandle::worker_failures`.
+    pub fn set_heartbeat_interval(&mut self, every: time::Duration) {
+        self.config.heartbeat_every = every;
+    }
+
+    /// Set how often the controller checks for workers that have missed their heartbeats, which
+    /// bounds how long a failed worker goes unnoticed. Defaults to every ten seconds.
+    pub fn set_healthcheck_interval(&mut self, every: time::Duration) {
+        self.config.healthcheck_every = every;
+    }
+
     /// Set the memory limit (target) and how often we check it (in millis).
     pub fn set_memory_limit(&mut self, limit: usize, check_freq: time::Duration) {
         assert_ne!(limit, 0);
@@ -78,11 +194,39 @@ impl Builder {
         self.memory_check_frequency = Some(check_freq);
     }
 
+    /// Limit the disk space that base table persistence may use on this worker to `bytes`.
+    ///
+    /// The controller does not place new base table shards on a worker whose persistence files
+    /// already use (or, with `set_base_disk_reservation`, would use) more than its quota, and
+    /// refuses recipe changes that add base tables if no worker has room for them. This only has
+    /// an effect if base tables are persisted to disk.
+    pub fn set_disk_quota(&mut self, bytes: u64) {
+        self.disk_quota = Some(bytes);
+    }
+
+    /// Set the disk space, in bytes, that placement assumes each base table shard uses at least
+    /// (default is 0).
+    ///
+    /// This keeps room in a worker's disk quota for base tables that have not yet grown to their
+    /// eventual size. With the default, only workers whose persistence files already exceed their
+    /// disk quota are passed over.
+    pub fn set_base_disk_reservation(&mut self, bytes: u64) {
+        self.config.base_disk_reservation = bytes;
+    }
+
     /// Set the IP address that the worker should use for listening.
     pub fn set_listen_addr(&mut self, listen_addr: IpAddr) {
         self.listen_addr = listen_addr;
     }
 
+    /// Accept connections from MySQL clients on `addr`.
+    ///
+    /// Clients can create tables, write to them, and issue queries using the MySQL protocol, as
+    /// described for `Handle::mysql_addr`. By default, no MySQL frontend is started.
+    pub fn set_mysql_listen_addr(&mut self, addr: SocketAddr) {
+        self.mysql_addr = Some(addr);
+    }
+
     /// Set the logger that the derived worker should use. By default, it uses `slog::Discard`.
     pub fn log_with(&mut self, log: slog::Logger) {
         self.log = log;
@@ -93,6 +237,250 @@ impl Builder {
         self.config.reuse = reuse_type;
     }
 
+    /// Only have joins carry the columns that the rest of their query uses, rather than all of
+    /// the columns of both their inputs.
+    ///
+    /// This keeps unused columns out of the state and the updates of the nodes below joins. By
+    /// default, joins carry all of their inputs' columns, which lets more queries share them. The
+    /// number of columns each new join leaves out is reported in `DataflowDiff::pruned_columns`.
+    pub fn set_column_pruning(&mut self, enabled: bool) {
+        self.config.prune_columns = enabled;
+    }
+
+    /// Keep an audit log of the last `capacity` writes to every base table created by subsequent
+    /// migrations.
+    ///
+    /// Writes are attributed to the identity set with `Table::set_identity`, and the log can be
+    /// read back with `ControllerHandle::audit_log`.
+    pub fn enable_audit(&mut self, capacity: usize) {
+        assert_ne!(capacity, 0);
+        self.config.audit_capacity = Some(capacity);
+    }
+
+    /// Discard audit log entries once they are older than `max_age`.
+    ///
+    /// By default, entries are only discarded once a table's audit log reaches the capacity given
+    /// to `enable_audit`. How many entries each table has discarded for either reason is reported
+    /// in the `probe_result` of its node statistics.
+    pub fn set_audit_retention(&mut self, max_age: time::Duration) {
+        self.config.domain_config.audit_retention = Some(max_age);
+    }
+
+    /// Save the contents of every view to disk every `every`, so that a restarted deployment can
+    /// serve reads right away.
+    ///
+    /// This only has an effect with `DurabilityMode::Permanent`. After a restart, each view serves
+    /// the rows it had when it was last saved until it has been rebuilt from the base tables.
+    /// Such results are flagged with `Results::is_stale`. For partially materialized views, this
+    /// happens per key, as keys are filled. Each domain writes its snapshots on a thread of its
+    /// own; see `set_domain_maintenance_thread`.
+    pub fn set_reader_snapshot_interval(&mut self, every: time::Duration) {
+        self.config.domain_config.reader_snapshot_interval = Some(every);
+    }
+
+    /// Give each domain that saves reader snapshots a second thread to encode and write them to
+    /// disk on.
+    ///
+    /// This is enabled by default, and keeps the latency of reads and writes steady for domains
+    /// with large readers, at the cost of a thread per domain shard. With it disabled, snapshots
+    /// are written on the thread that processes the domain's packets, which holds up its reads
+    /// and writes for as long as that takes. Either way, the rows to save are copied on that
+    /// thread.
+    pub fn set_domain_maintenance_thread(&mut self, enabled: bool) {
+        self.config.domain_config.inline_reader_snapshots = !enabled;
+    }
+
+    /// Checksum the data of every replay piece a domain sends, and verify it on receipt.
+    ///
+    /// A partial replay piece that fails verification is discarded and its keys are requested
+    /// again, up to a few times. For any other corrupt piece, the controller rebuilds the queries
+    /// that depend on the receiving domain. Every corrupt piece is listed by
+    /// `ControllerHandle::replay_corruptions`, and counted in its domain's statistics.
+    pub fn set_replay_checksums(&mut self, enabled: bool) {
+        self.config.domain_config.replay_checksums = enabled;
+    }
+
+    /// Spoil the checksum of every `every`'th replay piece each domain sends, to test how corrupt
+    /// replays are recovered from. Only has an effect with replay checksums enabled.
+    #[doc(hidden)]
+    pub fn set_replay_corruption(&mut self, every: usize) {
+        assert_ne!(every, 0);
+        self.config.domain_config.corrupt_replay_pieces = Some(every);
+    }
+
+    /// Have every domain report its statistics to the controller this often.
+    ///
+    /// Clients can then follow the statistics through `ControllerHandle::statistics_stream`
+    /// without the controller having to ask each domain for them.
+    pub fn set_statistics_interval(&mut self, every: time::Duration) {
+        self.config.domain_config.statistics_interval = Some(every);
+    }
+
+    /// Ask the watermark policy what to do whenever the state of an operator grows past one of
+    /// `watermarks`, given in bytes.
+    ///
+    /// Base tables and readers are left alone. Each operator's node statistics count how many
+    /// times its state has grown past a watermark.
+    pub fn set_state_watermarks(&mut self, mut watermarks: Vec<u64>) {
+        watermarks.sort_unstable();
+        watermarks.dedup();
+        self.config.domain_config.state_watermarks = watermarks;
+    }
+
+    /// Decide what to do about operators whose state grows past a watermark with `policy`.
+    ///
+    /// By default, partially materialized state is evicted from until it is back below the
+    /// watermark, and fully materialized state is only logged, as `DefaultWatermarkPolicy` does.
+    /// The policy is only used by the workers started from this builder.
+    pub fn set_watermark_policy<P: WatermarkPolicy + 'static>(&mut self, policy: P) {
+        self.watermark_policy = Arc::new(policy);
+    }
+
+    /// Let the group commit window of each base table adapt to its load, up to `max_window`.
+    ///
+    /// Writes to a base table are batched for the persistence `flush_timeout` by default. With
+    /// this set, a table's window widens while writes keep arriving faster than their batches are
+    /// processed, and narrows again when writes arrive alone or would wait longer than
+    /// `max_window` to be acknowledged. Each table's current window is reported as the
+    /// `flush_window` of its node statistics.
+    pub fn set_adaptive_flush(&mut self, max_window: time::Duration) {
+        self.config.domain_config.adaptive_flush = Some(max_window);
+    }
+
+    /// Make domains shed load according to `policy` when they cannot keep up with their input.
+    ///
+    /// By default, domains never shed load. How often each domain has been overloaded, and how
+    /// much it has shed as a result, is reported in its domain statistics.
+    pub fn set_load_shedding(&mut self, policy: LoadSheddingPolicy) {
+        self.config.domain_config.load_shedding = Some(policy);
+    }
+
+    /// Compress the packets that domains send to domains on other workers with `codec`.
+    ///
+    /// This trades CPU time for network bandwidth, which is mostly worthwhile for large replays
+    /// and wide records. Packets between domains on the same worker are never compressed. How
+    /// well each domain's packets compress is reported in its domain statistics.
+    pub fn set_domain_compression(&mut self, codec: Compression) {
+        self.config.domain_config.compression = Some(codec);
+    }
+
+    /// Let sharders spread the records of any key that accounts for more than `share` of their
+    /// input across all shards, rather than sending them all to the one shard the key hashes to.
+    ///
+    /// This keeps a single hot key from overloading one shard. It only applies to sharders below
+    /// which every node up to where the shards are merged again handles each record on its own,
+    /// such as filters and projections, since the split key's results would otherwise be spread
+    /// across shards. Keys are never split while being replayed. How many keys have been split is
+    /// reported in the domain statistics.
+    pub fn set_hot_key_split(&mut self, share: f64) {
+        assert!(share > 0.0 && share <= 1.0);
+        self.config.hot_key_split = Some(share);
+    }
+
+    /// Give each shard of a sharded domain a share of the keys proportional to its weight in
+    /// `weights`, rather than an equal share.
+    ///
+    /// A key space with a hot range can then give the shard that owns it fewer other keys. There
+    /// must be one weight per shard, as set with `set_sharding`; domains with a different number
+    /// of shards keep spreading keys evenly. Each domain keeps the weights it was created with,
+    /// since keys are not moved between shards. See also `set_shard_weights_for`.
+    pub fn set_shard_weights(&mut self, weights: Vec<u32>) {
+        assert!(!weights.is_empty() && weights.iter().all(|&w| w > 0));
+        self.config.domain_config.shard_weights = weights;
+    }
+
+    /// Give the shards of the domain that holds the table or query `name` the weights in
+    /// `weights`, in place of those set with `set_shard_weights`.
+    ///
+    /// Read replicas of a view are always weighted like the view's own reader.
+    pub fn set_shard_weights_for(&mut self, name: &str, weights: Vec<u32>) {
+        assert!(!weights.is_empty() && weights.iter().all(|&w| w > 0));
+        self.config.shard_weights.insert(name.to_owned(), weights);
+    }
+
+    /// Set the number of independent accept loops each worker runs for reads (default is 1).
+    ///
+    /// With more than one acceptor, the listeners share the worker's read port using
+    /// `SO_REUSEPORT`, and the kernel spreads incoming client connections across them. Each
+    /// acceptor has its own queue of blocking reads, so read-heavy workloads with many clients are
+    /// not limited by a single accept loop. On platforms without `SO_REUSEPORT`, a single acceptor
+    /// is always used.
+    pub fn set_read_acceptors(&mut self, acceptors: usize) {
+        assert_ne!(acceptors, 0);
+        self.config.read_acceptors = acceptors;
+    }
+
+    /// Periodically check that partially materialized views agree with the data they are
+    /// computed from.
+    ///
+    /// Every `every`, the controller checks up to `samples` randomly chosen keys from each shard of
+    /// each partially materialized view, as described in `ControllerHandle::check_consistency`.
+    /// Divergence is logged, and reported through `ControllerHandle::consistency_events`.
+    pub fn set_consistency_check(&mut self, every: time::Duration, samples: usize) {
+        self.config.consistency_check = Some((every, samples));
+    }
+
+    /// Periodically verify the rows that base tables keep on disk.
+    ///
+    /// Every `every`, the controller checks each base table as described in
+    /// `ControllerHandle::verify_base`, without repairing it. Problems are logged.
+    pub fn set_base_verification(&mut self, every: time::Duration) {
+        self.config.base_verification = Some(every);
+    }
+
+    /// Keep workers running when one of their domains panics.
+    ///
+    /// By default, a panic in a domain takes the domain down with it. With supervision enabled,
+    /// the worker instead reports the failed domain to the controller (see
+    /// `ControllerHandle::domain_failures`), and rebuilds it in place if it keeps no state, that
+    /// is, if it holds no base tables, views, or materialized operators, and if the packet that
+    /// caused the panic carried no updates or replays. Domains downstream of a rebuilt domain ask
+    /// again for the partial replays they were waiting for, since those may have been lost with
+    /// it; keys whose replays keep getting lost are given up on after a few tries, and asked for
+    /// anew when next read. Any other domain without base tables stops processing, and the
+    /// controller rebuilds the queries that depend on it, so that their state is recomputed from
+    /// the base tables. A domain with base tables is still taken down by its panic.
+    pub fn set_domain_supervision(&mut self, supervise: bool) {
+        self.config.supervise_domains = supervise;
+    }
+
+    /// Limit the number of reads each client connection may have in flight at a worker.
+    ///
+    /// A worker stops reading further requests from a connection that has `max` reads
+    /// outstanding until one of them completes. By default, connections are not limited. The
+    /// clients connected to each worker can be listed with `ControllerHandle::connections`.
+    pub fn set_max_outstanding_reads(&mut self, max: usize) {
+        assert_ne!(max, 0);
+        self.config.max_outstanding_reads = Some(max);
+    }
+
+    /// Limit the number of writes each client connection may have in flight at a domain.
+    ///
+    /// A domain stops reading further writes from a connection that has `max` writes it has not
+    /// yet acknowledged, until it acknowledges one of them. By default, connections are not
+    /// limited.
+    pub fn set_max_outstanding_writes(&mut self, max: usize) {
+        assert_ne!(max, 0);
+        self.config.max_outstanding_writes = Some(max);
+    }
+
+    /// Add read replicas to views whose reads queue up, and remove them again once they don't.
+    ///
+    /// When more than `queue_threshold` blocking reads stay queued on some shard of a view's
+    /// readers for `window`, the controller adds another reader for the view in a domain of its
+    /// own, up to `max_replicas` of them. A replica is removed once no shard of the view's readers
+    /// has had more than half that many reads queued for `window`. `View` handles spread their
+    /// reads across the view's readers, and read from the view's own reader instead of a replica
+    /// that has been removed. The changes are listed by `ControllerHandle::scaling_events`.
+    pub fn set_read_autoscaling(
+        &mut self,
+        queue_threshold: usize,
+        max_replicas: usize,
+        window: time::Duration,
+    ) {
+        self.config.read_autoscaling = Some((queue_threshold, max_replicas, window));
+    }
+
     /// Set the number of pool threads to use (default is #cores)
     pub fn set_threads(&mut self, threads: usize) {
         self.config.threads = Some(threads);
@@ -113,11 +501,20 @@ impl Builder {
             ref config,
             memory_limit,
             memory_check_frequency,
+            disk_quota,
+            ref worker_label,
+            upgrade_token,
+            mysql_addr,
             ref log,
+            ref clock,
+            ref watermark_policy,
         } = *self;
 
         let config = config.clone();
+        let worker_label = worker_label.clone();
         let log = log.clone();
+        let clock = clock.clone();
+        let watermark_policy = watermark_policy.clone();
 
         crate::startup::start_instance(
             authority,
@@ -125,7 +522,13 @@ impl Builder {
             config,
             memory_limit,
             memory_check_frequency,
+            disk_quota,
+            worker_label,
+            upgrade_token,
+            mysql_addr,
             log,
+            clock,
+            watermark_policy,
         )
     }
 
@@ -0,0 +1,149 @@
+use crate::builder::Builder;
+use crate::handle::Handle;
+use dataflow::PersistenceParameters;
+use noria::consensus::LocalAuthority;
+use noria::ControllerHandle;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+/// A Noria deployment whose controller and workers all run in the current process.
+///
+/// The workers coordinate through a shared `LocalAuthority` instead of ZooKeeper, and talk to
+/// each other over the loopback interface just like a distributed deployment would. This makes
+/// it a convenient way to test multi-worker behavior without any external services:
+///
+/// ```no_run
+/// # async fn run() -> Result<(), failure::Error> {
+/// let mut cluster = noria_server::LocalCluster::builder()
+///     .workers(2)
+///     .sharding(Some(2))
+///     .build()
+///     .await?;
+/// cluster.install_recipe("CREATE TABLE t (id int, PRIMARY KEY(id));").await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The cluster derefs to a `ControllerHandle` for the deployment. Dropping it stops all of its
+/// workers.
+pub struct LocalCluster {
+    authority: Arc<LocalAuthority>,
+    handle: ControllerHandle<LocalAuthority>,
+    workers: Vec<Handle<LocalAuthority>>,
+    done: Vec<Box<dyn Future<Output = ()> + Unpin + Send>>,
+}
+
+/// Used to construct a `LocalCluster`.
+pub struct LocalClusterBuilder {
+    builder: Builder,
+    workers: usize,
+}
+
+impl LocalCluster {
+    /// Start describing a single-process deployment with one worker and the default sharding.
+    pub fn builder() -> LocalClusterBuilder {
+        LocalClusterBuilder {
+            builder: Builder::default(),
+            workers: 1,
+        }
+    }
+
+    /// A handle to the controller of this deployment.
+    pub fn handle(&self) -> ControllerHandle<LocalAuthority> {
+        self.handle.clone()
+    }
+
+    /// The authority the workers of this deployment coordinate through.
+    ///
+    /// Additional workers can join the deployment by starting them with this authority.
+    pub fn authority(&self) -> Arc<LocalAuthority> {
+        self.authority.clone()
+    }
+
+    /// Stop all workers, and wait for them to exit.
+    pub async fn shutdown(mut self) {
+        for mut worker in self.workers.drain(..) {
+            worker.shutdown();
+        }
+        for done in self.done.drain(..) {
+            done.await;
+        }
+    }
+}
+
+impl Deref for LocalCluster {
+    type Target = ControllerHandle<LocalAuthority>;
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
+
+impl DerefMut for LocalCluster {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.handle
+    }
+}
+
+impl LocalClusterBuilder {
+    /// Run `n` workers (default is 1).
+    ///
+    /// The deployment is not considered ready until all of them have joined, so every worker is
+    /// eligible to host the domains of the first migration.
+    pub fn workers(mut self, n: usize) -> Self {
+        assert_ne!(n, 0);
+        self.workers = n;
+        self
+    }
+
+    /// Set the sharding policy for all migrations; `None` disables sharding.
+    pub fn sharding(mut self, shards: Option<usize>) -> Self {
+        self.builder.set_sharding(shards);
+        self
+    }
+
+    /// Set the persistence parameters used by every worker.
+    pub fn persistence(mut self, p: PersistenceParameters) -> Self {
+        self.builder.set_persistence(p);
+        self
+    }
+
+    /// Adjust any other configuration of the workers.
+    pub fn configure<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut Builder),
+    {
+        f(&mut self.builder);
+        self
+    }
+
+    /// Start all workers, and wait for the deployment to be ready to accept migrations.
+    pub async fn build(self) -> Result<LocalCluster, failure::Error> {
+        let LocalClusterBuilder {
+            mut builder,
+            workers: n,
+        } = self;
+        builder.set_quorum(n);
+
+        let authority = Arc::new(LocalAuthority::new());
+        let mut workers = Vec::with_capacity(n);
+        let mut done = Vec::with_capacity(n);
+        for _ in 0..n {
+            let (worker, d) = builder.start(authority.clone()).await?;
+            workers.push(worker);
+            done.push(Box::new(d) as Box<dyn Future<Output = ()> + Unpin + Send>);
+        }
+
+        // the controller answers most requests only once a quorum of workers has joined, and the
+        // handle retries until then.
+        let mut handle = (*workers[0]).clone();
+        handle.inputs().await?;
+
+        Ok(LocalCluster {
+            authority,
+            handle,
+            workers,
+            done,
+        })
+    }
+}
@@ -0,0 +1,154 @@
+//! Consistency checks of partial views.
+//!
+//! A check has to wait for the keys it evicts to be recomputed from upstream, which can take a
+//! while. Checks therefore run as tasks of their own, and only hand the controller the short
+//! steps that talk to domains, so that the controller keeps handling heartbeats and requests while
+//! a check waits.
+
+use crate::controller::inner::ControllerInner;
+use crate::startup::Event;
+use dataflow::prelude::DataType;
+use futures_util::stream::StreamExt;
+use noria::ConsistencyEvent;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use stream_cancel::Valve;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// How long a consistency check waits for sampled keys to be recomputed from upstream.
+const REFILL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Run `f` on the controller, and wait for its result.
+pub(super) async fn on_controller<F, T>(tx: &UnboundedSender<Event>, f: F) -> Result<T, String>
+where
+    F: FnOnce(&mut ControllerInner) -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let (done, rx) = tokio::sync::oneshot::channel();
+    tx.send(Event::OnController(Box::new(move |ctrl| {
+        let _ = done.send(f(ctrl));
+    })))
+    .map_err(|_| "controller has shut down".to_owned())?;
+    rx.await
+        .map_err(|_| "controller stepped down during the check".to_owned())
+}
+
+/// Fingerprint the given keys of the view `view` (and up to `count` random others from each
+/// shard), have them recomputed, and return the original and the recomputed fingerprints.
+///
+/// Keys whose recomputation has not completed within `REFILL_TIMEOUT` are reported as `None` in
+/// the recomputed fingerprints.
+async fn refill(
+    tx: &UnboundedSender<Event>,
+    view: &str,
+    count: usize,
+    keys: Vec<Vec<DataType>>,
+) -> Result<Vec<(Vec<DataType>, u64, Option<u64>)>, String> {
+    let v = view.to_owned();
+    let before = on_controller(tx, move |ctrl| ctrl.sample_reader_keys(&v, count, keys)).await??;
+    let keys: Vec<_> = before.keys().cloned().collect();
+
+    let start = Instant::now();
+    let mut after = HashMap::new();
+    while !keys.is_empty() {
+        let (v, keys) = (view.to_owned(), keys.clone());
+        after = on_controller(tx, move |ctrl| ctrl.fingerprint_reader_keys(&v, keys)).await??;
+        if after.values().all(Option::is_some) || start.elapsed() > REFILL_TIMEOUT {
+            break;
+        }
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+    }
+
+    Ok(before
+        .into_iter()
+        .filter_map(|(k, fp)| {
+            let fp = fp?;
+            let refilled = after.get(&k).and_then(|fp| *fp);
+            Some((k, fp, refilled))
+        })
+        .collect())
+}
+
+/// Check a random sample of the keys of the view `view` against a recomputation from the
+/// materializations it is computed from.
+///
+/// See `ControllerHandle::check_consistency`.
+pub(super) async fn check(
+    tx: UnboundedSender<Event>,
+    log: slog::Logger,
+    view: String,
+    samples: usize,
+) -> Result<ConsistencyEvent, String> {
+    let v = view.clone();
+    on_controller(&tx, move |ctrl| ctrl.consistency_reader(&v).map(|_| ())).await??;
+
+    let mut checked = 0;
+    let mut mismatched = Vec::new();
+    for (k, before, after) in refill(&tx, &view, samples, Vec::new()).await? {
+        if let Some(after) = after {
+            checked += 1;
+            if before != after {
+                mismatched.push(k);
+            }
+        }
+    }
+
+    // a write to a key between when it was fingerprinted and when it was recomputed also
+    // changes its fingerprint, so only report keys that differ twice in a row.
+    let mut diverged = Vec::new();
+    if !mismatched.is_empty() {
+        for (k, before, after) in refill(&tx, &view, 0, mismatched).await? {
+            if after.map(|after| before != after).unwrap_or(false) {
+                diverged.push(k);
+            }
+        }
+    }
+
+    let event = if diverged.is_empty() {
+        debug!(log, "view is consistent"; "view" => &view, "checked" => checked);
+        ConsistencyEvent::Consistent { view, checked }
+    } else {
+        diverged.sort();
+        crit!(
+            log,
+            "view diverged from upstream";
+            "view" => &view,
+            "checked" => checked,
+            "diverged" => diverged.len()
+        );
+        ConsistencyEvent::Diverged {
+            view,
+            checked,
+            keys: diverged,
+        }
+    };
+
+    let e = event.clone();
+    on_controller(&tx, move |ctrl| ctrl.record_consistency_event(e)).await?;
+    Ok(event)
+}
+
+/// Check every partially materialized view whenever a periodic consistency check is due.
+///
+/// See `Builder::set_consistency_check`.
+pub(super) async fn run_periodically(
+    tx: UnboundedSender<Event>,
+    valve: Valve,
+    log: slog::Logger,
+    every: Duration,
+) {
+    let mut ticks = valve.wrap(tokio::time::interval(every));
+    while ticks.next().await.is_some() {
+        let (views, samples) = match on_controller(&tx, |ctrl| ctrl.due_consistency_checks()).await
+        {
+            Ok(Some(due)) => due,
+            Ok(None) => continue,
+            Err(_) => break,
+        };
+        for view in views {
+            if let Err(e) = check(tx.clone(), log.clone(), view.clone(), samples).await {
+                warn!(log, "failed to check consistency"; "view" => view, "err" => e);
+            }
+        }
+    }
+}
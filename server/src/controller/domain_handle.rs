@@ -1,21 +1,153 @@
 use crate::controller::{Worker, WorkerIdentifier};
 use dataflow::prelude::*;
+use futures_util::future::FutureExt;
 use noria::channel::tcp;
 use slog::Logger;
 use std::collections::HashMap;
 use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use tokio::sync::mpsc;
+
+/// The number of packets that may be queued for a domain shard before sends to it have to wait,
+/// or are turned away with `SendError::Backpressure`.
+const HIGH_WATERMARK: usize = 256;
+
+#[derive(Default)]
+struct Queue {
+    /// Packets queued for the shard that have not been sent yet.
+    len: usize,
+    /// Set once sending to the shard has failed; nothing is sent to it after that.
+    closed: bool,
+}
+
+fn closed() -> tcp::SendError {
+    io::Error::new(io::ErrorKind::BrokenPipe, "connection to domain closed").into()
+}
 
 pub(super) struct DomainShardHandle {
     pub(super) worker: WorkerIdentifier,
-    pub(super) tx: Box<dyn noria::channel::Sender<Item = Box<Packet>> + Send>,
+    /// Whether the shard panicked and was not rebuilt. It drops whatever it is sent, other than
+    /// `Packet::Quit`, and so never replies.
+    pub(super) failed: bool,
+    tx: mpsc::UnboundedSender<Box<Packet>>,
+    queue: Arc<(Mutex<Queue>, Condvar)>,
+}
+
+impl DomainShardHandle {
+    /// Send the packets for a domain shard over `tx` from a task of its own, so that a shard that
+    /// is slow to accept packets does not hold up sends to other shards.
+    ///
+    /// Sending over `tx` blocks, so the task hands whatever is queued to the runtime's blocking
+    /// threads, rather than keep a thread for every shard.
+    pub(super) fn new(
+        worker: WorkerIdentifier,
+        tx: Box<dyn noria::channel::Sender<Item = Box<Packet>> + Send>,
+        log: Logger,
+    ) -> Self {
+        let (qtx, mut qrx) = mpsc::unbounded_channel::<Box<Packet>>();
+        let queue = Arc::new((Mutex::new(Queue::default()), Condvar::new()));
+        let q = Arc::clone(&queue);
+        tokio::spawn(async move {
+            let mut tx = Some(tx);
+            // the task exits once the handle is dropped and everything queued has been sent
+            while let Some(p) = qrx.recv().await {
+                let mut batch = vec![p];
+                while let Some(Some(p)) = qrx.recv().now_or_never() {
+                    batch.push(p);
+                }
+
+                let mut sender = tx.take().unwrap();
+                let q = Arc::clone(&q);
+                let sending = tokio::task::spawn_blocking(move || {
+                    let (ref lock, ref drained) = *q;
+                    for p in batch {
+                        let res = sender.send(p);
+                        let mut queue = lock.lock().unwrap();
+                        queue.len -= 1;
+                        if let Err(e) = res {
+                            queue.closed = true;
+                            drained.notify_all();
+                            return Err(e);
+                        }
+                        drained.notify_all();
+                    }
+                    Ok(sender)
+                });
+                match sending.await {
+                    Ok(Ok(sender)) => tx = Some(sender),
+                    Ok(Err(e)) => {
+                        error!(log, "failed to send packet to domain"; "err" => ?e);
+                        return;
+                    }
+                    Err(e) => {
+                        error!(log, "sending packets to domain panicked"; "err" => ?e);
+                        let (ref lock, ref drained) = *q;
+                        lock.lock().unwrap().closed = true;
+                        drained.notify_all();
+                        return;
+                    }
+                }
+            }
+        });
+
+        DomainShardHandle {
+            worker,
+            failed: false,
+            tx: qtx,
+            queue,
+        }
+    }
+
+    /// Whether another packet can be queued for the shard without going over the high watermark.
+    fn has_room(&self) -> Result<bool, tcp::SendError> {
+        let queue = self.queue.0.lock().unwrap();
+        if queue.closed {
+            return Err(closed());
+        }
+        Ok(queue.len < HIGH_WATERMARK)
+    }
+
+    /// Wait until another packet can be queued for the shard.
+    fn wait_for_room(&self) -> Result<(), tcp::SendError> {
+        let (ref lock, ref drained) = *self.queue;
+        let mut queue = lock.lock().unwrap();
+        while !queue.closed && queue.len >= HIGH_WATERMARK {
+            queue = drained.wait(queue).unwrap();
+        }
+        if queue.closed {
+            Err(closed())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Queue `p` to be sent to the shard.
+    fn enqueue(&self, p: Box<Packet>) -> Result<(), tcp::SendError> {
+        let mut queue = self.queue.0.lock().unwrap();
+        if queue.closed {
+            return Err(closed());
+        }
+        // counted before it is handed over, so that the sending task never sees it uncounted
+        queue.len += 1;
+        self.tx.send(p).map_err(|_| closed())
+    }
 }
 
 /// A `DomainHandle` is a handle that allows communicating with all of the shards of a given
 /// domain.
+///
+/// Packets are queued for each shard, and sent from a task per shard. A send only waits if a
+/// shard already has `HIGH_WATERMARK` packets queued, and then only for that shard.
 pub(super) struct DomainHandle {
     pub(super) idx: DomainIndex,
     pub(super) shards: Vec<DomainShardHandle>,
     pub(super) log: Logger,
+    /// Whether the domain keeps base table state on disk, which counts against the disk quota of
+    /// the workers its shards are on.
+    pub(super) uses_disk: bool,
+    /// The share of the keys each shard owns relative to the others, or empty if they are spread
+    /// evenly. Everything that routes keys to the domain's shards must use these weights.
+    pub(super) shard_weights: Vec<u32>,
 }
 
 impl DomainHandle {
@@ -35,22 +167,59 @@ impl DomainHandle {
         self.shards.iter().any(|s| s.worker == *worker)
     }
 
+    /// Whether any shard of the domain panicked and was not rebuilt.
+    pub(super) fn has_failed_shards(&self) -> bool {
+        self.shards.iter().any(|s| s.failed)
+    }
+
+    /// Whether packets can still be sent to every shard of the domain.
+    pub(super) fn is_reachable(&self) -> bool {
+        self.shards.iter().all(|s| s.has_room().is_ok())
+    }
+
+    fn check_healthy(
+        &self,
+        i: usize,
+        workers: &HashMap<WorkerIdentifier, Worker>,
+    ) -> Result<(), tcp::SendError> {
+        if workers[&self.shards[i].worker].healthy {
+            Ok(())
+        } else {
+            error!(
+                self.log,
+                "Tried to send packet to failed worker {:?}; ignoring!", &self.shards[i].worker
+            );
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "worker failed").into())
+        }
+    }
+
     pub(super) fn send_to_healthy(
         &mut self,
         p: Box<Packet>,
         workers: &HashMap<WorkerIdentifier, Worker>,
     ) -> Result<(), tcp::SendError> {
-        for shard in self.shards.iter_mut() {
-            if workers[&shard.worker].healthy {
-                shard.tx.send(p.clone())?;
-            } else {
-                error!(
-                    self.log,
-                    "Tried to send packet to failed worker {:?}; ignoring!", shard.worker
-                );
-                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "worker failed").into());
+        for i in 0..self.shards.len() {
+            self.send_to_healthy_shard(i, p.clone(), workers)?;
+        }
+        Ok(())
+    }
+
+    /// Like `send_to_healthy`, but rather than wait for shards that have too many packets queued
+    /// already, fails with `SendError::Backpressure` without sending `p` to any shard.
+    pub(super) fn try_send_to_healthy(
+        &mut self,
+        p: Box<Packet>,
+        workers: &HashMap<WorkerIdentifier, Worker>,
+    ) -> Result<(), tcp::SendError> {
+        for (i, shard) in self.shards.iter().enumerate() {
+            self.check_healthy(i, workers)?;
+            if !shard.has_room()? {
+                return Err(tcp::SendError::Backpressure);
             }
         }
+        for shard in &self.shards {
+            shard.enqueue(p.clone())?;
+        }
         Ok(())
     }
 
@@ -60,15 +229,123 @@ impl DomainHandle {
         p: Box<Packet>,
         workers: &HashMap<WorkerIdentifier, Worker>,
     ) -> Result<(), tcp::SendError> {
-        if workers[&self.shards[i].worker].healthy {
-            self.shards[i].tx.send(p)?;
-        } else {
-            error!(
-                self.log,
-                "Tried to send packet to failed worker {:?}; ignoring!", &self.shards[i].worker
-            );
-            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "worker failed").into());
+        self.check_healthy(i, workers)?;
+        self.shards[i].wait_for_room()?;
+        self.shards[i].enqueue(p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+
+    /// Sends each packet only once it is let through by the gate.
+    struct Gated {
+        gate: mpsc::Receiver<()>,
+        sent: mpsc::Sender<Box<Packet>>,
+    }
+
+    impl noria::channel::Sender for Gated {
+        type Item = Box<Packet>;
+
+        fn send(&mut self, p: Box<Packet>) -> Result<(), tcp::SendError> {
+            self.gate.recv().map_err(|_| closed())?;
+            self.sent.send(p).unwrap();
+            Ok(())
         }
-        Ok(())
+    }
+
+    type Sent = mpsc::Receiver<Box<Packet>>;
+
+    fn shard() -> (DomainShardHandle, mpsc::Sender<()>, Sent) {
+        let (gate_tx, gate) = mpsc::channel();
+        let (sent, sent_rx) = mpsc::channel();
+        let handle = DomainShardHandle::new(
+            ([127, 0, 0, 1], 0).into(),
+            Box::new(Gated { gate, sent }),
+            Logger::root(slog::Discard, o!()),
+        );
+        (handle, gate_tx, sent_rx)
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn full_shard_does_not_stall_others() {
+        let (stuck, gate, _stuck_sent) = shard();
+        let (free, free_gate, free_sent) = shard();
+
+        for _ in 0..HIGH_WATERMARK {
+            assert!(stuck.has_room().unwrap());
+            stuck.enqueue(Box::new(Packet::Quit)).unwrap();
+        }
+        assert!(!stuck.has_room().unwrap());
+
+        // the other shard is unaffected
+        free_gate.send(()).unwrap();
+        free.enqueue(Box::new(Packet::Quit)).unwrap();
+        free_sent.recv().unwrap();
+        assert!(free.has_room().unwrap());
+
+        // once the stuck shard makes progress, there is room again
+        gate.send(()).unwrap();
+        stuck.wait_for_room().unwrap();
+        assert!(stuck.has_room().unwrap());
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn failed_shard_is_closed() {
+        let (handle, gate, _) = shard();
+        drop(gate);
+        handle.enqueue(Box::new(Packet::Quit)).unwrap();
+        while handle.has_room().is_ok() {
+            thread::yield_now();
+        }
+        assert!(handle.wait_for_room().is_err());
+        assert!(handle.enqueue(Box::new(Packet::Quit)).is_err());
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn domain_with_failed_shard_is_unreachable() {
+        let (live, _live_gate, _live_sent) = shard();
+        let (failed, gate, _) = shard();
+        let mut d = DomainHandle {
+            idx: DomainIndex::from(0),
+            shards: vec![live],
+            log: Logger::root(slog::Discard, o!()),
+            uses_disk: false,
+            shard_weights: Vec::new(),
+        };
+        assert!(d.is_reachable());
+
+        drop(gate);
+        failed.enqueue(Box::new(Packet::Quit)).unwrap();
+        while failed.has_room().is_ok() {
+            thread::yield_now();
+        }
+        d.shards.push(failed);
+        assert!(!d.is_reachable());
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn failed_shard_fails_reply_wait() {
+        use crate::controller::inner::DomainReplies;
+
+        let (failed, gate, _) = shard();
+        let d = DomainHandle {
+            idx: DomainIndex::from(0),
+            shards: vec![failed],
+            log: Logger::root(slog::Discard, o!()),
+            uses_disk: false,
+            shard_weights: Vec::new(),
+        };
+        let (_reply_tx, reply_rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut replies = DomainReplies::new(reply_rx, None);
+
+        // the packet never reaches the shard, so the wait for its reply gives up rather than
+        // waiting forever.
+        drop(gate);
+        d.shards[0].enqueue(Box::new(Packet::Quit)).unwrap();
+        assert!(replies.wait_for_acks(&d).await.is_err());
     }
 }
@@ -1,28 +1,60 @@
 use crate::controller::domain_handle::{DomainHandle, DomainShardHandle};
+use crate::controller::keys::provenance_of;
 use crate::controller::migrate::materialization::Materializations;
+use crate::controller::placement::{Candidate, PlacementStrategy};
 use crate::controller::recipe::Schema;
 use crate::controller::schema;
 use crate::controller::{ControllerState, Migration, Recipe};
 use crate::controller::{Worker, WorkerIdentifier};
 use crate::coordination::{CoordinationMessage, CoordinationPayload, DomainDescriptor};
 use dataflow::prelude::*;
-use dataflow::{node, payload::ControlReplyPacket, prelude::Packet, DomainBuilder, DomainConfig};
+use dataflow::{
+    node,
+    payload::{ControlReplyPacket, ReshardTarget},
+    prelude::Packet,
+    Clock, DomainBuilder, DomainConfig, ReadMask,
+};
 use futures_util::stream::StreamExt;
 use hyper::{self, Method, StatusCode};
-use nom_sql::ColumnSpecification;
+use nom_sql::{ColumnSpecification, SqlQuery};
 use noria::builders::*;
 use noria::channel::tcp::{SendError, TcpSender};
 use noria::consensus::{Authority, Epoch, STATE_KEY};
-use noria::debug::stats::{DomainStats, GraphStats, NodeStats};
-use noria::ActivationResult;
+use noria::debug::stats::{DomainStats, GraphStats, NodeStats, ReadAttribution, WorkerStats};
+use noria::{
+    ActivationResult, AuditEntry, BaseExport, BaseVerification, ConsistencyEvent, DataflowDiff,
+    DomainFailure, Failover, NodeSample, QueryInfo, ReplayCorruption, ReplayPriority, ScalingEvent,
+    ShardExport, UpgradeEvent, WorkerFailure,
+};
 use petgraph::visit::Bfs;
 use slog::Logger;
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::mem;
 use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
-use std::{cell, io, time};
+use std::{cell, cmp, fmt, io, iter, time};
+
+/// The number of consistency check results the controller remembers.
+const MAX_CONSISTENCY_EVENTS: usize = 1024;
+
+/// The number of domain failures the controller remembers.
+const MAX_DOMAIN_FAILURES: usize = 1024;
+
+/// The number of corrupted replay pieces the controller remembers.
+const MAX_REPLAY_CORRUPTIONS: usize = 1024;
+
+/// The number of domains moved off their workers that the controller remembers.
+const MAX_FAILOVERS: usize = 1024;
+
+/// The number of worker failures the controller remembers.
+const MAX_WORKER_FAILURES: usize = 1024;
+
+/// The number of read replica changes the controller remembers.
+const MAX_SCALING_EVENTS: usize = 1024;
+
+/// How often a wait for domain replies checks that the domain can still be sent to.
+const REACHABILITY_CHECK_INTERVAL: Duration = Duration::from_millis(100);
 
 /// `Controller` is the core component of the alternate Soup implementation.
 ///
@@ -30,14 +62,23 @@ use std::{cell, io, time};
 /// does not allow direct manipulation of the graph. Instead, changes must be instigated through a
 /// `Migration`, which can be performed using `ControllerInner::migrate`. Only one `Migration` can
 /// occur at any given point in time.
-pub(super) struct ControllerInner {
+pub(crate) struct ControllerInner {
     pub(super) ingredients: Graph,
     pub(super) source: NodeIndex,
     pub(super) ndomains: usize,
     pub(super) sharding: Option<usize>,
+    /// Shard counts that base tables were given by `reshard_base`, in place of `sharding`.
+    pub(super) base_shards: HashMap<String, usize>,
+    /// The split points between the ranges of keys that the shards of base tables own.
+    pub(super) shard_ranges: HashMap<String, Vec<DataType>>,
 
     pub(super) domain_config: DomainConfig,
 
+    /// Capacity of the audit log given to new base tables, if auditing is enabled.
+    pub(super) audit_capacity: Option<usize>,
+    /// Share of a sharder's records above which new sharders spread a key across shards, if any.
+    pub(super) hot_key_split: Option<f64>,
+
     /// Parameters for persistence code.
     pub(super) persistence: PersistenceParameters,
     pub(super) materializations: Materializations,
@@ -48,6 +89,10 @@ pub(super) struct ControllerInner {
     pub(super) domains: HashMap<DomainIndex, DomainHandle>,
     pub(in crate::controller) domain_nodes: HashMap<DomainIndex, Vec<NodeIndex>>,
     pub(super) channel_coordinator: Arc<ChannelCoordinator>,
+    /// The most recently announced route to every domain shard.
+    routes: HashMap<(DomainIndex, usize), DomainDescriptor>,
+    /// The version of the most recently announced route.
+    route_version: u64,
 
     /// Map from worker address to the address the worker is listening on for reads.
     read_addrs: HashMap<WorkerIdentifier, SocketAddr>,
@@ -60,23 +105,148 @@ pub(super) struct ControllerInner {
 
     pending_recovery: Option<(Vec<String>, usize)>,
 
+    /// Disk space assumed to be used by each new base table shard when placing it.
+    base_disk_reservation: u64,
+    /// How `place_domain` chooses the worker for each domain shard.
+    placement: PlacementStrategy,
+    /// Whether base domains turn away writes while a migration brings up new domains.
+    pub(super) reject_writes_during_migration: bool,
+    /// How many mirrored writes the handles of each base table may have in flight, where set.
+    mirror_limits: HashMap<String, usize>,
+    /// The weights of the shards of the domains that hold each named table or query, where set.
+    shard_weights: HashMap<String, Vec<u32>>,
+
+    /// The rolling upgrade that is currently in progress (or that completed most recently).
+    upgrade: Option<RollingUpgrade>,
+    /// If set, `place_domain` assigns all new domains to this worker while it is healthy.
+    preferred_worker: Option<WorkerIdentifier>,
+    /// If set, `place_domain` assigns no new domains to this worker.
+    excluded_worker: Option<WorkerIdentifier>,
+
     quorum: usize,
     heartbeat_every: Duration,
     healthcheck_every: Duration,
     last_checked_workers: Instant,
 
+    /// How often, and with how many keys per shard, to check the consistency of partial views.
+    consistency_check: Option<(Duration, usize)>,
+    last_consistency_check: Instant,
+    /// The results of the most recent consistency checks, oldest first.
+    consistency_events: VecDeque<ConsistencyEvent>,
+
+    /// How often to verify the rows base tables keep on disk.
+    base_verification: Option<Duration>,
+    last_base_verification: Instant,
+
+    /// Domains that panicked on workers with domain supervision enabled, oldest first.
+    domain_failures: VecDeque<DomainFailure>,
+    /// Replay pieces that failed their checksums, oldest first.
+    replay_corruptions: VecDeque<ReplayCorruption>,
+    /// Domains moved off their workers by `fail_over`, oldest first.
+    failovers: VecDeque<Failover>,
+    /// Workers found to have stopped sending heartbeats, oldest first.
+    worker_failures: VecDeque<WorkerFailure>,
+    /// The statistics each domain shard last reported on its own, along with the number of the
+    /// report that last changed the shard and each of its nodes.
+    reported_statistics:
+        HashMap<(DomainIndex, usize), (u64, DomainStats, HashMap<NodeIndex, (u64, NodeStats)>)>,
+    /// The number of statistics reports domains have sent.
+    statistics_reports: u64,
+
+    /// The base nodes holding the copied views of each snapshot.
+    snapshots: HashMap<String, Vec<NodeIndex>>,
+
+    /// How many reads may stay queued on a shard of a view's readers before the view is given a
+    /// read replica, how many replicas a view may have, and how long reads must stay queued (or
+    /// not) before a replica is added (or removed).
+    read_autoscaling: Option<(usize, usize, Duration)>,
+    /// The read replicas of each view, by the view's own reader, oldest first.
+    read_replicas: HashMap<NodeIndex, Vec<NodeIndex>>,
+    /// Whether reads have been queued up on the readers of a view that may be scaled, and since
+    /// when, by the view's own reader.
+    read_pressure: HashMap<NodeIndex, (bool, Instant)>,
+    /// The read replicas added to and removed from views, oldest first.
+    scaling_events: VecDeque<ScalingEvent>,
+    /// The settings of each view's own reader that its domain keeps, and that must therefore be
+    /// handed again to a reader that replaces it.
+    reader_settings: HashMap<NodeIndex, ReaderSettings>,
+
+    /// The time that worker liveness and the periodic checks above go by.
+    clock: Clock,
     log: slog::Logger,
 
     pub(in crate::controller) replies: DomainReplies,
 }
 
-pub(in crate::controller) struct DomainReplies(
-    tokio::sync::mpsc::UnboundedReceiver<ControlReplyPacket>,
-);
+/// Settings of a reader that only its domain keeps.
+#[derive(Clone, Copy, Debug, Default)]
+struct ReaderSettings {
+    priority: ReplayPriority,
+    attribution: bool,
+}
+
+/// Book-keeping for a rolling upgrade of a single worker.
+struct RollingUpgrade {
+    worker: WorkerIdentifier,
+    /// The token `worker` must register with again once it is restarted.
+    token: u64,
+    /// Base tables whose rows were moved off `worker`, and must be moved back once it rejoins.
+    bases: Vec<String>,
+    /// Queries whose domains were moved off `worker`, and must be moved back once it rejoins.
+    queries: Vec<String>,
+    events: Vec<UpgradeEvent>,
+    done: bool,
+}
+
+/// Why waiting for the replies of a domain failed.
+#[derive(Debug)]
+pub(crate) enum WaitError {
+    /// Not every shard of the domain replied in time, and the domain either became unreachable or
+    /// used up its retries.
+    TimedOut { expected: usize, received: usize },
+    /// Packets could no longer be sent to a shard of the domain, which will therefore not reply.
+    Unreachable { expected: usize, received: usize },
+}
+
+impl fmt::Display for WaitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            WaitError::TimedOut { expected, received } => write!(
+                f,
+                "timed out waiting for domain replies ({} of {} arrived)",
+                received, expected
+            ),
+            WaitError::Unreachable { expected, received } => write!(
+                f,
+                "domain became unreachable while waiting for its replies ({} of {} arrived)",
+                received, expected
+            ),
+        }
+    }
+}
+
+pub(in crate::controller) struct DomainReplies {
+    rx: tokio::sync::mpsc::UnboundedReceiver<ControlReplyPacket>,
+    /// How long to wait for a domain's replies, and how many more times to wait that long again
+    /// if the domain is still reachable, or `None` to wait for as long as it takes.
+    ///
+    /// The replies of different domains cannot be told apart, so a reply that arrives after its
+    /// wait timed out is taken for a reply to a later request. The timeout should therefore be
+    /// long enough that only domains that have died hit it.
+    timeout: Option<(Duration, usize)>,
+}
 
 impl DomainReplies {
+    pub(in crate::controller) fn new(
+        rx: tokio::sync::mpsc::UnboundedReceiver<ControlReplyPacket>,
+        timeout: Option<(Duration, usize)>,
+    ) -> Self {
+        DomainReplies { rx, timeout }
+    }
+
+    /// Read the replies of domains that are still booting, and so cannot be sent to yet.
     async fn read_n_domain_replies(&mut self, n: usize) -> Vec<ControlReplyPacket> {
-        let crps: Vec<_> = (&mut self.0).take(n).collect().await;
+        let crps: Vec<_> = (&mut self.rx).take(n).collect().await;
 
         if crps.len() != n {
             unreachable!(
@@ -88,27 +258,280 @@ impl DomainReplies {
         crps
     }
 
-    pub(in crate::controller) async fn wait_for_acks(&mut self, d: &DomainHandle) {
-        for r in self.read_n_domain_replies(d.shards()).await {
+    /// Read a reply from every shard of `d`, within the configured timeout.
+    async fn read_domain_replies(
+        &mut self,
+        d: &DomainHandle,
+    ) -> Result<Vec<ControlReplyPacket>, WaitError> {
+        self.read_replies(d, d.shards()).await
+    }
+
+    /// Read `n` replies from shards of `d`, within the configured timeout.
+    ///
+    /// The wait fails as soon as packets can no longer be sent to one of `d`'s shards, since the
+    /// packet it is to reply to may never have reached it. When the timeout passes, the wait is
+    /// only extended if `d` has retries left.
+    async fn read_replies(
+        &mut self,
+        d: &DomainHandle,
+        n: usize,
+    ) -> Result<Vec<ControlReplyPacket>, WaitError> {
+        let (timeout, mut retries) = match self.timeout {
+            Some((timeout, retries)) => (Some(timeout), retries),
+            None => (None, 0),
+        };
+
+        let mut crps = Vec::with_capacity(n);
+        let mut waited = Duration::from_secs(0);
+        while crps.len() < n {
+            match tokio::time::timeout(REACHABILITY_CHECK_INTERVAL, self.rx.next()).await {
+                Ok(Some(crp)) => crps.push(crp),
+                Ok(None) => unreachable!(
+                    "got unexpected EOF from domain reply channel after {} replies",
+                    crps.len()
+                ),
+                Err(_) if !d.is_reachable() => {
+                    return Err(WaitError::Unreachable {
+                        expected: n,
+                        received: crps.len(),
+                    });
+                }
+                Err(_) => {
+                    waited += REACHABILITY_CHECK_INTERVAL;
+                    match timeout {
+                        Some(timeout) if waited >= timeout && retries > 0 => {
+                            retries -= 1;
+                            waited = Duration::from_secs(0);
+                        }
+                        Some(timeout) if waited >= timeout => {
+                            return Err(WaitError::TimedOut {
+                                expected: n,
+                                received: crps.len(),
+                            });
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Ok(crps)
+    }
+
+    pub(in crate::controller) async fn wait_for_acks(
+        &mut self,
+        d: &DomainHandle,
+    ) -> Result<(), WaitError> {
+        for r in self.read_domain_replies(d).await? {
+            match r {
+                ControlReplyPacket::Ack(_) => {}
+                r => unreachable!("got unexpected non-ack control reply: {:?}", r),
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait for the acknowledgement of a packet that was sent to a single shard of `d`.
+    async fn wait_for_shard_ack(&mut self, d: &DomainHandle) -> Result<(), WaitError> {
+        for r in self.read_replies(d, 1).await? {
             match r {
                 ControlReplyPacket::Ack(_) => {}
                 r => unreachable!("got unexpected non-ack control reply: {:?}", r),
             }
         }
+        Ok(())
     }
 
     async fn wait_for_statistics(
         &mut self,
         d: &DomainHandle,
-    ) -> Vec<(DomainStats, HashMap<NodeIndex, NodeStats>)> {
+    ) -> Result<Vec<(DomainStats, HashMap<NodeIndex, NodeStats>)>, WaitError> {
         let mut stats = Vec::with_capacity(d.shards());
-        for r in self.read_n_domain_replies(d.shards()).await {
+        for r in self.read_domain_replies(d).await? {
             match r {
                 ControlReplyPacket::Statistics(d, s) => stats.push((d, s)),
                 r => unreachable!("got unexpected non-stats control reply: {:?}", r),
             }
         }
-        stats
+        Ok(stats)
+    }
+
+    async fn wait_for_base_keys(
+        &mut self,
+        d: &DomainHandle,
+    ) -> Result<Vec<Option<Vec<Vec<DataType>>>>, WaitError> {
+        let mut keys = Vec::with_capacity(d.shards());
+        for r in self.read_domain_replies(d).await? {
+            match r {
+                ControlReplyPacket::BaseKeys(ks) => keys.push(ks),
+                r => unreachable!("got unexpected non-keys control reply: {:?}", r),
+            }
+        }
+        Ok(keys)
+    }
+
+    async fn wait_for_fingerprints(
+        &mut self,
+        d: &DomainHandle,
+    ) -> Result<HashMap<Vec<DataType>, Option<u64>>, WaitError> {
+        // every shard reports on every key it was asked about, but only the shard that owns a key
+        // has a fingerprint for it.
+        let mut fps = HashMap::new();
+        for r in self.read_domain_replies(d).await? {
+            match r {
+                ControlReplyPacket::Fingerprints(shard) => {
+                    for (k, fp) in shard {
+                        let e = fps.entry(k).or_insert(None);
+                        *e = e.or(fp);
+                    }
+                }
+                r => unreachable!("got unexpected non-fingerprint control reply: {:?}", r),
+            }
+        }
+        Ok(fps)
+    }
+
+    async fn wait_for_audit_logs(
+        &mut self,
+        d: &DomainHandle,
+    ) -> Result<Vec<Option<Vec<AuditEntry>>>, WaitError> {
+        let mut logs = Vec::with_capacity(d.shards());
+        for r in self.read_domain_replies(d).await? {
+            match r {
+                ControlReplyPacket::AuditLog(log) => logs.push(log),
+                r => unreachable!("got unexpected non-audit control reply: {:?}", r),
+            }
+        }
+        Ok(logs)
+    }
+
+    async fn wait_for_reader_summaries(
+        &mut self,
+        d: &DomainHandle,
+    ) -> Result<Vec<(bool, bool, u64)>, WaitError> {
+        let mut summaries = Vec::with_capacity(d.shards());
+        for r in self.read_domain_replies(d).await? {
+            match r {
+                ControlReplyPacket::ReaderSummary(ready, paused, mem_size) => {
+                    summaries.push((ready, paused, mem_size))
+                }
+                r => unreachable!("got unexpected non-summary control reply: {:?}", r),
+            }
+        }
+        Ok(summaries)
+    }
+
+    async fn wait_for_base_verifications(
+        &mut self,
+        d: &DomainHandle,
+    ) -> Result<Vec<BaseVerification>, WaitError> {
+        let mut reports = Vec::with_capacity(d.shards());
+        for r in self.read_domain_replies(d).await? {
+            match r {
+                ControlReplyPacket::BaseVerification(report) => reports.push(report),
+                r => unreachable!("got unexpected non-verification control reply: {:?}", r),
+            }
+        }
+        Ok(reports)
+    }
+
+    async fn wait_for_state_samples(
+        &mut self,
+        d: &DomainHandle,
+    ) -> Result<(bool, Option<Vec<Vec<DataType>>>), WaitError> {
+        let mut materialized = false;
+        let mut rows: Option<Vec<Vec<DataType>>> = None;
+        for r in self.read_domain_replies(d).await? {
+            match r {
+                ControlReplyPacket::StateSample(m, rs) => {
+                    materialized |= m;
+                    // only the shard that owns the key has rows for it
+                    if let Some(rs) = rs {
+                        rows.get_or_insert_with(Vec::new).extend(rs);
+                    }
+                }
+                r => unreachable!("got unexpected non-sample control reply: {:?}", r),
+            }
+        }
+        Ok((materialized, rows))
+    }
+
+    async fn wait_for_read_attribution(
+        &mut self,
+        d: &DomainHandle,
+    ) -> Result<Vec<ReadAttribution>, WaitError> {
+        // every key is read from only one shard, so the hot keys of the shards never overlap.
+        let mut by_identity: HashMap<Option<String>, ReadAttribution> = HashMap::new();
+        for r in self.read_domain_replies(d).await? {
+            match r {
+                ControlReplyPacket::ReadAttribution(shard) => {
+                    for ReadAttribution {
+                        identity,
+                        reads,
+                        hot_keys,
+                    } in shard
+                    {
+                        let e = by_identity.entry(identity.clone()).or_insert_with(|| {
+                            ReadAttribution {
+                                identity,
+                                reads: 0,
+                                hot_keys: Vec::new(),
+                            }
+                        });
+                        e.reads += reads;
+                        e.hot_keys.extend(hot_keys);
+                    }
+                }
+                r => unreachable!("got unexpected non-attribution control reply: {:?}", r),
+            }
+        }
+
+        let mut reads: Vec<_> = by_identity.into_iter().map(|(_, r)| r).collect();
+        for r in &mut reads {
+            r.hot_keys.sort_by(|a, b| b.1.cmp(&a.1));
+        }
+        reads.sort_by(|a, b| b.reads.cmp(&a.reads));
+        Ok(reads)
+    }
+
+    async fn wait_for_drained(
+        &mut self,
+        d: &DomainHandle,
+    ) -> Result<Vec<Vec<(NodeIndex, u64)>>, WaitError> {
+        let mut seqs = vec![Vec::new(); d.shards()];
+        for r in self.read_domain_replies(d).await? {
+            match r {
+                ControlReplyPacket::Drained(shard, s) => seqs[shard] = s,
+                r => unreachable!("got unexpected non-drain control reply: {:?}", r),
+            }
+        }
+        Ok(seqs)
+    }
+
+    async fn wait_for_base_rows(
+        &mut self,
+        d: &DomainHandle,
+    ) -> Result<Vec<Option<ShardExport>>, WaitError> {
+        let mut shards = vec![None; d.shards()];
+        for r in self.read_domain_replies(d).await? {
+            match r {
+                ControlReplyPacket::BaseRows(shard, commit_seq, rows) => {
+                    shards[shard] = rows.map(|rows| ShardExport { commit_seq, rows });
+                }
+                r => unreachable!("got unexpected non-rows control reply: {:?}", r),
+            }
+        }
+        Ok(shards)
+    }
+
+    async fn wait_for_commit_seqs(&mut self, d: &DomainHandle) -> Result<Vec<u64>, WaitError> {
+        let mut seqs = vec![0; d.shards()];
+        for r in self.read_domain_replies(d).await? {
+            match r {
+                ControlReplyPacket::CommitSeq(shard, seq) => seqs[shard] = seq,
+                r => unreachable!("got unexpected non-seq control reply: {:?}", r),
+            }
+        }
+        Ok(seqs)
     }
 }
 
@@ -211,10 +634,19 @@ impl ControllerInner {
             (&Method::POST, "/get_statistics") => {
                 return Ok(Ok(json::to_string(&self.get_statistics()).unwrap()));
             }
+            (&Method::GET, "/metrics") => {
+                // don't make every scrape wait for all the domains if they report on their own
+                let stats = if self.reported_statistics.is_empty() {
+                    self.get_statistics()
+                } else {
+                    self.statistics_updates(0).1
+                };
+                return Ok(Ok(crate::metrics::graph(&stats)));
+            }
             _ => {}
         }
 
-        if self.pending_recovery.is_some() || self.workers.len() < self.quorum {
+        if !self.is_available() {
             return Err(StatusCode::SERVICE_UNAVAILABLE);
         }
 
@@ -224,6 +656,7 @@ impl ControllerInner {
             }
             (Method::POST, "/inputs") => Ok(Ok(json::to_string(&self.inputs()).unwrap())),
             (Method::POST, "/outputs") => Ok(Ok(json::to_string(&self.outputs()).unwrap())),
+            (Method::POST, "/queries") => Ok(Ok(json::to_string(&self.queries()).unwrap())),
             (Method::GET, "/instances") => Ok(Ok(json::to_string(&self.get_instances()).unwrap())),
             (Method::GET, "/nodes") => {
                 // TODO(malte): this is a pretty yucky hack, but hyper doesn't provide easy access
@@ -289,6 +722,126 @@ impl ControllerInner {
                     self.create_universe(args)
                         .map(|r| json::to_string(&r).unwrap())
                 }),
+            (Method::POST, "/audit_log") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args: String| self.audit_log(&args).map(|r| json::to_string(&r).unwrap())),
+            (Method::POST, "/commit_seqs") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args: String| {
+                    self.commit_seqs(&args)
+                        .map(|r| json::to_string(&r).unwrap())
+                }),
+            (Method::POST, "/export_base") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args: String| {
+                    self.export_base(&args)
+                        .map(|r| json::to_string(&r).unwrap())
+                }),
+            (Method::POST, "/import_base") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| self.import_base(args).map(|r| json::to_string(&r).unwrap())),
+            (Method::POST, "/verify_base") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| self.verify_base(args).map(|r| json::to_string(&r).unwrap())),
+            (Method::POST, "/sample_key") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| self.sample_key(args).map(|r| json::to_string(&r).unwrap())),
+            (Method::POST, "/base_keys") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| self.base_keys(args).map(|r| json::to_string(&r).unwrap())),
+            (Method::POST, "/pause_view") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| self.pause_view(args).map(|r| json::to_string(&r).unwrap())),
+            (Method::POST, "/resume_view") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| self.resume_view(args).map(|r| json::to_string(&r).unwrap())),
+            (Method::POST, "/set_replay_priority") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| {
+                    self.set_replay_priority(args)
+                        .map(|r| json::to_string(&r).unwrap())
+                }),
+            (Method::POST, "/set_read_attribution") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| {
+                    self.set_read_attribution(args)
+                        .map(|r| json::to_string(&r).unwrap())
+                }),
+            (Method::POST, "/set_read_replicas") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| {
+                    self.set_read_replicas(args)
+                        .map(|r| json::to_string(&r).unwrap())
+                }),
+            (Method::POST, "/read_attribution") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| {
+                    self.read_attribution(args)
+                        .map(|r| json::to_string(&r).unwrap())
+                }),
+            (Method::POST, "/consistency_events") => {
+                Ok(Ok(json::to_string(&self.consistency_events).unwrap()))
+            }
+            (Method::POST, "/statistics_updates") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|since| Ok(json::to_string(&self.statistics_updates(since)).unwrap())),
+            (Method::POST, "/domain_failures") => {
+                Ok(Ok(json::to_string(&self.domain_failures).unwrap()))
+            }
+            (Method::POST, "/replay_corruptions") => {
+                Ok(Ok(json::to_string(&self.replay_corruptions).unwrap()))
+            }
+            (Method::POST, "/scaling_events") => {
+                Ok(Ok(json::to_string(&self.scaling_events).unwrap()))
+            }
+            (Method::POST, "/connections") => {
+                let connections: Vec<_> = self
+                    .workers
+                    .values()
+                    .flat_map(|w| w.connections.iter())
+                    .collect();
+                Ok(Ok(json::to_string(&connections).unwrap()))
+            }
+            (Method::POST, "/rolling_upgrade") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| {
+                    self.rolling_upgrade(args)
+                        .map(|r| json::to_string(&r).unwrap())
+                }),
+            (Method::POST, "/upgrade_status") => {
+                Ok(Ok(json::to_string(&self.upgrade_status()).unwrap()))
+            }
+            (Method::POST, "/fail_over") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| self.fail_over(args).map(|r| json::to_string(&r).unwrap())),
+            (Method::POST, "/reshard_base") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| {
+                    self.reshard_base(authority, args)
+                        .map(|r| json::to_string(&r).unwrap())
+                }),
+            (Method::POST, "/set_shard_ranges") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| {
+                    self.set_shard_ranges(authority, args)
+                        .map(|r| json::to_string(&r).unwrap())
+                }),
+            (Method::POST, "/failovers") => Ok(Ok(json::to_string(&self.failovers).unwrap())),
+            (Method::POST, "/worker_failures") => {
+                Ok(Ok(json::to_string(&self.worker_failures).unwrap()))
+            }
+            (Method::POST, "/fork_snapshot") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| {
+                    self.fork_snapshot(args)
+                        .map(|r| json::to_string(&r).unwrap())
+                }),
+            (Method::POST, "/drop_snapshot") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| {
+                    self.drop_snapshot(args)
+                        .map(|r| json::to_string(&r).unwrap())
+                }),
             (Method::POST, "/remove_node") => json::from_slice(&body)
                 .map_err(|_| StatusCode::BAD_REQUEST)
                 .map(|args| {
@@ -300,27 +853,59 @@ impl ControllerInner {
     }
 
     pub(super) fn handle_register(&mut self, msg: CoordinationMessage) -> Result<(), io::Error> {
-        let (remote, read_listen_addr) = if let CoordinationPayload::Register {
-            addr: remote,
-            read_listen_addr,
-            ..
-        } = msg.payload
-        {
-            (remote, read_listen_addr)
-        } else {
-            unreachable!();
-        };
+        let (remote, read_listen_addr, disk_quota, disk_usage, label, upgrade_token) =
+            if let CoordinationPayload::Register {
+                addr: remote,
+                read_listen_addr,
+                disk_quota,
+                disk_usage,
+                label,
+                upgrade_token,
+                ..
+            } = msg.payload
+            {
+                (
+                    remote,
+                    read_listen_addr,
+                    disk_quota,
+                    disk_usage,
+                    label,
+                    upgrade_token,
+                )
+            } else {
+                unreachable!();
+            };
 
         info!(
             self.log,
             "new worker registered from {:?}, which listens on {:?}", msg.source, remote
         );
 
-        let sender = TcpSender::connect(&remote)?;
-        let ws = Worker::new(sender);
+        let mut sender = TcpSender::connect(&remote)?;
+        if !self.routes.is_empty() {
+            // bring the new worker up to date on where existing domains are. if it is already gone,
+            // it is not added as a worker.
+            let src = sender.local_addr()?;
+            sender.send(CoordinationMessage {
+                epoch: self.epoch,
+                source: src,
+                payload: CoordinationPayload::RoutingTable(self.routes.values().cloned().collect()),
+            })?;
+        }
+        // only the worker that is being upgraded finishes the upgrade: it is restarted with the
+        // upgrade's token.
+        let upgraded = match (&self.upgrade, upgrade_token) {
+            (Some(u), Some(token)) => !u.done && u.token == token,
+            _ => false,
+        };
+        let ws = Worker::new(sender, disk_quota, disk_usage, label, self.clock.now());
         self.workers.insert(msg.source, ws);
         self.read_addrs.insert(msg.source, read_listen_addr);
 
+        if upgraded {
+            self.finish_upgrade(msg.source);
+        }
+
         if self.workers.len() >= self.quorum {
             if let Some((recipes, recipe_version)) = self.pending_recovery.take() {
                 assert_eq!(self.workers.len(), self.quorum);
@@ -346,13 +931,14 @@ impl ControllerInner {
         let mut any_failed = false;
 
         // check if there are any newly failed workers
-        if self.last_checked_workers.elapsed() > self.healthcheck_every {
+        let now = self.clock.now();
+        if now.duration_since(self.last_checked_workers) > self.healthcheck_every {
             for (_addr, ws) in self.workers.iter() {
-                if ws.healthy && ws.last_heartbeat.elapsed() > self.heartbeat_every * 4 {
+                if ws.healthy && now.duration_since(ws.last_heartbeat) > self.heartbeat_every * 4 {
                     any_failed = true;
                 }
             }
-            self.last_checked_workers = Instant::now();
+            self.last_checked_workers = now;
         }
 
         // if we have newly failed workers, iterate again to find all workers that have missed >= 3
@@ -361,7 +947,7 @@ impl ControllerInner {
         if any_failed {
             let mut failed = Vec::new();
             for (addr, ws) in self.workers.iter_mut() {
-                if ws.healthy && ws.last_heartbeat.elapsed() > self.heartbeat_every * 3 {
+                if ws.healthy && now.duration_since(ws.last_heartbeat) > self.heartbeat_every * 3 {
                     error!(self.log, "worker at {:?} has failed!", addr);
                     ws.healthy = false;
                     failed.push(addr.clone());
@@ -374,19 +960,70 @@ impl ControllerInner {
     fn handle_failed_workers(&mut self, failed: Vec<WorkerIdentifier>) {
         // first, translate from the affected workers to affected data-flow nodes
         let mut affected_nodes = Vec::new();
+        let mut failures = Vec::new();
         for wi in failed {
             info!(self.log, "handling failure of worker {:?}", wi);
-            affected_nodes.extend(self.get_failed_nodes(&wi));
+            let nodes = self.get_failed_nodes(&wi);
+            let mut domains: Vec<_> = self
+                .domains
+                .values()
+                .flat_map(|dh| {
+                    (0..dh.shards())
+                        .filter(move |&shard| dh.assignment(shard) == wi)
+                        .map(move |shard| (dh.index().index(), shard))
+                })
+                .collect();
+            domains.sort();
+            let mut queries = self.recipe.queries_for_nodes(nodes.clone());
+            queries.sort();
+            queries.dedup();
+            failures.push(WorkerFailure {
+                worker: wi,
+                domains,
+                queries,
+            });
+            affected_nodes.extend(nodes);
         }
 
         // then, figure out which queries are affected (and thus must be removed and added again in
         // a migration)
-        let affected_queries = self.recipe.queries_for_nodes(affected_nodes);
+        let mut affected_queries = self.recipe.queries_for_nodes(affected_nodes);
+        affected_queries.sort();
+        affected_queries.dedup();
+        if !affected_queries.is_empty() {
+            if let Err(e) = self.recover_queries(affected_queries) {
+                crit!(self.log, "failed to recover from worker failure: {}", e);
+            }
+        }
+
+        for failure in failures {
+            if self.worker_failures.len() == MAX_WORKER_FAILURES {
+                self.worker_failures.pop_front();
+            }
+            self.worker_failures.push_back(failure);
+        }
+    }
+
+    /// Remove the given queries and add them again, which places their domains anew.
+    ///
+    /// The views of the queries get new readers, which are given the settings and the read
+    /// replicas of the readers they replace.
+    fn recover_queries(&mut self, affected_queries: Vec<String>) -> Result<(), String> {
+        let views: Vec<_> = self
+            .outputs()
+            .into_iter()
+            .filter_map(|(view, _)| {
+                let r = self.reader_for(&view)?;
+                let settings = self.reader_settings.get(&r).cloned().unwrap_or_default();
+                let replicas = self.read_replicas.get(&r).map(Vec::len).unwrap_or(0);
+                Some((view, r, settings, replicas))
+            })
+            .collect();
         let (recovery, mut original) = self.recipe.make_recovery(affected_queries);
 
         // activate recipe
         self.apply_recipe(recovery.clone())
-            .expect("failed to apply recovery recipe");
+            .map_err(|e| format!("failed to apply recovery recipe: {}", e))?;
 
         // we must do this *after* the migration, since the migration itself modifies the recipe in
         // `recovery`, and we currently need to clone it here.
@@ -397,40 +1034,717 @@ impl ControllerInner {
 
         // back to original recipe, which should add the query again
         self.apply_recipe(original)
-            .expect("failed to activate original recipe");
-    }
+            .map_err(|e| format!("failed to activate original recipe: {}", e))?;
 
-    pub(super) fn handle_heartbeat(&mut self, msg: CoordinationMessage) -> Result<(), io::Error> {
-        match self.workers.get_mut(&msg.source) {
-            None => crit!(
-                self.log,
-                "got heartbeat for unknown worker {:?}",
-                msg.source
-            ),
-            Some(ref mut ws) => {
-                ws.last_heartbeat = Instant::now();
+        for (view, old, settings, replicas) in views {
+            let r = match self.reader_for(&view) {
+                Some(r) if r != old => r,
+                _ => continue,
+            };
+            if settings.priority != ReplayPriority::default() {
+                self.set_replay_priority((view.clone(), settings.priority))?;
+            }
+            if settings.attribution {
+                self.set_read_attribution((view.clone(), true))?;
+            }
+            for _ in 0..replicas {
+                self.add_read_replica(r)?;
             }
+            debug!(
+                self.log,
+                "moved reader of recovered view";
+                "view" => view,
+                "from" => old.index(),
+                "to" => r.index()
+            );
         }
-
-        self.check_worker_liveness();
         Ok(())
     }
 
-    /// Construct `ControllerInner` with a specified listening interface
-    pub(super) fn new(
-        log: slog::Logger,
-        state: ControllerState,
-        drx: tokio::sync::mpsc::UnboundedReceiver<ControlReplyPacket>,
-    ) -> Self {
-        let mut g = petgraph::Graph::new();
-        let source = g.add_node(node::Node::new(
-            "source",
-            &["because-type-inference"],
-            node::special::Source,
-        ));
-
-        let mut materializations = Materializations::new(&log);
-        if !state.config.partial_enabled {
+    /// The base tables in the recipe that have a shard on `worker`.
+    fn bases_on_worker(&self, worker: &WorkerIdentifier) -> Vec<String> {
+        let nodes: HashSet<_> = self.nodes_on_worker(Some(worker)).into_iter().collect();
+        let mut bases: Vec<_> = self
+            .recipe
+            .expressions()
+            .into_iter()
+            .filter_map(|(_, q)| match *q {
+                SqlQuery::CreateTable(ref ct) => Some(ct.table.name.clone()),
+                _ => None,
+            })
+            .filter(|base| {
+                self.recipe
+                    .node_addr_for(base)
+                    .map(|ni| nodes.contains(&ni))
+                    .unwrap_or(false)
+            })
+            .collect();
+        bases.sort();
+        bases.dedup();
+        bases
+    }
+
+    /// Hand off all domains on `worker` to its peers, so that it can be restarted.
+    ///
+    /// The rows of the base tables on the worker are copied to new bases on its peers first, and
+    /// then the remaining domains are rebuilt there as if the worker had failed. Everything is
+    /// moved back once the worker registers again with the returned token; see `finish_upgrade`.
+    fn rolling_upgrade(&mut self, worker: WorkerIdentifier) -> Result<u64, String> {
+        if self.upgrade.as_ref().map(|u| !u.done).unwrap_or(false) {
+            return Err("a rolling upgrade is already in progress".to_owned());
+        }
+        match self.workers.get(&worker) {
+            None => return Err(format!("unknown worker {:?}", worker)),
+            Some(w) if !w.healthy => return Err(format!("worker {:?} is not healthy", worker)),
+            Some(_) => {}
+        }
+        if self.workers.values().filter(|w| w.healthy).count() < 2 {
+            return Err("no other healthy worker to hand domains off to".to_owned());
+        }
+        // the rows of the worker's bases would be lost with it, so all of them must be movable
+        // before anything is moved.
+        let bases = self.bases_on_worker(&worker);
+        for base in &bases {
+            self.movable_base(base)
+                .map_err(|e| format!("cannot hand off base table '{}': {}", base, e))?;
+        }
+
+        let domains = self
+            .domains
+            .values()
+            .filter(|dh| dh.assigned_to_worker(&worker))
+            .count();
+        info!(
+            self.log,
+            "starting rolling upgrade of worker {:?} with {} domains", worker, domains;
+            "bases" => bases.len()
+        );
+        let token = rand::random();
+        self.upgrade = Some(RollingUpgrade {
+            worker,
+            token,
+            bases: Vec::new(),
+            queries: Vec::new(),
+            events: vec![UpgradeEvent::Draining { worker, domains }],
+            done: false,
+        });
+
+        // the bases are moved while the worker still serves them, since it is the one that copies
+        // their rows to the new bases.
+        self.excluded_worker = Some(worker);
+        for base in bases {
+            if let Err(e) = self.relocate_base(&base) {
+                self.excluded_worker = None;
+                self.upgrade = None;
+                return Err(format!("failed to hand off base table '{}': {}", base, e));
+            }
+            self.upgrade.as_mut().unwrap().bases.push(base);
+        }
+        self.excluded_worker = None;
+
+        // from here on, the worker is treated as if it had failed, which moves its remaining
+        // domains (and everything downstream of them) elsewhere.
+        let affected_nodes = self.get_failed_nodes(&worker);
+        let mut queries = self.recipe.queries_for_nodes(affected_nodes);
+        queries.sort();
+        queries.dedup();
+        self.workers.get_mut(&worker).unwrap().healthy = false;
+        self.upgrade.as_mut().unwrap().queries = queries.clone();
+
+        if let Err(e) = self.recover_queries(queries) {
+            self.workers.get_mut(&worker).unwrap().healthy = true;
+            self.upgrade = None;
+            return Err(e);
+        }
+
+        self.upgrade
+            .as_mut()
+            .unwrap()
+            .events
+            .push(UpgradeEvent::AwaitingRestart { worker });
+        Ok(token)
+    }
+
+    /// Move the domains drained by `rolling_upgrade` onto the upgraded worker, which just
+    /// registered again.
+    fn finish_upgrade(&mut self, new: WorkerIdentifier) {
+        let (old, bases, mut queries) = {
+            let u = self.upgrade.as_mut().unwrap();
+            u.events.push(UpgradeEvent::Rejoined { old: u.worker, new });
+            (u.worker, u.bases.clone(), u.queries.clone())
+        };
+        info!(self.log, "upgraded worker {:?} rejoined as {:?}", old, new);
+
+        // the old worker entry is stale; the process behind it is gone.
+        if old != new {
+            self.workers.remove(&old);
+            self.read_addrs.remove(&old);
+        }
+
+        self.preferred_worker = Some(new);
+        for base in bases {
+            match self.relocate_base(&base) {
+                // the queries over the base were moved along with it
+                Ok(moved) => queries.retain(|q| !moved.contains(q)),
+                Err(e) => crit!(
+                    self.log,
+                    "failed to move base table onto upgraded worker: {}", e;
+                    "base" => base
+                ),
+            }
+        }
+        let recovered = if queries.is_empty() {
+            Ok(())
+        } else {
+            self.recover_queries(queries)
+        };
+        self.preferred_worker = None;
+        if let Err(e) = recovered {
+            crit!(
+                self.log,
+                "failed to move domains onto upgraded worker: {}",
+                e
+            );
+        }
+
+        let domains = self
+            .domains
+            .values()
+            .filter(|dh| dh.assigned_to_worker(&new))
+            .count();
+        let u = self.upgrade.as_mut().unwrap();
+        u.events.push(UpgradeEvent::Complete {
+            worker: new,
+            domains,
+        });
+        u.done = true;
+    }
+
+    /// Move shard `shard` of domain `domain` off the worker that runs it.
+    ///
+    /// This rebuilds the queries that depend on the domain like `handle_failed_workers` does,
+    /// except that the worker stays healthy and only sits out the placement of the new domains.
+    fn fail_over(&mut self, (domain, shard): (usize, usize)) -> Result<Failover, String> {
+        let di = DomainIndex::from(domain);
+        let worker = match self.domains.get(&di) {
+            None => return Err(format!("unknown domain {}", domain)),
+            Some(dh) if shard >= dh.shards() => {
+                return Err(format!("domain {} has no shard {}", domain, shard))
+            }
+            Some(dh) => dh.assignment(shard),
+        };
+        if !self.workers.iter().any(|(i, w)| *i != worker && w.healthy) {
+            return Err("no other healthy worker to move the domain to".to_owned());
+        }
+
+        let nodes: Vec<_> = self.domain_nodes[&di]
+            .iter()
+            .cloned()
+            .filter(|&ni| !self.ingredients[ni].is_dropped())
+            .collect();
+        if nodes.iter().any(|&ni| self.ingredients[ni].is_base()) {
+            return Err(format!(
+                "domain {} holds base tables, whose rows cannot be moved",
+                domain
+            ));
+        }
+        let mut queries = self.recipe.queries_for_nodes(self.with_downstream(nodes));
+        queries.sort();
+        queries.dedup();
+        if queries.is_empty() {
+            return Err(format!("domain {} is not part of any query", domain));
+        }
+
+        info!(
+            self.log,
+            "failing over domain {}.{} from worker {:?}", domain, shard, worker
+        );
+        self.excluded_worker = Some(worker);
+        let recovered = self.recover_queries(queries.clone());
+        self.excluded_worker = None;
+        recovered?;
+
+        let failover = Failover {
+            worker,
+            domain,
+            shard,
+            queries,
+        };
+        if self.failovers.len() == MAX_FAILOVERS {
+            self.failovers.pop_front();
+        }
+        self.failovers.push_back(failover.clone());
+        Ok(failover)
+    }
+
+    /// See `ControllerHandle::reshard_base`.
+    fn reshard_base<A: Authority + 'static>(
+        &mut self,
+        authority: &Arc<A>,
+        (base, shards): (String, usize),
+    ) -> Result<Vec<String>, String> {
+        self.check_authority(authority)?;
+        if shards == 0 {
+            return Err("a base table needs at least one shard".to_owned());
+        }
+        self.repartition_base(authority, base, shards, Vec::new())
+    }
+
+    /// See `ControllerHandle::set_shard_ranges`.
+    fn set_shard_ranges<A: Authority + 'static>(
+        &mut self,
+        authority: &Arc<A>,
+        (base, split_points): (String, Vec<DataType>),
+    ) -> Result<Vec<String>, String> {
+        self.check_authority(authority)?;
+        if split_points.is_empty() {
+            return Err("at least one split point is needed".to_owned());
+        }
+        if split_points.iter().any(|p| *p == DataType::None) {
+            return Err("split points cannot be NULL".to_owned());
+        }
+        let ascending = split_points
+            .windows(2)
+            .all(|w| noria::range_order(&w[0], &w[1]) == cmp::Ordering::Less);
+        if !ascending {
+            return Err("split points must be distinct and in ascending order".to_owned());
+        }
+        let tb = self
+            .table_builder(&base)
+            .ok_or_else(|| format!("no base table named '{}' in the recipe", base))?;
+        if tb.key.len() != 1 {
+            return Err(format!(
+                "base table '{}' is not keyed by a single column, and cannot be split by range",
+                base
+            ));
+        }
+        let shards = split_points.len() + 1;
+        self.repartition_base(authority, base, shards, split_points)
+    }
+
+    /// Move the rows of the base table `base` to a new base with `shards` shards, split by
+    /// `ranges` if given, and rebuild the queries over it.
+    fn repartition_base<A: Authority + 'static>(
+        &mut self,
+        authority: &Arc<A>,
+        base: String,
+        shards: usize,
+        ranges: Vec<DataType>,
+    ) -> Result<Vec<String>, String> {
+        let ni = self.movable_base(&base)?;
+        let current = self.domains[&self.ingredients[ni].domain()].shards();
+        let current_ranges = self.ingredients[ni]
+            .get_base()
+            .map(|b| b.shard_ranges().to_vec())
+            .unwrap_or_default();
+        if current == shards && current_ranges == ranges {
+            return Err(format!(
+                "base table '{}' already has {} shards split this way",
+                base, shards
+            ));
+        }
+        let queries = self.move_base(&base, shards, ranges.clone())?;
+
+        // a restart rebuilds the table from the recipe, and must give it the same shards.
+        self.base_shards.insert(base.clone(), shards);
+        if ranges.is_empty() {
+            self.shard_ranges.remove(&base);
+        } else {
+            self.shard_ranges.insert(base, ranges);
+        }
+        if authority
+            .read_modify_write(STATE_KEY, |state: Option<ControllerState>| match state {
+                None => unreachable!(),
+                Some(ref state) if state.epoch > self.epoch => Err(()),
+                Some(mut state) => {
+                    state.base_shards = self.base_shards.clone();
+                    state.shard_ranges = self.shard_ranges.clone();
+                    Ok(state)
+                }
+            })
+            .is_err()
+        {
+            return Err("failed to persist the new shard count".to_owned());
+        }
+        Ok(queries)
+    }
+
+    /// Move the rows of the base table `base` to a new base that is sharded the same way, and
+    /// return the queries over it, which are rebuilt.
+    fn relocate_base(&mut self, base: &str) -> Result<Vec<String>, String> {
+        let ni = self.movable_base(base)?;
+        let shards = self.domains[&self.ingredients[ni].domain()].shards();
+        let ranges = self.shard_ranges.get(base).cloned().unwrap_or_default();
+        self.move_base(base, shards, ranges)
+    }
+
+    /// The node of the base table `base`, if its rows can be moved to a new base.
+    fn movable_base(&self, base: &str) -> Result<NodeIndex, String> {
+        if self.sharding.is_none() {
+            return Err("sharding is disabled".to_owned());
+        }
+        if self.persistence.mode == DurabilityMode::Permanent {
+            // after a restart, the table is rebuilt under its own name, whose files still hold
+            // the rows from before it was moved.
+            return Err("permanently persisted base tables cannot be moved".to_owned());
+        }
+        let ni = self
+            .recipe
+            .node_addr_for(base)
+            .map_err(|_| format!("no base table named '{}' in the recipe", base))?;
+        match self.materializations.get_status(ni, &self.ingredients[ni]) {
+            MaterializationStatus::Full => {}
+            _ => return Err(format!("base table '{}' does not keep its rows", base)),
+        }
+        let tb = self
+            .table_builder(base)
+            .ok_or_else(|| format!("base table '{}' cannot be written to", base))?;
+        if tb.key.is_empty() {
+            return Err(format!("base table '{}' has no key to shard by", base));
+        }
+        Ok(ni)
+    }
+
+    /// Copy the rows of the base table `base` to a new base with `shards` shards, split by
+    /// `ranges` if given, rebuild the queries over it, and return those queries.
+    ///
+    /// The new base is placed like any other new domain, so it avoids `excluded_worker` and
+    /// prefers `preferred_worker`.
+    fn move_base(
+        &mut self,
+        base: &str,
+        shards: usize,
+        ranges: Vec<DataType>,
+    ) -> Result<Vec<String>, String> {
+        let ni = self.movable_base(base)?;
+        let current = self.domains[&self.ingredients[ni].domain()].shards();
+        let below = self.with_downstream(vec![ni]);
+        // cascades write to their child table directly, and are set up anew over the new base
+        let cascaded = below.iter().any(|&n| self.ingredients[n].is_cascade());
+        let mut queries = self.recipe.queries_for_nodes(below);
+        queries.retain(|q| q != base);
+        queries.sort();
+        queries.dedup();
+
+        // the new base starts out next to the old one, under a name of its own so that their
+        // shards do not share files. domain indices are never reused, and so neither is the name.
+        let staged = format!("{}@{}", base, self.ndomains);
+        info!(
+            self.log,
+            "moving base {} from {} to {} shards", base, current, shards;
+            "staged" => &staged,
+            "queries" => queries.len(),
+            "split_points" => ranges.len(),
+        );
+        let (fields, b) = {
+            let n = &self.ingredients[ni];
+            let b = n.get_base().unwrap().duplicate();
+            (n.fields().to_vec(), b.with_shard_ranges(ranges.clone()))
+        };
+        self.base_shards.insert(staged.clone(), shards);
+        let to = self.migrate(|mig| mig.add_base(staged.clone(), fields, b));
+        self.base_shards.remove(&staged);
+        let to = to.map_err(|e| format!("failed to add base {}: {}", staged, e))?;
+
+        // from here on, the old base copies its rows to the new one, and then passes on every
+        // write it accepts, so the views over it stay up to date while the new base catches up.
+        let tt = self.table_builder(&staged).unwrap();
+        let target = ReshardTarget {
+            domain: self.ingredients[to].domain(),
+            node: self.ingredients[to].local_addr(),
+            key: tt.key,
+            shards: tt.txs.len(),
+            ranges: tt.shard_ranges,
+            weights: tt.shard_weights,
+        };
+        let na = self.ingredients[ni].local_addr();
+        {
+            let workers = &self.workers;
+            let replies = &mut self.replies;
+            let domain = self
+                .domains
+                .get_mut(&self.ingredients[ni].domain())
+                .unwrap();
+            domain
+                .send_to_healthy(
+                    Box::new(Packet::StartResharding {
+                        node: na,
+                        to: target,
+                    }),
+                    workers,
+                )
+                .map_err(|e| format!("failed to reach base table '{}': {:?}", base, e))?;
+            futures_executor::block_on(replies.wait_for_acks(&domain))
+                .map_err(|e| format!("base table '{}' did not respond: {}", base, e))?;
+        }
+
+        // the queries are then rebuilt over the new base, and fill their views from it.
+        self.recipe.move_base(base, to);
+        if !queries.is_empty() || cascaded {
+            self.recover_queries(queries.clone())?;
+        }
+
+        // clients that still write to the old base find it gone, and fetch the table again. nodes
+        // that were added outside of the recipe still read from the old base, which then stays
+        // and keeps passing its writes on.
+        let children = self
+            .ingredients
+            .neighbors_directed(ni, petgraph::EdgeDirection::Outgoing)
+            .count();
+        if children == 0 {
+            self.remove_nodes(&[ni])?;
+        } else {
+            warn!(self.log, "keeping moved base, which still has children"; "base" => base);
+        }
+        Ok(queries)
+    }
+
+    fn upgrade_status(&self) -> Vec<UpgradeEvent> {
+        self.upgrade
+            .as_ref()
+            .map(|u| u.events.clone())
+            .unwrap_or_default()
+    }
+
+    pub(super) fn handle_domain_failure(&mut self, msg: CoordinationMessage) {
+        if let CoordinationPayload::DomainFailed {
+            domain,
+            shard,
+            reason,
+            respawned,
+        } = msg.payload
+        {
+            if respawned {
+                warn!(
+                    self.log,
+                    "domain panicked and was respawned";
+                    "worker" => ?msg.source,
+                    "domain" => domain.index(),
+                    "shard" => shard,
+                    "reason" => &reason,
+                );
+            } else {
+                crit!(
+                    self.log,
+                    "domain panicked and could not be respawned";
+                    "worker" => ?msg.source,
+                    "domain" => domain.index(),
+                    "shard" => shard,
+                    "reason" => &reason,
+                );
+            }
+
+            let queries = if respawned {
+                self.retry_replays_through(domain);
+                Vec::new()
+            } else {
+                self.recover_failed_domain(domain, shard)
+            };
+
+            if self.domain_failures.len() == MAX_DOMAIN_FAILURES {
+                self.domain_failures.pop_front();
+            }
+            self.domain_failures.push_back(DomainFailure {
+                worker: msg.source,
+                domain: domain.index(),
+                shard,
+                reason,
+                respawned,
+                queries,
+            });
+        }
+    }
+
+    /// Rebuild the queries that depend on shard `shard` of `domain`, which panicked and was not
+    /// respawned, and return them.
+    ///
+    /// Whatever the domain was handling when it panicked is lost, so the state downstream of it
+    /// may have diverged or be waiting for a replay, and the domain no longer processes updates.
+    /// Domains with base tables cannot be rebuilt, since their rows would be lost with them.
+    fn recover_failed_domain(&mut self, domain: DomainIndex, shard: usize) -> Vec<String> {
+        let nodes: Vec<_> = match self.domain_nodes.get(&domain) {
+            Some(nodes) => nodes
+                .iter()
+                .cloned()
+                .filter(|&ni| !self.ingredients[ni].is_dropped())
+                .collect(),
+            None => return Vec::new(),
+        };
+        if nodes.iter().any(|&ni| self.ingredients[ni].is_base()) {
+            crit!(
+                self.log,
+                "domain with base tables failed, and cannot be rebuilt";
+                "domain" => domain.index(),
+                "shard" => shard,
+            );
+            return Vec::new();
+        }
+        if let Some(dh) = self.domains.get_mut(&domain) {
+            dh.shards[shard].failed = true;
+        }
+
+        let mut queries = self.recipe.queries_for_nodes(self.with_downstream(nodes));
+        queries.sort();
+        queries.dedup();
+        if !queries.is_empty() {
+            if let Err(e) = self.recover_queries(queries.clone()) {
+                crit!(self.log, "failed to recover from domain failure: {}", e);
+            }
+        }
+        queries
+    }
+
+    /// Have every domain downstream of `domain` ask again for the partial replays it waits for.
+    ///
+    /// Replays to those domains may pass through `domain`, and whichever piece `domain` was
+    /// handling when it was rebuilt is lost.
+    fn retry_replays_through(&mut self, domain: DomainIndex) {
+        let nodes = match self.domain_nodes.get(&domain) {
+            Some(nodes) => nodes.clone(),
+            None => return,
+        };
+        let mut downstream: Vec<_> = self
+            .with_downstream(nodes)
+            .into_iter()
+            .filter(|&ni| !self.ingredients[ni].is_dropped())
+            .map(|ni| self.ingredients[ni].domain())
+            .filter(|&di| di != domain)
+            .collect();
+        downstream.sort();
+        downstream.dedup();
+
+        for di in downstream {
+            let workers = &self.workers;
+            if let Some(dh) = self.domains.get_mut(&di) {
+                if let Err(e) = dh.send_to_healthy(Box::new(Packet::RetryPartialReplays), workers) {
+                    warn!(
+                        self.log,
+                        "failed to have domain ask for replays again";
+                        "domain" => di.index(),
+                        "err" => ?e,
+                    );
+                }
+            }
+        }
+    }
+
+    pub(super) fn handle_corrupt_replay(&mut self, msg: CoordinationMessage) {
+        if let CoordinationPayload::CorruptReplay {
+            domain,
+            shard,
+            tag,
+            keys,
+            recovered,
+        } = msg.payload
+        {
+            // a domain that could not get the piece again may be missing rows, so the queries
+            // that depend on it are rebuilt from their materializations upstream.
+            let mut queries = Vec::new();
+            if !recovered {
+                if let Some(nodes) = self.domain_nodes.get(&domain) {
+                    let nodes: Vec<_> = nodes
+                        .iter()
+                        .cloned()
+                        .filter(|&ni| {
+                            !self.ingredients[ni].is_dropped() && !self.ingredients[ni].is_base()
+                        })
+                        .collect();
+                    queries = self.recipe.queries_for_nodes(self.with_downstream(nodes));
+                    queries.sort();
+                    queries.dedup();
+                }
+            }
+
+            if recovered {
+                warn!(
+                    self.log,
+                    "replay piece failed its checksum and was replayed again";
+                    "worker" => ?msg.source,
+                    "domain" => domain.index(),
+                    "shard" => shard,
+                    "tag" => tag,
+                );
+            } else {
+                crit!(
+                    self.log,
+                    "replay piece failed its checksum, rebuilding the queries that depend on it";
+                    "worker" => ?msg.source,
+                    "domain" => domain.index(),
+                    "shard" => shard,
+                    "tag" => tag,
+                    "queries" => ?queries,
+                );
+                if !queries.is_empty() {
+                    if let Err(e) = self.recover_queries(queries.clone()) {
+                        crit!(self.log, "failed to rebuild queries"; "err" => e);
+                    }
+                }
+            }
+
+            if self.replay_corruptions.len() == MAX_REPLAY_CORRUPTIONS {
+                self.replay_corruptions.pop_front();
+            }
+            self.replay_corruptions.push_back(ReplayCorruption {
+                worker: msg.source,
+                domain: domain.index(),
+                shard,
+                tag,
+                keys,
+                recovered,
+                queries,
+            });
+        }
+    }
+
+    pub(super) fn handle_heartbeat(&mut self, msg: CoordinationMessage) -> Result<(), io::Error> {
+        match self.workers.get_mut(&msg.source) {
+            None => crit!(
+                self.log,
+                "got heartbeat for unknown worker {:?}",
+                msg.source
+            ),
+            Some(ref mut ws) => {
+                ws.last_heartbeat = self.clock.now();
+                if let CoordinationPayload::Heartbeat {
+                    disk_usage,
+                    connections,
+                    domains_hosted,
+                    memory_used,
+                    cpu_load,
+                    read_queues,
+                } = msg.payload
+                {
+                    ws.disk_usage = disk_usage;
+                    ws.connections = connections;
+                    ws.domains_hosted = domains_hosted;
+                    ws.memory_used = memory_used;
+                    ws.cpu_load = cpu_load;
+                    ws.read_queues = read_queues;
+                }
+            }
+        }
+
+        self.check_worker_liveness();
+        self.run_read_autoscaling();
+        Ok(())
+    }
+
+    /// Construct `ControllerInner` with a specified listening interface
+    pub(super) fn new(
+        log: slog::Logger,
+        state: ControllerState,
+        drx: tokio::sync::mpsc::UnboundedReceiver<ControlReplyPacket>,
+        clock: Clock,
+    ) -> Self {
+        let mut g = petgraph::Graph::new();
+        let source = g.add_node(node::Node::new(
+            "source",
+            &["because-type-inference"],
+            node::special::Source,
+        ));
+
+        let mut materializations = Materializations::new(&log);
+        if !state.config.partial_enabled {
             materializations.disable_partial()
         }
         materializations.set_frontier_strategy(state.config.frontier_strategy);
@@ -446,6 +1760,7 @@ impl ControllerInner {
 
         let mut recipe = Recipe::blank(Some(log.clone()));
         recipe.enable_reuse(state.config.reuse);
+        recipe.enable_column_pruning(state.config.prune_columns);
 
         ControllerInner {
             ingredients: g,
@@ -454,7 +1769,16 @@ impl ControllerInner {
 
             materializations,
             sharding: state.config.sharding,
+            base_shards: state.base_shards,
+            shard_ranges: state.shard_ranges,
             domain_config: state.config.domain_config,
+            audit_capacity: state.config.audit_capacity,
+            hot_key_split: state.config.hot_key_split,
+            base_disk_reservation: state.config.base_disk_reservation,
+            placement: state.config.placement,
+            reject_writes_during_migration: state.config.reject_writes_during_migration,
+            mirror_limits: state.config.mirror_limits,
+            shard_weights: state.config.shard_weights,
             persistence: state.config.persistence,
             heartbeat_every: state.config.heartbeat_every,
             healthcheck_every: state.config.healthcheck_every,
@@ -465,6 +1789,8 @@ impl ControllerInner {
             domains: Default::default(),
             domain_nodes: Default::default(),
             channel_coordinator: cc,
+            routes: HashMap::default(),
+            route_version: 0,
             epoch: state.epoch,
 
             remap: HashMap::default(),
@@ -473,9 +1799,33 @@ impl ControllerInner {
             workers: HashMap::default(),
 
             pending_recovery,
-            last_checked_workers: Instant::now(),
-
-            replies: DomainReplies(drx),
+            upgrade: None,
+            preferred_worker: None,
+            excluded_worker: None,
+            last_checked_workers: clock.now(),
+            consistency_check: state.config.consistency_check,
+            last_consistency_check: clock.now(),
+            consistency_events: VecDeque::new(),
+            base_verification: state.config.base_verification,
+            last_base_verification: clock.now(),
+            domain_failures: VecDeque::new(),
+            replay_corruptions: VecDeque::new(),
+            reported_statistics: HashMap::new(),
+            statistics_reports: 0,
+            failovers: VecDeque::new(),
+            worker_failures: VecDeque::new(),
+
+            snapshots: HashMap::new(),
+
+            read_autoscaling: state.config.read_autoscaling,
+            read_replicas: HashMap::new(),
+            read_pressure: HashMap::new(),
+            reader_settings: HashMap::new(),
+            scaling_events: VecDeque::new(),
+
+            clock,
+
+            replies: DomainReplies::new(drx, state.config.control_reply_timeout),
         }
     }
 
@@ -506,9 +1856,13 @@ impl ControllerInner {
         num_shards: Option<usize>,
         log: &Logger,
         nodes: Vec<(NodeIndex, bool)>,
+        shard_weights: Vec<u32>,
     ) -> DomainHandle {
         // TODO: can we just redirect all domain traffic through the worker's connection?
         let mut assignments = Vec::new();
+        let uses_disk = self.persistence.mode != DurabilityMode::MemoryOnly
+            && nodes.iter().any(|&(ni, _)| self.ingredients[ni].is_base());
+        let reservation = self.base_disk_reservation;
         let mut nodes = Some(
             nodes
                 .into_iter()
@@ -520,8 +1874,26 @@ impl ControllerInner {
                 .collect(),
         );
 
-        // TODO(malte): simple round-robin placement for the moment
-        let mut wi = self.workers.iter_mut();
+        // during a rolling upgrade, domains are moved back to the upgraded worker
+        let workers = &self.workers;
+        let preferred = self
+            .preferred_worker
+            .filter(|p| workers.get(p).map(|w| w.healthy).unwrap_or(false));
+
+        let excluded = self.excluded_worker;
+        let eligible = |i: &WorkerIdentifier, w: &Worker| {
+            w.healthy && preferred.map(|p| p == *i).unwrap_or(true) && excluded != Some(*i)
+        };
+
+        let mut load: HashMap<WorkerIdentifier, usize> = HashMap::new();
+        for dh in self.domains.values() {
+            for shard in 0..dh.shards() {
+                *load.entry(dh.assignment(shard)).or_default() += 1;
+            }
+        }
+        let wids: Vec<_> = self.workers.keys().cloned().collect();
+        let mut cursor = 0;
+        let mut placed = Vec::new();
 
         // Send `AssignDomain` to each shard of the given domain
         for i in 0..num_shards.unwrap_or(1) {
@@ -535,20 +1907,65 @@ impl ControllerInner {
                 index: idx,
                 shard: if num_shards.is_some() { Some(i) } else { None },
                 nshards: num_shards.unwrap_or(1),
-                config: self.domain_config.clone(),
+                config: DomainConfig {
+                    shard_weights: shard_weights.clone(),
+                    ..self.domain_config.clone()
+                },
                 nodes,
                 persistence_parameters: self.persistence.clone(),
             };
 
-            let (identifier, w) = loop {
-                if let Some((i, w)) = wi.next() {
-                    if w.healthy {
-                        break (*i, w);
+            // base table shards only go to workers with room left in their disk quota. if no
+            // worker has any room left, there is nothing better to do than to ignore the quota.
+            let enforce_quota = uses_disk
+                && self
+                    .workers
+                    .iter()
+                    .any(|(i, w)| eligible(i, w) && w.disk_headroom(reservation) != Some(0));
+            if uses_disk && !enforce_quota {
+                crit!(
+                    log,
+                    "placing base domain {}.{} beyond the disk quota of every worker",
+                    domain.index.index(),
+                    domain.shard.unwrap_or(0)
+                );
+            }
+
+            let candidates: Vec<_> = wids
+                .iter()
+                .map(|i| {
+                    let w = &self.workers[i];
+                    Candidate {
+                        id: *i,
+                        label: w.label.as_deref(),
+                        load: load.get(i).cloned().unwrap_or(0),
+                        memory_used: w.memory_used,
+                        eligible: eligible(i, w)
+                            && (!enforce_quota || w.disk_headroom(reservation) != Some(0)),
                     }
-                } else {
-                    wi = self.workers.iter_mut();
-                }
-            };
+                })
+                .collect();
+            if !self.placement.satisfiable(&candidates) {
+                crit!(
+                    log,
+                    "no worker matches placement {:?} for domain {}.{}",
+                    self.placement,
+                    domain.index.index(),
+                    domain.shard.unwrap_or(0)
+                );
+            }
+            let chosen = self
+                .placement
+                .choose(&candidates, &placed, &mut cursor)
+                .unwrap();
+            placed.push(chosen);
+            let identifier = wids[chosen];
+            *load.entry(identifier).or_default() += 1;
+
+            let w = self.workers.get_mut(&identifier).unwrap();
+            if uses_disk {
+                w.base_shards += 1;
+            }
 
             // send domain to worker
             info!(
@@ -578,8 +1995,15 @@ impl ControllerInner {
         for r in replies {
             match r {
                 ControlReplyPacket::Booted(shard, addr) => {
-                    self.channel_coordinator.insert_remote((idx, shard), addr);
-                    announce.push(DomainDescriptor::new(idx, shard, addr));
+                    // every boot gets a new route version, so that workers that still know the
+                    // shard's previous location replace it, whatever order the gossip arrives in.
+                    self.route_version += 1;
+                    let version = self.route_version;
+                    let dd = DomainDescriptor::new(idx, shard, addr).with_version(version);
+                    self.channel_coordinator
+                        .insert_remote_versioned((idx, shard), addr, version);
+                    self.routes.insert((idx, shard), dd);
+                    announce.push(dd);
                     txs.insert(
                         shard,
                         self.channel_coordinator
@@ -626,7 +2050,8 @@ impl ControllerInner {
             .enumerate()
             .map(|(i, worker)| {
                 let tx = txs.remove(&i).unwrap();
-                DomainShardHandle { worker, tx }
+                let log = log.new(o!("shard" => i));
+                DomainShardHandle::new(worker, tx, log)
             })
             .collect();
 
@@ -634,6 +2059,42 @@ impl ControllerInner {
             idx,
             shards,
             log: log.clone(),
+            uses_disk,
+            shard_weights,
+        }
+    }
+
+    /// The share of the keys each shard of a new domain that holds `nodes` owns.
+    ///
+    /// A domain that holds a table or query with weights of its own gets those, and any other
+    /// sharded domain the default weights. A read replica gets the weights of the reader it copies,
+    /// so that views can read from either alike. Empty if keys are spread evenly.
+    pub(super) fn shard_weights_for(&self, nodes: &[NodeIndex]) -> Vec<u32> {
+        let shards = match self.ingredients[nodes[0]].sharded_by().shards() {
+            Some(shards) => shards,
+            None => return Vec::new(),
+        };
+        for &ni in nodes {
+            if let Ok(of) = self.ingredients[ni].with_reader(|r| r.is_for()) {
+                let copied = self
+                    .ingredients
+                    .neighbors_directed(of, petgraph::EdgeDirection::Outgoing)
+                    .filter(|&r| r != ni && self.ingredients[r].is_reader())
+                    .find_map(|r| self.domains.get(&self.ingredients[r].domain()));
+                if let Some(d) = copied {
+                    return d.shard_weights.clone();
+                }
+            }
+        }
+
+        let weights = nodes
+            .iter()
+            .find_map(|&ni| self.shard_weights.get(self.ingredients[ni].name()))
+            .unwrap_or(&self.domain_config.shard_weights);
+        if weights.len() == shards {
+            weights.clone()
+        } else {
+            Vec::new()
         }
     }
 
@@ -648,7 +2109,11 @@ impl ControllerInner {
 
     /// Adds a new user universe.
     /// User universes automatically enforce security policies.
-    fn add_universe<F, T>(&mut self, context: HashMap<String, DataType>, f: F) -> T
+    fn add_universe<F, T>(
+        &mut self,
+        context: HashMap<String, DataType>,
+        f: F,
+    ) -> Result<T, WaitError>
     where
         F: FnOnce(&mut Migration) -> T,
     {
@@ -664,13 +2129,15 @@ impl ControllerInner {
             log: miglog,
         };
         let r = f(&mut m);
-        m.commit();
-        r
+        m.commit()?;
+        Ok(r)
     }
 
     /// Perform a new query schema migration.
+    ///
+    /// Fails if a domain stops replying to the controller before the migration completes.
     // crate viz for tests
-    pub(crate) fn migrate<F, T>(&mut self, f: F) -> T
+    pub(crate) fn migrate<F, T>(&mut self, f: F) -> Result<T, WaitError>
     where
         F: FnOnce(&mut Migration) -> T,
     {
@@ -686,8 +2153,8 @@ impl ControllerInner {
             log: miglog,
         };
         let r = f(&mut m);
-        m.commit();
-        r
+        m.commit()?;
+        Ok(r)
     }
 
     #[cfg(test)]
@@ -710,13 +2177,68 @@ impl ControllerInner {
             .collect()
     }
 
-    /// Get a Vec of all known output nodes.
-    ///
-    /// Output nodes here refers to nodes of type `Reader`, which is the nodes created in response
-    /// to calling `.maintain` or `.stream` for a node during a migration.
-    fn outputs(&self) -> BTreeMap<String, NodeIndex> {
-        self.ingredients
-            .externals(petgraph::EdgeDirection::Outgoing)
+    /// The domains of the existing base tables that the nodes in `new` receive writes from.
+    pub(super) fn bases_feeding(&self, new: &HashSet<NodeIndex>) -> HashSet<DomainIndex> {
+        let mut domains = HashSet::new();
+        let mut seen = HashSet::new();
+        let mut stack: Vec<_> = new.iter().cloned().collect();
+        while let Some(ni) = stack.pop() {
+            for p in self
+                .ingredients
+                .neighbors_directed(ni, petgraph::EdgeDirection::Incoming)
+            {
+                if p == self.source || !seen.insert(p) {
+                    continue;
+                }
+                let n = &self.ingredients[p];
+                if n.is_base() && !new.contains(&p) && self.domains.contains_key(&n.domain()) {
+                    domains.insert(n.domain());
+                }
+                stack.push(p);
+            }
+        }
+        domains
+    }
+
+    /// Have the given base domains start or stop turning away client writes while a migration is
+    /// in progress.
+    ///
+    /// Domains that cannot be reached are skipped, with a warning, rather than failing the
+    /// migration.
+    pub(super) fn set_migrating(&mut self, domains: &HashSet<DomainIndex>, migrating: bool) {
+        for &di in domains {
+            let domain = match self.domains.get_mut(&di) {
+                Some(domain) => domain,
+                None => continue,
+            };
+            let m = Box::new(Packet::SetMigrating { migrating });
+            let res = match domain.send_to_healthy(m, &self.workers) {
+                Ok(()) => futures_executor::block_on(self.replies.wait_for_acks(domain))
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(format!("{:?}", e)),
+            };
+            if let Err(e) = res {
+                warn!(
+                    self.log,
+                    "failed to tell base domain about migration";
+                    "domain" => di.index(),
+                    "migrating" => migrating,
+                    "err" => e,
+                );
+            }
+        }
+    }
+
+    /// Get a Vec of all known output nodes.
+    ///
+    /// Output nodes here refers to nodes of type `Reader`, which is the nodes created in response
+    /// to calling `.maintain` or `.stream` for a node during a migration. Read replicas are not
+    /// listed separately from the views they serve.
+    fn outputs(&self) -> BTreeMap<String, NodeIndex> {
+        let replicas: HashSet<_> = self.read_replicas.values().flatten().collect();
+        self.ingredients
+            .externals(petgraph::EdgeDirection::Outgoing)
+            .filter(|n| !replicas.contains(n))
             .filter_map(|n| {
                 let name = self.ingredients[n].name().to_owned();
                 self.ingredients[n]
@@ -730,6 +2252,91 @@ impl ControllerInner {
             .collect()
     }
 
+    /// Describe every named query in the recipe that has a view, ordered by name.
+    fn queries(&mut self) -> Vec<QueryInfo> {
+        let mut queries: Vec<_> = self
+            .recipe
+            .expressions()
+            .into_iter()
+            .filter_map(|(name, q)| match *q {
+                SqlQuery::Select(_) | SqlQuery::CompoundSelect(_) => {
+                    Some((name?.clone(), q.to_string()))
+                }
+                _ => None,
+            })
+            .filter_map(|(name, sql)| Some((self.reader_for(&name)?, name, sql)))
+            .collect();
+        queries.sort_by(|a, b| a.1.cmp(&b.1));
+
+        queries
+            .into_iter()
+            .map(|(r, name, sql)| self.describe_query(r, name, sql))
+            .collect()
+    }
+
+    fn describe_query(&mut self, r: NodeIndex, name: String, sql: String) -> QueryInfo {
+        let n = &self.ingredients[r];
+        let key_columns = n
+            .with_reader(|reader| {
+                reader
+                    .key()
+                    .unwrap_or(&[])
+                    .iter()
+                    .map(|&c| n.fields()[c].clone())
+                    .collect()
+            })
+            .unwrap();
+        let partial = match self.materializations.get_status(r, n) {
+            MaterializationStatus::Partial { .. } => true,
+            _ => false,
+        };
+
+        let workers = &self.workers;
+        let replies = &mut self.replies;
+        let domain = self.domains.get_mut(&n.domain()).unwrap();
+        let shards = domain.shards();
+        let mut info = QueryInfo {
+            name,
+            sql,
+            key_columns,
+            shards,
+            partial,
+            paused: false,
+            ready: false,
+            mem_size: 0,
+        };
+
+        // a shard on a failed worker can't serve reads, and won't answer either
+        if (0..shards).any(|i| !workers[&domain.assignment(i)].healthy) {
+            return info;
+        }
+        let m = Box::new(Packet::GetReaderSummary {
+            node: n.local_addr(),
+        });
+        // a domain that is backed up would keep us from describing any other query, so we
+        // report the query as not ready instead of waiting for it.
+        if let Err(e) = domain.try_send_to_healthy(m, workers) {
+            warn!(self.log, "failed to describe query"; "query" => &info.name, "err" => ?e);
+            return info;
+        }
+
+        let summaries = match futures_executor::block_on(replies.wait_for_reader_summaries(&domain))
+        {
+            Ok(summaries) => summaries,
+            Err(e) => {
+                warn!(self.log, "failed to describe query"; "query" => &info.name, "err" => %e);
+                return info;
+            }
+        };
+        info.ready = true;
+        for (ready, paused, mem_size) in summaries {
+            info.ready &= ready;
+            info.paused |= paused;
+            info.mem_size += mem_size;
+        }
+        info
+    }
+
     fn find_view_for(&self, node: NodeIndex, name: &str) -> Option<NodeIndex> {
         // reader should be a child of the given node. however, due to sharding, it may not be an
         // *immediate* child. furthermore, once we go beyond depth 1, we may accidentally hit an
@@ -746,155 +2353,1334 @@ impl ControllerInner {
                 return Some(child);
             }
         }
-        None
+        None
+    }
+
+    /// Find the reader node for the (already maintained) view called `name`.
+    fn reader_for(&self, name: &str) -> Option<NodeIndex> {
+        // first try to resolve the node via the recipe, which handles aliasing between identical
+        // queries.
+        let node = match self.recipe.node_addr_for(name) {
+            Ok(ni) => ni,
+            Err(_) => {
+                // if the recipe doesn't know about this query, traverse the graph.
+                // we need this do deal with manually constructed graphs (e.g., in tests).
+                *self.outputs().get(name)?
+            }
+        };
+
+        let name = match self.recipe.resolve_alias(name) {
+            None => name,
+            Some(alias) => alias,
+        };
+        self.find_view_for(node, name)
+    }
+
+    /// Obtain a `ViewBuilder` that can be sent to a client and then used to query a given
+    /// (already maintained) reader node called `name`.
+    fn view_builder(&self, name: &str) -> Option<ViewBuilder> {
+        self.reader_for(name).map(|r| {
+            let count_key = self.ingredients[r]
+                .with_reader(|rn| if rn.is_count_only() { rn.key() } else { None })
+                .unwrap();
+            let key_expressions = self.ingredients[r]
+                .with_reader(|rn| rn.key_expressions().to_vec())
+                .unwrap();
+            let fields = self.ingredients[r].fields();
+            let (columns, schema, valid_time, tombstone) = match count_key {
+                Some(_) if !key_expressions.is_empty() => {
+                    let mut columns: Vec<_> = key_expressions
+                        .iter()
+                        .map(|e| e.name(&fields[e.column()]))
+                        .collect();
+                    columns.push("count".to_owned());
+                    (columns, None, None, None)
+                }
+                Some(key) => {
+                    // count-only views hold the key columns followed by the number of rows
+                    let mut columns: Vec<_> = key.iter().map(|&c| fields[c].clone()).collect();
+                    columns.push("count".to_owned());
+                    (columns, None, None, None)
+                }
+                None if !key_expressions.is_empty() => {
+                    // the computed key values are stored after the view's own columns
+                    let mut columns = fields.to_vec();
+                    columns.extend(key_expressions.iter().map(|e| e.name(&fields[e.column()])));
+                    (
+                        columns,
+                        None,
+                        self.view_valid_time(r),
+                        self.view_tombstone(r),
+                    )
+                }
+                None => (
+                    fields.to_vec(),
+                    self.view_schema(r),
+                    self.view_valid_time(r),
+                    self.view_tombstone(r),
+                ),
+            };
+            let shard_key = match self.ingredients[r].sharded_by() {
+                Sharding::ByColumn(c, _) => self.ingredients[r]
+                    .with_reader(|rn| rn.key().and_then(|k| k.iter().position(|&kc| kc == c)))
+                    .unwrap()
+                    .unwrap_or(0),
+                _ => 0,
+            };
+
+            // the view spreads its reads across its own reader and its read replicas, which are
+            // all configured alike
+            let shards = |r: NodeIndex| -> Vec<_> {
+                let domain = &self.domains[&self.ingredients[r].domain()];
+                (0..domain.shards())
+                    .map(|i| self.read_addrs[&domain.assignment(i)])
+                    .collect()
+            };
+            let replicas = self
+                .read_replicas
+                .get(&r)
+                .map(|replicas| replicas.iter().map(|&r| (r, shards(r))).collect())
+                .unwrap_or_default();
+
+            ViewBuilder {
+                node: r,
+                columns,
+                schema,
+                shards: shards(r),
+                replicas,
+                shard_key,
+                valid_time,
+                tombstone,
+                key_expressions,
+                shard_weights: self.domains[&self.ingredients[r].domain()]
+                    .shard_weights
+                    .clone(),
+            }
+        })
+    }
+
+    /// Find the columns of the given view that hold the valid-from and valid-to times of a base
+    /// table with valid-time columns, if there are any.
+    fn view_valid_time(&self, view_ni: NodeIndex) -> Option<(usize, usize)> {
+        self.view_base_columns(view_ni, |b| b.valid_time().map(|(from, to)| vec![from, to]))
+            .map(|cs| (cs[0], cs[1]))
+    }
+
+    /// Find the column of the given view that marks the soft-deleted rows of a base table with
+    /// soft deletes, if there is one.
+    fn view_tombstone(&self, view_ni: NodeIndex) -> Option<usize> {
+        self.view_base_columns(view_ni, |b| b.soft_delete().map(|c| vec![c]))
+            .map(|cs| cs[0])
+    }
+
+    /// Find the columns of the given view that all of the columns `base_columns` picks out of some
+    /// base table it reads from end up in.
+    fn view_base_columns<F>(&self, view_ni: NodeIndex, base_columns: F) -> Option<Vec<usize>>
+    where
+        F: Fn(&node::special::Base) -> Option<Vec<usize>>,
+    {
+        let any = self.ingredients.node_indices().any(|ni| {
+            self.ingredients[ni]
+                .get_base()
+                .and_then(&base_columns)
+                .is_some()
+        });
+        if !any {
+            return None;
+        }
+
+        let columns: Vec<_> = (0..self.ingredients[view_ni].fields().len()).collect();
+        for path in provenance_of(&self.ingredients, view_ni, &columns[..], |_, _, _| None) {
+            let (base, ref origins) = *path.last().unwrap();
+            let wanted = match self.ingredients[base].get_base().and_then(&base_columns) {
+                Some(wanted) => wanted,
+                None => continue,
+            };
+            let found: Option<Vec<_>> = wanted
+                .into_iter()
+                .map(|w| origins.iter().position(|&c| c == Some(w)))
+                .collect();
+            if found.is_some() {
+                return found;
+            }
+        }
+        None
+    }
+
+    /// Find the columns of the given view whose values are derived from any of the `tagged` base
+    /// table columns, given by table and column name.
+    ///
+    /// A column copied from a tagged column is derived from it. So is a column that a node
+    /// computes, such as an aggregate or an expression, if any of that node's inputs is derived
+    /// from a tagged column, since which inputs a computed value depends on is not known.
+    pub(super) fn masked_columns(
+        &self,
+        view_ni: NodeIndex,
+        tagged: &[(String, String)],
+    ) -> Vec<usize> {
+        if tagged.is_empty() {
+            return Vec::new();
+        }
+        self.derived_columns(view_ni, tagged, &mut HashMap::new())
+    }
+
+    /// See `masked_columns`; `seen` holds the answer for nodes that have been visited already.
+    fn derived_columns(
+        &self,
+        ni: NodeIndex,
+        tagged: &[(String, String)],
+        seen: &mut HashMap<NodeIndex, Vec<usize>>,
+    ) -> Vec<usize> {
+        if let Some(derived) = seen.get(&ni) {
+            return derived.clone();
+        }
+
+        let n = &self.ingredients[ni];
+        let parents: Vec<_> = self
+            .ingredients
+            .neighbors_directed(ni, petgraph::EdgeDirection::Incoming)
+            .collect();
+        let derived: Vec<_> = if n.is_base() {
+            (0..n.fields().len())
+                .filter(|&c| {
+                    let field = &n.fields()[c];
+                    tagged.iter().any(|(t, f)| t == n.name() && f == field)
+                })
+                .collect()
+        } else if !n.is_internal() {
+            // all non-internal nodes pass on the rows of their one parent unchanged
+            match parents.first() {
+                Some(&p) if p != self.source => self.derived_columns(p, tagged, seen),
+                _ => Vec::new(),
+            }
+        } else {
+            let inputs: HashMap<_, _> = parents
+                .iter()
+                .map(|&p| (p, self.derived_columns(p, tagged, seen)))
+                .collect();
+            let any_input = inputs.values().any(|d| !d.is_empty());
+            (0..n.fields().len())
+                .filter(|&c| {
+                    n.parent_columns(c).into_iter().any(|(p, pc)| match pc {
+                        Some(pc) => inputs.get(&p).map(|d| d.contains(&pc)).unwrap_or(false),
+                        // the column is computed by the node itself
+                        None => p == ni && any_input,
+                    })
+                })
+                .collect()
+        };
+        seen.insert(ni, derived.clone());
+        derived
+    }
+
+    /// The read mask the recipe's masking policy calls for on the view `view` of `view_ni`.
+    fn read_mask_for(&self, view: &str, view_ni: NodeIndex) -> Option<ReadMask> {
+        let policy = self.recipe.masks();
+        let exempt = policy
+            .views
+            .iter()
+            .any(|v| v == view || self.recipe.resolve_alias(v) == Some(view));
+        if exempt {
+            return None;
+        }
+        let columns = self.masked_columns(view_ni, &policy.columns);
+        if columns.is_empty() {
+            return None;
+        }
+        Some(ReadMask { columns })
+    }
+
+    /// Bring the read masks of all views in line with the recipe's masking policy.
+    fn apply_read_masks(&mut self) {
+        for (view, view_ni) in self.outputs() {
+            let r = match self.reader_for(&view) {
+                Some(r) => r,
+                None => continue,
+            };
+            let mask = self.read_mask_for(&view, view_ni);
+            let current = self.ingredients[r].with_reader(|r| r.read_mask().cloned());
+            if current.map(|m| m == mask).unwrap_or(true) {
+                continue;
+            }
+
+            // remember the mask in the graph too, so that the view keeps it if it is rebuilt
+            self.ingredients[r]
+                .with_reader_mut(|r| r.set_read_mask(mask.clone()))
+                .unwrap();
+            let masked = mask.as_ref().map(|m| m.columns.clone()).unwrap_or_default();
+            match self.send_to_reader(&view, |node| Packet::SetReadMask { node, mask }) {
+                Ok(()) => info!(self.log, "set read mask"; "view" => &view, "columns" => ?masked),
+                Err(e) => warn!(self.log, "failed to apply read mask from recipe: {}", e),
+            }
+        }
+    }
+
+    fn view_schema(&self, view_ni: NodeIndex) -> Option<Vec<ColumnSpecification>> {
+        let n = &self.ingredients[view_ni];
+        let schema: Vec<_> = (0..n.fields().len())
+            .map(|i| schema::column_schema(&self.ingredients, view_ni, &self.recipe, i, &self.log))
+            .collect();
+
+        if schema.iter().any(Option::is_none) {
+            None
+        } else {
+            Some(schema.into_iter().map(Option::unwrap).collect())
+        }
+    }
+
+    /// Obtain a TableBuild that can be used to construct a Table to perform writes and deletes
+    /// from the given named base node.
+    fn table_builder(&self, base: &str) -> Option<TableBuilder> {
+        let ni = match self.recipe.node_addr_for(base) {
+            Ok(ni) => ni,
+            Err(_) => *self.inputs().get(base)?,
+        };
+        if self.snapshots.values().any(|bases| bases.contains(&ni)) {
+            // the views of snapshots are read-only
+            return None;
+        }
+        let node = &self.ingredients[ni];
+
+        trace!(self.log, "creating table"; "for" => base);
+
+        let mut key = self.ingredients[ni]
+            .suggest_indexes(ni)
+            .remove(&ni)
+            .unwrap_or_else(Vec::new);
+        let mut is_primary = false;
+        if key.is_empty() {
+            if let Sharding::ByColumn(col, _) = self.ingredients[ni].sharded_by() {
+                key = vec![col];
+            }
+        } else {
+            is_primary = true;
+        }
+
+        let txs = (0..self.domains[&node.domain()].shards())
+            .map(|i| {
+                self.channel_coordinator
+                    .get_addr(&(node.domain(), i))
+                    .unwrap()
+            })
+            .collect();
+
+        let base_operator = node
+            .get_base()
+            .expect("asked to get table for non-base node");
+        let columns: Vec<String> = node
+            .fields()
+            .iter()
+            .enumerate()
+            .filter(|&(n, _)| !base_operator.get_dropped().contains_key(n))
+            .map(|(_, s)| s.clone())
+            .collect();
+        assert_eq!(
+            columns.len(),
+            node.fields().len() - base_operator.get_dropped().len()
+        );
+        let schema = self.recipe.schema_for(base).map(|s| match s {
+            Schema::Table(s) => s,
+            _ => panic!("non-base schema {:?} returned for table '{}'", s, base),
+        });
+
+        Some(TableBuilder {
+            txs,
+            ni: node.global_addr(),
+            addr: node.local_addr(),
+            key,
+            key_is_primary: is_primary,
+            dropped: base_operator.get_dropped(),
+            table_name: base.to_owned(),
+            columns,
+            schema,
+            size_limits: base_operator.get_size_limits().to_vec(),
+            shard_weights: self.domains[&node.domain()].shard_weights.clone(),
+            shard_ranges: base_operator.shard_ranges().to_vec(),
+            mirror_limit: self.mirror_limits.get(base).cloned(),
+        })
+    }
+
+    /// Record the statistics a domain shard reported on its own.
+    ///
+    /// Reports from domains that have since been torn down are ignored.
+    pub(super) fn handle_domain_statistics(&mut self, msg: CoordinationMessage) {
+        if let CoordinationPayload::DomainStatistics {
+            domain,
+            shard,
+            stats,
+            nodes,
+        } = msg.payload
+        {
+            if !self.domains.contains_key(&domain) {
+                return;
+            }
+
+            self.statistics_reports += 1;
+            let report = self.statistics_reports;
+            let mut known = self
+                .reported_statistics
+                .remove(&(domain, shard))
+                .map(|(_, _, known)| known)
+                .unwrap_or_default();
+            known.extend(nodes.into_iter().map(|(ni, s)| (ni, (report, s))));
+            self.reported_statistics
+                .insert((domain, shard), (report, stats, known));
+        }
+    }
+
+    /// The statistics domains have reported on their own since report number `since`, and the
+    /// number of the latest report.
+    ///
+    /// Only the nodes whose statistics changed since then are included for each domain shard.
+    fn statistics_updates(&self, since: u64) -> (u64, GraphStats) {
+        // a new controller starts counting reports from scratch
+        let since = if since > self.statistics_reports {
+            0
+        } else {
+            since
+        };
+
+        let domains = self
+            .reported_statistics
+            .iter()
+            .filter(|&(&(di, _), &(report, ..))| report > since && self.domains.contains_key(&di))
+            .map(|(&addr, &(_, ref stats, ref nodes))| {
+                let nodes = nodes
+                    .iter()
+                    .filter(|&(_, &(report, _))| report > since)
+                    .map(|(&ni, &(_, ref s))| (ni, s.clone()))
+                    .collect();
+                (addr, (stats.clone(), nodes))
+            })
+            .collect();
+
+        (
+            self.statistics_reports,
+            GraphStats {
+                domains,
+                workers: self.worker_stats(),
+            },
+        )
+    }
+
+    fn worker_stats(&self) -> Vec<WorkerStats> {
+        self.workers
+            .iter()
+            .map(|(&worker, w)| WorkerStats {
+                worker,
+                healthy: w.healthy,
+                domains_hosted: w.domains_hosted,
+                memory_used: w.memory_used,
+                disk_usage: w.disk_usage,
+                cpu_load: w.cpu_load,
+            })
+            .collect()
+    }
+
+    /// Get statistics about the time spent processing different parts of the graph.
+    fn get_statistics(&mut self) -> GraphStats {
+        trace!(self.log, "asked to get statistics");
+        let log = &self.log;
+        let workers = &self.workers;
+        let replies = &mut self.replies;
+        // TODO: request stats from domains in parallel.
+        let domains = self
+            .domains
+            .iter_mut()
+            .flat_map(|(&di, s)| {
+                trace!(log, "requesting stats from domain"; "di" => di.index());
+                s.send_to_healthy(Box::new(Packet::GetStatistics), workers)
+                    .unwrap();
+                futures_executor::block_on(replies.wait_for_statistics(&s))
+                    .unwrap_or_else(|e| {
+                        warn!(log, "no statistics from domain"; "di" => di.index(), "err" => %e);
+                        Vec::new()
+                    })
+                    .into_iter()
+                    .enumerate()
+                    .map(move |(i, s)| ((di, i), s))
+            })
+            .collect();
+
+        GraphStats {
+            domains,
+            workers: self.worker_stats(),
+        }
+    }
+
+    /// Find the node of the given base table, whether it was added by a recipe or by a manual
+    /// migration.
+    fn base_node(&self, base: &str) -> Result<NodeIndex, String> {
+        match self.recipe.node_addr_for(base) {
+            Ok(ni) => Ok(ni),
+            Err(_) => self
+                .inputs()
+                .get(base)
+                .cloned()
+                .ok_or_else(|| format!("no base table named '{}'", base)),
+        }
+    }
+
+    /// Fetch the audit log of the given base table, merged across all of its shards.
+    fn audit_log(&mut self, base: &str) -> Result<Vec<AuditEntry>, String> {
+        let ni = self.base_node(base)?;
+        let node = &self.ingredients[ni];
+        let (di, na) = (node.domain(), node.local_addr());
+
+        let workers = &self.workers;
+        let replies = &mut self.replies;
+        let domain = self.domains.get_mut(&di).unwrap();
+        domain
+            .send_to_healthy(Box::new(Packet::GetAuditLog { node: na }), workers)
+            .map_err(|e| format!("failed to request audit log: {:?}", e))?;
+
+        let mut entries = Vec::new();
+        let logs = futures_executor::block_on(replies.wait_for_audit_logs(&domain))
+            .map_err(|e| format!("failed to fetch audit log: {}", e))?;
+        for log in logs {
+            match log {
+                Some(log) => entries.extend(log),
+                None => return Err(format!("auditing is not enabled for '{}'", base)),
+            }
+        }
+        entries.sort_by_key(|e| e.timestamp);
+        Ok(entries)
+    }
+
+    fn commit_seqs(&mut self, base: &str) -> Result<Vec<u64>, String> {
+        let ni = self.base_node(base)?;
+        let node = &self.ingredients[ni];
+        let (di, na) = (node.domain(), node.local_addr());
+
+        let workers = &self.workers;
+        let replies = &mut self.replies;
+        let domain = self.domains.get_mut(&di).unwrap();
+        domain
+            .send_to_healthy(Box::new(Packet::GetCommitSeq { node: na }), workers)
+            .map_err(|e| format!("failed to request commit sequence numbers: {:?}", e))?;
+
+        futures_executor::block_on(replies.wait_for_commit_seqs(&domain))
+            .map_err(|e| format!("failed to fetch commit sequence numbers: {}", e))
+    }
+
+    /// See `ControllerHandle::export_base`.
+    fn export_base(&mut self, base: &str) -> Result<BaseExport, String> {
+        let ni = self.base_node(base)?;
+        let node = &self.ingredients[ni];
+        let columns = node.fields().to_vec();
+        let (di, na) = (node.domain(), node.local_addr());
+
+        let workers = &self.workers;
+        let replies = &mut self.replies;
+        let domain = self.domains.get_mut(&di).unwrap();
+        domain
+            .send_to_healthy(Box::new(Packet::GetBaseRows { node: na }), workers)
+            .map_err(|e| format!("failed to request base rows: {:?}", e))?;
+
+        let shards = futures_executor::block_on(replies.wait_for_base_rows(&domain))
+            .map_err(|e| format!("failed to fetch base rows: {}", e))?
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| format!("base table '{}' does not keep all of its rows", base))?;
+        let export = BaseExport {
+            table: base.to_owned(),
+            columns,
+            shards,
+        };
+        info!(self.log, "exported base"; "base" => base, "rows" => export.len());
+        Ok(export)
+    }
+
+    /// See `ControllerHandle::import_base`.
+    fn import_base(&mut self, export: BaseExport) -> Result<(), String> {
+        let base = &export.table;
+        let ni = self.base_node(base)?;
+        if self.ingredients[ni].fields() != &export.columns[..] {
+            return Err(format!(
+                "base table '{}' has columns {:?}, but the export has {:?}",
+                base,
+                self.ingredients[ni].fields(),
+                export.columns
+            ));
+        }
+        let shards = self.domains[&self.ingredients[ni].domain()].shards();
+        if shards != export.shards.len() {
+            return Err(format!(
+                "base table '{}' has {} shards, but the export has {}",
+                base,
+                shards,
+                export.shards.len()
+            ));
+        }
+        if self.commit_seqs(base)?.iter().any(|&seq| seq != 0) {
+            return Err(format!("base table '{}' has already been written to", base));
+        }
+
+        let node = &self.ingredients[ni];
+        let (di, na) = (node.domain(), node.local_addr());
+        let rows = export.len();
+        for (i, shard) in export.shards.into_iter().enumerate() {
+            let workers = &self.workers;
+            let replies = &mut self.replies;
+            let domain = self.domains.get_mut(&di).unwrap();
+            let m = Box::new(Packet::ImportRows {
+                node: na,
+                rows: shard.rows,
+                commit_seq: shard.commit_seq,
+            });
+            domain
+                .send_to_healthy_shard(i, m, workers)
+                .map_err(|e| format!("failed to import shard {}: {:?}", i, e))?;
+            futures_executor::block_on(replies.wait_for_shard_ack(&domain))
+                .map_err(|e| format!("failed to import shard {}: {}", i, e))?;
+        }
+        info!(self.log, "imported base"; "base" => &export.table, "rows" => rows);
+        Ok(())
+    }
+
+    /// See `ControllerHandle::verify_base`.
+    pub(super) fn verify_base(
+        &mut self,
+        (base, repair): (String, bool),
+    ) -> Result<Vec<BaseVerification>, String> {
+        let ni = self.base_node(&base)?;
+        let node = &self.ingredients[ni];
+        let (di, na) = (node.domain(), node.local_addr());
+
+        let workers = &self.workers;
+        let replies = &mut self.replies;
+        let domain = self.domains.get_mut(&di).unwrap();
+        domain
+            .send_to_healthy(Box::new(Packet::VerifyBase { node: na, repair }), workers)
+            .map_err(|e| format!("failed to request base verification: {:?}", e))?;
+
+        let mut reports = futures_executor::block_on(replies.wait_for_base_verifications(&domain))
+            .map_err(|e| format!("failed to verify base: {}", e))?;
+        reports.sort_by_key(|r| r.shard);
+        Ok(reports)
+    }
+
+    fn sample_key(
+        &mut self,
+        (view, key): (String, Vec<DataType>),
+    ) -> Result<Vec<NodeSample>, String> {
+        let reader = self
+            .reader_for(&view)
+            .ok_or_else(|| format!("no view named '{}'", view))?;
+        let key_columns = self.ingredients[reader]
+            .with_reader(|r| r.key().map(Vec::from))
+            .unwrap()
+            .ok_or_else(|| format!("view '{}' has no key", view))?;
+        if key.len() != key_columns.len() {
+            return Err(format!(
+                "view '{}' is keyed by {} columns, but the key has {}",
+                view,
+                key_columns.len(),
+                key.len()
+            ));
+        }
+
+        // walk every path the key takes towards the base tables, and sample each operator along
+        // the way once. a path ends where the key is no longer made up of the operator's columns,
+        // such as above an aggregation over it.
+        let mut nodes = Vec::new();
+        let mut seen = HashSet::new();
+        for path in provenance_of(&self.ingredients, reader, &key_columns[..], |_, _, _| None) {
+            for (ni, columns) in path.into_iter().skip(1) {
+                let columns = match columns.into_iter().collect::<Option<Vec<_>>>() {
+                    Some(columns) => columns,
+                    None => break,
+                };
+                let n = &self.ingredients[ni];
+                if (n.is_internal() || n.is_base()) && seen.insert(ni) {
+                    nodes.push((ni, columns));
+                }
+            }
+        }
+
+        let mut samples = Vec::with_capacity(nodes.len());
+        for (ni, key_columns) in nodes {
+            let node = &self.ingredients[ni];
+            let (di, na) = (node.domain(), node.local_addr());
+            let workers = &self.workers;
+            let replies = &mut self.replies;
+            let domain = self.domains.get_mut(&di).unwrap();
+            domain
+                .send_to_healthy(
+                    Box::new(Packet::SampleState {
+                        node: na,
+                        columns: key_columns.clone(),
+                        key: key.clone(),
+                    }),
+                    workers,
+                )
+                .map_err(|e| format!("failed to request state sample: {:?}", e))?;
+            let (materialized, rows) =
+                futures_executor::block_on(replies.wait_for_state_samples(&domain))
+                    .map_err(|e| format!("failed to sample state: {}", e))?;
+
+            samples.push(NodeSample {
+                node: ni.index(),
+                name: node.name().to_owned(),
+                description: node.description(true),
+                parents: self
+                    .ingredients
+                    .neighbors_directed(ni, petgraph::EdgeDirection::Incoming)
+                    .filter(|&p| p != self.source)
+                    .map(|p| p.index())
+                    .collect(),
+                key_columns,
+                materialized,
+                rows,
+            });
+        }
+        Ok(samples)
+    }
+
+    /// Add a read replica to each view whose reads have stayed queued up, and remove one from
+    /// each view whose reads have stopped queueing up, if the controller is configured to do so.
+    fn run_read_autoscaling(&mut self) {
+        let (threshold, max_replicas, window) = match self.read_autoscaling {
+            Some(autoscaling) => autoscaling,
+            None => return,
+        };
+        if self.pending_recovery.is_some() {
+            return;
+        }
+
+        // the most reads waiting on any one shard of each reader
+        let mut queued: HashMap<NodeIndex, usize> = HashMap::new();
+        for ws in self.workers.values().filter(|ws| ws.healthy) {
+            for (&(r, _), &n) in &ws.read_queues {
+                let q = queued.entry(r).or_insert(0);
+                *q = cmp::max(*q, n);
+            }
+        }
+
+        let now = self.clock.now();
+        let mut changes = Vec::new();
+        let mut views = HashSet::new();
+        for (view, ni) in self.outputs() {
+            let r = match self.find_view_for(ni, &view) {
+                Some(r) => r,
+                None => continue,
+            };
+            views.insert(r);
+
+            let replicas = self.read_replicas.get(&r).map(Vec::as_slice).unwrap_or(&[]);
+            let deepest = iter::once(&r)
+                .chain(replicas)
+                .filter_map(|r| queued.get(r))
+                .max()
+                .cloned()
+                .unwrap_or(0);
+            // in between, the view is left as it is
+            let busy = if deepest > threshold {
+                true
+            } else if deepest <= threshold / 2 && !replicas.is_empty() {
+                false
+            } else {
+                self.read_pressure.remove(&r);
+                continue;
+            };
+
+            let pressure = self.read_pressure.entry(r).or_insert((busy, now));
+            if pressure.0 != busy {
+                *pressure = (busy, now);
+            }
+            if now.duration_since(pressure.1) < window {
+                continue;
+            }
+            if !busy {
+                changes.push((view, r, None));
+            } else if replicas.len() < max_replicas {
+                changes.push((view, r, Some(deepest)));
+            }
+        }
+        self.read_pressure.retain(|r, _| views.contains(r));
+
+        for (view, r, queued) in changes {
+            // the next change needs the view to stay busy (or idle) for another window
+            self.read_pressure.remove(&r);
+            let event = match queued {
+                Some(queued) => {
+                    self.add_read_replica(r)
+                        .map(|replicas| ScalingEvent::ReplicaAdded {
+                            view: view.clone(),
+                            replicas,
+                            queued,
+                        })
+                }
+                None => self
+                    .remove_read_replica(r)
+                    .map(|replicas| ScalingEvent::ReplicaRemoved {
+                        view: view.clone(),
+                        replicas,
+                    }),
+            };
+            match event {
+                Ok(event) => {
+                    info!(self.log, "scaled view"; "view" => &view, "event" => ?event);
+                    if self.scaling_events.len() == MAX_SCALING_EVENTS {
+                        self.scaling_events.pop_front();
+                    }
+                    self.scaling_events.push_back(event);
+                }
+                Err(e) => warn!(self.log, "failed to scale view"; "view" => view, "err" => e),
+            }
+        }
+    }
+
+    /// Add a read replica to the view whose own reader is `r`, and return the number of replicas
+    /// the view has now.
+    fn add_read_replica(&mut self, r: NodeIndex) -> Result<usize, String> {
+        let n = self.ingredients[r]
+            .with_reader(|r| r.is_for())
+            .map_err(|_| format!("node {} is not a reader", r.index()))?;
+        let i = self.read_replicas.get(&r).map(Vec::len).unwrap_or(0) + 1;
+        let name = format!("REPLICA_{}_{}", i, self.ingredients[r].name());
+        let replica = self
+            .migrate(move |mig| mig.add_read_replica(n, r, name))
+            .map_err(|e| format!("failed to add read replica: {}", e))?;
+
+        let replicas = self.read_replicas.entry(r).or_insert_with(Vec::new);
+        replicas.push(replica);
+        Ok(replicas.len())
+    }
+
+    /// Remove the newest read replica of the view whose own reader is `r`, and return the number
+    /// of replicas the view has left.
+    fn remove_read_replica(&mut self, r: NodeIndex) -> Result<usize, String> {
+        let replica = self
+            .read_replicas
+            .get_mut(&r)
+            .and_then(Vec::pop)
+            .ok_or_else(|| format!("reader {} has no read replicas", r.index()))?;
+        let left = self.read_replicas[&r].len();
+        if left == 0 {
+            self.read_replicas.remove(&r);
+        }
+        self.remove_leaf(replica)?;
+        Ok(left)
+    }
+
+    /// Add read replicas to, or remove them from, the view called `view` until it has `replicas`
+    /// of them.
+    fn set_read_replicas(&mut self, (view, replicas): (String, usize)) -> Result<(), String> {
+        let r = self
+            .reader_for(&view)
+            .ok_or_else(|| format!("no view named '{}'", view))?;
+        let count = |ctrl: &Self| ctrl.read_replicas.get(&r).map(Vec::len).unwrap_or(0);
+        while count(self) < replicas {
+            self.add_read_replica(r)?;
+        }
+        while count(self) > replicas {
+            self.remove_read_replica(r)?;
+        }
+        info!(self.log, "set read replicas"; "view" => &view, "replicas" => replicas);
+        Ok(())
+    }
+
+    fn base_keys(
+        &mut self,
+        (base, columns): (String, Vec<usize>),
+    ) -> Result<Vec<Vec<DataType>>, String> {
+        let ni = self.base_node(&base)?;
+        let node = &self.ingredients[ni];
+        if let Some(&c) = columns.iter().find(|&&c| c >= node.fields().len()) {
+            return Err(format!("base table '{}' has no column {}", base, c));
+        }
+        let (di, na) = (node.domain(), node.local_addr());
+
+        let workers = &self.workers;
+        let replies = &mut self.replies;
+        let domain = self.domains.get_mut(&di).unwrap();
+        domain
+            .send_to_healthy(Box::new(Packet::GetBaseKeys { node: na, columns }), workers)
+            .map_err(|e| format!("failed to request base keys: {:?}", e))?;
+
+        let mut keys = Vec::new();
+        let shards = futures_executor::block_on(replies.wait_for_base_keys(&domain))
+            .map_err(|e| format!("failed to fetch base keys: {}", e))?;
+        for ks in shards {
+            match ks {
+                Some(ks) => keys.extend(ks),
+                None => return Err(format!("base table '{}' is not materialized", base)),
+            }
+        }
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
+    }
+
+    /// Have every domain with base nodes hold on to the writes clients send from now on, and
+    /// return the commit sequence numbers of each shard of each base once it does.
+    fn hold_writes(&mut self) -> Result<Vec<(NodeIndex, usize, u64)>, String> {
+        let domains: HashSet<_> = self
+            .ingredients
+            .neighbors_directed(self.source, petgraph::EdgeDirection::Outgoing)
+            .filter(|&ni| !self.ingredients[ni].is_dropped())
+            .map(|ni| self.ingredients[ni].domain())
+            .collect();
+
+        let mut watermark = Vec::new();
+        for di in domains {
+            let domain = self.domains.get_mut(&di).unwrap();
+            domain
+                .send_to_healthy(Box::new(Packet::HoldWrites { hold: true }), &self.workers)
+                .map_err(|e| format!("failed to hold writes to domain {}: {:?}", di.index(), e))?;
+            let seqs = futures_executor::block_on(self.replies.wait_for_drained(&domain))
+                .map_err(|e| format!("failed to hold writes to domain {}: {}", di.index(), e))?;
+            for (shard, seqs) in seqs.into_iter().enumerate() {
+                watermark.extend(seqs.into_iter().map(|(base, seq)| (base, shard, seq)));
+            }
+        }
+        Ok(watermark)
+    }
+
+    /// Have every domain handle the writes it held since `hold_writes`.
+    fn release_writes(&mut self) {
+        let domains: HashSet<_> = self
+            .ingredients
+            .neighbors_directed(self.source, petgraph::EdgeDirection::Outgoing)
+            .filter(|&ni| !self.ingredients[ni].is_dropped())
+            .map(|ni| self.ingredients[ni].domain())
+            .collect();
+        for di in domains {
+            let domain = self.domains.get_mut(&di).unwrap();
+            let m = Box::new(Packet::HoldWrites { hold: false });
+            let res = match domain.send_to_healthy(m, &self.workers) {
+                Ok(()) => futures_executor::block_on(self.replies.wait_for_acks(domain))
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(format!("{:?}", e)),
+            };
+            if let Err(e) = res {
+                warn!(
+                    self.log,
+                    "failed to release held writes";
+                    "domain" => di.index(),
+                    "err" => e,
+                );
+            }
+        }
+    }
+
+    /// Wait until every update sent before writes were held has been applied by every domain.
+    ///
+    /// Each domain passes a `SettleMarker` on to the domains it sends updates to once it has
+    /// heard from every domain that sends updates to it, starting from those with no domains
+    /// above them.
+    fn settle_domains(&mut self) -> Result<(), String> {
+        let domain_of = |ni: NodeIndex| {
+            let n = &self.ingredients[ni];
+            if n.is_source() || n.is_dropped() || !self.domains.contains_key(&n.domain()) {
+                None
+            } else {
+                Some(n.domain())
+            }
+        };
+        let mut below: HashMap<DomainIndex, HashSet<DomainIndex>> = HashMap::new();
+        let edges = self
+            .ingredients
+            .raw_edges()
+            .iter()
+            .map(|e| (e.source(), e.target()))
+            // cascades send writes straight to their child tables
+            .chain(self.ingredients.node_indices().filter_map(|ni| {
+                let c = self.ingredients[ni].get_cascade()?;
+                Some((ni, c.child()))
+            }));
+        for (from, to) in edges {
+            match (domain_of(from), domain_of(to)) {
+                (Some(from), Some(to)) if from != to => {
+                    below.entry(from).or_default().insert(to);
+                }
+                _ => {}
+            }
+        }
+
+        let mut expected: HashMap<DomainIndex, usize> = HashMap::new();
+        for (from, to) in &below {
+            for to in to {
+                *expected.entry(*to).or_default() += self.domains[from].shards();
+            }
+        }
+        let dis: Vec<_> = self.domains.keys().cloned().collect();
+        for &di in &dis {
+            let downstream = below
+                .get(&di)
+                .into_iter()
+                .flatten()
+                .flat_map(|&to| (0..self.domains[&to].shards()).map(move |s| (to, s)))
+                .collect();
+            let m = Box::new(Packet::Settle {
+                expected: expected.get(&di).cloned().unwrap_or(0),
+                downstream,
+            });
+            self.domains
+                .get_mut(&di)
+                .unwrap()
+                .send_to_healthy(m, &self.workers)
+                .map_err(|e| format!("failed to settle domain {}: {:?}", di.index(), e))?;
+        }
+        // the acknowledgements may arrive in any order, but they are all alike
+        for di in dis {
+            futures_executor::block_on(self.replies.wait_for_acks(&self.domains[&di]))
+                .map_err(|e| format!("failed to settle domain {}: {}", di.index(), e))?;
+        }
+        Ok(())
+    }
+
+    /// Have each shard of the reader `r` send the rows it holds to the base `to`, and wait for
+    /// them to have been inserted.
+    fn copy_reader_rows(&mut self, r: NodeIndex, to: NodeIndex) -> Result<(), String> {
+        let (di, node) = (
+            self.ingredients[r].domain(),
+            self.ingredients[r].local_addr(),
+        );
+        let m = Box::new(Packet::CopyReaderRows {
+            node,
+            domain: self.ingredients[to].domain(),
+            to: self.ingredients[to].local_addr(),
+        });
+
+        let domain = self.domains.get_mut(&di).unwrap();
+        domain
+            .send_to_healthy(m, &self.workers)
+            .map_err(|e| format!("failed to copy reader rows: {:?}", e))?;
+        futures_executor::block_on(self.replies.wait_for_acks(&domain))
+            .map_err(|e| format!("failed to copy reader rows: {}", e))?;
+        let shards = domain.shards();
+
+        // the copy acknowledges the rows of each shard as it inserts them
+        let copy = &self.domains[&self.ingredients[to].domain()];
+        for _ in 0..shards {
+            futures_executor::block_on(self.replies.wait_for_shard_ack(copy))
+                .map_err(|e| format!("failed to insert reader rows: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Copy the current contents of every view into a new view named `<view>@<name>`, and return
+    /// the names of the copies.
+    ///
+    /// The copies are fed by base nodes of their own, which are placed in new domains and which
+    /// clients cannot write to, so reads from them do not compete with the live views. Views that
+    /// keep only counts, or that are keyed on computed values, are not copied. Partially
+    /// materialized views are copied with the keys they currently hold. Snapshots are not
+    /// recovered if the controller fails over.
+    ///
+    /// All the copies reflect the same writes: clients' writes are held while they are taken,
+    /// after every write accepted before that has reached the views. The readers send their rows
+    /// to the copies themselves.
+    fn fork_snapshot(&mut self, name: String) -> Result<Vec<String>, String> {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("'{}' is not a valid snapshot name", name));
+        }
+        if self.snapshots.contains_key(&name) {
+            return Err(format!("snapshot '{}' already exists", name));
+        }
+
+        // snapshots are not copied again
+        let copied: HashSet<_> = self.snapshots.values().flatten().cloned().collect();
+        let mut views = Vec::new();
+        for (view, ni) in self.outputs() {
+            if copied.contains(&ni) {
+                continue;
+            }
+            let r = match self.find_view_for(ni, &view) {
+                Some(r) => r,
+                None => continue,
+            };
+            let key = match self.ingredients[r]
+                .with_reader(|r| {
+                    if r.is_count_only() || !r.key_expressions().is_empty() {
+                        None
+                    } else {
+                        r.key().map(Vec::from)
+                    }
+                })
+                .unwrap()
+            {
+                Some(key) => key,
+                None => continue,
+            };
+            let fields = self.ingredients[r].fields().to_vec();
+            views.push((format!("{}@{}", view, name), fields, key, r));
+        }
+
+        let bases = self
+            .migrate(|mig| {
+                views
+                    .iter()
+                    .map(|(copy, fields, key, _)| {
+                        let base = mig.add_base(copy, fields, node::special::Base::default());
+                        mig.maintain(copy.clone(), base, key);
+                        base
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .map_err(|e| format!("failed to add snapshot tables: {}", e))?;
+
+        // whatever happens, clients' writes must not stay held
+        let watermark = self.hold_writes();
+        let copied = watermark.and_then(|watermark| {
+            self.settle_domains()?;
+            for (&base, (copy, _, _, r)) in bases.iter().zip(views.iter()) {
+                // the base is not keyed, so its first shard can hold all of its rows
+                self.copy_reader_rows(*r, base)
+                    .map_err(|e| format!("failed to fill {}: {}", copy, e))?;
+            }
+            Ok(watermark)
+        });
+        self.release_writes();
+        let watermark = copied?;
+
+        info!(self.log, "forked snapshot"; "snapshot" => &name, "views" => views.len());
+        for (base, shard, seq) in watermark {
+            debug!(
+                self.log,
+                "snapshot taken after commit {} of {}.{}", seq, base.index(), shard;
+                "snapshot" => &name,
+            );
+        }
+        self.snapshots.insert(name, bases);
+        Ok(views.into_iter().map(|(copy, _, _, _)| copy).collect())
     }
 
-    /// Obtain a `ViewBuilder` that can be sent to a client and then used to query a given
-    /// (already maintained) reader node called `name`.
-    fn view_builder(&self, name: &str) -> Option<ViewBuilder> {
-        // first try to resolve the node via the recipe, which handles aliasing between identical
-        // queries.
-        let node = match self.recipe.node_addr_for(name) {
-            Ok(ni) => ni,
-            Err(_) => {
-                // if the recipe doesn't know about this query, traverse the graph.
-                // we need this do deal with manually constructed graphs (e.g., in tests).
-                *self.outputs().get(name)?
+    /// Remove the views of the snapshot `name`.
+    fn drop_snapshot(&mut self, name: String) -> Result<(), String> {
+        let bases = self
+            .snapshots
+            .remove(&name)
+            .ok_or_else(|| format!("no snapshot named '{}'", name))?;
+        for base in bases {
+            let copy = self.ingredients[base].name().to_owned();
+            if let Some(r) = self.find_view_for(base, &copy) {
+                self.remove_leaf(r)?;
             }
-        };
+            self.remove_nodes(&[base])?;
+        }
+        Ok(())
+    }
 
-        let name = match self.recipe.resolve_alias(name) {
-            None => name,
-            Some(alias) => alias,
-        };
-        self.find_view_for(node, name).map(|r| {
-            let domain = self.ingredients[r].domain();
-            let columns = self.ingredients[r].fields().to_vec();
-            let schema = self.view_schema(r);
-            let shards = (0..self.domains[&domain].shards())
-                .map(|i| self.read_addrs[&self.domains[&domain].assignment(i)])
-                .collect();
+    /// Check that `view` is partially materialized, and so can be paused.
+    fn check_pausable(&self, view: &str) -> Result<(), String> {
+        let r = self
+            .reader_for(view)
+            .ok_or_else(|| format!("no view named '{}'", view))?;
+        match self.materializations.get_status(r, &self.ingredients[r]) {
+            MaterializationStatus::Partial { .. } => Ok(()),
+            // there is no way to bring a full materialization up to date again short of
+            // rebuilding it from scratch.
+            _ => Err(format!(
+                "view '{}' is not partially materialized, and cannot be paused",
+                view
+            )),
+        }
+    }
 
-            ViewBuilder {
-                node: r,
-                columns,
-                schema,
-                shards,
-            }
-        })
+    /// Send a control packet to the domain of the reader for `view`, and wait for it to be
+    /// acknowledged.
+    fn send_to_reader<F>(&mut self, view: &str, packet: F) -> Result<(), String>
+    where
+        F: FnOnce(LocalNodeIndex) -> Packet,
+    {
+        let r = self
+            .reader_for(view)
+            .ok_or_else(|| format!("no view named '{}'", view))?;
+        let m = packet(self.ingredients[r].local_addr());
+        let workers = &self.workers;
+        let replies = &mut self.replies;
+        let domain = self.domains.get_mut(&self.ingredients[r].domain()).unwrap();
+        domain
+            .send_to_healthy(Box::new(m), workers)
+            .map_err(|e| format!("failed to reach view '{}': {:?}", view, e))?;
+        futures_executor::block_on(replies.wait_for_acks(&domain))
+            .map_err(|e| format!("view '{}' did not respond: {}", view, e))
     }
 
-    fn view_schema(&self, view_ni: NodeIndex) -> Option<Vec<ColumnSpecification>> {
-        let n = &self.ingredients[view_ni];
-        let schema: Vec<_> = (0..n.fields().len())
-            .map(|i| schema::column_schema(&self.ingredients, view_ni, &self.recipe, i, &self.log))
-            .collect();
+    /// Stop maintaining the view called `view` until `resume_view` is called.
+    ///
+    /// Reads of keys that were present when the view was paused return their state as of that
+    /// moment, unless `drop_state` is set, in which case all of the view's state is evicted.
+    fn pause_view(&mut self, (view, drop_state): (String, bool)) -> Result<(), String> {
+        self.check_pausable(&view)?;
+        self.send_to_reader(&view, |node| Packet::PauseReader { node, drop_state })?;
+        info!(self.log, "paused view"; "view" => &view, "dropped" => drop_state);
+        Ok(())
+    }
 
-        if schema.iter().any(Option::is_none) {
-            None
-        } else {
-            Some(schema.into_iter().map(Option::unwrap).collect())
-        }
+    /// Resume maintaining the view called `view`.
+    ///
+    /// Any state the view kept while it was paused is stale, and is evicted. It is brought up to
+    /// date again through replays as it is read.
+    fn resume_view(&mut self, view: String) -> Result<(), String> {
+        self.check_pausable(&view)?;
+        self.send_to_reader(&view, |node| Packet::ResumeReader { node })?;
+        info!(self.log, "resumed view"; "view" => &view);
+        Ok(())
     }
 
-    /// Obtain a TableBuild that can be used to construct a Table to perform writes and deletes
-    /// from the given named base node.
-    fn table_builder(&self, base: &str) -> Option<TableBuilder> {
-        let ni = match self.recipe.node_addr_for(base) {
-            Ok(ni) => ni,
-            Err(_) => *self.inputs().get(base)?,
+    /// Set the priority of upqueries made to fill holes in the view called `view`.
+    fn set_replay_priority(
+        &mut self,
+        (view, priority): (String, ReplayPriority),
+    ) -> Result<(), String> {
+        self.send_to_reader(&view, |node| Packet::SetReplayPriority { node, priority })?;
+        let r = self.reader_for(&view).unwrap();
+        self.reader_settings.entry(r).or_default().priority = priority;
+        info!(self.log, "set replay priority"; "view" => &view, "priority" => ?priority);
+        Ok(())
+    }
+
+    /// Start or stop attributing the reads of the view called `view` to client identities.
+    fn set_read_attribution(&mut self, (view, enabled): (String, bool)) -> Result<(), String> {
+        self.send_to_reader(&view, |node| Packet::SetReadAttribution { node, enabled })?;
+        let r = self.reader_for(&view).unwrap();
+        self.reader_settings.entry(r).or_default().attribution = enabled;
+        info!(self.log, "set read attribution"; "view" => &view, "enabled" => enabled);
+        Ok(())
+    }
+
+    /// The reads of the view called `view` by each client identity, most active first.
+    fn read_attribution(&mut self, view: String) -> Result<Vec<ReadAttribution>, String> {
+        let r = self
+            .reader_for(&view)
+            .ok_or_else(|| format!("no view named '{}'", view))?;
+        let m = Packet::GetReadAttribution {
+            node: self.ingredients[r].local_addr(),
         };
-        let node = &self.ingredients[ni];
+        let workers = &self.workers;
+        let replies = &mut self.replies;
+        let domain = self.domains.get_mut(&self.ingredients[r].domain()).unwrap();
+        domain
+            .send_to_healthy(Box::new(m), workers)
+            .map_err(|e| format!("failed to reach view '{}': {:?}", view, e))?;
+        futures_executor::block_on(replies.wait_for_read_attribution(&domain))
+            .map_err(|e| format!("failed to fetch read attribution of '{}': {}", view, e))
+    }
 
-        trace!(self.log, "creating table"; "for" => base);
+    /// Send a fingerprinting request for the reader of `view` to all of its shards, and collect
+    /// the fingerprints they report.
+    fn fingerprint_reader<F>(
+        &mut self,
+        view: &str,
+        m: F,
+    ) -> Result<HashMap<Vec<DataType>, Option<u64>>, String>
+    where
+        F: FnOnce(LocalNodeIndex) -> Packet,
+    {
+        let r = self.consistency_reader(view)?;
+        let m = m(self.ingredients[r].local_addr());
+        let workers = &self.workers;
+        let replies = &mut self.replies;
+        let domain = self.domains.get_mut(&self.ingredients[r].domain()).unwrap();
+        domain
+            .send_to_healthy(Box::new(m), workers)
+            .map_err(|e| format!("failed to reach reader: {:?}", e))?;
+        futures_executor::block_on(replies.wait_for_fingerprints(&domain))
+            .map_err(|e| format!("failed to fingerprint reader: {}", e))
+    }
 
-        let mut key = self.ingredients[ni]
-            .suggest_indexes(ni)
-            .remove(&ni)
-            .unwrap_or_else(Vec::new);
-        let mut is_primary = false;
-        if key.is_empty() {
-            if let Sharding::ByColumn(col, _) = self.ingredients[ni].sharded_by() {
-                key = vec![col];
+    /// The reader of the view called `view`, if that view can be checked for consistency.
+    pub(super) fn consistency_reader(&self, view: &str) -> Result<NodeIndex, String> {
+        let r = self
+            .reader_for(view)
+            .ok_or_else(|| format!("no view named '{}'", view))?;
+        match self.materializations.get_status(r, &self.ingredients[r]) {
+            MaterializationStatus::Partial { .. } => Ok(r),
+            _ => {
+                // evicting keys from a full materialization would lose them for good.
+                Err(format!(
+                    "view '{}' is not partially materialized, and cannot be checked",
+                    view
+                ))
             }
-        } else {
-            is_primary = true;
         }
+    }
 
-        let txs = (0..self.domains[&node.domain()].shards())
-            .map(|i| {
-                self.channel_coordinator
-                    .get_addr(&(node.domain(), i))
-                    .unwrap()
-            })
-            .collect();
+    /// Fingerprint the given keys of the view `view` (and up to `count` random others from each
+    /// shard), and evict them so that they are recomputed from upstream.
+    pub(super) fn sample_reader_keys(
+        &mut self,
+        view: &str,
+        count: usize,
+        keys: Vec<Vec<DataType>>,
+    ) -> Result<HashMap<Vec<DataType>, Option<u64>>, String> {
+        self.fingerprint_reader(view, |node| Packet::SampleReaderKeys { node, count, keys })
+    }
 
-        let base_operator = node
-            .get_base()
-            .expect("asked to get table for non-base node");
-        let columns: Vec<String> = node
-            .fields()
-            .iter()
-            .enumerate()
-            .filter(|&(n, _)| !base_operator.get_dropped().contains_key(n))
-            .map(|(_, s)| s.clone())
-            .collect();
-        assert_eq!(
-            columns.len(),
-            node.fields().len() - base_operator.get_dropped().len()
-        );
-        let schema = self.recipe.schema_for(base).map(|s| match s {
-            Schema::Table(s) => s,
-            _ => panic!("non-base schema {:?} returned for table '{}'", s, base),
-        });
+    /// Fingerprint the given keys of the view `view`, or `None` for keys that are missing.
+    pub(super) fn fingerprint_reader_keys(
+        &mut self,
+        view: &str,
+        keys: Vec<Vec<DataType>>,
+    ) -> Result<HashMap<Vec<DataType>, Option<u64>>, String> {
+        self.fingerprint_reader(view, |node| Packet::FingerprintReaderKeys { node, keys })
+    }
 
-        Some(TableBuilder {
-            txs,
-            ni: node.global_addr(),
-            addr: node.local_addr(),
-            key,
-            key_is_primary: is_primary,
-            dropped: base_operator.get_dropped(),
-            table_name: node.name().to_owned(),
-            columns,
-            schema,
-        })
+    /// Remember the result of a consistency check.
+    pub(super) fn record_consistency_event(&mut self, event: ConsistencyEvent) {
+        if self.consistency_events.len() == MAX_CONSISTENCY_EVENTS {
+            self.consistency_events.pop_front();
+        }
+        self.consistency_events.push_back(event);
     }
 
-    /// Get statistics about the time spent processing different parts of the graph.
-    fn get_statistics(&mut self) -> GraphStats {
-        trace!(self.log, "asked to get statistics");
-        let log = &self.log;
-        let workers = &self.workers;
-        let replies = &mut self.replies;
-        // TODO: request stats from domains in parallel.
-        let domains = self
-            .domains
-            .iter_mut()
-            .flat_map(|(&di, s)| {
-                trace!(log, "requesting stats from domain"; "di" => di.index());
-                s.send_to_healthy(Box::new(Packet::GetStatistics), workers)
-                    .unwrap();
-                futures_executor::block_on(replies.wait_for_statistics(&s))
-                    .into_iter()
-                    .enumerate()
-                    .map(move |(i, s)| ((di, i), s))
-            })
+    /// The partially materialized views to check, and how many keys to sample from each shard,
+    /// if a periodic consistency check is due.
+    pub(super) fn due_consistency_checks(&mut self) -> Option<(Vec<String>, usize)> {
+        let (every, samples) = self.consistency_check?;
+        let since = self.clock.now().duration_since(self.last_consistency_check);
+        if !self.is_available() || since < every {
+            return None;
+        }
+        self.last_consistency_check = self.clock.now();
+
+        let views = self
+            .outputs()
+            .into_iter()
+            .map(|(view, _)| view)
+            .filter(|view| self.consistency_reader(view).is_ok())
             .collect();
+        Some((views, samples))
+    }
+
+    /// The base tables to verify, if a periodic verification of them is due.
+    pub(super) fn due_base_verifications(&mut self) -> Option<Vec<String>> {
+        let every = self.base_verification?;
+        let since = self.clock.now().duration_since(self.last_base_verification);
+        if !self.is_available() || since < every {
+            return None;
+        }
+        self.last_base_verification = self.clock.now();
+        Some(self.inputs().into_iter().map(|(base, _)| base).collect())
+    }
 
-        GraphStats { domains }
+    /// Whether the controller has recovered and has enough workers to serve requests.
+    pub(super) fn is_available(&self) -> bool {
+        self.pending_recovery.is_none() && self.workers.len() >= self.quorum
     }
 
     fn get_instances(&self) -> Vec<(WorkerIdentifier, bool, Duration)> {
+        let now = self.clock.now();
         self.workers
             .iter()
-            .map(|(&id, ref status)| (id, status.healthy, status.last_heartbeat.elapsed()))
+            .map(|(&id, ref status)| {
+                (
+                    id,
+                    status.healthy,
+                    now.duration_since(status.last_heartbeat),
+                )
+            })
             .collect()
     }
 
     fn flush_partial(&mut self) -> u64 {
         // get statistics for current domain sizes
         // and evict all state from partial nodes
+        let log = &self.log;
         let workers = &self.workers;
         let replies = &mut self.replies;
         let to_evict: Vec<_> = self
@@ -905,6 +3691,10 @@ impl ControllerInner {
                     .unwrap();
                 let to_evict: Vec<(NodeIndex, u64)> =
                     futures_executor::block_on(replies.wait_for_statistics(&s))
+                        .unwrap_or_else(|e| {
+                            warn!(log, "not flushing domain"; "di" => di.index(), "err" => %e);
+                            Vec::new()
+                        })
                         .into_iter()
                         .flat_map(move |(_, node_stats)| {
                             node_stats
@@ -995,7 +3785,8 @@ impl ControllerInner {
                 }
             }
             .unwrap();
-        });
+        })
+        .map_err(|e| format!("failed to create universe: {}", e))?;
 
         self.recipe = r;
         Ok(())
@@ -1007,13 +3798,20 @@ impl ControllerInner {
     }
 
     fn apply_recipe(&mut self, mut new: Recipe) -> Result<ActivationResult, String> {
-        let r = self.migrate(|mig| {
-            new.activate(mig)
-                .map_err(|e| format!("failed to activate recipe: {}", e))
-        });
+        let nodes_before = self.live_nodes();
+        let domains_before: HashSet<_> = self.domains.keys().cloned().collect();
+        self.materializations.take_replayed();
+
+        let mut r = self
+            .migrate(|mig| {
+                new.activate(mig)
+                    .map_err(|e| format!("failed to activate recipe: {}", e))
+            })
+            .map_err(|e| format!("failed to migrate: {}", e))
+            .and_then(|r| r);
 
         match r {
-            Ok(ref ra) => {
+            Ok(ref mut ra) => {
                 let (removed_bases, removed_other): (Vec<_>, Vec<_>) = ra
                     .removed_leaves
                     .iter()
@@ -1054,7 +3852,20 @@ impl ControllerInner {
                     self.remove_nodes(vec![base].as_slice()).unwrap();
                 }
 
+                let priorities = new.changed_replay_priorities();
                 self.recipe = new;
+
+                for (view, priority) in priorities {
+                    if let Err(e) = self.set_replay_priority((view, priority)) {
+                        warn!(
+                            self.log,
+                            "failed to apply replay priority from recipe: {}", e
+                        );
+                    }
+                }
+                self.apply_read_masks();
+
+                ra.dataflow = self.dataflow_diff(&nodes_before, &domains_before, &ra.new_nodes);
             }
             Err(ref e) => {
                 crit!(self.log, "failed to apply recipe: {}", e);
@@ -1067,15 +3878,160 @@ impl ControllerInner {
         r
     }
 
+    /// All nodes in the graph that have not been removed, other than the source.
+    fn live_nodes(&self) -> HashSet<NodeIndex> {
+        self.ingredients
+            .node_indices()
+            .filter(|&ni| ni != self.source && !self.ingredients[ni].is_dropped())
+            .collect()
+    }
+
+    /// Describe how the graph changed since it consisted of `nodes_before` and `domains_before`,
+    /// given the query leaves that the change produced.
+    fn dataflow_diff(
+        &mut self,
+        nodes_before: &HashSet<NodeIndex>,
+        domains_before: &HashSet<DomainIndex>,
+        leaves: &HashMap<String, NodeIndex>,
+    ) -> DataflowDiff {
+        let nodes_after = self.live_nodes();
+        let mut nodes_created: Vec<_> = nodes_after.difference(nodes_before).cloned().collect();
+        let mut nodes_removed: Vec<_> = nodes_before.difference(&nodes_after).cloned().collect();
+        nodes_created.sort();
+        nodes_removed.sort();
+
+        // new nodes build on the existing nodes they are attached to, and queries that existed
+        // in full are reused as they are
+        let mut nodes_reused: Vec<_> = nodes_created
+            .iter()
+            .flat_map(|&ni| {
+                self.ingredients
+                    .neighbors_directed(ni, petgraph::EdgeDirection::Incoming)
+            })
+            .chain(leaves.values().cloned())
+            .filter(|ni| nodes_before.contains(ni) && nodes_after.contains(ni))
+            .collect();
+        nodes_reused.sort();
+        nodes_reused.dedup();
+
+        let mut domains_created: Vec<_> = self
+            .domains
+            .keys()
+            .filter(|di| !domains_before.contains(di))
+            .map(|di| di.index())
+            .collect();
+        domains_created.sort();
+
+        // joins that carry all of their inputs' columns emit all but the right-hand join column
+        let pruned_columns = nodes_created
+            .iter()
+            .filter(|&&ni| self.ingredients[ni].is_internal() && self.ingredients[ni].is_join())
+            .filter_map(|&ni| {
+                let n = &self.ingredients[ni];
+                let carried: usize = n
+                    .ancestors()
+                    .into_iter()
+                    .map(|p| self.ingredients[p].fields().len())
+                    .sum();
+                match carried.saturating_sub(1 + n.fields().len()) {
+                    0 => None,
+                    pruned => Some((ni, pruned)),
+                }
+            })
+            .collect();
+
+        let (partial, full): (Vec<_>, Vec<_>) = self
+            .materializations
+            .take_replayed()
+            .into_iter()
+            .partition(|&(_, partial)| partial);
+
+        DataflowDiff {
+            nodes_created,
+            nodes_reused,
+            nodes_removed,
+            domains_created,
+            full_replays: full.into_iter().map(|(ni, _)| ni).collect(),
+            partial_replays: partial.into_iter().map(|(ni, _)| ni).collect(),
+            pruned_columns,
+        }
+    }
+
+    /// Check that the base tables that `new` adds to its prior recipe fit within the disk quotas
+    /// of the healthy workers.
+    fn check_disk_quota(&self, new: &Recipe) -> Result<(), String> {
+        if self.persistence.mode == DurabilityMode::MemoryOnly {
+            return Ok(());
+        }
+
+        let tables = |r: &Recipe| -> HashSet<String> {
+            r.expressions()
+                .into_iter()
+                .filter_map(|(_, q)| match *q {
+                    SqlQuery::CreateTable(ref ct) => Some(ct.table.name.clone()),
+                    _ => None,
+                })
+                .collect()
+        };
+        let mut added = tables(new);
+        if let Some(prior) = new.prior() {
+            for t in tables(prior) {
+                added.remove(&t);
+            }
+        }
+        if added.is_empty() {
+            return Ok(());
+        }
+
+        let shards = added.len() as u64 * self.sharding.unwrap_or(1) as u64;
+        let mut room = 0u64;
+        for w in self.workers.values().filter(|w| w.healthy) {
+            match w.disk_headroom(self.base_disk_reservation) {
+                None => return Ok(()),
+                Some(n) => room += n,
+            }
+        }
+        if room < shards {
+            return Err(format!(
+                "adding {} base table shards would exceed the disk quota of the workers",
+                shards
+            ));
+        }
+        Ok(())
+    }
+
+    /// Make sure the authority can be reached before starting a migration.
+    ///
+    /// A migration whose outcome cannot be persisted would be lost on the next leader change, so
+    /// while the authority is away we keep serving the current dataflow but refuse to change it.
+    fn check_authority<A: Authority + 'static>(&self, authority: &Arc<A>) -> Result<(), String> {
+        authority.try_read(STATE_KEY).map(|_| ()).map_err(|e| {
+            format!(
+                "refusing to migrate while the authority is unreachable: {}",
+                e
+            )
+        })
+    }
+
     fn extend_recipe<A: Authority + 'static>(
         &mut self,
         authority: &Arc<A>,
         add_txt: String,
     ) -> Result<ActivationResult, String> {
+        if let Err(e) = self.check_authority(authority) {
+            crit!(self.log, "{}", e);
+            return Err(e);
+        }
+
         // needed because self.apply_recipe needs to mutate self.recipe, so can't have it borrowed
         let new = mem::replace(&mut self.recipe, Recipe::blank(None));
         match new.extend(&add_txt) {
             Ok(new) => {
+                if let Err(e) = self.check_disk_quota(&new) {
+                    crit!(self.log, "refusing to extend recipe: {}", e);
+                    self.recipe = new.discard();
+                    return Err(e);
+                }
                 let activation_result = self.apply_recipe(new);
                 if authority
                     .read_modify_write(STATE_KEY, |state: Option<ControllerState>| match state {
@@ -1108,10 +4064,20 @@ impl ControllerInner {
         authority: &Arc<A>,
         r_txt: String,
     ) -> Result<ActivationResult, String> {
+        if let Err(e) = self.check_authority(authority) {
+            crit!(self.log, "{}", e);
+            return Err(e);
+        }
+
         match Recipe::from_str(&r_txt, Some(self.log.clone())) {
             Ok(r) => {
                 let old = mem::replace(&mut self.recipe, Recipe::blank(None));
                 let new = old.replace(r).unwrap();
+                if let Err(e) = self.check_disk_quota(&new) {
+                    crit!(self.log, "refusing to install recipe: {}", e);
+                    self.recipe = new.discard();
+                    return Err(e);
+                }
                 let activation_result = self.apply_recipe(new);
                 if authority
                     .read_modify_write(STATE_KEY, |state: Option<ControllerState>| match state {
@@ -1145,6 +4111,19 @@ impl ControllerInner {
         let start = leaf;
         assert!(!self.ingredients[leaf].is_source());
 
+        // the read replicas of the leaf's view go first, since they hang off the leaf too
+        let replicas: Vec<_> = self
+            .read_replicas
+            .keys()
+            .cloned()
+            .filter(|&r| r == leaf || self.ingredients[r].with_reader(|r| r.is_for()) == Ok(leaf))
+            .collect();
+        for r in replicas {
+            while self.read_replicas.contains_key(&r) {
+                self.remove_read_replica(r)?;
+            }
+        }
+
         info!(
             self.log,
             "Computing removals for removing node {}",
@@ -1236,8 +4215,18 @@ impl ControllerInner {
     fn remove_nodes(&mut self, removals: &[NodeIndex]) -> Result<(), String> {
         // Remove node from controller local state
         let mut domain_removals: HashMap<DomainIndex, Vec<LocalNodeIndex>> = HashMap::default();
+        let mut egress_removals: HashMap<NodeIndex, Vec<NodeIndex>> = HashMap::default();
         for ni in removals {
+            if self.ingredients[*ni].is_ingress() {
+                for egress in self
+                    .ingredients
+                    .neighbors_directed(*ni, petgraph::EdgeDirection::Incoming)
+                {
+                    egress_removals.entry(egress).or_default().push(*ni);
+                }
+            }
             self.ingredients[*ni].remove();
+            self.reader_settings.remove(ni);
             debug!(self.log, "Removed node {}", ni.index());
             domain_removals
                 .entry(self.ingredients[*ni].domain())
@@ -1245,6 +4234,50 @@ impl ControllerInner {
                 .push(self.ingredients[*ni].local_addr())
         }
 
+        // Domains that are left without nodes are torn down, but not before they have applied and
+        // acknowledged every write they accepted, since those would otherwise be lost.
+        let retiring: Vec<_> = domain_removals
+            .keys()
+            .cloned()
+            .filter(|&di| {
+                !self.ingredients.node_indices().any(|ni| {
+                    ni != self.source
+                        && !self.ingredients[ni].is_dropped()
+                        && self.ingredients[ni].domain() == di
+                })
+            })
+            .filter(|&di| self.domain_is_healthy(di))
+            .collect();
+        for &di in &retiring {
+            // a shard that failed holds no writes, and does not reply
+            if !self.domains[&di].has_failed_shards() {
+                self.drain_domain(di)?;
+            }
+        }
+
+        // Stop upstream domains from sending to ingress nodes that are going away
+        for (egress, targets) in egress_removals {
+            if !self.ingredients[egress].is_egress() {
+                // either removed as well, or a sharder
+                continue;
+            }
+
+            let di = self.ingredients[egress].domain();
+            if !self.domain_is_healthy(di) || self.domains[&di].has_failed_shards() {
+                continue;
+            }
+            let node = self.ingredients[egress].local_addr();
+            let domain = self.domains.get_mut(&di).unwrap();
+            domain
+                .send_to_healthy(
+                    Box::new(Packet::RemoveEgressTargets { node, targets }),
+                    &self.workers,
+                )
+                .map_err(|e| format!("failed to remove egress targets: {:?}", e))?;
+            futures_executor::block_on(self.replies.wait_for_acks(&domain))
+                .map_err(|e| format!("failed to remove egress targets: {}", e))?;
+        }
+
         // Send messages to domains
         for (domain, nodes) in domain_removals {
             trace!(
@@ -1277,14 +4310,68 @@ impl ControllerInner {
             }
         }
 
+        for di in retiring {
+            info!(self.log, "tearing down drained domain {}", di.index());
+            let mut domain = self.domains.remove(&di).unwrap();
+            domain
+                .send_to_healthy(Box::new(Packet::Quit), &self.workers)
+                .map_err(|e| format!("failed to tear down domain {}: {:?}", di.index(), e))?;
+            self.domain_nodes.remove(&di);
+            self.routes.retain(|&(d, _), _| d != di);
+            self.reported_statistics.retain(|&(d, _), _| d != di);
+            if domain.uses_disk {
+                for shard in 0..domain.shards() {
+                    if let Some(w) = self.workers.get_mut(&domain.assignment(shard)) {
+                        w.base_shards = w.base_shards.saturating_sub(1);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether every shard of the given domain is on a healthy worker.
+    fn domain_is_healthy(&self, di: DomainIndex) -> bool {
+        let domain = &self.domains[&di];
+        (0..domain.shards()).all(|i| self.workers[&domain.assignment(i)].healthy)
+    }
+
+    /// Have every shard of a domain stop accepting writes, and apply and acknowledge the writes
+    /// it has already accepted.
+    ///
+    /// Clients whose writes arrive after this see their connection to the domain close rather
+    /// than an acknowledgement.
+    fn drain_domain(&mut self, di: DomainIndex) -> Result<(), String> {
+        let domain = self.domains.get_mut(&di).unwrap();
+        domain
+            .send_to_healthy(Box::new(Packet::Drain), &self.workers)
+            .map_err(|e| format!("failed to drain domain {}: {:?}", di.index(), e))?;
+        let seqs = futures_executor::block_on(self.replies.wait_for_drained(&domain))
+            .map_err(|e| format!("failed to drain domain {}: {}", di.index(), e))?;
+        for (shard, seqs) in seqs.into_iter().enumerate() {
+            for (base, seq) in seqs {
+                info!(
+                    self.log,
+                    "drained domain {}.{}", di.index(), shard;
+                    "base" => base.index(),
+                    "commit_seq" => seq,
+                );
+            }
+        }
         Ok(())
     }
 
     fn get_failed_nodes(&self, lost_worker: &WorkerIdentifier) -> Vec<NodeIndex> {
         // Find nodes directly impacted by worker failure.
-        let mut nodes: Vec<NodeIndex> = self.nodes_on_worker(Some(lost_worker));
+        let nodes: Vec<NodeIndex> = self.nodes_on_worker(Some(lost_worker));
 
         // Add any other downstream nodes.
+        self.with_downstream(nodes)
+    }
+
+    /// The given nodes, along with all the nodes downstream of them.
+    fn with_downstream(&self, mut nodes: Vec<NodeIndex>) -> Vec<NodeIndex> {
         let mut failed_nodes = Vec::new();
         while let Some(node) = nodes.pop() {
             failed_nodes.push(node);
@@ -92,6 +92,12 @@ pub fn assign(log: &Logger, graph: &mut Graph, topo_list: &[NodeIndex], ndomains
                 return next_domain();
             }
 
+            if n.with_reader(|r| r.is_replica()).unwrap_or(false) {
+                // read replicas are there to take reads off the domain of the view's reader, so
+                // they get domains of their own.
+                return next_domain();
+            }
+
             let any_parents = move |prime: &dyn Fn(&Node) -> bool,
                                     check: &dyn Fn(&Node) -> bool| {
                 let mut stack: Vec<_> = graph
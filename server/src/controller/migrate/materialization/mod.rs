@@ -7,11 +7,13 @@
 
 use crate::controller::domain_handle::DomainHandle;
 use crate::controller::{
-    inner::{graphviz, DomainReplies},
+    inner::{graphviz, DomainReplies, WaitError},
     keys,
 };
 use crate::controller::{Worker, WorkerIdentifier};
 use dataflow::prelude::*;
+use dataflow::telemetry;
+use noria::TraceContext;
 use petgraph;
 use petgraph::graph::NodeIndex;
 use slog::Logger;
@@ -56,6 +58,9 @@ pub(in crate::controller) struct Materializations {
     partial_enabled: bool,
     frontier_strategy: FrontierStrategy,
 
+    /// Nodes whose state has been set up through replay, and whether that state is partial.
+    replayed: Vec<(NodeIndex, bool)>,
+
     tag_generator: AtomicUsize,
 }
 
@@ -72,6 +77,8 @@ impl Materializations {
             partial_enabled: true,
             frontier_strategy: FrontierStrategy::None,
 
+            replayed: Vec::new(),
+
             tag_generator: AtomicUsize::default(),
         }
     }
@@ -90,6 +97,12 @@ impl Materializations {
     pub(in crate::controller) fn set_frontier_strategy(&mut self, f: FrontierStrategy) {
         self.frontier_strategy = f;
     }
+
+    /// The nodes whose state has been set up through replay since the last call, and whether
+    /// that state is partial.
+    pub(in crate::controller) fn take_replayed(&mut self) -> Vec<(NodeIndex, bool)> {
+        mem::replace(&mut self.replayed, Vec::new())
+    }
 }
 
 impl Materializations {
@@ -335,6 +348,14 @@ impl Materializations {
                 able = false;
             }
 
+            if graph[ni]
+                .with_reader(|r| !r.key_expressions().is_empty())
+                .unwrap_or(false)
+            {
+                warn!(self.log, "full because keyed on computed values"; "node" => ni.index());
+                able = false;
+            }
+
             // we are already fully materialized, so can't be made partial
             if !new.contains(&ni)
                 && self.added.get(&ni).map(|i| i.len()).unwrap_or(0)
@@ -476,7 +497,8 @@ impl Materializations {
     /// Commit to all materialization decisions since the last time `commit` was called.
     ///
     /// This includes setting up replay paths, adding new indices to existing materializations, and
-    /// populating new materializations.
+    /// populating new materializations. Fails if a domain stops replying to the controller along
+    /// the way, in which case the materializations may be only partly set up.
     #[allow(clippy::cognitive_complexity)]
     pub(super) fn commit(
         &mut self,
@@ -485,7 +507,8 @@ impl Materializations {
         domains: &mut HashMap<DomainIndex, DomainHandle>,
         workers: &HashMap<WorkerIdentifier, Worker>,
         replies: &mut DomainReplies,
-    ) {
+        trace: Option<TraceContext>,
+    ) -> Result<(), WaitError> {
         self.extend(graph, new);
 
         // check that we don't have fully materialized nodes downstream of partially materialized
@@ -806,8 +829,9 @@ impl Materializations {
                       "cols" => ?index_on);
                 let log = self.log.new(o!("node" => node.index()));
                 let log = mem::replace(&mut self.log, log);
-                self.setup(node, &mut index_on, graph, domains, workers, replies);
+                let setup = self.setup(node, &mut index_on, graph, domains, workers, replies);
                 mem::replace(&mut self.log, log);
+                setup?;
                 index_on.clear();
             } else {
                 use dataflow::payload::InitialState;
@@ -838,7 +862,7 @@ impl Materializations {
                 .unwrap_or_else(HashSet::new);
 
             let start = ::std::time::Instant::now();
-            self.ready_one(ni, &mut index_on, graph, domains, workers, replies);
+            self.ready_one(ni, &mut index_on, graph, domains, workers, replies, trace)?;
             let reconstructed = index_on.is_empty();
 
             // communicate to the domain in charge of a particular node that it should start
@@ -858,7 +882,7 @@ impl Materializations {
                     workers,
                 )
                 .unwrap();
-            futures_executor::block_on(replies.wait_for_acks(&domain));
+            futures_executor::block_on(replies.wait_for_acks(&domain))?;
             trace!(self.log, "node ready"; "node" => ni.index());
 
             if reconstructed {
@@ -870,6 +894,7 @@ impl Materializations {
         }
 
         self.added.clear();
+        Ok(())
     }
 
     /// Perform all operations necessary to bring any materializations for the given node up, and
@@ -882,7 +907,8 @@ impl Materializations {
         domains: &mut HashMap<DomainIndex, DomainHandle>,
         workers: &HashMap<WorkerIdentifier, Worker>,
         replies: &mut DomainReplies,
-    ) {
+        trace: Option<TraceContext>,
+    ) -> Result<(), WaitError> {
         let n = &graph[ni];
         let mut has_state = !index_on.is_empty();
 
@@ -900,7 +926,7 @@ impl Materializations {
             // a new base must be empty, so we can materialize it immediately
             info!(self.log, "no need to replay empty new base"; "node" => ni.index());
             assert!(!self.partial.contains(&ni));
-            return;
+            return Ok(());
         }
 
         // if this node doesn't need to be materialized, then we're done.
@@ -914,21 +940,25 @@ impl Materializations {
 
         if !has_state {
             debug!(self.log, "no need to replay non-materialized view"; "node" => ni.index());
-            return;
+            return Ok(());
         }
 
         // we have a parent that has data, so we need to replay and reconstruct
         info!(self.log, "beginning reconstruction of {:?}", n);
+        let span = telemetry::Span::start("replay", trace);
+        span.set("node", ni.index());
         let log = self.log.new(o!("node" => ni.index()));
         let log = mem::replace(&mut self.log, log);
-        self.setup(ni, index_on, graph, domains, workers, replies);
+        let setup = self.setup(ni, index_on, graph, domains, workers, replies);
         mem::replace(&mut self.log, log);
+        drop(span);
+        setup?;
 
         // NOTE: the state has already been marked ready by the replay completing, but we want to
         // wait for the domain to finish replay, which the ready executed by the outer commit()
         // loop does.
         index_on.clear();
-        return;
+        Ok(())
     }
 
     /// Reconstruct the materialized state required by the given (new) node through replay.
@@ -940,7 +970,7 @@ impl Materializations {
         domains: &mut HashMap<DomainIndex, DomainHandle>,
         workers: &HashMap<WorkerIdentifier, Worker>,
         replies: &mut DomainReplies,
-    ) {
+    ) -> Result<(), WaitError> {
         if index_on.is_empty() {
             // we must be reconstructing a Reader.
             // figure out what key that Reader is using
@@ -954,11 +984,13 @@ impl Materializations {
                 .unwrap();
         }
 
+        self.replayed.push((ni, self.partial.contains(&ni)));
+
         // construct and disseminate a plan for each index
         let pending = {
             let mut plan = plan::Plan::new(self, graph, ni, domains, workers);
             for index in index_on.drain() {
-                plan.add(index, replies);
+                plan.add(index, replies)?;
             }
             plan.finalize()
         };
@@ -992,7 +1024,8 @@ impl Materializations {
                "domain" => target.index(),
             );
 
-            futures_executor::block_on(replies.wait_for_acks(&domains[&target]));
+            futures_executor::block_on(replies.wait_for_acks(&domains[&target]))?;
         }
+        Ok(())
     }
 }
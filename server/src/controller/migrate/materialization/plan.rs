@@ -1,5 +1,5 @@
 use crate::controller::domain_handle::DomainHandle;
-use crate::controller::inner::{graphviz, DomainReplies};
+use crate::controller::inner::{graphviz, DomainReplies, WaitError};
 use crate::controller::keys;
 use crate::controller::{Worker, WorkerIdentifier};
 use dataflow::payload::{ReplayPathSegment, SourceSelection, TriggerEndpoint};
@@ -105,14 +105,20 @@ impl<'a> Plan<'a> {
     /// Finds the appropriate replay paths for the given index, and inform all domains on those
     /// paths about them. It also notes if any data backfills will need to be run, which is
     /// eventually reported back by `finalize`.
+    ///
+    /// Fails if a domain on the paths does not acknowledge its part of them.
     #[allow(clippy::cognitive_complexity)]
-    pub(super) fn add(&mut self, index_on: Vec<usize>, replies: &mut DomainReplies) {
+    pub(super) fn add(
+        &mut self,
+        index_on: Vec<usize>,
+        replies: &mut DomainReplies,
+    ) -> Result<(), WaitError> {
         if !self.partial && !self.paths.is_empty() {
             // non-partial views should not have one replay path per index. that would cause us to
             // replay several times, even though one full replay should always be sufficient.
             // we do need to keep track of the fact that there should be an index here though.
             self.tags.entry(index_on).or_default();
-            return;
+            return Ok(());
         }
 
         // inform domains about replay paths
@@ -278,9 +284,25 @@ impl<'a> Plan<'a> {
                                 //    aliased in dst. because of this, it should be the case that
                                 //    KeyShard == SameShard; if that were not the case, the value
                                 //    in dst.x should never have reached dst in the first place.
+                                //
+                                // a base whose shards own ranges of keys (which always has a
+                                // shuffle below it) is asked by the shard whose range the key is
+                                // in, rather than by the shard the key hashes to.
+                                let ranges = self.graph[segments[0].1[0].0]
+                                    .get_base()
+                                    .map(|b| b.shard_ranges().to_vec())
+                                    .unwrap_or_default();
+                                // keys are otherwise spread by the weights of the source's shards
+                                let weights = self
+                                    .domains
+                                    .get(&segments[0].0)
+                                    .map(|d| d.shard_weights.clone())
+                                    .unwrap_or_default();
                                 SourceSelection::KeyShard {
                                     key_i_to_shard: i,
                                     nshards: shards,
+                                    ranges,
+                                    weights,
                                 }
                             } else {
                                 // replay key != sharding key
@@ -358,7 +380,7 @@ impl<'a> Plan<'a> {
                 trace!(self.m.log, "telling domain about replay path"; "domain" => domain.index());
                 let ctx = self.domains.get_mut(&domain).unwrap();
                 ctx.send_to_healthy(setup, self.workers).unwrap();
-                futures_executor::block_on(replies.wait_for_acks(&ctx));
+                futures_executor::block_on(replies.wait_for_acks(&ctx))?;
             }
 
             if !self.partial {
@@ -370,6 +392,7 @@ impl<'a> Plan<'a> {
         }
 
         self.tags.entry(index_on).or_default().extend(tags);
+        Ok(())
     }
 
     /// Instructs the target node to set up appropriate state for any new indices that have been
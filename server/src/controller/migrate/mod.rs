@@ -20,9 +20,11 @@
 //!
 //! Beware, Here be dragons™
 
+use crate::controller::inner::WaitError;
 use crate::controller::ControllerInner;
 use dataflow::prelude::*;
-use dataflow::{node, prelude::Packet};
+use dataflow::{node, prelude::Packet, telemetry, ReadMask};
+use noria::KeyExpression;
 use std::collections::{HashMap, HashSet};
 use std::time::Instant;
 
@@ -39,6 +41,7 @@ mod sharding;
 pub(super) enum ColumnChange {
     Add(String, DataType),
     Drop(usize),
+    Widen(usize),
 }
 
 /// A `Migration` encapsulates a number of changes to the Soup data flow graph.
@@ -104,18 +107,28 @@ impl<'a> Migration<'a> {
         &mut self,
         name: S1,
         fields: FS,
-        b: node::special::Base,
+        mut b: node::special::Base,
     ) -> NodeIndex
     where
         S1: ToString,
         S2: ToString,
         FS: IntoIterator<Item = S2>,
     {
+        if let Some(capacity) = self.mainline.audit_capacity {
+            if !b.is_audited() {
+                b = b.with_audit(capacity);
+            }
+        }
+        let name = name.to_string();
+        if let Some(ranges) = self.mainline.shard_ranges.get(&name) {
+            b = b.with_shard_ranges(ranges.clone());
+        }
+
         // add to the graph
         let ni = self
             .mainline
             .ingredients
-            .add_node(node::Node::new(name.to_string(), fields, b));
+            .add_node(node::Node::new(name, fields, b));
         info!(self.log,
               "adding new base";
               "node" => ni.index(),
@@ -225,6 +238,89 @@ impl<'a> Migration<'a> {
         self.columns.push((node, ColumnChange::Drop(column)));
     }
 
+    /// Widen an integer column of a base node to 64 bits.
+    ///
+    /// Existing records are not rewritten. Instead, the base widens old records (including ones
+    /// still in flight from clients that predate the change) as they are emitted or replayed. See
+    /// `Base::widen_column` for the restrictions that apply.
+    pub fn widen_column(&mut self, node: NodeIndex, column: usize) {
+        // not allowed to change columns of new nodes
+        assert!(!self.added.contains(&node));
+
+        let base = &mut self.mainline.ingredients[node];
+        assert!(base.is_base());
+        assert!(column < base.fields().len());
+
+        // we can't rely on DerefMut, since it disallows mutating Taken nodes
+        base.get_base_mut().unwrap().widen_column(column);
+
+        // also eventually propagate to domain clone
+        self.columns.push((node, ColumnChange::Widen(column)));
+    }
+
+    /// Delete the rows of the `child` base table whose column `child_col` refers to a value of
+    /// column `parent_col` that no longer exists in the `parent` base table, like a foreign key
+    /// with `ON DELETE CASCADE` would.
+    ///
+    /// Column `parent_col` must be the primary key of `parent`, so that a value is gone once the
+    /// row holding it is. The child table must have a primary key, since that is what the deletes
+    /// name rows by.
+    pub fn cascade_deletes(
+        &mut self,
+        parent: NodeIndex,
+        parent_col: usize,
+        child: NodeIndex,
+        child_col: usize,
+    ) -> Result<NodeIndex, String> {
+        let (name, fields, key, ranges) = {
+            let p = &self.mainline.ingredients[parent];
+            let c = &self.mainline.ingredients[child];
+            if !p.is_base() || !c.is_base() {
+                return Err("cascading deletes only works between base tables".to_owned());
+            }
+            if p.get_base().unwrap().key() != Some(&[parent_col][..]) {
+                return Err(format!(
+                    "column {} is not the primary key of table {}",
+                    p.fields()[parent_col],
+                    p.name()
+                ));
+            }
+            let key = match c.get_base().unwrap().key() {
+                Some(key) => key.to_vec(),
+                None => return Err(format!("table {} has no primary key", c.name())),
+            };
+            (
+                // cascades keep their journals under their name, so it must not be shared
+                format!(
+                    "{}_{}_cascade_{}_{}",
+                    p.name(),
+                    p.fields()[parent_col],
+                    c.name(),
+                    c.fields()[child_col]
+                ),
+                c.fields().to_vec(),
+                key,
+                c.get_base().unwrap().shard_ranges().to_vec(),
+            )
+        };
+
+        let cascade =
+            dataflow::ops::cascade::Cascade::new(parent, parent_col, child, child_col, key)
+                .with_shard_ranges(ranges);
+        Ok(self.add_ingredient(name, fields, cascade))
+    }
+
+    /// The cascades added by `cascade_deletes` that are still in place, along with the parent
+    /// table, referenced column, child table, and referencing column of each.
+    pub fn cascades(&self) -> Vec<(NodeIndex, (NodeIndex, usize, NodeIndex, usize))> {
+        let graph = &self.mainline.ingredients;
+        graph
+            .node_indices()
+            .filter(|&ni| !graph[ni].is_dropped())
+            .filter_map(|ni| Some((ni, graph[ni].get_cascade()?.foreign_key())))
+            .collect()
+    }
+
     #[cfg(test)]
     pub(crate) fn graph(&self) -> &Graph {
         self.mainline.graph()
@@ -277,13 +373,114 @@ impl<'a> Migration<'a> {
             .unwrap();
     }
 
+    /// Set up the given node such that its output can be queried by values computed from its
+    /// columns, such as `lower(email)`.
+    ///
+    /// Views keyed on computed values are always fully materialized, since the computed values
+    /// cannot be traced back to upstream state.
+    pub fn maintain_computed(&mut self, name: String, n: NodeIndex, key: &[KeyExpression]) {
+        self.ensure_reader_for(n, Some(name));
+
+        let ri = self.readers[&n];
+
+        self.mainline.ingredients[ri]
+            .with_reader_mut(|r| r.set_key_expressions(key.to_vec()))
+            .unwrap();
+    }
+
+    /// Add another reader for the given node, configured like its existing reader `of`, so that
+    /// the reads of the view can be spread across the two.
+    ///
+    /// The new reader is named `name`, and is marked as a replica, which places it in a domain of
+    /// its own.
+    pub(crate) fn add_read_replica(
+        &mut self,
+        n: NodeIndex,
+        of: NodeIndex,
+        name: String,
+    ) -> NodeIndex {
+        let mut r = self.mainline.ingredients[of]
+            .with_reader(Clone::clone)
+            .unwrap();
+        r.set_replica();
+        let r = self.mainline.ingredients[n].named_mirror(r, name);
+        let r = self.mainline.ingredients.add_node(r);
+        self.mainline.ingredients.add_edge(n, r, ());
+        self.added.insert(r);
+        r
+    }
+
+    /// Have the view maintained for the given node keep only the number of rows for each key,
+    /// rather than the rows themselves.
+    ///
+    /// The node must have been maintained earlier in this same migration, since the layout of a
+    /// view's state cannot change once it has been built.
+    pub fn maintain_count_only(&mut self, n: NodeIndex) -> Result<(), String> {
+        let ri = match self.readers.get(&n) {
+            Some(&ri) => ri,
+            None => return Err(format!("node {} has no new view", n.index())),
+        };
+
+        self.mainline.ingredients[ri]
+            .with_reader_mut(|r| r.set_count_only())
+            .unwrap();
+        Ok(())
+    }
+
+    /// Have deletes from the base table `base` only mark rows as deleted in `column`.
+    ///
+    /// See `Base::with_soft_delete`. This only works for bases added in this same migration,
+    /// since existing rows would otherwise have no value in `column`.
+    pub fn soft_delete(&mut self, base: NodeIndex, column: &str) -> Result<(), String> {
+        if !self.added.contains(&base) {
+            return Err("soft deletes must be declared along with the table".to_owned());
+        }
+        let node = &mut self.mainline.ingredients[base];
+        let col = match node.fields().iter().position(|f| f == column) {
+            Some(col) => col,
+            None => return Err(format!("{} has no column {}", node.name(), column)),
+        };
+        let b = node
+            .get_base_mut()
+            .expect("soft deletes only apply to bases");
+        if b.key().is_none() {
+            return Err("soft deletes need a primary key".to_owned());
+        }
+        *b = std::mem::take(b).with_soft_delete(col);
+        Ok(())
+    }
+
+    /// Have the view maintained for the given node mask the values derived from the `tagged` base
+    /// table columns, given by table and column name.
+    ///
+    /// This only affects views added in this same migration, which are then masked from the
+    /// moment they are first read.
+    pub fn mask_reads(&mut self, n: NodeIndex, tagged: &[(String, String)]) {
+        let ri = match self.readers.get(&n) {
+            Some(&ri) => ri,
+            None => return,
+        };
+        let columns = self.mainline.masked_columns(n, tagged);
+        if columns.is_empty() {
+            return;
+        }
+
+        let mask = ReadMask { columns };
+        self.mainline.ingredients[ri]
+            .with_reader_mut(|r| r.set_read_mask(Some(mask)))
+            .unwrap();
+    }
+
     /// Commit the changes introduced by this `Migration` to the master `Soup`.
     ///
     /// This will spin up an execution thread for each new thread domain, and hook those new
     /// domains into the larger Soup graph. The returned map contains entry points through which
     /// new updates should be sent to introduce them into the Soup.
+    ///
+    /// Fails if a domain stops replying to the controller before the migration completes, in which
+    /// case the changes may have been only partly made.
     #[allow(clippy::cognitive_complexity)]
-    pub(super) fn commit(self) {
+    pub(super) fn commit(self) -> Result<(), WaitError> {
         info!(self.log, "finalizing migration"; "#nodes" => self.added.len());
 
         let log = self.log;
@@ -292,10 +489,20 @@ impl<'a> Migration<'a> {
         let mut new = self.added;
         let mut topo = mainline.topo_order(&new);
 
+        let span = telemetry::Span::start("migration", None);
+        span.set("nodes", new.len());
+        let phase = telemetry::Span::start("migration.plan", span.context());
+
         // Shard the graph as desired
         let mut swapped0 = if let Some(shards) = mainline.sharding {
-            let (t, swapped) =
-                sharding::shard(&log, &mut mainline.ingredients, &mut new, &topo, shards);
+            let (t, swapped) = sharding::shard(
+                &log,
+                &mut mainline.ingredients,
+                &mut new,
+                &topo,
+                shards,
+                &mainline.base_shards,
+            );
             topo = t;
 
             swapped
@@ -303,6 +510,21 @@ impl<'a> Migration<'a> {
             HashMap::default()
         };
 
+        // Spread hot keys across shards where that is safe, and stop doing so where the new nodes
+        // have made it unsafe.
+        if let Some(share) = mainline.hot_key_split {
+            sharding::split_hot_keys(&log, &mut mainline.ingredients, &new, share);
+        }
+        for ni in sharding::revoke_key_splitting(&log, &mut mainline.ingredients, &new) {
+            let n = &mainline.ingredients[ni];
+            let m = Box::new(Packet::StopSplittingKeys {
+                node: n.local_addr(),
+            });
+            let domain = mainline.domains.get_mut(&n.domain()).unwrap();
+            domain.send_to_healthy(m, &mainline.workers).unwrap();
+            futures_executor::block_on(mainline.replies.wait_for_acks(&domain))?;
+        }
+
         // Assign domains
         assignment::assign(
             &log,
@@ -383,6 +605,20 @@ impl<'a> Migration<'a> {
                 dns
             });
 
+        // Decide how the keys are spread across the shards of each new domain, and have the new
+        // sharders and cascades route keys to every domain by its weights.
+        let mut shard_weights: HashMap<_, _> = mainline
+            .domains
+            .iter()
+            .map(|(&di, d)| (di, d.shard_weights.clone()))
+            .collect();
+        for (&di, nodes) in &domain_new_nodes {
+            if !mainline.domains.contains_key(&di) {
+                shard_weights.insert(di, mainline.shard_weights_for(nodes));
+            }
+        }
+        sharding::weigh_shards(&mut mainline.ingredients, &new, &shard_weights);
+
         // Assign local addresses to all new nodes, and initialize them
         for (domain, nodes) in &mut domain_new_nodes {
             // Number of pre-existing nodes
@@ -494,93 +730,134 @@ impl<'a> Migration<'a> {
             })
             .collect();
 
-        // Boot up new domains (they'll ignore all updates for now)
-        debug!(log, "booting new domains");
-        for domain in changed_domains {
-            if mainline.domains.contains_key(&domain) {
-                // this is not a new domain
-                continue;
-            }
+        drop(phase);
 
-            let nodes = uninformed_domain_nodes.remove(&domain).unwrap();
-            let d = mainline.place_domain(
-                domain,
-                mainline.ingredients[nodes[0].0].sharded_by().shards(),
-                &log,
-                nodes,
-            );
-            mainline.domains.insert(domain, d);
-        }
+        // Existing domains will soon block on the new ones, so have writes to the existing base
+        // tables that feed the new nodes turned away until the new domains are up, if so
+        // configured. Writes to other base tables do not reach the new domains.
+        let turn_away = if mainline.reject_writes_during_migration && !changed_domains.is_empty() {
+            mainline.bases_feeding(&new)
+        } else {
+            HashSet::new()
+        };
+        mainline.set_migrating(&turn_away, true);
+
+        // whatever happens from here on, the bases must take writes again once we are done.
+        let committed =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| -> Result<(), WaitError> {
+                // Boot up new domains (they'll ignore all updates for now)
+                let phase = telemetry::Span::start("migration.boot", span.context());
+                debug!(log, "booting new domains");
+                for domain in changed_domains {
+                    if mainline.domains.contains_key(&domain) {
+                        // this is not a new domain
+                        continue;
+                    }
 
-        // Add any new nodes to existing domains (they'll also ignore all updates for now)
-        debug!(log, "mutating existing domains");
-        augmentation::inform(&log, &mut mainline, uninformed_domain_nodes);
+                    let nodes = uninformed_domain_nodes.remove(&domain).unwrap();
+                    let d = mainline.place_domain(
+                        domain,
+                        mainline.ingredients[nodes[0].0].sharded_by().shards(),
+                        &log,
+                        nodes,
+                        shard_weights.remove(&domain).unwrap_or_default(),
+                    );
+                    mainline.domains.insert(domain, d);
+                }
 
-        // Tell all base nodes and base ingress children about newly added columns
-        for (ni, change) in self.columns {
-            let mut inform = if let ColumnChange::Add(..) = change {
-                // we need to inform all of the base's children too,
-                // so that they know to add columns to existing records when replaying
-                mainline
-                    .ingredients
-                    .neighbors_directed(ni, petgraph::EdgeDirection::Outgoing)
-                    .filter(|&eni| mainline.ingredients[eni].is_egress())
-                    .flat_map(|eni| {
-                        // find ingresses under this egress
+                // Add any new nodes to existing domains (they'll also ignore all updates for now)
+                debug!(log, "mutating existing domains");
+                augmentation::inform(&log, &mut mainline, uninformed_domain_nodes);
+
+                // Tell all base nodes and base ingress children about newly added columns
+                for (ni, change) in self.columns {
+                    let mut inform = if let ColumnChange::Add(..) = change {
+                        // we need to inform all of the base's children too,
+                        // so that they know to add columns to existing records when replaying
                         mainline
                             .ingredients
-                            .neighbors_directed(eni, petgraph::EdgeDirection::Outgoing)
-                    })
-                    .collect()
-            } else {
-                // ingress nodes don't need to know about deleted columns, because those are only
-                // relevant when new writes enter the graph.
-                Vec::new()
-            };
-            inform.push(ni);
-
-            for ni in inform {
-                let n = &mainline.ingredients[ni];
-                let m = match change.clone() {
-                    ColumnChange::Add(field, default) => Box::new(Packet::AddBaseColumn {
-                        node: n.local_addr(),
-                        field,
-                        default,
-                    }),
-                    ColumnChange::Drop(column) => Box::new(Packet::DropBaseColumn {
-                        node: n.local_addr(),
-                        column,
-                    }),
-                };
-
-                let domain = mainline.domains.get_mut(&n.domain()).unwrap();
-
-                domain.send_to_healthy(m, &mainline.workers).unwrap();
-                futures_executor::block_on(mainline.replies.wait_for_acks(&domain));
-            }
-        }
+                            .neighbors_directed(ni, petgraph::EdgeDirection::Outgoing)
+                            .filter(|&eni| mainline.ingredients[eni].is_egress())
+                            .flat_map(|eni| {
+                                // find ingresses under this egress
+                                mainline
+                                    .ingredients
+                                    .neighbors_directed(eni, petgraph::EdgeDirection::Outgoing)
+                            })
+                            .collect()
+                    } else {
+                        // ingress nodes don't need to know about deleted or widened columns, because those
+                        // are only relevant when writes leave the base.
+                        Vec::new()
+                    };
+                    inform.push(ni);
+
+                    for ni in inform {
+                        let n = &mainline.ingredients[ni];
+                        let m = match change.clone() {
+                            ColumnChange::Add(field, default) => Box::new(Packet::AddBaseColumn {
+                                node: n.local_addr(),
+                                field,
+                                default,
+                            }),
+                            ColumnChange::Drop(column) => Box::new(Packet::DropBaseColumn {
+                                node: n.local_addr(),
+                                column,
+                            }),
+                            ColumnChange::Widen(column) => Box::new(Packet::WidenBaseColumn {
+                                node: n.local_addr(),
+                                column,
+                            }),
+                        };
+
+                        let domain = mainline.domains.get_mut(&n.domain()).unwrap();
+
+                        domain.send_to_healthy(m, &mainline.workers).unwrap();
+                        futures_executor::block_on(mainline.replies.wait_for_acks(&domain))?;
+                    }
+                }
 
-        // Set up inter-domain connections
-        // NOTE: once we do this, we are making existing domains block on new domains!
-        info!(log, "bringing up inter-domain connections");
-        routing::connect(
-            &log,
-            &mut mainline.ingredients,
-            &mut mainline.domains,
-            &mainline.workers,
-            &new,
-        );
+                drop(phase);
+
+                // Set up inter-domain connections
+                // NOTE: once we do this, we are making existing domains block on new domains!
+                let phase = telemetry::Span::start("migration.connect", span.context());
+                info!(log, "bringing up inter-domain connections");
+                routing::connect(
+                    &log,
+                    &mut mainline.ingredients,
+                    &mut mainline.domains,
+                    &mainline.workers,
+                    &new,
+                );
 
-        // And now, the last piece of the puzzle -- set up materializations
-        info!(log, "initializing new materializations");
-        mainline.materializations.commit(
-            &mut mainline.ingredients,
-            &new,
-            &mut mainline.domains,
-            &mainline.workers,
-            &mut mainline.replies,
-        );
+                drop(phase);
+
+                // And now, the last piece of the puzzle -- set up materializations
+                let phase = telemetry::Span::start("migration.materialize", span.context());
+                info!(log, "initializing new materializations");
+                mainline.materializations.commit(
+                    &mut mainline.ingredients,
+                    &new,
+                    &mut mainline.domains,
+                    &mainline.workers,
+                    &mut mainline.replies,
+                    phase.context(),
+                )?;
+                drop(phase);
+                Ok(())
+            }));
+        mainline.set_migrating(&turn_away, false);
+        match committed {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                crit!(log, "migration failed: {}", e);
+                return Err(e);
+            }
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
 
         warn!(log, "migration completed"; "ms" => start.elapsed().as_millis());
+        Ok(())
     }
 }
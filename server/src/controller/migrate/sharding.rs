@@ -13,6 +13,7 @@ pub fn shard(
     new: &mut HashSet<NodeIndex>,
     topo_list: &[NodeIndex],
     sharding_factor: usize,
+    base_shards: &HashMap<String, usize>,
 ) -> (Vec<NodeIndex>, HashMap<(NodeIndex, NodeIndex), NodeIndex>) {
     // we must keep track of changes we make to the parent of a node, since this remapping must be
     // communicated to the nodes so they know the true identifier of their parent in the graph.
@@ -23,9 +24,39 @@ pub fn shard(
     'nodes: for &node in topo_list {
         let mut input_shardings: HashMap<_, _> = graph
             .neighbors_directed(node, petgraph::EdgeDirection::Incoming)
-            .map(|ni| (ni, graph[ni].sharded_by()))
+            .map(|ni| {
+                let s = graph[ni].sharded_by();
+                match (graph[ni].get_base(), s) {
+                    // the shards of a base that own ranges of keys hold different keys than those
+                    // that hash to them, so nodes below it must not take them for hashed shards.
+                    (Some(b), Sharding::ByColumn(_, shards)) if !b.shard_ranges().is_empty() => {
+                        (ni, Sharding::Random(shards))
+                    }
+                    _ => (ni, s),
+                }
+            })
             .collect();
 
+        // a base may have been given a shard count of its own, in which case the nodes below it
+        // shuffle its rows into the usual number of shards.
+        let own_shards = if graph[node].is_base() {
+            base_shards.get(graph[node].name()).cloned()
+        } else {
+            None
+        };
+        let sharding_factor = match own_shards {
+            Some(1) => {
+                info!(log, "not sharding base with a single shard"; "node" => ?node);
+                graph
+                    .node_weight_mut(node)
+                    .unwrap()
+                    .shard_by(Sharding::ForcedNone);
+                continue;
+            }
+            Some(shards) => shards,
+            None => sharding_factor,
+        };
+
         let mut need_sharding = if graph[node].is_internal() || graph[node].is_base() {
             // suggest_indexes is okay because `node` *must* be new, and therefore will return
             // global node indices.
@@ -37,18 +68,32 @@ pub fn shard(
                 continue;
             }
 
+            let computed = graph[node]
+                .with_reader(|r| !r.key_expressions().is_empty())
+                .unwrap();
             let s = graph[node]
                 .with_reader(|r| r.key())
                 .unwrap()
                 .and_then(|c| {
                     if c.len() == 1 {
-                        if graph[node].fields()[c[0]] == "bogokey" {
+                        // rows are sharded by their own values, which computed keys don't match
+                        if computed || graph[node].fields()[c[0]] == "bogokey" {
                             Some(Sharding::ForcedNone)
                         } else {
                             Some(Sharding::ByColumn(c[0], sharding_factor))
                         }
-                    } else {
+                    } else if computed {
                         None
+                    } else {
+                        // a compound key that includes the column our input is sharded by can
+                        // only be found in the shard that owns that column's value, so reads and
+                        // replays for it need only go there.
+                        match input_shardings[&ni] {
+                            Sharding::ByColumn(col, _) if c.contains(&col) => {
+                                Some(input_shardings[&ni])
+                            }
+                            _ => None,
+                        }
                     }
                 })
                 .unwrap_or(Sharding::ForcedNone);
@@ -120,7 +165,15 @@ pub fn shard(
             }
         }
         if complex {
-            if !graph[node].is_base() {
+            if graph[node].is_base() {
+                // clients spread the rows of a base with a compound key by hashing the whole key,
+                // so no single column tells which shard a row lives on.
+                warn!(log, "sharding base node by compound key"; "node" => ?node);
+                graph
+                    .node_weight_mut(node)
+                    .unwrap()
+                    .shard_by(Sharding::Random(sharding_factor));
+            } else {
                 // not supported yet -- force no sharding
                 // TODO: if we're sharding by a two-part key and need sharding by the *first* part
                 // of that key, we can probably re-use the existing sharding?
@@ -623,6 +676,107 @@ pub fn shard(
     (topo_list, swaps)
 }
 
+/// Let every new sharder for which it is safe spread keys that account for more than `share` of
+/// its records across all shards.
+pub fn split_hot_keys(log: &Logger, graph: &mut Graph, new: &HashSet<NodeIndex>, share: f64) {
+    for &ni in new {
+        if graph[ni].is_sharder() && can_split_keys(graph, ni) {
+            debug!(log, "sharder may split hot keys"; "sharder" => ?ni);
+            graph[ni].with_sharder_mut(|s| s.split_hot_keys(share));
+        }
+    }
+}
+
+/// Make every new sharder and cascade send keys to the shards of the domain they send to by that
+/// domain's `weights`.
+pub fn weigh_shards(
+    graph: &mut Graph,
+    new: &HashSet<NodeIndex>,
+    weights: &HashMap<DomainIndex, Vec<u32>>,
+) {
+    for &ni in new {
+        if graph[ni].is_dropped() {
+            continue;
+        }
+        let to = if graph[ni].is_sharder() {
+            graph
+                .neighbors_directed(ni, petgraph::EdgeDirection::Outgoing)
+                .next()
+        } else {
+            graph[ni].get_cascade().map(|c| c.child())
+        };
+        let w = match to.and_then(|to| weights.get(&graph[to].domain())) {
+            Some(w) if !w.is_empty() => w.clone(),
+            _ => continue,
+        };
+        if graph[ni].is_sharder() {
+            graph[ni].with_sharder_mut(|s| s.set_shard_weights(w));
+        } else {
+            graph[ni].get_cascade_mut().unwrap().set_shard_weights(w);
+        }
+    }
+}
+
+/// Find existing sharders that may split hot keys, but below which new nodes were added that make
+/// it unsafe to do so, and make them stop.
+///
+/// The sharders' domains still need to be told about this.
+pub fn revoke_key_splitting(
+    log: &Logger,
+    graph: &mut Graph,
+    new: &HashSet<NodeIndex>,
+) -> Vec<NodeIndex> {
+    let revoked: Vec<_> = graph
+        .node_indices()
+        .filter(|ni| !new.contains(ni))
+        .filter(|&ni| graph[ni].with_sharder(|s| s.splits_hot_keys()) == Some(true))
+        .filter(|&ni| !can_split_keys(graph, ni))
+        .collect();
+    for &ni in &revoked {
+        warn!(log, "sharder can no longer split hot keys"; "sharder" => ?ni);
+        graph[ni].with_sharder_mut(|s| s.stop_splitting_keys());
+    }
+    revoked
+}
+
+/// Whether `sharder` may send the records of a single key to more than one shard.
+///
+/// The shards of a split key each compute part of that key's results, and there is no operator
+/// that can combine those parts again. So, this is only safe if every node between the sharder
+/// and the point where its shards are merged handles each record on its own.
+fn can_split_keys(graph: &Graph, sharder: NodeIndex) -> bool {
+    let mut stack: Vec<_> = graph
+        .neighbors_directed(sharder, petgraph::EdgeDirection::Outgoing)
+        .collect();
+    let mut seen = HashSet::new();
+    while let Some(ni) = stack.pop() {
+        if !seen.insert(ni) {
+            continue;
+        }
+
+        let n = &graph[ni];
+        if n.is_dropped() || n.is_shard_merger() {
+            continue;
+        }
+
+        let stateless = if n.is_internal() {
+            match **n {
+                ops::NodeOperator::Project(_)
+                | ops::NodeOperator::Filter(_)
+                | ops::NodeOperator::Identity(_) => true,
+                _ => false,
+            }
+        } else {
+            n.is_ingress() || n.is_egress()
+        };
+        if !stateless {
+            return false;
+        }
+        stack.extend(graph.neighbors_directed(ni, petgraph::EdgeDirection::Outgoing));
+    }
+    true
+}
+
 /// Modify the graph such that the path between `src` and `dst` shuffles the input such that the
 /// records received by `dst` are sharded by sharding `to`.
 fn reshard(
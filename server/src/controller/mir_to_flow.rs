@@ -1,5 +1,6 @@
 use nom_sql::{
-    ArithmeticBase, ArithmeticExpression, ColumnConstraint, ColumnSpecification, Literal, OrderType,
+    ArithmeticBase, ArithmeticExpression, ColumnConstraint, ColumnSpecification, Literal,
+    OrderType, SqlType,
 };
 use std::collections::HashMap;
 
@@ -330,6 +331,11 @@ fn mir_node_to_flow_parts(
                         mig,
                     )
                 }
+                MirNodeType::Unnest { ref column } => {
+                    assert_eq!(mir_node.ancestors.len(), 1);
+                    let parent = mir_node.ancestors[0].clone();
+                    make_unnest_node(&name, parent, mir_node.columns.as_slice(), column, mig)
+                }
             };
 
             // any new flow nodes have been instantiated by now, so we replace them with
@@ -431,7 +437,19 @@ fn make_base_node(
         })
         .collect::<Vec<DataType>>();
 
-    let base = if !pkey_columns.is_empty() {
+    // binary columns are limited to the sizes their SQL types allow
+    let size_limits: Vec<_> = column_specs
+        .iter()
+        .map(|&(ref cs, _)| match cs.sql_type {
+            SqlType::Binary(n) | SqlType::Varbinary(n) => Some(usize::from(n)),
+            SqlType::Tinyblob => Some(255),
+            SqlType::Blob => Some(65_535),
+            SqlType::Mediumblob => Some(16_777_215),
+            _ => None,
+        })
+        .collect();
+
+    let mut base = if !pkey_columns.is_empty() {
         let pkey_column_ids = pkey_columns
             .iter()
             .map(|pkc| {
@@ -446,6 +464,11 @@ fn make_base_node(
     } else {
         node::special::Base::new(default_values)
     };
+    for (column, limit) in size_limits.into_iter().enumerate() {
+        if let Some(limit) = limit {
+            base = base.with_size_limit(column, limit);
+        }
+    }
 
     FlowNode::New(mig.add_base(name, column_names.as_slice(), base))
 }
@@ -857,6 +880,25 @@ fn make_distinct_node(
     FlowNode::New(na)
 }
 
+fn make_unnest_node(
+    name: &str,
+    parent: MirNodeRef,
+    columns: &[Column],
+    column: &Column,
+    mig: &mut Migration,
+) -> FlowNode {
+    let parent_na = parent.borrow().flow_node_addr().unwrap();
+    let column_names = column_names(columns);
+    let column_id = parent.borrow().column_id_for_column(column, None);
+
+    let na = mig.add_ingredient(
+        String::from(name),
+        column_names.as_slice(),
+        ops::unnest::Unnest::new(parent_na, column_id),
+    );
+    FlowNode::New(na)
+}
+
 fn make_topk_node(
     name: &str,
     parent: MirNodeRef,
@@ -1,4 +1,4 @@
-use crate::controller::inner::ControllerInner;
+pub(crate) use crate::controller::inner::ControllerInner;
 use crate::controller::migrate::Migration;
 use crate::controller::recipe::Recipe;
 use crate::coordination::CoordinationMessage;
@@ -7,16 +7,20 @@ use crate::startup::Event;
 use crate::Config;
 use async_bincode::AsyncBincodeReader;
 use dataflow::payload::ControlReplyPacket;
+use dataflow::prelude::DataType;
+use dataflow::Clock;
 use futures_util::{
     future::FutureExt,
     future::TryFutureExt,
     sink::SinkExt,
     stream::{StreamExt, TryStreamExt},
 };
-use hyper::{self, StatusCode};
+use hyper::{self, Method, StatusCode};
 use noria::channel::TcpSender;
 use noria::consensus::{Authority, Epoch, STATE_KEY};
-use noria::ControllerDescriptor;
+use noria::{ClientConnection, ControllerDescriptor};
+use petgraph::graph::NodeIndex;
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::thread::{self, JoinHandle};
@@ -24,15 +28,18 @@ use std::time;
 use stream_cancel::Valve;
 use tokio::sync::mpsc::UnboundedSender;
 
+mod consistency;
 mod domain_handle;
 mod inner;
 mod keys;
 pub(crate) mod migrate; // crate viz for tests
 mod mir_to_flow;
+pub(crate) mod placement;
 pub(crate) mod recipe; // crate viz for tests
 mod schema;
 mod security;
 pub(crate) mod sql; // crate viz for tests
+mod verification;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub(crate) struct ControllerState {
@@ -41,20 +48,81 @@ pub(crate) struct ControllerState {
 
     recipe_version: usize,
     recipes: Vec<String>,
+    /// Shard counts that base tables were given by `reshard_base`, in place of the configured
+    /// sharding.
+    #[serde(default)]
+    base_shards: HashMap<String, usize>,
+    /// The split points between the ranges of keys that the shards of base tables own, as given
+    /// by `set_shard_ranges`.
+    #[serde(default)]
+    shard_ranges: HashMap<String, Vec<DataType>>,
 }
 
 struct Worker {
     healthy: bool,
     last_heartbeat: time::Instant,
     sender: TcpSender<CoordinationMessage>,
+    /// The most disk space base table persistence may use on this worker, if limited.
+    disk_quota: Option<u64>,
+    /// Disk space used by base table persistence on this worker, as of the last heartbeat.
+    disk_usage: u64,
+    /// The number of base table shards placed on this worker.
+    base_shards: u64,
+    /// The clients connected to this worker, as of the last heartbeat.
+    connections: Vec<ClientConnection>,
+    /// The label the worker was started with, if any.
+    label: Option<String>,
+    /// The number of domain shards this worker has started, as of the last heartbeat.
+    domains_hosted: usize,
+    /// Memory used by the partial state of this worker's domains, as of the last heartbeat.
+    memory_used: u64,
+    /// The load average of this worker's host, as of the last heartbeat.
+    cpu_load: Option<f64>,
+    /// The number of blocking reads waiting on each reader shard this worker serves, as of the
+    /// last heartbeat.
+    read_queues: HashMap<(NodeIndex, usize), usize>,
 }
 
 impl Worker {
-    fn new(sender: TcpSender<CoordinationMessage>) -> Self {
+    fn new(
+        sender: TcpSender<CoordinationMessage>,
+        disk_quota: Option<u64>,
+        disk_usage: u64,
+        label: Option<String>,
+        now: time::Instant,
+    ) -> Self {
         Worker {
             healthy: true,
-            last_heartbeat: time::Instant::now(),
+            last_heartbeat: now,
             sender,
+            disk_quota,
+            disk_usage,
+            base_shards: 0,
+            connections: Vec::new(),
+            label,
+            domains_hosted: 0,
+            memory_used: 0,
+            cpu_load: None,
+            read_queues: HashMap::new(),
+        }
+    }
+
+    /// The number of base table shards of `reservation` bytes each that still fit within this
+    /// worker's disk quota, or `None` if that number is unlimited.
+    ///
+    /// Each shard already on the worker is assumed to use at least `reservation` bytes, even if
+    /// it has not written that much to disk yet.
+    fn disk_headroom(&self, reservation: u64) -> Option<u64> {
+        let quota = self.disk_quota?;
+        let used = std::cmp::max(self.disk_usage, self.base_shards * reservation);
+        if reservation == 0 {
+            if used <= quota {
+                None
+            } else {
+                Some(0)
+            }
+        } else {
+            Some(quota.saturating_sub(used) / reservation)
         }
     }
 }
@@ -71,6 +139,7 @@ pub(super) async fn main<A: Authority + 'static>(
     log: slog::Logger,
     authority: Arc<A>,
     tx: tokio::sync::mpsc::UnboundedSender<Event>,
+    clock: Clock,
 ) {
     let (dtx, drx) = tokio::sync::mpsc::unbounded_channel();
 
@@ -111,15 +180,54 @@ pub(super) async fn main<A: Authority + 'static>(
                         });
                     }
                 }
-                CoordinationPayload::Heartbeat => {
+                CoordinationPayload::Heartbeat { .. } => {
                     if let Some(ref mut ctrl) = controller {
                         tokio::task::block_in_place(|| ctrl.handle_heartbeat(msg).unwrap());
                     }
                 }
+                CoordinationPayload::DomainFailed { .. } => {
+                    if let Some(ref mut ctrl) = controller {
+                        tokio::task::block_in_place(|| ctrl.handle_domain_failure(msg));
+                    }
+                }
+                CoordinationPayload::DomainStatistics { .. } => {
+                    if let Some(ref mut ctrl) = controller {
+                        ctrl.handle_domain_statistics(msg);
+                    }
+                }
+                CoordinationPayload::CorruptReplay { .. } => {
+                    if let Some(ref mut ctrl) = controller {
+                        ctrl.handle_corrupt_replay(msg);
+                    }
+                }
                 _ => unreachable!(),
             },
             Event::ExternalRequest(method, path, query, body, reply_tx) => {
                 if let Some(ref mut ctrl) = controller {
+                    if method == Method::POST && path == "/check_consistency" {
+                        // the check waits for keys to be recomputed, which it must not do on the
+                        // controller's own thread.
+                        let args = serde_json::from_slice(&body);
+                        match args {
+                            _ if !ctrl.is_available() => {
+                                let _ = reply_tx.send(Err(StatusCode::SERVICE_UNAVAILABLE));
+                            }
+                            Ok((view, samples)) => {
+                                let check =
+                                    consistency::check(tx.clone(), log.clone(), view, samples);
+                                tokio::spawn(async move {
+                                    let reply =
+                                        check.await.map(|e| serde_json::to_string(&e).unwrap());
+                                    let _ = reply_tx.send(Ok(reply));
+                                });
+                            }
+                            Err(_) => {
+                                let _ = reply_tx.send(Err(StatusCode::BAD_REQUEST));
+                            }
+                        }
+                        continue;
+                    }
+
                     let authority = &authority;
                     let reply = tokio::task::block_in_place(|| {
                         ctrl.external_request(method, path, query, body, &authority)
@@ -135,15 +243,23 @@ pub(super) async fn main<A: Authority + 'static>(
             Event::ManualMigration { f, done } => {
                 if let Some(ref mut ctrl) = controller {
                     if !ctrl.workers.is_empty() {
-                        tokio::task::block_in_place(|| {
-                            ctrl.migrate(move |m| f(m));
-                            done.send(()).unwrap();
+                        tokio::task::block_in_place(|| match ctrl.migrate(move |m| f(m)) {
+                            Ok(()) => done.send(()).unwrap(),
+                            // dropping `done` tells the sender that the migration failed
+                            Err(e) => warn!(log, "migration failed: {}", e),
                         });
                     }
                 } else {
                     unreachable!("got migration closure before becoming leader");
                 }
             }
+            Event::OnController(f) => {
+                // if this instance is not the controller (any more), dropping `f` tells its
+                // sender as much.
+                if let Some(ref mut ctrl) = controller {
+                    tokio::task::block_in_place(|| f(ctrl));
+                }
+            }
             #[cfg(test)]
             Event::IsReady(reply) => {
                 reply
@@ -159,10 +275,26 @@ pub(super) async fn main<A: Authority + 'static>(
                 let c = campaign.take().unwrap();
                 tokio::task::block_in_place(move || c.join().unwrap());
                 let drx = drx.take().unwrap();
-                controller = Some(ControllerInner::new(log.clone(), state, drx));
+                if state.config.consistency_check.is_some() {
+                    tokio::spawn(consistency::run_periodically(
+                        tx.clone(),
+                        valve.clone(),
+                        log.clone(),
+                        state.config.heartbeat_every,
+                    ));
+                }
+                if state.config.base_verification.is_some() {
+                    tokio::spawn(verification::run_periodically(
+                        tx.clone(),
+                        valve.clone(),
+                        log.clone(),
+                        state.config.heartbeat_every,
+                    ));
+                }
+                controller = Some(ControllerInner::new(log.clone(), state, drx, clock.clone()));
             }
-            Event::CampaignError(e) => {
-                panic!("{:?}", e);
+            Event::AuthorityUnreachable(e) => {
+                warn!(log, "authority is unreachable: {:?}", e);
             }
             e => unreachable!("{:?} is not a controller event", e),
         }
@@ -212,6 +344,9 @@ async fn listen_domain_replies(
     }
 }
 
+/// How long to wait before contacting the authority again after it could not be reached.
+const AUTHORITY_RETRY_INTERVAL: time::Duration = time::Duration::from_secs(1);
+
 fn instance_campaign<A: Authority + 'static>(
     event_tx: UnboundedSender<Event>,
     authority: Arc<A>,
@@ -219,13 +354,29 @@ fn instance_campaign<A: Authority + 'static>(
     config: Config,
 ) -> JoinHandle<()> {
     let descriptor_bytes = serde_json::to_vec(&descriptor).unwrap();
-    let campaign_inner = move |event_tx: UnboundedSender<Event>| -> Result<(), failure::Error> {
+    let campaign_inner = move |event_tx: &UnboundedSender<Event>,
+                               announced: &mut Option<Epoch>|
+          -> Result<(), failure::Error> {
         let payload_to_event = |payload: Vec<u8>| -> Result<Event, failure::Error> {
             let descriptor: ControllerDescriptor = serde_json::from_slice(&payload[..])?;
-            let state: ControllerState =
-                serde_json::from_slice(&authority.try_read(STATE_KEY).unwrap().unwrap())?;
+            let state = authority
+                .try_read(STATE_KEY)?
+                .ok_or_else(|| format_err!("leader has not written any controller state"))?;
+            let state: ControllerState = serde_json::from_slice(&state)?;
             Ok(Event::LeaderChange(state, descriptor))
         };
+        // we may be back here after losing our connection to the authority, in which case the
+        // workers must not tear down their dataflow for a leader they are already following.
+        let mut announce = |epoch: Epoch, payload: Vec<u8>| -> Result<(), failure::Error> {
+            if *announced == Some(epoch) {
+                return Ok(());
+            }
+            event_tx
+                .send(payload_to_event(payload)?)
+                .map_err(|_| format_err!("send failed"))?;
+            *announced = Some(epoch);
+            Ok(())
+        };
 
         loop {
             // WORKER STATE - watch for leadership changes
@@ -235,14 +386,10 @@ fn instance_campaign<A: Authority + 'static>(
             let mut epoch;
             if let Some(leader) = authority.try_get_leader()? {
                 epoch = leader.0;
-                event_tx
-                    .send(payload_to_event(leader.1)?)
-                    .map_err(|_| format_err!("send failed"))?;
+                announce(epoch, leader.1)?;
                 while let Some(leader) = authority.await_new_epoch(epoch)? {
                     epoch = leader.0;
-                    event_tx
-                        .send(payload_to_event(leader.1)?)
-                        .map_err(|_| format_err!("send failed"))?;
+                    announce(epoch, leader.1)?;
                 }
             }
 
@@ -262,6 +409,8 @@ fn instance_campaign<A: Authority + 'static>(
                         epoch,
                         recipe_version: 0,
                         recipes: vec![],
+                        base_shards: HashMap::new(),
+                        shard_ranges: HashMap::new(),
                     }),
                     Some(ref state) if state.epoch > epoch => Err(()),
                     Some(mut state) => {
@@ -297,8 +446,13 @@ fn instance_campaign<A: Authority + 'static>(
     thread::Builder::new()
         .name("srv-zk".to_owned())
         .spawn(move || {
-            if let Err(e) = campaign_inner(event_tx.clone()) {
-                let _ = event_tx.send(Event::CampaignError(e));
+            let mut announced = None;
+            while let Err(e) = campaign_inner(&event_tx, &mut announced) {
+                // keep serving with what we have until the authority comes back
+                if event_tx.send(Event::AuthorityUnreachable(e)).is_err() {
+                    break;
+                }
+                thread::sleep(AUTHORITY_RETRY_INTERVAL);
             }
         })
         .unwrap()
@@ -0,0 +1,201 @@
+use crate::controller::WorkerIdentifier;
+
+/// How the controller chooses the worker for each shard of a new domain.
+///
+/// Workers can be given a label with `Builder::set_worker_label`, which some of the strategies
+/// take into account. Whatever the strategy, domains are only ever placed on healthy workers, and
+/// base table shards are kept within the disk quota of their worker where possible.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum PlacementStrategy {
+    /// Cycle through the workers (this is the default).
+    RoundRobin,
+    /// Place each shard on the worker that hosts the fewest domain shards, and among those, the
+    /// one whose domains last reported using the least memory for partial state.
+    LeastLoaded,
+    /// Treat the label of each worker as the rack it runs in, and spread the shards of each
+    /// domain over as many racks as possible, preferring the least loaded worker within a rack.
+    ///
+    /// Workers without a label are each considered to be in a rack of their own.
+    RackAware,
+    /// Only place domains on workers with the given label.
+    ///
+    /// If no such worker is available, domains are placed as with `LeastLoaded`.
+    Pinned(String),
+}
+
+impl Default for PlacementStrategy {
+    fn default() -> Self {
+        PlacementStrategy::RoundRobin
+    }
+}
+
+/// A worker that a domain shard could be placed on.
+#[derive(Clone, Debug)]
+pub(super) struct Candidate<'a> {
+    pub(super) id: WorkerIdentifier,
+    pub(super) label: Option<&'a str>,
+    /// The number of domain shards the worker hosts.
+    pub(super) load: usize,
+    /// Memory used by the partial state of the worker's domains, as last reported by the worker.
+    pub(super) memory_used: u64,
+    /// Whether the worker may host the shard at all.
+    pub(super) eligible: bool,
+}
+
+impl<'a> Candidate<'a> {
+    /// How loaded the worker is, for the strategies that prefer less loaded workers.
+    fn weight(&self) -> (usize, u64) {
+        (self.load, self.memory_used)
+    }
+
+    fn same_rack(&self, other: &Candidate<'_>) -> bool {
+        match (self.label, other.label) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.id == other.id,
+        }
+    }
+}
+
+impl PlacementStrategy {
+    /// Whether any of `candidates` can host a domain under this strategy without falling back.
+    pub(super) fn satisfiable(&self, candidates: &[Candidate<'_>]) -> bool {
+        match *self {
+            PlacementStrategy::Pinned(ref label) => candidates
+                .iter()
+                .any(|c| c.eligible && c.label == Some(&label[..])),
+            _ => true,
+        }
+    }
+
+    /// Choose one of `candidates` to host the next shard of a domain, given the candidates that
+    /// host the shards of the domain that have been placed so far.
+    ///
+    /// `cursor` is where round-robin placement resumes, and is advanced past the chosen
+    /// candidate. Returns `None` if no candidate is eligible.
+    pub(super) fn choose(
+        &self,
+        candidates: &[Candidate<'_>],
+        placed: &[usize],
+        cursor: &mut usize,
+    ) -> Option<usize> {
+        let n = candidates.len();
+        let satisfiable = self.satisfiable(candidates);
+        let allowed = |c: &Candidate<'_>| match *self {
+            PlacementStrategy::Pinned(ref label) if satisfiable => {
+                c.eligible && c.label == Some(&label[..])
+            }
+            _ => c.eligible,
+        };
+
+        // all strategies consider the candidates in round-robin order, so that ties are broken
+        // by spreading shards over the workers.
+        let order: Vec<usize> = (0..n)
+            .map(|i| (*cursor + i) % n)
+            .filter(|&i| allowed(&candidates[i]))
+            .collect();
+
+        let chosen = match *self {
+            PlacementStrategy::RoundRobin => order.first().cloned(),
+            PlacementStrategy::LeastLoaded | PlacementStrategy::Pinned(_) => order
+                .iter()
+                .cloned()
+                .min_by_key(|&i| candidates[i].weight()),
+            PlacementStrategy::RackAware => {
+                let new_rack = |i: usize| {
+                    !placed
+                        .iter()
+                        .any(|&p| candidates[p].same_rack(&candidates[i]))
+                };
+                order
+                    .iter()
+                    .cloned()
+                    .min_by_key(|&i| (!new_rack(i), candidates[i].weight()))
+            }
+        }?;
+
+        *cursor = (chosen + 1) % n;
+        Some(chosen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates<'a>(workers: &[(Option<&'a str>, usize)]) -> Vec<Candidate<'a>> {
+        workers
+            .iter()
+            .enumerate()
+            .map(|(i, &(label, load))| Candidate {
+                id: ([127, 0, 0, 1], 1000 + i as u16).into(),
+                label,
+                load,
+                memory_used: 0,
+                eligible: true,
+            })
+            .collect()
+    }
+
+    fn place(strategy: &PlacementStrategy, cs: &mut [Candidate<'_>], shards: usize) -> Vec<usize> {
+        let mut cursor = 0;
+        let mut placed = Vec::new();
+        for _ in 0..shards {
+            let i = strategy.choose(cs, &placed, &mut cursor).unwrap();
+            cs[i].load += 1;
+            placed.push(i);
+        }
+        placed
+    }
+
+    #[test]
+    fn round_robin() {
+        let mut cs = candidates(&[(None, 5), (None, 0), (None, 0)]);
+        cs[1].eligible = false;
+        assert_eq!(
+            place(&PlacementStrategy::RoundRobin, &mut cs, 4),
+            vec![0, 2, 0, 2]
+        );
+    }
+
+    #[test]
+    fn least_loaded() {
+        let mut cs = candidates(&[(None, 3), (None, 1), (None, 0)]);
+        assert_eq!(
+            place(&PlacementStrategy::LeastLoaded, &mut cs, 4),
+            vec![2, 1, 2, 1]
+        );
+    }
+
+    #[test]
+    fn least_loaded_by_memory() {
+        let mut cs = candidates(&[(None, 1), (None, 1), (None, 2)]);
+        cs[0].memory_used = 1 << 20;
+        assert_eq!(
+            place(&PlacementStrategy::LeastLoaded, &mut cs, 2),
+            vec![1, 0]
+        );
+    }
+
+    #[test]
+    fn rack_aware() {
+        let mut cs = candidates(&[(Some("a"), 0), (Some("a"), 0), (Some("b"), 1), (None, 2)]);
+        assert_eq!(
+            place(&PlacementStrategy::RackAware, &mut cs, 4),
+            vec![0, 2, 3, 1]
+        );
+    }
+
+    #[test]
+    fn pinned() {
+        let mut cs = candidates(&[(Some("a"), 0), (Some("b"), 3), (Some("b"), 2)]);
+        let strategy = PlacementStrategy::Pinned("b".to_owned());
+        assert!(strategy.satisfiable(&cs));
+        assert_eq!(place(&strategy, &mut cs, 3), vec![2, 1, 2]);
+
+        // falls back to the other workers if none has the label
+        cs[1].eligible = false;
+        cs[2].eligible = false;
+        assert!(!strategy.satisfiable(&cs));
+        assert_eq!(place(&strategy, &mut cs, 2), vec![0, 0]);
+    }
+}
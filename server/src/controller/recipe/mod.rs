@@ -7,12 +7,12 @@ use dataflow::ops::trigger::TriggerEvent;
 use dataflow::prelude::DataType;
 use nom_sql::parser as sql_parser;
 use nom_sql::SqlQuery;
-use noria::ActivationResult;
+use noria::{ActivationResult, DataflowDiff, ReplayPriority};
 use petgraph::graph::NodeIndex;
 
 use nom_sql::CreateTableStatement;
 use slog;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::str;
 use std::vec::Vec;
 
@@ -28,6 +28,20 @@ pub(crate) struct Recipe {
     expression_order: Vec<QueryID>,
     /// Named read/write expression aliases, mapping to queries in `expressions`.
     aliases: HashMap<String, QueryID>,
+    /// Replay priorities given to named queries.
+    priorities: HashMap<String, ReplayPriority>,
+    /// Named queries whose views only keep the number of rows for each key.
+    count_only: HashSet<String>,
+    /// Foreign keys whose referencing rows are deleted along with the rows they reference.
+    foreign_keys: Vec<ForeignKey>,
+    /// Named queries that expand a list column into one row per element, and the column.
+    unnest: HashMap<String, nom_sql::Column>,
+    /// Base table columns holding personal data, and who may see them anyway.
+    masks: MaskPolicy,
+    /// Base tables whose deletes only mark rows as deleted, and the column that marks them.
+    soft_deletes: HashMap<String, String>,
+    /// Named queries that also see soft-deleted rows.
+    with_deleted: HashSet<String>,
     /// Security configuration
     security_config: Option<SecurityConfig>,
 
@@ -55,6 +69,55 @@ impl PartialEq for Recipe {
     }
 }
 
+/// A foreign key from a column of one base table to a column of another, declared with
+/// `FOREIGN KEY table(column) REFERENCES references(referenced_column) ON DELETE CASCADE;`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ForeignKey {
+    table: String,
+    column: String,
+    references: String,
+    referenced_column: String,
+}
+
+/// Which base table columns views mask, declared with `MASK table(column);`, and which views show
+/// them in the clear anyway, declared with `UNMASK VIEW name;`.
+///
+/// Clients that may see the values are given views of their own to read from, rather than trusted
+/// to say who they are.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct MaskPolicy {
+    pub(crate) columns: Vec<(String, String)>,
+    pub(crate) views: HashSet<String>,
+}
+
+impl MaskPolicy {
+    fn add(&mut self, statement: MaskStatement) {
+        match statement {
+            MaskStatement::Column(table, column) => {
+                if !self.columns.contains(&(table.clone(), column.clone())) {
+                    self.columns.push((table, column));
+                }
+            }
+            MaskStatement::View(view) => {
+                self.views.insert(view);
+            }
+        }
+    }
+
+    fn extend(&mut self, other: MaskPolicy) {
+        for (table, column) in other.columns {
+            self.add(MaskStatement::Column(table, column));
+        }
+        self.views.extend(other.views);
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum MaskStatement {
+    Column(String, String),
+    View(String),
+}
+
 #[derive(Debug)]
 pub(super) enum Schema {
     Table(CreateTableStatement),
@@ -78,7 +141,430 @@ fn ident(input: &str) -> nom::IResult<&str, &str> {
     })
 }
 
-fn query_prefix(input: &str) -> nom::IResult<&str, (bool, Option<&str>)> {
+fn replay_priority(input: &str) -> nom::IResult<&str, ReplayPriority> {
+    use nom::branch::alt;
+    use nom::bytes::complete::tag_no_case;
+    use nom::character::complete::{multispace0, space1};
+    use nom::combinator::value;
+    let (input, _) = tag_no_case("priority")(input)?;
+    let (input, _) = space1(input)?;
+    let (input, priority) = alt((
+        value(ReplayPriority::Batch, tag_no_case("batch")),
+        value(ReplayPriority::Normal, tag_no_case("normal")),
+        value(ReplayPriority::Interactive, tag_no_case("interactive")),
+    ))(input)?;
+    let (input, _) = multispace0(input)?;
+    Ok((input, priority))
+}
+
+fn count_only_flag(input: &str) -> nom::IResult<&str, ()> {
+    use nom::bytes::complete::tag_no_case;
+    use nom::character::complete::{multispace0, space1};
+    let (input, _) = tag_no_case("count")(input)?;
+    let (input, _) = space1(input)?;
+    let (input, _) = tag_no_case("only")(input)?;
+    let (input, _) = multispace0(input)?;
+    Ok((input, ()))
+}
+
+fn with_deleted_flag(input: &str) -> nom::IResult<&str, ()> {
+    use nom::bytes::complete::tag_no_case;
+    use nom::character::complete::{multispace0, space1};
+    let (input, _) = tag_no_case("with")(input)?;
+    let (input, _) = space1(input)?;
+    let (input, _) = tag_no_case("deleted")(input)?;
+    let (input, _) = multispace0(input)?;
+    Ok((input, ()))
+}
+
+fn table_column(input: &str) -> nom::IResult<&str, (&str, &str)> {
+    use nom::character::complete::{char, multispace0};
+    let (input, table) = ident(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = char('(')(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, column) = ident(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = char(')')(input)?;
+    let (input, _) = multispace0(input)?;
+    Ok((input, (table, column)))
+}
+
+fn foreign_key(input: &str) -> nom::IResult<&str, ForeignKey> {
+    use nom::bytes::complete::tag_no_case;
+    use nom::character::complete::{char, multispace0, multispace1};
+    let (input, _) = tag_no_case("foreign")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, _) = tag_no_case("key")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, (table, column)) = table_column(input)?;
+    let (input, _) = tag_no_case("references")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, (references, referenced_column)) = table_column(input)?;
+    // cascading deletes is the only action we support, so it must be asked for explicitly
+    let (input, _) = tag_no_case("on")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, _) = tag_no_case("delete")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, _) = tag_no_case("cascade")(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = char(';')(input)?;
+    let (input, _) = multispace0(input)?;
+    Ok((
+        input,
+        ForeignKey {
+            table: table.to_owned(),
+            column: column.to_owned(),
+            references: references.to_owned(),
+            referenced_column: referenced_column.to_owned(),
+        },
+    ))
+}
+
+fn soft_delete(input: &str) -> nom::IResult<&str, (&str, &str)> {
+    use nom::bytes::complete::tag_no_case;
+    use nom::character::complete::{char, multispace0, multispace1};
+    let (input, _) = tag_no_case("soft")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, _) = tag_no_case("delete")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, table_column) = table_column(input)?;
+    let (input, _) = char(';')(input)?;
+    let (input, _) = multispace0(input)?;
+    Ok((input, table_column))
+}
+
+fn unmask_view(input: &str) -> nom::IResult<&str, MaskStatement> {
+    use nom::bytes::complete::tag_no_case;
+    use nom::character::complete::{multispace0, multispace1};
+    let (input, _) = tag_no_case("view")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, view) = ident(input)?;
+    let (input, _) = multispace0(input)?;
+    Ok((input, MaskStatement::View(view.to_owned())))
+}
+
+fn mask_column(input: &str) -> nom::IResult<&str, MaskStatement> {
+    use nom::bytes::complete::tag_no_case;
+    use nom::character::complete::multispace1;
+    let (input, _) = tag_no_case("mask")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, (table, column)) = table_column(input)?;
+    Ok((
+        input,
+        MaskStatement::Column(table.to_owned(), column.to_owned()),
+    ))
+}
+
+fn unmask(input: &str) -> nom::IResult<&str, MaskStatement> {
+    use nom::bytes::complete::tag_no_case;
+    use nom::character::complete::multispace1;
+    let (input, _) = tag_no_case("unmask")(input)?;
+    let (input, _) = multispace1(input)?;
+    unmask_view(input)
+}
+
+fn mask_statement(input: &str) -> nom::IResult<&str, MaskStatement> {
+    use nom::branch::alt;
+    use nom::character::complete::{char, multispace0};
+    let (input, statement) = alt((mask_column, unmask))(input)?;
+    let (input, _) = char(';')(input)?;
+    let (input, _) = multispace0(input)?;
+    Ok((input, statement))
+}
+
+/// Parses the argument of `UNNEST`, which must be a plain, possibly table-qualified, column.
+fn unnest_column(input: &str) -> Option<nom_sql::Column> {
+    let (table, name) = match input.find('.') {
+        Some(i) => (Some(&input[..i]), &input[i + 1..]),
+        None => (None, input),
+    };
+    let is_ident = |s: &str| match ident(s) {
+        Ok((rest, id)) => rest.is_empty() && !id.is_empty(),
+        Err(_) => false,
+    };
+    if !is_ident(name) || !table.map(is_ident).unwrap_or(true) {
+        return None;
+    }
+    Some(nom_sql::Column {
+        name: name.to_owned(),
+        alias: None,
+        table: table.map(String::from),
+        function: None,
+    })
+}
+
+/// Replaces `UNNEST(column)` in `query`, which nom_sql does not know about, with just `column`.
+///
+/// Returns the rewritten query along with the unnested column, if there was one.
+fn strip_unnest(query: &str) -> Result<(String, Option<nom_sql::Column>), String> {
+    // lowercasing ASCII leaves all byte offsets as they are
+    let lower = query.to_ascii_lowercase();
+    let mut stripped = String::with_capacity(query.len());
+    let mut column = None;
+    let mut copied = 0;
+    let mut search = 0;
+    while let Some(i) = lower[search..].find("unnest") {
+        let start = search + i;
+        search = start + "unnest".len();
+
+        // only the whole word counts, and only if it is called
+        let in_word = lower[..start]
+            .chars()
+            .next_back()
+            .map(|c| c.is_ascii_alphanumeric() || c == '_')
+            .unwrap_or(false);
+        let args = lower[search..].trim_start();
+        if in_word || !args.starts_with('(') {
+            continue;
+        }
+        let open = lower.len() - args.len();
+        let close = match query[open..].find(')') {
+            Some(j) => open + j,
+            None => return Err(format!("Query \"{}\": unterminated UNNEST", query)),
+        };
+        let arg = query[open + 1..close].trim();
+
+        if column.is_some() {
+            return Err(format!(
+                "Query \"{}\": only one column can be unnested per query",
+                query
+            ));
+        }
+        column = Some(unnest_column(arg).ok_or_else(|| {
+            format!(
+                "Query \"{}\": cannot UNNEST {}, which is not a column",
+                query, arg
+            )
+        })?);
+
+        stripped.push_str(&query[copied..start]);
+        stripped.push_str(arg);
+        copied = close + 1;
+        search = copied;
+    }
+    stripped.push_str(&query[copied..]);
+    Ok((stripped, column))
+}
+
+/// Finds the parenthesis that closes the one at `open`, skipping over quoted strings.
+fn closing_paren(query: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    for (i, c) in query[open..].char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            (None, _) => {}
+        }
+    }
+    None
+}
+
+/// Picks a column that `condition` refers to, for a filtered `COUNT(*)` to count over.
+fn condition_column(condition: &str) -> Option<String> {
+    use nom_sql::{ConditionBase, ConditionExpression};
+    fn first_column(ce: &ConditionExpression) -> Option<&nom_sql::Column> {
+        match *ce {
+            ConditionExpression::LogicalOp(ref ct) | ConditionExpression::ComparisonOp(ref ct) => {
+                first_column(&ct.left).or_else(|| first_column(&ct.right))
+            }
+            ConditionExpression::NegationOp(ref inner)
+            | ConditionExpression::Bracketed(ref inner) => first_column(inner),
+            ConditionExpression::Base(ConditionBase::Field(ref c)) => Some(c),
+            _ => None,
+        }
+    }
+
+    let q = sql_parser::parse_query(&format!("SELECT x FROM t WHERE {};", condition)).ok()?;
+    let c = match q {
+        SqlQuery::Select(ref s) => first_column(s.where_clause.as_ref()?)?,
+        _ => return None,
+    };
+    Some(match c.table {
+        Some(ref table) => format!("{}.{}", table, c.name),
+        None => c.name.clone(),
+    })
+}
+
+/// Rewrites aggregations with a `FILTER (WHERE condition)` clause in `query`, which nom_sql does
+/// not know about, into aggregations over `CASE WHEN condition THEN .. END`, which it does.
+///
+/// Only `COUNT` and `SUM` can be filtered. A filtered `COUNT(*)` counts over one of the columns in
+/// the condition instead, which makes no difference, since filtered counts count every row that
+/// matches regardless of its value.
+fn rewrite_aggregate_filters(query: &str) -> Result<String, String> {
+    // lowercasing ASCII leaves all byte offsets as they are
+    let lower = query.to_ascii_lowercase();
+    let mut rewritten = String::with_capacity(query.len());
+    let mut copied = 0;
+    let mut search = 0;
+    while let Some(i) = lower[search..].find("filter") {
+        let start = search + i;
+        search = start + "filter".len();
+
+        // only the whole word counts, and only right after a call
+        let in_word = lower[search..]
+            .chars()
+            .next()
+            .map(|c| c.is_ascii_alphanumeric() || c == '_')
+            .unwrap_or(false);
+        let args = lower[search..].trim_start();
+        let before = lower[copied..start].trim_end();
+        if in_word || !args.starts_with('(') || !before.ends_with(')') {
+            continue;
+        }
+        let open = lower.len() - args.len();
+        let close = closing_paren(&lower, open)
+            .ok_or_else(|| format!("Query \"{}\": unterminated FILTER", query))?;
+        let clause = query[open + 1..close].trim();
+        if !clause.to_ascii_lowercase().starts_with("where ") {
+            return Err(format!(
+                "Query \"{}\": FILTER must hold a WHERE clause",
+                query
+            ));
+        }
+        let condition = clause["where".len()..].trim();
+
+        // the filtered aggregation's argument is a column, or *, so it holds no parentheses
+        let arg_close = copied + before.len() - 1;
+        let arg_open = match lower[copied..arg_close].rfind('(') {
+            Some(j) => copied + j,
+            None => continue,
+        };
+        let func_end = lower[..arg_open].trim_end().len();
+        let func_start = lower[..func_end]
+            .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .map(|j| j + 1)
+            .unwrap_or(0)
+            .max(copied);
+        let arg = query[arg_open + 1..arg_close].trim();
+        let over = match &lower[func_start..func_end] {
+            _ if arg.to_ascii_lowercase().starts_with("distinct ") => {
+                return Err(format!(
+                    "Query \"{}\": DISTINCT aggregations cannot be filtered",
+                    query
+                ));
+            }
+            "count" if arg == "*" => condition_column(condition).ok_or_else(|| {
+                format!(
+                    "Query \"{}\": FILTER condition {} refers to no column",
+                    query, condition
+                )
+            })?,
+            "count" | "sum" => arg.to_owned(),
+            _ => {
+                return Err(format!(
+                    "Query \"{}\": only COUNT and SUM can be filtered",
+                    query
+                ));
+            }
+        };
+
+        rewritten.push_str(&query[copied..func_start]);
+        rewritten.push_str(&format!(
+            "{}(CASE WHEN {} THEN {} END)",
+            &query[func_start..func_end],
+            condition,
+            over
+        ));
+        copied = close + 1;
+        search = copied;
+    }
+    rewritten.push_str(&query[copied..]);
+    Ok(rewritten)
+}
+
+/// Adds a condition to `query` that leaves out the rows of each table in `soft_deletes` that have
+/// been deleted, so that the filter ends up right below the table's base node.
+fn hide_deleted_rows(query: SqlQuery, soft_deletes: &HashMap<String, String>) -> SqlQuery {
+    match query {
+        SqlQuery::Select(mut sq) => {
+            hide_deleted_in_select(&mut sq, soft_deletes);
+            SqlQuery::Select(sq)
+        }
+        SqlQuery::CompoundSelect(mut csq) => {
+            for (_, sq) in &mut csq.selects {
+                hide_deleted_in_select(sq, soft_deletes);
+            }
+            SqlQuery::CompoundSelect(csq)
+        }
+        q => q,
+    }
+}
+
+fn hide_deleted_in_select(
+    sq: &mut nom_sql::SelectStatement,
+    soft_deletes: &HashMap<String, String>,
+) {
+    use nom_sql::{
+        ConditionBase, ConditionExpression, ConditionTree, JoinRightSide, Literal, Operator,
+    };
+
+    let mut tables = sq.tables.clone();
+    for jc in &mut sq.join {
+        match jc.right {
+            JoinRightSide::Table(ref t) => tables.push(t.clone()),
+            JoinRightSide::Tables(ref ts) => tables.extend(ts.iter().cloned()),
+            JoinRightSide::NestedSelect(ref mut nested, _) => {
+                hide_deleted_in_select(nested, soft_deletes)
+            }
+            JoinRightSide::NestedJoin(_) => {}
+        }
+    }
+
+    for t in tables {
+        let column = match soft_deletes.get(&t.name) {
+            Some(column) => column,
+            None => continue,
+        };
+        let live = ConditionExpression::ComparisonOp(ConditionTree {
+            operator: Operator::Equal,
+            left: Box::new(ConditionExpression::Base(ConditionBase::Field(
+                nom_sql::Column {
+                    name: column.clone(),
+                    alias: None,
+                    table: Some(t.alias.unwrap_or(t.name)),
+                    function: None,
+                },
+            ))),
+            right: Box::new(ConditionExpression::Base(ConditionBase::Literal(
+                Literal::Integer(0),
+            ))),
+        });
+        sq.where_clause = Some(match sq.where_clause.take() {
+            None => live,
+            Some(wc) => ConditionExpression::LogicalOp(ConditionTree {
+                operator: Operator::And,
+                left: Box::new(wc),
+                right: Box::new(live),
+            }),
+        });
+    }
+}
+
+/// The modifiers that may precede a query in a recipe.
+#[derive(Default)]
+struct QueryModifiers<'a> {
+    /// Whether the query gets a reader (`QUERY` or `VIEW`).
+    public: bool,
+    name: Option<&'a str>,
+    priority: Option<ReplayPriority>,
+    /// Whether the query's view keeps only the number of rows for each key.
+    count_only: bool,
+    /// Whether the query sees soft-deleted rows.
+    with_deleted: bool,
+}
+
+fn query_prefix(input: &str) -> nom::IResult<&str, QueryModifiers<'_>> {
     use nom::branch::alt;
     use nom::bytes::complete::tag_no_case;
     use nom::character::complete::{char, multispace0, space1};
@@ -91,12 +577,43 @@ fn query_prefix(input: &str) -> nom::IResult<&str, (bool, Option<&str>)> {
     let (input, _) = multispace0(input)?;
     let (input, name) = opt(terminated(ident, multispace0))(input)?;
     let (input, _) = multispace0(input)?;
+    // only named queries can be given a priority, be count-only, or see deleted rows, since all
+    // of those are set by name
+    let (input, priority) = match name {
+        Some(_) => opt(replay_priority)(input)?,
+        None => (input, None),
+    };
+    let (input, count_only) = match name {
+        Some(_) => opt(count_only_flag)(input)?,
+        None => (input, None),
+    };
+    let count_only = count_only.is_some();
+    let (input, with_deleted) = match name {
+        Some(_) => opt(with_deleted_flag)(input)?,
+        None => (input, None),
+    };
+    let with_deleted = with_deleted.is_some();
     let (input, _) = char(':')(input)?;
     let (input, _) = multispace0(input)?;
-    Ok((input, (public.is_some(), name)))
+    Ok((
+        input,
+        QueryModifiers {
+            public: public.is_some(),
+            name,
+            priority,
+            count_only,
+            with_deleted,
+        },
+    ))
+}
+
+/// A query along with its modifiers.
+struct ParsedQuery<'a> {
+    modifiers: QueryModifiers<'a>,
+    query: SqlQuery,
 }
 
-fn query_expr(input: &str) -> nom::IResult<&str, (bool, Option<&str>, SqlQuery)> {
+fn query_expr(input: &str) -> nom::IResult<&str, ParsedQuery<'_>> {
     use nom::character::complete::multispace0;
     use nom::combinator::opt;
     let (input, prefix) = opt(query_prefix)(input)?;
@@ -112,14 +629,29 @@ fn query_expr(input: &str) -> nom::IResult<&str, (bool, Option<&str>, SqlQuery)>
     let (input, _) = multispace0(input)?;
     Ok((
         input,
-        match prefix {
-            None => (false, None, expr),
-            Some((public, name)) => (public, name, expr),
+        ParsedQuery {
+            modifiers: prefix.unwrap_or_default(),
+            query: expr,
         },
     ))
 }
 
-fn query_exprs(input: &str) -> nom::IResult<&str, Vec<(bool, Option<&str>, SqlQuery)>> {
+/// The statements of a recipe, as `Recipe::parse` picks them out.
+struct ParsedRecipe {
+    /// The queries, with their names and whether they get a reader.
+    queries: Vec<(Option<String>, SqlQuery, bool)>,
+    priorities: HashMap<String, ReplayPriority>,
+    count_only: HashSet<String>,
+    foreign_keys: Vec<ForeignKey>,
+    /// The column each query unnests, by query name.
+    unnest: HashMap<String, nom_sql::Column>,
+    masks: MaskPolicy,
+    /// The column that marks rows as deleted, by table name.
+    soft_deletes: HashMap<String, String>,
+    with_deleted: HashSet<String>,
+}
+
+fn query_exprs(input: &str) -> nom::IResult<&str, Vec<ParsedQuery<'_>>> {
     nom::multi::many1(query_expr)(input)
 }
 
@@ -146,6 +678,13 @@ impl Recipe {
             expressions: HashMap::default(),
             expression_order: Vec::default(),
             aliases: HashMap::default(),
+            priorities: HashMap::default(),
+            count_only: HashSet::default(),
+            foreign_keys: Vec::default(),
+            unnest: HashMap::default(),
+            masks: MaskPolicy::default(),
+            soft_deletes: HashMap::default(),
+            with_deleted: HashSet::default(),
             version: 0,
             prior: None,
             inc: match log {
@@ -185,6 +724,11 @@ impl Recipe {
         self.inc.as_mut().unwrap().enable_reuse(reuse_type)
     }
 
+    /// Only have the joins of future queries carry the columns that the queries use.
+    pub(super) fn enable_column_pruning(&mut self, enabled: bool) {
+        self.inc.as_mut().unwrap().enable_column_pruning(enabled)
+    }
+
     pub(in crate::controller) fn resolve_alias(&self, alias: &str) -> Option<&str> {
         self.aliases.get(alias).map(|ref qid| {
             let (ref internal_qn, _, _) = self.expressions[qid];
@@ -249,9 +793,17 @@ impl Recipe {
         let cleaned_recipe_text = lines.join("\n");
 
         // parse and compute differences to current recipe
-        let parsed_queries = Recipe::parse(&cleaned_recipe_text)?;
-
-        Ok(Recipe::from_queries(parsed_queries, log))
+        let parsed = Recipe::parse(&cleaned_recipe_text)?;
+
+        let mut recipe = Recipe::from_queries(parsed.queries, log);
+        recipe.priorities = parsed.priorities;
+        recipe.count_only = parsed.count_only;
+        recipe.foreign_keys = parsed.foreign_keys;
+        recipe.unnest = parsed.unnest;
+        recipe.masks = parsed.masks;
+        recipe.soft_deletes = parsed.soft_deletes;
+        recipe.with_deleted = parsed.with_deleted;
+        Ok(recipe)
     }
 
     /// Creates a recipe from a set of pre-parsed `SqlQuery` structures.
@@ -304,6 +856,13 @@ impl Recipe {
             expressions,
             expression_order,
             aliases,
+            priorities: HashMap::default(),
+            count_only: HashSet::default(),
+            foreign_keys: Vec::default(),
+            unnest: HashMap::default(),
+            masks: MaskPolicy::default(),
+            soft_deletes: HashMap::default(),
+            with_deleted: HashSet::default(),
             security_config: None,
             version: 0,
             prior: None,
@@ -325,6 +884,10 @@ impl Recipe {
             removed_leaves: Vec::default(),
             expressions_added: 0,
             expressions_removed: 0,
+            queries_added: Vec::default(),
+            queries_removed: Vec::default(),
+            queries_changed: Vec::default(),
+            dataflow: DataflowDiff::default(),
         };
 
         if self.security_config.is_some() {
@@ -397,11 +960,29 @@ impl Recipe {
             }
         };
 
+        // a name that is both added and removed now refers to a different query
+        let added_names: Vec<_> = added.iter().filter_map(|qid| self.name_of(qid)).collect();
+        let removed_names: Vec<_> = match self.prior {
+            None => Vec::new(),
+            Some(ref pr) => removed.iter().filter_map(|qid| pr.name_of(qid)).collect(),
+        };
+        let (queries_changed, queries_added): (Vec<_>, Vec<_>) = added_names
+            .into_iter()
+            .partition(|name| removed_names.contains(name));
+        let queries_removed = removed_names
+            .into_iter()
+            .filter(|name| !queries_changed.contains(name))
+            .collect();
+
         let mut result = ActivationResult {
             new_nodes: HashMap::default(),
             removed_leaves: Vec::default(),
             expressions_added: added.len(),
             expressions_removed: removed.len(),
+            queries_added,
+            queries_removed,
+            queries_changed,
+            dataflow: DataflowDiff::default(),
         };
 
         // upgrade schema version *before* applying changes, so that new queries are correctly
@@ -451,9 +1032,47 @@ impl Recipe {
         // add new queries to the Soup graph carried by `mig`, and reflect state in the
         // incorporator in `inc`. `NodeIndex`es for new nodes are collected in `new_nodes` to be
         // returned to the caller (who may use them to obtain mutators and getters)
+        // deleted rows have to be marked from the start, so tables cannot switch to soft deletes
+        let created: HashSet<_> = added
+            .iter()
+            .filter_map(|qid| match self.expressions[qid].1 {
+                SqlQuery::CreateTable(ref ctq) => Some(&ctq.table.name),
+                _ => None,
+            })
+            .collect();
+        for (table, column) in &self.soft_deletes {
+            let before = self.prior.as_ref().and_then(|p| p.soft_deletes.get(table));
+            if before != Some(column) && !created.contains(table) {
+                return Err(format!(
+                    "soft deletes from {} must be declared along with the table",
+                    table
+                ));
+            }
+        }
+
         for qid in added {
             let (n, q, is_leaf) = self.expressions[&qid].clone();
 
+            // queries only see soft-deleted rows if they ask for them
+            let q = match n {
+                Some(ref name) if self.with_deleted.contains(name) => q,
+                _ => hide_deleted_rows(q, &self.soft_deletes),
+            };
+            let soft_delete = match q {
+                SqlQuery::CreateTable(ref ctq) => self
+                    .soft_deletes
+                    .get(&ctq.table.name)
+                    .map(|column| (ctq.table.name.clone(), column.clone())),
+                _ => None,
+            };
+
+            if let Some(column) = n.as_ref().and_then(|name| self.unnest.get(name)) {
+                self.inc
+                    .as_mut()
+                    .unwrap()
+                    .unnest_column(n.as_ref().unwrap(), column.clone());
+            }
+
             // add the query
             let qfp = self
                 .inc
@@ -461,6 +1080,11 @@ impl Recipe {
                 .unwrap()
                 .add_parsed_query(q, n.clone(), is_leaf, mig)?;
 
+            if let Some((table, column)) = soft_delete {
+                mig.soft_delete(qfp.query_leaf, &column)
+                    .map_err(|e| format!("cannot soft-delete from {}: {}", table, e))?;
+            }
+
             // If the user provided us with a query name, use that.
             // If not, use the name internally used by the QFP.
             let query_name = match n {
@@ -468,6 +1092,14 @@ impl Recipe {
                 None => qfp.name.clone(),
             };
 
+            if self.count_only.contains(&query_name) {
+                mig.maintain_count_only(qfp.query_leaf)
+                    .map_err(|e| format!("cannot make query {} count-only: {}", query_name, e))?;
+            }
+            if !self.masks.views.contains(&query_name) {
+                mig.mask_reads(qfp.query_leaf, &self.masks.columns);
+            }
+
             result.new_nodes.insert(query_name, qfp.query_leaf);
         }
 
@@ -503,9 +1135,70 @@ impl Recipe {
             })
             .collect();
 
+        // cascading deletes follow the foreign keys, now that all the tables they refer to exist.
+        // a cascade whose foreign key is gone, or whose tables have been replaced, is removed, and
+        // set up again over the current tables if it is still wanted.
+        let mut wanted = Vec::with_capacity(self.foreign_keys.len());
+        for fk in &self.foreign_keys {
+            wanted.push((fk, self.resolve_foreign_key(fk)?));
+        }
+        let mut kept = Vec::new();
+        for (ni, cascade) in mig.cascades() {
+            if wanted.iter().any(|&(_, w)| w == cascade) {
+                kept.push(cascade);
+            } else {
+                result.removed_leaves.push(ni);
+            }
+        }
+        for (fk, cascade) in wanted {
+            if kept.contains(&cascade) {
+                continue;
+            }
+            let (parent, parent_col, child, child_col) = cascade;
+            mig.cascade_deletes(parent, parent_col, child, child_col)
+                .map_err(|e| {
+                    format!(
+                        "cannot cascade deletes from {} to {}: {}",
+                        fk.references, fk.table, e
+                    )
+                })?;
+        }
+
         Ok(result)
     }
 
+    /// The parent table, referenced column, child table, and referencing column of `fk`.
+    fn resolve_foreign_key(
+        &self,
+        fk: &ForeignKey,
+    ) -> Result<(NodeIndex, usize, NodeIndex, usize), String> {
+        let inc = self.inc.as_ref().unwrap();
+        let resolve = |table: &str, column: &str| -> Result<(NodeIndex, usize), String> {
+            let schema = inc
+                .get_base_schema(table)
+                .ok_or_else(|| format!("foreign key refers to unknown table {}", table))?;
+            let col = schema
+                .fields
+                .iter()
+                .position(|f| f.column.name == column)
+                .ok_or_else(|| format!("table {} has no column {}", table, column))?;
+            Ok((self.node_addr_for(table)?, col))
+        };
+
+        let (child, child_col) = resolve(&fk.table, &fk.column)?;
+        let (parent, parent_col) = resolve(&fk.references, &fk.referenced_column)?;
+        Ok((parent, parent_col, child, child_col))
+    }
+
+    /// The name of the expression `qid`, which for a table is the name of the table.
+    fn name_of(&self, qid: &QueryID) -> Option<String> {
+        match self.expressions[qid] {
+            (Some(ref name), _, _) => Some(name.clone()),
+            (None, SqlQuery::CreateTable(ref ctq), _) => Some(ctq.table.name.clone()),
+            (None, _, _) => None,
+        }
+    }
+
     /// Work out the delta between two recipes.
     /// Returns two sets of `QueryID` -> `SqlQuery` mappings:
     /// (1) those queries present in `self`, but not in `other`; and
@@ -557,6 +1250,13 @@ impl Recipe {
             expressions: self.expressions.clone(),
             expression_order: self.expression_order.clone(),
             aliases: self.aliases.clone(),
+            priorities: self.priorities.clone(),
+            count_only: self.count_only.clone(),
+            foreign_keys: self.foreign_keys.clone(),
+            unnest: self.unnest.clone(),
+            masks: self.masks.clone(),
+            soft_deletes: self.soft_deletes.clone(),
+            with_deleted: self.with_deleted.clone(),
             version: self.version + 1,
             inc: prior_inc,
             log: self.log.clone(),
@@ -580,6 +1280,17 @@ impl Recipe {
             );
         }
         new.aliases.extend(add_rp.aliases);
+        new.priorities.extend(add_rp.priorities);
+        new.count_only.extend(add_rp.count_only);
+        new.unnest.extend(add_rp.unnest);
+        new.masks.extend(add_rp.masks);
+        new.soft_deletes.extend(add_rp.soft_deletes);
+        new.with_deleted.extend(add_rp.with_deleted);
+        for fk in add_rp.foreign_keys {
+            if !new.foreign_keys.contains(&fk) {
+                new.foreign_keys.push(fk);
+            }
+        }
 
         // return new recipe as replacement for self
         Ok(new)
@@ -601,7 +1312,46 @@ impl Recipe {
         self.inc = Some(new_inc);
     }
 
-    fn parse(recipe_text: &str) -> Result<Vec<(Option<String>, SqlQuery, bool)>, String> {
+    /// Have the base table `name` be backed by the data-flow node `to` from now on.
+    ///
+    /// The queries over the table keep reading from the node that backed it before until they
+    /// are added again.
+    pub(super) fn move_base(&mut self, name: &str, to: NodeIndex) {
+        self.inc
+            .as_mut()
+            .expect("need SQL incorporator")
+            .move_base(name, to);
+    }
+
+    /// Named queries whose replay priority differs from the one they had in the prior recipe,
+    /// along with their new priority.
+    pub(super) fn changed_replay_priorities(&self) -> Vec<(String, ReplayPriority)> {
+        let empty = HashMap::new();
+        let before = self.prior.as_ref().map(|p| &p.priorities).unwrap_or(&empty);
+        let mut changed: Vec<_> = self
+            .priorities
+            .iter()
+            .filter(|&(name, p)| before.get(name) != Some(p))
+            .map(|(name, &p)| (name.clone(), p))
+            .collect();
+        // queries that are still around, but lost their priority, go back to the default
+        changed.extend(
+            before
+                .keys()
+                .filter(|name| {
+                    !self.priorities.contains_key(*name) && self.aliases.contains_key(*name)
+                })
+                .map(|name| (name.clone(), ReplayPriority::default())),
+        );
+        changed
+    }
+
+    /// The columns that views mask, and who may see them anyway.
+    pub(super) fn masks(&self) -> &MaskPolicy {
+        &self.masks
+    }
+
+    fn parse(recipe_text: &str) -> Result<ParsedRecipe, String> {
         let lines: Vec<&str> = recipe_text
             .lines()
             .filter(|l| !l.is_empty() && !l.starts_with('#'))
@@ -632,9 +1382,50 @@ impl Recipe {
             i += 1;
         }
 
+        // nom_sql does not know about foreign keys, so we pick those out ourselves
+        let mut foreign_keys = Vec::new();
+        query_strings.retain(|q| match foreign_key(q) {
+            Ok((remainder, fk)) if remainder.is_empty() => {
+                if !foreign_keys.contains(&fk) {
+                    foreign_keys.push(fk);
+                }
+                false
+            }
+            _ => true,
+        });
+
+        // or about masking personal data
+        let mut masks = MaskPolicy::default();
+        query_strings.retain(|q| match mask_statement(q) {
+            Ok((remainder, statement)) if remainder.is_empty() => {
+                masks.add(statement);
+                false
+            }
+            _ => true,
+        });
+
+        // or about soft deletes
+        let mut soft_deletes = HashMap::new();
+        query_strings.retain(|q| match soft_delete(q) {
+            Ok((remainder, (table, column))) if remainder.is_empty() => {
+                soft_deletes.insert(table.to_owned(), column.to_owned());
+                false
+            }
+            _ => true,
+        });
+
+        // nor does it know about UNNEST, so we remember the column and hand it the rest, or
+        // about filtered aggregations, which we hand it as the CASE WHEN they are equivalent to
+        let query_strings = query_strings
+            .iter()
+            .map(|q| rewrite_aggregate_filters(q).and_then(|q| strip_unnest(&q)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut unnest = HashMap::new();
+        let mut unnest_errors = Vec::new();
         let parsed_queries = query_strings.iter().fold(
             Vec::new(),
-            |mut acc: Vec<Result<(bool, Option<&str>, SqlQuery), String>>, q| {
+            |mut acc: Vec<Result<ParsedQuery, String>>, (q, unnested)| {
                 match query_exprs(q) {
                     Result::Err(e) => {
                         // we got a parse error
@@ -649,20 +1440,67 @@ impl Recipe {
                                 remainder
                             )
                         );
+                        if let Some(ref column) = *unnested {
+                            match parsed.as_slice() {
+                                [ParsedQuery {
+                                    modifiers:
+                                        QueryModifiers {
+                                            public: true,
+                                            name: Some(name),
+                                            ..
+                                        },
+                                    query: SqlQuery::Select(_),
+                                }] => {
+                                    unnest.insert((*name).to_owned(), column.clone());
+                                }
+                                _ => unnest_errors.push(format!(
+                                    "Query \"{}\": only named queries with a reader can UNNEST",
+                                    q
+                                )),
+                            }
+                        }
                         acc.extend(parsed.into_iter().map(|p| Ok(p)).collect::<Vec<_>>());
                     }
                 }
                 acc
             },
         );
+        if !unnest_errors.is_empty() {
+            return Err(unnest_errors.join("\n"));
+        }
 
-        Ok(parsed_queries
+        let mut priorities = HashMap::new();
+        let mut count_only = HashSet::new();
+        let mut with_deleted = HashSet::new();
+        let queries = parsed_queries
             .into_iter()
             .map(|pr| {
-                let pr = pr.unwrap();
-                (pr.1.map(String::from), pr.2, pr.0)
+                let ParsedQuery {
+                    modifiers: m,
+                    query,
+                } = pr.unwrap();
+                if let (Some(name), Some(priority)) = (m.name, m.priority) {
+                    priorities.insert(name.to_owned(), priority);
+                }
+                if let (Some(name), true) = (m.name, m.count_only) {
+                    count_only.insert(name.to_owned());
+                }
+                if let (Some(name), true) = (m.name, m.with_deleted) {
+                    with_deleted.insert(name.to_owned());
+                }
+                (m.name.map(String::from), query, m.public)
             })
-            .collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        Ok(ParsedRecipe {
+            queries,
+            priorities,
+            count_only,
+            foreign_keys,
+            unnest,
+            masks,
+            soft_deletes,
+            with_deleted,
+        })
     }
 
     /// Returns the predecessor from which this `Recipe` was migrated to.
@@ -724,6 +1562,15 @@ impl Recipe {
         }
     }
 
+    /// Reverts to prior version of recipe without having activated this one, handing the
+    /// incorporator state back to the prior version.
+    pub(super) fn discard(mut self) -> Recipe {
+        let inc = self.inc.take();
+        let mut prior = self.revert();
+        prior.inc = inc;
+        prior
+    }
+
     pub(super) fn queries_for_nodes(&self, nodes: Vec<NodeIndex>) -> Vec<String> {
         nodes
             .iter()
@@ -743,6 +1590,10 @@ impl Recipe {
         let mut recovery = self.clone();
         recovery.prior = Some(Box::new(self.clone()));
         recovery.next();
+        // cascades over tables that are rebuilt are rebuilt along with them
+        recovery.foreign_keys.retain(|fk| {
+            !affected_queries.contains(&fk.table) && !affected_queries.contains(&fk.references)
+        });
 
         // remove from recipe
         for q in affected_queries {
@@ -890,4 +1741,201 @@ mod tests {
         let r1 = r0.replace(r1_t).unwrap();
         assert_eq!(r1.expressions.len(), 2);
     }
+
+    #[test]
+    fn it_tracks_replay_priorities() {
+        let r0 = Recipe::blank(None);
+
+        let r1_txt = "QUERY q_0 PRIORITY interactive: SELECT a FROM b;\n\
+                      QUERY q_1 priority Batch : SELECT x FROM y;\n\
+                      QUERY q_2: SELECT c FROM b;";
+        let r1_t = Recipe::from_str(r1_txt, None).unwrap();
+        let r1 = r0.replace(r1_t).unwrap();
+        assert_eq!(r1.expressions.len(), 3);
+        let mut changed = r1.changed_replay_priorities();
+        changed.sort();
+        assert_eq!(
+            changed,
+            vec![
+                ("q_0".to_owned(), ReplayPriority::Interactive),
+                ("q_1".to_owned(), ReplayPriority::Batch),
+            ]
+        );
+
+        // only the newly prioritized query changes
+        let r2 = r1
+            .extend("QUERY q_2 PRIORITY interactive: SELECT c FROM b;")
+            .unwrap();
+        assert_eq!(
+            r2.changed_replay_priorities(),
+            vec![("q_2".to_owned(), ReplayPriority::Interactive)]
+        );
+
+        // queries that lose their priority go back to the default, and removed ones are ignored
+        let r3_t = Recipe::from_str("QUERY q_0: SELECT a FROM b;", None).unwrap();
+        let r3 = r2.replace(r3_t).unwrap();
+        assert_eq!(
+            r3.changed_replay_priorities(),
+            vec![("q_0".to_owned(), ReplayPriority::Normal)]
+        );
+    }
+
+    #[test]
+    fn it_tracks_count_only_queries() {
+        let r0 = Recipe::blank(None);
+
+        let r1_txt = "QUERY q_0 COUNT ONLY: SELECT a FROM b;\n\
+                      QUERY q_1 PRIORITY batch count only: SELECT x FROM y;\n\
+                      QUERY q_2: SELECT c FROM b;";
+        let r1_t = Recipe::from_str(r1_txt, None).unwrap();
+        let r1 = r0.replace(r1_t).unwrap();
+        assert_eq!(r1.expressions.len(), 3);
+        let mut count_only: Vec<_> = r1.count_only.iter().cloned().collect();
+        count_only.sort();
+        assert_eq!(count_only, vec!["q_0".to_owned(), "q_1".to_owned()]);
+        assert_eq!(
+            r1.changed_replay_priorities(),
+            vec![("q_1".to_owned(), ReplayPriority::Batch)]
+        );
+
+        let r2 = r1.extend("QUERY q_3 COUNT ONLY: SELECT d FROM b;").unwrap();
+        assert!(r2.count_only.contains("q_3"));
+        assert!(r2.count_only.contains("q_0"));
+    }
+
+    #[test]
+    fn it_parses_foreign_keys() {
+        let r0 = Recipe::blank(None);
+
+        let r1_txt = "CREATE TABLE Class (c_id int, PRIMARY KEY(c_id));\n\
+                      CREATE TABLE Post (p_id int, p_cid int, PRIMARY KEY(p_id));\n\
+                      foreign key Post (p_cid) references Class(c_id) on delete cascade;";
+        let r1_t = Recipe::from_str(r1_txt, None).unwrap();
+        let r1 = r0.replace(r1_t).unwrap();
+        assert_eq!(r1.expressions.len(), 2);
+        let fk = ForeignKey {
+            table: "Post".to_owned(),
+            column: "p_cid".to_owned(),
+            references: "Class".to_owned(),
+            referenced_column: "c_id".to_owned(),
+        };
+        assert_eq!(r1.foreign_keys, vec![fk.clone()]);
+
+        // declaring a foreign key again does not add another one
+        let r2 = r1
+            .extend("FOREIGN KEY Post(p_cid) REFERENCES Class(c_id) ON DELETE CASCADE;")
+            .unwrap();
+        assert_eq!(r2.foreign_keys, vec![fk]);
+
+        // other actions are not supported
+        assert!(foreign_key("FOREIGN KEY Post(p_cid) REFERENCES Class(c_id);").is_err());
+    }
+
+    #[test]
+    fn it_parses_masks() {
+        let r0 = Recipe::blank(None);
+
+        let r1_txt = "CREATE TABLE Users (u_id int, email text, PRIMARY KEY(u_id));\n\
+                      MASK Users(email);\n\
+                      unmask view Support;";
+        let r1_t = Recipe::from_str(r1_txt, None).unwrap();
+        let r1 = r0.replace(r1_t).unwrap();
+        assert_eq!(r1.expressions.len(), 1);
+        assert_eq!(
+            r1.masks().columns,
+            vec![("Users".to_owned(), "email".to_owned())]
+        );
+        assert!(r1.masks().views.contains("Support"));
+
+        // masks accumulate as the recipe is extended
+        let r2 = r1.extend("MASK Users(email);\nMASK Users(u_id);").unwrap();
+        assert_eq!(r2.masks().columns.len(), 2);
+        assert!(r2.masks().views.contains("Support"));
+
+        // clients cannot be exempted by the identity they claim
+        assert!(mask_statement("UNMASK IDENTITY 'auditor';").is_err());
+    }
+
+    #[test]
+    fn it_hides_deleted_rows() {
+        let r_txt = "CREATE TABLE Post (p_id int, deleted int, PRIMARY KEY(p_id));\n\
+                     SOFT DELETE Post(deleted);\n\
+                     QUERY live: SELECT p_id FROM Post AS p WHERE p_id = ?;\n\
+                     QUERY everything WITH DELETED: SELECT p_id FROM Post WHERE p_id = ?;";
+        let r = Recipe::from_str(r_txt, None).unwrap();
+        assert_eq!(r.expressions.len(), 3);
+        assert_eq!(r.soft_deletes["Post"], "deleted");
+        assert!(r.with_deleted.contains("everything"));
+        assert!(!r.with_deleted.contains("live"));
+
+        let q = sql_parser::parse_query("SELECT p_id FROM Post AS p WHERE p_id = ?;").unwrap();
+        let hidden = hide_deleted_rows(q, &r.soft_deletes);
+        assert_eq!(
+            hidden.to_string(),
+            "SELECT p_id FROM Post AS p WHERE p_id = ? AND p.deleted = 0"
+        );
+    }
+
+    #[test]
+    fn it_tracks_unnested_queries() {
+        let r0 = Recipe::blank(None);
+
+        let r1_txt = "CREATE TABLE Post (id int, tags text);\n\
+                      QUERY PostsByTag: SELECT id, UNNEST(tags) AS tag FROM Post WHERE tags = ?;\n\
+                      QUERY Posts: SELECT id, unnest_count FROM Post;";
+        let r1_t = Recipe::from_str(r1_txt, None).unwrap();
+        let r1 = r0.replace(r1_t).unwrap();
+        assert_eq!(r1.expressions.len(), 3);
+        assert_eq!(r1.unnest.len(), 1);
+        assert_eq!(r1.unnest["PostsByTag"].name, "tags");
+
+        let r2 = r1
+            .extend("QUERY Tags: SELECT unnest ( Post.tags ) FROM Post WHERE id = ?;")
+            .unwrap();
+        assert_eq!(r2.unnest["Tags"].table.as_deref(), Some("Post"));
+        assert!(r2.unnest.contains_key("PostsByTag"));
+
+        // only queries with readers can be unnested, and only once
+        assert!(Recipe::from_str("SELECT UNNEST(tags) FROM Post;", None).is_err());
+        assert!(strip_unnest("SELECT UNNEST(a), UNNEST(b) FROM Post;").is_err());
+        assert!(strip_unnest("SELECT UNNEST(a + 1) FROM Post;").is_err());
+    }
+
+    #[test]
+    fn it_rewrites_filtered_aggregations() {
+        assert_eq!(
+            rewrite_aggregate_filters(
+                "SELECT COUNT(*) FILTER (WHERE aid = 5) AS c FROM votes GROUP BY userid;"
+            )
+            .unwrap(),
+            "SELECT COUNT(CASE WHEN aid = 5 THEN aid END) AS c FROM votes GROUP BY userid;"
+        );
+        assert_eq!(
+            rewrite_aggregate_filters(
+                "SELECT SUM(votes.sign) filter(where (votes.aid = ')')) FROM votes;"
+            )
+            .unwrap(),
+            "SELECT SUM(CASE WHEN (votes.aid = ')') THEN votes.sign END) FROM votes;"
+        );
+
+        // queries without filtered aggregations are left alone
+        let q = "SELECT filter, COUNT(id) FROM filters WHERE filter_id = ? GROUP BY filter;";
+        assert_eq!(rewrite_aggregate_filters(q).unwrap(), q);
+
+        assert!(rewrite_aggregate_filters("SELECT MAX(a) FILTER (WHERE b = 1) FROM t;").is_err());
+        assert!(
+            rewrite_aggregate_filters("SELECT COUNT(DISTINCT a) FILTER (WHERE b = 1) FROM t;")
+                .is_err()
+        );
+        assert!(rewrite_aggregate_filters("SELECT COUNT(a) FILTER (b = 1) FROM t;").is_err());
+
+        let r = Recipe::from_str(
+            "CREATE TABLE votes (userid int, aid int, sign int);\n\
+             QUERY Upvotes: SELECT userid, SUM(sign) FILTER (WHERE sign > 0) AS ups \
+             FROM votes WHERE userid = ? GROUP BY userid;",
+            None,
+        )
+        .unwrap();
+        assert_eq!(r.expressions.len(), 2);
+    }
 }
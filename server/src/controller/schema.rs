@@ -24,6 +24,9 @@ fn to_sql_type(d: &DataType) -> Option<SqlType> {
         // type), so caller must handle appropriately.
         DataType::None => None,
         DataType::Timestamp(_) => Some(SqlType::Timestamp),
+        DataType::Bytes(_) => Some(SqlType::Blob),
+        // SQL has no list type either, and lists only ever come from base tables
+        DataType::List(_) => None,
     }
 }
 
@@ -1,6 +1,6 @@
 use mir::node::{GroupedNodeType, MirNode, MirNodeType};
 use mir::query::MirQuery;
-use mir::{Column, MirNodeRef};
+use mir::{Column, FlowNode, MirNodeRef};
 use noria::DataType;
 use petgraph::graph::NodeIndex;
 // TODO(malte): remove if possible
@@ -456,6 +456,15 @@ impl SqlToMirConverter {
         }
     }
 
+    /// Have every version of the base table `name` refer to the data-flow node `to`.
+    pub(super) fn move_base(&mut self, name: &str, to: NodeIndex) {
+        for ((n, _), node) in &self.nodes {
+            if n == name {
+                node.borrow_mut().flow_node = Some(FlowNode::New(to));
+            }
+        }
+    }
+
     pub(super) fn get_leaf(&self, name: &str) -> Option<NodeIndex> {
         match self.current.get(name) {
             None => None,
@@ -522,12 +531,17 @@ impl SqlToMirConverter {
         }
     }
 
+    /// Compute the MIR for the query `sq` named `name`.
+    ///
+    /// If `unnest` is given, it names a projected column holding lists, which is expanded into
+    /// one row per element right before the query's reader.
     pub(super) fn named_query_to_mir(
         &mut self,
         name: &str,
         sq: &SelectStatement,
         qg: &QueryGraph,
         has_leaf: bool,
+        unnest: Option<&Column>,
         universe: UniverseId,
     ) -> Result<
         (
@@ -539,7 +553,7 @@ impl SqlToMirConverter {
         String,
     > {
         let (sec, nodes, table_mapping, base_name) =
-            self.make_nodes_for_selection(&name, sq, qg, has_leaf, universe)?;
+            self.make_nodes_for_selection(&name, sq, qg, has_leaf, unnest, universe)?;
         let mut roots = Vec::new();
         let mut leaves = Vec::new();
         for mn in nodes.into_iter() {
@@ -1610,6 +1624,7 @@ impl SqlToMirConverter {
         st: &SelectStatement,
         qg: &QueryGraph,
         has_leaf: bool,
+        unnest: Option<&Column>,
         universe: UniverseId,
     ) -> Result<
         (
@@ -2037,6 +2052,20 @@ impl SqlToMirConverter {
                 false
             };
 
+            // the position of the list column to expand among the projected columns
+            let unnest_at = match unnest {
+                None => None,
+                Some(c) => match projected_columns.iter().position(|pc| pc == c) {
+                    Some(i) => Some(i),
+                    None => {
+                        return Err(format!(
+                            "query {} does not select the column {} it unnests",
+                            name, c.name
+                        ));
+                    }
+                },
+            };
+
             let ident = if has_leaf {
                 format!("q_{:x}_n{}{}", qg.signature().hash, new_node_count, uformat)
             } else {
@@ -2054,6 +2083,26 @@ impl SqlToMirConverter {
 
             nodes_added.push(leaf_project_node.clone());
 
+            // lists are expanded last, so that the reader can be keyed on their elements
+            let leaf_project_node = match unnest_at {
+                Some(i) => {
+                    let columns = leaf_project_node.borrow().columns().to_vec();
+                    let unnest_node = MirNode::new(
+                        &format!("{}_unnest", ident),
+                        self.schema_version,
+                        columns.clone(),
+                        MirNodeType::Unnest {
+                            column: columns[i].clone(),
+                        },
+                        vec![leaf_project_node.clone()],
+                        vec![],
+                    );
+                    nodes_added.push(unnest_node.clone());
+                    unnest_node
+                }
+                None => leaf_project_node,
+            };
+
             if has_leaf {
                 // We are supposed to add a `MaterializedLeaf` node keyed on the query
                 // parameters. For purely internal views (e.g., subqueries), this is not set.
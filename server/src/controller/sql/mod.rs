@@ -62,9 +62,15 @@ pub(crate) struct SqlIncorporator {
 
     reuse_type: ReuseConfigType,
 
+    /// Whether joins only carry the columns that their queries use.
+    prune_columns: bool,
+
     /// Active universes mapped to the group they belong to.
     /// If an user universe, mapped to None.
     universes: HashMap<Option<DataType>, Vec<UniverseId>>,
+
+    /// The list columns that named queries expand with `UNNEST`.
+    unnest: HashMap<String, nom_sql::Column>,
 }
 
 impl Default for SqlIncorporator {
@@ -86,7 +92,10 @@ impl Default for SqlIncorporator {
             schema_version: 0,
 
             reuse_type: ReuseConfigType::Finkelstein,
+            prune_columns: false,
             universes: HashMap::default(),
+
+            unnest: HashMap::default(),
         }
     }
 }
@@ -114,6 +123,11 @@ impl SqlIncorporator {
         self.reuse_type = reuse_type;
     }
 
+    /// Only have the joins of future queries carry the columns that the queries use.
+    pub(super) fn enable_column_pruning(&mut self, enabled: bool) {
+        self.prune_columns = enabled;
+    }
+
     /// Incorporates a single query into via the flow graph migration in `mig`. The `query`
     /// argument is a string that holds a parameterized SQL query, and the `name` argument supplies
     /// an optional name for the query. If no `name` is specified, the table name is used in the
@@ -152,6 +166,65 @@ impl SqlIncorporator {
         }
     }
 
+    /// Expand the list column `column` of the query named `query_name` into one row per element.
+    ///
+    /// This must be called before the query is added.
+    pub(super) fn unnest_column(&mut self, query_name: &str, column: nom_sql::Column) {
+        self.unnest.insert(query_name.to_owned(), column);
+    }
+
+    /// The select field of `st` that the query named `query_name` unnests, if any.
+    fn unnested_field(
+        &self,
+        query_name: &str,
+        st: &SelectStatement,
+    ) -> Result<Option<Column>, String> {
+        use nom_sql::FieldDefinitionExpression;
+
+        let column = match self.unnest.get(query_name) {
+            None => return Ok(None),
+            Some(column) => column,
+        };
+        st.fields
+            .iter()
+            .filter_map(|f| match *f {
+                FieldDefinitionExpression::Col(ref c) => Some(c),
+                _ => None,
+            })
+            .find(|c| {
+                c.name == column.name
+                    && c.function.is_none()
+                    && (column.table.is_none() || c.table == column.table)
+            })
+            .map(|c| Some(Column::from(c)))
+            .ok_or_else(|| {
+                format!(
+                    "query {} does not select the column {} it unnests",
+                    query_name, column
+                )
+            })
+    }
+
+    /// The hash that the query graph `qg` of the query named `query_name` is registered under.
+    ///
+    /// Queries that unnest a column are registered apart from otherwise identical queries that do
+    /// not, so that neither is ever reused for the other.
+    fn query_graph_hash(&self, query_name: &str, qg: &QueryGraph) -> u64 {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        let qg_hash = qg.signature().hash;
+        match self.unnest.get(query_name) {
+            None => qg_hash,
+            Some(column) => {
+                let mut hasher = DefaultHasher::new();
+                qg_hash.hash(&mut hasher);
+                column.hash(&mut hasher);
+                hasher.finish()
+            }
+        }
+    }
+
     pub(super) fn get_base_schema(&self, name: &str) -> Option<CreateTableStatement> {
         self.base_schemas.get(name).cloned()
     }
@@ -174,6 +247,13 @@ impl SqlIncorporator {
         }
     }
 
+    /// Have the base table `name` be backed by the data-flow node `to` from now on, so that
+    /// queries that are added over the table read from `to`.
+    pub(super) fn move_base(&mut self, name: &str, to: NodeIndex) {
+        self.mir_converter.move_base(name, to);
+        self.leaf_addresses.insert(name.to_owned(), to);
+    }
+
     pub(super) fn is_leaf_address(&self, ni: NodeIndex) -> bool {
         self.leaf_addresses.values().any(|nn| *nn == ni)
     }
@@ -208,7 +288,7 @@ impl SqlIncorporator {
 
         // Do we already have this exact query or a subset of it in the same universe?
         // TODO(malte): make this an O(1) lookup by QG signature
-        let qg_hash = qg.signature().hash;
+        let qg_hash = self.query_graph_hash(query_name, &qg);
         match self.mir_queries.get(&(qg_hash, universe.clone())) {
             None => (),
             Some(ref mir_query) => {
@@ -241,6 +321,7 @@ impl SqlIncorporator {
                     return (qg, QueryGraphReuse::ExactMatch(mir_query.leaf.clone()));
                 } else if existing_qg.signature() == qg.signature()
                     && existing_qg.parameters() != qg.parameters()
+                    && !self.unnest.contains_key(query_name)
                 {
                     use self::query_graph::OutputColumn;
 
@@ -523,11 +604,13 @@ impl SqlIncorporator {
         let universe = mig.universe();
         // no QG-level reuse possible, so we'll build a new query.
         // first, compute the MIR representation of the SQL query
+        let unnest = self.unnested_field(query_name, query)?;
         let (sec, og_mir, table_mapping, base_name) = self.mir_converter.named_query_to_mir(
             query_name,
             query,
             &qg,
             is_leaf,
+            unnest.as_ref(),
             universe.clone(),
         )?;
 
@@ -538,7 +621,8 @@ impl SqlIncorporator {
         );
 
         // run MIR-level optimizations
-        let (mut mir, nodes_added) = og_mir.optimize(table_mapping.as_ref(), sec);
+        let (mut mir, nodes_added) =
+            og_mir.optimize(table_mapping.as_ref(), sec, self.prune_columns && !sec);
         // update mir_converter with the nodes added. Note (jamb): we never remove the nodes removed
         // by the optimizations, but they do get disconnected pointer-wise, so I think it's fine.
         // (If we ever want to fix this, it's also relevant to the place below that calls optimize.)
@@ -658,7 +742,7 @@ impl SqlIncorporator {
         // This means we cannot reuse these queries.
         match qg {
             Some(qg) => {
-                let qg_hash = qg.signature().hash;
+                let qg_hash = self.query_graph_hash(query_name, &qg);
                 self.query_graphs.insert(qg_hash, qg);
                 self.mir_queries.insert((qg_hash, universe), mir.clone());
                 self.named_queries.insert(query_name.to_owned(), qg_hash);
@@ -685,9 +769,16 @@ impl SqlIncorporator {
 
         // no QG-level reuse possible, so we'll build a new query.
         // first, compute the MIR representation of the SQL query
-        let (sec, new_query_mir, table_mapping, base_name) = self
-            .mir_converter
-            .named_query_to_mir(query_name, query, &qg, is_leaf, universe.clone())?;
+        let unnest = self.unnested_field(query_name, query)?;
+        let (sec, new_query_mir, table_mapping, base_name) =
+            self.mir_converter.named_query_to_mir(
+                query_name,
+                query,
+                &qg,
+                is_leaf,
+                unnest.as_ref(),
+                universe.clone(),
+            )?;
 
         trace!(
             self.log,
@@ -695,7 +786,8 @@ impl SqlIncorporator {
             new_query_mir.to_graphviz().unwrap()
         );
 
-        let (new_opt_mir, new_nodes) = new_query_mir.optimize(table_mapping.as_ref(), sec);
+        let (new_opt_mir, new_nodes) =
+            new_query_mir.optimize(table_mapping.as_ref(), sec, self.prune_columns && !sec);
         self.mir_converter.add_nodes(new_nodes);
 
         trace!(
@@ -0,0 +1,55 @@
+//! Periodic verification of the on-disk rows of base tables.
+//!
+//! Verifying a base table scans every one of its rows, which can take a while for large tables.
+//! Periodic verifications therefore run as a task of their own, and hand the controller one base
+//! table at a time, so that the controller keeps handling heartbeats and requests in between.
+
+use crate::controller::consistency::on_controller;
+use crate::startup::Event;
+use futures_util::stream::StreamExt;
+use std::time::Duration;
+use stream_cancel::Valve;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Verify every base table whenever a periodic verification is due, and log any problems found.
+///
+/// See `Builder::set_base_verification`.
+pub(super) async fn run_periodically(
+    tx: UnboundedSender<Event>,
+    valve: Valve,
+    log: slog::Logger,
+    every: Duration,
+) {
+    let mut ticks = valve.wrap(tokio::time::interval(every));
+    while ticks.next().await.is_some() {
+        let bases = match on_controller(&tx, |ctrl| ctrl.due_base_verifications()).await {
+            Ok(Some(bases)) => bases,
+            Ok(None) => continue,
+            Err(_) => break,
+        };
+        for base in bases {
+            let b = base.clone();
+            let reports = match on_controller(&tx, move |ctrl| ctrl.verify_base((b, false))).await {
+                Ok(reports) => reports,
+                Err(_) => return,
+            };
+            match reports {
+                Ok(reports) => {
+                    for r in reports.into_iter().filter(|r| !r.is_clean()) {
+                        crit!(
+                            log,
+                            "base table failed verification";
+                            "base" => &base,
+                            "shard" => r.shard,
+                            "corrupt_rows" => r.corrupt_rows,
+                            "dangling_index_entries" => r.dangling_index_entries,
+                            "missing_index_entries" => r.missing_index_entries,
+                            "corrupt_commit_seq" => r.corrupt_commit_seq,
+                        );
+                    }
+                }
+                Err(e) => warn!(log, "failed to verify base"; "base" => base, "err" => e),
+            }
+        }
+    }
+}
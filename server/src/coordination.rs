@@ -1,6 +1,7 @@
 use dataflow::prelude::*;
 use dataflow::DomainBuilder;
 use noria::consensus::Epoch;
+use noria::ClientConnection;
 use std::collections::HashMap;
 use std::net::SocketAddr;
 
@@ -26,19 +27,79 @@ pub enum CoordinationPayload {
         read_listen_addr: SocketAddr,
         /// Which log files are stored locally on the worker.
         log_files: Vec<String>,
+        /// The most disk space, in bytes, that base table persistence may use on the worker.
+        disk_quota: Option<u64>,
+        /// Disk space, in bytes, currently used by base table persistence on the worker.
+        disk_usage: u64,
+        /// The label the worker was started with, if any.
+        label: Option<String>,
+        /// The token of the rolling upgrade the worker was restarted to finish, if any.
+        upgrade_token: Option<u64>,
     },
     /// Worker going offline.
     Deregister,
     /// Worker is still alive.
-    Heartbeat,
+    Heartbeat {
+        /// Disk space, in bytes, currently used by base table persistence on the worker.
+        disk_usage: u64,
+        /// The clients currently connected to the worker.
+        connections: Vec<ClientConnection>,
+        /// The number of domain shards the worker has started.
+        domains_hosted: usize,
+        /// Total memory size, in bytes, of the partial state kept by the worker's domains.
+        memory_used: u64,
+        /// The one-minute load average of the worker's host, if known.
+        cpu_load: Option<f64>,
+        /// The number of blocking reads waiting on each reader shard the worker serves, for those
+        /// that have any.
+        read_queues: HashMap<(NodeIndex, usize), usize>,
+    },
     /// Assign a new domain for a worker to run.
     AssignDomain(DomainBuilder),
     /// Remove a running domain from a worker.
     RemoveDomain,
     /// Domain connectivity gossip.
     DomainBooted(DomainDescriptor),
+    /// The controller's full routing table, sent to workers when they join.
+    RoutingTable(Vec<DomainDescriptor>),
+    /// A domain on the worker panicked.
+    DomainFailed {
+        /// The domain that failed.
+        domain: DomainIndex,
+        /// The shard of the domain that failed.
+        shard: usize,
+        /// What the domain panicked with.
+        reason: String,
+        /// Whether the worker rebuilt the domain, which it only does for domains without state
+        /// that did not lose any updates or replays.
+        respawned: bool,
+    },
     /// Create a new security universe.
     CreateUniverse(HashMap<String, DataType>),
+    /// A domain on the worker reported its statistics.
+    DomainStatistics {
+        /// The domain that reported.
+        domain: DomainIndex,
+        /// The shard of the domain that reported.
+        shard: usize,
+        /// The statistics of the domain.
+        stats: noria::debug::stats::DomainStats,
+        /// The statistics of the domain's nodes that changed since its previous report.
+        nodes: HashMap<NodeIndex, noria::debug::stats::NodeStats>,
+    },
+    /// A domain on the worker received a replay piece that failed its checksum.
+    CorruptReplay {
+        /// The domain that received the piece.
+        domain: DomainIndex,
+        /// The shard of the domain that received the piece.
+        shard: usize,
+        /// The replay path the piece was sent along.
+        tag: u32,
+        /// How many keys the piece was for, or 0 if it was part of a full replay.
+        keys: usize,
+        /// Whether the domain recovered by asking for the keys again.
+        recovered: bool,
+    },
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, Serialize)]
@@ -46,11 +107,26 @@ pub struct DomainDescriptor {
     id: DomainIndex,
     shard: usize,
     addr: SocketAddr,
+    /// The version of this route; the controller bumps it every time the domain boots somewhere.
+    ///
+    /// Workers only accept routes newer than the one they already know. 0 marks an unversioned
+    /// route.
+    version: u64,
 }
 
 impl DomainDescriptor {
     pub fn new(id: DomainIndex, shard: usize, addr: SocketAddr) -> Self {
-        DomainDescriptor { id, shard, addr }
+        DomainDescriptor {
+            id,
+            shard,
+            addr,
+            version: 0,
+        }
+    }
+
+    pub fn with_version(mut self, version: u64) -> Self {
+        self.version = version;
+        self
     }
 
     pub fn domain(&self) -> DomainIndex {
@@ -64,4 +140,8 @@ impl DomainDescriptor {
     pub fn addr(&self) -> SocketAddr {
         self.addr
     }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
 }
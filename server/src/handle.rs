@@ -4,6 +4,8 @@ use dataflow::prelude::*;
 use noria::consensus::Authority;
 use noria::prelude::*;
 use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
 use stream_cancel::Trigger;
@@ -14,6 +16,7 @@ pub struct Handle<A: Authority + 'static> {
     #[allow(dead_code)]
     event_tx: Option<tokio::sync::mpsc::UnboundedSender<Event>>,
     kill: Option<Trigger>,
+    mysql_addr: Option<SocketAddr>,
 }
 
 impl<A: Authority> Deref for Handle<A> {
@@ -40,9 +43,28 @@ impl<A: Authority + 'static> Handle<A> {
             c: Some(c),
             event_tx: Some(event_tx),
             kill: Some(kill),
+            mysql_addr: None,
         })
     }
 
+    /// Start accepting MySQL connections on `addr`.
+    pub(super) fn serve_mysql(&mut self, addr: SocketAddr, log: slog::Logger) -> io::Result<()> {
+        let ch = self.c.clone().unwrap();
+        self.mysql_addr = Some(crate::mysql_frontend::listen(addr, ch, log)?);
+        Ok(())
+    }
+
+    /// The address on which this instance accepts connections from MySQL clients, if any.
+    ///
+    /// `CREATE TABLE` statements add base tables to the recipe, and `INSERT`, `UPDATE`, and
+    /// `DELETE` statements are executed as with `ControllerHandle::execute`. `SELECT` statements
+    /// are added to the recipe as queries keyed by the columns they compare against values in
+    /// their `WHERE` clause, and answered from the resulting views. Both ad-hoc and prepared
+    /// statements are supported.
+    pub fn mysql_addr(&self) -> Option<SocketAddr> {
+        self.mysql_addr
+    }
+
     #[cfg(test)]
     pub(super) async fn backend_ready(&mut self) {
         use std::time;
@@ -89,8 +111,13 @@ impl<A: Authority + 'static> Handle<A> {
     /// Install a new set of policies on the controller.
     #[must_use]
     pub async fn set_security_config(&mut self, p: String) -> Result<(), failure::Error> {
-        self.rpc("set_security_config", p, "failed to set security config")
-            .await
+        self.rpc(
+            "set_security_config",
+            false,
+            p,
+            "failed to set security config",
+        )
+        .await
     }
 
     /// Install a new set of policies on the controller.
@@ -108,6 +135,7 @@ impl<A: Authority + 'static> Handle<A> {
         let _ = self
             .rpc::<_, ()>(
                 "create_universe",
+                false,
                 &context,
                 "failed to create security universe",
             )
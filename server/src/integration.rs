@@ -8,9 +8,9 @@ use dataflow::ops::join::JoinSource::*;
 use dataflow::ops::join::{Join, JoinSource, JoinType};
 use dataflow::ops::project::Project;
 use dataflow::ops::union::Union;
-use dataflow::{DurabilityMode, PersistenceParameters};
+use dataflow::{Clock, DurabilityMode, PersistenceParameters};
 use noria::consensus::LocalAuthority;
-use noria::DataType;
+use noria::{DataType, KeyExpression};
 
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -53,9 +53,20 @@ async fn build(prefix: &str, sharding: Option<usize>, log: bool) -> Handle<Local
     }
     builder.set_sharding(sharding);
     builder.set_persistence(get_persistence_params(prefix));
+    if let Some(seed) = get_interleave_seed() {
+        builder.set_deterministic(seed);
+    }
     builder.start_local().await.unwrap().0
 }
 
+// Runs the data-flow in deterministic mode with the seed given through the NORIA_TEST_SEED
+// environment variable, if any, so that a failing interleaving can be replayed.
+fn get_interleave_seed() -> Option<u64> {
+    env::var("NORIA_TEST_SEED")
+        .ok()
+        .map(|seed| seed.parse().expect("NORIA_TEST_SEED must be an integer"))
+}
+
 fn get_settle_time() -> Duration {
     let settle_time: u64 = match env::var("SETTLE_TIME") {
         Ok(value) => value.parse().unwrap(),
@@ -880,6 +891,136 @@ async fn it_recovers_persisted_bases() {
     done.await;
 }
 
+#[tokio::test(threaded_scheduler)]
+async fn it_serves_reader_snapshots_after_restart() {
+    let authority = Arc::new(LocalAuthority::new());
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("it_serves_reader_snapshots_after_restart");
+    let persistence_params = PersistenceParameters::new(
+        DurabilityMode::Permanent,
+        Duration::from_millis(1),
+        Some(path.to_string_lossy().into()),
+        1,
+    );
+
+    {
+        let mut g = Builder::default();
+        g.set_persistence(persistence_params.clone());
+        g.set_reader_snapshot_interval(Duration::from_millis(10));
+        // write the snapshots on the domains' own threads, which the maintenance thread spares
+        // every other test
+        g.set_domain_maintenance_thread(false);
+        let (mut g, done) = g.start(authority.clone()).await.unwrap();
+
+        g.install_recipe(
+            "CREATE TABLE Car (id int, price int, PRIMARY KEY(id));
+             QUERY CarPrice: SELECT price FROM Car WHERE id = ?;",
+        )
+        .await
+        .unwrap();
+        let mut mutator = g.table("Car").await.unwrap();
+        for i in 1..10 {
+            mutator
+                .insert(vec![i.into(), (i * 10).into()])
+                .await
+                .unwrap();
+        }
+
+        // make sure the view has been filled, and then saved
+        let mut getter = g.view("CarPrice").await.unwrap();
+        for i in 1..10 {
+            getter.lookup(&[i.into()], true).await.unwrap();
+        }
+        sleep().await;
+        drop(g);
+        done.await;
+    }
+
+    let saved = std::fs::read_dir(dir.path())
+        .unwrap()
+        .filter_map(Result::ok)
+        .any(|e| e.path().extension().map_or(false, |ext| ext == "snapshot"));
+    assert!(saved);
+
+    let mut g = Builder::default();
+    g.set_persistence(persistence_params);
+    g.set_reader_snapshot_interval(Duration::from_millis(10));
+    let (mut g, done) = g.start(authority.clone()).await.unwrap();
+    {
+        let mut getter = g.view("CarPrice").await.unwrap();
+
+        // stale or not, the results are the ones from before the restart
+        for i in 1..10 {
+            let result = getter.lookup(&[i.into()], true).await.unwrap();
+            assert_eq!(result.len(), 1);
+            assert_eq!(result[0][0], (i * 10).into());
+        }
+
+        // and the view eventually catches up
+        let mut mutator = g.table("Car").await.unwrap();
+        mutator.insert(vec![10.into(), 100.into()]).await.unwrap();
+        sleep().await;
+        let result = getter.lookup(&[10.into()], true).await.unwrap();
+        assert!(!result.is_stale());
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0][0], 100.into());
+    }
+    drop(g);
+    done.await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_saves_reader_snapshots_on_maintenance_thread() {
+    let authority = Arc::new(LocalAuthority::new());
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir
+        .path()
+        .join("it_saves_reader_snapshots_on_maintenance_thread");
+    let persistence_params = PersistenceParameters::new(
+        DurabilityMode::Permanent,
+        Duration::from_millis(1),
+        Some(path.to_string_lossy().into()),
+        1,
+    );
+
+    let mut g = Builder::default();
+    g.set_persistence(persistence_params);
+    g.set_reader_snapshot_interval(Duration::from_millis(10));
+    let (mut g, done) = g.start(authority).await.unwrap();
+
+    g.install_recipe(
+        "CREATE TABLE Car (id int, price int, PRIMARY KEY(id));
+         QUERY CarPrice: SELECT price FROM Car WHERE id = ?;",
+    )
+    .await
+    .unwrap();
+    let mut mutator = g.table("Car").await.unwrap();
+    let mut getter = g.view("CarPrice").await.unwrap();
+    for i in 1..10 {
+        mutator
+            .insert(vec![i.into(), (i * 10).into()])
+            .await
+            .unwrap();
+        getter.lookup(&[i.into()], true).await.unwrap();
+    }
+    sleep().await;
+    drop(g);
+    done.await;
+
+    // shutting down waits for snapshots that were handed off to be written in full
+    let extensions: Vec<_> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .filter_map(Result::ok)
+        .filter_map(|e| {
+            e.path()
+                .extension()
+                .map(|ext| ext.to_string_lossy().into_owned())
+        })
+        .collect();
+    assert!(extensions.iter().any(|ext| ext == "snapshot"));
+    assert!(!extensions.iter().any(|ext| ext == "tmp"));
+}
+
 #[tokio::test(threaded_scheduler)]
 async fn mutator_churn() {
     let mut g = start_simple("mutator_churn").await;
@@ -974,6 +1115,45 @@ async fn connection_churn() {
     done.await;
 }
 
+#[tokio::test(threaded_scheduler)]
+async fn local_cluster_with_multiple_workers() {
+    use crate::LocalCluster;
+
+    let mut cluster = LocalCluster::builder()
+        .workers(2)
+        .sharding(DEFAULT_SHARDING)
+        .persistence(get_persistence_params(
+            "local_cluster_with_multiple_workers",
+        ))
+        .build()
+        .await
+        .unwrap();
+
+    cluster
+        .install_recipe(
+            "CREATE TABLE Car (id int, price int, PRIMARY KEY(id));
+             QUERY CarPrice: SELECT price FROM Car WHERE id = ?;",
+        )
+        .await
+        .unwrap();
+
+    let mut car = cluster.table("Car").await.unwrap();
+    for i in 1..10 {
+        car.insert(vec![i.into(), (i * 10).into()]).await.unwrap();
+    }
+    sleep().await;
+
+    // handles obtained from the cluster talk to the same controller
+    let mut price = cluster.handle().view("CarPrice").await.unwrap();
+    for i in 1..10 {
+        let result = price.lookup(&[i.into()], true).await.unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0][0], (i * 10).into());
+    }
+
+    cluster.shutdown().await;
+}
+
 #[tokio::test(threaded_scheduler)]
 async fn it_recovers_persisted_bases_w_multiple_nodes() {
     let authority = Arc::new(LocalAuthority::new());
@@ -1467,6 +1647,46 @@ async fn migrate_added_columns() {
     assert!(res.iter().any(|r| r == &vec![10.into(), id.clone()]));
 }
 
+#[tokio::test(threaded_scheduler)]
+async fn migrate_widen_columns() {
+    // set up graph
+    let mut g = start_simple("migrate_widen_columns").await;
+    let a = g
+        .migrate(|mig| mig.add_base("a", &["a", "b"], Base::new(vec![0.into(), 0.into()])))
+        .await;
+    let mut muta = g.table("a").await.unwrap();
+
+    // send a narrow value on a
+    muta.insert(vec![1.into(), DataType::Int(2)]).await.unwrap();
+    sleep().await;
+
+    // widen the second column, and add a view that uses it
+    g.migrate(move |mig| {
+        mig.widen_column(a, 1);
+        let b = mig.add_ingredient("x", &["a", "b"], Project::new(a, &[0, 1], None, None));
+        mig.maintain_anonymous(b, &[0]);
+    })
+    .await;
+
+    let mut bq = g.view("x").await.unwrap();
+
+    // send another narrow value using the old table handle
+    muta.insert(vec![1.into(), DataType::Int(3)]).await.unwrap();
+    sleep().await;
+
+    // both the replayed, persisted record and the one that was in flight come out widened
+    let res = bq.lookup(&[1.into()], true).await.unwrap();
+    assert_eq!(res.len(), 2);
+    assert!(res.contains(&vec![1.into(), 2.into()]));
+    assert!(res.contains(&vec![1.into(), 3.into()]));
+    for r in res {
+        match r[1] {
+            DataType::BigInt(_) => {}
+            ref v => unreachable!("column was not widened: {:?}", v),
+        }
+    }
+}
+
 #[tokio::test(threaded_scheduler)]
 async fn migrate_drop_columns() {
     let id: DataType = "x".into();
@@ -1734,6 +1954,97 @@ async fn cascading_replays_with_sharding() {
     sleep().await;
 }
 
+#[tokio::test(threaded_scheduler)]
+async fn join_across_shardings() {
+    use noria::Modification;
+
+    let mut g = start_simple("join_across_shardings").await;
+
+    // both bases are sharded by their primary key. the join is on article.author, which is not
+    // article's key, so the planner has to shuffle article by author before the join. the reader
+    // is keyed by the article id, so the join's output must then be shuffled back.
+    g.migrate(|mig| {
+        let article = mig.add_base(
+            "article",
+            &["id", "author", "title"],
+            Base::default().with_key(vec![0]),
+        );
+        let author = mig.add_base(
+            "author",
+            &["aid", "name"],
+            Base::default().with_key(vec![0]),
+        );
+        let j = Join::new(
+            article,
+            author,
+            JoinType::Inner,
+            vec![L(0), B(1, 0), L(2), R(1)],
+        );
+        let j = mig.add_ingredient("j", &["id", "author", "title", "name"], j);
+        mig.maintain_anonymous(j, &[0]);
+    })
+    .await;
+
+    let mut article = g.table("article").await.unwrap();
+    let mut author = g.table("author").await.unwrap();
+    let mut j = g.view("j").await.unwrap();
+
+    for aid in 0..4 {
+        author
+            .insert(vec![aid.into(), format!("author {}", aid).into()])
+            .await
+            .unwrap();
+    }
+    // spread articles by each author across shards
+    for id in 0..16 {
+        article
+            .insert(vec![id.into(), (id % 4).into(), format!("t{}", id).into()])
+            .await
+            .unwrap();
+    }
+    sleep().await;
+
+    for id in 0..16 {
+        assert_eq!(
+            j.lookup(&[id.into()], true).await.unwrap(),
+            vec![vec![
+                id.into(),
+                (id % 4).into(),
+                format!("t{}", id).into(),
+                format!("author {}", id % 4).into()
+            ]],
+            "wrong result for article {}",
+            id
+        );
+    }
+
+    // an update on the right side has to reach every article by that author, regardless of
+    // which shard the article lives on.
+    author
+        .update(
+            vec![1.into()],
+            vec![(1, Modification::Set("renamed".into()))],
+        )
+        .await
+        .unwrap();
+    // and moving an article to another author has to retract it from its old author's shard.
+    article
+        .update(vec![2.into()], vec![(1, Modification::Set(3.into()))])
+        .await
+        .unwrap();
+    sleep().await;
+
+    for id in (1..16).step_by(4) {
+        let rs = j.lookup(&[id.into()], true).await.unwrap();
+        assert_eq!(rs.len(), 1);
+        assert_eq!(rs[0][3], "renamed".into());
+    }
+    assert_eq!(
+        j.lookup(&[2.into()], true).await.unwrap(),
+        vec![vec![2.into(), 3.into(), "t2".into(), "author 3".into()]]
+    );
+}
+
 #[tokio::test(threaded_scheduler)]
 async fn full_aggregation_with_bogokey() {
     // set up graph
@@ -2644,6 +2955,41 @@ async fn remove_query() {
     }
 }
 
+#[tokio::test(threaded_scheduler)]
+async fn drain_removed_domains() {
+    let r_txt = "CREATE TABLE a (x int, y int);\n
+                 QUERY qa: SELECT x, y FROM a WHERE x = ?;\n
+                 CREATE TABLE b (x int);";
+
+    let r2_txt = "CREATE TABLE a (x int, y int);\n
+                  QUERY qa: SELECT x, y FROM a WHERE x = ?;";
+
+    let mut g = start_simple("drain_removed_domains").await;
+    g.install_recipe(r_txt).await.unwrap();
+
+    let mut muta = g.table("a").await.unwrap();
+    let mut mutb = g.table("b").await.unwrap();
+    let mut qa = g.view("qa").await.unwrap();
+
+    mutb.insert(vec![1.into()]).await.unwrap();
+    mutb.insert(vec![2.into()]).await.unwrap();
+
+    // removing b tears down its domain once it has drained
+    g.install_recipe(r2_txt).await.unwrap();
+    assert_eq!(g.inputs().await.unwrap().len(), 1);
+
+    // later writes fail instead of being acknowledged and dropped
+    assert!(mutb.insert(vec![3.into()]).await.is_err());
+
+    // the rest of the graph keeps working
+    muta.insert(vec![1.into(), 2.into()]).await.unwrap();
+    sleep().await;
+    assert_eq!(
+        qa.lookup(&[1.into()], true).await.unwrap(),
+        vec![vec![1.into(), 2.into()]]
+    );
+}
+
 macro_rules! get {
     ($private:ident, $public:ident, $uid:expr, $aid:expr) => {{
         // combine private and public results
@@ -2782,3 +3128,3137 @@ async fn correct_nested_view_schema() {
     ];
     assert_eq!(q.schema(), Some(&expected_schema[..]));
 }
+
+#[tokio::test(threaded_scheduler)]
+async fn audit_log_attributes_writes() {
+    use noria::AuditOperation;
+
+    let mut g = start_simple("audit_log_attributes_writes").await;
+    g.migrate(|mig| {
+        let a = mig.add_base(
+            "a",
+            &["a", "b"],
+            Base::new(vec![]).with_key(vec![0]).with_audit(16),
+        );
+        mig.maintain_anonymous(a, &[0]);
+    })
+    .await;
+
+    let mut alice = g.table("a").await.unwrap();
+    alice.set_identity("alice");
+    let mut anon = g.table("a").await.unwrap();
+
+    alice.insert(vec![1.into(), 2.into()]).await.unwrap();
+    sleep().await;
+    anon.delete(vec![1.into()]).await.unwrap();
+    sleep().await;
+
+    let log = g.audit_log("a").await.unwrap();
+    assert_eq!(log.len(), 2);
+    assert_eq!(log[0].identity.as_deref(), Some("alice"));
+    assert_eq!(log[0].operation, AuditOperation::Insert);
+    assert_eq!(log[0].key, vec![DataType::from(1)]);
+    assert_eq!(log[1].identity, None);
+    assert_eq!(log[1].operation, AuditOperation::Delete);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn commit_seqs_in_acks() {
+    let mut g = start_simple_unsharded("commit_seqs_in_acks").await;
+    g.migrate(|mig| {
+        let a = mig.add_base("a", &["a", "b"], Base::new(vec![]).with_key(vec![0]));
+        mig.maintain_anonymous(a, &[0]);
+    })
+    .await;
+
+    let mut a = g.table("a").await.unwrap();
+    assert_eq!(g.commit_seqs("a").await.unwrap(), vec![0]);
+
+    let first = a
+        .perform_all_seq(vec![vec![1.into(), 2.into()], vec![2.into(), 3.into()]])
+        .await
+        .unwrap();
+    assert_eq!(first, vec![Some(1)]);
+    let second = a
+        .perform_all_seq(vec![vec![3.into(), 4.into()]])
+        .await
+        .unwrap();
+    assert_eq!(second, vec![Some(2)]);
+
+    // an acknowledged write has been committed, so the base reports at least its sequence number
+    assert_eq!(g.commit_seqs("a").await.unwrap(), vec![2]);
+    assert!(g.commit_seqs("b").await.is_err());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn verify_base_tables() {
+    let mut g = start_simple("verify_base_tables").await;
+    g.migrate(|mig| {
+        let a = mig.add_base("a", &["a", "b"], Base::new(vec![]).with_key(vec![0]));
+        mig.maintain_anonymous(a, &[0]);
+    })
+    .await;
+
+    let mut a = g.table("a").await.unwrap();
+    for i in 0..4 {
+        a.insert(vec![i.into(), (i * 10).into()]).await.unwrap();
+    }
+    sleep().await;
+
+    let reports = g.verify_base("a", false).await.unwrap();
+    assert_eq!(reports.len(), DEFAULT_SHARDING.unwrap_or(1));
+    for (shard, report) in reports.iter().enumerate() {
+        assert_eq!(report.shard, shard);
+        assert!(report.durable);
+        assert!(report.is_clean());
+        assert!(!report.repaired);
+    }
+    assert_eq!(reports.iter().map(|r| r.rows).sum::<u64>(), 4);
+    assert!(g.verify_base("b", false).await.is_err());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn load_shedding_rejects_best_effort_writes() {
+    use crate::{LoadSheddingPolicy, ShedAction};
+    use noria::error::TableError;
+
+    let mut builder = Builder::default();
+    builder.set_persistence(get_persistence_params(
+        "load_shedding_rejects_best_effort_writes",
+    ));
+    // any amount of work counts as overload
+    builder.set_load_shedding(LoadSheddingPolicy {
+        busy_threshold: 0.0,
+        window: Duration::from_millis(10),
+        actions: vec![ShedAction::RejectBestEffortWrites],
+        best_effort_tables: vec!["a".to_owned()],
+    });
+    let mut g = builder.start_local().await.unwrap().0;
+    g.migrate(|mig| {
+        let a = mig.add_base("a", &["a", "b"], Base::new(vec![]));
+        mig.maintain_anonymous(a, &[0]);
+    })
+    .await;
+
+    let mut a = g.table("a").await.unwrap();
+    let mut aq = g.view("a").await.unwrap();
+
+    // the domain is overloaded by the end of the first window in which it does any work, after
+    // which writes are rejected, and the client is told so.
+    let mut acked = 0;
+    for b in 0..5 {
+        match a.insert(vec![1.into(), b.into()]).await {
+            Ok(()) => acked += 1,
+            Err(TableError::Shed) => {}
+            Err(e) => panic!("unexpected error: {:?}", e),
+        }
+        sleep().await;
+    }
+    assert!(acked < 5);
+
+    // every acknowledged write was applied
+    let kept = aq.lookup(&[1.into()], true).await.unwrap().len();
+    assert_eq!(kept, acked);
+
+    let stats = g.statistics().await.unwrap();
+    let (overloaded, rejected) = stats.values().fold((0, 0), |(o, r), (ds, _)| {
+        (o + ds.overloaded_windows, r + ds.rejected_writes)
+    });
+    assert!(overloaded > 0);
+    assert_eq!(rejected as usize, 5 - kept);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn lookup_filtered() {
+    use noria::results::{Comparison, Predicate};
+
+    let mut g = start_simple("lookup_filtered").await;
+    g.migrate(|mig| {
+        let a = mig.add_base("a", &["a", "b"], Base::new(vec![]));
+        mig.maintain_anonymous(a, &[0]);
+    })
+    .await;
+
+    let mut a = g.table("a").await.unwrap();
+    let mut aq = g.view("a").await.unwrap();
+    for b in 0..10 {
+        a.insert(vec![1.into(), b.into()]).await.unwrap();
+    }
+    sleep().await;
+
+    let p = Predicate::compare(1, Comparison::GreaterOrEqual, 3.into()).and(Predicate::compare(
+        1,
+        Comparison::Less,
+        5.into(),
+    ));
+    let mut rs: Vec<Vec<DataType>> = aq
+        .lookup_filtered(&[1.into()], p, true)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.into())
+        .collect();
+    rs.sort();
+    assert_eq!(rs, vec![vec![1.into(), 3.into()], vec![1.into(), 4.into()]]);
+
+    // an unfiltered lookup still returns everything
+    assert_eq!(aq.lookup(&[1.into()], true).await.unwrap().len(), 10);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn valid_time_views() {
+    use noria::ValidTime;
+
+    let now = || DataType::from(nom_sql::Literal::CurrentTimestamp);
+
+    let mut g = start_simple("valid_time_views").await;
+    g.migrate(|mig| {
+        let a = mig.add_base(
+            "price",
+            &["item", "price", "valid_from", "valid_to"],
+            Base::new(vec![]).with_valid_time(2, 3),
+        );
+        mig.maintain_anonymous(a, &[0]);
+    })
+    .await;
+
+    let mut price = g.table("price").await.unwrap();
+    let mut prices = g.view("price").await.unwrap();
+    assert_eq!(prices.valid_time_columns(), Some((2, 3)));
+
+    let before = now();
+    sleep().await;
+    let changed = now();
+    let old = vec![1.into(), 10.into(), DataType::None, changed.clone()];
+    let new = vec![1.into(), 20.into(), changed, DataType::None];
+    price.insert(old.clone()).await.unwrap();
+    price.insert(new.clone()).await.unwrap();
+    sleep().await;
+
+    // by default, only the rows that are valid now are returned
+    assert_eq!(prices.lookup(&[1.into()], true).await.unwrap(), vec![new]);
+    assert_eq!(prices.count(&[1.into()], true).await.unwrap(), 1);
+
+    prices.set_valid_time(ValidTime::AsOf(before));
+    assert_eq!(prices.lookup(&[1.into()], true).await.unwrap(), vec![old]);
+
+    prices.set_valid_time(ValidTime::All);
+    assert_eq!(prices.lookup(&[1.into()], true).await.unwrap().len(), 2);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn valid_time_goes_by_server_clock() {
+    use noria::error::TableError;
+
+    let clock = Clock::manual();
+    let mut b = Builder::default();
+    b.set_sharding(DEFAULT_SHARDING);
+    b.set_persistence(get_persistence_params("valid_time_goes_by_server_clock"));
+    b.set_clock(clock.clone());
+    let mut g = b.start_local().await.unwrap().0;
+    g.migrate(|mig| {
+        let a = mig.add_base(
+            "price",
+            &["item", "price", "valid_from", "valid_to"],
+            Base::new(vec![]).with_valid_time(2, 3),
+        );
+        mig.maintain_anonymous(a, &[0]);
+    })
+    .await;
+
+    let mut price = g.table("price").await.unwrap();
+    let mut prices = g.view("price").await.unwrap();
+
+    // a clock of our own, to tell the time an hour from now
+    let ahead = Clock::manual();
+    ahead.advance(Duration::from_secs(3600));
+    let in_an_hour = DataType::from(ahead.local());
+
+    let row = vec![1.into(), 10.into(), DataType::None, in_an_hour.clone()];
+    price.insert(row.clone()).await.unwrap();
+    sleep().await;
+    assert_eq!(prices.lookup(&[1.into()], true).await.unwrap(), vec![row]);
+
+    // the row has expired as far as the server is concerned, if not by our clock
+    clock.advance(Duration::from_secs(2 * 3600));
+    assert!(prices.lookup(&[1.into()], true).await.unwrap().is_empty());
+
+    // rows that are valid for a negative period are rejected rather than dropped
+    let now = DataType::from(Clock::default().local());
+    match price
+        .insert(vec![2.into(), 20.into(), in_an_hour, now])
+        .await
+    {
+        Err(TableError::Rejected(rows)) => {
+            assert_eq!(
+                rows.into_iter().map(|(i, _)| i).collect::<Vec<_>>(),
+                vec![0]
+            )
+        }
+        r => unreachable!("{:?}", r),
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn count_only_views() {
+    let mut g = start_simple("count_only_views").await;
+    let sql = "
+        CREATE TABLE Vote (id int, aid int, PRIMARY KEY(id));
+        QUERY VotesByArticle: SELECT id, aid FROM Vote WHERE aid = ?;
+        QUERY VoteCounts COUNT ONLY: SELECT aid, id FROM Vote WHERE aid = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+    let mut mutator = g.table("Vote").await.unwrap();
+    let mut votes = g.view("VotesByArticle").await.unwrap();
+    let mut counts = g.view("VoteCounts").await.unwrap();
+    assert_eq!(counts.columns(), &["aid", "count"]);
+
+    for (id, aid) in &[(1, 1), (2, 1), (3, 1), (4, 2)] {
+        mutator
+            .insert(vec![(*id).into(), (*aid).into()])
+            .await
+            .unwrap();
+    }
+    sleep().await;
+
+    // counts can be read off regular views...
+    assert_eq!(votes.count(&[1.into()], true).await.unwrap(), 3);
+    assert!(votes.exists(&[2.into()], true).await.unwrap());
+    assert!(!votes.exists(&[3.into()], true).await.unwrap());
+
+    // ...and off count-only ones, which hold a single row per key
+    assert_eq!(counts.count(&[1.into()], true).await.unwrap(), 3);
+    assert_eq!(
+        counts.lookup(&[1.into()], true).await.unwrap(),
+        vec![vec![1.into(), 3.into()]]
+    );
+    assert!(!counts.exists(&[3.into()], true).await.unwrap());
+
+    mutator.delete(vec![2.into()]).await.unwrap();
+    mutator.delete(vec![4.into()]).await.unwrap();
+    sleep().await;
+
+    assert_eq!(votes.count(&[1.into()], true).await.unwrap(), 2);
+    assert_eq!(counts.count(&[1.into()], true).await.unwrap(), 2);
+    assert_eq!(
+        counts.lookup(&[1.into()], true).await.unwrap(),
+        vec![vec![1.into(), 2.into()]]
+    );
+    assert!(!counts.exists(&[2.into()], true).await.unwrap());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn computed_key_views() {
+    let mut g = start_simple("computed_key_views").await;
+    g.migrate(|mig| {
+        let a = mig.add_base("users", &["id", "email"], Base::default());
+        let by_email = mig.add_ingredient("by_email", &["id", "email"], Identity::new(a));
+        mig.maintain_computed(
+            "UsersByEmail".to_owned(),
+            by_email,
+            &[KeyExpression::Lower(1)],
+        );
+    })
+    .await;
+
+    let mut mutator = g.table("users").await.unwrap();
+    let mut users = g.view("UsersByEmail").await.unwrap();
+    assert_eq!(users.columns(), &["id", "email", "lower(email)"]);
+
+    mutator
+        .insert(vec![1.into(), "Foo@Example.com".into()])
+        .await
+        .unwrap();
+    mutator
+        .insert(vec![2.into(), "bar@example.com".into()])
+        .await
+        .unwrap();
+    sleep().await;
+
+    // lookups are normalized the same way the stored rows are
+    let foo = vec![1.into(), "Foo@Example.com".into(), "foo@example.com".into()];
+    assert_eq!(
+        users
+            .lookup(&["foo@example.com".into()], true)
+            .await
+            .unwrap(),
+        vec![foo.clone()]
+    );
+    assert_eq!(
+        users
+            .lookup(&["FOO@EXAMPLE.COM".into()], true)
+            .await
+            .unwrap(),
+        vec![foo]
+    );
+    assert_eq!(
+        users
+            .lookup(&["Bar@Example.com".into()], true)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn foreign_key_cascades_deletes() {
+    let mut g = start_simple("foreign_key_cascades_deletes").await;
+    let sql = "
+        CREATE TABLE Class (cid int, name varchar(255), PRIMARY KEY(cid));
+        CREATE TABLE Post (pid int, cid int, content varchar(255), PRIMARY KEY(pid));
+        FOREIGN KEY Post(cid) REFERENCES Class(cid) ON DELETE CASCADE;
+        QUERY posts: SELECT Post.pid, Post.content FROM Post WHERE Post.cid = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    let mut class = g.table("Class").await.unwrap();
+    let mut post = g.table("Post").await.unwrap();
+    let mut posts = g.view("posts").await.unwrap();
+
+    class.insert(vec![1.into(), "a".into()]).await.unwrap();
+    class.insert(vec![2.into(), "b".into()]).await.unwrap();
+    post.insert(vec![1.into(), 1.into(), "x".into()])
+        .await
+        .unwrap();
+    post.insert(vec![2.into(), 1.into(), "y".into()])
+        .await
+        .unwrap();
+    post.insert(vec![3.into(), 2.into(), "z".into()])
+        .await
+        .unwrap();
+    sleep().await;
+    assert_eq!(posts.lookup(&[1.into()], true).await.unwrap().len(), 2);
+
+    class.delete(vec![1.into()]).await.unwrap();
+    // renaming a class does not remove it
+    class
+        .update(vec![2.into()], vec![(1, Modification::Set("c".into()))])
+        .await
+        .unwrap();
+    sleep().await;
+
+    assert!(posts.lookup(&[1.into()], true).await.unwrap().is_empty());
+    assert_eq!(
+        posts.lookup(&[2.into()], true).await.unwrap(),
+        vec![vec![3.into(), "z".into()]]
+    );
+
+    // deletes keep cascading to the posts once they have moved to new shards
+    g.reshard_base("Post", 3).await.unwrap();
+    let mut post = g.table("Post").await.unwrap();
+    class.insert(vec![3.into(), "d".into()]).await.unwrap();
+    post.insert(vec![4.into(), 3.into(), "w".into()])
+        .await
+        .unwrap();
+    sleep().await;
+    class.delete(vec![2.into()]).await.unwrap();
+    sleep().await;
+    let mut posts = g.view("posts").await.unwrap();
+    assert!(posts.lookup(&[2.into()], true).await.unwrap().is_empty());
+    assert_eq!(g.export_base("Post").await.unwrap().len(), 1);
+
+    // and stop once the foreign key is gone
+    g.install_recipe(&sql.replace(
+        "FOREIGN KEY Post(cid) REFERENCES Class(cid) ON DELETE CASCADE;",
+        "",
+    ))
+    .await
+    .unwrap();
+    class.delete(vec![3.into()]).await.unwrap();
+    sleep().await;
+    assert_eq!(
+        posts.lookup(&[3.into()], true).await.unwrap(),
+        vec![vec![4.into(), "w".into()]]
+    );
+
+    // a foreign key can only refer to a primary key
+    assert!(g
+        .extend_recipe("FOREIGN KEY Class(name) REFERENCES Post(content) ON DELETE CASCADE;")
+        .await
+        .is_err());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_executes_sql_dml() {
+    let mut g = start_simple("it_executes_sql_dml").await;
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255), votes int, PRIMARY KEY(id));
+        QUERY ArticleById: SELECT id, title, votes FROM Article WHERE id = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+    let mut getter = g.view("ArticleById").await.unwrap();
+
+    let n = g
+        .execute("INSERT INTO Article (id, title, votes) VALUES (1, 'a', 0), (2, 'b', 0)")
+        .await
+        .unwrap();
+    assert_eq!(n, 2);
+    g.execute("UPDATE Article SET votes = votes + 1, title = 'c' WHERE id = 1")
+        .await
+        .unwrap();
+    g.execute("DELETE FROM Article WHERE id = 2").await.unwrap();
+    sleep().await;
+
+    let result = getter.lookup(&[1.into()], true).await.unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0]["title"], "c".into());
+    assert_eq!(result[0]["votes"], 1.into());
+    assert!(getter.lookup(&[2.into()], true).await.unwrap().is_empty());
+
+    // rows can only be addressed by their full primary key
+    assert!(g
+        .execute("DELETE FROM Article WHERE votes = 1")
+        .await
+        .is_err());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn warm_view_from_base() {
+    use noria::WarmKeys;
+
+    let mut g = start_simple("warm_view_from_base").await;
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+        QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    assert!(g
+        .warm_view("ArticleById", WarmKeys::Keys(vec![vec![1.into()]]), 0)
+        .await
+        .is_err());
+
+    let mut mutator = g.table("Article").await.unwrap();
+    for i in 0..10 {
+        mutator.insert(vec![i.into(), "x".into()]).await.unwrap();
+    }
+    sleep().await;
+
+    let warmed = g
+        .warm_view(
+            "ArticleById",
+            WarmKeys::Base {
+                table: "Article".into(),
+                columns: vec![0],
+            },
+            3,
+        )
+        .await
+        .unwrap();
+    assert_eq!(warmed, 10);
+
+    // all keys are now present, so non-blocking lookups hit
+    let mut getter = g.view("ArticleById").await.unwrap();
+    for i in 0..10 {
+        let rs = getter.lookup(&[i.into()], false).await.unwrap();
+        assert_eq!(rs.len(), 1);
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn view_lifecycle_states() {
+    use noria::ViewState;
+
+    let mut g = start_simple("view_lifecycle_states").await;
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+        QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    let mut getter = g.view("ArticleById").await.unwrap();
+    getter.wait_until_ready().await.unwrap();
+    assert_eq!(getter.state().await.unwrap(), ViewState::Ready);
+
+    // removing the query deprecates the view
+    g.install_recipe("CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));")
+        .await
+        .unwrap();
+    sleep().await;
+    assert_eq!(getter.state().await.unwrap(), ViewState::Deprecated);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn pause_and_resume_view() {
+    let mut g = start_simple("pause_and_resume_view").await;
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255));
+        QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+    let mut mutator = g.table("Article").await.unwrap();
+    let mut getter = g.view("ArticleById").await.unwrap();
+
+    mutator.insert(vec![1.into(), "a".into()]).await.unwrap();
+    sleep().await;
+    assert_eq!(getter.lookup(&[1.into()], true).await.unwrap().len(), 1);
+
+    // writes no longer reach a paused view
+    g.pause_view("ArticleById", false).await.unwrap();
+    mutator.insert(vec![1.into(), "b".into()]).await.unwrap();
+    sleep().await;
+    assert_eq!(getter.lookup(&[1.into()], true).await.unwrap().len(), 1);
+
+    // but it catches up once resumed
+    g.resume_view("ArticleById").await.unwrap();
+    let mut res = getter.lookup(&[1.into()], true).await.unwrap();
+    res.sort();
+    assert_eq!(
+        res,
+        vec![vec![1.into(), "a".into()], vec![1.into(), "b".into()]]
+    );
+
+    assert!(g.pause_view("NoSuchView", false).await.is_err());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn describe_queries() {
+    let mut g = start_simple_unsharded("describe_queries").await;
+    let sql = "
+        CREATE TABLE Article (id int, author int, title varchar(255), PRIMARY KEY(id));
+        QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;
+        QUERY ArticlesByAuthor: SELECT id, title FROM Article WHERE author = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+    let mut mutator = g.table("Article").await.unwrap();
+    mutator
+        .insert(vec![1.into(), 2.into(), "a".into()])
+        .await
+        .unwrap();
+    sleep().await;
+    let mut getter = g.view("ArticleById").await.unwrap();
+    assert_eq!(getter.lookup(&[1.into()], true).await.unwrap().len(), 1);
+
+    let queries = g.queries().await.unwrap();
+    let names: Vec<_> = queries.iter().map(|q| &q.name[..]).collect();
+    assert_eq!(names, vec!["ArticleById", "ArticlesByAuthor"]);
+    let by_id = &queries[0];
+    assert!(by_id.sql.to_lowercase().starts_with("select"));
+    assert_eq!(by_id.key_columns, vec!["id".to_owned()]);
+    assert_eq!(by_id.shards, 1);
+    assert!(by_id.partial);
+    assert!(by_id.ready);
+    assert!(!by_id.paused);
+    assert!(by_id.mem_size > 0);
+
+    g.pause_view("ArticleById", false).await.unwrap();
+    let queries = g.queries().await.unwrap();
+    assert!(queries[0].paused);
+    assert!(!queries[1].paused);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn bytes_columns_with_size_limits() {
+    let mut g = start_simple("bytes_columns_with_size_limits").await;
+    let sql = "
+        CREATE TABLE Attachment (id int, data varbinary(4), PRIMARY KEY(id));
+        QUERY AttachmentById: SELECT id, data FROM Attachment WHERE id = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+    let mut mutator = g.table("Attachment").await.unwrap();
+    mutator
+        .insert(vec![1.into(), vec![0_u8, 1, 2, 3].into()])
+        .await
+        .unwrap();
+    match mutator
+        .insert(vec![2.into(), vec![0_u8, 1, 2, 3, 4].into()])
+        .await
+    {
+        Err(noria::error::TableError::ValueTooLarge(1, 5, 4)) => {}
+        r => unreachable!("{:?}", r),
+    }
+    sleep().await;
+
+    let mut getter = g.view("AttachmentById").await.unwrap();
+    let rows = getter.lookup(&[1.into()], true).await.unwrap();
+    assert_eq!(rows, vec![vec![1.into(), vec![0_u8, 1, 2, 3].into()]]);
+    let data: Vec<u8> = (&rows[0][1]).into();
+    assert_eq!(data, vec![0, 1, 2, 3]);
+    assert!(getter.lookup(&[2.into()], true).await.unwrap().is_empty());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn check_view_consistency() {
+    let mut g = start_simple("check_view_consistency").await;
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255));
+        QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+    let mut mutator = g.table("Article").await.unwrap();
+    let mut getter = g.view("ArticleById").await.unwrap();
+
+    for i in 0..10 {
+        mutator.insert(vec![i.into(), "a".into()]).await.unwrap();
+    }
+    sleep().await;
+    for i in 0..5 {
+        assert_eq!(getter.lookup(&[i.into()], true).await.unwrap().len(), 1);
+    }
+
+    let event = g.check_consistency("ArticleById", 10).await.unwrap();
+    assert_eq!(
+        event,
+        noria::ConsistencyEvent::Consistent {
+            view: "ArticleById".to_owned(),
+            checked: 5,
+        }
+    );
+    assert_eq!(g.consistency_events().await.unwrap(), vec![event]);
+
+    // the checked keys are recomputed, and still readable
+    for i in 0..5 {
+        assert_eq!(
+            getter.lookup(&[i.into()], true).await.unwrap(),
+            vec![vec![i.into(), "a".into()]]
+        );
+    }
+
+    assert!(g.check_consistency("NoSuchView", 10).await.is_err());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn periodic_consistency_check() {
+    let clock = Clock::manual();
+    let mut b = Builder::default();
+    b.set_sharding(DEFAULT_SHARDING);
+    b.set_persistence(get_persistence_params("periodic_consistency_check"));
+    b.set_heartbeat_interval(Duration::from_millis(100));
+    b.set_consistency_check(Duration::from_secs(3600), 10);
+    b.set_clock(clock.clone());
+    let mut g = b.start_local().await.unwrap().0;
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255));
+        QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+    let mut mutator = g.table("Article").await.unwrap();
+    let mut getter = g.view("ArticleById").await.unwrap();
+    for i in 0..10 {
+        mutator.insert(vec![i.into(), "a".into()]).await.unwrap();
+    }
+    sleep().await;
+    for i in 0..5 {
+        assert_eq!(getter.lookup(&[i.into()], true).await.unwrap().len(), 1);
+    }
+    assert!(g.consistency_events().await.unwrap().is_empty());
+
+    // the check runs once it is due, and the controller keeps answering while it does
+    clock.advance(Duration::from_secs(2 * 3600));
+    let mut events = Vec::new();
+    for _ in 0..50 {
+        events = g.consistency_events().await.unwrap();
+        if !events.is_empty() {
+            break;
+        }
+        sleep().await;
+    }
+    assert_eq!(
+        events,
+        vec![noria::ConsistencyEvent::Consistent {
+            view: "ArticleById".to_owned(),
+            checked: 5,
+        }]
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn base_placement_respects_disk_quota() {
+    let mut builder = Builder::default();
+    builder.set_sharding(None);
+    builder.set_persistence(get_persistence_params("base_placement_respects_disk_quota"));
+    builder.set_disk_quota(1000);
+    builder.set_base_disk_reservation(600);
+    let mut g = builder.start_local().await.unwrap().0;
+
+    g.install_recipe("CREATE TABLE Article (id int, title varchar(255));")
+        .await
+        .unwrap();
+
+    // the only worker has no room for a second base table
+    assert!(g
+        .extend_recipe("CREATE TABLE Vote (aid int, uid int);")
+        .await
+        .is_err());
+    assert!(g.table("Vote").await.is_err());
+
+    // but queries that add no base tables are still fine
+    g.extend_recipe("QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;")
+        .await
+        .unwrap();
+    let mut mutator = g.table("Article").await.unwrap();
+    let mut getter = g.view("ArticleById").await.unwrap();
+    mutator.insert(vec![1.into(), "a".into()]).await.unwrap();
+    sleep().await;
+    assert_eq!(getter.lookup(&[1.into()], true).await.unwrap().len(), 1);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn reads_with_multiple_acceptors() {
+    let mut builder = Builder::default();
+    builder.set_sharding(DEFAULT_SHARDING);
+    builder.set_persistence(get_persistence_params("reads_with_multiple_acceptors"));
+    builder.set_read_acceptors(4);
+    let mut g = builder.start_local().await.unwrap().0;
+
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+        QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+    let mut mutator = g.table("Article").await.unwrap();
+    for i in 0..10 {
+        mutator.insert(vec![i.into(), "x".into()]).await.unwrap();
+    }
+    sleep().await;
+
+    // each view handle opens its own connections, which may land on any of the acceptors
+    for i in 0..10 {
+        let mut getter = g.view("ArticleById").await.unwrap();
+        assert_eq!(
+            getter.lookup(&[i.into()], true).await.unwrap(),
+            vec![vec![i.into(), "x".into()]]
+        );
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn mysql_frontend() {
+    use mysql::prelude::Queryable;
+
+    let mut builder = Builder::default();
+    builder.set_sharding(DEFAULT_SHARDING);
+    builder.set_persistence(get_persistence_params("mysql_frontend"));
+    builder.set_mysql_listen_addr("127.0.0.1:0".parse().unwrap());
+    let g = builder.start_local().await.unwrap().0;
+    let addr = g.mysql_addr().unwrap();
+
+    // the MySQL client blocks, so keep it off the runtime's worker threads
+    tokio::task::spawn_blocking(move || {
+        let mut conn = mysql::Conn::new(format!("mysql://{}", addr)).unwrap();
+        conn.query_drop("CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id))")
+            .unwrap();
+        conn.query_drop("INSERT INTO Article (id, title) VALUES (1, 'a')")
+            .unwrap();
+        conn.exec_drop("INSERT INTO Article (id, title) VALUES (?, ?)", (2, "b"))
+            .unwrap();
+        std::thread::sleep(get_settle_time());
+
+        let rows: Vec<(i32, String)> = conn
+            .query("SELECT id, title FROM Article WHERE id = 1")
+            .unwrap();
+        assert_eq!(rows, vec![(1, "a".to_owned())]);
+
+        // the prepared statement shares its view with the ad-hoc query above
+        let rows: Vec<(i32, String)> = conn
+            .exec("SELECT id, title FROM Article WHERE id = ?", (2,))
+            .unwrap();
+        assert_eq!(rows, vec![(2, "b".to_owned())]);
+
+        conn.exec_drop("DELETE FROM Article WHERE id = ?", (1,))
+            .unwrap();
+        std::thread::sleep(get_settle_time());
+        let rows: Vec<(i32, String)> = conn
+            .exec("SELECT id, title FROM Article WHERE id = ?", (1,))
+            .unwrap();
+        assert!(rows.is_empty());
+
+        assert!(conn.query_drop("DROP TABLE Article").is_err());
+
+        // placeholders can only be filled in by prepared statements, and the connection survives
+        // trying to use one without
+        assert!(conn
+            .query_drop("SELECT id, title FROM Article WHERE id = ?")
+            .is_err());
+        let rows: Vec<(i32, String)> = conn
+            .query("SELECT id, title FROM Article WHERE id = 2")
+            .unwrap();
+        assert_eq!(rows, vec![(2, "b".to_owned())]);
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn replay_priorities() {
+    use noria::ReplayPriority;
+
+    let mut g = start_simple("replay_priorities").await;
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+        QUERY ArticleById PRIORITY interactive: SELECT id, title FROM Article WHERE id = ?;
+        QUERY ArticleByTitle PRIORITY batch: SELECT id, title FROM Article WHERE title = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+    let mut mutator = g.table("Article").await.unwrap();
+    let mut by_id = g.view("ArticleById").await.unwrap();
+    let mut by_title = g.view("ArticleByTitle").await.unwrap();
+    for i in 0..10 {
+        mutator.insert(vec![i.into(), "x".into()]).await.unwrap();
+    }
+    sleep().await;
+
+    for i in 0..10 {
+        assert_eq!(
+            by_id.lookup(&[i.into()], true).await.unwrap(),
+            vec![vec![i.into(), "x".into()]]
+        );
+    }
+    assert_eq!(
+        by_title.lookup(&["x".into()], true).await.unwrap().len(),
+        10
+    );
+
+    // priorities can also be changed while the views are in use
+    g.set_replay_priority("ArticleByTitle", ReplayPriority::Interactive)
+        .await
+        .unwrap();
+    mutator.insert(vec![10.into(), "y".into()]).await.unwrap();
+    sleep().await;
+    assert_eq!(
+        by_title.lookup(&["y".into()], true).await.unwrap(),
+        vec![vec![10.into(), "y".into()]]
+    );
+
+    assert!(g
+        .set_replay_priority("NoSuchView", ReplayPriority::Batch)
+        .await
+        .is_err());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn sample_key_through_join() {
+    let mut g = start_simple("sample_key_through_join").await;
+    g.migrate(|mig| {
+        let article = mig.add_base(
+            "article",
+            &["id", "title"],
+            Base::new(vec![]).with_key(vec![0]),
+        );
+        let vote = mig.add_base("vote", &["user", "id"], Base::default());
+        let vc = mig.add_ingredient(
+            "vc",
+            &["id", "votes"],
+            Aggregation::COUNT.over(vote, 0, &[1]),
+        );
+        let j = Join::new(article, vc, JoinType::Inner, vec![B(0, 0), L(1), R(1)]);
+        let end = mig.add_ingredient("end", &["id", "title", "votes"], j);
+        mig.maintain_anonymous(end, &[0]);
+    })
+    .await;
+
+    let mut article = g.table("article").await.unwrap();
+    let mut vote = g.table("vote").await.unwrap();
+    article.insert(vec![1.into(), "a".into()]).await.unwrap();
+    article.insert(vec![2.into(), "b".into()]).await.unwrap();
+    vote.insert(vec![1.into(), 1.into()]).await.unwrap();
+    vote.insert(vec![2.into(), 1.into()]).await.unwrap();
+    vote.insert(vec![1.into(), 2.into()]).await.unwrap();
+    sleep().await;
+
+    let sample = g.sample_key("end", vec![1.into()]).await.unwrap();
+    assert_eq!(sample.view, "end");
+    assert_eq!(sample.rows, vec![vec![1.into(), "a".into(), 2.into()]]);
+
+    let node = |name: &str| {
+        sample
+            .upstream
+            .iter()
+            .find(|n| n.name == name)
+            .unwrap_or_else(|| panic!("no sample for {}", name))
+    };
+    assert_eq!(node("end").key_columns, vec![0]);
+    assert_eq!(node("article").key_columns, vec![0]);
+    assert_eq!(node("vc").key_columns, vec![0]);
+    // the key is the group-by column of the count, which is the second column of the votes
+    assert_eq!(node("vote").key_columns, vec![1]);
+
+    // the join looks up into both of its parents, so they keep the rows for the key
+    assert_eq!(node("article").rows, Some(vec![vec![1.into(), "a".into()]]));
+    assert_eq!(node("vc").rows, Some(vec![vec![1.into(), 2.into()]]));
+
+    assert!(g.sample_key("end", vec![]).await.is_err());
+    assert!(g.sample_key("nonexistent", vec![1.into()]).await.is_err());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn supervised_domain_rebuilds_queries_after_panic() {
+    let mut builder = Builder::default();
+    builder.set_sharding(None);
+    builder.set_persistence(get_persistence_params(
+        "supervised_domain_rebuilds_queries_after_panic",
+    ));
+    builder.set_domain_supervision(true);
+    let mut g = builder.start_local().await.unwrap().0;
+    // nodes named BOUNDARY_ get a domain of their own, so the division runs in a domain without
+    // base tables.
+    g.install_recipe(
+        "CREATE TABLE a (x int, y int, PRIMARY KEY(x));
+         QUERY BOUNDARY_q: SELECT x, x / y AS q FROM a WHERE x = ?;",
+    )
+    .await
+    .unwrap();
+
+    let mut a = g.table("a").await.unwrap();
+    let mut q = g.view("BOUNDARY_q").await.unwrap();
+    a.insert(vec![4.into(), 2.into()]).await.unwrap();
+    sleep().await;
+    assert_eq!(
+        q.lookup(&[4.into()], true).await.unwrap(),
+        vec![vec![4.into(), 2.into()]]
+    );
+    assert!(g.domain_failures().await.unwrap().is_empty());
+
+    // dividing by zero panics while the domain handles an update, which is lost with it, so the
+    // domain is not respawned, and the query is rebuilt instead
+    a.insert(vec![5.into(), 0.into()]).await.unwrap();
+    sleep().await;
+    let failures = g.domain_failures().await.unwrap();
+    assert_eq!(failures.len(), 1);
+    assert!(!failures[0].respawned);
+    assert!(failures[0].reason.contains("divide by zero"));
+    assert_eq!(failures[0].queries, vec!["BOUNDARY_q".to_owned()]);
+
+    // the rebuilt query has a new reader, whose state is recomputed from the base table
+    let mut q = g.view("BOUNDARY_q").await.unwrap();
+    assert_eq!(
+        q.lookup(&[4.into()], true).await.unwrap(),
+        vec![vec![4.into(), 2.into()]]
+    );
+    a.insert(vec![6.into(), 3.into()]).await.unwrap();
+    sleep().await;
+    assert_eq!(
+        q.lookup(&[6.into()], true).await.unwrap(),
+        vec![vec![6.into(), 2.into()]]
+    );
+    assert_eq!(g.domain_failures().await.unwrap().len(), 1);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn workers_report_client_connections() {
+    use noria::ConnectionKind;
+
+    let mut builder = Builder::default();
+    builder.set_sharding(None);
+    builder.set_persistence(get_persistence_params("workers_report_client_connections"));
+    builder.set_max_outstanding_reads(1);
+    builder.set_max_outstanding_writes(1);
+    let mut g = builder.start_local().await.unwrap().0;
+    g.install_recipe(
+        "CREATE TABLE Car (id int, price int, PRIMARY KEY(id));
+         QUERY CarPrice: SELECT price FROM Car WHERE id = ?;",
+    )
+    .await
+    .unwrap();
+
+    // writes are still acknowledged with only one allowed in flight per connection
+    let mut mutator = g.table("Car").await.unwrap();
+    mutator.set_identity("alice");
+    futures_util::future::try_join_all((1..=8).map(|id| {
+        let mut mutator = mutator.clone();
+        async move { mutator.insert(vec![id.into(), (id * 10).into()]).await }
+    }))
+    .await
+    .unwrap();
+    sleep().await;
+
+    // reads are still served with only one allowed in flight per connection
+    let mut getter = g.view("CarPrice").await.unwrap();
+    for id in 1..=8 {
+        assert_eq!(
+            getter.lookup(&[id.into()], true).await.unwrap(),
+            vec![vec![(id * 10).into()]]
+        );
+    }
+    let (a, b) = futures_util::future::join(
+        getter.clone().lookup(&[1.into()], true),
+        getter.lookup(&[9.into()], false),
+    )
+    .await;
+    assert_eq!(a.unwrap(), vec![vec![10.into()]]);
+    assert!(b.unwrap().is_empty());
+
+    // connections are reported with the next heartbeat
+    let mut waited = 0;
+    let connections = loop {
+        let connections = g.connections().await.unwrap();
+        let kinds: Vec<_> = connections.iter().map(|c| c.kind).collect();
+        if kinds.contains(&ConnectionKind::Read) && kinds.contains(&ConnectionKind::Write) {
+            break connections;
+        }
+        waited += 1;
+        assert!(waited < 50, "connections were never reported");
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+    };
+    let write = connections
+        .iter()
+        .find(|c| c.kind == ConnectionKind::Write)
+        .unwrap();
+    assert_eq!(write.identity.as_deref(), Some("alice"));
+    assert!(write.ops >= 1);
+    assert!(write.bytes_in > 0);
+    let read = connections
+        .iter()
+        .find(|c| c.kind == ConnectionKind::Read)
+        .unwrap();
+    assert!(read.ops >= 2);
+    assert!(read.bytes_out > 0);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn unnest_list_column() {
+    let mut g = start_simple("unnest_list_column").await;
+    let sql = "
+        CREATE TABLE Post (id int, tags text, PRIMARY KEY(id));
+        QUERY PostsByTag: SELECT id, UNNEST(tags) AS tag FROM Post WHERE tags = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+    let mut mutator = g.table("Post").await.unwrap();
+    let mut getter = g.view("PostsByTag").await.unwrap();
+
+    let tags = |tags: &[&str]| -> DataType {
+        tags.iter()
+            .map(|&t| DataType::from(t))
+            .collect::<Vec<_>>()
+            .into()
+    };
+    mutator
+        .insert(vec![1.into(), tags(&["rust", "databases"])])
+        .await
+        .unwrap();
+    mutator
+        .insert(vec![2.into(), tags(&["databases"])])
+        .await
+        .unwrap();
+    sleep().await;
+
+    assert_eq!(
+        getter.lookup(&["rust".into()], true).await.unwrap(),
+        vec![vec![1.into(), "rust".into()]]
+    );
+    let mut res = getter.lookup(&["databases".into()], true).await.unwrap();
+    res.sort();
+    assert_eq!(
+        res,
+        vec![
+            vec![1.into(), "databases".into()],
+            vec![2.into(), "databases".into()]
+        ]
+    );
+
+    // removing a post takes all of its tags with it
+    mutator.delete(vec![1.into()]).await.unwrap();
+    sleep().await;
+    assert!(getter
+        .lookup(&["rust".into()], true)
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        getter.lookup(&["databases".into()], true).await.unwrap(),
+        vec![vec![2.into(), "databases".into()]]
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn recipe_changes_are_described() {
+    let mut g = start_simple("recipe_changes_are_described").await;
+    let table = "CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));";
+
+    let r = g.install_recipe(table).await.unwrap();
+    assert_eq!(r.queries_added, vec!["Article".to_owned()]);
+    assert!(!r.dataflow.nodes_created.is_empty());
+    assert!(!r.dataflow.domains_created.is_empty());
+    assert!(r.dataflow.full_replays.is_empty());
+
+    // a new query builds on the existing base, and its reader is filled on demand
+    let r = g
+        .extend_recipe("QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;")
+        .await
+        .unwrap();
+    assert_eq!(r.queries_added, vec!["ArticleById".to_owned()]);
+    assert!(r.queries_removed.is_empty());
+    assert!(!r.dataflow.nodes_created.is_empty());
+    assert!(!r.dataflow.nodes_reused.is_empty());
+    assert!(r.dataflow.nodes_removed.is_empty());
+    assert!(r.dataflow.full_replays.is_empty());
+    assert!(!r.dataflow.partial_replays.is_empty());
+
+    // replacing a query under the same name changes it
+    let r = g
+        .install_recipe(&format!(
+            "{}\nQUERY ArticleById: SELECT id FROM Article WHERE title = ?;",
+            table
+        ))
+        .await
+        .unwrap();
+    assert_eq!(r.queries_changed, vec!["ArticleById".to_owned()]);
+    assert!(r.queries_added.is_empty());
+    assert!(r.queries_removed.is_empty());
+    assert!(!r.dataflow.nodes_removed.is_empty());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn writes_give_up_at_their_deadline() {
+    use noria::error::TableError;
+    use noria::TableOperation;
+    use std::time::Instant;
+
+    let mut g = start_simple("writes_give_up_at_their_deadline").await;
+    g.install_recipe(
+        "CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+         QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;",
+    )
+    .await
+    .unwrap();
+    let mut mutator = g.table("Article").await.unwrap();
+    let mut getter = g.view("ArticleById").await.unwrap();
+
+    // a deadline that has already passed cannot be met
+    let ops = vec![TableOperation::Insert(vec![1.into(), "a".into()])];
+    match mutator.perform_all_until(ops, Instant::now()).await {
+        Err(TableError::TimedOut) => {}
+        r => unreachable!("{:?}", r),
+    }
+
+    // but the handle can still be used for writes that have enough time
+    mutator.set_write_timeout(Some(Duration::from_secs(10)));
+    mutator.insert(vec![2.into(), "b".into()]).await.unwrap();
+    sleep().await;
+    assert_eq!(
+        getter.lookup(&[2.into()], true).await.unwrap(),
+        vec![vec![2.into(), "b".into()]]
+    );
+
+    let waits = mutator.write_waits();
+    assert_eq!(waits.writes, 2);
+    assert_eq!(waits.timed_out, 1);
+    assert!(waits.total >= waits.backpressure);
+    assert!(waits.backpressure >= waits.max_backpressure);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn snapshot_views_are_read_only_copies() {
+    let mut g = start_simple("snapshot_views_are_read_only_copies").await;
+    g.install_recipe(
+        "CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+         QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;",
+    )
+    .await
+    .unwrap();
+    let mut mutator = g.table("Article").await.unwrap();
+    let mut getter = g.view("ArticleById").await.unwrap();
+
+    mutator.insert(vec![1.into(), "a".into()]).await.unwrap();
+    sleep().await;
+    assert_eq!(getter.lookup(&[1.into()], true).await.unwrap().len(), 1);
+
+    assert!(g.fork_snapshot("not a name").await.is_err());
+    let copies = g.fork_snapshot("snap").await.unwrap();
+    assert!(copies.contains(&"ArticleById@snap".to_owned()));
+    assert!(g.fork_snapshot("snap").await.is_err());
+
+    // the copy holds what the view held when it was forked, and does not see later writes
+    mutator.insert(vec![2.into(), "b".into()]).await.unwrap();
+    sleep().await;
+    let mut copy = g.view("ArticleById@snap").await.unwrap();
+    assert_eq!(
+        copy.lookup(&[1.into()], true).await.unwrap(),
+        vec![vec![1.into(), "a".into()]]
+    );
+    assert!(copy.lookup(&[2.into()], true).await.unwrap().is_empty());
+    assert_eq!(getter.lookup(&[2.into()], true).await.unwrap().len(), 1);
+
+    // and it cannot be written to
+    assert!(g.table("ArticleById@snap").await.is_err());
+
+    g.drop_snapshot("snap").await.unwrap();
+    assert!(g.view("ArticleById@snap").await.is_err());
+    assert!(g.drop_snapshot("snap").await.is_err());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn snapshot_views_reflect_the_same_writes() {
+    // fully materialized views hold every row written so far
+    let mut b = Builder::default();
+    b.disable_partial();
+    b.set_persistence(get_persistence_params(
+        "snapshot_views_reflect_the_same_writes",
+    ));
+    let mut g = b.start_local().await.unwrap().0;
+    g.install_recipe(
+        "CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+         QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;
+         QUERY ArticleByTitle: SELECT id, title FROM Article WHERE title = ?;",
+    )
+    .await
+    .unwrap();
+    let mut mutator = g.table("Article").await.unwrap();
+
+    // fork while writes keep coming in
+    let writes = async move {
+        for id in 0..200 {
+            mutator
+                .insert(vec![id.into(), id.to_string().into()])
+                .await
+                .unwrap();
+        }
+    };
+    let (_, copies) = futures_util::future::join(writes, g.fork_snapshot("snap")).await;
+    assert_eq!(copies.unwrap().len(), 2);
+
+    // whatever writes made it into one copy made it into the other as well, and those were the
+    // first writes, in order
+    let mut by_id = g.view("ArticleById@snap").await.unwrap();
+    let mut by_title = g.view("ArticleByTitle@snap").await.unwrap();
+    let mut copied = Vec::new();
+    for id in 0..200 {
+        let a = by_id.lookup(&[id.into()], true).await.unwrap();
+        let b = by_title
+            .lookup(&[id.to_string().into()], true)
+            .await
+            .unwrap();
+        assert_eq!(a, b);
+        copied.push(!a.is_empty());
+    }
+    assert!(copied.windows(2).all(|w| w[0] || !w[1]));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn weighted_shards_serve_all_keys() {
+    let mut b = Builder::default();
+    b.set_sharding(Some(2));
+    b.set_shard_weights(vec![1, 3]);
+    b.set_shard_weights_for("ArticleByAuthor", vec![3, 1]);
+    b.set_persistence(get_persistence_params("weighted_shards_serve_all_keys"));
+    let mut g = b.start_local().await.unwrap().0;
+    g.install_recipe(
+        "CREATE TABLE Article (id int, author int, PRIMARY KEY(id));
+         QUERY ArticleById: SELECT id, author FROM Article WHERE id = ?;
+         QUERY ArticleByAuthor: SELECT id, author FROM Article WHERE author = ?;",
+    )
+    .await
+    .unwrap();
+    let mut mutator = g.table("Article").await.unwrap();
+    let mut by_id = g.view("ArticleById").await.unwrap();
+    let mut by_author = g.view("ArticleByAuthor").await.unwrap();
+
+    for id in 0..20 {
+        mutator
+            .insert(vec![id.into(), (id % 5).into()])
+            .await
+            .unwrap();
+    }
+    sleep().await;
+
+    // every key is found on the shard it was written to, whether it is read directly or replayed
+    // through a sharder into a domain weighted differently
+    for id in 0..20 {
+        assert_eq!(
+            by_id.lookup(&[id.into()], true).await.unwrap(),
+            vec![vec![id.into(), (id % 5).into()]]
+        );
+    }
+    for author in 0..5 {
+        assert_eq!(
+            by_author
+                .lookup(&[author.into()], true)
+                .await
+                .unwrap()
+                .len(),
+            4
+        );
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn range_shards_serve_all_keys() {
+    let mut g = start_simple("range_shards_serve_all_keys").await;
+    g.install_recipe(
+        "CREATE TABLE Article (id int, author int, PRIMARY KEY(id));
+         QUERY ArticleById: SELECT id, author FROM Article WHERE id = ?;
+         QUERY ArticleByAuthor: SELECT id, author FROM Article WHERE author = ?;",
+    )
+    .await
+    .unwrap();
+    let mut queries = g.set_shard_ranges("Article", vec![5.into()]).await.unwrap();
+    queries.sort();
+    assert_eq!(queries, vec!["ArticleByAuthor", "ArticleById"]);
+    assert!(g
+        .set_shard_ranges("Article", vec![10.into(), 5.into()])
+        .await
+        .is_err());
+
+    let mut mutator = g.table("Article").await.unwrap();
+    for id in 0..20 {
+        mutator
+            .insert(vec![id.into(), (id / 2).into()])
+            .await
+            .unwrap();
+    }
+    sleep().await;
+
+    // each write went to the shard that owns the range its key falls in
+    assert_eq!(g.commit_seqs("Article").await.unwrap(), vec![5, 15]);
+
+    // every key is found, whether it is read directly or replayed through a sharder, and so is
+    // every author, whose articles are on both sides of the split point.
+    async fn check(g: &mut Handle<LocalAuthority>) {
+        let mut by_id = g.view("ArticleById").await.unwrap();
+        let mut by_author = g.view("ArticleByAuthor").await.unwrap();
+        for id in 0..20 {
+            assert_eq!(
+                by_id.lookup(&[id.into()], true).await.unwrap(),
+                vec![vec![id.into(), (id / 2).into()]]
+            );
+        }
+        for author in 0..10 {
+            let mut rows = by_author.lookup(&[author.into()], true).await.unwrap();
+            rows.sort();
+            assert_eq!(
+                rows,
+                vec![
+                    vec![(author * 2).into(), author.into()],
+                    vec![(author * 2 + 1).into(), author.into()],
+                ]
+            );
+        }
+    }
+    check(&mut g).await;
+
+    // moving the split point moves the keys between it and the old one to the other shard
+    g.set_shard_ranges("Article", vec![15.into()])
+        .await
+        .unwrap();
+    sleep().await;
+    check(&mut g).await;
+    assert_eq!(g.commit_seqs("Article").await.unwrap().len(), 2);
+    assert_eq!(g.export_base("Article").await.unwrap().len(), 20);
+
+    // and writes after the move go to the shard that owns the key now
+    let mut mutator = g.table("Article").await.unwrap();
+    mutator.insert(vec![20.into(), 10.into()]).await.unwrap();
+    sleep().await;
+    let mut by_id = g.view("ArticleById").await.unwrap();
+    assert_eq!(
+        by_id.lookup(&[20.into()], true).await.unwrap(),
+        vec![vec![20.into(), 10.into()]]
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn filtered_aggregations() {
+    let mut g = start_simple("filtered_aggregations").await;
+    let sql = "
+        CREATE TABLE Car (id int, brand varchar(255), PRIMARY KEY(id));
+        QUERY CountNewCars: SELECT COUNT(*) FILTER (WHERE id > 0) FROM Car WHERE brand = ?;
+        QUERY SumNewCars: SELECT SUM(id) FILTER (WHERE id > 1) FROM Car WHERE brand = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    let mut mutator = g.table("Car").await.unwrap();
+    let mut count = g.view("CountNewCars").await.unwrap();
+    let mut sum = g.view("SumNewCars").await.unwrap();
+
+    let brands = vec!["Volvo", "Volvo", "Volvo", "Volkswagen"];
+    for (i, &brand) in brands.iter().enumerate() {
+        mutator.insert(vec![i.into(), brand.into()]).await.unwrap();
+    }
+    sleep().await;
+
+    let result = count.lookup(&["Volvo".into()], true).await.unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0][0], 2.into());
+    let result = sum.lookup(&["Volvo".into()], true).await.unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0][0], 2.into());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn authority_outage_degrades_gracefully() {
+    use crate::LocalCluster;
+
+    let mut cluster = LocalCluster::builder()
+        .workers(2)
+        .persistence(get_persistence_params(
+            "authority_outage_degrades_gracefully",
+        ))
+        .build()
+        .await
+        .unwrap();
+
+    cluster
+        .install_recipe(
+            "CREATE TABLE Car (id int, price int, PRIMARY KEY(id));
+             QUERY CarPrice: SELECT price FROM Car WHERE id = ?;",
+        )
+        .await
+        .unwrap();
+    let mut car = cluster.table("Car").await.unwrap();
+    let mut price = cluster.view("CarPrice").await.unwrap();
+
+    cluster.authority().set_reachable(false);
+    sleep().await;
+
+    // the existing dataflow keeps serving reads and writes
+    car.insert(vec![1.into(), 10.into()]).await.unwrap();
+    sleep().await;
+    assert_eq!(
+        price.lookup(&[1.into()], true).await.unwrap(),
+        vec![vec![10.into()]]
+    );
+
+    // the controller can still be found, but refuses to change the dataflow
+    assert!(cluster.outputs().await.unwrap().contains_key("CarPrice"));
+    assert!(cluster
+        .extend_recipe("QUERY CarById: SELECT id FROM Car WHERE price = ?;")
+        .await
+        .is_err());
+
+    cluster.authority().set_reachable(true);
+    sleep().await;
+
+    cluster
+        .extend_recipe("QUERY CarById: SELECT id FROM Car WHERE price = ?;")
+        .await
+        .unwrap();
+    let mut by_price = cluster.view("CarById").await.unwrap();
+    assert_eq!(
+        by_price.lookup(&[10.into()], true).await.unwrap(),
+        vec![vec![1.into()]]
+    );
+
+    cluster.shutdown().await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn sharded_compound_key_base() {
+    use noria::Modification;
+
+    let mut g = start_simple("sharded_compound_key_base").await;
+    let sql = "
+        CREATE TABLE Vote (aid int, uid int, weight int, PRIMARY KEY(aid, uid));
+        QUERY VotesByArticle: SELECT uid, weight FROM Vote WHERE aid = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+    let mut vote = g.table("Vote").await.unwrap();
+    let mut votes = g.view("VotesByArticle").await.unwrap();
+
+    for aid in 0..4 {
+        for uid in 0..4 {
+            vote.insert(vec![aid.into(), uid.into(), 1.into()])
+                .await
+                .unwrap();
+        }
+    }
+    sleep().await;
+
+    // updates and deletes find the row wherever its key was placed
+    vote.update(
+        vec![1.into(), 2.into()],
+        vec![(2, Modification::Set(5.into()))],
+    )
+    .await
+    .unwrap();
+    vote.delete(vec![1.into(), 3.into()]).await.unwrap();
+    sleep().await;
+
+    for aid in 0..4 {
+        let mut rs = votes.lookup(&[aid.into()], true).await.unwrap();
+        rs.sort();
+        if aid == 1 {
+            assert_eq!(
+                rs,
+                vec![
+                    vec![0.into(), 1.into()],
+                    vec![1.into(), 1.into()],
+                    vec![2.into(), 5.into()],
+                ]
+            );
+        } else {
+            assert_eq!(rs.len(), 4);
+        }
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn all_or_nothing_inserts() {
+    use noria::error::TableError;
+
+    let mut g = start_simple_unsharded("all_or_nothing_inserts").await;
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+        QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+    let mut article = g.table("Article").await.unwrap();
+    let mut articles = g.view("ArticleById").await.unwrap();
+
+    article.insert(vec![1.into(), "a".into()]).await.unwrap();
+    match article
+        .insert_all_or_nothing(vec![
+            vec![2.into(), "b".into()],
+            vec![1.into(), "c".into()],
+            vec![3.into(), "d".into()],
+            vec![3.into(), "e".into()],
+        ])
+        .await
+    {
+        Err(TableError::Rejected(rows)) => {
+            assert_eq!(
+                rows.into_iter().map(|(i, _)| i).collect::<Vec<_>>(),
+                vec![1, 3]
+            );
+        }
+        r => unreachable!("{:?}", r),
+    }
+    sleep().await;
+
+    // none of the rows were inserted
+    for id in 2..4 {
+        assert!(articles
+            .lookup(&[id.into()], true)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+    assert_eq!(
+        articles.lookup(&[1.into()], true).await.unwrap(),
+        vec![vec![1.into(), "a".into()]]
+    );
+
+    article
+        .insert_all_or_nothing(vec![vec![2.into(), "b".into()], vec![3.into(), "d".into()]])
+        .await
+        .unwrap();
+    sleep().await;
+
+    assert_eq!(
+        articles.lookup(&[3.into()], true).await.unwrap(),
+        vec![vec![3.into(), "d".into()]]
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn lookups_give_up_on_pending_replays() {
+    use noria::error::ViewError;
+
+    let mut g = Builder::default();
+    g.set_sharding(None);
+    g.set_persistence(get_persistence_params("lookups_give_up_on_pending_replays"));
+    // no upqueries are ever issued, so misses in partial views are never filled
+    g.set_max_concurrent_replay(0);
+    let mut g = g.start_local().await.unwrap().0;
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+        QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+    let mut mutator = g.table("Article").await.unwrap();
+    let mut getter = g.view("ArticleById").await.unwrap();
+
+    mutator.insert(vec![1.into(), "a".into()]).await.unwrap();
+    sleep().await;
+
+    match getter
+        .multi_lookup_timeout(
+            vec![vec![1.into()], vec![2.into()]],
+            Duration::from_millis(100),
+        )
+        .await
+    {
+        Err(ViewError::ReplayPending(rs)) => assert_eq!(rs, vec![None, None]),
+        r => unreachable!("{:?}", r),
+    }
+
+    // the handle is still usable afterwards
+    assert!(getter.lookup(&[1.into()], false).await.unwrap().is_empty());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn compound_key_reader_stays_sharded() {
+    let mut g = start_simple("compound_key_reader_stays_sharded").await;
+
+    // article is shuffled by author for the join, so the join is sharded by author. the reader's
+    // key includes author, so the reader can stay sharded too, and each lookup only goes to the
+    // shard that owns its author.
+    g.migrate(|mig| {
+        let article = mig.add_base(
+            "article",
+            &["id", "author", "title"],
+            Base::default().with_key(vec![0]),
+        );
+        let author = mig.add_base(
+            "author",
+            &["aid", "name"],
+            Base::default().with_key(vec![0]),
+        );
+        let j = Join::new(
+            article,
+            author,
+            JoinType::Inner,
+            vec![L(0), B(1, 0), L(2), R(1)],
+        );
+        let j = mig.add_ingredient("j", &["id", "author", "title", "name"], j);
+        mig.maintain_anonymous(j, &[1, 2]);
+    })
+    .await;
+
+    let mut article = g.table("article").await.unwrap();
+    let mut author = g.table("author").await.unwrap();
+    let mut j = g.view("j").await.unwrap();
+
+    for aid in 0..4 {
+        author
+            .insert(vec![aid.into(), format!("author {}", aid).into()])
+            .await
+            .unwrap();
+    }
+    for id in 0..16 {
+        article
+            .insert(vec![
+                id.into(),
+                (id % 4).into(),
+                format!("t{}", id % 3).into(),
+            ])
+            .await
+            .unwrap();
+    }
+    sleep().await;
+
+    let mut keys = Vec::new();
+    for aid in 0..4 {
+        for t in 0..3 {
+            keys.push(vec![aid.into(), format!("t{}", t).into()]);
+        }
+    }
+    let results = j.multi_lookup(keys.clone(), true).await.unwrap();
+    assert_eq!(results.len(), keys.len());
+    for rs in results {
+        let mut rs: Vec<Vec<DataType>> = rs.into();
+        rs.sort();
+        // every row in a key's results belongs to that key, and none are missing
+        assert!(!rs.is_empty());
+        let key = vec![rs[0][1].clone(), rs[0][2].clone()];
+        let expected: Vec<Vec<DataType>> = (0..16)
+            .filter(|id| DataType::from(id % 4) == key[0])
+            .filter(|id| DataType::from(format!("t{}", id % 3)) == key[1])
+            .map(|id| {
+                vec![
+                    id.into(),
+                    (id % 4).into(),
+                    format!("t{}", id % 3).into(),
+                    format!("author {}", id % 4).into(),
+                ]
+            })
+            .collect();
+        assert_eq!(rs, expected);
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn manual_domain_failover() {
+    use crate::LocalCluster;
+
+    let mut cluster = LocalCluster::builder()
+        .workers(2)
+        .sharding(Some(2))
+        .persistence(get_persistence_params("manual_domain_failover"))
+        .build()
+        .await
+        .unwrap();
+
+    // the view is keyed by price rather than by the table's key, so its reader is shuffled into
+    // domains of its own.
+    cluster
+        .install_recipe(
+            "CREATE TABLE Car (id int, price int, PRIMARY KEY(id));
+             QUERY CarByPrice: SELECT id FROM Car WHERE price = ?;",
+        )
+        .await
+        .unwrap();
+    let mut car = cluster.table("Car").await.unwrap();
+    car.insert(vec![1.into(), 10.into()]).await.unwrap();
+    sleep().await;
+
+    let stats = cluster.statistics().await.unwrap();
+    let (readers, bases): (Vec<_>, Vec<_>) = stats
+        .iter()
+        .map(|(&(di, shard), (_, nodes))| {
+            let reader = nodes.values().any(|n| n.read_amplification.is_some());
+            (di.index(), shard, reader)
+        })
+        .partition(|&(_, _, reader)| reader);
+
+    // base tables can't be moved
+    let (domain, shard, _) = bases[0];
+    assert!(cluster.fail_over(domain, shard).await.is_err());
+
+    // the view's new reader is set up like the one it replaces
+    cluster
+        .set_read_attribution("CarByPrice", true)
+        .await
+        .unwrap();
+
+    let (domain, shard, _) = readers[0];
+    let failover = cluster.fail_over(domain, shard).await.unwrap();
+    assert_eq!(failover.domain, domain);
+    assert_eq!(failover.queries, vec!["CarByPrice".to_owned()]);
+    assert_eq!(cluster.failovers().await.unwrap(), vec![failover]);
+
+    // the view is served from its new domains
+    let mut by_price = cluster.view("CarByPrice").await.unwrap();
+    by_price.set_identity("alice");
+    assert_eq!(
+        by_price.lookup(&[10.into()], true).await.unwrap(),
+        vec![vec![1.into()]]
+    );
+    let reads = cluster.read_attribution("CarByPrice").await.unwrap();
+    assert_eq!(reads.len(), 1);
+    assert_eq!(reads[0].identity.as_deref(), Some("alice"));
+    car.insert(vec![2.into(), 10.into()]).await.unwrap();
+    sleep().await;
+    let mut rs = by_price.lookup(&[10.into()], true).await.unwrap();
+    rs.sort();
+    assert_eq!(rs, vec![vec![1.into()], vec![2.into()]]);
+
+    cluster.shutdown().await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn ordered_writes_per_key() {
+    let mut g = start_simple("ordered_writes_per_key").await;
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+        QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+    let table = g.table("Article").await.unwrap();
+    assert!(table.ordered(0).is_err());
+    let article = table.ordered(4).unwrap();
+    let mut articles = g.view("ArticleById").await.unwrap();
+
+    // queue up every write before waiting for any of them, so that a handle that did not keep
+    // them in order could deliver a delete after the insert that follows it
+    let mut writes = Vec::new();
+    for id in 0..8 {
+        for v in 0..20 {
+            writes.push(article.insert(vec![id.into(), format!("{}", v).into()]));
+            writes.push(article.delete(vec![id.into()]));
+        }
+        writes.push(article.insert(vec![id.into(), "last".into()]));
+    }
+    for res in futures_util::future::join_all(writes).await {
+        res.unwrap();
+    }
+    sleep().await;
+
+    for id in 0..8 {
+        assert_eq!(
+            articles.lookup(&[id.into()], true).await.unwrap(),
+            vec![vec![id.into(), "last".into()]]
+        );
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn ordered_writes_per_key_survive_timeouts() {
+    use noria::error::TableError;
+
+    let mut g = start_simple("ordered_writes_per_key_survive_timeouts").await;
+    let sql = "
+        CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+        QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+    let mut table = g.table("Article").await.unwrap();
+    table.set_write_timeout(Some(Duration::from_nanos(1)));
+    let article = table.ordered(1).unwrap();
+    let mut articles = g.view("ArticleById").await.unwrap();
+
+    // the submitters give up on their writes, but the writes are still delivered in order
+    let mut writes = Vec::new();
+    for v in 0..20 {
+        writes.push(article.insert(vec![1.into(), format!("{}", v).into()]));
+        writes.push(article.delete(vec![1.into()]));
+    }
+    writes.push(article.insert(vec![1.into(), "last".into()]));
+    let results = futures_util::future::join_all(writes).await;
+    assert!(results
+        .iter()
+        .any(|r| matches!(r, Err(TableError::TimedOut))));
+    sleep().await;
+
+    assert_eq!(
+        articles.lookup(&[1.into()], true).await.unwrap(),
+        vec![vec![1.into(), "last".into()]]
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn pinned_placement() {
+    use crate::PlacementStrategy;
+    use noria::ConnectionKind;
+
+    let authority = Arc::new(LocalAuthority::new());
+    let mut builder = Builder::default();
+    builder.set_sharding(Some(2));
+    builder.set_quorum(2);
+    builder.set_persistence(get_persistence_params("pinned_placement"));
+    builder.set_placement_strategy(PlacementStrategy::Pinned("b".to_owned()));
+    builder.set_worker_label("a");
+    let (mut g, _) = builder.start(authority.clone()).await.unwrap();
+    builder.set_worker_label("b");
+    let (_b, _) = builder.start(authority).await.unwrap();
+
+    g.install_recipe(
+        "CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+         QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;",
+    )
+    .await
+    .unwrap();
+    let mut mutator = g.table("Article").await.unwrap();
+    for i in 0..10 {
+        mutator.insert(vec![i.into(), "x".into()]).await.unwrap();
+    }
+    sleep().await;
+    let mut getter = g.view("ArticleById").await.unwrap();
+    for i in 0..10 {
+        assert_eq!(getter.lookup(&[i.into()], true).await.unwrap().len(), 1);
+    }
+
+    // round-robin placement would have spread the shards over both workers
+    let mut waited = 0;
+    let connections = loop {
+        let connections = g.connections().await.unwrap();
+        let writes = connections
+            .iter()
+            .filter(|c| c.kind == ConnectionKind::Write)
+            .count();
+        if writes >= 2 {
+            break connections;
+        }
+        waited += 1;
+        assert!(waited < 50, "connections were never reported");
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+    };
+    let worker = connections[0].worker;
+    assert!(connections.iter().all(|c| c.worker == worker));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn soft_deletes() {
+    let mut g = start_simple("soft_deletes").await;
+    g.install_recipe(
+        "CREATE TABLE Article (id int, title varchar(255), deleted int, PRIMARY KEY(id));
+         SOFT DELETE Article(deleted);
+         QUERY ArticleById: SELECT id, title, deleted FROM Article WHERE id = ?;
+         QUERY ArticleCount: SELECT title, COUNT(id) AS n FROM Article WHERE title = ? GROUP BY title;
+         QUERY AllArticles WITH DELETED: SELECT id, title, deleted FROM Article WHERE id = ?;",
+    )
+    .await
+    .unwrap();
+
+    let mut article = g.table("Article").await.unwrap();
+    let mut by_id = g.view("ArticleById").await.unwrap();
+    let mut count = g.view("ArticleCount").await.unwrap();
+    let mut all = g.view("AllArticles").await.unwrap();
+    assert_eq!(all.tombstone_column(), Some(2));
+
+    article
+        .insert(vec![1.into(), "a".into(), 0.into()])
+        .await
+        .unwrap();
+    article
+        .insert(vec![2.into(), "a".into(), 0.into()])
+        .await
+        .unwrap();
+    article.delete(vec![1.into()]).await.unwrap();
+    sleep().await;
+
+    // deleted rows do not reach queries, including their aggregations
+    assert!(by_id.lookup(&[1.into()], true).await.unwrap().is_empty());
+    assert_eq!(
+        count.lookup(&["a".into()], true).await.unwrap(),
+        vec![vec!["a".into(), 1.into()]]
+    );
+    // unless they ask for them
+    assert_eq!(
+        all.lookup(&[1.into()], true).await.unwrap(),
+        vec![vec![1.into(), "a".into(), 1.into()]]
+    );
+
+    // inserting the key again brings the row back
+    article
+        .insert(vec![1.into(), "b".into(), 0.into()])
+        .await
+        .unwrap();
+    sleep().await;
+    assert_eq!(
+        by_id.lookup(&[1.into()], true).await.unwrap(),
+        vec![vec![1.into(), "b".into(), 0.into()]]
+    );
+    assert_eq!(
+        count.lookup(&["a".into()], true).await.unwrap(),
+        vec![vec!["a".into(), 1.into()]]
+    );
+
+    // tables cannot switch to soft deletes once they exist
+    assert!(g
+        .extend_recipe("CREATE TABLE Comment (id int, deleted int, PRIMARY KEY(id));")
+        .await
+        .is_ok());
+    assert!(g
+        .extend_recipe("SOFT DELETE Comment(deleted);")
+        .await
+        .is_err());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn workers_report_resources() {
+    let mut g = start_simple("workers_report_resources").await;
+    g.install_recipe(
+        "CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+         QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;",
+    )
+    .await
+    .unwrap();
+    let mut mutator = g.table("Article").await.unwrap();
+    mutator.insert(vec![1.into(), "a".into()]).await.unwrap();
+
+    // resources are reported with the next heartbeat
+    let mut waited = 0;
+    let worker = loop {
+        let stats = g.statistics().await.unwrap();
+        assert_eq!(stats.workers.len(), 1);
+        let worker = stats.workers[0].clone();
+        if worker.domains_hosted > 0 {
+            break worker;
+        }
+        waited += 1;
+        assert!(waited < 50, "resources were never reported");
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+    };
+    assert!(worker.healthy);
+    if cfg!(target_os = "linux") {
+        assert!(worker.cpu_load.is_some());
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn writes_during_migration() {
+    use noria::error::TableError;
+
+    let mut builder = Builder::default();
+    builder.set_sharding(Some(2));
+    builder.set_persistence(get_persistence_params("writes_during_migration"));
+    builder.set_reject_writes_during_migration(true);
+    let mut g = builder.start_local().await.unwrap().0;
+    g.install_recipe(
+        "CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+         CREATE TABLE Vote (id int, PRIMARY KEY(id));",
+    )
+    .await
+    .unwrap();
+
+    // one writer gives up on writes that are turned away, while the other retries them
+    let mut impatient = g.table("Article").await.unwrap();
+    let mut patient = g.table("Article").await.unwrap();
+    patient.set_migration_backoff(Some(Duration::from_millis(1)));
+    // writes to a table that the migrations add nothing below are never turned away
+    let mut unaffected = g.table("Vote").await.unwrap();
+    let writers = tokio::spawn(async move {
+        let mut applied = 0;
+        for i in 0..200 {
+            match impatient.insert(vec![(2 * i).into(), "a".into()]).await {
+                Ok(()) => applied += 1,
+                Err(TableError::MigrationInProgress) => {}
+                Err(e) => panic!("write failed: {:?}", e),
+            }
+            patient
+                .insert(vec![(2 * i + 1).into(), "b".into()])
+                .await
+                .unwrap();
+            unaffected.insert(vec![i.into()]).await.unwrap();
+        }
+        applied
+    });
+
+    for i in 0..5 {
+        g.extend_recipe(&format!(
+            "QUERY Q{}: SELECT id, title FROM Article WHERE id = ?;",
+            i
+        ))
+        .await
+        .unwrap();
+    }
+    let applied = writers.await.unwrap();
+    sleep().await;
+
+    let mut all = g.view("Q4").await.unwrap();
+    let keys: Vec<_> = (0..400).map(|i| vec![DataType::from(i)]).collect();
+    let rows = all.multi_lookup(keys, true).await.unwrap();
+    let found = |parity: usize| {
+        rows.iter()
+            .enumerate()
+            .filter(|&(i, r)| i % 2 == parity && !r.is_empty())
+            .count()
+    };
+    assert_eq!(found(0), applied);
+    assert_eq!(found(1), 200);
+
+    // once the migrations have completed, writes are accepted again
+    let mut mutator = g.table("Article").await.unwrap();
+    mutator.insert(vec![400.into(), "c".into()]).await.unwrap();
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn read_attribution() {
+    let mut g = start_simple("read_attribution").await;
+    g.install_recipe(
+        "CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+         QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;",
+    )
+    .await
+    .unwrap();
+    let mut mutator = g.table("Article").await.unwrap();
+    for i in 0..4 {
+        mutator.insert(vec![i.into(), "x".into()]).await.unwrap();
+    }
+    sleep().await;
+
+    let mut getter = g.view("ArticleById").await.unwrap();
+    getter.lookup(&[0.into()], true).await.unwrap();
+    assert!(g.read_attribution("ArticleById").await.unwrap().is_empty());
+
+    g.set_read_attribution("ArticleById", true).await.unwrap();
+    let mut a = g.view("ArticleById").await.unwrap();
+    a.set_identity("a");
+    let mut b = a.clone();
+    b.set_identity("b");
+    for i in 0..4 {
+        a.lookup(&[i.into()], true).await.unwrap();
+        a.lookup(&[1.into()], true).await.unwrap();
+    }
+    b.lookup(&[2.into()], true).await.unwrap();
+    getter.lookup(&[3.into()], true).await.unwrap();
+    getter.lookup(&[3.into()], true).await.unwrap();
+
+    let reads = g.read_attribution("ArticleById").await.unwrap();
+    let identities: Vec<_> = reads.iter().map(|r| r.identity.as_deref()).collect();
+    assert_eq!(identities, vec![Some("a"), None, Some("b")]);
+    assert_eq!(reads[0].reads, 8);
+    assert_eq!(reads[0].hot_keys[0], (vec![1.into()], 5));
+    assert_eq!(reads[1].reads, 2);
+    assert_eq!(reads[2].reads, 1);
+
+    g.set_read_attribution("ArticleById", false).await.unwrap();
+    assert!(g.read_attribution("ArticleById").await.unwrap().is_empty());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn export_and_import_base() {
+    let recipe = "CREATE TABLE Article (id int, title varchar(255), PRIMARY KEY(id));
+                  QUERY ArticleById: SELECT id, title FROM Article WHERE id = ?;";
+
+    let mut blue = start_simple("export_and_import_base_blue").await;
+    blue.install_recipe(recipe).await.unwrap();
+    let mut mutator = blue.table("Article").await.unwrap();
+    for i in 0..10 {
+        mutator.insert(vec![i.into(), "a".into()]).await.unwrap();
+    }
+    mutator.delete(vec![3.into()]).await.unwrap();
+    sleep().await;
+
+    let export = blue.export_base("Article").await.unwrap();
+    assert_eq!(export.table, "Article");
+    assert_eq!(export.columns, vec!["id", "title"]);
+    assert_eq!(export.len(), 9);
+    let seqs: Vec<_> = export.shards.iter().map(|s| s.commit_seq).collect();
+    assert_eq!(seqs, blue.commit_seqs("Article").await.unwrap());
+
+    let mut green = start_simple("export_and_import_base_green").await;
+    green.install_recipe(recipe).await.unwrap();
+    green.import_base(export.clone()).await.unwrap();
+    sleep().await;
+
+    // the rows show up in the views, and each shard continues numbering after the export
+    let mut getter = green.view("ArticleById").await.unwrap();
+    for i in 0..10 {
+        let expected = if i == 3 { 0 } else { 1 };
+        assert_eq!(
+            getter.lookup(&[i.into()], true).await.unwrap().len(),
+            expected
+        );
+    }
+    assert_eq!(green.commit_seqs("Article").await.unwrap(), seqs);
+    assert_eq!(green.export_base("Article").await.unwrap().len(), 9);
+
+    // a base that has been written to can't be imported into
+    assert!(green.import_base(export).await.is_err());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn compressed_domain_connections() {
+    use crate::LocalCluster;
+    use noria::channel::Compression;
+
+    let mut cluster = LocalCluster::builder()
+        .workers(2)
+        .sharding(Some(2))
+        .configure(|b| b.set_domain_compression(Compression::Lz4))
+        .build()
+        .await
+        .unwrap();
+
+    // the view is keyed by price rather than by the table's key, so records are shuffled between
+    // the shards of the table and of the view, which are spread over both workers.
+    cluster
+        .install_recipe(
+            "CREATE TABLE Car (id int, price int, name text, PRIMARY KEY(id));
+             QUERY CarByPrice: SELECT id, name FROM Car WHERE price = ?;",
+        )
+        .await
+        .unwrap();
+    let mut car = cluster.table("Car").await.unwrap();
+    let name = "a very long and very repetitive name ".repeat(10);
+    for id in 0..20 {
+        car.insert(vec![id.into(), (id % 4).into(), name.clone().into()])
+            .await
+            .unwrap();
+    }
+    sleep().await;
+
+    let mut by_price = cluster.view("CarByPrice").await.unwrap();
+    for price in 0..4 {
+        let rows = by_price.lookup(&[price.into()], true).await.unwrap();
+        assert_eq!(rows.len(), 5);
+        assert!(rows.iter().all(|r| r[1] == name.as_str().into()));
+    }
+
+    let stats = cluster.statistics().await.unwrap();
+    let (uncompressed, compressed) = stats.values().fold((0, 0), |(u, c), (ds, _)| {
+        (u + ds.uncompressed_bytes, c + ds.compressed_bytes)
+    });
+    assert!(compressed > 0);
+    assert!(uncompressed > compressed);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn masked_columns() {
+    use noria::results::{Comparison, Predicate};
+
+    let mut g = start_simple("masked_columns").await;
+    g.install_recipe(
+        "CREATE TABLE Users (id int, email text, PRIMARY KEY(id));
+         MASK Users(email);
+         UNMASK VIEW Support;
+         QUERY Profile: SELECT id, email FROM Users WHERE id = ?;
+         QUERY Addresses: SELECT id, GROUP_CONCAT(email) AS emails FROM Users \
+                          WHERE id = ? GROUP BY id;
+         QUERY Support: SELECT email, id FROM Users WHERE id = ?;",
+    )
+    .await
+    .unwrap();
+
+    let mut users = g.table("Users").await.unwrap();
+    users
+        .insert(vec![1.into(), "alice@example.com".into()])
+        .await
+        .unwrap();
+    sleep().await;
+
+    // the email address is masked from ordinary clients
+    let mut profile = g.view("Profile").await.unwrap();
+    assert_eq!(
+        profile.lookup(&[1.into()], true).await.unwrap(),
+        vec![vec![1.into(), DataType::None]]
+    );
+
+    // and cannot be probed for with a filter
+    let p = Predicate::compare(1, Comparison::Equal, "alice@example.com".into());
+    assert!(profile
+        .lookup_filtered(&[1.into()], p, true)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // whatever identity the client claims
+    let mut claimed = g.view("Profile").await.unwrap();
+    claimed.set_identity("auditor");
+    assert_eq!(
+        claimed.lookup(&[1.into()], true).await.unwrap(),
+        vec![vec![1.into(), DataType::None]]
+    );
+
+    // values computed from it are masked too
+    let mut addresses = g.view("Addresses").await.unwrap();
+    assert_eq!(
+        addresses.lookup(&[1.into()], true).await.unwrap(),
+        vec![vec![1.into(), DataType::None]]
+    );
+
+    // only those reading an exempt view see it in the clear
+    let mut support = g.view("Support").await.unwrap();
+    assert_eq!(
+        support.lookup(&[1.into()], true).await.unwrap(),
+        vec![vec!["alice@example.com".into(), 1.into()]]
+    );
+
+    // masks added later apply to existing views
+    g.extend_recipe("MASK Users(id);").await.unwrap();
+    assert_eq!(
+        profile.lookup(&[1.into()], true).await.unwrap(),
+        vec![vec![DataType::None, DataType::None]]
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn replay_checksums() {
+    let mut b = Builder::default();
+    b.set_sharding(DEFAULT_SHARDING);
+    b.set_persistence(get_persistence_params("replay_checksums"));
+    b.set_replay_checksums(true);
+    let mut g = b.start_local().await.unwrap().0;
+    g.install_recipe(
+        "CREATE TABLE Car (id int, price int, name text, PRIMARY KEY(id));
+         QUERY CarByPrice: SELECT id, name FROM Car WHERE price = ?;",
+    )
+    .await
+    .unwrap();
+    let mut car = g.table("Car").await.unwrap();
+    for id in 0..20 {
+        car.insert(vec![id.into(), (id % 4).into(), id.to_string().into()])
+            .await
+            .unwrap();
+    }
+    sleep().await;
+
+    // every lookup misses, so the rows are replayed to the view with checksums.
+    let mut by_price = g.view("CarByPrice").await.unwrap();
+    for price in 0..4 {
+        let rows = by_price.lookup(&[price.into()], true).await.unwrap();
+        assert_eq!(rows.len(), 5);
+    }
+
+    let stats = g.statistics().await.unwrap();
+    assert!(stats.values().all(|(ds, _)| ds.corrupt_replay_pieces == 0));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn replay_checksums_catch_corruption() {
+    let mut b = Builder::default();
+    b.set_sharding(None);
+    b.set_persistence(get_persistence_params("replay_checksums_catch_corruption"));
+    b.set_replay_checksums(true);
+    b.set_replay_corruption(2);
+    let mut g = b.start_local().await.unwrap().0;
+    g.install_recipe(
+        "CREATE TABLE Car (id int, price int, name text, PRIMARY KEY(id));
+         QUERY CarByPrice: SELECT id, name FROM Car WHERE price = ?;",
+    )
+    .await
+    .unwrap();
+    let mut car = g.table("Car").await.unwrap();
+    for id in 0..20 {
+        car.insert(vec![id.into(), (id % 4).into(), id.to_string().into()])
+            .await
+            .unwrap();
+    }
+    sleep().await;
+
+    // every other piece the base's domain sends is corrupted on the way to the view, whose domain
+    // asks for the keys again rather than fill them with the corrupt rows.
+    let mut by_price = g.view("CarByPrice").await.unwrap();
+    for price in 0..4 {
+        let mut rows = by_price.lookup(&[price.into()], true).await.unwrap();
+        rows.sort();
+        let mut expected: Vec<Vec<DataType>> = (0..20)
+            .filter(|id| id % 4 == price)
+            .map(|id| vec![id.into(), id.to_string().into()])
+            .collect();
+        expected.sort();
+        assert_eq!(rows, expected);
+    }
+
+    let corruptions = g.replay_corruptions().await.unwrap();
+    assert!(!corruptions.is_empty());
+    assert!(corruptions.iter().all(|c| c.recovered && c.keys == 1));
+    assert!(corruptions.iter().all(|c| c.queries.is_empty()));
+    let stats = g.statistics().await.unwrap();
+    let corrupt: u64 = stats.values().map(|(ds, _)| ds.corrupt_replay_pieces).sum();
+    assert_eq!(corrupt, corruptions.len() as u64);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn statistics_stream() {
+    use futures_util::stream::StreamExt;
+
+    let mut b = Builder::default();
+    b.set_sharding(DEFAULT_SHARDING);
+    b.set_persistence(get_persistence_params("statistics_stream"));
+    b.set_statistics_interval(Duration::from_millis(10));
+    let mut g = b.start_local().await.unwrap().0;
+    g.install_recipe(
+        "CREATE TABLE Car (id int, price int, PRIMARY KEY(id));
+         QUERY CarPrice: SELECT price FROM Car WHERE id = ?;",
+    )
+    .await
+    .unwrap();
+    let leaf = g.outputs().await.unwrap()["CarPrice"];
+    sleep().await;
+
+    // the first item holds the latest statistics of every node
+    let mut stream = Box::pin(g.statistics_stream(Duration::from_millis(10)));
+    let first = stream.next().await.unwrap().unwrap();
+    assert!(!first.domains.is_empty());
+    assert!(first.values().any(|(_, nodes)| nodes.contains_key(&leaf)));
+
+    // later items only hold the nodes whose statistics changed
+    let mut car = g.table("Car").await.unwrap();
+    car.insert(vec![1.into(), 10.into()]).await.unwrap();
+    let mut price = g.view("CarPrice").await.unwrap();
+    assert_eq!(
+        price.lookup(&[1.into()], true).await.unwrap(),
+        vec![vec![10.into()]]
+    );
+    loop {
+        let update = stream.next().await.unwrap().unwrap();
+        assert!(!update.domains.is_empty());
+        if update.values().any(|(_, nodes)| nodes.contains_key(&leaf)) {
+            break;
+        }
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn read_autoscaling() {
+    let mut b = Builder::default();
+    b.set_sharding(DEFAULT_SHARDING);
+    b.set_persistence(get_persistence_params("read_autoscaling"));
+    // any queued read counts, and reads only have to be queued at one heartbeat
+    b.set_read_autoscaling(0, 1, Duration::from_millis(0));
+    let mut g = b.start_local().await.unwrap().0;
+    g.install_recipe(
+        "CREATE TABLE Car (id int, price int, PRIMARY KEY(id));
+         QUERY CarPrice: SELECT price FROM Car WHERE id = ?;",
+    )
+    .await
+    .unwrap();
+    let mut car = g.table("Car").await.unwrap();
+    car.insert(vec![1.into(), 10.into()]).await.unwrap();
+    let price = g.view("CarPrice").await.unwrap();
+
+    // keep blocking reads of keys that have yet to be replayed in flight until the controller
+    // sees them queue up
+    let deadline = std::time::Instant::now() + Duration::from_secs(30);
+    let mut next = 2;
+    loop {
+        let reads: Vec<_> = (next..next + 64)
+            .map(|id: i32| {
+                let mut price = price.clone();
+                async move { price.lookup(&[id.into()], true).await }
+            })
+            .collect();
+        next += 64;
+        for res in futures_util::future::join_all(reads).await {
+            assert!(res.unwrap().is_empty());
+        }
+        if !g.scaling_events().await.unwrap().is_empty() {
+            break;
+        }
+        assert!(std::time::Instant::now() < deadline, "no replica was added");
+    }
+    match g.scaling_events().await.unwrap()[0] {
+        noria::ScalingEvent::ReplicaAdded {
+            ref view, replicas, ..
+        } => {
+            assert_eq!(view, "CarPrice");
+            assert_eq!(replicas, 1);
+        }
+        ref e => unreachable!("{:?}", e),
+    }
+    // replicas are not views of their own
+    assert_eq!(g.outputs().await.unwrap().len(), 1);
+
+    // once the reads stop queueing up, the replica is removed again
+    let events = loop {
+        let events = g.scaling_events().await.unwrap();
+        if events.len() > 1 {
+            break events;
+        }
+        assert!(std::time::Instant::now() < deadline, "replica not removed");
+        sleep().await;
+    };
+    assert_eq!(
+        events[1],
+        noria::ScalingEvent::ReplicaRemoved {
+            view: "CarPrice".to_owned(),
+            replicas: 0,
+        }
+    );
+    let mut price = g.view("CarPrice").await.unwrap();
+    assert_eq!(
+        price.lookup(&[1.into()], true).await.unwrap(),
+        vec![vec![10.into()]]
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn read_replicas_serve_reads() {
+    async fn own_reads(g: &mut Handle<LocalAuthority>) -> u64 {
+        g.read_attribution("CarPrice")
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|a| a.identity.as_deref() == Some("alice"))
+            .map(|a| a.reads)
+            .unwrap_or(0)
+    }
+
+    let mut g = start_simple("read_replicas_serve_reads").await;
+    g.install_recipe(
+        "CREATE TABLE Car (id int, price int, PRIMARY KEY(id));
+         QUERY CarPrice: SELECT price FROM Car WHERE id = ?;",
+    )
+    .await
+    .unwrap();
+    let mut car = g.table("Car").await.unwrap();
+    car.insert(vec![1.into(), 10.into()]).await.unwrap();
+    sleep().await;
+
+    // only the view's own reader attributes reads, so it only sees those it serves itself
+    g.set_read_attribution("CarPrice", true).await.unwrap();
+    g.set_read_replicas("CarPrice", 1).await.unwrap();
+
+    let mut price = g.view("CarPrice").await.unwrap();
+    price.set_identity("alice");
+    for _ in 0..10 {
+        assert_eq!(
+            price.lookup(&[1.into()], true).await.unwrap(),
+            vec![vec![10.into()]]
+        );
+    }
+    let served = own_reads(&mut g).await;
+    assert!(
+        served > 0 && served < 10,
+        "own reader served {} of 10",
+        served
+    );
+
+    // once the replica is gone, the handle reads from the view's own reader instead
+    g.set_read_replicas("CarPrice", 0).await.unwrap();
+    for _ in 0..4 {
+        assert_eq!(
+            price.lookup(&[1.into()], true).await.unwrap(),
+            vec![vec![10.into()]]
+        );
+    }
+    assert_eq!(own_reads(&mut g).await, served + 4);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn deterministic_runs_agree() {
+    async fn run(prefix: &str) -> (Vec<Vec<DataType>>, Vec<((usize, usize), Option<u64>)>) {
+        let mut b = Builder::default();
+        b.set_sharding(None);
+        b.set_persistence(get_persistence_params(prefix));
+        b.set_deterministic(42);
+        let mut g = b.start_local().await.unwrap().0;
+        g.migrate(|mig| {
+            let a = mig.add_base("a", &["a", "b"], Base::default());
+            let b = mig.add_base("b", &["a", "b"], Base::default());
+            let mut emits = HashMap::new();
+            emits.insert(a, vec![0, 1]);
+            emits.insert(b, vec![0, 1]);
+            // nodes named BOUNDARY_ get a domain of their own, so that two domains are scheduled
+            let c = mig.add_ingredient("BOUNDARY_c", &["a", "b"], Union::new(emits));
+            mig.maintain_anonymous(c, &[0]);
+        })
+        .await;
+
+        let mut a = g.table("a").await.unwrap();
+        let mut b = g.table("b").await.unwrap();
+        for i in 0..20 {
+            let t = if i % 3 == 0 { &mut b } else { &mut a };
+            t.insert(vec![1.into(), i.into()]).await.unwrap();
+        }
+        sleep().await;
+
+        // the rows are compared in the order the reader returns them, which is the order in
+        // which they were processed
+        let mut c = g.view("BOUNDARY_c").await.unwrap();
+        let rows = c.lookup(&[1.into()], true).await.unwrap();
+
+        let mut schedules: Vec<_> = g
+            .statistics()
+            .await
+            .unwrap()
+            .domains
+            .into_iter()
+            .map(|((di, shard), (stats, _))| ((di.index(), shard), stats.schedule))
+            .collect();
+        schedules.sort();
+        (rows, schedules)
+    }
+
+    let (rows, schedules) = run("deterministic_runs_agree_1").await;
+    assert_eq!(rows.len(), 20);
+    assert!(schedules.len() > 1);
+    assert!(schedules.iter().all(|(_, schedule)| schedule.is_some()));
+    assert_eq!((rows, schedules), run("deterministic_runs_agree_2").await);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn manual_clock_expires_audit_log() {
+    let clock = Clock::manual();
+    let mut b = Builder::default();
+    b.set_sharding(DEFAULT_SHARDING);
+    b.set_persistence(get_persistence_params("manual_clock_expires_audit_log"));
+    b.set_audit_retention(Duration::from_secs(3600));
+    b.set_clock(clock.clone());
+    let mut g = b.start_local().await.unwrap().0;
+    g.migrate(|mig| {
+        let a = mig.add_base(
+            "a",
+            &["a", "b"],
+            Base::new(vec![]).with_key(vec![0]).with_audit(16),
+        );
+        mig.maintain_anonymous(a, &[0]);
+    })
+    .await;
+
+    let mut a = g.table("a").await.unwrap();
+    a.insert(vec![1.into(), 2.into()]).await.unwrap();
+    sleep().await;
+    assert_eq!(g.audit_log("a").await.unwrap().len(), 1);
+
+    // two hours pass in an instant
+    clock.advance(Duration::from_secs(2 * 3600));
+    assert!(g.audit_log("a").await.unwrap().is_empty());
+
+    // and what is written afterwards is kept again
+    a.insert(vec![2.into(), 3.into()]).await.unwrap();
+    sleep().await;
+    assert_eq!(g.audit_log("a").await.unwrap().len(), 1);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn adaptive_flush_window() {
+    let mut b = Builder::default();
+    b.set_sharding(None);
+    b.set_persistence(get_persistence_params("adaptive_flush_window"));
+    b.set_adaptive_flush(Duration::from_millis(50));
+    let mut g = b.start_local().await.unwrap().0;
+    g.install_recipe(
+        "CREATE TABLE Car (id int, price int, PRIMARY KEY(id));
+         QUERY CarPrice: SELECT price FROM Car WHERE id = ?;",
+    )
+    .await
+    .unwrap();
+
+    let car = g.table("Car").await.unwrap();
+    let writes: Vec<_> = (0..512)
+        .map(|id: i32| {
+            let mut car = car.clone();
+            async move { car.insert(vec![id.into(), id.into()]).await }
+        })
+        .collect();
+    for res in futures_util::future::join_all(writes).await {
+        res.unwrap();
+    }
+    sleep().await;
+
+    // however the burst moved the window, it stays within its bounds
+    let stats = g.statistics().await.unwrap();
+    let windows: Vec<_> = stats
+        .values()
+        .flat_map(|(_, nodes)| nodes.values().filter_map(|n| n.flush_window))
+        .collect();
+    assert_eq!(windows.len(), 1);
+    let min = PersistenceParameters::default().flush_timeout.as_nanos() as u64;
+    let max = Duration::from_millis(50).as_nanos() as u64;
+    assert!(windows[0] >= min && windows[0] <= max);
+
+    let mut price = g.view("CarPrice").await.unwrap();
+    assert_eq!(
+        price.lookup(&[7.into()], true).await.unwrap(),
+        vec![vec![7.into()]]
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn mirror_writes_to_shadow() {
+    let recipe = "CREATE TABLE Car (id int, price int, PRIMARY KEY(id));
+                  QUERY CarPrice: SELECT price FROM Car WHERE id = ?;";
+    let mut g = start_simple("mirror_writes_to_shadow").await;
+    g.install_recipe(recipe).await.unwrap();
+    let mut shadow = start_simple("mirror_writes_to_shadow_shadow").await;
+    shadow.install_recipe(recipe).await.unwrap();
+
+    let mut car = g.table("Car").await.unwrap();
+    for fraction in &[0.0, 1.5, std::f64::NAN] {
+        assert!(car
+            .mirror_to(shadow.table("Car").await.unwrap(), *fraction)
+            .is_err());
+    }
+    car.mirror_to(shadow.table("Car").await.unwrap(), 1.0)
+        .unwrap();
+    for id in 0..10i32 {
+        car.insert(vec![id.into(), (id * 10).into()]).await.unwrap();
+    }
+    sleep().await;
+    assert_eq!(
+        car.mirrored_writes(),
+        noria::MirroredWrites {
+            sent: 10,
+            failed: 0,
+            dropped: 0,
+        }
+    );
+
+    let mut price = shadow.view("CarPrice").await.unwrap();
+    assert_eq!(
+        price.lookup(&[3.into()], true).await.unwrap(),
+        vec![vec![30.into()]]
+    );
+
+    // writes made after mirroring stops only reach the original deployment
+    car.stop_mirroring();
+    car.insert(vec![10.into(), 100.into()]).await.unwrap();
+    sleep().await;
+    assert!(price.lookup(&[10.into()], true).await.unwrap().is_empty());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn mirror_limit_drops_writes() {
+    let recipe = "CREATE TABLE Car (id int, price int, PRIMARY KEY(id));";
+    let mut b = Builder::default();
+    b.set_persistence(get_persistence_params("mirror_limit_drops_writes"));
+    b.set_mirror_limit("Car", 1);
+    let mut g = b.start_local().await.unwrap().0;
+    g.install_recipe(recipe).await.unwrap();
+    let mut shadow = start_simple("mirror_limit_drops_writes_shadow").await;
+    shadow.install_recipe(recipe).await.unwrap();
+
+    let mut car = g.table("Car").await.unwrap();
+    car.mirror_to(shadow.table("Car").await.unwrap(), 1.0)
+        .unwrap();
+
+    // with only one mirrored write allowed in flight, a burst of writes drops some of them
+    let writes: Vec<_> = (0..50)
+        .map(|id: i32| {
+            let mut car = car.clone();
+            async move { car.insert(vec![id.into(), id.into()]).await }
+        })
+        .collect();
+    for res in futures_util::future::join_all(writes).await {
+        res.unwrap();
+    }
+    sleep().await;
+    let mirrored = car.mirrored_writes();
+    assert_eq!(mirrored.sent + mirrored.dropped, 50);
+    assert!(mirrored.sent >= 1);
+    assert!(mirrored.dropped >= 1);
+    assert_eq!(mirrored.failed, 0);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn state_watermarks() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct CountAggregations(Arc<AtomicUsize>);
+    impl crate::WatermarkPolicy for CountAggregations {
+        fn on_crossing(&self, crossing: &crate::WatermarkCrossing) -> crate::WatermarkAction {
+            if crossing.kind == crate::OperatorKind::Aggregation {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+            crate::WatermarkAction::Spill(16)
+        }
+    }
+
+    let crossings = Arc::new(AtomicUsize::new(0));
+    let mut b = Builder::default();
+    b.set_sharding(None);
+    b.set_persistence(get_persistence_params("state_watermarks"));
+    b.disable_partial();
+    b.set_state_watermarks(vec![1 << 20, 1]);
+    b.set_watermark_policy(CountAggregations(crossings.clone()));
+    let mut g = b.start_local().await.unwrap().0;
+    g.install_recipe(
+        "CREATE TABLE Vote (aid int, uid int);
+         QUERY VoteCount: SELECT aid, COUNT(uid) AS votes FROM Vote WHERE aid = ? GROUP BY aid;",
+    )
+    .await
+    .unwrap();
+
+    let mut vote = g.table("Vote").await.unwrap();
+    for aid in 0..100i32 {
+        vote.insert(vec![aid.into(), 1.into()]).await.unwrap();
+    }
+    sleep().await;
+
+    // the count's state grew past the lower watermark only
+    assert_eq!(crossings.load(Ordering::SeqCst), 1);
+    let stats = g.statistics().await.unwrap();
+    let crossed: u64 = stats
+        .values()
+        .flat_map(|(_, nodes)| nodes.values().map(|n| n.watermarks_crossed))
+        .sum();
+    assert!(crossed >= 1);
+
+    let mut count = g.view("VoteCount").await.unwrap();
+    assert_eq!(
+        count.lookup(&[7.into()], true).await.unwrap(),
+        vec![vec![7.into(), 1.into()]]
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn coalesced_reads() {
+    let mut g = start_simple("coalesced_reads").await;
+    g.install_recipe(
+        "CREATE TABLE Car (id int, price int, PRIMARY KEY(id));
+         QUERY CarPrice: SELECT price FROM Car WHERE id = ?;",
+    )
+    .await
+    .unwrap();
+    let mut car = g.table("Car").await.unwrap();
+    let price = g.view("CarPrice").await.unwrap();
+
+    // look up a key that has yet to be replayed from many clients at once, until some of the
+    // lookups are answered along with another
+    let deadline = std::time::Instant::now() + Duration::from_secs(30);
+    let mut id: i32 = 1;
+    loop {
+        car.insert(vec![id.into(), (id * 10).into()]).await.unwrap();
+        sleep().await;
+        let reads: Vec<_> = (0..64)
+            .map(|_| {
+                let mut price = price.clone();
+                async move { price.lookup(&[id.into()], true).await }
+            })
+            .collect();
+        for res in futures_util::future::join_all(reads).await {
+            assert_eq!(res.unwrap(), vec![vec![(id * 10).into()]]);
+        }
+        id += 1;
+
+        let coalesced: u64 = g
+            .statistics()
+            .await
+            .unwrap()
+            .values()
+            .flat_map(|(_, nodes)| nodes.values())
+            .filter_map(|n| n.read_amplification)
+            .map(|amp| amp.coalesced)
+            .sum();
+        if coalesced > 0 {
+            break;
+        }
+        assert!(
+            std::time::Instant::now() < deadline,
+            "no reads were coalesced"
+        );
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn column_pruning() {
+    let mut b = Builder::default();
+    b.set_sharding(DEFAULT_SHARDING);
+    b.set_persistence(get_persistence_params("column_pruning"));
+    b.set_column_pruning(true);
+    let mut g = b.start_local().await.unwrap().0;
+    let r = g
+        .install_recipe(
+            "CREATE TABLE Article (id int, title text, body text, author int, PRIMARY KEY(id));
+             CREATE TABLE Author (id int, name text, email text, PRIMARY KEY(id));
+             QUERY ArticleWithAuthor: SELECT Article.id, Article.title, Author.name \
+                FROM Article JOIN Author ON Article.author = Author.id \
+                WHERE Article.id = ?;",
+        )
+        .await
+        .unwrap();
+
+    // the article bodies and author emails are left out of the join
+    assert!(!r.dataflow.pruned_columns.is_empty());
+    assert!(r.dataflow.pruned_columns.iter().all(|&(_, n)| n > 0));
+
+    let mut article = g.table("Article").await.unwrap();
+    let mut author = g.table("Author").await.unwrap();
+    author
+        .insert(vec![1.into(), "Jane".into(), "jane@example.com".into()])
+        .await
+        .unwrap();
+    article
+        .insert(vec![1.into(), "Title".into(), "Body".into(), 1.into()])
+        .await
+        .unwrap();
+    sleep().await;
+
+    let mut q = g.view("ArticleWithAuthor").await.unwrap();
+    assert_eq!(
+        q.lookup(&[1.into()], true).await.unwrap(),
+        vec![vec![1.into(), "Title".into(), "Jane".into()]]
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn worker_failure_is_recovered() {
+    use crate::LocalCluster;
+
+    let fast_heartbeats = |b: &mut Builder| {
+        b.set_heartbeat_interval(Duration::from_millis(50));
+        b.set_healthcheck_interval(Duration::from_millis(100));
+    };
+    let mut cluster = LocalCluster::builder()
+        .sharding(Some(2))
+        .persistence(get_persistence_params("worker_failure_is_recovered"))
+        .configure(fast_heartbeats)
+        .build()
+        .await
+        .unwrap();
+    cluster
+        .install_recipe("CREATE TABLE Car (id int, price int, PRIMARY KEY(id));")
+        .await
+        .unwrap();
+
+    // a second worker joins, and is handed a shard of every domain of the next query
+    let mut b = Builder::default();
+    b.set_sharding(Some(2));
+    fast_heartbeats(&mut b);
+    let (mut worker, done) = b.start(cluster.authority()).await.unwrap();
+    sleep().await;
+    cluster
+        .extend_recipe("QUERY CarPrice: SELECT price FROM Car WHERE id = ?;")
+        .await
+        .unwrap();
+    let mut car = cluster.table("Car").await.unwrap();
+    car.insert(vec![1.into(), 10.into()]).await.unwrap();
+    assert!(cluster.worker_failures().await.unwrap().is_empty());
+
+    worker.shutdown();
+    done.await;
+    let failures = loop {
+        let failures = cluster.worker_failures().await.unwrap();
+        if !failures.is_empty() {
+            break failures;
+        }
+        sleep().await;
+    };
+    assert_eq!(failures.len(), 1);
+    assert!(!failures[0].domains.is_empty());
+    assert_eq!(failures[0].queries, vec!["CarPrice".to_owned()]);
+    let workers = cluster.statistics().await.unwrap().workers;
+    assert!(workers
+        .iter()
+        .any(|w| w.worker == failures[0].worker && !w.healthy));
+
+    // the query was rebuilt on the remaining worker
+    let mut price = cluster.view("CarPrice").await.unwrap();
+    assert_eq!(
+        price.lookup(&[1.into()], true).await.unwrap(),
+        vec![vec![10.into()]]
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn rolling_upgrade_hands_off_bases() {
+    use crate::LocalCluster;
+    use noria::UpgradeEvent;
+
+    let labeled = |label: &str| {
+        let mut b = Builder::default();
+        b.set_sharding(Some(2));
+        b.set_persistence(get_persistence_params("rolling_upgrade_hands_off_bases"));
+        b.set_worker_label(label);
+        b
+    };
+    let mut cluster = LocalCluster::builder()
+        .sharding(Some(2))
+        .persistence(get_persistence_params("rolling_upgrade_hands_off_bases"))
+        .build()
+        .await
+        .unwrap();
+    let first = cluster.statistics().await.unwrap().workers[0].worker;
+
+    // the worker to upgrade joins before the recipe is installed, so it gets a shard of the table
+    let (mut upgraded, done) = labeled("upgraded")
+        .start(cluster.authority())
+        .await
+        .unwrap();
+    sleep().await;
+    let worker = cluster
+        .statistics()
+        .await
+        .unwrap()
+        .workers
+        .iter()
+        .map(|w| w.worker)
+        .find(|&w| w != first)
+        .unwrap();
+    cluster
+        .install_recipe(
+            "CREATE TABLE Car (id int, price int, PRIMARY KEY(id));
+             QUERY CarPrice: SELECT price FROM Car WHERE id = ?;",
+        )
+        .await
+        .unwrap();
+    let mut car = cluster.table("Car").await.unwrap();
+    for id in 1..=8i32 {
+        car.insert(vec![id.into(), (id * 10).into()]).await.unwrap();
+    }
+    sleep().await;
+
+    let token = cluster.rolling_upgrade(worker).await.unwrap();
+    assert_eq!(
+        cluster.upgrade_status().await.unwrap().last(),
+        Some(&UpgradeEvent::AwaitingRestart { worker })
+    );
+
+    // the worker no longer holds any rows, so none are lost when it stops, and the table still
+    // takes writes
+    upgraded.shutdown();
+    done.await;
+    let mut car = cluster.table("Car").await.unwrap();
+    car.insert(vec![9.into(), 90.into()]).await.unwrap();
+    sleep().await;
+    let mut price = cluster.view("CarPrice").await.unwrap();
+    for id in 1..=9i32 {
+        assert_eq!(
+            price.lookup(&[id.into()], true).await.unwrap(),
+            vec![vec![(id * 10).into()]]
+        );
+    }
+
+    // a worker that joins without the upgrade's token does not finish the upgrade, even if it has
+    // the same label
+    let (mut other, other_done) = labeled("upgraded")
+        .start(cluster.authority())
+        .await
+        .unwrap();
+    sleep().await;
+    assert_eq!(
+        cluster.upgrade_status().await.unwrap().last(),
+        Some(&UpgradeEvent::AwaitingRestart { worker })
+    );
+
+    // the upgraded worker does, and gets the table back
+    let mut restarted = labeled("upgraded");
+    restarted.set_upgrade_token(token);
+    let (mut restarted, restarted_done) = restarted.start(cluster.authority()).await.unwrap();
+    let domains = loop {
+        match cluster.upgrade_status().await.unwrap().last() {
+            Some(&UpgradeEvent::Complete { domains, .. }) => break domains,
+            _ => sleep().await,
+        }
+    };
+    assert!(domains > 0);
+    let mut price = cluster.view("CarPrice").await.unwrap();
+    for id in 1..=9i32 {
+        assert_eq!(
+            price.lookup(&[id.into()], true).await.unwrap(),
+            vec![vec![(id * 10).into()]]
+        );
+    }
+
+    other.shutdown();
+    other_done.await;
+    restarted.shutdown();
+    restarted_done.await;
+    cluster.shutdown().await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn reshard_base() {
+    let mut g = start_simple("reshard_base").await;
+    g.install_recipe(
+        "CREATE TABLE Car (id int, price int, PRIMARY KEY(id));
+         QUERY CarPrice: SELECT price FROM Car WHERE id = ?;",
+    )
+    .await
+    .unwrap();
+    let mut car = g.table("Car").await.unwrap();
+    for id in 0..10 {
+        car.insert(vec![id.into(), (id * 10).into()]).await.unwrap();
+    }
+    sleep().await;
+
+    // a client keeps writing while the table is resharded, and fetches the table again once the
+    // old one is gone.
+    let mut ch = g.clone();
+    let writer = tokio::spawn(async move {
+        for id in 10..200 {
+            let row: Vec<DataType> = vec![id.into(), (id * 10).into()];
+            while car.insert(row.clone()).await.is_err() {
+                car = ch.table("Car").await.unwrap();
+            }
+        }
+    });
+    let queries = g.reshard_base("Car", 3).await.unwrap();
+    assert_eq!(queries, vec!["CarPrice".to_owned()]);
+    writer.await.unwrap();
+    assert!(g.reshard_base("Car", 3).await.is_err());
+    sleep().await;
+
+    // every write made before, during and after resharding reaches the view
+    assert_eq!(g.commit_seqs("Car").await.unwrap().len(), 3);
+    let mut car = g.table("Car").await.unwrap();
+    car.insert(vec![200.into(), 2000.into()]).await.unwrap();
+    sleep().await;
+    let mut price = g.view("CarPrice").await.unwrap();
+    for id in 0..=200 {
+        assert_eq!(
+            price.lookup(&[id.into()], true).await.unwrap(),
+            vec![vec![(id * 10).into()]]
+        );
+    }
+    assert_eq!(g.export_base("Car").await.unwrap().len(), 201);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn rpc_retries_are_bounded() {
+    use crate::LocalCluster;
+    use noria::error::RpcError;
+
+    let cluster = LocalCluster::builder()
+        .persistence(get_persistence_params("rpc_retries_are_bounded"))
+        .build()
+        .await
+        .unwrap();
+    let mut handle = cluster.handle();
+    handle.set_rpc_retries(2);
+    handle.set_rpc_timeout_for("inputs", Some(Duration::from_secs(10)));
+    assert!(handle.inputs().await.unwrap().is_empty());
+
+    // an attempt that times out is only repeated if doing so is harmless
+    let mut hasty = handle.clone();
+    hasty.set_rpc_timeout(Some(Duration::from_nanos(1)));
+    let e = hasty.outputs().await.unwrap_err();
+    match e.downcast_ref::<RpcError>() {
+        Some(RpcError::TimedOut { attempts, .. }) => assert_eq!(*attempts, 3),
+        _ => panic!("unexpected error: {:?}", e),
+    }
+    let e = hasty
+        .extend_recipe("CREATE TABLE Car (id int, PRIMARY KEY(id));")
+        .await
+        .unwrap_err();
+    match e.downcast_ref::<RpcError>() {
+        Some(RpcError::TimedOut { attempts, .. }) => assert_eq!(*attempts, 1),
+        _ => panic!("unexpected error: {:?}", e),
+    }
+    hasty.set_rpc_timeout(None);
+
+    // with the controller gone, requests give up instead of waiting for a new one
+    cluster.shutdown().await;
+    let e = handle.inputs().await.unwrap_err();
+    match e.downcast_ref::<RpcError>() {
+        Some(RpcError::Unavailable { rpc, attempts, .. }) => {
+            assert_eq!(rpc, "inputs");
+            assert_eq!(*attempts, 3);
+        }
+        _ => panic!("unexpected error: {:?}", e),
+    }
+}
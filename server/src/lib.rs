@@ -110,7 +110,8 @@
 //! This crate also provides `LocalAuthority`, which allows you to _embed_ a `noriad` worker, and
 //! not bother with setting up ZooKeeper or multiple workers. This provides no fault-tolerance and
 //! no multi-machine operations, but can be a convenient way to set things up for development and
-//! testing. See `Builder::build_local` or the `basic-recipe` example for details.
+//! testing. See `Builder::build_local` or the `basic-recipe` example for details. To test with
+//! multiple workers in a single process, use `LocalCluster` instead.
 //!
 //! # I'm a visual learner
 //!
@@ -359,9 +360,12 @@ extern crate serde_derive;
 extern crate slog;
 
 mod builder;
+mod cluster;
 mod controller;
 mod coordination;
 mod handle;
+mod metrics;
+mod mysql_frontend;
 mod startup;
 mod worker;
 
@@ -378,9 +382,15 @@ pub enum ReuseConfigType {
 }
 
 pub use crate::builder::Builder;
+pub use crate::cluster::{LocalCluster, LocalClusterBuilder};
 pub use crate::handle::Handle;
 pub use controller::migrate::materialization::FrontierStrategy;
-pub use dataflow::{DurabilityMode, PersistenceParameters};
+pub use controller::placement::PlacementStrategy;
+pub use dataflow::{
+    telemetry, verify_base_offline, Clock, DefaultWatermarkPolicy, DurabilityMode,
+    LoadSheddingPolicy, OperatorKind, PersistenceParameters, ShedAction, WatermarkAction,
+    WatermarkCrossing, WatermarkPolicy,
+};
 pub use noria::consensus::LocalAuthority;
 pub use noria::*;
 pub use petgraph::graph::NodeIndex;
@@ -407,6 +417,22 @@ pub(crate) struct Config {
     pub(crate) quorum: usize,
     pub(crate) reuse: ReuseConfigType,
     pub(crate) threads: Option<usize>,
+    pub(crate) audit_capacity: Option<usize>,
+    pub(crate) read_acceptors: usize,
+    pub(crate) consistency_check: Option<(time::Duration, usize)>,
+    pub(crate) base_disk_reservation: u64,
+    pub(crate) hot_key_split: Option<f64>,
+    pub(crate) base_verification: Option<time::Duration>,
+    pub(crate) supervise_domains: bool,
+    pub(crate) max_outstanding_reads: Option<usize>,
+    pub(crate) max_outstanding_writes: Option<usize>,
+    pub(crate) placement: PlacementStrategy,
+    pub(crate) reject_writes_during_migration: bool,
+    pub(crate) control_reply_timeout: Option<(time::Duration, usize)>,
+    pub(crate) read_autoscaling: Option<(usize, usize, time::Duration)>,
+    pub(crate) prune_columns: bool,
+    pub(crate) mirror_limits: std::collections::HashMap<String, usize>,
+    pub(crate) shard_weights: std::collections::HashMap<String, Vec<u32>>,
 }
 impl Default for Config {
     fn default() -> Self {
@@ -420,6 +446,19 @@ impl Default for Config {
             domain_config: DomainConfig {
                 concurrent_replays: 512,
                 replay_batch_timeout: time::Duration::new(0, 100_000),
+                interleave_seed: None,
+                spill_threshold: None,
+                audit_retention: None,
+                load_shedding: None,
+                reader_snapshot_interval: None,
+                shard_weights: Vec::new(),
+                compression: None,
+                inline_reader_snapshots: false,
+                replay_checksums: false,
+                corrupt_replay_pieces: None,
+                statistics_interval: None,
+                adaptive_flush: None,
+                state_watermarks: Vec::new(),
             },
             persistence: Default::default(),
             heartbeat_every: time::Duration::from_secs(1),
@@ -430,6 +469,22 @@ impl Default for Config {
             threads: Some(2),
             #[cfg(not(any(debug_assertions, test)))]
             threads: None,
+            audit_capacity: None,
+            read_acceptors: 1,
+            consistency_check: None,
+            base_disk_reservation: 0,
+            hot_key_split: None,
+            base_verification: None,
+            supervise_domains: false,
+            max_outstanding_reads: None,
+            max_outstanding_writes: None,
+            placement: Default::default(),
+            reject_writes_during_migration: false,
+            control_reply_timeout: None,
+            read_autoscaling: None,
+            prune_columns: false,
+            mirror_limits: Default::default(),
+            shard_weights: Default::default(),
         }
     }
 }
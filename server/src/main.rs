@@ -1,5 +1,6 @@
 use clap::value_t_or_exit;
-use noria_server::{Builder, ReuseConfigType, ZookeeperAuthority};
+use noria_server::{Builder, PlacementStrategy, ReuseConfigType, ZookeeperAuthority};
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
@@ -75,6 +76,46 @@ fn main() {
                 .requires("memory")
                 .help("Frequency at which to check the state size against the memory limit [in milliseconds]."),
         )
+        .arg(
+            Arg::with_name("disk_quota")
+                .long("disk-quota")
+                .takes_value(true)
+                .default_value("0")
+                .help("Disk space, in bytes, available for persisted base tables on this worker [0 = unlimited]."),
+        )
+        .arg(
+            Arg::with_name("label")
+                .long("label")
+                .takes_value(true)
+                .help("Label this worker, for placement strategies that take labels into account."),
+        )
+        .arg(
+            Arg::with_name("upgrade_token")
+                .long("upgrade-token")
+                .takes_value(true)
+                .help("Finish the rolling upgrade that returned this token once this worker registers."),
+        )
+        .arg(
+            Arg::with_name("placement")
+                .long("placement")
+                .takes_value(true)
+                .possible_values(&["round-robin", "least-loaded", "rack-aware", "pinned"])
+                .default_value("round-robin")
+                .help("How to choose the worker for each shard of a new domain. Racks are worker labels."),
+        )
+        .arg(
+            Arg::with_name("pin_to")
+                .long("pin-to")
+                .takes_value(true)
+                .required_if("placement", "pinned")
+                .help("The label of the workers to place domains on with --placement=pinned."),
+        )
+        .arg(
+            Arg::with_name("mysql_port")
+                .long("mysql-port")
+                .takes_value(true)
+                .help("Accept connections from MySQL clients on this port."),
+        )
         .arg(
             Arg::with_name("noreuse")
                 .long("no-reuse")
@@ -93,6 +134,13 @@ fn main() {
                 .default_value("1")
                 .help("Number of workers to wait for before starting (including this one)."),
         )
+        .arg(
+            Arg::with_name("read_acceptors")
+                .long("read-acceptors")
+                .takes_value(true)
+                .default_value("1")
+                .help("Number of accept loops to use for reads on each worker."),
+        )
         .arg(
             Arg::with_name("shards")
                 .long("shards")
@@ -100,6 +148,12 @@ fn main() {
                 .default_value("0")
                 .help("Shard the graph this many ways (0 = disable sharding)."),
         )
+        .arg(
+            Arg::with_name("jaeger")
+                .long("jaeger")
+                .takes_value(true)
+                .help("Export traces of writes, replays, and migrations to the Jaeger agent at this address."),
+        )
         .arg(
             Arg::with_name("verbose")
                 .short("v")
@@ -116,7 +170,9 @@ fn main() {
     let zookeeper_addr = matches.value_of("zookeeper").unwrap();
     let memory = value_t_or_exit!(matches, "memory", usize);
     let memory_check_freq = value_t_or_exit!(matches, "memory_check_freq", u64);
+    let disk_quota = value_t_or_exit!(matches, "disk_quota", u64);
     let quorum = value_t_or_exit!(matches, "quorum", usize);
+    let read_acceptors = value_t_or_exit!(matches, "read_acceptors", usize);
     let persistence_threads = value_t_or_exit!(matches, "persistence-threads", i32);
     let flush_ns = value_t_or_exit!(matches, "flush-timeout", u32);
     let sharding = match value_t_or_exit!(matches, "shards", usize) {
@@ -133,8 +189,29 @@ fn main() {
     if memory > 0 {
         builder.set_memory_limit(memory, Duration::from_millis(memory_check_freq));
     }
+    if disk_quota > 0 {
+        builder.set_disk_quota(disk_quota);
+    }
+    if let Some(label) = matches.value_of("label") {
+        builder.set_worker_label(label);
+    }
+    if matches.is_present("upgrade_token") {
+        builder.set_upgrade_token(value_t_or_exit!(matches, "upgrade_token", u64));
+    }
+    builder.set_placement_strategy(match matches.value_of("placement").unwrap() {
+        "round-robin" => PlacementStrategy::RoundRobin,
+        "least-loaded" => PlacementStrategy::LeastLoaded,
+        "rack-aware" => PlacementStrategy::RackAware,
+        "pinned" => PlacementStrategy::Pinned(matches.value_of("pin_to").unwrap().to_owned()),
+        _ => unreachable!(),
+    });
     builder.set_sharding(sharding);
     builder.set_quorum(quorum);
+    builder.set_read_acceptors(read_acceptors.max(1));
+    if matches.is_present("mysql_port") {
+        let port = value_t_or_exit!(matches, "mysql_port", u16);
+        builder.set_mysql_listen_addr(SocketAddr::new(listen_addr, port));
+    }
     if matches.is_present("nopartial") {
         builder.disable_partial();
     }
@@ -163,6 +240,17 @@ fn main() {
         builder.log_with(log);
     }
 
+    // traces are exported for as long as the exporter stays installed
+    let _exporter = matches.value_of("jaeger").map(|agent| {
+        let (_, uninstall) = opentelemetry_jaeger::new_pipeline()
+            .with_agent_endpoint(agent)
+            .with_service_name("noria-server")
+            .install()
+            .expect("failed to install Jaeger exporter");
+        noria_server::telemetry::enable();
+        uninstall
+    });
+
     let mut rt = tokio::runtime::Builder::new();
     rt.enable_all();
     rt.threaded_scheduler();
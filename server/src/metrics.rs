@@ -0,0 +1,283 @@
+use noria::debug::stats::{DomainStats, GraphStats};
+use noria::internal::DomainIndex;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Metrics in the Prometheus text exposition format.
+#[derive(Default)]
+struct Exposition(String);
+
+impl Exposition {
+    /// Start a new metric family; its samples must follow before the next family is started.
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        writeln!(self.0, "# HELP {} {}", name, help).unwrap();
+        writeln!(self.0, "# TYPE {} {}", name, kind).unwrap();
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &dyn std::fmt::Display)], value: f64) {
+        self.0.push_str(name);
+        if !labels.is_empty() {
+            self.0.push('{');
+            for (i, (label, v)) in labels.iter().enumerate() {
+                if i != 0 {
+                    self.0.push(',');
+                }
+                let v = v.to_string().replace('\\', "\\\\").replace('"', "\\\"");
+                write!(self.0, "{}=\"{}\"", label, v).unwrap();
+            }
+            self.0.push('}');
+        }
+        writeln!(self.0, " {}", value).unwrap();
+    }
+}
+
+/// What the controller knows about the data-flow, as Prometheus metrics.
+pub(crate) fn graph(stats: &GraphStats) -> String {
+    let mut e = Exposition::default();
+    let mut domains: Vec<_> = stats.domains.iter().collect();
+    domains.sort_by_key(|&(&(di, shard), _)| (di.index(), shard));
+
+    fn seconds(ns: u64) -> f64 {
+        ns as f64 / 1e9
+    }
+    let domain_families: &[(&str, &str, &str, fn(&DomainStats) -> f64)] = &[
+        (
+            "noria_domain_processed_packets_total",
+            "counter",
+            "Packets the domain has been handed to process.",
+            |ds| ds.processed_packets as f64,
+        ),
+        (
+            "noria_domain_replay_pieces_total",
+            "counter",
+            "Replay pieces the domain has processed.",
+            |ds| ds.replay_pieces as f64,
+        ),
+        (
+            "noria_domain_replay_seconds_total",
+            "counter",
+            "Time the domain has spent processing replay pieces.",
+            |ds| seconds(ds.total_replay_time),
+        ),
+        (
+            "noria_domain_forward_seconds_total",
+            "counter",
+            "Time the domain has spent processing forward updates.",
+            |ds| seconds(ds.total_forward_time),
+        ),
+        (
+            "noria_domain_wait_seconds_total",
+            "counter",
+            "Time the domain has spent waiting for work.",
+            |ds| seconds(ds.wait_time),
+        ),
+        (
+            "noria_domain_rejected_writes_total",
+            "counter",
+            "Writes to best-effort tables the domain rejected while overloaded.",
+            |ds| ds.rejected_writes as f64,
+        ),
+        (
+            "noria_domain_corrupt_replay_pieces_total",
+            "counter",
+            "Replay pieces the domain received whose data did not match their checksum.",
+            |ds| ds.corrupt_replay_pieces as f64,
+        ),
+    ];
+    for &(name, kind, help, value) in domain_families {
+        e.family(name, kind, help);
+        for &(&(di, shard), &(ref ds, _)) in &domains {
+            e.sample(
+                name,
+                &[("domain", &di.index()), ("shard", &shard)],
+                value(ds),
+            );
+        }
+    }
+
+    e.family(
+        "noria_node_state_bytes",
+        "gauge",
+        "Size of the state the node keeps.",
+    );
+    for &(&(di, shard), &(_, ref nodes)) in &domains {
+        let mut nodes: Vec<_> = nodes.iter().collect();
+        nodes.sort_by_key(|&(&ni, _)| ni);
+        for (ni, ns) in nodes {
+            e.sample(
+                "noria_node_state_bytes",
+                &[
+                    ("domain", &di.index()),
+                    ("shard", &shard),
+                    ("node", &ni.index()),
+                ],
+                ns.mem_size as f64,
+            );
+        }
+    }
+
+    e.family(
+        "noria_controller_worker_healthy",
+        "gauge",
+        "Whether the controller considers the worker to be alive.",
+    );
+    for w in &stats.workers {
+        let healthy = if w.healthy { 1.0 } else { 0.0 };
+        e.sample(
+            "noria_controller_worker_healthy",
+            &[("worker", &w.worker)],
+            healthy,
+        );
+    }
+    e.family(
+        "noria_controller_worker_memory_bytes",
+        "gauge",
+        "Partial state kept by the worker's domains, as of its last heartbeat.",
+    );
+    for w in &stats.workers {
+        e.sample(
+            "noria_controller_worker_memory_bytes",
+            &[("worker", &w.worker)],
+            w.memory_used as f64,
+        );
+    }
+
+    e.0
+}
+
+/// What a worker knows about itself and the domain shards it runs, for its `/metrics` endpoint.
+#[derive(Default)]
+pub(crate) struct WorkerMetrics {
+    /// The size of the state of each domain shard, and the number of packets it has queued for
+    /// other domains.
+    replicas: Mutex<HashMap<(DomainIndex, usize), (Arc<AtomicUsize>, Arc<AtomicUsize>)>>,
+    disk_usage: AtomicU64,
+    connections: AtomicUsize,
+}
+
+impl WorkerMetrics {
+    /// Start reporting on a domain shard that the worker has booted.
+    pub(crate) fn add_replica(
+        &self,
+        replica: (DomainIndex, usize),
+        state_size: Arc<AtomicUsize>,
+        queued: Arc<AtomicUsize>,
+    ) {
+        self.replicas
+            .lock()
+            .unwrap()
+            .insert(replica, (state_size, queued));
+    }
+
+    /// Update what the worker last measured in its heartbeat.
+    pub(crate) fn heartbeat(&self, disk_usage: u64, connections: usize) {
+        self.disk_usage.store(disk_usage, Ordering::Relaxed);
+        self.connections.store(connections, Ordering::Relaxed);
+    }
+
+    pub(crate) fn render(&self) -> String {
+        let mut e = Exposition::default();
+        let mut replicas: Vec<_> = self
+            .replicas
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&(di, shard), &(ref size, ref queued))| {
+                (
+                    (di.index(), shard),
+                    size.load(Ordering::Relaxed),
+                    queued.load(Ordering::Relaxed),
+                )
+            })
+            .collect();
+        replicas.sort_by_key(|&(r, ..)| r);
+
+        e.family(
+            "noria_worker_domains",
+            "gauge",
+            "Domain shards the worker has started.",
+        );
+        e.sample("noria_worker_domains", &[], replicas.len() as f64);
+        e.family(
+            "noria_worker_connections",
+            "gauge",
+            "Clients connected to the worker, as of its last heartbeat.",
+        );
+        let connections = self.connections.load(Ordering::Relaxed);
+        e.sample("noria_worker_connections", &[], connections as f64);
+        e.family(
+            "noria_worker_disk_usage_bytes",
+            "gauge",
+            "Disk space used by base table persistence, as of the worker's last heartbeat.",
+        );
+        let disk_usage = self.disk_usage.load(Ordering::Relaxed);
+        e.sample("noria_worker_disk_usage_bytes", &[], disk_usage as f64);
+
+        e.family(
+            "noria_replica_state_bytes",
+            "gauge",
+            "Size of the partial state of the domain shard, as last measured by the domain.",
+        );
+        for &((di, shard), size, _) in &replicas {
+            e.sample(
+                "noria_replica_state_bytes",
+                &[("domain", &di), ("shard", &shard)],
+                size as f64,
+            );
+        }
+        e.family(
+            "noria_replica_queued_packets",
+            "gauge",
+            "Packets the domain shard has queued for other domains that are not yet sent.",
+        );
+        for &((di, shard), _, queued) in &replicas {
+            e.sample(
+                "noria_replica_queued_packets",
+                &[("domain", &di), ("shard", &shard)],
+                queued as f64,
+            );
+        }
+
+        e.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exposition_format() {
+        let mut e = Exposition::default();
+        e.family("noria_things", "gauge", "Things.");
+        e.sample("noria_things", &[], 1.0);
+        e.sample("noria_things", &[("a", &1), ("b", &"x\"y")], 2.5);
+        assert_eq!(
+            e.0,
+            "# HELP noria_things Things.\n\
+             # TYPE noria_things gauge\n\
+             noria_things 1\n\
+             noria_things{a=\"1\",b=\"x\\\"y\"} 2.5\n"
+        );
+    }
+
+    #[test]
+    fn worker_replicas() {
+        let m = WorkerMetrics::default();
+        m.add_replica(
+            (DomainIndex::from(3), 1),
+            Arc::new(AtomicUsize::new(100)),
+            Arc::new(AtomicUsize::new(7)),
+        );
+        m.heartbeat(4096, 2);
+
+        let out = m.render();
+        assert!(out.contains("noria_worker_domains 1\n"));
+        assert!(out.contains("noria_worker_connections 2\n"));
+        assert!(out.contains("noria_worker_disk_usage_bytes 4096\n"));
+        assert!(out.contains("noria_replica_state_bytes{domain=\"3\",shard=\"1\"} 100\n"));
+        assert!(out.contains("noria_replica_queued_packets{domain=\"3\",shard=\"1\"} 7\n"));
+    }
+}
@@ -0,0 +1,547 @@
+//! A frontend that speaks the MySQL client/server protocol.
+//!
+//! This lets existing applications and tools (such as the `mysql` command-line client, or ORMs)
+//! connect to Noria as if it were a MySQL server:
+//!
+//!  - `CREATE TABLE` statements extend the recipe with a new base table.
+//!  - `INSERT`, `UPDATE`, and `DELETE` statements are executed against base tables, with the
+//!    restrictions described for `ControllerHandle::execute`.
+//!  - `SELECT` statements extend the recipe with a query in which every `column = value`
+//!    comparison in the `WHERE` clause is turned into a parameter. The statement is then answered
+//!    by looking up its values in the resulting view, so all statements of the same shape share a
+//!    single view.
+//!
+//! Both ad-hoc and prepared statements are supported. Every connection is served by its own
+//! thread, since the protocol implementation uses blocking I/O.
+
+use msql_srv::{
+    Column, ColumnFlags, ColumnType, ErrorKind, MysqlIntermediary, MysqlShim, ParamParser,
+    QueryResultWriter, RowWriter, StatementMetaWriter, ValueInner,
+};
+use nom_sql::{
+    ArithmeticBase, ConditionBase, ConditionExpression, FieldValueExpression, Literal, Operator,
+    SelectStatement, SqlQuery, SqlType,
+};
+use noria::consensus::Authority;
+use noria::{ControllerHandle, DataType, View};
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::thread;
+
+/// Start accepting MySQL connections on `addr`, and return the address that was bound.
+///
+/// Must be called from within a tokio runtime, which is used to issue requests to Noria.
+pub(crate) fn listen<A: Authority + 'static>(
+    addr: SocketAddr,
+    ch: ControllerHandle<A>,
+    log: slog::Logger,
+) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let addr = listener.local_addr()?;
+    let rt = tokio::runtime::Handle::current();
+    info!(log, "listening for MySQL connections"; "on" => ?addr);
+
+    thread::Builder::new()
+        .name("mysql-accept".to_owned())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(s) => s,
+                    Err(e) => {
+                        warn!(log, "failed to accept MySQL connection"; "err" => ?e);
+                        continue;
+                    }
+                };
+                let _ = stream.set_nodelay(true);
+
+                let backend = Backend {
+                    ch: ch.clone(),
+                    rt: rt.clone(),
+                    log: log.clone(),
+                    views: HashMap::new(),
+                    prepared: HashMap::new(),
+                    next_id: 0,
+                };
+                let log = log.clone();
+                thread::spawn(move || {
+                    if let Err(e) = MysqlIntermediary::run_on_tcp(backend, stream) {
+                        debug!(log, "MySQL connection closed"; "err" => ?e);
+                    }
+                });
+            }
+        })?;
+
+    Ok(addr)
+}
+
+/// A statement prepared by a client.
+enum Prepared {
+    /// A `SELECT` against the view called `view`, whose key is made up of the given values, with
+    /// `None` standing for the statement's parameters.
+    Select {
+        view: String,
+        key: Vec<Option<DataType>>,
+    },
+    /// A data-manipulation statement with placeholders for its parameters.
+    Write(SqlQuery),
+}
+
+struct Backend<A: Authority + 'static> {
+    ch: ControllerHandle<A>,
+    rt: tokio::runtime::Handle,
+    log: slog::Logger,
+    /// Views this connection has looked up, by name.
+    views: HashMap<String, View>,
+    prepared: HashMap<u32, Prepared>,
+    next_id: u32,
+}
+
+impl<A: Authority + 'static> Backend<A> {
+    /// Make sure the recipe contains a query for `q`, and return the name of its view along with
+    /// the view's key.
+    fn install_select(
+        &mut self,
+        mut q: SelectStatement,
+    ) -> Result<(String, Vec<Option<DataType>>), failure::Error> {
+        let mut key = Vec::new();
+        if let Some(ref mut cond) = q.where_clause {
+            parameterize(cond, &mut key);
+        }
+
+        let text = SqlQuery::Select(q).to_string();
+        let mut hasher = DefaultHasher::new();
+        text.hash(&mut hasher);
+        let name = format!("q_{:x}", hasher.finish());
+
+        if !self.views.contains_key(&name) {
+            let ch = &mut self.ch;
+            let view = self.rt.enter(|| {
+                futures_executor::block_on(async {
+                    ch.extend_recipe(&format!("QUERY {}: {};", name, text))
+                        .await?;
+                    ch.view(&name).await
+                })
+            })?;
+            debug!(
+                self.log,
+                "installed query for MySQL client";
+                "name" => &name,
+                "query" => &text
+            );
+            self.views.insert(name.clone(), view);
+        }
+        Ok((name, key))
+    }
+
+    /// The result columns of the view called `view`.
+    fn columns(&self, view: &str) -> Vec<Column> {
+        let view = &self.views[view];
+        match view.schema() {
+            Some(schema) => schema
+                .iter()
+                .map(|cs| Column {
+                    table: cs.column.table.clone().unwrap_or_default(),
+                    column: cs.column.name.clone(),
+                    coltype: column_type(&cs.sql_type),
+                    colflags: ColumnFlags::empty(),
+                })
+                .collect(),
+            None => view
+                .columns()
+                .iter()
+                .filter(|c| *c != "bogokey")
+                .map(|c| Column {
+                    table: String::new(),
+                    column: c.clone(),
+                    coltype: ColumnType::MYSQL_TYPE_VAR_STRING,
+                    colflags: ColumnFlags::empty(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Look up `key` in the view called `view`, and send the results to the client.
+    fn select<W: Write>(
+        &mut self,
+        view: &str,
+        key: Vec<DataType>,
+        results: QueryResultWriter<W>,
+    ) -> io::Result<()> {
+        // queries without parameters are keyed by a constant
+        let key = if key.is_empty() { vec![0.into()] } else { key };
+
+        let columns = self.columns(view);
+        let rt = self.rt.clone();
+        let v = self.views.get_mut(view).unwrap();
+        let rows: Vec<Vec<DataType>> =
+            match rt.enter(|| futures_executor::block_on(v.lookup(&key, true))) {
+                Ok(rows) => rows.into(),
+                Err(e) => return error(results, e),
+            };
+
+        let mut rw = results.start(&columns)?;
+        for row in &rows {
+            for (c, v) in columns.iter().zip(row.iter()) {
+                write_value(&mut rw, c.coltype, v)?;
+            }
+            rw.end_row()?;
+        }
+        rw.finish()
+    }
+
+    /// Execute a data-manipulation statement, and report the number of writes issued.
+    fn write<W: Write>(&mut self, sql: &str, results: QueryResultWriter<W>) -> io::Result<()> {
+        let ch = &mut self.ch;
+        match self
+            .rt
+            .enter(|| futures_executor::block_on(ch.execute(sql)))
+        {
+            Ok(n) => results.completed(n as u64, 0),
+            Err(e) => error(results, e),
+        }
+    }
+}
+
+impl<A: Authority + 'static, W: Write> MysqlShim<W> for Backend<A> {
+    type Error = io::Error;
+
+    fn on_prepare(&mut self, query: &str, info: StatementMetaWriter<W>) -> io::Result<()> {
+        let q = match nom_sql::parse_query(query) {
+            Ok(q) => q,
+            Err(e) => return info.error(ErrorKind::ER_PARSE_ERROR, e.as_bytes()),
+        };
+
+        let id = self.next_id;
+        self.next_id += 1;
+        match q {
+            SqlQuery::Select(q) => {
+                let (view, key) = match self.install_select(q) {
+                    Ok(r) => r,
+                    Err(e) => {
+                        return info.error(ErrorKind::ER_UNKNOWN_ERROR, e.to_string().as_bytes())
+                    }
+                };
+                let params = placeholder_columns(key.iter().filter(|k| k.is_none()).count());
+                let columns = self.columns(&view);
+                self.prepared.insert(id, Prepared::Select { view, key });
+                info.reply(id, &params, &columns)
+            }
+            mut q @ SqlQuery::Insert(_)
+            | mut q @ SqlQuery::Update(_)
+            | mut q @ SqlQuery::Delete(_) => {
+                let params = placeholder_columns(bind(&mut q, &mut std::iter::empty()));
+                self.prepared.insert(id, Prepared::Write(q));
+                info.reply(id, &params, &[])
+            }
+            _ => info.error(
+                ErrorKind::ER_NOT_SUPPORTED_YET,
+                b"only SELECT, INSERT, UPDATE, and DELETE statements can be prepared",
+            ),
+        }
+    }
+
+    fn on_execute(
+        &mut self,
+        id: u32,
+        params: ParamParser,
+        results: QueryResultWriter<W>,
+    ) -> io::Result<()> {
+        let mut values = Vec::new();
+        for p in params {
+            match param_value(p.value.into_inner()) {
+                Some(v) => values.push(v),
+                None => {
+                    return results.error(
+                        ErrorKind::ER_NOT_SUPPORTED_YET,
+                        b"unsupported parameter type",
+                    )
+                }
+            }
+        }
+        let mut values = values.into_iter();
+
+        match self.prepared.get(&id) {
+            None => results.error(ErrorKind::ER_UNKNOWN_STMT_HANDLER, b"no such statement"),
+            Some(Prepared::Select { view, key }) => {
+                let view = view.clone();
+                let key: Option<Vec<_>> = key
+                    .iter()
+                    .map(|k| k.clone().or_else(|| values.next()))
+                    .collect();
+                match key {
+                    Some(key) => self.select(&view, key, results),
+                    None => results.error(ErrorKind::ER_WRONG_ARGUMENTS, b"missing parameters"),
+                }
+            }
+            Some(Prepared::Write(q)) => {
+                let mut q = q.clone();
+                bind(&mut q, &mut values.map(|v| literal(&v)));
+                // any placeholders that are left were missing or had an unsupported type
+                if bind(&mut q, &mut std::iter::empty()) != 0 {
+                    return results.error(
+                        ErrorKind::ER_WRONG_ARGUMENTS,
+                        b"missing or unsupported parameters",
+                    );
+                }
+                self.write(&q.to_string(), results)
+            }
+        }
+    }
+
+    fn on_close(&mut self, id: u32) {
+        self.prepared.remove(&id);
+    }
+
+    fn on_query(&mut self, query: &str, results: QueryResultWriter<W>) -> io::Result<()> {
+        // clients like to poke at server variables when they connect
+        let lower = query.trim().to_lowercase();
+        if lower.starts_with("set ") {
+            return results.completed(0, 0);
+        } else if lower.starts_with("select @@") {
+            let columns = [Column {
+                table: String::new(),
+                column: query.trim()[7..].to_owned(),
+                coltype: ColumnType::MYSQL_TYPE_VAR_STRING,
+                colflags: ColumnFlags::empty(),
+            }];
+            let mut rw = results.start(&columns)?;
+            rw.write_col("Noria")?;
+            rw.end_row()?;
+            return rw.finish();
+        }
+
+        match nom_sql::parse_query(query) {
+            Ok(SqlQuery::CreateTable(_)) => {
+                let ch = &mut self.ch;
+                let sql = format!("{};", query.trim().trim_end_matches(';'));
+                match self
+                    .rt
+                    .enter(|| futures_executor::block_on(ch.extend_recipe(&sql)))
+                {
+                    Ok(_) => results.completed(0, 0),
+                    Err(e) => error(results, e),
+                }
+            }
+            Ok(SqlQuery::Select(q)) => match self.install_select(q) {
+                Ok((view, key)) => {
+                    // there is nothing to fill in placeholders with outside of prepared statements
+                    match key.into_iter().collect::<Option<Vec<_>>>() {
+                        Some(key) => self.select(&view, key, results),
+                        None => results.error(
+                            ErrorKind::ER_PARSE_ERROR,
+                            b"placeholders are only supported in prepared statements",
+                        ),
+                    }
+                }
+                Err(e) => error(results, e),
+            },
+            Ok(SqlQuery::Insert(_)) | Ok(SqlQuery::Update(_)) | Ok(SqlQuery::Delete(_)) => {
+                self.write(query, results)
+            }
+            Ok(_) => results.error(ErrorKind::ER_NOT_SUPPORTED_YET, b"unsupported statement"),
+            Err(e) => results.error(ErrorKind::ER_PARSE_ERROR, e.as_bytes()),
+        }
+    }
+}
+
+fn error<W: Write, E: std::fmt::Display>(results: QueryResultWriter<W>, e: E) -> io::Result<()> {
+    results.error(ErrorKind::ER_UNKNOWN_ERROR, e.to_string().as_bytes())
+}
+
+fn placeholder_columns(n: usize) -> Vec<Column> {
+    (0..n)
+        .map(|_| Column {
+            table: String::new(),
+            column: "?".to_owned(),
+            coltype: ColumnType::MYSQL_TYPE_VAR_STRING,
+            colflags: ColumnFlags::empty(),
+        })
+        .collect()
+}
+
+/// Turn every `column = value` comparison in `cond` into `column = ?`, and record the key values
+/// in order of appearance, with `None` for comparisons that already used a placeholder.
+fn parameterize(cond: &mut ConditionExpression, key: &mut Vec<Option<DataType>>) {
+    match *cond {
+        ConditionExpression::ComparisonOp(ref mut ct) if ct.operator == Operator::Equal => {
+            if let ConditionExpression::Base(ConditionBase::Literal(ref mut l)) = *ct.right {
+                match std::mem::replace(l, Literal::Placeholder) {
+                    Literal::Placeholder => key.push(None),
+                    l => key.push(Some(DataType::from(l))),
+                }
+            }
+        }
+        ConditionExpression::LogicalOp(ref mut ct) => {
+            parameterize(&mut ct.left, key);
+            parameterize(&mut ct.right, key);
+        }
+        ConditionExpression::NegationOp(ref mut c) | ConditionExpression::Bracketed(ref mut c) => {
+            parameterize(c, key)
+        }
+        _ => {}
+    }
+}
+
+/// Replace the placeholders in the data-manipulation statement `q` with values from `values`,
+/// in the order in which they appear in the statement, and return the number of placeholders.
+///
+/// Placeholders for which `values` yields `None`, or has run out, are left in place.
+fn bind<I>(q: &mut SqlQuery, values: &mut I) -> usize
+where
+    I: Iterator<Item = Option<Literal>>,
+{
+    fn fill<I: Iterator<Item = Option<Literal>>>(l: &mut Literal, values: &mut I) -> usize {
+        if let Literal::Placeholder = *l {
+            if let Some(Some(v)) = values.next() {
+                *l = v;
+            }
+            1
+        } else {
+            0
+        }
+    }
+
+    fn fill_expr<I: Iterator<Item = Option<Literal>>>(
+        e: &mut FieldValueExpression,
+        values: &mut I,
+    ) -> usize {
+        match *e {
+            FieldValueExpression::Literal(ref mut le) => fill(&mut le.value, values),
+            FieldValueExpression::Arithmetic(ref mut a) => {
+                let mut n = 0;
+                if let ArithmeticBase::Scalar(ref mut l) = a.left {
+                    n += fill(l, values);
+                }
+                if let ArithmeticBase::Scalar(ref mut l) = a.right {
+                    n += fill(l, values);
+                }
+                n
+            }
+        }
+    }
+
+    fn fill_cond<I: Iterator<Item = Option<Literal>>>(
+        c: &mut ConditionExpression,
+        values: &mut I,
+    ) -> usize {
+        match *c {
+            ConditionExpression::ComparisonOp(ref mut ct)
+            | ConditionExpression::LogicalOp(ref mut ct) => {
+                fill_cond(&mut ct.left, values) + fill_cond(&mut ct.right, values)
+            }
+            ConditionExpression::NegationOp(ref mut c)
+            | ConditionExpression::Bracketed(ref mut c) => fill_cond(c, values),
+            ConditionExpression::Base(ConditionBase::Literal(ref mut l)) => fill(l, values),
+            _ => 0,
+        }
+    }
+
+    match *q {
+        SqlQuery::Insert(ref mut q) => {
+            let mut n = 0;
+            for row in &mut q.data {
+                for l in row {
+                    n += fill(l, values);
+                }
+            }
+            if let Some(ref mut set) = q.on_duplicate {
+                for (_, e) in set {
+                    n += fill_expr(e, values);
+                }
+            }
+            n
+        }
+        SqlQuery::Update(ref mut q) => {
+            let mut n = 0;
+            for (_, e) in &mut q.fields {
+                n += fill_expr(e, values);
+            }
+            if let Some(ref mut c) = q.where_clause {
+                n += fill_cond(c, values);
+            }
+            n
+        }
+        SqlQuery::Delete(ref mut q) => match q.where_clause {
+            Some(ref mut c) => fill_cond(c, values),
+            None => 0,
+        },
+        _ => 0,
+    }
+}
+
+/// Convert a parameter sent by the client, if it is of a supported type.
+fn param_value(v: ValueInner) -> Option<DataType> {
+    match v {
+        ValueInner::NULL => Some(DataType::None),
+        ValueInner::Bytes(b) => Some(String::from_utf8_lossy(b).into_owned().into()),
+        ValueInner::Int(i) => Some(i.into()),
+        ValueInner::UInt(i) => Some(i.into()),
+        ValueInner::Double(f) if f.is_finite() => Some(f.into()),
+        _ => None,
+    }
+}
+
+/// Convert a parameter value to a literal that can be spliced into a statement.
+fn literal(v: &DataType) -> Option<Literal> {
+    match *v {
+        DataType::None => Some(Literal::Null),
+        DataType::Int(_) | DataType::BigInt(_) => Some(Literal::Integer(v.into())),
+        DataType::UnsignedInt(_) | DataType::UnsignedBigInt(_) => {
+            Some(Literal::UnsignedInteger(v.into()))
+        }
+        DataType::Text(_) | DataType::TinyText(_) => Some(Literal::String(v.into())),
+        DataType::Bytes(_) => Some(Literal::Blob(v.into())),
+        // fixed-point literals cannot represent every value precisely
+        DataType::Real(..) | DataType::Timestamp(_) => None,
+        // SQL has no literals for lists
+        DataType::List(_) => None,
+    }
+}
+
+fn column_type(t: &SqlType) -> ColumnType {
+    match *t {
+        SqlType::Int(_)
+        | SqlType::UnsignedInt(_)
+        | SqlType::Bigint(_)
+        | SqlType::UnsignedBigint(_)
+        | SqlType::Tinyint(_) => ColumnType::MYSQL_TYPE_LONGLONG,
+        SqlType::Real | SqlType::Float | SqlType::Double => ColumnType::MYSQL_TYPE_DOUBLE,
+        SqlType::Timestamp | SqlType::DateTime(_) => ColumnType::MYSQL_TYPE_DATETIME,
+        _ => ColumnType::MYSQL_TYPE_VAR_STRING,
+    }
+}
+
+fn write_value<W: Write>(
+    rw: &mut RowWriter<W>,
+    coltype: ColumnType,
+    v: &DataType,
+) -> io::Result<()> {
+    match (coltype, v) {
+        (_, DataType::None) => rw.write_col(None::<i64>),
+        (ColumnType::MYSQL_TYPE_LONGLONG, DataType::Int(_))
+        | (ColumnType::MYSQL_TYPE_LONGLONG, DataType::BigInt(_))
+        | (ColumnType::MYSQL_TYPE_LONGLONG, DataType::UnsignedInt(_)) => {
+            rw.write_col(Into::<i64>::into(v))
+        }
+        (ColumnType::MYSQL_TYPE_LONGLONG, DataType::UnsignedBigInt(_)) => {
+            rw.write_col(Into::<u64>::into(v))
+        }
+        (ColumnType::MYSQL_TYPE_DOUBLE, DataType::Real(..))
+        | (ColumnType::MYSQL_TYPE_DOUBLE, DataType::Int(_))
+        | (ColumnType::MYSQL_TYPE_DOUBLE, DataType::BigInt(_)) => {
+            rw.write_col(Into::<f64>::into(v))
+        }
+        (ColumnType::MYSQL_TYPE_DATETIME, DataType::Timestamp(ts)) => rw.write_col(*ts),
+        (_, DataType::Text(_)) | (_, DataType::TinyText(_)) => {
+            let s: Cow<str> = v.into();
+            rw.write_col(&*s)
+        }
+        (_, DataType::Bytes(ref b)) => rw.write_col(&b[..]),
+        // the value does not match the column's declared type, so fall back to text
+        (_, v) => rw.write_col(v.to_string()),
+    }
+}
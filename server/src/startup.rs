@@ -1,6 +1,7 @@
 use crate::controller::ControllerState;
 use crate::coordination::{CoordinationMessage, CoordinationPayload};
 use async_bincode::AsyncBincodeReader;
+use dataflow::{Clock, WatermarkPolicy};
 use futures_util::{
     future::FutureExt,
     future::TryFutureExt,
@@ -23,6 +24,7 @@ use stream_cancel::Valve;
 use tokio::sync::mpsc::UnboundedSender;
 
 use crate::handle::Handle;
+use crate::metrics::WorkerMetrics;
 use crate::Config;
 
 #[allow(clippy::large_enum_variant)]
@@ -37,13 +39,14 @@ pub(crate) enum Event {
     ),
     LeaderChange(ControllerState, ControllerDescriptor),
     WonLeaderElection(ControllerState),
-    CampaignError(failure::Error),
+    AuthorityUnreachable(failure::Error),
     #[cfg(test)]
     IsReady(tokio::sync::oneshot::Sender<bool>),
     ManualMigration {
         f: Box<dyn FnOnce(&mut crate::controller::migrate::Migration) + Send + 'static>,
         done: tokio::sync::oneshot::Sender<()>,
     },
+    OnController(Box<dyn FnOnce(&mut crate::controller::ControllerInner) + Send + 'static>),
 }
 
 use std::fmt;
@@ -54,10 +57,11 @@ impl fmt::Debug for Event {
             Event::ExternalRequest(ref m, ref path, ..) => write!(f, "Request({} {})", m, path),
             Event::LeaderChange(..) => write!(f, "LeaderChange(..)"),
             Event::WonLeaderElection(..) => write!(f, "Won(..)"),
-            Event::CampaignError(ref e) => write!(f, "CampaignError({:?})", e),
+            Event::AuthorityUnreachable(ref e) => write!(f, "AuthorityUnreachable({:?})", e),
             #[cfg(test)]
             Event::IsReady(..) => write!(f, "IsReady"),
             Event::ManualMigration { .. } => write!(f, "ManualMigration{{..}}"),
+            Event::OnController(..) => write!(f, "OnController(..)"),
         }
     }
 }
@@ -70,7 +74,13 @@ pub(super) async fn start_instance<A: Authority + 'static>(
     config: Config,
     memory_limit: Option<usize>,
     memory_check_frequency: Option<time::Duration>,
+    disk_quota: Option<u64>,
+    worker_label: Option<String>,
+    upgrade_token: Option<u64>,
+    mysql_addr: Option<SocketAddr>,
     log: slog::Logger,
+    clock: Clock,
+    watermark_policy: Arc<dyn WatermarkPolicy>,
 ) -> Result<(Handle<A>, impl Future<Output = ()> + Unpin + Send), failure::Error> {
     let (trigger, valve) = Valve::new();
     let (alive, done) = tokio::sync::mpsc::channel(1);
@@ -98,6 +108,9 @@ pub(super) async fn start_instance<A: Authority + 'static>(
     let (ctrl_tx, ctrl_rx) = tokio::sync::mpsc::unbounded_channel();
     let (worker_tx, worker_rx) = tokio::sync::mpsc::unbounded_channel();
 
+    // what the worker "part" of us exposes at /metrics
+    let metrics = Arc::new(WorkerMetrics::default());
+
     // spawn all of those
     tokio::spawn(listen_internal(
         alive.clone(),
@@ -114,6 +127,7 @@ pub(super) async fn start_instance<A: Authority + 'static>(
             tx.clone(),
             xport,
             authority.clone(),
+            metrics.clone(),
         )
         .map_err(move |e| {
             warn!(ext_log, "external request failed: {:?}", e);
@@ -134,15 +148,20 @@ pub(super) async fn start_instance<A: Authority + 'static>(
                     CoordinationPayload::RemoveDomain => wtx.send(e),
                     CoordinationPayload::AssignDomain(..) => wtx.send(e),
                     CoordinationPayload::DomainBooted(..) => wtx.send(e),
+                    CoordinationPayload::RoutingTable(..) => wtx.send(e),
+                    CoordinationPayload::DomainFailed { .. } => ctx.send(e),
                     CoordinationPayload::Register { .. } => ctx.send(e),
-                    CoordinationPayload::Heartbeat => ctx.send(e),
+                    CoordinationPayload::Heartbeat { .. } => ctx.send(e),
                     CoordinationPayload::CreateUniverse(..) => ctx.send(e),
+                    CoordinationPayload::DomainStatistics { .. } => ctx.send(e),
+                    CoordinationPayload::CorruptReplay { .. } => ctx.send(e),
                 },
                 Event::ExternalRequest(..) => ctx.send(e),
                 Event::ManualMigration { .. } => ctx.send(e),
+                Event::OnController(..) => ctx.send(e),
                 Event::LeaderChange(..) => wtx.send(e),
                 Event::WonLeaderElection(..) => ctx.send(e),
-                Event::CampaignError(..) => ctx.send(e),
+                Event::AuthorityUnreachable(..) => ctx.send(e),
                 #[cfg(test)]
                 Event::IsReady(..) => ctx.send(e),
             };
@@ -167,6 +186,7 @@ pub(super) async fn start_instance<A: Authority + 'static>(
         log.clone(),
         authority.clone(),
         tx.clone(),
+        clock.clone(),
     ));
     tokio::spawn(crate::worker::main(
         alive.clone(),
@@ -175,10 +195,19 @@ pub(super) async fn start_instance<A: Authority + 'static>(
         waddr,
         memory_limit,
         memory_check_frequency,
+        disk_quota,
+        worker_label,
+        upgrade_token,
+        metrics,
         log.clone(),
+        clock,
+        watermark_policy,
     ));
 
-    let h = Handle::new(authority, tx, trigger).await?;
+    let mut h = Handle::new(authority, tx, trigger).await?;
+    if let Some(addr) = mysql_addr {
+        h.serve_mysql(addr, log)?;
+    }
     Ok((h, done.into_future().map(|_| {})))
 }
 
@@ -222,6 +251,7 @@ struct ExternalServer<A: Authority>(
     tokio::sync::mpsc::Sender<()>,
     UnboundedSender<Event>,
     Arc<A>,
+    Arc<WorkerMetrics>,
 );
 
 async fn listen_external<A: Authority + 'static>(
@@ -230,6 +260,7 @@ async fn listen_external<A: Authority + 'static>(
     event_tx: UnboundedSender<Event>,
     mut on: tokio::net::TcpListener,
     authority: Arc<A>,
+    metrics: Arc<WorkerMetrics>,
 ) -> Result<(), hyper::Error> {
     let on = valve.wrap(on.incoming());
     use hyper::{service::make_service_fn, Body, Request, Response};
@@ -237,7 +268,12 @@ async fn listen_external<A: Authority + 'static>(
     impl<A: Authority> Clone for ExternalServer<A> {
         // Needed due to #26925
         fn clone(&self) -> Self {
-            ExternalServer(self.0.clone(), self.1.clone(), self.2.clone())
+            ExternalServer(
+                self.0.clone(),
+                self.1.clone(),
+                self.2.clone(),
+                self.3.clone(),
+            )
         }
     }
 
@@ -271,6 +307,30 @@ async fn listen_external<A: Authority + 'static>(
                         };
                         return Box::pin(async move { Ok(res.unwrap()) });
                     }
+                    "/metrics" => {
+                        // the worker's own metrics, followed by the controller's if we are it
+                        let mut metrics = self.3.render();
+                        let event_tx = self.1.clone();
+                        return Box::pin(async move {
+                            let (tx, rx) = tokio::sync::oneshot::channel();
+                            let req = Event::ExternalRequest(
+                                Method::GET,
+                                "/metrics".to_owned(),
+                                None,
+                                Default::default(),
+                                tx,
+                            );
+                            if event_tx.send(req).is_ok() {
+                                if let Ok(Ok(Ok(graph))) = rx.await {
+                                    metrics.push_str(&graph);
+                                }
+                            }
+                            let res = res
+                                .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+                                .body(hyper::Body::from(metrics));
+                            Ok(res.unwrap())
+                        });
+                    }
                     _ => {}
                 }
             }
@@ -315,7 +375,7 @@ async fn listen_external<A: Authority + 'static>(
         }
     }
 
-    let service = ExternalServer(alive, event_tx, authority);
+    let service = ExternalServer(alive, event_tx, authority, metrics);
     hyper::server::Server::builder(hyper::server::accept::from_stream(on))
         .serve(make_service_fn(move |_| {
             let s = service.clone();
@@ -0,0 +1,338 @@
+use futures_util::{sink::Sink, stream::Stream, task::AtomicWaker};
+use noria::{ClientConnection, ConnectionKind};
+use pin_project::pin_project;
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// A client connection to this worker, along with counters that are updated as it is used.
+#[derive(Debug)]
+pub(super) struct Connection {
+    id: u64,
+    peer: SocketAddr,
+    kind: ConnectionKind,
+    connected_at: SystemTime,
+    identity: Mutex<Option<String>>,
+    ops: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+}
+
+impl Connection {
+    /// Count a request sent by the client.
+    pub(super) fn record_op(&self) {
+        self.ops.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Remember the identity the client attached to a write.
+    pub(super) fn set_identity(&self, identity: Option<&str>) {
+        if let Some(identity) = identity {
+            let mut current = self.identity.lock().unwrap();
+            if current.as_deref() != Some(identity) {
+                *current = Some(identity.to_owned());
+            }
+        }
+    }
+}
+
+/// The clients that are connected to this worker, which are reported to the controller with
+/// every heartbeat.
+#[derive(Clone, Debug, Default)]
+pub(super) struct Registry {
+    inner: Arc<Mutex<RegistryInner>>,
+}
+
+#[derive(Debug, Default)]
+struct RegistryInner {
+    next_id: u64,
+    connections: HashMap<u64, Arc<Connection>>,
+}
+
+impl Registry {
+    /// Add a connection from `peer` to the registry.
+    ///
+    /// The connection is removed again when the returned handle is dropped.
+    pub(super) fn register(&self, peer: SocketAddr, kind: ConnectionKind) -> Registered {
+        let mut inner = self.inner.lock().unwrap();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        let conn = Arc::new(Connection {
+            id,
+            peer,
+            kind,
+            connected_at: SystemTime::now(),
+            identity: Mutex::new(None),
+            ops: AtomicU64::new(0),
+            bytes_in: AtomicU64::new(0),
+            bytes_out: AtomicU64::new(0),
+        });
+        inner.connections.insert(id, conn.clone());
+        Registered {
+            conn,
+            registry: self.clone(),
+        }
+    }
+
+    /// Describe every connection in the registry.
+    pub(super) fn connections(&self, worker: SocketAddr) -> Vec<ClientConnection> {
+        let inner = self.inner.lock().unwrap();
+        let mut conns: Vec<_> = inner
+            .connections
+            .values()
+            .map(|c| ClientConnection {
+                worker,
+                id: c.id,
+                peer: c.peer,
+                kind: c.kind,
+                identity: c.identity.lock().unwrap().clone(),
+                connected_at: c.connected_at,
+                ops: c.ops.load(Ordering::Relaxed),
+                bytes_in: c.bytes_in.load(Ordering::Relaxed),
+                bytes_out: c.bytes_out.load(Ordering::Relaxed),
+            })
+            .collect();
+        conns.sort_by_key(|c| c.id);
+        conns
+    }
+}
+
+/// A connection that is listed in a `Registry` for as long as this handle is alive.
+#[derive(Debug)]
+pub(super) struct Registered {
+    conn: Arc<Connection>,
+    registry: Registry,
+}
+
+impl Registered {
+    /// The counters of the registered connection.
+    pub(super) fn connection(&self) -> &Arc<Connection> {
+        &self.conn
+    }
+}
+
+impl Drop for Registered {
+    fn drop(&mut self) {
+        self.registry
+            .inner
+            .lock()
+            .unwrap()
+            .connections
+            .remove(&self.conn.id);
+    }
+}
+
+/// A stream that counts the bytes read from and written to it towards a registered connection.
+///
+/// Streams that are not registered, such as those between domains, are passed through unchanged.
+#[pin_project]
+pub(super) struct Counted<S> {
+    #[pin]
+    inner: S,
+    registered: Option<Registered>,
+}
+
+impl<S> Counted<S> {
+    pub(super) fn new(inner: S, registered: Option<Registered>) -> Self {
+        Counted { inner, registered }
+    }
+}
+
+impl<S: AsyncRead> AsyncRead for Counted<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.project();
+        let res = this.inner.poll_read(cx, buf);
+        if let (Poll::Ready(Ok(n)), Some(r)) = (&res, this.registered.as_ref()) {
+            r.conn.bytes_in.fetch_add(*n as u64, Ordering::Relaxed);
+        }
+        res
+    }
+}
+
+impl<S: AsyncWrite> AsyncWrite for Counted<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.project();
+        let res = this.inner.poll_write(cx, buf);
+        if let (Poll::Ready(Ok(n)), Some(r)) = (&res, this.registered.as_ref()) {
+            r.conn.bytes_out.fetch_add(*n as u64, Ordering::Relaxed);
+        }
+        res
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_shutdown(cx)
+    }
+}
+
+/// The writes a client connection has in flight, for connections whose writes are limited.
+#[derive(Debug)]
+pub(super) struct InFlight {
+    writes: AtomicUsize,
+    max: usize,
+    waker: AtomicWaker,
+}
+
+impl InFlight {
+    pub(super) fn new(max: usize) -> Arc<Self> {
+        Arc::new(InFlight {
+            writes: AtomicUsize::new(0),
+            max,
+            waker: AtomicWaker::new(),
+        })
+    }
+
+    /// Count a write as acknowledged, which lets the connection be read from again if it was at
+    /// its limit.
+    pub(super) fn finished(&self) {
+        self.writes.fetch_sub(1, Ordering::AcqRel);
+        self.waker.wake();
+    }
+
+    fn full(&self) -> bool {
+        self.writes.load(Ordering::Acquire) >= self.max
+    }
+}
+
+/// A stream of requests that is not read from while the connection has as many writes in flight
+/// as it may.
+///
+/// Each request read counts as in flight until it is passed to `InFlight::finished`. Streams
+/// without a limit, such as those between domains, are passed through unchanged.
+#[pin_project]
+pub(super) struct Limited<S> {
+    #[pin]
+    inner: S,
+    in_flight: Option<Arc<InFlight>>,
+}
+
+impl<S> Limited<S> {
+    pub(super) fn new(inner: S, in_flight: Option<Arc<InFlight>>) -> Self {
+        Limited { inner, in_flight }
+    }
+}
+
+impl<S: Stream> Stream for Limited<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if let Some(ref in_flight) = *this.in_flight {
+            if in_flight.full() {
+                in_flight.waker.register(cx.waker());
+                // a write may have finished before we registered
+                if in_flight.full() {
+                    return Poll::Pending;
+                }
+            }
+        }
+        let res = this.inner.poll_next(cx);
+        if let (Poll::Ready(Some(_)), Some(in_flight)) = (&res, this.in_flight.as_ref()) {
+            in_flight.writes.fetch_add(1, Ordering::AcqRel);
+        }
+        res
+    }
+}
+
+impl<S: Sink<I>, I> Sink<I> for Limited<S> {
+    type Error = S::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: I) -> Result<(), Self::Error> {
+        self.project().inner.start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connections_are_listed_while_registered() {
+        let registry = Registry::default();
+        let worker = "127.0.0.1:1".parse().unwrap();
+        let peer = "127.0.0.1:2".parse().unwrap();
+
+        let a = registry.register(peer, ConnectionKind::Read);
+        let b = registry.register(peer, ConnectionKind::Write);
+        a.connection().record_op();
+        b.connection().set_identity(Some("alice"));
+        b.connection().set_identity(None);
+
+        let conns = registry.connections(worker);
+        assert_eq!(conns.len(), 2);
+        assert_eq!(conns[0].kind, ConnectionKind::Read);
+        assert_eq!(conns[0].ops, 1);
+        assert_eq!(conns[1].identity.as_deref(), Some("alice"));
+        assert_eq!(conns[1].worker, worker);
+
+        drop(a);
+        let conns = registry.connections(worker);
+        assert_eq!(conns.len(), 1);
+        assert_eq!(conns[0].kind, ConnectionKind::Write);
+    }
+
+    #[test]
+    fn writes_in_flight_are_limited() {
+        use futures_util::stream::{self, StreamExt};
+        use futures_util::task::noop_waker;
+
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let in_flight = InFlight::new(2);
+        let mut writes = Limited::new(stream::iter(0..5), Some(in_flight.clone()));
+
+        // the connection is read from until it has two writes in flight
+        assert_eq!(writes.poll_next_unpin(&mut cx), Poll::Ready(Some(0)));
+        assert_eq!(writes.poll_next_unpin(&mut cx), Poll::Ready(Some(1)));
+        assert_eq!(writes.poll_next_unpin(&mut cx), Poll::Pending);
+        assert_eq!(writes.poll_next_unpin(&mut cx), Poll::Pending);
+
+        // and then one more write for each that is acknowledged
+        in_flight.finished();
+        assert_eq!(writes.poll_next_unpin(&mut cx), Poll::Ready(Some(2)));
+        assert_eq!(writes.poll_next_unpin(&mut cx), Poll::Pending);
+        in_flight.finished();
+        in_flight.finished();
+        assert_eq!(writes.poll_next_unpin(&mut cx), Poll::Ready(Some(3)));
+        assert_eq!(writes.poll_next_unpin(&mut cx), Poll::Ready(Some(4)));
+        assert_eq!(writes.poll_next_unpin(&mut cx), Poll::Pending);
+        in_flight.finished();
+        assert_eq!(writes.poll_next_unpin(&mut cx), Poll::Ready(None));
+
+        // streams without a limit are read from freely
+        let mut unlimited = Limited::new(stream::iter(0..3), None);
+        for i in 0..3 {
+            assert_eq!(unlimited.poll_next_unpin(&mut cx), Poll::Ready(Some(i)));
+        }
+    }
+}
@@ -1,8 +1,9 @@
 use crate::controller::ControllerState;
 use crate::coordination::{CoordinationMessage, CoordinationPayload, DomainDescriptor};
+use crate::metrics::WorkerMetrics;
 use crate::startup::Event;
 use async_bincode::AsyncBincodeWriter;
-use dataflow::{DomainBuilder, Packet};
+use dataflow::{Clock, DomainBuilder, Packet, PersistenceParameters, WatermarkPolicy};
 use futures_util::{future::FutureExt, future::TryFutureExt, sink::SinkExt, stream::StreamExt};
 use noria::channel::{self, TcpSender};
 use noria::consensus::Epoch;
@@ -14,6 +15,7 @@ use std::collections::HashMap;
 use std::fs;
 use std::io;
 use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Arc, Mutex,
@@ -23,6 +25,7 @@ use stream_cancel::{Trigger, Valve};
 use tokio;
 use tokio::sync::mpsc::UnboundedSender;
 
+mod connections;
 mod readers;
 mod replica;
 
@@ -49,7 +52,13 @@ pub(super) async fn main(
     waddr: SocketAddr,
     memory_limit: Option<usize>,
     memory_check_frequency: Option<time::Duration>,
+    disk_quota: Option<u64>,
+    label: Option<String>,
+    upgrade_token: Option<u64>,
+    metrics: Arc<WorkerMetrics>,
     log: slog::Logger,
+    clock: Clock,
+    watermark_policy: Arc<dyn WatermarkPolicy>,
 ) {
     // shared df state
     let coord = Arc::new(ChannelCoordinator::new());
@@ -81,17 +90,16 @@ pub(super) async fn main(
                 CoordinationPayload::DomainBooted(dd) => {
                     if let InstanceState::Active { epoch, .. } = worker_state {
                         if epoch == msg.epoch {
-                            let domain = dd.domain();
-                            let shard = dd.shard();
-                            let addr = dd.addr();
-                            trace!(
-                                log,
-                                "found that domain {}.{} is at {:?}",
-                                domain.index(),
-                                shard,
-                                addr
-                            );
-                            coord.insert_remote((domain, shard), addr);
+                            learn_route(&log, &coord, dd);
+                        }
+                    }
+                }
+                CoordinationPayload::RoutingTable(dds) => {
+                    if let InstanceState::Active { epoch, .. } = worker_state {
+                        if epoch == msg.epoch {
+                            for dd in dds {
+                                learn_route(&log, &coord, dd);
+                            }
                         }
                     }
                 }
@@ -109,6 +117,10 @@ pub(super) async fn main(
                     info!(log, "detected leader change");
                     drop(add_domain);
                     trigger.cancel();
+
+                    // route versions are handed out by the controller, so the new leader's
+                    // versions can't be compared to the old one's.
+                    coord.clear_versions();
                 } else {
                     info!(log, "found initial leader");
                 }
@@ -137,12 +149,18 @@ pub(super) async fn main(
                     valve,
                     log.clone(),
                     (memory_limit, memory_check_frequency),
+                    disk_quota,
+                    label.clone(),
+                    upgrade_token,
+                    metrics.clone(),
                     &state,
                     &descriptor,
                     waddr,
                     coord.clone(),
                     listen_addr,
                     rep_rx,
+                    clock.clone(),
+                    watermark_policy.clone(),
                 )
                 .await;
 
@@ -171,17 +189,93 @@ pub(super) async fn main(
     // TODO: maybe flush things or something?
 }
 
+/// Record the location of a domain announced by the controller.
+fn learn_route(log: &slog::Logger, coord: &ChannelCoordinator, dd: DomainDescriptor) {
+    let domain = dd.domain();
+    let shard = dd.shard();
+    let addr = dd.addr();
+    if coord.insert_remote_versioned((domain, shard), addr, dd.version()) {
+        trace!(
+            log,
+            "found that domain {}.{} is at {:?}",
+            domain.index(),
+            shard,
+            addr;
+            "version" => dd.version()
+        );
+    }
+}
+
+/// The disk space, in bytes, used by the persistent state of base tables on this worker.
+///
+/// Walking the directories can take a while, so it is done on a thread where blocking is allowed.
+async fn measure_disk_usage(params: &PersistenceParameters) -> u64 {
+    let params = params.clone();
+    tokio::task::spawn_blocking(move || persistence_disk_usage(&params))
+        .await
+        .unwrap_or(0)
+}
+
+/// The disk space, in bytes, used by the persistent state of base tables on this worker.
+///
+/// This counts the files of `DurabilityMode::Permanent` base tables in the working directory, and
+/// their write-ahead logs if those are kept in a separate log directory.
+fn persistence_disk_usage(params: &PersistenceParameters) -> u64 {
+    fn size(path: &Path) -> u64 {
+        match fs::symlink_metadata(path) {
+            Ok(ref m) if m.is_dir() => fs::read_dir(path)
+                .map(|es| es.filter_map(Result::ok).map(|e| size(&e.path())).sum())
+                .unwrap_or(0),
+            Ok(m) => m.len(),
+            Err(_) => 0,
+        }
+    }
+
+    let prefix = format!("{}-", params.log_prefix);
+    let mut dirs = vec![PathBuf::from(".")];
+    if let Some(ref dir) = params.log_dir {
+        dirs.push(dir.clone());
+    }
+    dirs.into_iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_name().to_string_lossy().starts_with(&prefix))
+        .map(|e| size(&e.path()))
+        .sum()
+}
+
+/// The one-minute load average of this host, if the platform reports it.
+fn load_average() -> Option<f64> {
+    if cfg!(target_os = "linux") {
+        fs::read_to_string("/proc/loadavg")
+            .ok()?
+            .split_whitespace()
+            .next()?
+            .parse()
+            .ok()
+    } else {
+        None
+    }
+}
+
 async fn listen_df<'a>(
     alive: tokio::sync::mpsc::Sender<()>,
     valve: Valve,
     log: slog::Logger,
     (memory_limit, evict_every): (Option<usize>, Option<Duration>),
+    disk_quota: Option<u64>,
+    label: Option<String>,
+    upgrade_token: Option<u64>,
+    metrics: Arc<WorkerMetrics>,
     state: &'a ControllerState,
     desc: &'a ControllerDescriptor,
     waddr: SocketAddr,
     coord: Arc<ChannelCoordinator>,
     on: IpAddr,
     mut replicas: tokio::sync::mpsc::UnboundedReceiver<DomainBuilder>,
+    clock: Clock,
+    watermark_policy: Arc<dyn WatermarkPolicy>,
 ) -> Result<(), failure::Error> {
     // first, try to connect to controller
     let ctrl = tokio::net::TcpStream::connect(&desc.worker_addr).await?;
@@ -201,14 +295,21 @@ async fn listen_df<'a>(
     // extract important things from state config
     let epoch = state.epoch;
     let heartbeat_every = state.config.heartbeat_every;
+    let supervise_domains = state.config.supervise_domains;
+    let max_outstanding_writes = state.config.max_outstanding_writes;
 
     let (ctrl_tx, mut ctrl_rx) = tokio::sync::mpsc::unbounded_channel();
 
+    // clients connected to us, for reads or to write to our domains
+    let registry = connections::Registry::default();
+
     // reader setup
     let readers = Arc::new(Mutex::new(HashMap::new()));
-    let rport = tokio::net::TcpListener::bind(&SocketAddr::new(on, 0)).await?;
-    let raddr = rport.local_addr()?;
-    info!(log, "listening for reads"; "on" => ?raddr);
+    let read_queues = readers::ReadQueues::default();
+    let read_waiters = readers::Waiters::default();
+    let rports = readers::bind(SocketAddr::new(on, 0), state.config.read_acceptors)?;
+    let raddr = rports[0].local_addr()?;
+    info!(log, "listening for reads"; "on" => ?raddr, "acceptors" => rports.len());
 
     // start controller message handler
     let mut ctrl = AsyncBincodeWriter::from(ctrl).for_async();
@@ -232,12 +333,21 @@ async fn listen_df<'a>(
     });
 
     // also start readers
-    tokio::spawn(readers::listen(
-        alive.clone(),
-        valve.clone(),
-        rport,
-        readers.clone(),
-    ));
+    for rport in rports {
+        tokio::spawn(readers::listen(
+            alive.clone(),
+            valve.clone(),
+            rport,
+            readers.clone(),
+            read_queues.clone(),
+            read_waiters.clone(),
+            registry.clone(),
+            state.config.max_outstanding_reads,
+            clock.clone(),
+        ));
+    }
+
+    let state_sizes = Arc::new(Mutex::new(HashMap::<_, Arc<AtomicUsize>>::new()));
 
     // and tell the controller about us
     let mut timer = valve.wrap(tokio::time::interval_at(
@@ -246,24 +356,46 @@ async fn listen_df<'a>(
     ));
     let a = alive.clone();
     let ctx = ctrl_tx.clone();
+    let persistence = state.config.persistence.clone();
+    let reg = registry.clone();
+    let sizes = state_sizes.clone();
+    let m = metrics.clone();
     tokio::spawn(async move {
         let _alive = a;
         let _ = ctx.send(CoordinationPayload::Register {
             addr: waddr,
             read_listen_addr: raddr,
             log_files,
+            disk_quota,
+            disk_usage: measure_disk_usage(&persistence).await,
+            label,
+            upgrade_token,
         });
 
         // start sending heartbeats
         while let Some(_) = timer.next().await {
-            if let Err(_) = ctx.send(CoordinationPayload::Heartbeat) {
+            let disk_usage = measure_disk_usage(&persistence).await;
+            let connections = reg.connections(waddr);
+            m.heartbeat(disk_usage, connections.len());
+            let (domains_hosted, memory_used) = {
+                let sizes = sizes.lock().unwrap();
+                let used: usize = sizes.values().map(|s| s.load(Ordering::Relaxed)).sum();
+                (sizes.len(), used as u64)
+            };
+            if let Err(_) = ctx.send(CoordinationPayload::Heartbeat {
+                disk_usage,
+                connections,
+                domains_hosted,
+                memory_used,
+                cpu_load: load_average(),
+                read_queues: read_queues.depths(),
+            }) {
                 // if we error we're probably just shutting down
                 break;
             }
         }
     });
 
-    let state_sizes = Arc::new(Mutex::new(HashMap::new()));
     if let Some(evict_every) = evict_every {
         let log = log.clone();
         let mut domain_senders = HashMap::new();
@@ -281,6 +413,14 @@ async fn listen_df<'a>(
         });
     }
 
+    // in deterministic mode, a basic scheduler runs all of our domains on a single thread, so that
+    // they are scheduled one at a time.
+    let domains_rt = if state.config.domain_config.interleave_seed.is_some() {
+        Some(single_threaded_runtime(&valve)?)
+    } else {
+        None
+    };
+
     // Now we're ready to accept new domains.
     let dcaddr = desc.domain_addr;
     tokio::spawn(
@@ -294,6 +434,22 @@ async fn listen_df<'a>(
                 let addr = on.local_addr()?;
 
                 let state_size = Arc::new(AtomicUsize::new(0));
+                let queued = Arc::new(AtomicUsize::new(0));
+                metrics.add_replica((idx, shard), state_size.clone(), queued.clone());
+                let supervisor = if supervise_domains {
+                    Some(replica::Supervisor::new(
+                        d.clone(),
+                        log.clone(),
+                        readers.clone(),
+                        dcaddr,
+                        &valve,
+                        state_size.clone(),
+                        clock.clone(),
+                        watermark_policy.clone(),
+                    ))
+                } else {
+                    None
+                };
                 let d = d.build(
                     log.clone(),
                     readers.clone(),
@@ -301,6 +457,8 @@ async fn listen_df<'a>(
                     dcaddr,
                     &valve,
                     state_size.clone(),
+                    clock.clone(),
+                    watermark_policy.clone(),
                 );
 
                 let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
@@ -323,15 +481,23 @@ async fn listen_df<'a>(
                     ctrl_tx.clone(),
                     log.clone(),
                     coord.clone(),
+                    supervisor,
+                    registry.clone(),
+                    max_outstanding_writes,
+                    queued,
                 );
                 let a = alive.clone();
-                tokio::spawn(async move {
+                let replica = async move {
                     let _alive = a;
                     let log = replica.log.clone();
                     if let Err(e) = replica.await {
                         crit!(log, "replica failure: {:?}", e);
                     }
-                });
+                };
+                match domains_rt {
+                    Some(ref rt) => rt.spawn(replica),
+                    None => tokio::spawn(replica),
+                };
 
                 info!(
                     log,
@@ -360,6 +526,23 @@ async fn listen_df<'a>(
     Ok(())
 }
 
+/// Start a runtime that runs the tasks spawned onto it on a single thread, until `valve` closes.
+///
+/// The runtime uses the basic scheduler, which runs its tasks in the order they are woken up, so
+/// the domains spawned onto it must not block in place.
+fn single_threaded_runtime(valve: &Valve) -> io::Result<tokio::runtime::Handle> {
+    let mut rt = tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()?;
+    let handle = rt.handle().clone();
+    let mut closed = valve.wrap(futures_util::stream::pending::<()>());
+    std::thread::Builder::new()
+        .name("domains".to_string())
+        .spawn(move || rt.block_on(async move { while closed.next().await.is_some() {} }))?;
+    Ok(handle)
+}
+
 #[allow(clippy::type_complexity)]
 async fn do_eviction(
     log: &slog::Logger,
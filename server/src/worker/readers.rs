@@ -1,6 +1,8 @@
+use super::connections::{Counted, Registry};
 use async_bincode::AsyncBincodeStream;
 use dataflow::prelude::DataType;
 use dataflow::prelude::*;
+use dataflow::Clock;
 use dataflow::Readers;
 use dataflow::SingleReadHandle;
 use futures_util::{
@@ -8,13 +10,20 @@ use futures_util::{
     future::Either,
     future::{FutureExt, TryFutureExt},
     ready,
+    sink::Sink,
     stream::{Stream, StreamExt, TryStreamExt},
 };
-use noria::{ReadQuery, ReadReply, Tagged};
+use noria::results::Predicate;
+use noria::{ConnectionKind, ReadQuery, ReadReply, Tagged, ViewState};
 use pin_project::pin_project;
 use std::cell::RefCell;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io;
 use std::mem;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
 use std::time;
 use std::{
     future::Future,
@@ -23,7 +32,8 @@ use std::{
 };
 use stream_cancel::Valve;
 use tokio_tower::multiplex::server;
-use tower::service_fn;
+use tower::limit::ConcurrencyLimit;
+use tower::{service_fn, Service};
 
 /// Retry reads every this often.
 const RETRY_TIMEOUT_MS: u64 = 1;
@@ -33,6 +43,9 @@ const RETRY_TIMEOUT_MS: u64 = 1;
 /// while, waiting readers will use exponential backoff on this delay if they continue to miss.
 const TRIGGER_TIMEOUT_MS: u64 = 10;
 
+/// Where the reply to a blocking read is sent.
+type Ack = tokio::sync::oneshot::Sender<Result<Tagged<ReadReply>, ()>>;
+
 thread_local! {
     static READERS: RefCell<HashMap<
         (NodeIndex, usize),
@@ -40,22 +53,202 @@ thread_local! {
     >> = Default::default();
 }
 
+/// The number of blocking reads waiting on each reader shard, which is reported to the controller
+/// with every heartbeat.
+#[derive(Clone, Debug, Default)]
+pub(super) struct ReadQueues {
+    inner: Arc<Mutex<HashMap<(NodeIndex, usize), usize>>>,
+}
+
+impl ReadQueues {
+    /// Count a read as waiting on `target` until the returned handle is dropped.
+    fn enqueue(&self, target: (NodeIndex, usize)) -> Queued {
+        *self.inner.lock().unwrap().entry(target).or_insert(0) += 1;
+        Queued {
+            queues: self.clone(),
+            target,
+        }
+    }
+
+    /// The number of reads waiting on each reader shard that has any.
+    pub(super) fn depths(&self) -> HashMap<(NodeIndex, usize), usize> {
+        self.inner.lock().unwrap().clone()
+    }
+}
+
+/// A read counted as waiting in `ReadQueues`.
+#[derive(Debug)]
+struct Queued {
+    queues: ReadQueues,
+    target: (NodeIndex, usize),
+}
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        let mut inner = self.queues.inner.lock().unwrap();
+        if let Entry::Occupied(mut e) = inner.entry(self.target) {
+            *e.get_mut() -= 1;
+            if *e.get() == 0 {
+                e.remove();
+            }
+        }
+    }
+}
+
+/// Everything that determines the reply to a read of a view.
+#[derive(Clone, Debug, PartialEq)]
+struct Lookup {
+    target: (NodeIndex, usize),
+    keys: Vec<Vec<DataType>>,
+    filter: Option<Predicate>,
+    count: bool,
+    hidden: Vec<usize>,
+}
+
+impl Eq for Lookup {}
+
+impl Hash for Lookup {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // predicates can't be hashed, so lookups that differ only in their filters share a bucket
+        self.target.hash(state);
+        self.keys.hash(state);
+        self.count.hash(state);
+        self.hidden.hash(state);
+    }
+}
+
+/// A blocking read that is answered with the reply to an identical read that was already waiting.
+#[derive(Debug)]
+struct Follower {
+    tag: u32,
+    ack: Ack,
+    _queued: Queued,
+}
+
+/// The blocking reads that wait on an earlier, identical read instead of on their own keys, keyed
+/// by what they read.
+///
+/// Many clients looking up the same missing key then share a single waiting read, which retries
+/// the lookup and re-triggers the replay on their behalf, and its reply is sent to all of them.
+/// Reads with a timeout are never coalesced, since they may give up before the keys are filled.
+#[derive(Clone, Debug, Default)]
+pub(super) struct Waiters {
+    inner: Arc<Mutex<HashMap<Lookup, Vec<Follower>>>>,
+}
+
+impl Waiters {
+    /// Have the read with `tag` wait for the reply to an earlier read of `lookup`, if one is
+    /// still waiting.
+    ///
+    /// If there is none, `None` is returned, and later reads of `lookup` wait for the caller
+    /// instead, until it calls `answer`.
+    fn join(
+        &self,
+        lookup: &Lookup,
+        tag: u32,
+        queued: Queued,
+    ) -> Option<tokio::sync::oneshot::Receiver<Result<Tagged<ReadReply>, ()>>> {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.get_mut(lookup) {
+            Some(followers) => {
+                let (ack, rx) = tokio::sync::oneshot::channel();
+                followers.push(Follower {
+                    tag,
+                    ack,
+                    _queued: queued,
+                });
+                Some(rx)
+            }
+            None => {
+                inner.insert(lookup.clone(), Vec::new());
+                None
+            }
+        }
+    }
+
+    /// Send `reply`, the reply to a read of `lookup`, to the reads that waited for it.
+    fn answer(&self, lookup: &Lookup, reply: &Result<Tagged<ReadReply>, ()>) {
+        let followers = self.inner.lock().unwrap().remove(lookup);
+        for f in followers.into_iter().flatten() {
+            let reply = reply.as_ref().map(|r| Tagged {
+                tag: f.tag,
+                v: r.v.clone(),
+            });
+            // if this errors, the client just went away
+            let _ = f.ack.send(reply.map_err(|_| ()));
+        }
+    }
+}
+
+/// Bind `acceptors` listeners for reads to `addr`.
+///
+/// With more than one acceptor, all listeners share a single port using `SO_REUSEPORT`, and the
+/// kernel spreads incoming connections across them. Each listener is then driven by its own
+/// `listen` loop. This is safe since every thread that serves reads uses its own clones of the
+/// reader handles (see `READERS`), which never block the domain's write handle.
+pub(super) fn bind(addr: SocketAddr, acceptors: usize) -> io::Result<Vec<tokio::net::TcpListener>> {
+    #[cfg(unix)]
+    {
+        if acceptors > 1 {
+            let first = reuseport_listener(addr)?;
+            // the remaining acceptors need to bind to the port picked for the first one
+            let addr = first.local_addr()?;
+            let mut listeners = vec![first];
+            for _ in 1..acceptors {
+                listeners.push(reuseport_listener(addr)?);
+            }
+            return listeners
+                .into_iter()
+                .map(tokio::net::TcpListener::from_std)
+                .collect();
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = acceptors;
+
+    let listener = std::net::TcpListener::bind(addr)?;
+    Ok(vec![tokio::net::TcpListener::from_std(listener)?])
+}
+
+#[cfg(unix)]
+fn reuseport_listener(addr: SocketAddr) -> io::Result<std::net::TcpListener> {
+    use net2::unix::UnixTcpBuilderExt;
+
+    let builder = match addr {
+        SocketAddr::V4(_) => net2::TcpBuilder::new_v4()?,
+        SocketAddr::V6(_) => net2::TcpBuilder::new_v6()?,
+    };
+    builder
+        .reuse_address(true)?
+        .reuse_port(true)?
+        .bind(addr)?
+        .listen(1024)
+}
+
 pub(super) async fn listen(
     alive: tokio::sync::mpsc::Sender<()>,
     valve: Valve,
     mut on: tokio::net::TcpListener,
     readers: Readers,
+    queues: ReadQueues,
+    waiters: Waiters,
+    registry: Registry,
+    max_outstanding: Option<usize>,
+    clock: Clock,
 ) {
     // future that ensures all blocking reads are handled in FIFO order
     // and avoid hogging the executors with read retries
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<(
-        BlockingRead,
-        tokio::sync::oneshot::Sender<Result<Tagged<ReadReply>, ()>>,
-    )>();
+    let (tx, mut rx) =
+        tokio::sync::mpsc::unbounded_channel::<(BlockingRead, Option<Lookup>, Ack)>();
+    let w = waiters.clone();
     tokio::spawn(async move {
-        while let Some((blocking, ack)) = rx.next().await {
+        while let Some((blocking, lookup, ack)) = rx.next().await {
+            let reply = blocking.await;
+            if let Some(lookup) = lookup {
+                w.answer(&lookup, &reply);
+            }
             // if this errors, the client just went away
-            let _ = ack.send(blocking.await);
+            let _ = ack.send(reply);
         }
     });
 
@@ -68,14 +261,43 @@ pub(super) async fn listen(
 
         let stream = stream.unwrap();
         let readers = readers.clone();
+        let queues = queues.clone();
+        let waiters = waiters.clone();
+        let clock = clock.clone();
         stream.set_nodelay(true).expect("could not set TCP_NODELAY");
-        let alive = alive.clone();
+        let registered = stream
+            .peer_addr()
+            .ok()
+            .map(|peer| registry.register(peer, ConnectionKind::Read));
+        let conn = registered.as_ref().map(|r| r.connection().clone());
+        let stream = AsyncBincodeStream::from(Counted::new(stream, registered)).for_async();
         let mut tx = tx.clone();
-        tokio::spawn(
-            server::Server::new(
-                AsyncBincodeStream::from(stream).for_async(),
-                service_fn(move |req| handle_message(req, &readers, &mut tx)),
-            )
+        let service = service_fn(move |req| {
+            if let Some(ref conn) = conn {
+                conn.record_op();
+            }
+            handle_message(req, &readers, &queues, &waiters, &mut tx, &clock)
+        });
+        match max_outstanding {
+            // the connection isn't read from while it has this many reads in flight
+            Some(max) => serve(stream, ConcurrencyLimit::new(service, max), alive.clone()),
+            None => serve(stream, service, alive.clone()),
+        }
+    }
+}
+
+/// Serve the reads that arrive on `stream` with `service`.
+fn serve<T, S>(stream: T, service: S, alive: tokio::sync::mpsc::Sender<()>)
+where
+    T: Sink<Tagged<ReadReply>, Error = bincode::Error>
+        + Stream<Item = Result<Tagged<ReadQuery>, bincode::Error>>
+        + Send
+        + 'static,
+    S: Service<Tagged<ReadQuery>, Response = Tagged<ReadReply>, Error = ()> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    tokio::spawn(
+        server::Server::new(stream, service)
             .map_err(|e| {
                 match e {
                     server::Error::Service(()) => {
@@ -100,108 +322,246 @@ pub(super) async fn listen(
                 let _ = alive;
                 r
             }),
-        );
+    );
+}
+
+/// A copy of `r` in which the values of the `hidden` columns are replaced with `NULL`.
+fn mask(r: &[DataType], hidden: &[usize]) -> Vec<DataType> {
+    r.iter()
+        .enumerate()
+        .map(|(i, v)| {
+            if hidden.contains(&i) {
+                DataType::None
+            } else {
+                v.deep_clone()
+            }
+        })
+        .collect()
+}
+
+/// Whether `r` matches `filter` once its `hidden` columns are masked, so that filters can't be
+/// used to probe the values of masked columns.
+fn matches(filter: &Predicate, r: &[DataType], hidden: &[usize]) -> bool {
+    if hidden.is_empty() {
+        filter.matches(r)
+    } else {
+        filter.matches(&mask(r, hidden))
     }
 }
 
-fn dup<'a>(rs: impl IntoIterator<Item = &'a Vec<DataType>>) -> Vec<Vec<DataType>> {
+fn dup<'a>(
+    rs: impl IntoIterator<Item = &'a Vec<DataType>>,
+    filter: Option<&Predicate>,
+    hidden: &[usize],
+) -> Vec<Vec<DataType>> {
     let rs = rs.into_iter();
     let mut outer = Vec::with_capacity(rs.size_hint().0);
     for r in rs {
-        let mut inner = Vec::with_capacity(r.len());
-        for v in r {
-            inner.push(v.deep_clone())
+        let inner = mask(r, hidden);
+        if let Some(filter) = filter {
+            if !filter.matches(&inner) {
+                continue;
+            }
         }
         outer.push(inner);
     }
     outer
 }
 
+/// Look up `key` in `reader`, returning either the (filtered) rows or, if `count` is set, a single
+/// row holding the number of (filtered) rows for the key. The values of the `hidden` columns are
+/// masked.
+///
+/// Holes in partially materialized state are returned as `Ok(None)`.
+fn read_key(
+    reader: &SingleReadHandle,
+    key: &[DataType],
+    filter: Option<&Predicate>,
+    count: bool,
+    hidden: &[usize],
+) -> Result<Option<Vec<Vec<DataType>>>, ()> {
+    if !count {
+        return reader
+            .try_find_and(key, |rs| dup(rs, filter, hidden))
+            .map(|r| r.0);
+    }
+
+    let n = match filter {
+        Some(filter) => reader.try_find_and(key, |rs| {
+            rs.iter().filter(|r| matches(filter, r, hidden)).count()
+        }),
+        None => reader.try_count(key),
+    };
+    n.map(|r| r.0.map(|n| vec![vec![DataType::from(n)]]))
+}
+
+/// Like `read_key`, but looks `key` up in the snapshot that `reader` was restored from after a
+/// restart, if the reader can't answer for `key` itself yet.
+fn read_stale(
+    reader: &SingleReadHandle,
+    key: &[DataType],
+    filter: Option<&Predicate>,
+    count: bool,
+    hidden: &[usize],
+) -> Option<Vec<Vec<DataType>>> {
+    if !count {
+        return reader.try_find_stale_and(key, |rs| dup(rs, filter, hidden));
+    }
+
+    let n = match filter {
+        Some(filter) => reader.try_find_stale_and(key, |rs| {
+            rs.iter().filter(|r| matches(filter, r, hidden)).count()
+        }),
+        None => reader.try_count_stale(key),
+    };
+    n.map(|n| vec![vec![DataType::from(n)]])
+}
+
+/// The reply to a read that found `rows`, where `stale` says which keys' rows were read from a
+/// snapshot.
+fn reply(rows: Vec<Vec<Vec<DataType>>>, stale: Vec<bool>) -> ReadReply {
+    if stale.iter().any(|&s| s) {
+        ReadReply::Stale(rows.into_iter().zip(stale).collect())
+    } else {
+        ReadReply::Normal(Ok(rows))
+    }
+}
+
+/// The reply to a blocking read, once it is sent on `rx`.
+fn acked(
+    rx: tokio::sync::oneshot::Receiver<Result<Tagged<ReadReply>, ()>>,
+) -> impl Future<Output = Result<Tagged<ReadReply>, ()>> {
+    rx.map(|r| match r {
+        Err(_) => Err(()),
+        Ok(r) => r,
+    })
+}
+
 fn handle_message(
     m: Tagged<ReadQuery>,
     s: &Readers,
-    wait: &mut tokio::sync::mpsc::UnboundedSender<(
-        BlockingRead,
-        tokio::sync::oneshot::Sender<Result<Tagged<ReadReply>, ()>>,
-    )>,
+    queues: &ReadQueues,
+    waiters: &Waiters,
+    wait: &mut tokio::sync::mpsc::UnboundedSender<(BlockingRead, Option<Lookup>, Ack)>,
+    clock: &Clock,
 ) -> impl Future<Output = Result<Tagged<ReadReply>, ()>> + Send {
     let tag = m.tag;
     match m.v {
         ReadQuery::Normal {
             target,
-            mut keys,
+            keys,
             block,
+            timeout,
+            filter,
+            count,
+            identity,
         } => {
+            // comparisons against the current time go by our clock, not the client's
+            let filter = filter.map(|f| f.at(&DataType::from(clock.local())));
             let immediate = READERS.with(|readers_cache| {
                 let mut readers_cache = readers_cache.borrow_mut();
-                let reader = readers_cache.entry(target).or_insert_with(|| {
-                    let readers = s.lock().unwrap();
-                    readers.get(&target).unwrap().clone()
-                });
+                let reader = match readers_cache.entry(target) {
+                    Entry::Occupied(e) => e.into_mut(),
+                    Entry::Vacant(e) => match s.lock().unwrap().get(&target) {
+                        Some(r) => e.insert(r.clone()),
+                        None => {
+                            // the view has been removed
+                            return Ok(Tagged {
+                                tag,
+                                v: ReadReply::Normal(Err(())),
+                            });
+                        }
+                    },
+                };
 
                 let mut ret = Vec::with_capacity(keys.len());
+                let mut stale = vec![false; keys.len()];
+                // masking is enforced here rather than trusted to clients
+                let hidden = reader.hidden_columns();
 
                 // first do non-blocking reads for all keys to see if we can return immediately
-                let mut i = -1;
-                let mut ready = true;
                 let mut pending = Vec::new();
-                keys.retain(|key| {
-                    i += 1;
-                    if !ready {
-                        ret.push(Vec::new());
-                        return false;
+                let mut missed = Vec::new();
+                let mut refresh = Vec::new();
+                for (i, key) in keys.iter().enumerate() {
+                    reader.record_lookup(key, identity.as_deref());
+                    if let Some(rs) = read_stale(reader, key, filter.as_ref(), count, &hidden) {
+                        // the view is still catching up after a restart
+                        stale[i] = true;
+                        ret.push(rs);
+                        if reader.is_partial() {
+                            refresh.push(key.clone());
+                        }
+                        continue;
                     }
-                    let rs = reader.try_find_and(key, |rs| dup(rs)).map(|r| r.0);
-                    match rs {
+                    match read_key(reader, key, filter.as_ref(), count, &hidden) {
                         Ok(Some(rs)) => {
                             // immediate hit!
                             ret.push(rs);
-                            false
                         }
                         Err(()) => {
                             // map not yet ready
-                            ready = false;
-                            ret.push(Vec::new());
-                            false
+                            return Ok(Tagged {
+                                tag,
+                                v: ReadReply::Normal(Err(())),
+                            });
                         }
                         Ok(None) => {
                             // need to trigger partial replay for this key
-                            pending.push(i as usize);
+                            pending.push(i);
+                            missed.push(key.clone());
                             ret.push(Vec::new());
-                            true
                         }
                     }
-                });
+                }
 
-                if !ready {
-                    return Ok(Tagged {
-                        tag,
-                        v: ReadReply::Normal(Err(())),
-                    });
+                if !refresh.is_empty() {
+                    // fill the keys we served from the snapshot so that later reads are fresh
+                    reader.trigger(refresh.iter().map(Vec::as_slice));
                 }
 
-                if keys.is_empty() {
+                if missed.is_empty() {
                     // we hit on all the keys!
                     assert!(pending.is_empty());
                     return Ok(Tagged {
                         tag,
-                        v: ReadReply::Normal(Ok(ret)),
+                        v: reply(ret, stale),
                     });
                 }
 
+                let lookup = if block && timeout.is_none() {
+                    let lookup = Lookup {
+                        target,
+                        keys,
+                        filter: filter.clone(),
+                        count,
+                        hidden: hidden.clone(),
+                    };
+                    if let Some(rx) = waiters.join(&lookup, tag, queues.enqueue(target)) {
+                        // an identical read is already waiting for these keys to be filled, and
+                        // has triggered their replays
+                        reader.record_coalesced();
+                        return Err(Either::Left(rx));
+                    }
+                    Some(lookup)
+                } else {
+                    None
+                };
+
                 // trigger backfills for all the keys we missed on
-                reader.trigger(keys.iter().map(Vec::as_slice));
+                reader.trigger(missed.iter().map(Vec::as_slice));
 
-                Err((keys, ret, pending))
+                Err(Either::Right((missed, ret, stale, pending, hidden, lookup)))
             });
 
             match immediate {
                 Ok(reply) => Either::Left(Either::Left(future::ready(Ok(reply)))),
-                Err((keys, ret, pending)) => {
+                Err(Either::Left(rx)) => Either::Left(Either::Right(acked(rx))),
+                Err(Either::Right((keys, ret, stale, pending, hidden, lookup))) => {
                     if !block {
                         Either::Left(Either::Left(future::ready(Ok(Tagged {
                             tag,
-                            v: ReadReply::Normal(Ok(ret)),
+                            v: reply(ret, stale),
                         }))))
                     } else {
                         let (tx, rx) = tokio::sync::oneshot::channel();
@@ -214,7 +574,11 @@ fn handle_message(
                                 target,
                                 keys,
                                 pending,
+                                filter,
+                                count,
+                                hidden,
                                 read: ret,
+                                stale,
                                 truth: s.clone(),
                                 retry: tokio::time::interval_at(
                                     tokio::time::Instant::from_std(now + retry),
@@ -223,17 +587,20 @@ fn handle_message(
                                 trigger_timeout: trigger,
                                 next_trigger: now,
                                 first: now,
+                                deadline: timeout.map(|t| now + t),
+                                _queued: queues.enqueue(target),
                             },
+                            lookup,
                             tx,
                         ));
-                        if r.is_err() {
+                        if let Err(tokio::sync::mpsc::error::SendError((_, lookup, _))) = r {
                             // we're shutting down
+                            if let Some(lookup) = lookup {
+                                waiters.answer(&lookup, &Err(()));
+                            }
                             return Either::Left(Either::Left(future::ready(Err(()))));
                         }
-                        Either::Left(Either::Right(rx.map(|r| match r {
-                            Err(_) => Err(()),
-                            Ok(r) => r,
-                        })))
+                        Either::Left(Either::Right(acked(rx)))
                     }
                 }
             }
@@ -254,6 +621,21 @@ fn handle_message(
                 v: ReadReply::Size(size),
             })))
         }
+        ReadQuery::State { target } => {
+            // NOTE: we deliberately bypass the thread-local reader cache here, since it may still
+            // hold on to readers that have since been removed.
+            let state = match s.lock().unwrap().get(&target) {
+                // the reader was removed along with its view
+                None => ViewState::Deprecated,
+                Some(r) if r.is_ready() => ViewState::Ready,
+                Some(_) => ViewState::Installing,
+            };
+
+            Either::Right(future::ready(Ok(Tagged {
+                tag,
+                v: ReadReply::State(state),
+            })))
+        }
     }
 }
 
@@ -263,10 +645,18 @@ struct BlockingRead {
     target: (NodeIndex, usize),
     // records for keys we have already read
     read: Vec<Vec<Vec<DataType>>>,
+    // whether the records for each key were read from a snapshot
+    stale: Vec<bool>,
     // keys we have yet to read
     keys: Vec<Vec<DataType>>,
     // index in self.read that each entyr in keys corresponds to
     pending: Vec<usize>,
+    // only return rows that match this predicate
+    filter: Option<Predicate>,
+    // reply with the number of rows for each key instead of the rows
+    count: bool,
+    // columns whose values the client may not see
+    hidden: Vec<usize>,
     truth: Readers,
 
     #[pin]
@@ -275,6 +665,10 @@ struct BlockingRead {
     trigger_timeout: time::Duration,
     next_trigger: time::Instant,
     first: time::Instant,
+    // when to give up waiting for the pending keys, if ever
+    deadline: Option<time::Instant>,
+    // counts the read as waiting on its reader shard until it is answered
+    _queued: Queued,
 }
 
 impl Future for BlockingRead {
@@ -296,6 +690,9 @@ impl Future for BlockingRead {
 
                 let now = time::Instant::now();
                 let read = &mut this.read;
+                let filter = this.filter.as_ref();
+                let count = *this.count;
+                let hidden = &this.hidden[..];
                 let next_trigger = *this.next_trigger;
 
                 // here's the trick we're going to play:
@@ -307,7 +704,7 @@ impl Future for BlockingRead {
 
                 while let Some(read_i) = this.pending.pop() {
                     let key = this.keys.pop().expect("pending.len() == keys.len()");
-                    match reader.try_find_and(&key, |rs| dup(rs)).map(|r| r.0) {
+                    match read_key(reader, &key, filter, count, hidden) {
                         Ok(Some(rs)) => {
                             read[read_i] = rs;
                         }
@@ -355,7 +752,23 @@ impl Future for BlockingRead {
             if this.keys.is_empty() {
                 return Poll::Ready(Ok(Tagged {
                     tag: *this.tag,
-                    v: ReadReply::Normal(Ok(mem::replace(&mut this.read, Vec::new()))),
+                    v: reply(
+                        mem::replace(&mut this.read, Vec::new()),
+                        mem::replace(&mut this.stale, Vec::new()),
+                    ),
+                }));
+            }
+
+            if this.deadline.map_or(false, |d| time::Instant::now() >= d) {
+                // the client would rather have what we've got than wait any longer. the replays
+                // we triggered still fill the keys for later reads.
+                let rows = mem::replace(&mut this.read, Vec::new())
+                    .into_iter()
+                    .zip(mem::replace(&mut this.stale, Vec::new()))
+                    .collect();
+                return Poll::Ready(Ok(Tagged {
+                    tag: *this.tag,
+                    v: ReadReply::Pending(rows, mem::replace(&mut this.pending, Vec::new())),
                 }));
             }
         }
@@ -1,6 +1,7 @@
 /// Only allow processing this many inputs in a domain before we handle timer events, acks, etc.
 const FORCE_INPUT_YIELD_EVERY: usize = 32;
 
+use super::connections::{Counted, InFlight, Limited, Registry};
 use super::ChannelCoordinator;
 use crate::coordination::CoordinationPayload;
 use async_bincode::AsyncDestination;
@@ -8,8 +9,8 @@ use async_timer::Oneshot;
 use bincode;
 use dataflow::{
     payload::SourceChannelIdentifier,
-    prelude::{DataType, Executor},
-    Domain, Packet, PollEvent, ProcessResult,
+    prelude::{DataType, Executor, NodeIndex, Tag},
+    Clock, Domain, DomainBuilder, Packet, PollEvent, ProcessResult, Readers, WatermarkPolicy,
 };
 use failure::{self, ResultExt};
 use fnv::{FnvHashMap, FnvHashSet};
@@ -17,14 +18,24 @@ use futures_util::{
     sink::Sink,
     stream::{futures_unordered::FuturesUnordered, Stream},
 };
-use noria::channel::{DualTcpStream, CONNECTION_FROM_BASE};
+use noria::channel::{
+    Compression, DualTcpStream, CONNECTION_FROM_BASE, CONNECTION_FROM_DOMAIN_LZ4,
+};
+use noria::debug::stats::{DomainStats, NodeStats};
 use noria::internal::DomainIndex;
 use noria::internal::LocalOrNot;
-use noria::{Input, Tagged};
+use noria::{ConnectionKind, Input, Tagged, WriteAck, WriteRejection};
 use pin_project::pin_project;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use slog;
+use std::any::Any;
 use std::collections::{HashMap, VecDeque};
 use std::io;
+use std::mem;
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time;
 use std::{
@@ -39,6 +50,26 @@ use tokio::io::{AsyncReadExt, BufReader, BufStream, BufWriter};
 
 pub(super) type ReplicaAddr = (DomainIndex, usize);
 
+/// Put packets that were sent, but may not have arrived, back at the front of `queue`, in the
+/// order they were sent in.
+/// Run `f`, which may block the current thread.
+///
+/// The domains of a worker in deterministic mode share a basic scheduler, which does not allow
+/// blocking in place, and has no other tasks to move to another thread while `f` runs anyway.
+fn blocking<T>(in_place: bool, f: impl FnOnce() -> T) -> T {
+    if in_place {
+        tokio::task::block_in_place(f)
+    } else {
+        f()
+    }
+}
+
+fn requeue(queue: &mut VecDeque<Box<Packet>>, sent: Vec<Box<Packet>>) {
+    for m in sent.into_iter().rev() {
+        queue.push_front(m);
+    }
+}
+
 // https://github.com/rust-lang/rust/issues/64445
 type FirstByte = impl Future<Output = Result<(tokio::net::TcpStream, u8), tokio::io::Error>> + Send;
 
@@ -61,6 +92,9 @@ pub(super) struct Replica {
 
     retry: Option<Box<Packet>>,
 
+    // decides between local and remote inputs in deterministic mode
+    interleave: Option<StdRng>,
+
     #[pin]
     valve: Valve,
 
@@ -74,19 +108,30 @@ pub(super) struct Replica {
 
     #[pin]
     inputs: StreamUnordered<
-        DualTcpStream<
-            BufStream<tokio::net::TcpStream>,
-            Box<Packet>,
-            Tagged<LocalOrNot<Input>>,
-            AsyncDestination,
+        Limited<
+            DualTcpStream<
+                BufStream<Counted<tokio::net::TcpStream>>,
+                Box<Packet>,
+                Tagged<LocalOrNot<Input>>,
+                AsyncDestination,
+            >,
         >,
     >,
 
+    /// Connections to other domains, whether they have unflushed sends, copies of the unflushed
+    /// packets sent after the domain was announced to have moved, and the version of the route
+    /// each connection was built for.
+    ///
+    /// The copies are sent again to the domain's new location if the connection fails. Packets
+    /// sent before the move was announced are not copied, since the domain's state at its new
+    /// location is rebuilt by replay from upstream, which already reflects them.
     outputs: FnvHashMap<
         ReplicaAddr,
         (
             Box<dyn Sink<Box<Packet>, Error = bincode::Error> + Send + Unpin>,
             bool,
+            Vec<Box<Packet>>,
+            u64,
         ),
     >,
 
@@ -95,6 +140,157 @@ pub(super) struct Replica {
     timed_out: bool,
 
     out: Outboxes,
+
+    supervisor: Option<Supervisor>,
+
+    /// Where client connections to the domain are registered.
+    registry: Registry,
+
+    /// How many writes each client connection may have in flight.
+    max_outstanding_writes: Option<usize>,
+
+    /// The number of packets queued for other domains that have not been sent yet.
+    queued: Arc<AtomicUsize>,
+}
+
+/// Rebuilds the domain of a replica if the domain panics, keeps no state, and loses nothing that
+/// the domains downstream of it depend on.
+pub(super) struct Supervisor {
+    builder: DomainBuilder,
+    log: slog::Logger,
+    readers: Readers,
+    control_addr: SocketAddr,
+    valve: Valve,
+    state_size: Arc<AtomicUsize>,
+    clock: Clock,
+    watermark_policy: Arc<dyn WatermarkPolicy>,
+    /// Set once the domain panicked and was not rebuilt.
+    failed: AtomicBool,
+}
+
+impl Supervisor {
+    /// Supervise the domain built from `builder` with the given arguments to
+    /// `DomainBuilder::build`.
+    pub(super) fn new(
+        builder: DomainBuilder,
+        log: slog::Logger,
+        readers: Readers,
+        control_addr: SocketAddr,
+        valve: &Valve,
+        state_size: Arc<AtomicUsize>,
+        clock: Clock,
+        watermark_policy: Arc<dyn WatermarkPolicy>,
+    ) -> Self {
+        Supervisor {
+            builder,
+            log,
+            readers,
+            control_addr,
+            valve: valve.clone(),
+            state_size,
+            clock,
+            watermark_policy,
+            failed: AtomicBool::new(false),
+        }
+    }
+
+    /// Tell the controller that `domain` panicked, and replace it with a fresh domain if it keeps
+    /// no state and `lost` nothing that carried updates or replays when it panicked.
+    ///
+    /// A domain that is not replaced stops processing, and the controller rebuilds the queries
+    /// that depend on it. Until it is told to quit, it drops whatever it is sent, so that the
+    /// domains sending to it keep running. Domains with base tables cannot be rebuilt, and fail
+    /// with an error instead.
+    fn respawn(
+        &self,
+        domain: &mut Domain,
+        out: &mut Outboxes,
+        coord: &Arc<ChannelCoordinator>,
+        log: &slog::Logger,
+        reason: String,
+        lost: bool,
+    ) -> Result<(), failure::Error> {
+        let (index, shard) = domain.id();
+        let respawned = !lost && domain.is_stateless();
+        let _ = out.ctrl_tx.send(CoordinationPayload::DomainFailed {
+            domain: index,
+            shard,
+            reason: reason.clone(),
+            respawned,
+        });
+        if !respawned {
+            if domain.has_base_tables() {
+                bail!("domain panicked: {}", reason);
+            }
+            self.failed.store(true, Ordering::Relaxed);
+            crit!(log, "domain panicked, and drops what it is sent from now on"; "reason" => reason);
+            return Ok(());
+        }
+
+        let setup = domain.take_setup();
+        let mut fresh = self.builder.clone().build(
+            self.log.clone(),
+            self.readers.clone(),
+            coord.clone(),
+            self.control_addr,
+            &self.valve,
+            self.state_size.clone(),
+            self.clock.clone(),
+            self.watermark_policy.clone(),
+        );
+        fresh.restore(setup, out);
+        *domain = fresh;
+        warn!(log, "respawned domain after it panicked"; "reason" => reason);
+        Ok(())
+    }
+}
+
+/// Hand `event` to `domain`, and have the supervisor, if any, deal with the domain panicking.
+fn on_event(
+    domain: &mut Domain,
+    supervisor: Option<&Supervisor>,
+    out: &mut Outboxes,
+    coord: &Arc<ChannelCoordinator>,
+    log: &slog::Logger,
+    event: PollEvent,
+) -> Result<ProcessResult, failure::Error> {
+    let supervisor = match supervisor {
+        Some(supervisor) => supervisor,
+        None => return Ok(domain.on_event(out, event)),
+    };
+    if supervisor.failed.load(Ordering::Relaxed) {
+        return Ok(match event {
+            PollEvent::Process(p) => match *p {
+                Packet::Quit => ProcessResult::StopPolling,
+                _ => ProcessResult::Processed,
+            },
+            _ => ProcessResult::KeepPolling(None),
+        });
+    }
+
+    let lost = match event {
+        PollEvent::Process(ref p) => p.carries_updates(),
+        _ => false,
+    };
+    match panic::catch_unwind(AssertUnwindSafe(|| domain.on_event(out, event))) {
+        Ok(r) => Ok(r),
+        Err(e) => {
+            supervisor.respawn(domain, out, coord, log, panic_reason(&*e), lost)?;
+            // whatever the old domain was in the middle of is lost, but it carried no updates or
+            // replays if the domain was respawned
+            Ok(ProcessResult::KeepPolling(None))
+        }
+    }
+}
+
+fn panic_reason(e: &(dyn Any + Send)) -> String {
+    if let Some(s) = e.downcast_ref::<&str>() {
+        (*s).to_owned()
+    } else if let Some(s) = e.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_owned()
+    }
 }
 
 impl Replica {
@@ -106,14 +302,23 @@ impl Replica {
         ctrl_tx: tokio::sync::mpsc::UnboundedSender<CoordinationPayload>,
         log: slog::Logger,
         cc: Arc<ChannelCoordinator>,
+        supervisor: Option<Supervisor>,
+        registry: Registry,
+        max_outstanding_writes: Option<usize>,
+        queued: Arc<AtomicUsize>,
     ) -> Self {
         let id = domain.id();
+        let interleave = domain.interleave_seed().map(|seed| {
+            // give every domain shard its own, but reproducible, sequence of decisions
+            StdRng::seed_from_u64(seed ^ ((id.0.index() as u64) << 32) ^ id.1 as u64)
+        });
         let id = format!("{}.{}", id.0.index(), id.1);
         domain.booted(on.local_addr().unwrap());
         Replica {
             coord: cc,
             domain,
             retry: None,
+            interleave,
             valve: valve.clone(),
             incoming: Strawpoll::from(on),
             first_byte: FuturesUnordered::new(),
@@ -126,6 +331,10 @@ impl Replica {
                 3600,
             ))),
             timed_out: false,
+            supervisor,
+            registry,
+            max_outstanding_writes,
+            queued,
         }
     }
 
@@ -143,7 +352,7 @@ impl Replica {
             let mut stream = Pin::new(&mut inputs[streami]);
             let mut sent = 0;
 
-            for &tag in &conn.tag_acks {
+            for &(tag, ref ack) in &conn.tag_acks {
                 match stream.as_mut().poll_ready(cx) {
                     Poll::Ready(Ok(())) => {}
                     Poll::Pending => break,
@@ -153,7 +362,10 @@ impl Replica {
                     }
                 }
 
-                if let Err(e) = stream.as_mut().start_send(Tagged { tag, v: () }) {
+                if let Err(e) = stream.as_mut().start_send(Tagged {
+                    tag,
+                    v: ack.clone(),
+                }) {
                     // start_send shouldn't generally error
                     err.push(e.into());
                     break;
@@ -257,20 +469,37 @@ impl Replica {
 
         let cc = this.coord;
         let outputs = this.outputs;
+        let compression = this.domain.output_compression();
 
         // just like in try_acks:
         // first, queue up any additional writes we have to do
         let mut err = Vec::new();
+        let mut stale = Vec::new();
         for (&ri, ms) in &mut this.out.domains {
             if ms.is_empty() {
                 continue;
             }
 
-            let &mut (ref mut tx, ref mut pending) = outputs.entry(ri).or_insert_with(|| {
-                while !cc.has(&ri) {}
-                let tx = cc.builder_for(&ri).unwrap().build_async().unwrap();
-                (tx, true)
-            });
+            // if the controller has since announced that the domain moved, the connection we have
+            // goes to its old location, so we re-resolve it.
+            let version = cc.version(&ri);
+            if outputs.get(&ri).map(|o| o.3 < version).unwrap_or(false) {
+                debug!(this.log, "re-resolving moved domain";
+                       "domain" => ?ri, "version" => version);
+                let (_, _, copies, _) = outputs.remove(&ri).unwrap();
+                requeue(ms, copies);
+            }
+
+            let &mut (ref mut tx, ref mut pending, ref mut copies, built) =
+                outputs.entry(ri).or_insert_with(|| {
+                    while !cc.has(&ri) {}
+                    let mut builder = cc.builder_for(&ri).unwrap();
+                    if let Some((codec, ref stats)) = compression {
+                        builder = builder.compressed(codec, Arc::clone(stats));
+                    }
+                    let tx = builder.build_async().unwrap();
+                    (tx, true, Vec::new(), version)
+                });
 
             let mut tx = Pin::new(tx);
 
@@ -278,6 +507,13 @@ impl Replica {
                 match tx.as_mut().poll_ready(cx) {
                     Poll::Ready(Ok(())) => {}
                     Poll::Pending => break,
+                    Poll::Ready(Err(_)) if cc.version(&ri) > built => {
+                        // the domain moved while we were talking to it; the messages are queued
+                        // again, and will go to its new location next time around.
+                        requeue(ms, mem::replace(copies, Vec::new()));
+                        stale.push(ri);
+                        break;
+                    }
                     Poll::Ready(Err(e)) => {
                         err.push(e);
                         break;
@@ -285,10 +521,18 @@ impl Replica {
                 }
 
                 let m = ms.pop_front().expect("!is_empty");
+                // the domain may have moved since we last checked, in which case the packet may
+                // never arrive.
+                let copy = if cc.version(&ri) > built {
+                    Some(m.clone())
+                } else {
+                    None
+                };
                 match tx.as_mut().start_send(m) {
                     Ok(()) => {
                         // we queued something, so we'll need to send!
                         *pending = true;
+                        copies.extend(copy);
                     }
                     Err(e) => {
                         err.push(e);
@@ -297,13 +541,15 @@ impl Replica {
                 }
             }
         }
+        let queued = this.out.domains.values().map(VecDeque::len).sum();
+        this.queued.store(queued, Ordering::Relaxed);
 
         if !err.is_empty() {
             return Err(err.swap_remove(0).into());
         }
 
         // then, try to do any sends that are still pending
-        for &mut (ref mut tx, ref mut pending) in outputs.values_mut() {
+        for (ri, &mut (ref mut tx, ref mut pending, ref mut copies, built)) in outputs.iter_mut() {
             if !*pending {
                 continue;
             }
@@ -311,12 +557,24 @@ impl Replica {
             match Pin::new(tx).poll_flush(cx) {
                 Poll::Ready(Ok(())) => {
                     *pending = false;
+                    copies.clear();
                 }
                 Poll::Pending => {}
+                Poll::Ready(Err(_)) if cc.version(ri) > built => {
+                    // the packets sent since the move was announced may not have made it out, so
+                    // they are sent again to its new location.
+                    let ms = this.out.domains.entry(*ri).or_default();
+                    requeue(ms, mem::replace(copies, Vec::new()));
+                    stale.push(*ri);
+                }
                 Poll::Ready(Err(e)) => err.push(e),
             }
         }
 
+        for ri in stale {
+            outputs.remove(&ri);
+        }
+
         if !err.is_empty() {
             return Err(err.swap_remove(0).into());
         }
@@ -346,9 +604,15 @@ impl Replica {
             let is_base = tag == CONNECTION_FROM_BASE;
 
             debug!(this.log, "established new connection"; "base" => ?is_base);
+            let in_flight = if is_base {
+                this.max_outstanding_writes.map(InFlight::new)
+            } else {
+                None
+            };
             let slot = this.inputs.stream_entry();
             let token = slot.token();
             let epoch = if let Some(e) = this.out.connections.get_mut(token) {
+                e.in_flight = in_flight.clone();
                 e.epoch
             } else {
                 let epoch = 1;
@@ -357,6 +621,7 @@ impl Replica {
                     tag_acks: Vec::new(),
                     epoch,
                     pending_flush: false,
+                    in_flight: in_flight.clone(),
                 });
                 assert_eq!(t, token);
                 epoch
@@ -367,30 +632,46 @@ impl Replica {
                       "from" => ?stream.peer_addr().unwrap());
             }
             let tcp = if is_base {
+                let registered = stream
+                    .peer_addr()
+                    .ok()
+                    .map(|peer| this.registry.register(peer, ConnectionKind::Write));
+                let conn = registered.as_ref().map(|r| r.connection().clone());
                 DualTcpStream::upgrade(
-                    tokio::io::BufStream::new(stream),
+                    tokio::io::BufStream::new(Counted::new(stream, registered)),
                     move |Tagged { v: input, tag }| {
+                        if let Some(ref conn) = conn {
+                            let input = unsafe { input.deref() };
+                            conn.record_op();
+                            conn.set_identity(input.identity.as_deref());
+                        }
+                        let trace = unsafe { input.deref() }.trace;
                         Box::new(Packet::Input {
                             inner: input,
                             src: Some(SourceChannelIdentifier { token, tag, epoch }),
                             senders: Vec::new(),
+                            trace,
                         })
                     },
                 )
             } else {
-                tokio::io::BufStream::from(BufReader::with_capacity(
+                let stream = tokio::io::BufStream::from(BufReader::with_capacity(
                     2 * 1024 * 1024,
-                    BufWriter::with_capacity(4 * 1024, stream),
-                ))
-                .into()
+                    BufWriter::with_capacity(4 * 1024, Counted::new(stream, None)),
+                ));
+                if tag == CONNECTION_FROM_DOMAIN_LZ4 {
+                    DualTcpStream::compressed(stream, Compression::Lz4)
+                } else {
+                    stream.into()
+                }
             };
-            slot.insert(tcp);
+            slot.insert(Limited::new(tcp, in_flight));
         }
         Ok(true)
     }
 
     // returns true if on_event(Timeout) was called
-    fn try_timeout(self: Pin<&mut Self>, cx: &mut Context<'_>) -> bool {
+    fn try_timeout(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Result<bool, failure::Error> {
         let mut processed = false;
         let mut this = self.project();
 
@@ -402,13 +683,20 @@ impl Replica {
 
         if *this.timed_out {
             *this.timed_out = false;
-            tokio::task::block_in_place(|| {
-                this.domain.on_event(this.out, PollEvent::Timeout);
-                processed = true;
-            });
+            blocking(this.interleave.is_none(), || {
+                on_event(
+                    this.domain,
+                    this.supervisor.as_ref(),
+                    this.out,
+                    this.coord,
+                    this.log,
+                    PollEvent::Timeout,
+                )
+            })?;
+            processed = true;
         }
 
-        processed
+        Ok(processed)
     }
 }
 
@@ -416,14 +704,18 @@ struct ConnState {
     // number of unacked inputs
     unacked: usize,
 
-    // unsent acks (value is the tag)
-    tag_acks: Vec<u32>,
+    // unsent acks (values are the tag and the commit sequence number, or why the write was
+    // rejected)
+    tag_acks: Vec<(u32, WriteAck)>,
 
     // epoch counter for each stream index (since they're re-used)
     epoch: usize,
 
     // do we have stuff to flush
     pending_flush: bool,
+
+    // the writes in flight on this connection, if they are limited
+    in_flight: Option<Arc<InFlight>>,
 }
 
 struct Outboxes {
@@ -453,6 +745,7 @@ impl Outboxes {
             tag_acks: Vec::new(),
             epoch: 0,
             pending_flush: false,
+            in_flight: None,
         });
 
         Outboxes {
@@ -487,16 +780,17 @@ impl Outboxes {
             false
         }
     }
-}
 
-impl Executor for Outboxes {
-    fn ack(&mut self, id: SourceChannelIdentifier) {
+    fn reply(&mut self, id: SourceChannelIdentifier, ack: WriteAck) {
         self.dirty = true;
         let mut c = &mut self.connections[id.token];
         if id.epoch == c.epoch {
             // if the epoch doesn't match, the stream was closed and a new one has been established
             // note that this only matters for connections that do not wait for all acks!
-            c.tag_acks.push(id.tag);
+            c.tag_acks.push((id.tag, ack));
+            if let Some(ref in_flight) = c.in_flight {
+                in_flight.finished();
+            }
 
             // NOTE: it's a little sad we can't crash on underflow here.
             // it is because if a send fails, we set c.unacked = 0, and should the domain _then_
@@ -507,6 +801,16 @@ impl Executor for Outboxes {
             self.pending.insert(id.token);
         }
     }
+}
+
+impl Executor for Outboxes {
+    fn ack(&mut self, id: SourceChannelIdentifier, seq: u64) {
+        self.reply(id, Ok(seq));
+    }
+
+    fn reject(&mut self, id: SourceChannelIdentifier, why: WriteRejection) {
+        self.reply(id, Err(why));
+    }
 
     fn create_universe(&mut self, universe: HashMap<String, DataType>) {
         self.ctrl_tx
@@ -518,6 +822,37 @@ impl Executor for Outboxes {
         self.dirty = true;
         self.domains.entry(dest).or_default().push_back(m);
     }
+
+    fn report_statistics(
+        &mut self,
+        (domain, shard): ReplicaAddr,
+        stats: DomainStats,
+        nodes: HashMap<NodeIndex, NodeStats>,
+    ) {
+        // the controller may be busy being replaced; the next report will make up for this one.
+        let _ = self.ctrl_tx.send(CoordinationPayload::DomainStatistics {
+            domain,
+            shard,
+            stats,
+            nodes,
+        });
+    }
+
+    fn report_corrupt_replay(
+        &mut self,
+        (domain, shard): ReplicaAddr,
+        tag: Tag,
+        keys: usize,
+        recovered: bool,
+    ) {
+        let _ = self.ctrl_tx.send(CoordinationPayload::CorruptReplay {
+            domain,
+            shard,
+            tag: tag.id(),
+            keys,
+            recovered,
+        });
+    }
 }
 
 impl Future for Replica {
@@ -537,7 +872,7 @@ impl Future for Replica {
             }
 
             // have any of our timers expired?
-            self.as_mut().try_timeout(cx);
+            self.as_mut().try_timeout(cx)?;
 
             // we have three logical input sources: receives from local domains, receives from
             // remote domains, and remote mutators. we want to achieve some kind of fairness among
@@ -561,14 +896,18 @@ impl Future for Replica {
             let mut remote_done = false;
             let mut check_local = true;
             let mut this = self.as_mut().project();
+            let in_place = this.interleave.is_none();
             let d = this.domain;
             let out = this.out;
+            let supervisor = this.supervisor.as_ref();
+            let cc = &*this.coord;
+            let log = &*this.log;
 
             macro_rules! process {
                 ($retry:expr, $outbox:expr, $p:expr, $pp:expr) => {{
                     $retry = Some($p);
                     let retry = &mut $retry;
-                    if let ProcessResult::StopPolling = tokio::task::block_in_place(|| {
+                    if let ProcessResult::StopPolling = blocking(in_place, || {
                         let packet = retry.take().unwrap();
                         if let Packet::Input {
                             src: Some(SourceChannelIdentifier { token, epoch, .. }),
@@ -578,7 +917,7 @@ impl Future for Replica {
                             $outbox.saw_input(token, epoch);
                         }
                         $pp(packet)
-                    }) {
+                    })? {
                         // domain got a message to quit
                         // TODO: should we finish up remaining work?
                         return Poll::Ready(Ok(()));
@@ -588,16 +927,28 @@ impl Future for Replica {
 
             if let Some(p) = this.retry.take() {
                 // first try the thing we failed to process last time again
-                process!(*this.retry, out, p, |p| d
-                    .on_event(out, PollEvent::Process(p),));
+                process!(*this.retry, out, p, |p| on_event(
+                    d,
+                    supervisor,
+                    out,
+                    cc,
+                    log,
+                    PollEvent::Process(p)
+                ));
             }
 
             for _ in 0..FORCE_INPUT_YIELD_EVERY {
                 if !local_done && (check_local || remote_done) {
                     match this.locals.poll_recv(cx) {
                         Poll::Ready(Some(packet)) => {
-                            process!(*this.retry, out, packet, |p| d
-                                .on_event(out, PollEvent::Process(p),));
+                            process!(*this.retry, out, packet, |p| on_event(
+                                d,
+                                supervisor,
+                                out,
+                                cc,
+                                log,
+                                PollEvent::Process(p)
+                            ));
                         }
                         Poll::Ready(None) => {
                             // local input stream finished
@@ -614,8 +965,14 @@ impl Future for Replica {
                 if !remote_done && (!check_local || local_done) {
                     match this.inputs.as_mut().poll_next(cx) {
                         Poll::Ready(Some((StreamYield::Item(Ok(packet)), _))) => {
-                            process!(*this.retry, out, packet, |p| d
-                                .on_event(out, PollEvent::Process(p),));
+                            process!(*this.retry, out, packet, |p| on_event(
+                                d,
+                                supervisor,
+                                out,
+                                cc,
+                                log,
+                                PollEvent::Process(p)
+                            ));
                         }
                         Poll::Ready(Some((StreamYield::Finished(f), streami))) => {
                             if out.try_retire(streami) {
@@ -653,7 +1010,10 @@ impl Future for Replica {
                 }
 
                 // alternate between input sources
-                check_local = !check_local;
+                check_local = match this.interleave {
+                    Some(ref mut rng) => rng.gen(),
+                    None => !check_local,
+                };
             }
 
             // send to downstream
@@ -675,7 +1035,14 @@ impl Future for Replica {
             self.out.dirty = false;
             loop {
                 let mut this = self.as_mut().project();
-                match this.domain.on_event(this.out, PollEvent::ResumePolling) {
+                match on_event(
+                    this.domain,
+                    this.supervisor.as_ref(),
+                    this.out,
+                    this.coord,
+                    this.log,
+                    PollEvent::ResumePolling,
+                )? {
                     ProcessResult::KeepPolling(timeout) => {
                         if let Some(timeout) = timeout {
                             if timeout == time::Duration::new(0, 0) {
@@ -685,7 +1052,7 @@ impl Future for Replica {
                             }
 
                             // we need to poll the timer to ensure we'll get woken up
-                            if self.as_mut().try_timeout(cx) {
+                            if self.as_mut().try_timeout(cx)? {
                                 // a timeout occurred, so we may have to set a new timer
                                 if self.out.dirty {
                                     // if we're already dirty, we'll re-do processing anyway
@@ -311,7 +311,9 @@ async fn check_query(
                         DataType::UnsignedBigInt(i) => i.to_string(),
                         DataType::Real(i, f) => ((i as f64) + (f as f64) * 1.0e-9).to_string(),
                         DataType::Text(_) | DataType::TinyText(_) => v.into(),
-                        DataType::Timestamp(_) => unimplemented!(),
+                        DataType::Timestamp(_) | DataType::Bytes(_) | DataType::List(_) => {
+                            unimplemented!()
+                        }
                     })
                     .collect()
             })